@@ -1,5 +1,6 @@
 // File: args.rs
 use clap::{Parser, ValueEnum};
+use shared_utils::{datachannel_pointcloud::DataChannelConfig, peer_connection::PeerConnectionConfig};
 use tracing::level_filters::LevelFilter;
 
 #[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Debug, ValueEnum)]
@@ -11,6 +12,12 @@ pub enum LogLevel {
     Error = 4, // Designates very serious errors.
 }
 
+#[derive(Copy, Clone, PartialEq, Eq, Debug, ValueEnum)]
+pub enum WebRTCTransport {
+    Rtp, // Receive the frames on an RTP track
+    Datachannel, // Receive the frames on an SCTP DataChannel
+}
+
 #[derive(Parser, Debug)]
 #[command(version, about, long_about="A Headless client that receives 3D data from a server.")]
 pub struct Args {
@@ -24,12 +31,71 @@ pub struct Args {
     pub log_level: LogLevel,
     #[arg(short, long, default_value = "3380")]
     pub port: u16,
+    /// STUN/TURN server URLs used by the WebRTC peer connection
+    #[arg(long, value_delimiter = ',', default_value = "stun:stun.l.google.com:19302")]
+    pub ice_servers: Vec<String>,
+    /// Username of the TURN servers
+    #[arg(long)]
+    pub turn_username: Option<String>,
+    /// Credential of the TURN servers
+    #[arg(long)]
+    pub turn_credential: Option<String>,
+    /// Only connect to the server through the TURN servers
+    #[arg(long, action = clap::ArgAction::SetTrue)]
+    pub ice_relay_only: bool,
+    /// Range of local UDP ports used by WebRTC, e.g. 50000-50100
+    #[arg(long, value_parser = parse_port_range)]
+    pub webrtc_port_range: Option<(u16, u16)>,
+    /// Do not resolve the mDNS candidates of the server
+    #[arg(long, action = clap::ArgAction::SetTrue)]
+    pub disable_mdns: bool,
+    /// Transport used to receive the frames over WebRTC
+    #[arg(long, default_value = "rtp")]
+    pub webrtc_transport: WebRTCTransport,
+    /// Make the DataChannel unordered and retransmit lost chunks at most this many times, reliable if not set
+    #[arg(long)]
+    pub datachannel_max_retransmits: Option<u16>,
+    /// Keys to decrypt encrypted frames with, e.g. 1:<64 hex characters>,2:<64 hex characters>
+    #[arg(long)]
+    pub payload_keys: Option<String>,
+    /// Compression to request for the WebSocket frames: none, deflate or zstd
+    #[arg(long, default_value = "none")]
+    pub ws_compression: String,
 }
 
 pub fn parse_args() -> Args {
     Args::parse()
 }
 
+fn parse_port_range(value: &str) -> Result<(u16, u16), String> {
+    let (port_min, port_max) = value.split_once('-').ok_or("Expected a port range like 50000-50100")?;
+    let port_min = port_min.trim().parse::<u16>().map_err(|e| format!("Invalid port {port_min}: {e}"))?;
+    let port_max = port_max.trim().parse::<u16>().map_err(|e| format!("Invalid port {port_max}: {e}"))?;
+    Ok((port_min, port_max))
+}
+
+pub fn get_peer_connection_config(args: &Args) -> PeerConnectionConfig {
+    let config = PeerConnectionConfig::default()
+        .with_ice_servers(&args.ice_servers, args.turn_username.as_deref(), args.turn_credential.as_deref())
+        .with_relay_only(args.ice_relay_only)
+        .with_mdns(!args.disable_mdns);
+    match args.webrtc_port_range {
+        Some((port_min, port_max)) => config.with_port_range(port_min, port_max),
+        None => config,
+    }
+}
+
+/// The DataChannel settings if the frames should be received on a DataChannel instead of an RTP track.
+pub fn get_data_channel_config(args: &Args) -> Option<DataChannelConfig> {
+    match args.webrtc_transport {
+        WebRTCTransport::Rtp => None,
+        WebRTCTransport::Datachannel => Some(match args.datachannel_max_retransmits {
+            Some(max_retransmits) => DataChannelConfig::unreliable(max_retransmits),
+            None => DataChannelConfig::reliable(),
+        }),
+    }
+}
+
 pub fn get_log_level_filter(args: &Args) -> LevelFilter {
     // Map the LogLevel enum to the LevelFilter enum
     match args.log_level {
@@ -4,8 +4,7 @@ use std::{
 };
 
 use dash_player::{DashPlayer, DashEvent};
-use mp4_box::reader::extract_mdat_boxes;
-use shared_utils::types::FrameTaskData;
+use shared_utils::wire::decode_frame;
 use tokio::{runtime::Runtime, task::JoinHandle};
 use tracing::{debug, error, warn};
 use crate::{
@@ -86,47 +85,37 @@ impl DashIngress {
                 tokio::spawn(async move {
                     match event {
                         DashEvent::Segment {
-                            data,
+                            representation_id,
+                            segment_number,
+                            ..
+                        } => {
+                            // Media segments are demuxed by the player, only unparsed segments end up here
+                            debug!("DASH [{} - {}] - ignoring segment {}", cb_group_id, representation_id, segment_number);
+                        }
+                        DashEvent::Sample {
                             content_type,
                             representation_id,
                             segment_number,
-                            url,
+                            sample,
                             playback_rate,
                             ..
                         } => {
                             debug!(
-                                "DASH [{} - {}] - segment {} (type: {}, rate: {}) size: {} bytes",
+                                "DASH [{} - {}] - segment {} sample at {} (type: {}, rate: {}) size: {} bytes",
                                 cb_group_id,
                                 representation_id,
                                 segment_number,
+                                sample.decode_time,
                                 content_type,
                                 playback_rate,
-                                data.len()
+                                sample.data.len()
                             );
 
-                            if url.ends_with("init.mp4") {
+                            if sample.data.is_empty() {
+                                warn!("Empty sample found in segment {}", segment_number);
                                 return;
                             }
 
-                            //info!(url);
-                            //info!("First 16 bytes: {:?}", &data[..16.min(data.len())]);
-
-                            // Use fast mdat extractor
-                            let mdat_boxes = match extract_mdat_boxes(&data) {
-                                Ok(boxes) => boxes,
-                                Err(err) => {
-                                    warn!("Failed to parse mdat boxes: {}", err);
-                                    return;
-                                }
-                            };
-
-                            if mdat_boxes.is_empty() {
-                                warn!("No mdat boxes found in segment {}", segment_number);
-                                return;
-                            }
-
-
-
                             let quality = {
                                 // Split the representation id on '_' and take the last part
                                 let parts: Vec<&str> = representation_id.split('_').collect();
@@ -140,46 +129,42 @@ impl DashIngress {
                                 }
                             };
 
-
-                            for mdat in mdat_boxes {
-                                let mdat_data = mdat.data;
-                                if mdat_data.is_empty() {
-                                    warn!("Empty mdat box found");
-                                    continue;
+                            // Decode the payload
+                            let bytes_str = match std::str::from_utf8(&sample.data) {
+                                Ok(v) => v,
+                                Err(e) => {
+                                    warn!("Invalid UTF-8 sequence: {}", e);
+                                    return;
+                                }
+                            };
+                            let bytes_decoded = match rbase64::decode(bytes_str) {
+                                Ok(decoded) => decoded,
+                                Err(err) => {
+                                    warn!("Failed to decode payload: {}", err);
+                                    return;
                                 }
+                            };
+                            let frame_task_data = match decode_frame(&bytes_decoded) {
+                                Ok(decoded) => decoded,
+                                Err(err) => {
+                                    warn!("Failed to decode payload: {}", err);
+                                    return;
+                                }
+                            };
 
-                                // Decode the payload
-                                let bytes_str = match std::str::from_utf8(&mdat_data) {
-                                    Ok(v) => v,
-                                    Err(e) => {
-                                        warn!("Invalid UTF-8 sequence: {}", e);
-                                        continue;
-                                    }
-                                };
-                                let bytes_decoded = match rbase64::decode(bytes_str) {
-                                    Ok(decoded) => decoded,
-                                    Err(err) => {
-                                        warn!("Failed to decode payload: {}", err);
-                                        continue;
-                                    }
-                                };
-                                let frame_task_data = match bitcode::decode::<FrameTaskData>(&bytes_decoded) {
-                                    Ok(decoded) => decoded,
-                                    Err(err) => {
-                                        warn!("Failed to decode payload: {}", err);
-                                        continue;
-                                    }
-                                };
-
-                                cb_pipeline.ingest_data(
-                                    cb_stream_id.clone(),
-                                    quality,
-                                    frame_task_data.send_time,
-                                    frame_task_data.presentation_time,
-                                    frame_task_data.data,
-                                );
-                            }
+                            cb_pipeline.ingest_data(
+                                cb_stream_id.clone(),
+                                quality,
+                                frame_task_data.send_time,
+                                frame_task_data.presentation_time,
+                                frame_task_data.data,
+                            );
                         }
+                        DashEvent::Latency { adaptation_id, status } => debug!(
+                            "DASH [{} - {}] Latency: {:.3} s (target {:.3} s), rate: {:.2}",
+                            cb_group_id, adaptation_id, status.current_latency, status.target_latency, status.playback_rate
+                        ),
+                        DashEvent::EndOfStream => debug!("DASH [{}] End of stream", cb_group_id),
                         DashEvent::Info(msg) => debug!("DASH [{}] Info: {}", cb_group_id, msg),
                         DashEvent::Warning(msg) => error!("DASH [{}] Warning: {}", cb_group_id, msg),
                         DashEvent::DownloadError { url, reason } => {
@@ -193,6 +178,7 @@ impl DashIngress {
             match DashPlayer::new(&mpd_url, callback_arc).await {
                 Ok(player) => {
                     player.set_target_latency(0.001).await;
+                    player.set_demux_segments(true);
                     if let Err(e) = player.start().await {
                         error!("DASH [{}] Failed to start player: {}", group_id_clone, e);
                     }
@@ -6,7 +6,7 @@ use std::thread;
 use std::time::SystemTime;
 use flute::core::UDPEndpoint;
 use flute::receiver::{writer, MultiReceiver};
-use metrics::get_metrics;
+use metrics::{duration_buckets_us, get_metrics};
 use tracing::{error, info};
 use circular_buffer::CircularBuffer;
 
@@ -56,7 +56,7 @@ impl FluteIngress {
 
         let metrics = get_metrics();
         let reception_time_flute = metrics
-            .get_or_create_gauge("reception_time_flute", "Time it took to receive a FLUTE object.")
+            .get_or_create_histogram("reception_time_flute", "Time (us) it took to receive a FLUTE object", duration_buckets_us())
             .unwrap();
 
         let endpoint = UDPEndpoint::new(None, ip.clone(), port);
@@ -146,7 +146,7 @@ impl FluteIngress {
                         };
 
                         let receive_duration = obj.end_time.unwrap().duration_since(obj.start_time).unwrap();
-                        reception_time_flute.set(receive_duration.as_micros() as i64);
+                        reception_time_flute.observe(receive_duration.as_micros() as f64);
 
                         pipeline_clone.ingest_data(
                             format!("flute_{}:{}", ip_clone, port),
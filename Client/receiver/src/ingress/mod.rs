@@ -4,10 +4,16 @@ pub mod flute;
 pub mod dash;
 // pub mod flute; // Implement when ready
 use std::sync::Arc;
+use std::time::Duration;
+use metrics::{get_metrics, Metrics};
+use tracing::error;
 use crate::processing::ProcessingPipeline;
 use crate::services::stream_manager::StreamManager;
 use crate::storage::Storage;
 
+/// Streams that did not receive a frame for this long are removed, together with their metrics.
+const INACTIVE_STREAM_TIMEOUT: Duration = Duration::from_secs(30);
+
 pub struct Ingress {
     stream_manager: Arc<StreamManager>,
     processing_pipeline: Arc<ProcessingPipeline>,
@@ -18,7 +24,20 @@ impl Ingress {
     pub fn new(thread_count: usize, disable_parser: bool) -> Self {
         let stream_manager = Arc::new(StreamManager::new());
         let storage = Arc::new(Storage::new());
-        let processing_pipeline = Arc::new(ProcessingPipeline::new(storage.clone(), thread_count, disable_parser));
+
+        // Remove the buffers and metrics of streams that stopped sending frames, checked on every metrics update
+        let weak_storage = Arc::downgrade(&storage);
+        get_metrics().add_update_callback(Arc::new(move |metrics: &Metrics| {
+            let Some(storage) = weak_storage.upgrade() else {
+                return;
+            };
+            for stream_id in storage.remove_inactive_streams(INACTIVE_STREAM_TIMEOUT) {
+                if let Err(e) = metrics.remove_series("stream_id", &stream_id) {
+                    error!("Failed to remove the metrics of stream_id = {}: {}", stream_id, e);
+                }
+            }
+        }));
+        let processing_pipeline = Arc::new(ProcessingPipeline::new(storage.clone(), stream_manager.payload_keys.clone(), thread_count, disable_parser));
         Ingress {
             stream_manager,
             processing_pipeline,
@@ -10,7 +10,12 @@ use crate::{
     processing::ProcessingPipeline,
     services::stream_manager::StreamManager,
 };
-use shared_utils::{peer_connection::create_webrtc_peer_connection, track_remote_pointcloud_rtp::TrackRemotePointCloudRTP, types::FrameTaskData};
+use shared_utils::{
+    datachannel_pointcloud::{create_pointcloud_data_channel, DataChannelPointCloudReceiver},
+    peer_connection::create_webrtc_peer_connection,
+    track_remote_pointcloud_rtp::TrackRemotePointCloudRTP,
+    types::FrameTaskData,
+};
 
 /// A client-side module for receiving frames via WebRTC data channel.
 pub struct WebRTCIngress {
@@ -22,6 +27,8 @@ pub struct WebRTCIngress {
     pipeline: Arc<ProcessingPipeline>,
     /// Pending ICE candidates to be applied after the remote description is set
     pending_candidates: RwLock<Vec<RTCIceCandidateInit>>,
+    /// Receives the frames when they are sent over a DataChannel instead of an RTP track
+    data_channel_receiver: RwLock<Option<DataChannelPointCloudReceiver>>,
     pub runtime: Arc<Mutex<Runtime>>,
 }
 
@@ -38,6 +45,7 @@ impl WebRTCIngress {
             stream_manager: stream_manager.clone(),
             pipeline,
             pending_candidates: RwLock::new(Vec::new()),
+            data_channel_receiver: RwLock::new(None),
             runtime
         });
         // Keep a reference to ourselves in the StreamManager
@@ -48,7 +56,8 @@ impl WebRTCIngress {
     #[instrument(skip(self))]
     pub async fn create_offer(&self) -> Result<String, String> {
         // 1) Create PeerConnection
-        let pc = create_webrtc_peer_connection().await?;
+        let peer_connection_config = self.stream_manager.peer_connection_config.read().unwrap().clone();
+        let pc = create_webrtc_peer_connection(&peer_connection_config).await?;
 
         // 2) **Forward client-side ICE to server**:  
         //    Whenever the client finds a new ICE candidate,
@@ -110,31 +119,31 @@ impl WebRTCIngress {
             },
         ));
 
-        pc.add_transceiver_from_kind(RTPCodecType::Video, None)
-            .await
-            .map_err(|e| format!("add_transceiver_from_kind failed: {e}"))?;
-
-        let pipeline_clone = self.pipeline.clone();
-        pc.on_track(Box::new(move | track, _receiver, _transceiver| {
-            let p = pipeline_clone.clone();
-            Box::pin(async move {
-                info!("Created new track");
-                let some_on_frame_cb = Arc::new(move |frame: FrameTaskData| {            
-                    // info!("Received frame with {} bytes", frame.data.len());
-                
-                    p.ingest_data(
-                        format!("client_{}_{}", frame.sfu_client_id.unwrap_or(0), frame.sfu_tile_index.unwrap_or(0)),
-                        0,
-                        frame.send_time,
-                        frame.presentation_time,
-                        frame.data);
-                });
-            
-                let mut remote_pc_track = TrackRemotePointCloudRTP::new(track, some_on_frame_cb);
-                remote_pc_track.start(); 
-                // TODO: we should store this track somewhere so we can stop it when the connection is closed   
-            })
-        }));
+        let data_channel_config = *self.stream_manager.data_channel_config.read().unwrap();
+        if let Some(data_channel_config) = data_channel_config {
+            // Only negotiate the DataChannel, so the server does not send the frames over its RTP track as well
+            let data_channel = create_pointcloud_data_channel(&pc, &data_channel_config).await?;
+            let mut receiver = DataChannelPointCloudReceiver::new(data_channel, Self::on_frame_callback(self.pipeline.clone()));
+            receiver.start();
+            if let Some(mut previous_receiver) = self.data_channel_receiver.write().await.replace(receiver) {
+                previous_receiver.stop();
+            }
+        } else {
+            pc.add_transceiver_from_kind(RTPCodecType::Video, None)
+                .await
+                .map_err(|e| format!("add_transceiver_from_kind failed: {e}"))?;
+
+            let pipeline_clone = self.pipeline.clone();
+            pc.on_track(Box::new(move | track, _receiver, _transceiver| {
+                let some_on_frame_cb = Self::on_frame_callback(pipeline_clone.clone());
+                Box::pin(async move {
+                    info!("Created new track");
+                    let mut remote_pc_track = TrackRemotePointCloudRTP::new(track, some_on_frame_cb);
+                    remote_pc_track.start(); 
+                    // TODO: we should store this track somewhere so we can stop it when the connection is closed   
+                })
+            }));
+        }
 
         // Create the local SDP offer
         let offer = pc
@@ -155,6 +164,20 @@ impl WebRTCIngress {
         Ok(payload)
     }
 
+    /// Pass the received frames to the pipeline, for both the RTP track and the DataChannel.
+    fn on_frame_callback(pipeline: Arc<ProcessingPipeline>) -> Arc<dyn Fn(FrameTaskData) + Send + Sync> {
+        Arc::new(move |frame: FrameTaskData| {
+            // info!("Received frame with {} bytes", frame.data.len());
+
+            pipeline.ingest_data(
+                format!("client_{}_{}", frame.sfu_client_id.unwrap_or(0), frame.sfu_tile_index.unwrap_or(0)),
+                0,
+                frame.send_time,
+                frame.presentation_time,
+                frame.data);
+        })
+    }
+
     /// Handle the server's answer (SDP).
     #[instrument(skip(self, answer_sdp))]
     pub async fn handle_answer(&self, answer_sdp: String) -> Result<(), String> {
@@ -6,7 +6,9 @@ use webrtc::ice::candidate::candidate_base::unmarshal_candidate;
 use webrtc::ice::candidate::Candidate;
 use crate::services::stream_manager::StreamManager;
 use crate::processing::ProcessingPipeline;
+use shared_utils::frame_compression::FrameCompression;
 use shared_utils::types::FrameTaskData;
+use shared_utils::wire::decode_frame;
 use tracing::{debug, error, info, warn};
 use rbase64;
 
@@ -20,6 +22,8 @@ pub struct WebSocketIngress {
     pub runtime: Arc<Mutex<Runtime>>,
     webrtc_ingress: Arc<WebRTCIngress>,
     dash_ingress: Arc<DashIngress>,
+    /// Compression the server is asked to apply to the frames
+    compression: FrameCompression,
 }
 
 impl WebSocketIngress {
@@ -67,6 +71,7 @@ impl WebSocketIngress {
             runtime,
             webrtc_ingress,
             dash_ingress,
+            compression: *stream_manager.websocket_compression.read().unwrap(),
         });
 
         ingress.connect();
@@ -77,6 +82,7 @@ impl WebSocketIngress {
     fn process_payload(
         stream_id: String,
         payload: Payload,
+        compression: FrameCompression,
         processing_pipeline: Arc<ProcessingPipeline>,
     ) {
         let Payload::Binary(bytes) = payload else {
@@ -84,28 +90,26 @@ impl WebSocketIngress {
             return;
         };
 
-        // base64 decode using rbase64
-        let bytes_str = match std::str::from_utf8(&bytes) {
-            Ok(v) => v,
-            Err(e) => {
-                warn!("Invalid UTF-8 sequence: {}", e);
-                return;
-            },
-        };
-        let bytes_decoded = match rbase64::decode(bytes_str) {
-            Ok(decoded) => decoded,
-            Err(err) => {
-                warn!("Failed to decode payload: {}", err);
-                return;
-            },
+        // The server only compresses the frames once it handled our compression request,
+        // so the first frames can still arrive uncompressed
+        let decompressed = match compression {
+            FrameCompression::None => None,
+            _ => compression.decompress(&bytes).ok(),
         };
+        let decoded = decompressed
+            .and_then(|decompressed| decode_frame(&decompressed).ok())
+            .map(Ok)
+            .unwrap_or_else(|| decode_frame(&bytes));
 
-        // To vec
-        let frame_task_data = match bitcode::decode::<FrameTaskData>(&bytes_decoded) {
+        let frame_task_data = match decoded {
             Ok(decoded) => decoded,
-            Err(err) => {
-                warn!("Failed to decode payload: {}", err);
-                return;
+            Err(err) => match WebSocketIngress::decode_base64_frame(&bytes) {
+                // Older servers send the frames base64 encoded
+                Some(decoded) => decoded,
+                None => {
+                    warn!("Failed to decode payload: {}", err);
+                    return;
+                }
             },
         };
 
@@ -120,6 +124,12 @@ impl WebSocketIngress {
 
     }
 
+    fn decode_base64_frame(bytes: &[u8]) -> Option<FrameTaskData> {
+        let bytes_str = std::str::from_utf8(bytes).ok()?;
+        let bytes_decoded = rbase64::decode(bytes_str).ok()?;
+        decode_frame(&bytes_decoded).ok()
+    }
+
     pub fn get_socket(&self) -> Arc<Mutex<Option<Client>>> {
         Arc::clone(&self.socket)
     }
@@ -146,6 +156,7 @@ impl WebSocketIngress {
                 let runtime_clone = Arc::clone(&self.runtime);
                 let webrtc_ingress = Arc::clone(&self.webrtc_ingress);
                 let socket_id_ref = Arc::clone(&socket_id_ref);
+                let compression = self.compression;
                 move |payload: Payload, s: RawClient, ack: i32| {
                     // Acknowledge the event
                     let _ = s.ack(ack, "Ok".to_string());
@@ -168,6 +179,13 @@ impl WebSocketIngress {
                     let mut socket_id_lock = socket_id_ref.write().unwrap();
                     *socket_id_lock = Some(socket_id.clone().to_string());
 
+                    // Ask the server to compress the frames it broadcasts to us
+                    if compression != FrameCompression::None {
+                        if let Err(e) = s.emit::<&str, Value>("frame:compression", serde_json::json!(compression.name())) {
+                            error!("Failed to request {} compression: {:?}", compression.name(), e);
+                        }
+                    }
+
                     // Now that we are connected to the server, let's create our WebRTC offer.
                     // We must do this in a separate task (async).
                     let webrtc_ingress_clone = webrtc_ingress.clone();
@@ -314,18 +332,20 @@ impl WebSocketIngress {
             .on_with_ack("frame:broadcast:ack", {
                 let stream_id = self.get_stream_id();
                 let processing_pipeline = Arc::clone(&self.processing_pipeline);
+                let compression = self.compression;
                 move |payload: Payload, s: RawClient, ack: i32| {
                     let _ = s.ack(ack, "Ok".to_string());
                     debug!("Received frame broadcast with ack");
-                    WebSocketIngress::process_payload(stream_id.clone(), payload, Arc::clone(&processing_pipeline));
+                    WebSocketIngress::process_payload(stream_id.clone(), payload, compression, Arc::clone(&processing_pipeline));
                 }
             })
             .on("frame:broadcast", {
                 let stream_id = self.get_stream_id();
                 let processing_pipeline = Arc::clone(&self.processing_pipeline);
+                let compression = self.compression;
                 move |payload: Payload, _s: RawClient| {
                     debug!("Received frame broadcast without ack");
-                    WebSocketIngress::process_payload(stream_id.clone(), payload, Arc::clone(&processing_pipeline));
+                    WebSocketIngress::process_payload(stream_id.clone(), payload, compression, Arc::clone(&processing_pipeline));
                 }
             })
             .on("mpd::group_id",{
@@ -1,4 +1,5 @@
-use pc_receiver::{args::{get_log_level_filter, parse_args}, ingress::Ingress, utils::{create_metrics, start_metrics_server}};
+use pc_receiver::{args::{get_data_channel_config, get_log_level_filter, get_peer_connection_config, parse_args}, ingress::Ingress, utils::{create_metrics, start_metrics_server}};
+use shared_utils::frame_compression::FrameCompression;
 use tracing::{debug, error, info};
 use tracing_subscriber::{layer::SubscriberExt, Layer};
 use std::time::Duration;
@@ -46,8 +47,14 @@ fn main() {
     let ingress = Ingress::new(10, args.disable_parser);
     // Set the parameters first before initializing
     let stream_manager = ingress.get_stream_manager();
+    stream_manager.set_peer_connection_config(get_peer_connection_config(&args));
+    stream_manager.set_data_channel_config(get_data_channel_config(&args));
     stream_manager.set_websocket_url(args.server_url);
+    stream_manager.set_websocket_compression(FrameCompression::parse(&args.ws_compression).expect("Invalid WebSocket compression"));
     stream_manager.set_flute_url(args.multicast_url);
+    if let Some(payload_keys) = args.payload_keys.as_deref() {
+        stream_manager.payload_keys.insert_from_str(payload_keys).expect("Invalid payload keys");
+    }
     // Finish initializing the ingress system
     ingress.initialize();
 
@@ -78,7 +85,7 @@ fn main() {
                 let frames_to_skip = frames_in_buffer.saturating_sub(1); 
                 // e.g., skip all but the very last frame
                 let removed = storage.remove_oldest_frames(&stream_id, frames_to_skip);
-                storage.frames_skipped_total.add(removed as i64);
+                storage.frames_skipped_total.with_label_values(&[&stream_id]).inc_by(removed as u64);
                 if removed > 0 {
                     info!(
                         "Skipped {} oldest frames for stream_id = {} (too large backlog).",
@@ -2,7 +2,7 @@
 use bitcode::{Decode, Encode};
 use tracing::warn;
 
-use shared_utils::types::Point3D;
+use shared_utils::types::{Point3D, PointAttributes};
 
 use super::DecodeResult;
 
@@ -12,6 +12,14 @@ pub struct BitcodeData {
     pub points: Vec<Point3D>,
 }
 
+/// Points with attributes, identified by `BC2`.
+#[derive(Encode, Decode)]
+pub struct BitcodeDataV2 {
+    pub schema_version: u32,
+    pub points: Vec<Point3D>,
+    pub attributes: PointAttributes,
+}
+
 pub fn decode_bc_one_from_bytes(data: Vec<u8>) -> DecodeResult {
     // Make sure we at least have the 3 header bytes plus some payload
     if data.len() < 3 {
@@ -31,15 +39,37 @@ pub fn decode_bc_one_from_bytes(data: Vec<u8>) -> DecodeResult {
         },
     };
 
-    if bitcode_data.points.is_empty() {
+    flatten_points(bitcode_data.points)
+}
+
+/// Decode a frame with point attributes, only the xyz + rgb of the points are used for now.
+pub fn decode_bc_two_from_bytes(data: Vec<u8>) -> DecodeResult {
+    if data.len() < 3 {
+        warn!("Not enough data to contain BC2 header");
+        return Ok((1, Vec::new(), Vec::new()));
+    }
+
+    let bitcode_data = match bitcode::decode::<BitcodeDataV2>(&data[3..]) {
+        Ok(decoded) => decoded,
+        Err(err) => {
+            warn!("Failed to decode payload: {}", err);
+            return Ok((1, Vec::new(), Vec::new()));
+        },
+    };
+
+    flatten_points(bitcode_data.points)
+}
+
+pub(super) fn flatten_points(points: Vec<Point3D>) -> DecodeResult {
+    if points.is_empty() {
         // If there are no vertices, return no error and empty points.
         return Ok((0, Vec::new(), Vec::new()))
     }
 
     // Extract the vertex and color arrays and flatten them
-    let mut coords = Vec::with_capacity(bitcode_data.points.len()); // f32's in [x1,y1,z1, x2,y2,z2, ...]
-    let mut colors = Vec::with_capacity(bitcode_data.points.len());
-    for point in bitcode_data.points {
+    let mut coords = Vec::with_capacity(points.len()); // f32's in [x1,y1,z1, x2,y2,z2, ...]
+    let mut colors = Vec::with_capacity(points.len());
+    for point in points {
         // Add the coordinates to the coords array
         coords.push(point.x);
         coords.push(point.y);
@@ -0,0 +1,24 @@
+use std::sync::Mutex;
+
+use tracing::{debug, warn};
+
+use shared_utils::delta::DeltaDecoder;
+
+use super::bitcode::flatten_points;
+use super::DecodeResult;
+
+/// Decodes a delta coded frame with the decoder of its stream, which holds the keyframes the deltas refer to.
+pub fn decode_delta_from_bytes(data: Vec<u8>, decoder: &Mutex<DeltaDecoder>) -> DecodeResult {
+    match decoder.lock().unwrap().decode(&data) {
+        Ok(Some(points)) => flatten_points(points),
+        Ok(None) => {
+            // Not an error, e.g. right after joining the stream
+            debug!("Skipping a delta of a keyframe that was not received, waiting for the next keyframe");
+            Ok((0, Vec::new(), Vec::new()))
+        }
+        Err(err) => {
+            warn!("Failed to decode delta frame: {}", err);
+            Ok((1, Vec::new(), Vec::new()))
+        }
+    }
+}
@@ -1,16 +1,28 @@
+use std::sync::Mutex;
 use tracing::error;
-pub use draco_wrapper::decode_draco;
+pub use draco_wrapper::decode_draco_into;
 
 use super::DecodeResult;
 
-pub fn decode_draco_from_bytes(data: Vec<u8>) -> DecodeResult {
+/// The buffers the frames of a stream are decoded into, which keep their allocation across the frames of the stream.
+#[derive(Debug, Default)]
+pub struct DracoBuffers {
+    coordinates: Vec<f32>,
+    colors: Vec<u8>,
+}
+
+pub fn decode_draco_from_bytes(data: Vec<u8>, buffers: &Mutex<DracoBuffers>) -> DecodeResult {
     // info!("Decoding Draco data of length: {}", data.len());
+    // Frames of the stream that are decoded at the same time do not wait for the buffers, they decode into their own
+    let mut own_buffers = DracoBuffers::default();
+    let mut stream_buffers = buffers.try_lock().ok();
+    let buffers = stream_buffers.as_deref_mut().unwrap_or(&mut own_buffers);
     // Call the decode function from the DracoWrapper
-    match decode_draco(data) {
-        Ok((vertices, colors)) => {
+    match decode_draco_into(&data, &mut buffers.coordinates, &mut buffers.colors) {
+        Ok(_) => {
             // info!("Successfully decoded Draco data");
-            // No errors, return 0 errors, along with decoded vertices and colors
-            Ok((0, vertices, colors))
+            // No errors, return 0 errors, along with the decoded vertices and colors, the buffers are kept for the next frame
+            Ok((0, buffers.coordinates.clone(), buffers.colors.clone()))
         }
         Err(e) => {
             error!("Error decoding Draco data: {}", e);
@@ -2,14 +2,31 @@ pub mod ply;
 pub mod draco;
 pub mod tmf;
 pub mod bitcode;
+pub mod delta;
+
+use std::sync::Mutex;
 
 use tracing::error;
 
+use shared_utils::delta::DeltaDecoder;
+use shared_utils::error::{XrError, XrResult};
+
 use crate::types::FrameData;
 
-type DecodeResult = Result<(u64, Vec<f32>, Vec<u8>), Box<dyn std::error::Error>>;
+use self::draco::DracoBuffers;
+
+type DecodeResult = XrResult<(u64, Vec<f32>, Vec<u8>)>;
+
+/// The decoder state of one stream, which is kept across its frames.
+#[derive(Debug, Default)]
+pub struct StreamDecoder {
+    /// Holds the keyframes the delta coded frames of the stream refer to
+    delta: Mutex<DeltaDecoder>,
+    draco: Mutex<DracoBuffers>,
+}
 
-pub fn decode_data(send_time: u64, presentation_time: u64, data: Vec<u8>) -> Result<FrameData, Box<dyn std::error::Error>> {
+/// Decodes a frame with the decoder state of its stream.
+pub fn decode_data(send_time: u64, presentation_time: u64, data: Vec<u8>, decoder: &StreamDecoder) -> XrResult<FrameData> {
     let (error_count, vertices, colors) = if data.is_empty() || data.len() < 3 {
         error!("Data is empty or too short, returning error");
         // If the data is empty or too short, return an error
@@ -17,10 +34,12 @@ pub fn decode_data(send_time: u64, presentation_time: u64, data: Vec<u8>) -> Res
     } else {
         match &data[0..3] {
             b"ply" => ply::decode_ply_from_bytes(data)?,
-            b"DRA" => draco::decode_draco_from_bytes(data)?,
+            b"DRA" => draco::decode_draco_from_bytes(data, &decoder.draco)?,
             b"TMF" => tmf::decode_tmf_from_bytes(data)?,
             b"BC1" => bitcode::decode_bc_one_from_bytes(data)?,
-            _ => return Err("Unsupported data format".into()),
+            b"BC2" => bitcode::decode_bc_two_from_bytes(data)?,
+            b"DLT" => delta::decode_delta_from_bytes(data, &decoder.delta)?,
+            _ => return Err(XrError::Codec("Unsupported data format".to_string())),
         }
     };
     let point_count = (vertices.len() / 3) as u64;
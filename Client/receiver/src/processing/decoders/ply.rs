@@ -1,6 +1,7 @@
 use std::io::{Cursor, BufReader};
 use ply_rs::parser::Parser;
 
+use shared_utils::error::XrError;
 use shared_utils::types::Point3D;
 
 use super::DecodeResult;
@@ -9,7 +10,7 @@ pub fn decode_ply_from_bytes(data: Vec<u8>) -> DecodeResult {
     let cursor = Cursor::new(data);
     let mut reader = BufReader::new(cursor);
     let parser = Parser::<Point3D>::new();
-    let header = parser.read_header(&mut reader)?;
+    let header = parser.read_header(&mut reader).map_err(XrError::codec)?;
 
     let vertex_count: usize = header.elements.iter().filter(|e| e.name == "vertex").map(|e| e.count).sum();
 
@@ -19,7 +20,7 @@ pub fn decode_ply_from_bytes(data: Vec<u8>) -> DecodeResult {
 
     for element in &header.elements {
         if element.name == "vertex" {
-            let vertex_list = parser.read_payload_for_element(&mut reader, element, &header)?;
+            let vertex_list = parser.read_payload_for_element(&mut reader, element, &header).map_err(XrError::codec)?;
             for vertex in vertex_list {
                 vertices.push(vertex.x);
                 vertices.push(vertex.y);
@@ -1,7 +1,8 @@
-use std::{sync::{Arc, Mutex}, time::{SystemTime, UNIX_EPOCH}};
+use std::{collections::HashMap, sync::{Arc, Mutex}, time::{SystemTime, UNIX_EPOCH}};
 use crate::{storage::Storage, types::FrameData};
-use crate::processing::decoders::decode_data;
+use crate::processing::decoders::{decode_data, StreamDecoder};
 use rayon::{ThreadPoolBuilder, ThreadPool};
+use shared_utils::payload_crypto::PayloadKeyRing;
 use tokio::runtime::{Builder, Runtime};
 use tracing::{debug, error};
 
@@ -9,13 +10,16 @@ pub mod decoders;
 
 pub struct ProcessingPipeline {
     storage: Arc<Storage>,
+    payload_keys: Arc<PayloadKeyRing>,
+    /// The decoder state of every stream, e.g. the keyframes of its delta coded frames
+    decoders: Arc<Mutex<HashMap<String, Arc<StreamDecoder>>>>,
     thread_pool: Arc<ThreadPool>,
     pub runtime: Arc<Mutex<Runtime>>,
     disable_parser: bool,
 }
 
 impl ProcessingPipeline {
-    pub fn new(storage: Arc<Storage>, thread_count: usize, disable_parser: bool) -> Self {// Initialize thread pool
+    pub fn new(storage: Arc<Storage>, payload_keys: Arc<PayloadKeyRing>, thread_count: usize, disable_parser: bool) -> Self {// Initialize thread pool
         let thread_pool = Arc::new(
             ThreadPoolBuilder::new()
                 .num_threads(thread_count)
@@ -36,6 +40,8 @@ impl ProcessingPipeline {
 
         Self {
             storage,
+            payload_keys,
+            decoders: Arc::new(Mutex::new(HashMap::new())),
             thread_pool,
             runtime,
             disable_parser
@@ -47,8 +53,10 @@ impl ProcessingPipeline {
         let storage = self.storage.clone();
         let thread_pool = self.thread_pool.clone();
         let disable_parser = self.disable_parser;
+        let payload_keys = self.payload_keys.clone();
+        let decoder = self.decoders.lock().unwrap().entry(stream_id.clone()).or_default().clone();
 
-        storage.quality_metric.set(quality as i64);
+        storage.quality_metric.with_label_values(&[&stream_id]).set(quality as i64);
 
         thread_pool.spawn(move || {
             // info!("Processing frame data for stream_id: {} and send_time {}, length: {}", stream_id, send_time, presentation_time);
@@ -64,7 +72,7 @@ impl ProcessingPipeline {
                     colors: vec![255, 255, 255],
                 })
             } else {
-                decode_data(send_time, presentation_time, data.to_owned())
+                payload_keys.decrypt(data).and_then(|data| decode_data(send_time, presentation_time, data, &decoder))
             };
             match frame_data {
                 Ok(mut frame_data) => {
@@ -84,12 +92,12 @@ impl ProcessingPipeline {
                             return;
                         }
                     };
-                    storage.clone().decode_time.set(decode_duration as i64);
+                    storage.clone().decode_time.observe(decode_duration as f64);
 
 
                     frame_data.receive_time = start_time.duration_since(UNIX_EPOCH).unwrap().as_micros() as u64;
                     let send_to_receive = frame_data.receive_time.saturating_sub(frame_data.send_time);
-                    storage.clone().send_to_receive_time_diff.set(send_to_receive as i64);
+                    storage.clone().send_to_receive_time_diff.observe(send_to_receive as f64);
 
                     storage.insert_frame(stream_id, frame_data);
                 }
@@ -1,4 +1,5 @@
 use std::sync::{Arc, RwLock};
+use shared_utils::{datachannel_pointcloud::DataChannelConfig, frame_compression::FrameCompression, payload_crypto::PayloadKeyRing, peer_connection::PeerConnectionConfig};
 use crate::ingress::dash::DashIngress;
 use crate::ingress::flute::FluteIngress;
 use crate::ingress::websocket::WebSocketIngress;
@@ -11,6 +12,13 @@ pub struct StreamManager {
     pub flute_ingress: RwLock<Option<Arc<FluteIngress>>>,
     pub websocket_url: RwLock<Option<String>>,
     pub flute_url: RwLock<Option<String>>,
+    pub peer_connection_config: RwLock<PeerConnectionConfig>,
+    /// Receive the WebRTC frames on a DataChannel with these settings instead of on an RTP track
+    pub data_channel_config: RwLock<Option<DataChannelConfig>>,
+    /// Keys to decrypt the frames of streams that the server encrypts, shared with the processing pipeline
+    pub payload_keys: Arc<PayloadKeyRing>,
+    /// Compression the WebSocket ingress asks the server to apply to the frames
+    pub websocket_compression: RwLock<FrameCompression>,
 }
 
 impl StreamManager {
@@ -22,6 +30,10 @@ impl StreamManager {
             flute_ingress: RwLock::new(None),
             websocket_url: RwLock::new(None),
             flute_url: RwLock::new(None),
+            peer_connection_config: RwLock::new(PeerConnectionConfig::default()),
+            data_channel_config: RwLock::new(None),
+            payload_keys: Arc::new(PayloadKeyRing::default()),
+            websocket_compression: RwLock::new(FrameCompression::None),
         }
     }
 
@@ -45,7 +57,19 @@ impl StreamManager {
         *self.websocket_url.write().unwrap() = Some(url);
     }
 
+    pub fn set_websocket_compression(&self, compression: FrameCompression) {
+        *self.websocket_compression.write().unwrap() = compression;
+    }
+
     pub fn set_flute_url(&self, url: String) {
         *self.flute_url.write().unwrap() = Some(url);
     }
+
+    pub fn set_peer_connection_config(&self, config: PeerConnectionConfig) {
+        *self.peer_connection_config.write().unwrap() = config;
+    }
+
+    pub fn set_data_channel_config(&self, config: Option<DataChannelConfig>) {
+        *self.data_channel_config.write().unwrap() = config;
+    }
 }
@@ -1,27 +1,28 @@
 use std::sync::{Arc, RwLock};
 use std::collections::HashMap;
-use std::time::{SystemTime, UNIX_EPOCH};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 use crate::types::FrameData;
 use circular_buffer::CircularBuffer;
-use metrics::get_metrics;
-use prometheus::IntGauge;
+use metrics::{duration_buckets_us, get_metrics};
+use prometheus::{Histogram, IntCounterVec, IntGauge, IntGaugeVec};
 use tracing::info;
 
 pub struct Storage {
     buffers: RwLock<HashMap<String, Arc<RwLock<CircularBuffer<30, FrameData>>>>>,
     last_consumed_point_counts: RwLock<HashMap<String, u64>>,
-    pub reception_time_flute: IntGauge,
-    pub frames_consumed_total: IntGauge,
-    pub frames_received_total: IntGauge,
-    pub frames_skipped_total: IntGauge,
+    last_insert_times: RwLock<HashMap<String, Instant>>,
+    pub reception_time_flute: Histogram,
+    pub frames_consumed_total: IntCounterVec,
+    pub frames_received_total: IntCounterVec,
+    pub frames_skipped_total: IntCounterVec,
     pub current_backlog: IntGauge,
-    pub send_to_receive_time_diff: IntGauge,
-    pub send_to_consume_time_diff: IntGauge,
-    pub receive_to_consume_time_diff: IntGauge,
-    pub point_count_metric: IntGauge,
-    pub decode_time: IntGauge,
+    pub send_to_receive_time_diff: Histogram,
+    pub send_to_consume_time_diff: Histogram,
+    pub receive_to_consume_time_diff: Histogram,
+    pub point_count_metric: IntGaugeVec,
+    pub decode_time: Histogram,
     pub total_point_count: IntGauge,
-    pub quality_metric: IntGauge,
+    pub quality_metric: IntGaugeVec,
 }
 
 impl Default for Storage {
@@ -36,33 +37,37 @@ impl Storage {
 
         // Example metrics:
         let reception_time_flute = metrics
-            .get_or_create_gauge(
+            .get_or_create_histogram(
                 "reception_time_flute",
-                "Time (ms) it took to receive a FLUTE object",
+                "Time (us) it took to receive a FLUTE object",
+                duration_buckets_us(),
             )
-            .expect("Failed to create reception_time_flute gauge");
+            .expect("Failed to create reception_time_flute histogram");
 
         let frames_consumed_total = metrics
-            .get_or_create_gauge(
+            .get_or_create_counter_vec(
                 "frames_consumed_total",
                 "Total number of frames consumed properly",
+                &["stream_id"],
             )
-            .expect("Failed to create frames_consumed_total gauge");
+            .expect("Failed to create frames_consumed_total counter vector");
 
 
         let frames_received_total = metrics
-        .get_or_create_gauge(
+        .get_or_create_counter_vec(
             "frames_received_total",
             "Total number of frames that have been received",
+            &["stream_id"],
         )
-        .expect("Failed to create frames_received_total gauge");
+        .expect("Failed to create frames_received_total counter vector");
 
         let frames_skipped_total = metrics
-            .get_or_create_gauge(
+            .get_or_create_counter_vec(
                 "frames_skipped_total",
                 "Total number of frames skipped due to backlog",
+                &["stream_id"],
             )
-            .expect("Failed to create frames_skipped_total gauge");
+            .expect("Failed to create frames_skipped_total counter vector");
 
         let current_backlog = metrics
             .get_or_create_gauge(
@@ -72,36 +77,41 @@ impl Storage {
             .expect("Failed to create current_backlog gauge");
 
             let send_to_receive_time_diff = metrics
-            .get_or_create_gauge(
+            .get_or_create_histogram(
                 "send_to_receive_time_diff",
-                "Difference (ms) between send time and receive time of a frame",
+                "Difference (us) between send time and receive time of a frame",
+                duration_buckets_us(),
             )
-            .expect("Failed to create send_to_receive_time_diff gauge");
+            .expect("Failed to create send_to_receive_time_diff histogram");
 
         let send_to_consume_time_diff = metrics
-            .get_or_create_gauge(
+            .get_or_create_histogram(
                 "send_to_consume_time_diff",
-                "Difference (ms) between send time and consume time of a frame",
+                "Difference (us) between send time and consume time of a frame",
+                duration_buckets_us(),
             )
-            .expect("Failed to create send_to_consume_time_diff gauge");
+            .expect("Failed to create send_to_consume_time_diff histogram");
 
         let receive_to_consume_time_diff = metrics
-            .get_or_create_gauge(
+            .get_or_create_histogram(
                 "receive_to_consume_time_diff",
-                "Difference (ms) between receive time and consume time of a frame",
+                "Difference (us) between receive time and consume time of a frame",
+                duration_buckets_us(),
             )
-            .expect("Failed to create receive_to_consume_time_diff gauge");
+            .expect("Failed to create receive_to_consume_time_diff histogram");
 
         let point_count_metric = metrics
-            .get_or_create_gauge(
+            .get_or_create_gauge_vec(
                 "point_count_metric",
                 "Number of points in the last consumed frame",
+                &["stream_id"],
             )
-            .expect("Failed to create point_count_metric gauge");
+            .expect("Failed to create point_count_metric gauge vector");
 
-        let decode_time = metrics.get_or_create_gauge(
+        let decode_time = metrics.get_or_create_histogram(
             "decoding_time", 
-            "Time taken to decode a frame").unwrap();
+            "Time (us) taken to decode a frame",
+            duration_buckets_us()).unwrap();
 
         let total_point_count = metrics.get_or_create_gauge(
                 "total_point_count",
@@ -109,15 +119,17 @@ impl Storage {
             ).unwrap();
 
         let quality_metric = metrics
-            .get_or_create_gauge(
+            .get_or_create_gauge_vec(
                 "quality_metric",
                 "Quality id of the stream",
+                &["stream_id"],
             )
-            .expect("Failed to create quality_metric gauge");
+            .expect("Failed to create quality_metric gauge vector");
 
         Storage {
             buffers: RwLock::new(HashMap::new()),
             last_consumed_point_counts: RwLock::new(HashMap::new()),
+            last_insert_times: RwLock::new(HashMap::new()),
             reception_time_flute,
             frames_consumed_total,
             frames_received_total,
@@ -152,11 +164,42 @@ impl Storage {
             let mut b = buffer.write().unwrap();
             if b.is_full() {
                 // The first frame will be dropped by this circular buffer
-                self.frames_skipped_total.inc();
+                self.frames_skipped_total.with_label_values(&[&stream_id]).inc();
             }
             b.push_back(frame)
         }
-        self.frames_received_total.inc();
+        self.frames_received_total.with_label_values(&[&stream_id]).inc();
+        self.last_insert_times.write().unwrap().insert(stream_id, Instant::now());
+    }
+
+    /// Remove the buffers of the streams that did not receive a frame for `timeout`.
+    /// Returns the ids of the removed streams, so their metrics can be removed as well.
+    pub fn remove_inactive_streams(&self, timeout: Duration) -> Vec<String> {
+        let inactive_stream_ids: Vec<String> = {
+            let mut last_insert_times = self.last_insert_times.write().unwrap();
+            let inactive_stream_ids = last_insert_times
+                .iter()
+                .filter(|(_, last_insert_time)| last_insert_time.elapsed() > timeout)
+                .map(|(stream_id, _)| stream_id.clone())
+                .collect();
+            last_insert_times.retain(|_, last_insert_time| last_insert_time.elapsed() <= timeout);
+            inactive_stream_ids
+        };
+        if inactive_stream_ids.is_empty() {
+            return inactive_stream_ids;
+        }
+
+        {
+            let mut buffers = self.buffers.write().unwrap();
+            let mut last_consumed_point_counts = self.last_consumed_point_counts.write().unwrap();
+            for stream_id in &inactive_stream_ids {
+                info!("Removing inactive stream_id = {}", stream_id);
+                buffers.remove(stream_id);
+                last_consumed_point_counts.remove(stream_id);
+            }
+        }
+        self.total_point_count.set(self.get_total_point_count() as i64);
+        inactive_stream_ids
     }
 
     pub fn get_stream_ids(&self) -> Vec<String> {
@@ -196,7 +239,7 @@ impl Storage {
                 }
                 buffer.pop_front();
                 removed += 1;
-                self.frames_skipped_total.inc();
+                self.frames_skipped_total.with_label_values(&[stream_id]).inc();
             }
             removed
         } else {
@@ -235,7 +278,7 @@ impl Storage {
                             // remove it
                             info!("Removing frame older than 5s for stream_id = {}", stream_id);
                             buffer.pop_front();
-                            self.frames_skipped_total.inc();
+                            self.frames_skipped_total.with_label_values(&[stream_id]).inc();
                         } else {
                             // if not older than 5s, break out
                             break;
@@ -272,14 +315,14 @@ impl Storage {
                 if frame_index > 0 {
                     for _ in 0..frame_index {
                         buffer.pop_front();
-                        self.frames_skipped_total.inc();
+                        self.frames_skipped_total.with_label_values(&[stream_id]).inc();
                     }
                     info!("Skipped {} frames for stream_id = {} (catch-up).", frame_index, stream_id);
                 }
             }
 
 
-            self.frames_consumed_total.inc();
+            self.frames_consumed_total.with_label_values(&[stream_id]).inc();
 
             // Pop and store the "best" frame
             let consumed_frame = buffer.pop_front();
@@ -289,9 +332,9 @@ impl Storage {
                 let send_to_consume = current_time.saturating_sub(frame.send_time);
                 let receive_to_consume = current_time.saturating_sub(frame.receive_time);
 
-                self.send_to_consume_time_diff.set(send_to_consume as i64);
-                self.receive_to_consume_time_diff.set(receive_to_consume as i64);
-                self.point_count_metric.set(frame.point_count as i64);
+                self.send_to_consume_time_diff.observe(send_to_consume as f64);
+                self.receive_to_consume_time_diff.observe(receive_to_consume as f64);
+                self.point_count_metric.with_label_values(&[stream_id]).set(frame.point_count as i64);
 
                 self.last_consumed_point_counts
                     .write()
@@ -1,8 +1,8 @@
-use std::sync::Arc;
+use std::time::Duration;
 
 use metrics::{get_all_interfaces, MetricsBuilder, start_server};
 use tokio::runtime::Builder;
-use tracing::{debug, error, info};
+use tracing::{error, info};
 
 
 pub fn create_metrics() -> Result<(), Box<dyn std::error::Error>> {
@@ -15,7 +15,7 @@ pub fn create_metrics() -> Result<(), Box<dyn std::error::Error>> {
     info!("Tracking the following interfaces: {:?}", interfaces);
 
     // Build the metrics instance, tracking all interfaces
-    let mut builder = MetricsBuilder::new().add_label("mode", "client");
+    let mut builder = MetricsBuilder::new().add_label("mode", "client").track_process();
 
     for interface in interfaces {
         builder = builder.track_interface(&interface);
@@ -26,15 +26,7 @@ pub fn create_metrics() -> Result<(), Box<dyn std::error::Error>> {
     // Start the metrics update loop
     // These are for some default system metrics
     // We are responsible for updating your custom metrics
-    let metrics_clone = Arc::new(metrics);
-    std::thread::spawn(move || {
-        let interval = std::time::Duration::from_secs(1);
-        loop {
-            metrics_clone.update();
-            debug!("Metrics updated");
-            std::thread::sleep(interval);
-        }
-    });
+    metrics.start_updater(Duration::from_secs(1));
     Ok(())
 }
 
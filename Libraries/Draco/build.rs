@@ -150,6 +150,9 @@ fn main() {
         .allowlist_type("draco::DecoderBuffer")
         .allowlist_type("draco::PointCloud")
         .allowlist_type("draco_wrapper::DracoWrapper")
+        .allowlist_type("draco_wrapper::EncodeOptions")
+        .allowlist_type("draco_wrapper::AttributeDataType")
+        .allowlist_type("draco_wrapper::ColorFormat")
         .opaque_type("std::.*") // Rust bindgen is not fully compatible with the C++ standard library
         .generate_comments(true)
         .generate_inline_functions(true) // Required for buffer.size() and buffer.data() functions
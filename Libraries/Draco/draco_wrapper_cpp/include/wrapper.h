@@ -2,19 +2,24 @@
 #define DRACO_WRAPPER_CPP_H
 
 #include <draco/compression/encode.h>
+#include <draco/compression/expert_encode.h>
 #include <draco/compression/decode.h>
 #include <draco/core/encoder_buffer.h>
 #include <draco/core/decoder_buffer.h>
 #include <draco/point_cloud/point_cloud.h>
+#include <draco/mesh/mesh.h>
 #include <draco/point_cloud/point_cloud_builder.h>
 #include <draco/attributes/geometry_attribute.h>
 #include <draco/attributes/geometry_indices.h>
+#include <draco/metadata/geometry_metadata.h>
 #include <stdint.h>
 #include <stddef.h>
 #include <stdexcept>
 #include <vector>
 #include <cstring>
 #include <utility>
+#include <algorithm>
+#include <limits>
 
 #ifdef __cplusplus
 extern "C" {
@@ -23,6 +28,46 @@ extern "C" {
 
 namespace draco_wrapper {
 
+struct EncodeOptions {
+    int position_quantization_bits; // Quantization bits for the positions (1-30)
+    int color_quantization_bits;    // Significant bits kept for each color channel (1-8, or 1-16 for 16-bit colors)
+    int normal_quantization_bits;   // Quantization bits for the normals (1-30)
+    int tex_coord_quantization_bits; // Quantization bits for the texture coordinates of meshes (1-30)
+    int encode_speed;               // Encoding speed (0 = best compression, 10 = fastest)
+    int decode_speed;               // Decoding speed (0 = best compression, 10 = fastest)
+    int encoding_method;            // draco::PointCloudEncodingMethod of point clouds, ignored for meshes
+    int color_format;               // ColorFormat of the point cloud colors, ignored for meshes
+};
+
+enum ColorFormat {
+    COLOR_RGB8 = 0,
+    COLOR_RGBA8 = 1,
+    COLOR_RGB16 = 2,
+    COLOR_RGBA16 = 3,
+};
+
+enum AttributeDataType {
+    ATTRIBUTE_UINT8 = 0,
+    ATTRIBUTE_UINT16 = 1,
+    ATTRIBUTE_UINT32 = 2,
+    ATTRIBUTE_INT32 = 3,
+    ATTRIBUTE_FLOAT32 = 4,
+};
+
+struct CustomAttribute {
+    const char* name;          // Name of the attribute, stored in the attribute metadata
+    int data_type;             // One of AttributeDataType
+    int num_components;        // Number of values per point
+    int quantization_bits;     // Quantization bits for float attributes (1-30), ignored for integer attributes
+    const void* data;          // `num_points` * `num_components` values of the given data type
+};
+
+struct MetadataEntry {
+    const char* key;           // Key of the entry
+    const uint8_t* value;      // Value of the entry
+    size_t value_size;         // Length of the value in bytes
+};
+
 struct EncodeResult {
     bool success;              // Indicates if encoding was successful
     size_t size;               // Size of the encoded data
@@ -34,26 +79,104 @@ struct DecodeResult {
     bool success;              // Indicates if decoding was successful
     size_t num_points;         // Number of points in the decoded data
     float* coords;            // Decoded coordinates
-    uint8_t* colors;           // Decoded colors
+    uint8_t* colors;           // Decoded colors, converted to 8-bit RGB
+    int color_format;          // ColorFormat the colors were encoded with
+    void* native_colors;       // Decoded colors in `color_format`, null if that is 8-bit RGB or they were not requested
+    float* normals;            // Decoded normals, null if the point cloud has no normals
+    CustomAttribute* attributes; // Decoded custom attributes
+    size_t num_attributes;     // Number of decoded custom attributes
+    MetadataEntry* metadata;   // Decoded frame metadata
+    size_t num_metadata;       // Number of decoded metadata entries
+    char* error_msg;           // Error message if decoding fails
+};
+
+struct MeshDecodeResult {
+    bool success;              // Indicates if decoding was successful
+    size_t num_vertices;       // Number of vertices in the decoded mesh
+    size_t num_faces;          // Number of triangles in the decoded mesh
+    float* positions;          // Decoded vertex positions
+    uint32_t* indices;         // Decoded vertex indices, three per triangle
+    float* tex_coords;         // Decoded texture coordinates, null if the mesh has none
+    uint8_t* colors;           // Decoded vertex colors, null if the mesh has none
     char* error_msg;           // Error message if decoding fails
 };
 
+// Reusable encoder, created by DracoWrapper::create_encoder
+struct EncoderHandle;
+
+// Reusable decoder, created by DracoWrapper::create_decoder
+struct DecoderHandle;
+
 class DracoWrapper {
 public:
     // Function to encode points to Draco
     // `coords` is an array of `num_points` * 3 floats, representing X, Y, Z for each point
-    // `colors` is an array of `num_points` * 3 uint8_t, representing R, G, B for each point
-    static EncodeResult* encode_points_to_draco(const float* coords, size_t num_points, const uint8_t* colors);
+    // `colors` is an array of `num_points` * 3 or 4 values of 8 or 16 bits, as given by the color format of the options
+    // `normals` is an optional array of `num_points` * 3 floats, representing the normal vector of each point
+    // `attributes` is an array of `num_attributes` custom attributes that are stored alongside the points
+    // `metadata` is an array of `num_metadata` key/value entries that are stored in the frame
+    // `options` configures the quantization and speed of the encoder, the default settings are used if it is null
+    static EncodeResult* encode_points_to_draco(const float* coords, size_t num_points, const uint8_t* colors, const float* normals, const CustomAttribute* attributes, size_t num_attributes, const MetadataEntry* metadata, size_t num_metadata, const EncodeOptions* options);
 
     // Function to decode Draco data into points and colors
+    // The colors are also available in the format they were encoded with
     // `encoded_data` is a pointer to the encoded buffer, and `encoded_size` is the buffer length
     static DecodeResult* decode_draco_data(const uint8_t* encoded_data, size_t encoded_size);
 
+    // Function to decode the points and colors of Draco data into caller-provided buffers
+    // `coords` and `colors` must each have room for `capacity` * 3 values, the result does not own them
+    // The colors are always converted to 8-bit RGB
+    // If the point cloud has more than `capacity` points, the points are decoded into `coords` and `colors` of the result instead
+    static DecodeResult* decode_draco_into(const uint8_t* encoded_data, size_t encoded_size, float* coords, uint8_t* colors, size_t capacity);
+
+    // Function to decode a subset of the points and colors of Draco data, e.g. for a low-density preview
+    // Every `stride`-th point is kept, up to `max_points` points in total, the normals and custom attributes are skipped
+    // Draco has no progressive bitstream, so the whole frame is still decoded, only the copying of the skipped points is avoided
+    static DecodeResult* decode_draco_subset(const uint8_t* encoded_data, size_t encoded_size, size_t max_points, size_t stride);
+
+    // Function to create a reusable encoder, which keeps its output buffer across frames
+    // `options` configures the quantization and speed of the encoder, the default settings are used if it is null
+    static EncoderHandle* create_encoder(const EncodeOptions* options);
+
+    // Function to encode points with a reusable encoder, with the same input as `encode_points_to_draco`
+    // `color_format` is the ColorFormat of `colors`, the color format of the encoder options is ignored
+    // The result is owned by the encoder and stays valid until the next encode or until the encoder is freed
+    static const EncodeResult* encoder_encode_points(EncoderHandle* encoder, const float* coords, size_t num_points, const uint8_t* colors, const float* normals, const CustomAttribute* attributes, size_t num_attributes, const MetadataEntry* metadata, size_t num_metadata, int color_format);
+
+    // Function to free a reusable encoder
+    static void free_encoder(EncoderHandle* encoder);
+
+    // Function to create a reusable decoder, which keeps its output buffers across frames
+    static DecoderHandle* create_decoder();
+
+    // Function to decode the points and colors of Draco data with a reusable decoder
+    // The colors are always converted to 8-bit RGB, the normals and custom attributes are skipped
+    // The result is owned by the decoder and stays valid until the next decode or until the decoder is freed
+    static const DecodeResult* decoder_decode(DecoderHandle* decoder, const uint8_t* encoded_data, size_t encoded_size);
+
+    // Function to free a reusable decoder
+    static void free_decoder(DecoderHandle* decoder);
+
+    // Function to encode a triangle mesh to Draco
+    // `positions` is an array of `num_vertices` * 3 floats, representing X, Y, Z for each vertex
+    // `indices` is an array of `num_faces` * 3 vertex indices, one triangle per three indices
+    // `tex_coords` is an optional array of `num_vertices` * 2 floats, representing U, V for each vertex
+    // `colors` is an optional array of `num_vertices` * 3 uint8_t, representing R, G, B for each vertex
+    // `options` configures the quantization and speed of the encoder, the default settings are used if it is null
+    static EncodeResult* encode_mesh_to_draco(const float* positions, size_t num_vertices, const uint32_t* indices, size_t num_faces, const float* tex_coords, const uint8_t* colors, const EncodeOptions* options);
+
+    // Function to decode Draco data into a triangle mesh
+    // `encoded_data` is a pointer to the encoded buffer, and `encoded_size` is the buffer length
+    static MeshDecodeResult* decode_draco_mesh(const uint8_t* encoded_data, size_t encoded_size);
+
     // Function to free the encoded result
     static void free_encode_result(EncodeResult* result);
 
     // Function to free the decoded result
     static void free_decode_result(DecodeResult* result);
+
+    // Function to free the decoded mesh result
+    static void free_mesh_decode_result(MeshDecodeResult* result);
 };
 
 } // namespace draco_wrapper
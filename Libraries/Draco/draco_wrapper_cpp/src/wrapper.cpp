@@ -7,65 +7,276 @@ extern "C" {
 
 namespace draco_wrapper {
 
+// Settings that are used when no encoder options are passed
+static const EncodeOptions DEFAULT_ENCODE_OPTIONS = { 11, 8, 10, 12, 5, 5, draco::POINT_CLOUD_KD_TREE_ENCODING, COLOR_RGB8 };
+
+// Name of the metadata entry that identifies a custom attribute
+static const char* ATTRIBUTE_NAME_ENTRY = "name";
+
+// Maps an AttributeDataType to the corresponding Draco data type
+static bool to_draco_data_type(int data_type, draco::DataType* draco_type) {
+    switch (data_type) {
+        case ATTRIBUTE_UINT8: *draco_type = draco::DT_UINT8; return true;
+        case ATTRIBUTE_UINT16: *draco_type = draco::DT_UINT16; return true;
+        case ATTRIBUTE_UINT32: *draco_type = draco::DT_UINT32; return true;
+        case ATTRIBUTE_INT32: *draco_type = draco::DT_INT32; return true;
+        case ATTRIBUTE_FLOAT32: *draco_type = draco::DT_FLOAT32; return true;
+        default: return false;
+    }
+}
+
+// Maps a Draco data type back to the corresponding AttributeDataType
+static bool from_draco_data_type(draco::DataType draco_type, int* data_type) {
+    switch (draco_type) {
+        case draco::DT_UINT8: *data_type = ATTRIBUTE_UINT8; return true;
+        case draco::DT_UINT16: *data_type = ATTRIBUTE_UINT16; return true;
+        case draco::DT_UINT32: *data_type = ATTRIBUTE_UINT32; return true;
+        case draco::DT_INT32: *data_type = ATTRIBUTE_INT32; return true;
+        case draco::DT_FLOAT32: *data_type = ATTRIBUTE_FLOAT32; return true;
+        default: return false;
+    }
+}
+
+// Number of channels of a ColorFormat
+static int color_channels(int color_format) {
+    return (color_format == COLOR_RGBA8 || color_format == COLOR_RGBA16) ? 4 : 3;
+}
+
+// Bits per channel of a ColorFormat
+static int color_bits(int color_format) {
+    return (color_format == COLOR_RGB16 || color_format == COLOR_RGBA16) ? 16 : 8;
+}
+
+// Determines the ColorFormat of a decoded color attribute
+static int color_format_of(const draco::PointAttribute* color_att) {
+    const int num_components = color_att->num_components();
+    if (num_components != 3 && num_components != 4) {
+        throw std::runtime_error("Color attribute must have 3 or 4 channels");
+    }
+    switch (color_att->data_type()) {
+        case draco::DT_UINT8: return num_components == 3 ? COLOR_RGB8 : COLOR_RGBA8;
+        case draco::DT_UINT16: return num_components == 3 ? COLOR_RGB16 : COLOR_RGBA16;
+        default: throw std::runtime_error("Color attribute must have 8 or 16 bits per channel");
+    }
+}
+
+// Creates the color attribute of a point cloud from `num_points` colors with `num_channels` channels
+// Draco does not quantize integer attributes, so the least significant bits that are not kept are dropped here
+template <typename T>
+static std::unique_ptr<draco::PointAttribute> create_color_attribute(const T* colors, uint32_t num_points, int num_channels, int quantization_bits, draco::DataType data_type) {
+    const T mask = static_cast<T>(static_cast<uint32_t>(std::numeric_limits<T>::max()) << (sizeof(T) * 8 - quantization_bits));
+    std::unique_ptr<draco::PointAttribute> color_attribute = std::make_unique<draco::PointAttribute>();
+    color_attribute->Init(draco::GeometryAttribute::COLOR, static_cast<int8_t>(num_channels), data_type, true, num_points);
+    T color_value[4];
+    for (uint32_t i = 0; i < num_points; i++) {
+        for (int c = 0; c < num_channels; c++) {
+            color_value[c] = colors[i * num_channels + c] & mask;
+        }
+        color_attribute->SetAttributeValue(draco::AttributeValueIndex(i), color_value);
+    }
+    return color_attribute;
+}
+
+// Stores the key/value entries in the geometry metadata of the point cloud
+static void add_frame_metadata(draco::PointCloud& point_cloud, const MetadataEntry* metadata, size_t num_metadata) {
+    if (num_metadata == 0) {
+        return;
+    }
+    std::unique_ptr<draco::GeometryMetadata> geometry_metadata = std::make_unique<draco::GeometryMetadata>();
+    for (size_t m = 0; m < num_metadata; m++) {
+        const MetadataEntry& entry = metadata[m];
+        if (!entry.key || (entry.value_size > 0 && !entry.value)) {
+            throw std::runtime_error("Invalid metadata entry at index " + std::to_string(m));
+        }
+        geometry_metadata->AddEntryBinary(entry.key, std::vector<uint8_t>(entry.value, entry.value + entry.value_size));
+    }
+    point_cloud.AddMetadata(std::move(geometry_metadata));
+}
+
+// Copies the geometry metadata entries of a decoded point cloud into the result
+static void copy_frame_metadata(const draco::PointCloud& point_cloud, DecodeResult* result) {
+    const draco::GeometryMetadata* metadata = point_cloud.GetMetadata();
+    if (!metadata || metadata->entries().empty()) {
+        return;
+    }
+    result->metadata = new MetadataEntry[metadata->entries().size()]();
+    result->num_metadata = metadata->entries().size();
+    size_t m = 0;
+    for (const auto& entry : metadata->entries()) {
+        MetadataEntry& out = result->metadata[m++];
+        out.key = strdup(entry.first.c_str());
+        const std::vector<uint8_t>& value = entry.second.data();
+        uint8_t* value_copy = new uint8_t[value.size()];
+        memcpy(value_copy, value.data(), value.size());
+        out.value = value_copy;
+        out.value_size = value.size();
+    }
+}
+
+// Frees the metadata entries of a decode result
+static void free_frame_metadata(DecodeResult* result) {
+    if (result->metadata) {
+        for (size_t m = 0; m < result->num_metadata; m++) {
+            free(const_cast<char*>(result->metadata[m].key)); // Allocated with strdup
+            delete[] result->metadata[m].value;
+        }
+        delete[] result->metadata;
+    }
+    result->metadata = nullptr;
+    result->num_metadata = 0;
+}
+
+// Validates the input and options of a point cloud encode, returns an error message or null if they are valid
+static const char* validate_points_input(const float* coords, const uint8_t* colors, const float* normals, const CustomAttribute* attributes, size_t num_attributes, const MetadataEntry* metadata, size_t num_metadata, const EncodeOptions& opts) {
+    // Error handling: Check if pointers are null
+    if (!coords || !colors) {
+        return "Invalid input: coords/colors pointers are null.";
+    }
+    if (num_attributes > 0 && !attributes) {
+        return "Invalid input: attributes pointer is null.";
+    }
+    if (num_metadata > 0 && !metadata) {
+        return "Invalid input: metadata pointer is null.";
+    }
+
+    if (opts.position_quantization_bits < 1 || opts.position_quantization_bits > 30) {
+        return "Invalid options: position quantization bits must be between 1 and 30.";
+    }
+    if (opts.color_format < COLOR_RGB8 || opts.color_format > COLOR_RGBA16) {
+        return "Invalid options: unknown color format.";
+    }
+    if (opts.color_quantization_bits < 1 || opts.color_quantization_bits > color_bits(opts.color_format)) {
+        return "Invalid options: color quantization bits must be between 1 and the bits per channel of the color format.";
+    }
+    if (normals && (opts.normal_quantization_bits < 1 || opts.normal_quantization_bits > 30)) {
+        return "Invalid options: normal quantization bits must be between 1 and 30.";
+    }
+    if (opts.encode_speed < 0 || opts.encode_speed > 10 || opts.decode_speed < 0 || opts.decode_speed > 10) {
+        return "Invalid options: encode/decode speed must be between 0 and 10.";
+    }
+    if (opts.encoding_method != draco::POINT_CLOUD_SEQUENTIAL_ENCODING && opts.encoding_method != draco::POINT_CLOUD_KD_TREE_ENCODING) {
+        return "Invalid options: unknown point cloud encoding method.";
+    }
+    return nullptr;
+}
+
+// Builds a point cloud from the input and encodes it into `encoder_buffer`, throws if the encoding fails
+static void encode_points(const float* coords, size_t num_points, const uint8_t* colors, const float* normals, const CustomAttribute* attributes, size_t num_attributes, const MetadataEntry* metadata, size_t num_metadata, const EncodeOptions& opts, draco::EncoderBuffer* encoder_buffer) {
+    // Initialize point cloud
+    draco::PointCloud point_cloud;
+    point_cloud.set_num_points(static_cast<uint32_t>(num_points));
+
+    // Added before the custom attributes, as adding the geometry metadata would drop their metadata
+    add_frame_metadata(point_cloud, metadata, num_metadata);
+
+    // Create and add the position attribute
+    // Create a position attribute using a unique pointer
+    std::unique_ptr<draco::PointAttribute> position_attribute = std::make_unique<draco::PointAttribute>();
+    
+    position_attribute->Init(draco::GeometryAttribute::POSITION, 3, draco::DataType::DT_FLOAT32, false, point_cloud.num_points());
+    float position_value[3];
+    for(auto i = 0; i < point_cloud.num_points(); i++) {
+        position_value[0] = static_cast<float>(coords[i * 3]);
+        position_value[1] = static_cast<float>(coords[i * 3 + 1]);
+        position_value[2] = static_cast<float>(coords[i * 3 + 2]);
+        position_attribute->SetAttributeValue(draco::AttributeValueIndex(i), position_value);
+        //position_attribute->buffer()->Update(position_value, 3 * sizeof(float), i);
+    }
+
+    // The colors are 8 or 16 bits per channel, with an optional alpha channel
+    std::unique_ptr<draco::PointAttribute> color_attribute;
+    if (color_bits(opts.color_format) == 16) {
+        color_attribute = create_color_attribute(reinterpret_cast<const uint16_t*>(colors), point_cloud.num_points(), color_channels(opts.color_format), opts.color_quantization_bits, draco::DT_UINT16);
+    } else {
+        color_attribute = create_color_attribute(colors, point_cloud.num_points(), color_channels(opts.color_format), opts.color_quantization_bits, draco::DT_UINT8);
+    }
+
+    auto position_attribute_id = point_cloud.AddAttribute(std::move(position_attribute));
+    auto color_attribute_id = point_cloud.AddAttribute(std::move(color_attribute));
+
+    // The KD-tree encoder requires all float attributes to be quantized, keep track of their quantization bits
+    std::vector<std::pair<int, int>> quantized_attributes;
+    quantized_attributes.emplace_back(position_attribute_id, opts.position_quantization_bits);
+
+    // The normals are optional
+    if (normals) {
+        std::unique_ptr<draco::PointAttribute> normal_attribute = std::make_unique<draco::PointAttribute>();
+        normal_attribute->Init(draco::GeometryAttribute::NORMAL, 3, draco::DataType::DT_FLOAT32, false, point_cloud.num_points());
+        for (auto i = 0; i < point_cloud.num_points(); i++) {
+            normal_attribute->SetAttributeValue(draco::AttributeValueIndex(i), &normals[i * 3]);
+        }
+        auto normal_attribute_id = point_cloud.AddAttribute(std::move(normal_attribute));
+        quantized_attributes.emplace_back(normal_attribute_id, opts.normal_quantization_bits);
+    }
+
+    // Custom attributes are added as generic attributes, identified by the name in their metadata
+    for (size_t a = 0; a < num_attributes; a++) {
+        const CustomAttribute& custom = attributes[a];
+        draco::DataType data_type;
+        if (!custom.name || !custom.data || !to_draco_data_type(custom.data_type, &data_type)) {
+            throw std::runtime_error("Invalid custom attribute at index " + std::to_string(a));
+        }
+        if (custom.num_components < 1 || custom.num_components > 127) {
+            throw std::runtime_error("Custom attribute " + std::string(custom.name) + " must have between 1 and 127 components");
+        }
+        if (data_type == draco::DT_FLOAT32 && (custom.quantization_bits < 1 || custom.quantization_bits > 30)) {
+            throw std::runtime_error("Custom attribute " + std::string(custom.name) + " needs between 1 and 30 quantization bits");
+        }
+
+        std::unique_ptr<draco::PointAttribute> custom_attribute = std::make_unique<draco::PointAttribute>();
+        custom_attribute->Init(draco::GeometryAttribute::GENERIC, static_cast<int8_t>(custom.num_components), data_type, false, point_cloud.num_points());
+        const size_t stride = custom.num_components * draco::DataTypeLength(data_type);
+        const uint8_t* values = static_cast<const uint8_t*>(custom.data);
+        for (auto i = 0; i < point_cloud.num_points(); i++) {
+            custom_attribute->SetAttributeValue(draco::AttributeValueIndex(i), values + i * stride);
+        }
+        auto custom_attribute_id = point_cloud.AddAttribute(std::move(custom_attribute));
+
+        std::unique_ptr<draco::AttributeMetadata> metadata = std::make_unique<draco::AttributeMetadata>();
+        metadata->AddEntryString(ATTRIBUTE_NAME_ENTRY, custom.name);
+        point_cloud.AddAttributeMetadata(custom_attribute_id, std::move(metadata));
+
+        if (data_type == draco::DT_FLOAT32) {
+            quantized_attributes.emplace_back(custom_attribute_id, custom.quantization_bits);
+        }
+    }
+
+    // Initialize encoder
+    // The expert encoder allows us to set the quantization per attribute instead of per attribute type
+    draco::ExpertEncoder encoder(point_cloud);
+
+    // The KD-tree method compresses better, the sequential method is faster and keeps the order of the points
+    encoder.SetEncodingMethod(opts.encoding_method);
+    for (const auto& quantized_attribute : quantized_attributes) {
+        encoder.SetAttributeQuantization(quantized_attribute.first, quantized_attribute.second);
+    }
+    encoder.SetSpeedOptions(opts.encode_speed, opts.decode_speed);
+
+    // Encode the point cloud into the buffer
+    draco::Status status = encoder.EncodeToBuffer(encoder_buffer);
+    if (!status.ok()) {
+        throw std::runtime_error("Failed to encode point cloud: " + std::string(status.error_msg()));
+    }
+}
+
 // Function to encode points to Draco, returning a buffer of encoded data
-EncodeResult* DracoWrapper::encode_points_to_draco(const float* coords, size_t num_points, const uint8_t* colors) {
+EncodeResult* DracoWrapper::encode_points_to_draco(const float* coords, size_t num_points, const uint8_t* colors, const float* normals, const CustomAttribute* attributes, size_t num_attributes, const MetadataEntry* metadata, size_t num_metadata, const EncodeOptions* options) {
     EncodeResult* result = new EncodeResult();
     result->success = false;
     result->data = nullptr;
     result->size = 0;
     result->error_msg = nullptr;
 
-    // Error handling: Check if pointers are null
-    if (!coords || !colors) {
-        result->error_msg = strdup("Invalid input: coords/colors pointers are null.");
+    const EncodeOptions& opts = options ? *options : DEFAULT_ENCODE_OPTIONS;
+    const char* error = validate_points_input(coords, colors, normals, attributes, num_attributes, metadata, num_metadata, opts);
+    if (error) {
+        result->error_msg = strdup(error);
         return result;
     }
 
-
     try {
-        // Initialize point cloud
-        draco::PointCloud point_cloud;
-        point_cloud.set_num_points(static_cast<uint32_t>(num_points));
-
-        // Create and add the position attribute
-        // Create a position attribute using a unique pointer
-        std::unique_ptr<draco::PointAttribute> position_attribute = std::make_unique<draco::PointAttribute>();
-        std::unique_ptr<draco::PointAttribute> color_attribute = std::make_unique<draco::PointAttribute>();
-        
-        position_attribute->Init(draco::GeometryAttribute::POSITION, 3, draco::DataType::DT_FLOAT32, false, point_cloud.num_points());
-        color_attribute->Init(draco::GeometryAttribute::COLOR, 3, draco::DataType::DT_UINT8, true, point_cloud.num_points());
-        float position_value[3];
-        uint8_t color_value[3];
-        for(auto i = 0; i < point_cloud.num_points(); i++) {
-            position_value[0] = static_cast<float>(coords[i * 3]);
-            position_value[1] = static_cast<float>(coords[i * 3 + 1]);
-            position_value[2] = static_cast<float>(coords[i * 3 + 2]);
-            color_value[0] = colors[i * 3];
-            color_value[1] = colors[i * 3 + 1];
-            color_value[2] = colors[i * 3 + 2];
-            position_attribute->SetAttributeValue(draco::AttributeValueIndex(i), position_value);
-            //position_attribute->buffer()->Update(position_value, 3 * sizeof(float), i);
-            color_attribute->SetAttributeValue(draco::AttributeValueIndex(i), color_value);
-            //color_attribute->buffer()->Update(colors, 3 * sizeof(uint8_t), i);
-        }
-
-        auto position_attribute_id = point_cloud.AddAttribute(std::move(position_attribute));
-        auto color_attribute_id = point_cloud.AddAttribute(std::move(color_attribute));
-
-        // Initialize encoder and buffer
-        draco::Encoder encoder;
         draco::EncoderBuffer encoder_buffer;
-
-        // We will use the KD-tree encoding method
-        encoder.SetEncodingMethod(draco::POINT_CLOUD_KD_TREE_ENCODING);    
-        // We can potentially change quantization here    
-        encoder.SetAttributeQuantization(draco::GeometryAttribute::POSITION, 11);  
-
-        // Encode the point cloud into the buffer
-        draco::Status status = encoder.EncodePointCloudToBuffer(point_cloud, &encoder_buffer);
-        if (!status.ok()) {
-            throw std::runtime_error("Failed to encode point cloud: " + std::string(status.error_msg()));
-        }
+        encode_points(coords, num_points, colors, normals, attributes, num_attributes, metadata, num_metadata, opts, &encoder_buffer);
 
         // Allocate memory for the encoded data and copy it
         uint8_t* encoded_data = new uint8_t[encoder_buffer.size()];
@@ -83,12 +294,71 @@ EncodeResult* DracoWrapper::encode_points_to_draco(const float* coords, size_t n
     return result;
 }
 
+// Copies the positions and colors of every `stride`-th point of a decoded point cloud into arrays of `num_points` * 3 values
+// The colors are converted to 8-bit RGB, the ColorFormat they were encoded with is returned
+static int copy_positions_and_colors(const draco::PointCloud& point_cloud, float* coords, uint8_t* colors, size_t num_points, size_t stride) {
+    int pos_att_id = point_cloud.GetNamedAttributeId(draco::GeometryAttribute::POSITION);
+    if (pos_att_id < 0) {
+        throw std::runtime_error("Position attribute not found");
+    }
+    int color_att_id = point_cloud.GetNamedAttributeId(draco::GeometryAttribute::COLOR);
+    if (color_att_id < 0) {
+        std::cerr << "Error: Color attribute not found." << std::endl;
+        throw std::runtime_error("Color attribute not found");
+    }
+
+    const draco::PointAttribute* pos_att = point_cloud.GetAttributeByUniqueId(pos_att_id);
+    const draco::PointAttribute* color_att = point_cloud.GetAttributeByUniqueId(color_att_id);
+    const int color_format = color_format_of(color_att);
+    for (size_t i = 0; i < num_points; i++) {
+        draco::AttributeValueIndex index(static_cast<uint32_t>(i * stride));
+        pos_att->GetValue(index, &coords[i * 3]);
+        if (color_format == COLOR_RGB8) {
+            color_att->GetValue(index, &colors[i * 3]);
+        } else if (color_bits(color_format) == 16) {
+            // Keep the most significant byte of each channel and drop the alpha channel
+            uint16_t color_value[4];
+            color_att->GetValue(index, color_value);
+            for (int c = 0; c < 3; c++) {
+                colors[i * 3 + c] = static_cast<uint8_t>(color_value[c] >> 8);
+            }
+        } else {
+            uint8_t color_value[4];
+            color_att->GetValue(index, color_value);
+            memcpy(&colors[i * 3], color_value, 3);
+        }
+    }
+    return color_format;
+}
+
+// Copies the colors of a decoded point cloud into the result in the format they were encoded with, unless that is 8-bit RGB
+static void copy_native_colors(const draco::PointCloud& point_cloud, DecodeResult* result) {
+    const draco::PointAttribute* color_att = point_cloud.GetNamedAttribute(draco::GeometryAttribute::COLOR);
+    result->color_format = color_format_of(color_att);
+    if (result->color_format == COLOR_RGB8) {
+        return;
+    }
+    const size_t value_size = color_att->byte_stride();
+    uint8_t* native_colors = new uint8_t[result->num_points * value_size];
+    result->native_colors = native_colors;
+    for (draco::PointIndex i(0); i < point_cloud.num_points(); ++i) {
+        color_att->GetValue(draco::AttributeValueIndex(i.value()), native_colors + i.value() * value_size);
+    }
+}
+
 // Function to decode Draco data into points and colors
 DecodeResult* DracoWrapper::decode_draco_data(const uint8_t* encoded_data, size_t encoded_size) {
     DecodeResult* result = new DecodeResult();
     result->success = false;
     result->coords = nullptr;
     result->colors = nullptr;
+    result->color_format = COLOR_RGB8;
+    result->native_colors = nullptr;
+    result->normals = nullptr;
+    result->attributes = nullptr;
+    result->num_attributes = 0;
+    result->metadata = nullptr;
+    result->num_metadata = 0;
     result->num_points = 0;
     result->error_msg = nullptr;
 
@@ -105,45 +375,318 @@ DecodeResult* DracoWrapper::decode_draco_data(const uint8_t* encoded_data, size_
             throw std::runtime_error("Failed to decode point cloud: " + std::string(status.error_msg()));
         }
 
-        // Prepare arrays for decoded points and colors
         size_t num_points = point_cloud.num_points();
-        std::vector<float> coords;
-        std::vector<uint8_t> colors;
         result->num_points = num_points;
 
-        int pos_att_id = point_cloud.GetNamedAttributeId(draco::GeometryAttribute::POSITION);
-        if (pos_att_id >= 0) {
-            const draco::PointAttribute* pos_att = point_cloud.GetAttributeByUniqueId(pos_att_id);
-            coords.resize(point_cloud.num_points() * 3);
+        // Decode the positions and colors straight into the result
+        result->coords = new float[num_points * 3];
+        result->colors = new uint8_t[num_points * 3];
+        copy_positions_and_colors(point_cloud, result->coords, result->colors, num_points, 1);
+        copy_native_colors(point_cloud, result);
+
+        // The normals are optional
+        std::vector<float> normals;
+        int normal_att_id = point_cloud.GetNamedAttributeId(draco::GeometryAttribute::NORMAL);
+        if (normal_att_id >= 0) {
+            const draco::PointAttribute* normal_att = point_cloud.GetAttributeByUniqueId(normal_att_id);
+            normals.resize(point_cloud.num_points() * 3);
 
             for (draco::PointIndex i(0); i < point_cloud.num_points(); ++i) {
-                pos_att->GetValue(draco::AttributeValueIndex(i.value()), &coords[i.value() * 3]);
+                normal_att->GetValue(draco::AttributeValueIndex(i.value()), &normals[i.value() * 3]);
             }
-        } else {
-            throw std::runtime_error("Position attribute not found");
         }
 
-        int color_att_id = point_cloud.GetNamedAttributeId(draco::GeometryAttribute::COLOR);
-        if (color_att_id >= 0) {
-            const draco::PointAttribute* color_att = point_cloud.GetAttributeByUniqueId(color_att_id);
-            colors.resize(point_cloud.num_points() * 3);
+        // Collect the generic attributes that were named by the encoder
+        std::vector<const draco::PointAttribute*> custom_attributes;
+        std::vector<std::string> custom_names;
+        for (int att_id = 0; att_id < point_cloud.num_attributes(); att_id++) {
+            const draco::PointAttribute* att = point_cloud.attribute(att_id);
+            if (att->attribute_type() != draco::GeometryAttribute::GENERIC) {
+                continue;
+            }
+            const draco::AttributeMetadata* metadata = point_cloud.GetAttributeMetadataByAttributeId(att_id);
+            std::string name;
+            if (!metadata || !metadata->GetEntryString(ATTRIBUTE_NAME_ENTRY, &name)) {
+                continue;
+            }
+            custom_attributes.push_back(att);
+            custom_names.push_back(name);
+        }
 
-            for (draco::PointIndex i(0); i < point_cloud.num_points(); ++i) {
-                color_att->GetValue(draco::AttributeValueIndex(i.value()), &colors[i.value() * 3]);
+        // Copy the decoded data to the result
+        if (!custom_attributes.empty()) {
+            result->attributes = new CustomAttribute[custom_attributes.size()]();
+            result->num_attributes = custom_attributes.size();
+            for (size_t a = 0; a < custom_attributes.size(); a++) {
+                const draco::PointAttribute* att = custom_attributes[a];
+                CustomAttribute& custom = result->attributes[a];
+                custom.name = strdup(custom_names[a].c_str());
+                custom.num_components = att->num_components();
+                custom.quantization_bits = 0;
+                custom.data = nullptr;
+                if (!from_draco_data_type(att->data_type(), &custom.data_type)) {
+                    throw std::runtime_error("Custom attribute " + custom_names[a] + " has an unsupported data type");
+                }
+
+                const size_t stride = att->num_components() * draco::DataTypeLength(att->data_type());
+                uint8_t* values = new uint8_t[num_points * stride];
+                for (draco::PointIndex i(0); i < point_cloud.num_points(); ++i) {
+                    att->GetValue(draco::AttributeValueIndex(i.value()), values + i.value() * stride);
+                }
+                custom.data = values;
             }
+        }
+        if (!normals.empty()) {
+            result->normals = new float[num_points * 3];
+            memcpy(result->normals, normals.data(), num_points * 3 * sizeof(float));
+        }
+        copy_frame_metadata(point_cloud, result);
+
+        result->success = true;
+    } catch (const std::exception& e) {
+        std::cerr << "Error: " << e.what() << std::endl;
+        result->error_msg = strdup(e.what());
+    } catch (...) {
+        std::cerr << "Unknown error occurred during decoding." << std::endl;
+        result->error_msg = strdup("Unknown error occurred during decoding.");
+    }
+
+    return result;
+}
+
+// Function to decode Draco data into caller-provided buffers
+DecodeResult* DracoWrapper::decode_draco_into(const uint8_t* encoded_data, size_t encoded_size, float* coords, uint8_t* colors, size_t capacity) {
+    DecodeResult* result = new DecodeResult();
+    result->success = false;
+    result->coords = nullptr;
+    result->colors = nullptr;
+    result->color_format = COLOR_RGB8;
+    result->native_colors = nullptr;
+    result->normals = nullptr;
+    result->attributes = nullptr;
+    result->num_attributes = 0;
+    result->metadata = nullptr;
+    result->num_metadata = 0;
+    result->num_points = 0;
+    result->error_msg = nullptr;
+
+    try {
+        draco::PointCloud point_cloud;
+        draco::DecoderBuffer decoder_buffer;
+        decoder_buffer.Init(reinterpret_cast<const char*>(encoded_data), encoded_size);
+
+        draco::Decoder decoder;
+        draco::Status status = decoder.DecodeBufferToGeometry(&decoder_buffer, &point_cloud);
+        if (!status.ok()) {
+            throw std::runtime_error("Failed to decode point cloud: " + std::string(status.error_msg()));
+        }
+
+        size_t num_points = point_cloud.num_points();
+        result->num_points = num_points;
+        if (num_points > capacity) {
+            // The buffers are too small, so decode into arrays owned by the result instead of decoding twice
+            result->coords = new float[num_points * 3];
+            result->colors = new uint8_t[num_points * 3];
+            result->color_format = copy_positions_and_colors(point_cloud, result->coords, result->colors, num_points, 1);
         } else {
-            std::cerr << "Error: Color attribute not found." << std::endl;
-            throw std::runtime_error("Color attribute not found");
+            if (num_points > 0 && (!coords || !colors)) {
+                throw std::runtime_error("Invalid input: coords/colors pointers are null.");
+            }
+            result->color_format = copy_positions_and_colors(point_cloud, coords, colors, num_points, 1);
         }
+        copy_frame_metadata(point_cloud, result);
+        result->success = true;
+    } catch (const std::exception& e) {
+        std::cerr << "Error: " << e.what() << std::endl;
+        result->error_msg = strdup(e.what());
+    } catch (...) {
+        std::cerr << "Unknown error occurred during decoding." << std::endl;
+        result->error_msg = strdup("Unknown error occurred during decoding.");
+    }
+
+    return result;
+}
+
+// Function to decode every `stride`-th point of Draco data, up to `max_points` points
+DecodeResult* DracoWrapper::decode_draco_subset(const uint8_t* encoded_data, size_t encoded_size, size_t max_points, size_t stride) {
+    DecodeResult* result = new DecodeResult();
+    result->success = false;
+    result->coords = nullptr;
+    result->colors = nullptr;
+    result->color_format = COLOR_RGB8;
+    result->native_colors = nullptr;
+    result->normals = nullptr;
+    result->attributes = nullptr;
+    result->num_attributes = 0;
+    result->metadata = nullptr;
+    result->num_metadata = 0;
+    result->num_points = 0;
+    result->error_msg = nullptr;
+
+    if (stride < 1) {
+        result->error_msg = strdup("Invalid input: stride must be at least 1.");
+        return result;
+    }
+
+    try {
+        draco::PointCloud point_cloud;
+        draco::DecoderBuffer decoder_buffer;
+        decoder_buffer.Init(reinterpret_cast<const char*>(encoded_data), encoded_size);
+
+        draco::Decoder decoder;
+        draco::Status status = decoder.DecodeBufferToGeometry(&decoder_buffer, &point_cloud);
+        if (!status.ok()) {
+            throw std::runtime_error("Failed to decode point cloud: " + std::string(status.error_msg()));
+        }
+
+        // Number of points in the subset, rounded up so the first point is always kept
+        size_t num_points = (static_cast<size_t>(point_cloud.num_points()) + stride - 1) / stride;
+        num_points = std::min(num_points, max_points);
+        result->num_points = num_points;
 
-        // Copy the decoded data to the result
         result->coords = new float[num_points * 3];
         result->colors = new uint8_t[num_points * 3];
-        for (size_t i = 0; i < num_points * 3; i++) {
-            result->coords[i] = coords[i];
-            result->colors[i] = colors[i];
+        result->color_format = copy_positions_and_colors(point_cloud, result->coords, result->colors, num_points, stride);
+        copy_frame_metadata(point_cloud, result);
+        result->success = true;
+    } catch (const std::exception& e) {
+        std::cerr << "Error: " << e.what() << std::endl;
+        result->error_msg = strdup(e.what());
+    } catch (...) {
+        std::cerr << "Unknown error occurred during decoding." << std::endl;
+        result->error_msg = strdup("Unknown error occurred during decoding.");
+    }
+
+    return result;
+}
+
+// Reusable encoder, keeps its options and output buffer across frames
+struct EncoderHandle {
+    EncodeOptions options;
+    draco::EncoderBuffer buffer;
+    EncodeResult result;
+};
+
+// Reusable decoder, keeps its output buffers across frames
+struct DecoderHandle {
+    std::vector<float> coords;
+    std::vector<uint8_t> colors;
+    DecodeResult result;
+};
+
+// Function to create a reusable encoder with the given options
+EncoderHandle* DracoWrapper::create_encoder(const EncodeOptions* options) {
+    EncoderHandle* encoder = new EncoderHandle();
+    encoder->options = options ? *options : DEFAULT_ENCODE_OPTIONS;
+    encoder->result.success = false;
+    encoder->result.data = nullptr;
+    encoder->result.size = 0;
+    encoder->result.error_msg = nullptr;
+    return encoder;
+}
+
+// Function to encode points with a reusable encoder, the result is owned by the encoder
+const EncodeResult* DracoWrapper::encoder_encode_points(EncoderHandle* encoder, const float* coords, size_t num_points, const uint8_t* colors, const float* normals, const CustomAttribute* attributes, size_t num_attributes, const MetadataEntry* metadata, size_t num_metadata, int color_format) {
+    EncodeResult* result = &encoder->result;
+    result->success = false;
+    result->data = nullptr;
+    result->size = 0;
+    if (result->error_msg) {
+        free(result->error_msg);
+        result->error_msg = nullptr;
+    }
+
+    EncodeOptions opts = encoder->options;
+    opts.color_format = color_format;
+    const char* error = validate_points_input(coords, colors, normals, attributes, num_attributes, metadata, num_metadata, opts);
+    if (error) {
+        result->error_msg = strdup(error);
+        return result;
+    }
+
+    try {
+        // Clearing the buffer keeps its allocation for the next frame
+        encoder->buffer.Clear();
+        encode_points(coords, num_points, colors, normals, attributes, num_attributes, metadata, num_metadata, opts, &encoder->buffer);
+
+        result->success = true;
+        result->data = reinterpret_cast<const uint8_t*>(encoder->buffer.data());
+        result->size = encoder->buffer.size();
+    } catch (const std::exception& e) {
+        result->error_msg = strdup(e.what());
+    } catch (...) {
+        result->error_msg = strdup("Unknown error occurred during encoding.");
+    }
+
+    return result;
+}
+
+// Function to free a reusable encoder
+void DracoWrapper::free_encoder(EncoderHandle* encoder) {
+    if (encoder) {
+        if (encoder->result.error_msg) {
+            free(encoder->result.error_msg); // Free instead of delete[] because strdup uses malloc
         }
+        delete encoder;
+    } else {
+        std::cerr << "Error: Attempted to free a null EncoderHandle." << std::endl;
+    }
+}
 
+// Function to create a reusable decoder
+DecoderHandle* DracoWrapper::create_decoder() {
+    DecoderHandle* decoder = new DecoderHandle();
+    DecodeResult* result = &decoder->result;
+    result->success = false;
+    result->coords = nullptr;
+    result->colors = nullptr;
+    result->color_format = COLOR_RGB8;
+    result->native_colors = nullptr;
+    result->normals = nullptr;
+    result->attributes = nullptr;
+    result->num_attributes = 0;
+    result->metadata = nullptr;
+    result->num_metadata = 0;
+    result->num_points = 0;
+    result->error_msg = nullptr;
+    return decoder;
+}
+
+// Function to decode points and colors with a reusable decoder, the result is owned by the decoder
+const DecodeResult* DracoWrapper::decoder_decode(DecoderHandle* decoder, const uint8_t* encoded_data, size_t encoded_size) {
+    DecodeResult* result = &decoder->result;
+    result->success = false;
+    result->num_points = 0;
+    result->coords = nullptr;
+    result->colors = nullptr;
+    result->color_format = COLOR_RGB8;
+    free_frame_metadata(result);
+    if (result->error_msg) {
+        free(result->error_msg);
+        result->error_msg = nullptr;
+    }
+
+    try {
+        draco::PointCloud point_cloud;
+        draco::DecoderBuffer decoder_buffer;
+        decoder_buffer.Init(reinterpret_cast<const char*>(encoded_data), encoded_size);
+
+        draco::Decoder draco_decoder;
+        draco::Status status = draco_decoder.DecodeBufferToGeometry(&decoder_buffer, &point_cloud);
+        if (!status.ok()) {
+            throw std::runtime_error("Failed to decode point cloud: " + std::string(status.error_msg()));
+        }
+
+        // Resizing only allocates when the frame has more points than any frame before
+        size_t num_points = point_cloud.num_points();
+        decoder->coords.resize(num_points * 3);
+        decoder->colors.resize(num_points * 3);
+        result->color_format = copy_positions_and_colors(point_cloud, decoder->coords.data(), decoder->colors.data(), num_points, 1);
+        copy_frame_metadata(point_cloud, result);
+
+        result->num_points = num_points;
+        result->coords = decoder->coords.data();
+        result->colors = decoder->colors.data();
         result->success = true;
     } catch (const std::exception& e) {
         std::cerr << "Error: " << e.what() << std::endl;
@@ -156,6 +699,203 @@ DecodeResult* DracoWrapper::decode_draco_data(const uint8_t* encoded_data, size_
     return result;
 }
 
+// Function to free a reusable decoder
+void DracoWrapper::free_decoder(DecoderHandle* decoder) {
+    if (decoder) {
+        free_frame_metadata(&decoder->result);
+        if (decoder->result.error_msg) {
+            free(decoder->result.error_msg); // Free instead of delete[] because strdup uses malloc
+        }
+        delete decoder;
+    } else {
+        std::cerr << "Error: Attempted to free a null DecoderHandle." << std::endl;
+    }
+}
+
+// Function to encode a triangle mesh to Draco, returning a buffer of encoded data
+EncodeResult* DracoWrapper::encode_mesh_to_draco(const float* positions, size_t num_vertices, const uint32_t* indices, size_t num_faces, const float* tex_coords, const uint8_t* colors, const EncodeOptions* options) {
+    EncodeResult* result = new EncodeResult();
+    result->success = false;
+    result->data = nullptr;
+    result->size = 0;
+    result->error_msg = nullptr;
+
+    // Error handling: Check if pointers are null
+    if (!positions || !indices) {
+        result->error_msg = strdup("Invalid input: positions/indices pointers are null.");
+        return result;
+    }
+
+    const EncodeOptions& opts = options ? *options : DEFAULT_ENCODE_OPTIONS;
+    if (opts.position_quantization_bits < 1 || opts.position_quantization_bits > 30) {
+        result->error_msg = strdup("Invalid options: position quantization bits must be between 1 and 30.");
+        return result;
+    }
+    if (tex_coords && (opts.tex_coord_quantization_bits < 1 || opts.tex_coord_quantization_bits > 30)) {
+        result->error_msg = strdup("Invalid options: texture coordinate quantization bits must be between 1 and 30.");
+        return result;
+    }
+    if (opts.color_quantization_bits < 1 || opts.color_quantization_bits > 8) {
+        result->error_msg = strdup("Invalid options: color quantization bits must be between 1 and 8.");
+        return result;
+    }
+    if (opts.encode_speed < 0 || opts.encode_speed > 10 || opts.decode_speed < 0 || opts.decode_speed > 10) {
+        result->error_msg = strdup("Invalid options: encode/decode speed must be between 0 and 10.");
+        return result;
+    }
+    const uint8_t color_mask = static_cast<uint8_t>(0xFF << (8 - opts.color_quantization_bits));
+
+    try {
+        draco::Mesh mesh;
+        mesh.set_num_points(static_cast<uint32_t>(num_vertices));
+
+        // Every attribute has one value per vertex
+        std::unique_ptr<draco::PointAttribute> position_attribute = std::make_unique<draco::PointAttribute>();
+        position_attribute->Init(draco::GeometryAttribute::POSITION, 3, draco::DataType::DT_FLOAT32, false, mesh.num_points());
+        for (auto i = 0; i < mesh.num_points(); i++) {
+            position_attribute->SetAttributeValue(draco::AttributeValueIndex(i), &positions[i * 3]);
+        }
+        auto position_attribute_id = mesh.AddAttribute(std::move(position_attribute));
+
+        int tex_coord_attribute_id = -1;
+        if (tex_coords) {
+            std::unique_ptr<draco::PointAttribute> tex_coord_attribute = std::make_unique<draco::PointAttribute>();
+            tex_coord_attribute->Init(draco::GeometryAttribute::TEX_COORD, 2, draco::DataType::DT_FLOAT32, false, mesh.num_points());
+            for (auto i = 0; i < mesh.num_points(); i++) {
+                tex_coord_attribute->SetAttributeValue(draco::AttributeValueIndex(i), &tex_coords[i * 2]);
+            }
+            tex_coord_attribute_id = mesh.AddAttribute(std::move(tex_coord_attribute));
+        }
+
+        if (colors) {
+            std::unique_ptr<draco::PointAttribute> color_attribute = std::make_unique<draco::PointAttribute>();
+            color_attribute->Init(draco::GeometryAttribute::COLOR, 3, draco::DataType::DT_UINT8, true, mesh.num_points());
+            uint8_t color_value[3];
+            for (auto i = 0; i < mesh.num_points(); i++) {
+                color_value[0] = colors[i * 3] & color_mask;
+                color_value[1] = colors[i * 3 + 1] & color_mask;
+                color_value[2] = colors[i * 3 + 2] & color_mask;
+                color_attribute->SetAttributeValue(draco::AttributeValueIndex(i), color_value);
+            }
+            mesh.AddAttribute(std::move(color_attribute));
+        }
+
+        // Add the triangles
+        for (size_t f = 0; f < num_faces; f++) {
+            draco::Mesh::Face face;
+            for (int c = 0; c < 3; c++) {
+                const uint32_t index = indices[f * 3 + c];
+                if (index >= num_vertices) {
+                    throw std::runtime_error("Face " + std::to_string(f) + " refers to vertex " + std::to_string(index) + " which does not exist");
+                }
+                face[c] = draco::PointIndex(index);
+            }
+            mesh.AddFace(face);
+        }
+
+        draco::ExpertEncoder encoder(mesh);
+        draco::EncoderBuffer encoder_buffer;
+
+        // Edgebreaker gives the best compression for triangle meshes
+        encoder.SetEncodingMethod(draco::MESH_EDGEBREAKER_ENCODING);
+        encoder.SetAttributeQuantization(position_attribute_id, opts.position_quantization_bits);
+        if (tex_coord_attribute_id >= 0) {
+            encoder.SetAttributeQuantization(tex_coord_attribute_id, opts.tex_coord_quantization_bits);
+        }
+        encoder.SetSpeedOptions(opts.encode_speed, opts.decode_speed);
+
+        draco::Status status = encoder.EncodeToBuffer(&encoder_buffer);
+        if (!status.ok()) {
+            throw std::runtime_error("Failed to encode mesh: " + std::string(status.error_msg()));
+        }
+
+        uint8_t* encoded_data = new uint8_t[encoder_buffer.size()];
+        memcpy(encoded_data, encoder_buffer.data(), encoder_buffer.size());
+
+        result->success = true;
+        result->data = encoded_data;
+        result->size = encoder_buffer.size();
+    } catch (const std::exception& e) {
+        result->error_msg = strdup(e.what());
+    } catch (...) {
+        result->error_msg = strdup("Unknown error occurred during mesh encoding.");
+    }
+
+    return result;
+}
+
+// Function to decode Draco data into a triangle mesh
+MeshDecodeResult* DracoWrapper::decode_draco_mesh(const uint8_t* encoded_data, size_t encoded_size) {
+    MeshDecodeResult* result = new MeshDecodeResult();
+    result->success = false;
+    result->num_vertices = 0;
+    result->num_faces = 0;
+    result->positions = nullptr;
+    result->indices = nullptr;
+    result->tex_coords = nullptr;
+    result->colors = nullptr;
+    result->error_msg = nullptr;
+
+    try {
+        draco::DecoderBuffer decoder_buffer;
+        decoder_buffer.Init(reinterpret_cast<const char*>(encoded_data), encoded_size);
+
+        draco::Decoder decoder;
+        auto status_or_mesh = decoder.DecodeMeshFromBuffer(&decoder_buffer);
+        if (!status_or_mesh.ok()) {
+            throw std::runtime_error("Failed to decode mesh: " + std::string(status_or_mesh.status().error_msg()));
+        }
+        std::unique_ptr<draco::Mesh> mesh = std::move(status_or_mesh).value();
+
+        const draco::PointAttribute* pos_att = mesh->GetNamedAttribute(draco::GeometryAttribute::POSITION);
+        if (!pos_att) {
+            throw std::runtime_error("Position attribute not found");
+        }
+        const draco::PointAttribute* tex_coord_att = mesh->GetNamedAttribute(draco::GeometryAttribute::TEX_COORD);
+        const draco::PointAttribute* color_att = mesh->GetNamedAttribute(draco::GeometryAttribute::COLOR);
+
+        // Edgebreaker may reorder the vertices, so the values are looked up through the point mapping
+        const size_t num_vertices = mesh->num_points();
+        result->positions = new float[num_vertices * 3];
+        if (tex_coord_att) {
+            result->tex_coords = new float[num_vertices * 2];
+        }
+        if (color_att) {
+            result->colors = new uint8_t[num_vertices * 3];
+        }
+        for (draco::PointIndex i(0); i < mesh->num_points(); ++i) {
+            pos_att->GetValue(pos_att->mapped_index(i), &result->positions[i.value() * 3]);
+            if (tex_coord_att) {
+                tex_coord_att->GetValue(tex_coord_att->mapped_index(i), &result->tex_coords[i.value() * 2]);
+            }
+            if (color_att) {
+                color_att->GetValue(color_att->mapped_index(i), &result->colors[i.value() * 3]);
+            }
+        }
+
+        const size_t num_faces = mesh->num_faces();
+        result->indices = new uint32_t[num_faces * 3];
+        for (draco::FaceIndex f(0); f < mesh->num_faces(); ++f) {
+            const draco::Mesh::Face& face = mesh->face(f);
+            for (int c = 0; c < 3; c++) {
+                result->indices[f.value() * 3 + c] = face[c].value();
+            }
+        }
+
+        result->num_vertices = num_vertices;
+        result->num_faces = num_faces;
+        result->success = true;
+    } catch (const std::exception& e) {
+        std::cerr << "Error: " << e.what() << std::endl;
+        result->error_msg = strdup(e.what());
+    } catch (...) {
+        std::cerr << "Unknown error occurred during mesh decoding." << std::endl;
+        result->error_msg = strdup("Unknown error occurred during mesh decoding.");
+    }
+
+    return result;
+}
+
 // Function to free the memory allocated for the encoded result
 void DracoWrapper::free_encode_result(EncodeResult* result) {
     if (result) {
@@ -177,6 +917,20 @@ void DracoWrapper::free_decode_result(DecodeResult* result) {
         if (result->colors) {
             delete[] result->colors;
         }
+        if (result->native_colors) {
+            delete[] static_cast<uint8_t*>(result->native_colors);
+        }
+        if (result->normals) {
+            delete[] result->normals;
+        }
+        if (result->attributes) {
+            for (size_t a = 0; a < result->num_attributes; a++) {
+                free(const_cast<char*>(result->attributes[a].name)); // Allocated with strdup
+                delete[] static_cast<const uint8_t*>(result->attributes[a].data);
+            }
+            delete[] result->attributes;
+        }
+        free_frame_metadata(result);
         if (result->error_msg) {
             free(result->error_msg); // Free instead of delete[] because strdup uses malloc
         }
@@ -186,6 +940,29 @@ void DracoWrapper::free_decode_result(DecodeResult* result) {
     }
 }
 
+void DracoWrapper::free_mesh_decode_result(MeshDecodeResult* result) {
+    if (result) {
+        if (result->positions) {
+            delete[] result->positions;
+        }
+        if (result->indices) {
+            delete[] result->indices;
+        }
+        if (result->tex_coords) {
+            delete[] result->tex_coords;
+        }
+        if (result->colors) {
+            delete[] result->colors;
+        }
+        if (result->error_msg) {
+            free(result->error_msg); // Free instead of delete[] because strdup uses malloc
+        }
+        delete result;
+    } else {
+        std::cerr << "Error: Attempted to free a null MeshDecodeResult." << std::endl;
+    }
+}
+
 }
 
 #ifdef __cplusplus
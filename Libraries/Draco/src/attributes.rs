@@ -0,0 +1,187 @@
+use crate::bindings::*;
+use crate::error::DracoError;
+use crate::frame::slice_or_empty;
+use std::ffi::{c_void, CStr, CString};
+
+/// Data type of the values of a custom attribute.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AttributeType {
+    U8,
+    U16,
+    U32,
+    I32,
+    F32,
+}
+
+/// Mapping between the attribute types and the `AttributeDataType` values of the DracoWrapper.
+const FFI_TYPES: [(AttributeType, AttributeDataType); 5] = [
+    (AttributeType::U8, AttributeDataType_ATTRIBUTE_UINT8),
+    (AttributeType::U16, AttributeDataType_ATTRIBUTE_UINT16),
+    (AttributeType::U32, AttributeDataType_ATTRIBUTE_UINT32),
+    (AttributeType::I32, AttributeDataType_ATTRIBUTE_INT32),
+    (AttributeType::F32, AttributeDataType_ATTRIBUTE_FLOAT32),
+];
+
+impl AttributeType {
+    fn to_ffi(self) -> AttributeDataType {
+        FFI_TYPES.iter().find(|(data_type, _)| *data_type == self).map(|(_, ffi)| *ffi).unwrap()
+    }
+
+    fn from_ffi(ffi_type: AttributeDataType) -> Option<Self> {
+        FFI_TYPES.iter().find(|(_, ffi)| *ffi == ffi_type).map(|(data_type, _)| *data_type)
+    }
+}
+
+/// Describes a custom per-point attribute, such as intensity, confidence or a segment id.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AttributeDescriptor {
+    /// Name that identifies the attribute after decoding.
+    pub name: String,
+    pub data_type: AttributeType,
+    /// Number of values per point (1-127).
+    pub num_components: u8,
+    /// Quantization bits (1-30), only used for `F32` attributes.
+    pub quantization_bits: u8,
+}
+
+impl AttributeDescriptor {
+    pub fn new(name: impl Into<String>, data_type: AttributeType, num_components: u8) -> Self {
+        Self {
+            name: name.into(),
+            data_type,
+            num_components,
+            quantization_bits: 16,
+        }
+    }
+
+    pub fn with_quantization_bits(mut self, quantization_bits: u8) -> Self {
+        self.quantization_bits = quantization_bits;
+        self
+    }
+}
+
+/// The values of a custom attribute, `num_points` * `num_components` in total.
+#[derive(Debug, Clone, PartialEq)]
+pub enum AttributeValues {
+    U8(Vec<u8>),
+    U16(Vec<u16>),
+    U32(Vec<u32>),
+    I32(Vec<i32>),
+    F32(Vec<f32>),
+}
+
+impl AttributeValues {
+    pub fn data_type(&self) -> AttributeType {
+        match self {
+            AttributeValues::U8(_) => AttributeType::U8,
+            AttributeValues::U16(_) => AttributeType::U16,
+            AttributeValues::U32(_) => AttributeType::U32,
+            AttributeValues::I32(_) => AttributeType::I32,
+            AttributeValues::F32(_) => AttributeType::F32,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        match self {
+            AttributeValues::U8(values) => values.len(),
+            AttributeValues::U16(values) => values.len(),
+            AttributeValues::U32(values) => values.len(),
+            AttributeValues::I32(values) => values.len(),
+            AttributeValues::F32(values) => values.len(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    fn as_ptr(&self) -> *const c_void {
+        match self {
+            AttributeValues::U8(values) => values.as_ptr() as *const c_void,
+            AttributeValues::U16(values) => values.as_ptr() as *const c_void,
+            AttributeValues::U32(values) => values.as_ptr() as *const c_void,
+            AttributeValues::I32(values) => values.as_ptr() as *const c_void,
+            AttributeValues::F32(values) => values.as_ptr() as *const c_void,
+        }
+    }
+}
+
+/// A custom attribute together with its values.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AttributeData {
+    pub descriptor: AttributeDescriptor,
+    pub values: AttributeValues,
+}
+
+impl AttributeData {
+    pub fn new(descriptor: AttributeDescriptor, values: AttributeValues) -> Self {
+        Self { descriptor, values }
+    }
+}
+
+/// Custom attributes in the layout expected by the DracoWrapper.
+/// The names are owned here, so this must outlive the encode call.
+pub(crate) struct FfiAttributes {
+    _names: Vec<CString>,
+    pub(crate) attributes: Vec<CustomAttribute>,
+}
+
+/// Validates the custom attributes and converts them to the DracoWrapper layout.
+pub(crate) fn attributes_to_ffi(attributes: &[AttributeData], num_points: usize) -> Result<FfiAttributes, DracoError> {
+    let mut names = Vec::with_capacity(attributes.len());
+    let mut ffi_attributes = Vec::with_capacity(attributes.len());
+
+    for attribute in attributes {
+        let descriptor = &attribute.descriptor;
+        if attribute.values.data_type() != descriptor.data_type {
+            return Err(DracoError::InvalidInput(format!("Values of attribute {} do not match its data type {:?}", descriptor.name, descriptor.data_type)));
+        }
+        if attribute.values.len() != num_points * descriptor.num_components as usize {
+            return Err(DracoError::InvalidInput(format!("Number of values of attribute {} must match the number of points", descriptor.name)));
+        }
+
+        let name = CString::new(descriptor.name.as_str())
+            .map_err(|_| DracoError::InvalidInput(format!("Attribute name {} contains a null byte", descriptor.name)))?;
+        ffi_attributes.push(CustomAttribute {
+            name: name.as_ptr(),
+            data_type: descriptor.data_type.to_ffi() as i32,
+            num_components: descriptor.num_components as i32,
+            quantization_bits: descriptor.quantization_bits as i32,
+            data: attribute.values.as_ptr(),
+        });
+        // Moving the CString does not move its heap allocation, so the pointer stays valid
+        names.push(name);
+    }
+
+    Ok(FfiAttributes { _names: names, attributes: ffi_attributes })
+}
+
+/// Copies a decoded custom attribute out of the memory owned by the DracoWrapper.
+///
+/// # Safety
+/// `attribute` must be a custom attribute of a `DecodeResult` with `num_points` points that has not been freed yet.
+pub(crate) unsafe fn attribute_from_ffi(attribute: &CustomAttribute, num_points: usize) -> Result<AttributeData, DracoError> {
+    let name = CStr::from_ptr(attribute.name).to_string_lossy().into_owned();
+    let data_type = AttributeType::from_ffi(attribute.data_type as AttributeDataType)
+        .ok_or_else(|| DracoError::DecodeFailed(format!("Attribute {} has an unknown data type {}", name, attribute.data_type)))?;
+    let len = num_points * attribute.num_components as usize;
+
+    let values = match data_type {
+        AttributeType::U8 => AttributeValues::U8(slice_or_empty(attribute.data as *const u8, len).to_vec()),
+        AttributeType::U16 => AttributeValues::U16(slice_or_empty(attribute.data as *const u16, len).to_vec()),
+        AttributeType::U32 => AttributeValues::U32(slice_or_empty(attribute.data as *const u32, len).to_vec()),
+        AttributeType::I32 => AttributeValues::I32(slice_or_empty(attribute.data as *const i32, len).to_vec()),
+        AttributeType::F32 => AttributeValues::F32(slice_or_empty(attribute.data as *const f32, len).to_vec()),
+    };
+
+    Ok(AttributeData {
+        descriptor: AttributeDescriptor {
+            name,
+            data_type,
+            num_components: attribute.num_components as u8,
+            // The values have already been dequantized
+            quantization_bits: 0,
+        },
+        values,
+    })
+}
@@ -0,0 +1,78 @@
+use crate::attributes::AttributeData;
+use crate::colors::Colors;
+use crate::error::DracoError;
+use crate::frame::EncodedFrame;
+use crate::metadata::FrameMetadata;
+use crate::{encode_draco_with_options, EncoderOptions};
+use rayon::prelude::*;
+use rayon::{ThreadPool, ThreadPoolBuilder};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, OnceLock};
+use tracing::warn;
+
+/// The thread pools of the batch encodes, by number of threads, so they are only built once.
+static THREAD_POOLS: OnceLock<Mutex<HashMap<usize, Arc<ThreadPool>>>> = OnceLock::new();
+
+/// A point cloud that is encoded as part of a batch, e.g. a single tile of a partitioned frame.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct PointCloudFrame {
+    /// X, Y, Z for each point.
+    pub coords: Vec<f32>,
+    pub colors: Colors,
+    pub normals: Option<Vec<f32>>,
+    pub attributes: Vec<AttributeData>,
+    pub metadata: FrameMetadata,
+}
+
+impl PointCloudFrame {
+    pub fn new(coords: Vec<f32>, colors: impl Into<Colors>) -> Self {
+        Self {
+            coords,
+            colors: colors.into(),
+            ..Default::default()
+        }
+    }
+
+    fn encode(self, options: &EncoderOptions) -> Result<EncodedFrame, DracoError> {
+        encode_draco_with_options(self.coords, self.colors, self.normals, &self.attributes, &self.metadata, options)
+    }
+}
+
+/// Encodes several point clouds concurrently with the same options.
+/// Every encode uses its own Draco encoder, so the frames can safely be encoded on different threads.
+///
+/// `parallelism` is the number of threads to use: 0 uses the current rayon thread pool,
+/// 1 encodes the frames one after the other on the calling thread.
+/// The thread pool of every other number of threads is built on the first batch, and reused by the next ones.
+/// The results are returned in the same order as the frames, a failed frame does not affect the others.
+pub fn encode_draco_batch(
+    frames: Vec<PointCloudFrame>,
+    options: &EncoderOptions,
+    parallelism: usize,
+) -> Vec<Result<EncodedFrame, DracoError>> {
+    let encode_all = |frames: Vec<PointCloudFrame>| {
+        frames.into_par_iter().map(|frame| frame.encode(options)).collect()
+    };
+
+    match parallelism {
+        0 => encode_all(frames),
+        1 => frames.into_iter().map(|frame| frame.encode(options)).collect(),
+        num_threads => match thread_pool(num_threads) {
+            Ok(pool) => pool.install(|| encode_all(frames)),
+            Err(e) => {
+                warn!("Failed to build a thread pool for the batch encode, using the current one: {}", e);
+                encode_all(frames)
+            }
+        },
+    }
+}
+
+fn thread_pool(num_threads: usize) -> Result<Arc<ThreadPool>, rayon::ThreadPoolBuildError> {
+    let mut pools = THREAD_POOLS.get_or_init(Default::default).lock().unwrap();
+    if let Some(pool) = pools.get(&num_threads) {
+        return Ok(pool.clone());
+    }
+    let pool = Arc::new(ThreadPoolBuilder::new().num_threads(num_threads).build()?);
+    pools.insert(num_threads, pool.clone());
+    Ok(pool)
+}
@@ -4109,6 +4109,79 @@ extern "C" {
     #[link_name = "\u{1}_ZN5draco17PointCloudDecoder19OnAttributesDecodedEv"]
     pub fn PointCloudDecoder_OnAttributesDecoded(this: *mut ::std::os::raw::c_void) -> bool;
 }
+pub const AttributeDataType_ATTRIBUTE_UINT8: AttributeDataType = 0;
+pub const AttributeDataType_ATTRIBUTE_UINT16: AttributeDataType = 1;
+pub const AttributeDataType_ATTRIBUTE_UINT32: AttributeDataType = 2;
+pub const AttributeDataType_ATTRIBUTE_INT32: AttributeDataType = 3;
+pub const AttributeDataType_ATTRIBUTE_FLOAT32: AttributeDataType = 4;
+pub type AttributeDataType = ::std::os::raw::c_uint;
+#[repr(C)]
+#[derive(Debug, Copy, Clone)]
+pub struct CustomAttribute {
+    #[doc = " Name of the attribute, stored in the attribute metadata"]
+    pub name: *const ::std::os::raw::c_char,
+    #[doc = " One of AttributeDataType"]
+    pub data_type: ::std::os::raw::c_int,
+    #[doc = " Number of values per point"]
+    pub num_components: ::std::os::raw::c_int,
+    #[doc = " Quantization bits for float attributes (1-30), ignored for integer attributes"]
+    pub quantization_bits: ::std::os::raw::c_int,
+    #[doc = " `num_points` * `num_components` values of the given data type"]
+    pub data: *const ::std::os::raw::c_void,
+}
+impl Default for CustomAttribute {
+    fn default() -> Self {
+        let mut s = ::std::mem::MaybeUninit::<Self>::uninit();
+        unsafe {
+            ::std::ptr::write_bytes(s.as_mut_ptr(), 0, 1);
+            s.assume_init()
+        }
+    }
+}
+#[repr(C)]
+#[derive(Debug, Default, Copy, Clone)]
+pub struct EncodeOptions {
+    #[doc = " Quantization bits for the positions (1-30)"]
+    pub position_quantization_bits: ::std::os::raw::c_int,
+    #[doc = " Significant bits kept for each color channel (1-8, or 1-16 for 16-bit colors)"]
+    pub color_quantization_bits: ::std::os::raw::c_int,
+    #[doc = " Quantization bits for the normals (1-30)"]
+    pub normal_quantization_bits: ::std::os::raw::c_int,
+    #[doc = " Quantization bits for the texture coordinates of meshes (1-30)"]
+    pub tex_coord_quantization_bits: ::std::os::raw::c_int,
+    #[doc = " Encoding speed (0 = best compression, 10 = fastest)"]
+    pub encode_speed: ::std::os::raw::c_int,
+    #[doc = " Decoding speed (0 = best compression, 10 = fastest)"]
+    pub decode_speed: ::std::os::raw::c_int,
+    #[doc = " draco::PointCloudEncodingMethod of point clouds, ignored for meshes"]
+    pub encoding_method: ::std::os::raw::c_int,
+    #[doc = " ColorFormat of the point cloud colors, ignored for meshes"]
+    pub color_format: ::std::os::raw::c_int,
+}
+pub const ColorFormat_COLOR_RGB8: ColorFormat = 0;
+pub const ColorFormat_COLOR_RGBA8: ColorFormat = 1;
+pub const ColorFormat_COLOR_RGB16: ColorFormat = 2;
+pub const ColorFormat_COLOR_RGBA16: ColorFormat = 3;
+pub type ColorFormat = ::std::os::raw::c_uint;
+#[repr(C)]
+#[derive(Debug, Copy, Clone)]
+pub struct MetadataEntry {
+    #[doc = " Key of the entry"]
+    pub key: *const ::std::os::raw::c_char,
+    #[doc = " Value of the entry"]
+    pub value: *const u8,
+    #[doc = " Length of the value in bytes"]
+    pub value_size: usize,
+}
+impl Default for MetadataEntry {
+    fn default() -> Self {
+        let mut s = ::std::mem::MaybeUninit::<Self>::uninit();
+        unsafe {
+            ::std::ptr::write_bytes(s.as_mut_ptr(), 0, 1);
+            s.assume_init()
+        }
+    }
+}
 #[repr(C)]
 #[derive(Debug, Copy, Clone)]
 pub struct EncodeResult {
@@ -4139,8 +4212,22 @@ pub struct DecodeResult {
     pub num_points: usize,
     #[doc = " Decoded coordinates"]
     pub coords: *mut f32,
-    #[doc = " Decoded colors"]
+    #[doc = " Decoded colors, converted to 8-bit RGB"]
     pub colors: *mut u8,
+    #[doc = " ColorFormat the colors were encoded with"]
+    pub color_format: ::std::os::raw::c_int,
+    #[doc = " Decoded colors in `color_format`, null if that is 8-bit RGB or they were not requested"]
+    pub native_colors: *mut ::std::os::raw::c_void,
+    #[doc = " Decoded normals, null if the point cloud has no normals"]
+    pub normals: *mut f32,
+    #[doc = " Decoded custom attributes"]
+    pub attributes: *mut CustomAttribute,
+    #[doc = " Number of decoded custom attributes"]
+    pub num_attributes: usize,
+    #[doc = " Decoded frame metadata"]
+    pub metadata: *mut MetadataEntry,
+    #[doc = " Number of decoded metadata entries"]
+    pub num_metadata: usize,
     #[doc = " Error message if decoding fails"]
     pub error_msg: *mut ::std::os::raw::c_char,
 }
@@ -4154,27 +4241,161 @@ impl Default for DecodeResult {
     }
 }
 #[repr(C)]
+#[derive(Debug, Copy, Clone)]
+pub struct MeshDecodeResult {
+    #[doc = " Indicates if decoding was successful"]
+    pub success: bool,
+    #[doc = " Number of vertices in the decoded mesh"]
+    pub num_vertices: usize,
+    #[doc = " Number of triangles in the decoded mesh"]
+    pub num_faces: usize,
+    #[doc = " Decoded vertex positions"]
+    pub positions: *mut f32,
+    #[doc = " Decoded vertex indices, three per triangle"]
+    pub indices: *mut u32,
+    #[doc = " Decoded texture coordinates, null if the mesh has none"]
+    pub tex_coords: *mut f32,
+    #[doc = " Decoded vertex colors, null if the mesh has none"]
+    pub colors: *mut u8,
+    #[doc = " Error message if decoding fails"]
+    pub error_msg: *mut ::std::os::raw::c_char,
+}
+impl Default for MeshDecodeResult {
+    fn default() -> Self {
+        let mut s = ::std::mem::MaybeUninit::<Self>::uninit();
+        unsafe {
+            ::std::ptr::write_bytes(s.as_mut_ptr(), 0, 1);
+            s.assume_init()
+        }
+    }
+}
+#[doc = " Reusable encoder, created by DracoWrapper::create_encoder"]
+#[repr(C)]
+#[derive(Debug, Copy, Clone)]
+pub struct EncoderHandle {
+    _unused: [u8; 0],
+}
+#[doc = " Reusable decoder, created by DracoWrapper::create_decoder"]
+#[repr(C)]
+#[derive(Debug, Copy, Clone)]
+pub struct DecoderHandle {
+    _unused: [u8; 0],
+}
+#[repr(C)]
 #[derive(Debug, Default, Copy, Clone)]
 pub struct DracoWrapper {
     pub _address: u8,
 }
 extern "C" {
-    #[doc = " Function to encode points to Draco\n `coords` is an array of `num_points` * 3 floats, representing X, Y, Z for each point\n `colors` is an array of `num_points` * 3 uint8_t, representing R, G, B for each point"]
-    #[link_name = "\u{1}_ZN13draco_wrapper12DracoWrapper22encode_points_to_dracoEPKfmPKh"]
+    #[doc = " Function to encode points to Draco\n `coords` is an array of `num_points` * 3 floats, representing X, Y, Z for each point\n `colors` is an array of `num_points` * 3 or 4 values of 8 or 16 bits, as given by the color format of the options\n `normals` is an optional array of `num_points` * 3 floats, representing the normal vector of each point\n `attributes` is an array of `num_attributes` custom attributes that are stored alongside the points\n `metadata` is an array of `num_metadata` key/value entries that are stored in the frame\n `options` configures the quantization and speed of the encoder, the default settings are used if it is null"]
+    #[link_name = "\u{1}_ZN13draco_wrapper12DracoWrapper22encode_points_to_dracoEPKfmPKhS2_PKNS_15CustomAttributeEmPKNS_13MetadataEntryEmPKNS_13EncodeOptionsE"]
     pub fn DracoWrapper_encode_points_to_draco(
         coords: *const f32,
         num_points: usize,
         colors: *const u8,
+        normals: *const f32,
+        attributes: *const CustomAttribute,
+        num_attributes: usize,
+        metadata: *const MetadataEntry,
+        num_metadata: usize,
+        options: *const EncodeOptions,
     ) -> *mut EncodeResult;
 }
 extern "C" {
-    #[doc = " Function to decode Draco data into points and colors\n `encoded_data` is a pointer to the encoded buffer, and `encoded_size` is the buffer length"]
+    #[doc = " Function to decode Draco data into points and colors\n The colors are also available in the format they were encoded with\n `encoded_data` is a pointer to the encoded buffer, and `encoded_size` is the buffer length"]
     #[link_name = "\u{1}_ZN13draco_wrapper12DracoWrapper17decode_draco_dataEPKhm"]
     pub fn DracoWrapper_decode_draco_data(
         encoded_data: *const u8,
         encoded_size: usize,
     ) -> *mut DecodeResult;
 }
+extern "C" {
+    #[doc = " Function to decode the points and colors of Draco data into caller-provided buffers\n `coords` and `colors` must each have room for `capacity` * 3 values, the result does not own them\n The colors are always converted to 8-bit RGB\n If the point cloud has more than `capacity` points, the points are decoded into `coords` and `colors` of the result instead"]
+    #[link_name = "\u{1}_ZN13draco_wrapper12DracoWrapper17decode_draco_intoEPKhmPfPhm"]
+    pub fn DracoWrapper_decode_draco_into(
+        encoded_data: *const u8,
+        encoded_size: usize,
+        coords: *mut f32,
+        colors: *mut u8,
+        capacity: usize,
+    ) -> *mut DecodeResult;
+}
+extern "C" {
+    #[doc = " Function to decode a subset of the points and colors of Draco data, e.g. for a low-density preview\n Every `stride`-th point is kept, up to `max_points` points in total, the normals and custom attributes are skipped\n Draco has no progressive bitstream, so the whole frame is still decoded, only the copying of the skipped points is avoided"]
+    #[link_name = "\u{1}_ZN13draco_wrapper12DracoWrapper19decode_draco_subsetEPKhmmm"]
+    pub fn DracoWrapper_decode_draco_subset(
+        encoded_data: *const u8,
+        encoded_size: usize,
+        max_points: usize,
+        stride: usize,
+    ) -> *mut DecodeResult;
+}
+extern "C" {
+    #[doc = " Function to create a reusable encoder, which keeps its output buffer across frames\n `options` configures the quantization and speed of the encoder, the default settings are used if it is null"]
+    #[link_name = "\u{1}_ZN13draco_wrapper12DracoWrapper14create_encoderEPKNS_13EncodeOptionsE"]
+    pub fn DracoWrapper_create_encoder(options: *const EncodeOptions) -> *mut EncoderHandle;
+}
+extern "C" {
+    #[doc = " Function to encode points with a reusable encoder, with the same input as `encode_points_to_draco`\n `color_format` is the ColorFormat of `colors`, the color format of the encoder options is ignored\n The result is owned by the encoder and stays valid until the next encode or until the encoder is freed"]
+    #[link_name = "\u{1}_ZN13draco_wrapper12DracoWrapper21encoder_encode_pointsEPNS_13EncoderHandleEPKfmPKhS4_PKNS_15CustomAttributeEmPKNS_13MetadataEntryEmi"]
+    pub fn DracoWrapper_encoder_encode_points(
+        encoder: *mut EncoderHandle,
+        coords: *const f32,
+        num_points: usize,
+        colors: *const u8,
+        normals: *const f32,
+        attributes: *const CustomAttribute,
+        num_attributes: usize,
+        metadata: *const MetadataEntry,
+        num_metadata: usize,
+        color_format: ::std::os::raw::c_int,
+    ) -> *const EncodeResult;
+}
+extern "C" {
+    #[doc = " Function to free a reusable encoder"]
+    #[link_name = "\u{1}_ZN13draco_wrapper12DracoWrapper12free_encoderEPNS_13EncoderHandleE"]
+    pub fn DracoWrapper_free_encoder(encoder: *mut EncoderHandle);
+}
+extern "C" {
+    #[doc = " Function to create a reusable decoder, which keeps its output buffers across frames"]
+    #[link_name = "\u{1}_ZN13draco_wrapper12DracoWrapper14create_decoderEv"]
+    pub fn DracoWrapper_create_decoder() -> *mut DecoderHandle;
+}
+extern "C" {
+    #[doc = " Function to decode the points and colors of Draco data with a reusable decoder\n The colors are always converted to 8-bit RGB, the normals and custom attributes are skipped\n The result is owned by the decoder and stays valid until the next decode or until the decoder is freed"]
+    #[link_name = "\u{1}_ZN13draco_wrapper12DracoWrapper14decoder_decodeEPNS_13DecoderHandleEPKhm"]
+    pub fn DracoWrapper_decoder_decode(
+        decoder: *mut DecoderHandle,
+        encoded_data: *const u8,
+        encoded_size: usize,
+    ) -> *const DecodeResult;
+}
+extern "C" {
+    #[doc = " Function to free a reusable decoder"]
+    #[link_name = "\u{1}_ZN13draco_wrapper12DracoWrapper12free_decoderEPNS_13DecoderHandleE"]
+    pub fn DracoWrapper_free_decoder(decoder: *mut DecoderHandle);
+}
+extern "C" {
+    #[doc = " Function to encode a triangle mesh to Draco\n `positions` is an array of `num_vertices` * 3 floats, representing X, Y, Z for each vertex\n `indices` is an array of `num_faces` * 3 vertex indices, one triangle per three indices\n `tex_coords` is an optional array of `num_vertices` * 2 floats, representing U, V for each vertex\n `colors` is an optional array of `num_vertices` * 3 uint8_t, representing R, G, B for each vertex\n `options` configures the quantization and speed of the encoder, the default settings are used if it is null"]
+    #[link_name = "\u{1}_ZN13draco_wrapper12DracoWrapper20encode_mesh_to_dracoEPKfmPKjmS2_PKhPKNS_13EncodeOptionsE"]
+    pub fn DracoWrapper_encode_mesh_to_draco(
+        positions: *const f32,
+        num_vertices: usize,
+        indices: *const u32,
+        num_faces: usize,
+        tex_coords: *const f32,
+        colors: *const u8,
+        options: *const EncodeOptions,
+    ) -> *mut EncodeResult;
+}
+extern "C" {
+    #[doc = " Function to decode Draco data into a triangle mesh\n `encoded_data` is a pointer to the encoded buffer, and `encoded_size` is the buffer length"]
+    #[link_name = "\u{1}_ZN13draco_wrapper12DracoWrapper17decode_draco_meshEPKhm"]
+    pub fn DracoWrapper_decode_draco_mesh(
+        encoded_data: *const u8,
+        encoded_size: usize,
+    ) -> *mut MeshDecodeResult;
+}
 extern "C" {
     #[doc = " Function to free the encoded result"]
     #[link_name = "\u{1}_ZN13draco_wrapper12DracoWrapper18free_encode_resultEPNS_12EncodeResultE"]
@@ -4185,14 +4406,35 @@ extern "C" {
     #[link_name = "\u{1}_ZN13draco_wrapper12DracoWrapper18free_decode_resultEPNS_12DecodeResultE"]
     pub fn DracoWrapper_free_decode_result(result: *mut DecodeResult);
 }
+extern "C" {
+    #[doc = " Function to free the decoded mesh result"]
+    #[link_name = "\u{1}_ZN13draco_wrapper12DracoWrapper23free_mesh_decode_resultEPNS_16MeshDecodeResultE"]
+    pub fn DracoWrapper_free_mesh_decode_result(result: *mut MeshDecodeResult);
+}
 impl DracoWrapper {
     #[inline]
     pub unsafe fn encode_points_to_draco(
         coords: *const f32,
         num_points: usize,
         colors: *const u8,
+        normals: *const f32,
+        attributes: *const CustomAttribute,
+        num_attributes: usize,
+        metadata: *const MetadataEntry,
+        num_metadata: usize,
+        options: *const EncodeOptions,
     ) -> *mut EncodeResult {
-        DracoWrapper_encode_points_to_draco(coords, num_points, colors)
+        DracoWrapper_encode_points_to_draco(
+            coords,
+            num_points,
+            colors,
+            normals,
+            attributes,
+            num_attributes,
+            metadata,
+            num_metadata,
+            options,
+        )
     }
     #[inline]
     pub unsafe fn decode_draco_data(
@@ -4202,6 +4444,102 @@ impl DracoWrapper {
         DracoWrapper_decode_draco_data(encoded_data, encoded_size)
     }
     #[inline]
+    pub unsafe fn decode_draco_into(
+        encoded_data: *const u8,
+        encoded_size: usize,
+        coords: *mut f32,
+        colors: *mut u8,
+        capacity: usize,
+    ) -> *mut DecodeResult {
+        DracoWrapper_decode_draco_into(encoded_data, encoded_size, coords, colors, capacity)
+    }
+    #[inline]
+    pub unsafe fn decode_draco_subset(
+        encoded_data: *const u8,
+        encoded_size: usize,
+        max_points: usize,
+        stride: usize,
+    ) -> *mut DecodeResult {
+        DracoWrapper_decode_draco_subset(encoded_data, encoded_size, max_points, stride)
+    }
+    #[inline]
+    pub unsafe fn create_encoder(options: *const EncodeOptions) -> *mut EncoderHandle {
+        DracoWrapper_create_encoder(options)
+    }
+    #[inline]
+    pub unsafe fn encoder_encode_points(
+        encoder: *mut EncoderHandle,
+        coords: *const f32,
+        num_points: usize,
+        colors: *const u8,
+        normals: *const f32,
+        attributes: *const CustomAttribute,
+        num_attributes: usize,
+        metadata: *const MetadataEntry,
+        num_metadata: usize,
+        color_format: ::std::os::raw::c_int,
+    ) -> *const EncodeResult {
+        DracoWrapper_encoder_encode_points(
+            encoder,
+            coords,
+            num_points,
+            colors,
+            normals,
+            attributes,
+            num_attributes,
+            metadata,
+            num_metadata,
+            color_format,
+        )
+    }
+    #[inline]
+    pub unsafe fn free_encoder(encoder: *mut EncoderHandle) {
+        DracoWrapper_free_encoder(encoder)
+    }
+    #[inline]
+    pub unsafe fn create_decoder() -> *mut DecoderHandle {
+        DracoWrapper_create_decoder()
+    }
+    #[inline]
+    pub unsafe fn decoder_decode(
+        decoder: *mut DecoderHandle,
+        encoded_data: *const u8,
+        encoded_size: usize,
+    ) -> *const DecodeResult {
+        DracoWrapper_decoder_decode(decoder, encoded_data, encoded_size)
+    }
+    #[inline]
+    pub unsafe fn free_decoder(decoder: *mut DecoderHandle) {
+        DracoWrapper_free_decoder(decoder)
+    }
+    #[inline]
+    pub unsafe fn encode_mesh_to_draco(
+        positions: *const f32,
+        num_vertices: usize,
+        indices: *const u32,
+        num_faces: usize,
+        tex_coords: *const f32,
+        colors: *const u8,
+        options: *const EncodeOptions,
+    ) -> *mut EncodeResult {
+        DracoWrapper_encode_mesh_to_draco(
+            positions,
+            num_vertices,
+            indices,
+            num_faces,
+            tex_coords,
+            colors,
+            options,
+        )
+    }
+    #[inline]
+    pub unsafe fn decode_draco_mesh(
+        encoded_data: *const u8,
+        encoded_size: usize,
+    ) -> *mut MeshDecodeResult {
+        DracoWrapper_decode_draco_mesh(encoded_data, encoded_size)
+    }
+    #[inline]
     pub unsafe fn free_encode_result(result: *mut EncodeResult) {
         DracoWrapper_free_encode_result(result)
     }
@@ -4209,4 +4547,8 @@ impl DracoWrapper {
     pub unsafe fn free_decode_result(result: *mut DecodeResult) {
         DracoWrapper_free_decode_result(result)
     }
+    #[inline]
+    pub unsafe fn free_mesh_decode_result(result: *mut MeshDecodeResult) {
+        DracoWrapper_free_mesh_decode_result(result)
+    }
 }
@@ -0,0 +1,154 @@
+use crate::attributes::AttributeData;
+use crate::bindings::*;
+use crate::colors::{ColorType, ColorsRef};
+use crate::error::DracoError;
+use crate::frame::{classify, error_message, slice_or_empty};
+use crate::metadata::{metadata_from_ffi, FrameMetadata};
+use crate::{EncoderOptions, FfiPointCloud};
+use std::ptr::NonNull;
+use tracing::error;
+
+/// A reusable Draco encoder, which keeps its options and output buffer across frames.
+/// Prefer this over `encode_draco_with_options` when encoding a stream of frames, e.g. at 30 fps.
+pub struct DracoEncoder {
+    handle: NonNull<EncoderHandle>,
+}
+
+// The encoder is only used through &mut self and freed exactly once
+unsafe impl Send for DracoEncoder {}
+
+impl DracoEncoder {
+    pub fn new(options: &EncoderOptions) -> Result<Self, DracoError> {
+        let options = EncodeOptions::from(options);
+        let handle = NonNull::new(unsafe { DracoWrapper_create_encoder(&options) }).ok_or(DracoError::NullResult)?;
+        Ok(Self { handle })
+    }
+
+    /// Encodes a point cloud, with optional per-point normals, custom attributes and frame metadata.
+    /// The encoded data is borrowed from the encoder and is overwritten by the next encode.
+    pub fn encode<'c>(
+        &mut self,
+        coords: &[f32],
+        colors: impl Into<ColorsRef<'c>>,
+        normals: Option<&[f32]>,
+        attributes: &[AttributeData],
+        metadata: &FrameMetadata,
+    ) -> Result<&[u8], DracoError> {
+        let colors = colors.into();
+        let point_cloud = FfiPointCloud::new(coords, colors, normals, attributes, metadata)?;
+
+        let result = unsafe {
+            DracoWrapper_encoder_encode_points(
+                self.handle.as_ptr(),
+                coords.as_ptr(),
+                point_cloud.num_points,
+                point_cloud.colors,
+                point_cloud.normals,
+                point_cloud.attributes.attributes.as_ptr(),
+                point_cloud.attributes.attributes.len(),
+                point_cloud.metadata.entries.as_ptr(),
+                point_cloud.metadata.entries.len(),
+                colors.color_type().to_ffi() as i32,
+            )
+            .as_ref()
+        };
+
+        let encoded = match result {
+            None => Err(DracoError::NullResult),
+            Some(result) if !result.success || (result.data.is_null() && result.size > 0) => {
+                Err(classify(unsafe { error_message(result.error_msg) }, DracoError::EncodeFailed))
+            }
+            Some(result) => Ok(unsafe { slice_or_empty(result.data, result.size) }),
+        };
+        encoded.inspect_err(|e| error!("Failed to encode points: {}", e))
+    }
+}
+
+impl std::fmt::Debug for DracoEncoder {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("DracoEncoder").finish_non_exhaustive()
+    }
+}
+
+impl Drop for DracoEncoder {
+    fn drop(&mut self) {
+        unsafe { DracoWrapper_free_encoder(self.handle.as_ptr()) }
+    }
+}
+
+/// A reusable Draco decoder, which keeps its output buffers across frames.
+/// Only the coordinates, colors and metadata are decoded, the colors are converted to 8-bit RGB.
+pub struct DracoDecoder {
+    handle: NonNull<DecoderHandle>,
+    /// Result of the last decode, owned by the decoder handle.
+    result: Option<NonNull<DecodeResult>>,
+}
+
+// The decoder is only modified through &mut self and freed exactly once
+unsafe impl Send for DracoDecoder {}
+
+impl DracoDecoder {
+    pub fn new() -> Result<Self, DracoError> {
+        let handle = NonNull::new(unsafe { DracoWrapper_create_decoder() }).ok_or(DracoError::NullResult)?;
+        Ok(Self { handle, result: None })
+    }
+
+    /// Decodes a frame, which replaces the previously decoded frame.
+    /// Returns the number of decoded points.
+    pub fn decode(&mut self, encoded_data: &[u8]) -> Result<usize, DracoError> {
+        self.result = NonNull::new(unsafe {
+            DracoWrapper_decoder_decode(self.handle.as_ptr(), encoded_data.as_ptr(), encoded_data.len()) as *mut DecodeResult
+        });
+
+        let raw = self.raw().ok_or(DracoError::NullResult)?;
+        if !raw.success {
+            return Err(classify(unsafe { error_message(raw.error_msg) }, DracoError::DecodeFailed));
+        }
+        Ok(raw.num_points)
+    }
+
+    fn raw(&self) -> Option<&DecodeResult> {
+        self.result.map(|result| unsafe { result.as_ref() })
+    }
+
+    /// Number of points of the last decoded frame, 0 if the last decode failed.
+    pub fn num_points(&self) -> usize {
+        self.raw().map_or(0, |raw| raw.num_points)
+    }
+
+    /// X, Y, Z for each point of the last decoded frame.
+    pub fn coords(&self) -> &[f32] {
+        self.raw().map_or(&[], |raw| unsafe { slice_or_empty(raw.coords, raw.num_points * 3) })
+    }
+
+    /// R, G, B for each point of the last decoded frame, converted to 8 bits per channel.
+    pub fn colors(&self) -> &[u8] {
+        self.raw().map_or(&[], |raw| unsafe { slice_or_empty(raw.colors, raw.num_points * 3) })
+    }
+
+    /// The channel layout and bit depth the colors of the last decoded frame were encoded with.
+    pub fn color_type(&self) -> ColorType {
+        self.raw()
+            .and_then(|raw| ColorType::from_ffi(raw.color_format as ColorFormat))
+            .unwrap_or_default()
+    }
+
+    /// Copies the key/value metadata of the last decoded frame.
+    pub fn metadata(&self) -> FrameMetadata {
+        self.raw()
+            .map(|raw| unsafe { metadata_from_ffi(slice_or_empty(raw.metadata, raw.num_metadata)) })
+            .unwrap_or_default()
+    }
+}
+
+impl std::fmt::Debug for DracoDecoder {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("DracoDecoder").field("num_points", &self.num_points()).finish()
+    }
+}
+
+impl Drop for DracoDecoder {
+    fn drop(&mut self) {
+        unsafe { DracoWrapper_free_decoder(self.handle.as_ptr()) }
+    }
+}
@@ -0,0 +1,143 @@
+use crate::bindings::*;
+
+/// Channel layout and bit depth of point cloud colors.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ColorType {
+    #[default]
+    Rgb8,
+    Rgba8,
+    Rgb16,
+    Rgba16,
+}
+
+/// Mapping between the color types and the `ColorFormat` values of the DracoWrapper.
+const FFI_COLOR_TYPES: [(ColorType, ColorFormat); 4] = [
+    (ColorType::Rgb8, ColorFormat_COLOR_RGB8),
+    (ColorType::Rgba8, ColorFormat_COLOR_RGBA8),
+    (ColorType::Rgb16, ColorFormat_COLOR_RGB16),
+    (ColorType::Rgba16, ColorFormat_COLOR_RGBA16),
+];
+
+impl ColorType {
+    /// Number of values per point.
+    pub fn channels(self) -> usize {
+        match self {
+            ColorType::Rgb8 | ColorType::Rgb16 => 3,
+            ColorType::Rgba8 | ColorType::Rgba16 => 4,
+        }
+    }
+
+    /// Bits per channel.
+    pub fn bits(self) -> u8 {
+        match self {
+            ColorType::Rgb8 | ColorType::Rgba8 => 8,
+            ColorType::Rgb16 | ColorType::Rgba16 => 16,
+        }
+    }
+
+    pub(crate) fn to_ffi(self) -> ColorFormat {
+        FFI_COLOR_TYPES.iter().find(|(color_type, _)| *color_type == self).map(|(_, ffi)| *ffi).unwrap()
+    }
+
+    pub(crate) fn from_ffi(ffi_format: ColorFormat) -> Option<Self> {
+        FFI_COLOR_TYPES.iter().find(|(_, ffi)| *ffi == ffi_format).map(|(color_type, _)| *color_type)
+    }
+}
+
+/// The colors of a point cloud, `num_points` * `channels` values in total.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Colors {
+    Rgb8(Vec<u8>),
+    Rgba8(Vec<u8>),
+    Rgb16(Vec<u16>),
+    Rgba16(Vec<u16>),
+}
+
+impl Colors {
+    pub fn color_type(&self) -> ColorType {
+        ColorsRef::from(self).color_type()
+    }
+
+    /// Number of values, not the number of points.
+    pub fn len(&self) -> usize {
+        ColorsRef::from(self).len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl Default for Colors {
+    fn default() -> Self {
+        Colors::Rgb8(Vec::new())
+    }
+}
+
+impl From<Vec<u8>> for Colors {
+    fn from(colors: Vec<u8>) -> Self {
+        Colors::Rgb8(colors)
+    }
+}
+
+/// Borrowed colors of a point cloud, e.g. to encode the same buffers every frame with a `DracoEncoder`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ColorsRef<'a> {
+    Rgb8(&'a [u8]),
+    Rgba8(&'a [u8]),
+    Rgb16(&'a [u16]),
+    Rgba16(&'a [u16]),
+}
+
+impl ColorsRef<'_> {
+    pub fn color_type(&self) -> ColorType {
+        match self {
+            ColorsRef::Rgb8(_) => ColorType::Rgb8,
+            ColorsRef::Rgba8(_) => ColorType::Rgba8,
+            ColorsRef::Rgb16(_) => ColorType::Rgb16,
+            ColorsRef::Rgba16(_) => ColorType::Rgba16,
+        }
+    }
+
+    /// Number of values, not the number of points.
+    pub fn len(&self) -> usize {
+        match self {
+            ColorsRef::Rgb8(values) | ColorsRef::Rgba8(values) => values.len(),
+            ColorsRef::Rgb16(values) | ColorsRef::Rgba16(values) => values.len(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub(crate) fn as_ptr(&self) -> *const u8 {
+        match self {
+            ColorsRef::Rgb8(values) | ColorsRef::Rgba8(values) => values.as_ptr(),
+            ColorsRef::Rgb16(values) | ColorsRef::Rgba16(values) => values.as_ptr() as *const u8,
+        }
+    }
+}
+
+impl<'a> From<&'a Colors> for ColorsRef<'a> {
+    fn from(colors: &'a Colors) -> Self {
+        match colors {
+            Colors::Rgb8(values) => ColorsRef::Rgb8(values),
+            Colors::Rgba8(values) => ColorsRef::Rgba8(values),
+            Colors::Rgb16(values) => ColorsRef::Rgb16(values),
+            Colors::Rgba16(values) => ColorsRef::Rgba16(values),
+        }
+    }
+}
+
+impl<'a> From<&'a [u8]> for ColorsRef<'a> {
+    fn from(colors: &'a [u8]) -> Self {
+        ColorsRef::Rgb8(colors)
+    }
+}
+
+impl<'a> From<&'a Vec<u8>> for ColorsRef<'a> {
+    fn from(colors: &'a Vec<u8>) -> Self {
+        ColorsRef::Rgb8(colors)
+    }
+}
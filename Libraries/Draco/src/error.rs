@@ -0,0 +1,27 @@
+use std::fmt;
+
+/// Errors returned by the Draco wrapper.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DracoError {
+    /// The input does not describe a valid point cloud or mesh, or the options are out of range.
+    InvalidInput(String),
+    /// The DracoWrapper did not return a result.
+    NullResult,
+    /// Draco failed to encode the geometry.
+    EncodeFailed(String),
+    /// Draco failed to decode the data, e.g. because it is corrupted or lacks a required attribute.
+    DecodeFailed(String),
+}
+
+impl fmt::Display for DracoError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DracoError::InvalidInput(msg) => write!(f, "Invalid input: {}", msg),
+            DracoError::NullResult => write!(f, "The Draco wrapper returned a null result"),
+            DracoError::EncodeFailed(msg) => write!(f, "Failed to encode: {}", msg),
+            DracoError::DecodeFailed(msg) => write!(f, "Failed to decode: {}", msg),
+        }
+    }
+}
+
+impl std::error::Error for DracoError {}
@@ -0,0 +1,243 @@
+use crate::attributes::{attribute_from_ffi, AttributeData};
+use crate::bindings::*;
+use crate::colors::{ColorType, Colors};
+use crate::error::DracoError;
+use crate::metadata::{metadata_from_ffi, FrameMetadata};
+use std::ffi::{c_char, CStr};
+use std::ops::Deref;
+use std::ptr::NonNull;
+use std::slice;
+
+/// Reads the error message of a failed result.
+pub(crate) unsafe fn error_message(error_msg: *const c_char) -> String {
+    if error_msg.is_null() {
+        "Unknown error".to_string()
+    } else {
+        CStr::from_ptr(error_msg).to_string_lossy().into_owned()
+    }
+}
+
+/// Borrows `len` values, an empty slice is returned for null pointers.
+pub(crate) unsafe fn slice_or_empty<'a, T>(ptr: *const T, len: usize) -> &'a [T] {
+    if ptr.is_null() || len == 0 {
+        &[]
+    } else {
+        slice::from_raw_parts(ptr, len)
+    }
+}
+
+/// Validation errors of the DracoWrapper start with "Invalid", everything else is a Draco failure.
+pub(crate) fn classify(message: String, failed: fn(String) -> DracoError) -> DracoError {
+    if message.starts_with("Invalid") {
+        DracoError::InvalidInput(message)
+    } else {
+        failed(message)
+    }
+}
+
+/// Draco-encoded data, owned by the DracoWrapper and freed on drop.
+pub struct EncodedFrame {
+    result: NonNull<EncodeResult>,
+}
+
+// The result is only read after construction and freed exactly once
+unsafe impl Send for EncodedFrame {}
+unsafe impl Sync for EncodedFrame {}
+
+impl EncodedFrame {
+    /// Takes ownership of a result of the DracoWrapper.
+    ///
+    /// # Safety
+    /// `result` must be null or a result returned by the DracoWrapper that is not freed elsewhere.
+    pub(crate) unsafe fn from_raw(result: *mut EncodeResult) -> Result<Self, DracoError> {
+        let result = NonNull::new(result).ok_or(DracoError::NullResult)?;
+        // Constructed first, so the result is freed when we return an error
+        let frame = Self { result };
+
+        let raw = result.as_ref();
+        if !raw.success || (raw.data.is_null() && raw.size > 0) {
+            return Err(classify(error_message(raw.error_msg), DracoError::EncodeFailed));
+        }
+        Ok(frame)
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        unsafe {
+            let raw = self.result.as_ref();
+            slice_or_empty(raw.data, raw.size)
+        }
+    }
+}
+
+impl Deref for EncodedFrame {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        self.as_bytes()
+    }
+}
+
+impl AsRef<[u8]> for EncodedFrame {
+    fn as_ref(&self) -> &[u8] {
+        self.as_bytes()
+    }
+}
+
+impl From<EncodedFrame> for Vec<u8> {
+    fn from(frame: EncodedFrame) -> Self {
+        frame.as_bytes().to_vec()
+    }
+}
+
+impl std::fmt::Debug for EncodedFrame {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("EncodedFrame").field("size", &self.len()).finish()
+    }
+}
+
+impl Drop for EncodedFrame {
+    fn drop(&mut self) {
+        unsafe { DracoWrapper_free_encode_result(self.result.as_ptr()) }
+    }
+}
+
+/// A decoded point cloud, owned by the DracoWrapper and freed on drop.
+/// The coordinates, colors and normals are borrowed without copying them.
+pub struct DecodedPointCloud {
+    result: NonNull<DecodeResult>,
+}
+
+// The result is only read after construction and freed exactly once
+unsafe impl Send for DecodedPointCloud {}
+unsafe impl Sync for DecodedPointCloud {}
+
+impl DecodedPointCloud {
+    /// Takes ownership of a result of the DracoWrapper.
+    ///
+    /// # Safety
+    /// `result` must be null or a result returned by the DracoWrapper that is not freed elsewhere.
+    pub(crate) unsafe fn from_raw(result: *mut DecodeResult) -> Result<Self, DracoError> {
+        let result = NonNull::new(result).ok_or(DracoError::NullResult)?;
+        // Constructed first, so the result is freed when we return an error
+        let point_cloud = Self { result };
+
+        let raw = result.as_ref();
+        if !raw.success {
+            return Err(classify(error_message(raw.error_msg), DracoError::DecodeFailed));
+        }
+        Ok(point_cloud)
+    }
+
+    fn raw(&self) -> &DecodeResult {
+        unsafe { self.result.as_ref() }
+    }
+
+    pub fn num_points(&self) -> usize {
+        self.raw().num_points
+    }
+
+    /// X, Y, Z for each point.
+    pub fn coords(&self) -> &[f32] {
+        let raw = self.raw();
+        unsafe { slice_or_empty(raw.coords, raw.num_points * 3) }
+    }
+
+    /// R, G, B for each point, converted to 8 bits per channel.
+    pub fn colors(&self) -> &[u8] {
+        let raw = self.raw();
+        unsafe { slice_or_empty(raw.colors, raw.num_points * 3) }
+    }
+
+    /// The channel layout and bit depth the colors were encoded with.
+    pub fn color_type(&self) -> ColorType {
+        ColorType::from_ffi(self.raw().color_format as ColorFormat).unwrap_or_default()
+    }
+
+    /// Copies the colors in the format they were encoded with.
+    /// `None` if only the 8-bit RGB colors were decoded, e.g. by a partial decode of a frame with 16-bit or RGBA colors.
+    pub fn native_colors(&self) -> Option<Colors> {
+        let raw = self.raw();
+        let color_type = self.color_type();
+        let len = raw.num_points * color_type.channels();
+        unsafe {
+            match color_type {
+                ColorType::Rgb8 => Some(Colors::Rgb8(self.colors().to_vec())),
+                _ if raw.native_colors.is_null() => None,
+                ColorType::Rgba8 => Some(Colors::Rgba8(slice_or_empty(raw.native_colors as *const u8, len).to_vec())),
+                ColorType::Rgb16 => Some(Colors::Rgb16(slice_or_empty(raw.native_colors as *const u16, len).to_vec())),
+                ColorType::Rgba16 => Some(Colors::Rgba16(slice_or_empty(raw.native_colors as *const u16, len).to_vec())),
+            }
+        }
+    }
+
+    /// Copies the custom attributes out of the decoded point cloud.
+    pub fn attributes(&self) -> Result<Vec<AttributeData>, DracoError> {
+        let raw = self.raw();
+        unsafe {
+            slice_or_empty(raw.attributes, raw.num_attributes)
+                .iter()
+                .map(|attribute| attribute_from_ffi(attribute, raw.num_points))
+                .collect()
+        }
+    }
+
+    /// Copies the custom attribute with the given name out of the decoded point cloud.
+    pub fn attribute(&self, name: &str) -> Result<Option<AttributeData>, DracoError> {
+        Ok(self.attributes()?.into_iter().find(|attribute| attribute.descriptor.name == name))
+    }
+
+    /// Copies the key/value metadata that was stored in the frame.
+    pub fn metadata(&self) -> FrameMetadata {
+        let raw = self.raw();
+        unsafe { metadata_from_ffi(slice_or_empty(raw.metadata, raw.num_metadata)) }
+    }
+
+    /// Copies the coordinates and colors into owned vectors.
+    pub fn to_vecs(&self) -> (Vec<f32>, Vec<u8>) {
+        (self.coords().to_vec(), self.colors().to_vec())
+    }
+}
+
+impl std::fmt::Debug for DecodedPointCloud {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("DecodedPointCloud").field("num_points", &self.num_points()).finish()
+    }
+}
+
+impl Drop for DecodedPointCloud {
+    fn drop(&mut self) {
+        unsafe { DracoWrapper_free_decode_result(self.result.as_ptr()) }
+    }
+}
+
+/// Frees a decoded mesh of the DracoWrapper on drop.
+pub(crate) struct MeshDecodeGuard {
+    result: NonNull<MeshDecodeResult>,
+}
+
+impl MeshDecodeGuard {
+    /// Takes ownership of a result of the DracoWrapper.
+    ///
+    /// # Safety
+    /// `result` must be null or a result returned by the DracoWrapper that is not freed elsewhere.
+    pub(crate) unsafe fn from_raw(result: *mut MeshDecodeResult) -> Result<Self, DracoError> {
+        let result = NonNull::new(result).ok_or(DracoError::NullResult)?;
+        let guard = Self { result };
+
+        let raw = result.as_ref();
+        if !raw.success || (raw.num_vertices > 0 && raw.positions.is_null()) || (raw.num_faces > 0 && raw.indices.is_null()) {
+            return Err(classify(error_message(raw.error_msg), DracoError::DecodeFailed));
+        }
+        Ok(guard)
+    }
+
+    pub(crate) fn raw(&self) -> &MeshDecodeResult {
+        unsafe { self.result.as_ref() }
+    }
+}
+
+impl Drop for MeshDecodeGuard {
+    fn drop(&mut self) {
+        unsafe { DracoWrapper_free_mesh_decode_result(self.result.as_ptr()) }
+    }
+}
@@ -1,92 +1,228 @@
+mod attributes;
+mod batch;
 mod bindings;
+mod codec;
+mod colors;
+mod error;
+mod frame;
+mod mesh;
+mod metadata;
+pub use attributes::*;
+pub use batch::{encode_draco_batch, PointCloudFrame};
 pub use bindings::*;
+pub use codec::{DracoDecoder, DracoEncoder};
+pub use colors::{ColorType, Colors, ColorsRef};
+pub use error::DracoError;
+pub use frame::{DecodedPointCloud, EncodedFrame};
+pub use mesh::*;
+pub use metadata::FrameMetadata;
+use metadata::{metadata_to_ffi, FfiMetadata};
 use tracing::error;
-use std::error::Error;
-use std::ffi::CStr;
-use std::slice;
 
-/// Encodes a point cloud (coords and colors) to Draco format using the DracoWrapper.
-/// Returns the encoded data as a `Vec<u8>`, or an error if the encoding fails.
-pub fn encode_draco(coords: Vec<f32>, colors: Vec<u8>) -> Result<Vec<u8>, Box<dyn Error>> {
-    // There should be at least one point
-    /*if coords.is_empty() {
-        return Err("No points to encode".into());
-    }*/
-
-    // Verify that the number of coordinates is a multiple of 3
-    if coords.len() % 3 != 0 {
-        return Err("Number of coordinates must be a multiple of 3".into());
-    }
-
-    let num_points = coords.len() / 3;
+/// Method used to encode point clouds.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum EncodingMethod {
+    /// Encodes the points in their original order, faster to encode and decode but compresses worse.
+    Sequential,
+    /// Reorders the points in a KD-tree, which compresses better at the cost of encoding time.
+    #[default]
+    KdTree,
+}
 
-    // Verify that the number of colors matches the number of points
-    if colors.len() != num_points * 3 {
-        return Err("Number of colors must match the number of points".into());
+impl EncodingMethod {
+    fn to_ffi(self) -> PointCloudEncodingMethod {
+        match self {
+            EncodingMethod::Sequential => PointCloudEncodingMethod_POINT_CLOUD_SEQUENTIAL_ENCODING,
+            EncodingMethod::KdTree => PointCloudEncodingMethod_POINT_CLOUD_KD_TREE_ENCODING,
+        }
     }
-    
-    unsafe {
-        // Call the encode function from the DracoWrapper
-        let result_ptr = DracoWrapper_encode_points_to_draco(coords.as_ptr(), num_points, colors.as_ptr());
+}
 
-        // Check if result_ptr is null
-        if result_ptr.is_null() {
-            return Err("Failed to encode points: result pointer is null".into());
-        }
+/// Settings of the Draco encoder, to trade quality against encoding time and size.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct EncoderOptions {
+    /// Quantization bits for the positions (1-30).
+    pub position_quantization_bits: u8,
+    /// Significant bits kept for each color channel (1-8, or 1-16 for 16-bit colors).
+    pub color_quantization_bits: u8,
+    /// Quantization bits for the normals (1-30), only used when normals are encoded.
+    pub normal_quantization_bits: u8,
+    /// Quantization bits for the texture coordinates of meshes (1-30).
+    pub tex_coord_quantization_bits: u8,
+    /// Encoding speed, from 0 (best compression) to 10 (fastest).
+    pub encode_speed: u8,
+    /// Decoding speed, from 0 (best compression) to 10 (fastest).
+    pub decode_speed: u8,
+    /// Encoding method of point clouds, meshes always use Edgebreaker.
+    pub encoding_method: EncodingMethod,
+}
 
-        // Dereference the pointer to get the result
-        let result = &*result_ptr;
+impl Default for EncoderOptions {
+    fn default() -> Self {
+        Self {
+            position_quantization_bits: 11,
+            color_quantization_bits: 8,
+            normal_quantization_bits: 10,
+            tex_coord_quantization_bits: 12,
+            encode_speed: 5,
+            decode_speed: 5,
+            encoding_method: EncodingMethod::KdTree,
+        }
+    }
+}
 
-        if !result.success {
-            // Handle error and free memory
-            let c_str = CStr::from_ptr(result.error_msg);
-            let error_msg = c_str.to_string_lossy().into_owned();
-            error!("Failed to encode points: {}", error_msg);
-            DracoWrapper_free_encode_result(result_ptr);
-            return Err(error_msg.into());
+impl From<&EncoderOptions> for EncodeOptions {
+    fn from(options: &EncoderOptions) -> Self {
+        Self {
+            position_quantization_bits: options.position_quantization_bits as i32,
+            color_quantization_bits: options.color_quantization_bits as i32,
+            normal_quantization_bits: options.normal_quantization_bits as i32,
+            tex_coord_quantization_bits: options.tex_coord_quantization_bits as i32,
+            encode_speed: options.encode_speed as i32,
+            decode_speed: options.decode_speed as i32,
+            encoding_method: options.encoding_method.to_ffi() as i32,
+            color_format: ColorType::Rgb8.to_ffi() as i32,
         }
+    }
+}
 
-        // Copy the encoded data into a Vec<u8>
-        let encoded_data = slice::from_raw_parts(result.data, result.size).to_vec();
+/// Encodes a point cloud (coords and colors) to Draco format using the DracoWrapper.
+/// Returns the encoded data, or an error if the encoding fails.
+pub fn encode_draco(coords: Vec<f32>, colors: Vec<u8>) -> Result<EncodedFrame, DracoError> {
+    encode_draco_with_options(coords, colors, None, &[], &FrameMetadata::new(), &EncoderOptions::default())
+}
 
-        // Free the memory allocated for the result
-        DracoWrapper_free_encode_result(result_ptr);
+/// Encodes a point cloud, with optional per-point normals, custom attributes and frame metadata, to Draco format with the given quantization and speed settings.
+/// The colors can be RGB or RGBA with 8 or 16 bits per channel, a `Vec<u8>` is taken as 8-bit RGB.
+/// Fewer quantization bits result in smaller encodes at the cost of precision.
+/// Higher speeds result in faster encodes and decodes at the cost of size, the decoded points stay the same.
+pub fn encode_draco_with_options(
+    coords: Vec<f32>,
+    colors: impl Into<Colors>,
+    normals: Option<Vec<f32>>,
+    attributes: &[AttributeData],
+    metadata: &FrameMetadata,
+    options: &EncoderOptions,
+) -> Result<EncodedFrame, DracoError> {
+    let colors = colors.into();
+    let point_cloud = FfiPointCloud::new(&coords, ColorsRef::from(&colors), normals.as_deref(), attributes, metadata)?;
+
+    let mut options = EncodeOptions::from(options);
+    options.color_format = colors.color_type().to_ffi() as i32;
+
+    let frame = unsafe {
+        EncodedFrame::from_raw(DracoWrapper_encode_points_to_draco(
+            coords.as_ptr(),
+            point_cloud.num_points,
+            point_cloud.colors,
+            point_cloud.normals,
+            point_cloud.attributes.attributes.as_ptr(),
+            point_cloud.attributes.attributes.len(),
+            point_cloud.metadata.entries.as_ptr(),
+            point_cloud.metadata.entries.len(),
+            &options,
+        ))
+    };
+    frame.inspect_err(|e| error!("Failed to encode points: {}", e))
+}
 
-        Ok(encoded_data)
-    }
+/// A validated point cloud in the layout expected by the DracoWrapper.
+/// It borrows the colors and normals and owns the attribute names and metadata keys, so it must outlive the encode call.
+pub(crate) struct FfiPointCloud {
+    pub(crate) num_points: usize,
+    pub(crate) colors: *const u8,
+    pub(crate) normals: *const f32,
+    pub(crate) attributes: FfiAttributes,
+    pub(crate) metadata: FfiMetadata,
 }
 
-/// Decodes Draco-encoded data back into point cloud coordinates and colors.
-/// Returns the coordinates and colors as two separate `Vec`s, or an error if decoding fails.
-pub fn decode_draco(encoded_data: Vec<u8>) -> Result<(Vec<f32>, Vec<u8>), Box<dyn Error>> {
-    unsafe {
-        // Call the decode function from the DracoWrapper
-        let decoded_result_ptr = DracoWrapper_decode_draco_data(encoded_data.as_ptr(), encoded_data.len());
+impl FfiPointCloud {
+    pub(crate) fn new(
+        coords: &[f32],
+        colors: ColorsRef,
+        normals: Option<&[f32]>,
+        attributes: &[AttributeData],
+        metadata: &FrameMetadata,
+    ) -> Result<Self, DracoError> {
+        // Verify that the number of coordinates is a multiple of 3
+        if !coords.len().is_multiple_of(3) {
+            return Err(DracoError::InvalidInput("Number of coordinates must be a multiple of 3".into()));
+        }
+
+        let num_points = coords.len() / 3;
 
-        if decoded_result_ptr.is_null() {
-            return Err("Failed to decode the point cloud: result pointer is null".into());
+        // Verify that the number of colors matches the number of points
+        if colors.len() != num_points * colors.color_type().channels() {
+            return Err(DracoError::InvalidInput("Number of colors must match the number of points".into()));
         }
 
-        let decoded_result = &*decoded_result_ptr;
-
-        // Check if the decoding was successful and if the data is valid
-        if !decoded_result.success || decoded_result.coords.is_null() || decoded_result.colors.is_null() {
-            let error_msg = if !decoded_result.error_msg.is_null() {
-                CStr::from_ptr(decoded_result.error_msg).to_str().unwrap_or("Unknown error")
-            } else {
-                "Unknown error"
-            };
-            DracoWrapper_free_decode_result(decoded_result_ptr);
-            return Err(error_msg.into());
+        // Verify that the number of normals matches the number of points
+        if normals.is_some_and(|normals| normals.len() != num_points * 3) {
+            return Err(DracoError::InvalidInput("Number of normals must match the number of points".into()));
         }
 
-        // Convert the decoded coordinates and colors into Rust Vecs
-        let coords_vec = slice::from_raw_parts(decoded_result.coords, decoded_result.num_points * 3).to_vec();
-        let colors_vec = slice::from_raw_parts(decoded_result.colors, decoded_result.num_points * 3).to_vec();
+        Ok(Self {
+            num_points,
+            colors: colors.as_ptr(),
+            normals: normals.map_or(std::ptr::null(), |normals| normals.as_ptr()),
+            attributes: attributes_to_ffi(attributes, num_points)?,
+            metadata: metadata_to_ffi(metadata)?,
+        })
+    }
+}
+
+/// Decodes Draco-encoded data back into a point cloud.
+/// The colors are converted to 8-bit RGB, the colors in the format they were encoded with are available through `native_colors`.
+/// The decoded data stays owned by the DracoWrapper until the returned point cloud is dropped.
+pub fn decode_draco(encoded_data: &[u8]) -> Result<DecodedPointCloud, DracoError> {
+    unsafe { DecodedPointCloud::from_raw(DracoWrapper_decode_draco_data(encoded_data.as_ptr(), encoded_data.len())) }
+}
 
-        // Free the memory allocated for the decoded result
-        DracoWrapper_free_decode_result(decoded_result_ptr);
+/// Decodes the coordinates and colors of Draco-encoded data into the given buffers, reusing their allocations across frames.
+/// The buffers are resized to fit the decoded point cloud, which only allocates when it has more points than they can hold.
+/// The colors are converted to 8-bit RGB.
+/// Returns the number of decoded points.
+pub fn decode_draco_into(encoded_data: &[u8], coords: &mut Vec<f32>, colors: &mut Vec<u8>) -> Result<usize, DracoError> {
+    coords.clear();
+    colors.clear();
+    let capacity = coords.capacity().min(colors.capacity()) / 3;
+
+    let result = unsafe {
+        DecodedPointCloud::from_raw(DracoWrapper_decode_draco_into(
+            encoded_data.as_ptr(),
+            encoded_data.len(),
+            coords.as_mut_ptr(),
+            colors.as_mut_ptr(),
+            capacity,
+        ))?
+    };
+
+    let num_points = result.num_points();
+    if num_points > capacity {
+        // The buffers were too small, so the DracoWrapper decoded into its own arrays instead
+        coords.extend_from_slice(result.coords());
+        colors.extend_from_slice(result.colors());
+    } else {
+        // The DracoWrapper has written all values of the decoded points
+        unsafe {
+            coords.set_len(num_points * 3);
+            colors.set_len(num_points * 3);
+        }
+    }
+    Ok(num_points)
+}
 
-        Ok((coords_vec, colors_vec))
+/// Decodes a subset of the points of Draco-encoded data, e.g. to show a low-density preview when a full decode does not fit the frame budget.
+/// `level` 0 keeps every point, every further level halves the density, and at most `max_points` points are returned.
+/// Only the coordinates, colors and metadata are available on the result.
+///
+/// Draco has no progressive bitstream, so the frame is still decoded as a whole; only the copying of the skipped points is avoided.
+/// With the KD-tree encoding method the points are spatially sorted, so the first points of a frame cover only part of it.
+pub fn decode_draco_partial(encoded_data: &[u8], max_points: usize, level: u8) -> Result<DecodedPointCloud, DracoError> {
+    let stride = 1usize
+        .checked_shl(level as u32)
+        .ok_or_else(|| DracoError::InvalidInput(format!("Level {} is too coarse", level)))?;
+    unsafe {
+        DecodedPointCloud::from_raw(DracoWrapper_decode_draco_subset(encoded_data.as_ptr(), encoded_data.len(), max_points, stride))
     }
 }
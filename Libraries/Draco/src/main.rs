@@ -22,11 +22,11 @@ fn main() -> Result<(), Box<dyn Error>> {
     println!("Encoding successful! Encoded size: {} bytes", encoded_data.len());
 
     // Decode the Draco-encoded data back into point cloud
-    let (decoded_coords, decoded_colors) = decode_draco(encoded_data)?;
+    let decoded = decode_draco(&encoded_data)?;
 
-    println!("Decoding successful! Number of points: {}", decoded_coords.len() / 3);
-    println!("Decoded coordinates: {:?}", decoded_coords);
-    println!("Decoded colors: {:?}", decoded_colors);
+    println!("Decoding successful! Number of points: {}", decoded.num_points());
+    println!("Decoded coordinates: {:?}", decoded.coords());
+    println!("Decoded colors: {:?}", decoded.colors());
 
     Ok(())
 }
@@ -0,0 +1,80 @@
+use crate::bindings::*;
+use crate::error::DracoError;
+use crate::frame::{slice_or_empty, EncodedFrame, MeshDecodeGuard};
+use crate::EncoderOptions;
+use tracing::error;
+
+/// A decoded triangle mesh.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DecodedMesh {
+    /// X, Y, Z for each vertex.
+    pub positions: Vec<f32>,
+    /// Three vertex indices per triangle.
+    pub indices: Vec<u32>,
+    /// U, V for each vertex, `None` if the mesh was encoded without texture coordinates.
+    pub tex_coords: Option<Vec<f32>>,
+    /// R, G, B for each vertex, `None` if the mesh was encoded without colors.
+    pub colors: Option<Vec<u8>>,
+}
+
+/// Encodes a triangle mesh to Draco format using the Edgebreaker method.
+/// `indices` holds three vertex indices per triangle, `tex_coords` and `colors` are optional per-vertex attributes.
+pub fn encode_mesh(
+    positions: Vec<f32>,
+    indices: Vec<u32>,
+    tex_coords: Option<Vec<f32>>,
+    colors: Option<Vec<u8>>,
+    options: &EncoderOptions,
+) -> Result<EncodedFrame, DracoError> {
+    if !positions.len().is_multiple_of(3) {
+        return Err(DracoError::InvalidInput("Number of positions must be a multiple of 3".into()));
+    }
+    if !indices.len().is_multiple_of(3) {
+        return Err(DracoError::InvalidInput("Number of indices must be a multiple of 3".into()));
+    }
+
+    let num_vertices = positions.len() / 3;
+    let num_faces = indices.len() / 3;
+
+    if tex_coords.as_ref().is_some_and(|tex_coords| tex_coords.len() != num_vertices * 2) {
+        return Err(DracoError::InvalidInput("Number of texture coordinates must match the number of vertices".into()));
+    }
+    if colors.as_ref().is_some_and(|colors| colors.len() != num_vertices * 3) {
+        return Err(DracoError::InvalidInput("Number of colors must match the number of vertices".into()));
+    }
+
+    let tex_coords_ptr = tex_coords.as_ref().map_or(std::ptr::null(), |tex_coords| tex_coords.as_ptr());
+    let colors_ptr = colors.as_ref().map_or(std::ptr::null(), |colors| colors.as_ptr());
+    let options = EncodeOptions::from(options);
+
+    let frame = unsafe {
+        EncodedFrame::from_raw(DracoWrapper_encode_mesh_to_draco(
+            positions.as_ptr(),
+            num_vertices,
+            indices.as_ptr(),
+            num_faces,
+            tex_coords_ptr,
+            colors_ptr,
+            &options,
+        ))
+    };
+    frame.inspect_err(|e| error!("Failed to encode mesh: {}", e))
+}
+
+/// Decodes Draco-encoded data into a triangle mesh.
+/// The vertices may be in a different order than they were encoded in, but the indices refer to the decoded order.
+pub fn decode_mesh(encoded_data: &[u8]) -> Result<DecodedMesh, DracoError> {
+    let guard = unsafe { MeshDecodeGuard::from_raw(DracoWrapper_decode_draco_mesh(encoded_data.as_ptr(), encoded_data.len()))? };
+    let result = guard.raw();
+
+    unsafe {
+        Ok(DecodedMesh {
+            positions: slice_or_empty(result.positions, result.num_vertices * 3).to_vec(),
+            indices: slice_or_empty(result.indices, result.num_faces * 3).to_vec(),
+            tex_coords: (!result.tex_coords.is_null())
+                .then(|| slice_or_empty(result.tex_coords, result.num_vertices * 2).to_vec()),
+            colors: (!result.colors.is_null())
+                .then(|| slice_or_empty(result.colors, result.num_vertices * 3).to_vec()),
+        })
+    }
+}
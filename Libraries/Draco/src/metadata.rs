@@ -0,0 +1,94 @@
+use crate::bindings::*;
+use crate::error::DracoError;
+use crate::frame::slice_or_empty;
+use std::collections::BTreeMap;
+use std::ffi::{CStr, CString};
+
+/// Small key/value metadata that is stored inside a Draco frame, such as timestamps, a stream id or a tile index.
+/// Integers are stored in little-endian byte order.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct FrameMetadata {
+    entries: BTreeMap<String, Vec<u8>>,
+}
+
+impl FrameMetadata {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn insert(&mut self, key: impl Into<String>, value: impl Into<Vec<u8>>) {
+        self.entries.insert(key.into(), value.into());
+    }
+
+    pub fn insert_str(&mut self, key: impl Into<String>, value: &str) {
+        self.insert(key, value.as_bytes());
+    }
+
+    pub fn insert_u64(&mut self, key: impl Into<String>, value: u64) {
+        self.insert(key, value.to_le_bytes());
+    }
+
+    pub fn get(&self, key: &str) -> Option<&[u8]> {
+        self.entries.get(key).map(|value| value.as_slice())
+    }
+
+    pub fn get_str(&self, key: &str) -> Option<&str> {
+        self.get(key).and_then(|value| std::str::from_utf8(value).ok())
+    }
+
+    pub fn get_u64(&self, key: &str) -> Option<u64> {
+        self.get(key).and_then(|value| value.try_into().ok()).map(u64::from_le_bytes)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &[u8])> {
+        self.entries.iter().map(|(key, value)| (key.as_str(), value.as_slice()))
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+}
+
+/// Metadata entries in the layout expected by the DracoWrapper.
+/// The keys are owned here, so this must outlive the encode call.
+pub(crate) struct FfiMetadata {
+    _keys: Vec<CString>,
+    pub(crate) entries: Vec<MetadataEntry>,
+}
+
+/// Converts the metadata to the DracoWrapper layout.
+pub(crate) fn metadata_to_ffi(metadata: &FrameMetadata) -> Result<FfiMetadata, DracoError> {
+    let mut keys = Vec::with_capacity(metadata.len());
+    let mut entries = Vec::with_capacity(metadata.len());
+
+    for (key, value) in metadata.iter() {
+        let key = CString::new(key)
+            .map_err(|_| DracoError::InvalidInput(format!("Metadata key {} contains a null byte", key)))?;
+        entries.push(MetadataEntry {
+            key: key.as_ptr(),
+            value: value.as_ptr(),
+            value_size: value.len(),
+        });
+        // Moving the CString does not move its heap allocation, so the pointer stays valid
+        keys.push(key);
+    }
+
+    Ok(FfiMetadata { _keys: keys, entries })
+}
+
+/// Copies decoded metadata entries out of the memory owned by the DracoWrapper.
+///
+/// # Safety
+/// `entries` must be the metadata of a `DecodeResult` that has not been freed yet.
+pub(crate) unsafe fn metadata_from_ffi(entries: &[MetadataEntry]) -> FrameMetadata {
+    let mut metadata = FrameMetadata::new();
+    for entry in entries {
+        let key = CStr::from_ptr(entry.key).to_string_lossy().into_owned();
+        metadata.insert(key, slice_or_empty(entry.value, entry.value_size));
+    }
+    metadata
+}
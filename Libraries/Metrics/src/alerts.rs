@@ -0,0 +1,174 @@
+use prometheus::proto::{MetricFamily, MetricType};
+use serde_json::json;
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+    thread,
+    time::{Duration, Instant},
+};
+use tracing::{error, info};
+
+/// How the value of a metric is compared to the threshold of an alert rule.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Comparison {
+    Above,
+    Below,
+}
+
+/// A simple threshold rule, e.g. `current_backlog > 20 for 5s`.
+/// Labeled metrics are evaluated per series, histograms are not supported.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AlertRule {
+    pub metric: String,
+    pub comparison: Comparison,
+    pub threshold: f64,
+    /// How long the threshold must be crossed before the alert fires.
+    pub duration: Duration,
+}
+
+impl AlertRule {
+    /// Fires when the metric is above the threshold.
+    pub fn above(metric: &str, threshold: f64) -> Self {
+        Self {
+            metric: metric.to_string(),
+            comparison: Comparison::Above,
+            threshold,
+            duration: Duration::ZERO,
+        }
+    }
+
+    /// Fires when the metric is below the threshold.
+    pub fn below(metric: &str, threshold: f64) -> Self {
+        Self {
+            comparison: Comparison::Below,
+            ..Self::above(metric, threshold)
+        }
+    }
+
+    /// Only fire when the threshold is crossed for at least `duration`.
+    pub fn for_duration(mut self, duration: Duration) -> Self {
+        self.duration = duration;
+        self
+    }
+
+    fn is_crossed(&self, value: f64) -> bool {
+        match self.comparison {
+            Comparison::Above => value > self.threshold,
+            Comparison::Below => value < self.threshold,
+        }
+    }
+}
+
+/// Whether an alert started or stopped firing.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AlertState {
+    Firing,
+    Resolved,
+}
+
+/// Passed to the alert callback when a series of the metric starts or stops crossing the threshold.
+#[derive(Debug, Clone)]
+pub struct AlertEvent {
+    pub rule: AlertRule,
+    pub state: AlertState,
+    /// Labels of the series, e.g. `mode="server",egress="WS_E"`.
+    pub labels: String,
+    pub value: f64,
+}
+
+pub type AlertCallback = Arc<dyn Fn(&AlertEvent) + Send + Sync>;
+
+#[derive(Default)]
+struct SeriesState {
+    crossed_since: Option<Instant>,
+    firing: bool,
+}
+
+/// An alert rule with the state of each series, evaluated on every `Metrics::update`.
+pub(crate) struct Alert {
+    rule: AlertRule,
+    callback: AlertCallback,
+    series: Mutex<HashMap<String, SeriesState>>,
+}
+
+impl Alert {
+    pub(crate) fn new(rule: AlertRule, callback: AlertCallback) -> Self {
+        Self {
+            rule,
+            callback,
+            series: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub(crate) fn evaluate(&self, metric_families: &[MetricFamily]) {
+        let Some(family) = metric_families.iter().find(|family| family.get_name() == self.rule.metric) else {
+            return;
+        };
+
+        let now = Instant::now();
+        let mut series = self.series.lock().expect("Failed to lock alert series");
+        for metric in family.get_metric() {
+            let value = match family.get_field_type() {
+                MetricType::GAUGE => metric.get_gauge().get_value(),
+                MetricType::COUNTER => metric.get_counter().get_value(),
+                _ => continue,
+            };
+            let labels = metric
+                .get_label()
+                .iter()
+                .map(|label| format!("{}=\"{}\"", label.get_name(), label.get_value()))
+                .collect::<Vec<_>>()
+                .join(",");
+
+            let state = series.entry(labels.clone()).or_default();
+            let new_state = if self.rule.is_crossed(value) {
+                let crossed_since = *state.crossed_since.get_or_insert(now);
+                (!state.firing && now.duration_since(crossed_since) >= self.rule.duration).then_some(AlertState::Firing)
+            } else {
+                state.crossed_since = None;
+                state.firing.then_some(AlertState::Resolved)
+            };
+
+            if let Some(new_state) = new_state {
+                state.firing = new_state == AlertState::Firing;
+                let event = AlertEvent {
+                    rule: self.rule.clone(),
+                    state: new_state,
+                    labels,
+                    value,
+                };
+                info!("Alert {:?} for {} {{{}}}: {}", event.state, event.rule.metric, event.labels, event.value);
+                (self.callback)(&event);
+            }
+        }
+    }
+}
+
+/// An alert callback that posts every event as JSON to `url`, e.g. to notify the Controller.
+/// The requests are sent on a separate thread, so a slow receiver does not delay the metrics updates.
+pub fn http_alert_callback(url: &str) -> AlertCallback {
+    let url = url.to_string();
+    Arc::new(move |event: &AlertEvent| {
+        let body = json!({
+            "metric": event.rule.metric,
+            "comparison": match event.rule.comparison {
+                Comparison::Above => "above",
+                Comparison::Below => "below",
+            },
+            "threshold": event.rule.threshold,
+            "duration_ms": event.rule.duration.as_millis() as u64,
+            "state": match event.state {
+                AlertState::Firing => "firing",
+                AlertState::Resolved => "resolved",
+            },
+            "labels": event.labels,
+            "value": event.value,
+        });
+        let url = url.clone();
+        thread::spawn(move || {
+            if let Err(e) = reqwest::blocking::Client::new().post(&url).json(&body).send() {
+                error!("Failed to send alert to {}: {}", url, e);
+            }
+        });
+    })
+}
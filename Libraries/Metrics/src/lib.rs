@@ -1,7 +1,19 @@
+mod alerts;
 mod metrics;
+mod otlp;
+mod push;
+mod rate;
+mod recorder;
 mod server;
+mod updater;
 mod utils;
 
-pub use metrics::{Metrics, MetricsBuilder, get_metrics};
-pub use server::{start_server, metrics_handler};
+pub use alerts::{http_alert_callback, AlertCallback, AlertEvent, AlertRule, AlertState, Comparison};
+pub use metrics::{Metrics, MetricsBuilder, duration_buckets_us, get_metrics, UpdateCallback};
+pub use otlp::{OtlpConfig, start_otlp_exporter};
+pub use push::{PushConfig, push_metrics, start_pusher};
+pub use rate::RateGauge;
+pub use recorder::{MetricsRecorder, RecordFormat};
+pub use server::{start_server, metrics_handler, metrics_json_handler};
+pub use updater::UpdaterHandle;
 pub use utils::get_all_interfaces;
\ No newline at end of file
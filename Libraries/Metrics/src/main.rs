@@ -1,7 +1,6 @@
 use metrics::{MetricsBuilder, get_all_interfaces, start_server};
 use tracing::{info, debug, error};
 use tracing_subscriber::FmtSubscriber;
-use std::sync::Arc;
 use tokio::time::{self, Duration};
 
 #[tokio::main]
@@ -29,15 +28,7 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     // Start the metrics update loop
     // These are for some default system metrics
     // You are responsible for updating your custom metrics
-    let metrics_clone = Arc::new(metrics);
-    tokio::spawn(async move {
-        let mut interval = time::interval(Duration::from_secs(1));
-        loop {
-            metrics_clone.update();
-            debug!("Metrics updated");
-            interval.tick().await;
-        }
-    });
+    let _updater = metrics.start_updater(Duration::from_secs(1));
 
     // Start the server on port 8080 (optional)
     tokio::spawn(start_server(8080));
@@ -1,12 +1,36 @@
-use prometheus::{self, Gauge, IntGauge, Opts, Registry};
-use sysinfo::{System, Networks};
+use prometheus::core::{Collector, MetricVec, MetricVecBuilder};
+use prometheus::{
+    self, Gauge, GaugeVec, Histogram, HistogramOpts, HistogramVec, IntCounter, IntCounterVec, IntGauge, IntGaugeVec, Opts,
+    Registry,
+};
+use sysinfo::{Networks, Pid, System};
 use std::{
     collections::HashMap,
     sync::{Arc, Mutex, RwLock},
+    time::Duration,
 };
-use tracing::{debug, instrument};
+use tracing::{debug, instrument, warn};
 use once_cell::sync::Lazy;
 
+use crate::alerts::{Alert, AlertCallback, AlertRule};
+use crate::rate::RateGauge;
+use crate::updater::UpdaterHandle;
+
+/// Name and label values of a rate gauge.
+type RateGaugeKey = (String, Vec<String>);
+
+/// A callback that is called on every `Metrics::update`, e.g. to update custom metrics or remove the series of streams that ended.
+pub type UpdateCallback = Arc<dyn Fn(&Metrics) + Send + Sync>;
+
+#[derive(Clone, Default)]
+struct UpdateCallbacks(Vec<UpdateCallback>);
+
+impl std::fmt::Debug for UpdateCallbacks {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "UpdateCallbacks({})", self.0.len())
+    }
+}
+
 /// Global singleton for the `Metrics` instance.
 pub static METRICS: Lazy<Arc<Mutex<Option<Metrics>>>> = Lazy::new(|| Arc::new(Mutex::new(None)));
 
@@ -18,15 +42,31 @@ pub struct Metrics {
     cpu_usage: Gauge,
     memory_usage: Gauge,
     network_metrics: Vec<(String, Gauge, Gauge)>, // (Interface, RX, TX)
+    process_metrics: Option<(Pid, Gauge, Gauge)>, // (Current process, CPU, RSS)
+    #[cfg(feature = "gpu")]
+    gpu_metrics: Option<GpuMetrics>,
     custom_gauges: Arc<Mutex<HashMap<String, IntGauge>>>, // Store custom gauges by name
+    custom_histograms: Arc<Mutex<HashMap<String, Histogram>>>, // Store custom histograms by name
+    custom_counters: Arc<Mutex<HashMap<String, IntCounter>>>, // Store custom counters by name
+    custom_gauge_vecs: Arc<Mutex<HashMap<String, IntGaugeVec>>>, // Store labeled gauges by name
+    custom_counter_vecs: Arc<Mutex<HashMap<String, IntCounterVec>>>, // Store labeled counters by name
+    custom_histogram_vecs: Arc<Mutex<HashMap<String, HistogramVec>>>, // Store labeled histograms by name
+    rate_gauge_vecs: Arc<Mutex<HashMap<String, GaugeVec>>>, // Store the gauges behind the rate gauges by name
+    rate_gauges: Arc<Mutex<HashMap<RateGaugeKey, RateGauge>>>, // Store rate gauges by name and label values
+    update_callbacks: Arc<Mutex<UpdateCallbacks>>,
     system: Arc<Mutex<System>>,
     networks: Arc<Mutex<Networks>>,
 }
 
 pub struct MetricsBuilder {
     interfaces: Vec<String>,
+    track_process: bool,
+    #[cfg(feature = "gpu")]
+    track_gpus: bool,
     common_labels: Vec<(String, String)>,
     custom_gauges: HashMap<String, Opts>, // Custom gauges to be added
+    custom_histograms: HashMap<String, HistogramOpts>, // Custom histograms to be added
+    custom_counters: HashMap<String, Opts>, // Custom counters to be added
 }
 
 impl MetricsBuilder {
@@ -35,8 +75,13 @@ impl MetricsBuilder {
     pub fn new() -> Self {
         Self {
             interfaces: Vec::new(),
+            track_process: false,
+            #[cfg(feature = "gpu")]
+            track_gpus: false,
             common_labels: Vec::new(),
             custom_gauges: HashMap::new(),
+            custom_histograms: HashMap::new(),
+            custom_counters: HashMap::new(),
         }
     }
 
@@ -47,6 +92,22 @@ impl MetricsBuilder {
         self
     }
 
+    /// Track the CPU usage and resident memory of the current process.
+    #[instrument(skip_all)]
+    pub fn track_process(mut self) -> Self {
+        self.track_process = true;
+        self
+    }
+
+    /// Track the utilization and memory usage of all NVIDIA GPUs through NVML.
+    /// Nothing is tracked if NVML cannot be loaded, e.g. on machines without an NVIDIA driver.
+    #[cfg(feature = "gpu")]
+    #[instrument(skip_all)]
+    pub fn track_gpus(mut self) -> Self {
+        self.track_gpus = true;
+        self
+    }
+
     /// Add a common label to be applied to all metrics.
     #[instrument(skip_all)]
     pub fn add_label(mut self, key: &str, value: &str) -> Self {
@@ -62,6 +123,22 @@ impl MetricsBuilder {
         self
     }
 
+    /// Add a histogram by name, description and bucket upper bounds.
+    #[instrument(skip_all)]
+    pub fn add_histogram(mut self, name: &str, description: &str, buckets: Vec<f64>) -> Self {
+        let opts = Self::histogram_opts_with_labels(name, description, buckets, &self.common_labels);
+        self.custom_histograms.insert(name.to_string(), opts);
+        self
+    }
+
+    /// Add a monotonic counter by name and description.
+    #[instrument(skip_all)]
+    pub fn add_counter(mut self, name: &str, description: &str) -> Self {
+        let opts = Self::opts_with_labels(name, description, &self.common_labels);
+        self.custom_counters.insert(name.to_string(), opts);
+        self
+    }
+
     /// Build the Metrics struct.
     #[instrument(skip_all)]
     pub fn build(self) -> Metrics {
@@ -107,6 +184,42 @@ impl MetricsBuilder {
             network_metrics.push((interface, rx, tx)); // Store the non-sanitized interface name, needed for sysinfo lookups (in the update method)
         }
 
+        let process_metrics = if self.track_process {
+            match sysinfo::get_current_pid() {
+                Ok(pid) => {
+                    let cpu = Gauge::with_opts(Self::opts_with_labels(
+                        "process_cpu_usage",
+                        "CPU usage percentage of this process, can exceed 100 on multiple cores",
+                        &self.common_labels,
+                    ))
+                    .expect("Failed to create process CPU gauge");
+                    let rss = Gauge::with_opts(Self::opts_with_labels(
+                        "process_memory_usage",
+                        "Resident memory of this process in bytes",
+                        &self.common_labels,
+                    ))
+                    .expect("Failed to create process memory gauge");
+
+                    registry.register(Box::new(cpu.clone())).expect("Failed to register process CPU gauge");
+                    registry.register(Box::new(rss.clone())).expect("Failed to register process memory gauge");
+                    Some((pid, cpu, rss))
+                }
+                Err(e) => {
+                    warn!("Failed to get the current process id, not tracking the process: {}", e);
+                    None
+                }
+            }
+        } else {
+            None
+        };
+
+        #[cfg(feature = "gpu")]
+        let gpu_metrics = if self.track_gpus {
+            GpuMetrics::new(&registry, &self.common_labels)
+        } else {
+            None
+        };
+
         let mut custom_gauges = HashMap::new();
         for (name, opts) in self.custom_gauges {
             let gauge = IntGauge::with_opts(opts).expect("Failed to create custom gauge");
@@ -114,6 +227,20 @@ impl MetricsBuilder {
             custom_gauges.insert(name, gauge);
         }
 
+        let mut custom_histograms = HashMap::new();
+        for (name, opts) in self.custom_histograms {
+            let histogram = Histogram::with_opts(opts).expect("Failed to create custom histogram");
+            registry.register(Box::new(histogram.clone())).expect("Failed to register custom histogram");
+            custom_histograms.insert(name, histogram);
+        }
+
+        let mut custom_counters = HashMap::new();
+        for (name, opts) in self.custom_counters {
+            let counter = IntCounter::with_opts(opts).expect("Failed to create custom counter");
+            registry.register(Box::new(counter.clone())).expect("Failed to register custom counter");
+            custom_counters.insert(name, counter);
+        }
+
         debug!("Metrics successfully built");
 
         let metrics = Metrics {
@@ -122,7 +249,18 @@ impl MetricsBuilder {
             cpu_usage,
             memory_usage,
             network_metrics,
+            process_metrics,
+            #[cfg(feature = "gpu")]
+            gpu_metrics,
             custom_gauges: Arc::new(Mutex::new(custom_gauges)),
+            custom_histograms: Arc::new(Mutex::new(custom_histograms)),
+            custom_counters: Arc::new(Mutex::new(custom_counters)),
+            custom_gauge_vecs: Arc::new(Mutex::new(HashMap::new())),
+            custom_counter_vecs: Arc::new(Mutex::new(HashMap::new())),
+            custom_histogram_vecs: Arc::new(Mutex::new(HashMap::new())),
+            rate_gauge_vecs: Arc::new(Mutex::new(HashMap::new())),
+            rate_gauges: Arc::new(Mutex::new(HashMap::new())),
+            update_callbacks: Arc::new(Mutex::new(UpdateCallbacks::default())),
             system: Arc::new(Mutex::new(System::new())),
             networks: Arc::new(Mutex::new(Networks::new_with_refreshed_list())),
         };
@@ -160,6 +298,21 @@ impl MetricsBuilder {
         }
         opts
     }
+
+    /// Helper to create histogram options with labels.
+    #[instrument(skip_all)]
+    fn histogram_opts_with_labels(name: &str, help: &str, buckets: Vec<f64>, labels: &[(String, String)]) -> HistogramOpts {
+        let mut opts = HistogramOpts::new(name, help).buckets(buckets);
+        for (key, value) in labels {
+            opts = opts.const_label(key.clone(), value.clone());
+        }
+        opts
+    }
+}
+
+/// Histogram buckets for durations in microseconds, doubling from 100 µs up to about 6.5 s.
+pub fn duration_buckets_us() -> Vec<f64> {
+    prometheus::exponential_buckets(100.0, 2.0, 17).expect("Invalid duration buckets")
 }
 
 /// Retrieve the global Metrics instance.
@@ -187,6 +340,28 @@ impl Metrics {
         self.cpu_usage.set(cpu_usage as f64);
         self.memory_usage.set(memory_usage);
 
+        if let Some((pid, cpu, rss)) = &self.process_metrics {
+            if let Some(process) = sys.process(*pid) {
+                cpu.set(process.cpu_usage() as f64);
+                rss.set(process.memory() as f64);
+            }
+        }
+
+        #[cfg(feature = "gpu")]
+        if let Some(gpu_metrics) = &self.gpu_metrics {
+            gpu_metrics.update();
+        }
+
+        for rate_gauge in self.rate_gauges.lock().expect("Failed to lock rate gauges").values() {
+            rate_gauge.refresh();
+        }
+
+        // Clone the callbacks, so a callback can add another callback without a deadlock
+        let update_callbacks = self.update_callbacks.lock().expect("Failed to lock update callbacks").clone();
+        for callback in &update_callbacks.0 {
+            callback(self);
+        }
+
         if self.network_metrics.is_empty() {
             return;
         }
@@ -226,9 +401,423 @@ impl Metrics {
         Ok(gauge)
     }
 
+    /// Add or get a custom histogram by name.
+    /// The buckets are only used when the histogram is created, an existing histogram keeps its buckets.
+    #[instrument(skip_all)]
+    pub fn get_or_create_histogram(&self, name: &str, description: &str, buckets: Vec<f64>) -> Result<Histogram, String> {
+        let mut histograms = self
+            .custom_histograms
+            .lock()
+            .map_err(|_| "Failed to lock custom histograms".to_string())?;
+        if let Some(histogram) = histograms.get(name) {
+            return Ok(histogram.clone());
+        }
+
+        let labels = self
+            .common_labels
+            .read()
+            .map_err(|_| "Failed to lock common labels".to_string())?;
+        let opts = MetricsBuilder::histogram_opts_with_labels(name, description, buckets, &labels);
+        let histogram = Histogram::with_opts(opts).map_err(|e| format!("Failed to create histogram: {}", e))?;
+        self.registry
+            .register(Box::new(histogram.clone()))
+            .map_err(|e| format!("Failed to register histogram: {}", e))?;
+        histograms.insert(name.to_string(), histogram.clone());
+        Ok(histogram)
+    }
+
+    /// Add or get a custom monotonic counter by name.
+    #[instrument(skip_all)]
+    pub fn get_or_create_counter(&self, name: &str, description: &str) -> Result<IntCounter, String> {
+        let mut counters = self
+            .custom_counters
+            .lock()
+            .map_err(|_| "Failed to lock custom counters".to_string())?;
+        if let Some(counter) = counters.get(name) {
+            return Ok(counter.clone());
+        }
+
+        let labels = self
+            .common_labels
+            .read()
+            .map_err(|_| "Failed to lock common labels".to_string())?;
+        let opts = MetricsBuilder::opts_with_labels(name, description, &labels);
+        let counter = IntCounter::with_opts(opts).map_err(|e| format!("Failed to create counter: {}", e))?;
+        self.registry
+            .register(Box::new(counter.clone()))
+            .map_err(|e| format!("Failed to register counter: {}", e))?;
+        counters.insert(name.to_string(), counter.clone());
+        Ok(counter)
+    }
+
+    /// Add or get a custom gauge with variable labels by name, e.g. one gauge per stream.
+    /// The label names are only used when the gauge is created.
+    #[instrument(skip_all)]
+    pub fn get_or_create_gauge_vec(&self, name: &str, description: &str, label_names: &[&str]) -> Result<IntGaugeVec, String> {
+        let mut gauge_vecs = self
+            .custom_gauge_vecs
+            .lock()
+            .map_err(|_| "Failed to lock custom gauge vectors".to_string())?;
+        if let Some(gauge_vec) = gauge_vecs.get(name) {
+            return Ok(gauge_vec.clone());
+        }
+
+        let labels = self
+            .common_labels
+            .read()
+            .map_err(|_| "Failed to lock common labels".to_string())?;
+        let opts = MetricsBuilder::opts_with_labels(name, description, &labels);
+        let gauge_vec = IntGaugeVec::new(opts, label_names).map_err(|e| format!("Failed to create gauge vector: {}", e))?;
+        self.registry
+            .register(Box::new(gauge_vec.clone()))
+            .map_err(|e| format!("Failed to register gauge vector: {}", e))?;
+        gauge_vecs.insert(name.to_string(), gauge_vec.clone());
+        Ok(gauge_vec)
+    }
+
+    /// Add or get a custom monotonic counter with variable labels by name.
+    /// The label names are only used when the counter is created.
+    #[instrument(skip_all)]
+    pub fn get_or_create_counter_vec(&self, name: &str, description: &str, label_names: &[&str]) -> Result<IntCounterVec, String> {
+        let mut counter_vecs = self
+            .custom_counter_vecs
+            .lock()
+            .map_err(|_| "Failed to lock custom counter vectors".to_string())?;
+        if let Some(counter_vec) = counter_vecs.get(name) {
+            return Ok(counter_vec.clone());
+        }
+
+        let labels = self
+            .common_labels
+            .read()
+            .map_err(|_| "Failed to lock common labels".to_string())?;
+        let opts = MetricsBuilder::opts_with_labels(name, description, &labels);
+        let counter_vec = IntCounterVec::new(opts, label_names).map_err(|e| format!("Failed to create counter vector: {}", e))?;
+        self.registry
+            .register(Box::new(counter_vec.clone()))
+            .map_err(|e| format!("Failed to register counter vector: {}", e))?;
+        counter_vecs.insert(name.to_string(), counter_vec.clone());
+        Ok(counter_vec)
+    }
+
+    /// Add or get a custom histogram with variable labels by name.
+    /// The buckets and label names are only used when the histogram is created.
+    #[instrument(skip_all)]
+    pub fn get_or_create_histogram_vec(
+        &self,
+        name: &str,
+        description: &str,
+        buckets: Vec<f64>,
+        label_names: &[&str],
+    ) -> Result<HistogramVec, String> {
+        let mut histogram_vecs = self
+            .custom_histogram_vecs
+            .lock()
+            .map_err(|_| "Failed to lock custom histogram vectors".to_string())?;
+        if let Some(histogram_vec) = histogram_vecs.get(name) {
+            return Ok(histogram_vec.clone());
+        }
+
+        let labels = self
+            .common_labels
+            .read()
+            .map_err(|_| "Failed to lock common labels".to_string())?;
+        let opts = MetricsBuilder::histogram_opts_with_labels(name, description, buckets, &labels);
+        let histogram_vec = HistogramVec::new(opts, label_names).map_err(|e| format!("Failed to create histogram vector: {}", e))?;
+        self.registry
+            .register(Box::new(histogram_vec.clone()))
+            .map_err(|e| format!("Failed to register histogram vector: {}", e))?;
+        histogram_vecs.insert(name.to_string(), histogram_vec.clone());
+        Ok(histogram_vec)
+    }
+
+    /// Add or get a rate gauge by name and labels, e.g. `&[("egress", "WS_E")]`.
+    /// It exports the rate per second of the amounts it is fed over a sliding window.
+    /// The window and label names are only used when the rate gauge is created.
+    #[instrument(skip_all)]
+    pub fn get_or_create_rate_gauge(
+        &self,
+        name: &str,
+        description: &str,
+        window: Duration,
+        labels: &[(&str, &str)],
+    ) -> Result<RateGauge, String> {
+        let label_values: Vec<String> = labels.iter().map(|(_, value)| value.to_string()).collect();
+        let mut rate_gauges = self
+            .rate_gauges
+            .lock()
+            .map_err(|_| "Failed to lock rate gauges".to_string())?;
+        let key = (name.to_string(), label_values);
+        if let Some(rate_gauge) = rate_gauges.get(&key) {
+            return Ok(rate_gauge.clone());
+        }
+
+        let mut gauge_vecs = self
+            .rate_gauge_vecs
+            .lock()
+            .map_err(|_| "Failed to lock rate gauge vectors".to_string())?;
+        let gauge_vec = match gauge_vecs.get(name) {
+            Some(gauge_vec) => gauge_vec.clone(),
+            None => {
+                let common_labels = self
+                    .common_labels
+                    .read()
+                    .map_err(|_| "Failed to lock common labels".to_string())?;
+                let opts = MetricsBuilder::opts_with_labels(name, description, &common_labels);
+                let label_names: Vec<&str> = labels.iter().map(|(name, _)| *name).collect();
+                let gauge_vec = GaugeVec::new(opts, &label_names).map_err(|e| format!("Failed to create rate gauge: {}", e))?;
+                self.registry
+                    .register(Box::new(gauge_vec.clone()))
+                    .map_err(|e| format!("Failed to register rate gauge: {}", e))?;
+                gauge_vecs.insert(name.to_string(), gauge_vec.clone());
+                gauge_vec
+            }
+        };
+
+        let label_values: Vec<&str> = labels.iter().map(|(_, value)| *value).collect();
+        let gauge = gauge_vec
+            .get_metric_with_label_values(&label_values)
+            .map_err(|e| format!("Failed to create rate gauge: {}", e))?;
+        let rate_gauge = RateGauge::new(gauge, window);
+        rate_gauges.insert(key, rate_gauge.clone());
+        Ok(rate_gauge)
+    }
+
+    /// Add a callback that is called on every `update`, e.g. to update custom metrics or to remove the series of streams that ended with `remove_series`.
+    #[instrument(skip_all)]
+    pub fn add_update_callback(&self, callback: UpdateCallback) {
+        self.update_callbacks.lock().expect("Failed to lock update callbacks").0.push(callback);
+    }
+
+    /// Add an alert rule, e.g. `AlertRule::above("current_backlog", 20.0).for_duration(Duration::from_secs(5))`.
+    /// The rule is evaluated on every `update`, and `callback` is called when a series starts or stops crossing the threshold.
+    #[instrument(skip_all)]
+    pub fn add_alert(&self, rule: AlertRule, callback: AlertCallback) {
+        let alert = Alert::new(rule, callback);
+        self.add_update_callback(Arc::new(move |metrics: &Metrics| {
+            alert.evaluate(&metrics.registry().gather());
+        }));
+    }
+
+    /// Start a background thread that calls `update` every `interval`, including the update callbacks.
+    /// Dropping the returned handle leaves the updater running, call `stop` to shut it down.
+    #[instrument(skip_all)]
+    pub fn start_updater(&self, interval: Duration) -> UpdaterHandle {
+        UpdaterHandle::start(self.clone(), interval)
+    }
+
+    /// Remove a custom metric by name and unregister it, so it is no longer exported.
+    /// Existing handles to the metric keep working but are not exported anymore, `get_or_create_*` creates a new metric.
+    #[instrument(skip_all)]
+    pub fn remove_metric(&self, name: &str) -> Result<(), String> {
+        let removed = self.unregister_from(&self.custom_gauges, name)?
+            || self.unregister_from(&self.custom_counters, name)?
+            || self.unregister_from(&self.custom_histograms, name)?
+            || self.unregister_from(&self.custom_gauge_vecs, name)?
+            || self.unregister_from(&self.custom_counter_vecs, name)?
+            || self.unregister_from(&self.custom_histogram_vecs, name)?;
+        if removed {
+            return Ok(());
+        }
+
+        if self.unregister_from(&self.rate_gauge_vecs, name)? {
+            self.rate_gauges
+                .lock()
+                .map_err(|_| "Failed to lock rate gauges".to_string())?
+                .retain(|(rate_gauge_name, _), _| rate_gauge_name != name);
+            return Ok(());
+        }
+
+        Err(format!("Metric {} not found", name))
+    }
+
+    /// Remove the series of all labeled metrics whose `label_name` label has `label_value`, e.g. the metrics of a stream that ended.
+    /// Returns the number of removed series.
+    /// A series that is used again, e.g. through `with_label_values`, is created again from 0.
+    #[instrument(skip_all)]
+    pub fn remove_series(&self, label_name: &str, label_value: &str) -> Result<usize, String> {
+        let mut removed = 0;
+        for gauge_vec in self.custom_gauge_vecs.lock().map_err(|_| "Failed to lock custom gauge vectors".to_string())?.values() {
+            removed += remove_vec_series(gauge_vec, label_name, label_value).len();
+        }
+        for counter_vec in self.custom_counter_vecs.lock().map_err(|_| "Failed to lock custom counter vectors".to_string())?.values() {
+            removed += remove_vec_series(counter_vec, label_name, label_value).len();
+        }
+        for histogram_vec in self.custom_histogram_vecs.lock().map_err(|_| "Failed to lock custom histogram vectors".to_string())?.values() {
+            removed += remove_vec_series(histogram_vec, label_name, label_value).len();
+        }
+
+        let mut rate_gauges = self.rate_gauges.lock().map_err(|_| "Failed to lock rate gauges".to_string())?;
+        for (name, gauge_vec) in self.rate_gauge_vecs.lock().map_err(|_| "Failed to lock rate gauge vectors".to_string())?.iter() {
+            for label_values in remove_vec_series(gauge_vec, label_name, label_value) {
+                rate_gauges.remove(&(name.clone(), label_values));
+                removed += 1;
+            }
+        }
+        Ok(removed)
+    }
+
+    /// Reset the values of all custom metrics, e.g. between two experiment runs.
+    /// Gauges are set to 0 and counters restart from 0, the series themselves are kept.
+    /// Histograms cannot be reset by prometheus and keep their observations.
+    #[instrument(skip_all)]
+    pub fn reset_all(&self) -> Result<(), String> {
+        for gauge in self.custom_gauges.lock().map_err(|_| "Failed to lock custom gauges".to_string())?.values() {
+            gauge.set(0);
+        }
+        for counter in self.custom_counters.lock().map_err(|_| "Failed to lock custom counters".to_string())?.values() {
+            counter.reset();
+        }
+        for gauge_vec in self.custom_gauge_vecs.lock().map_err(|_| "Failed to lock custom gauge vectors".to_string())?.values() {
+            reset_vec_series(gauge_vec, |gauge| gauge.set(0));
+        }
+        for counter_vec in self.custom_counter_vecs.lock().map_err(|_| "Failed to lock custom counter vectors".to_string())?.values() {
+            reset_vec_series(counter_vec, |counter| counter.reset());
+        }
+        for rate_gauge in self.rate_gauges.lock().map_err(|_| "Failed to lock rate gauges".to_string())?.values() {
+            rate_gauge.reset();
+        }
+        Ok(())
+    }
+
+    /// Remove a metric from one of the custom metric maps and unregister it, returns whether it was found.
+    fn unregister_from<M: Collector + 'static>(&self, metrics: &Mutex<HashMap<String, M>>, name: &str) -> Result<bool, String> {
+        let metric = metrics
+            .lock()
+            .map_err(|_| "Failed to lock custom metrics".to_string())?
+            .remove(name);
+        match metric {
+            Some(metric) => {
+                self.registry
+                    .unregister(Box::new(metric))
+                    .map_err(|e| format!("Failed to unregister {}: {}", name, e))?;
+                Ok(true)
+            }
+            None => Ok(false),
+        }
+    }
+
     /// Get the Prometheus registry.
     #[instrument(skip_all)]
     pub fn registry(&self) -> &Registry {
         &self.registry
     }
-}
\ No newline at end of file
+}
+/// Utilization and memory usage of the NVIDIA GPUs, read through NVML.
+#[cfg(feature = "gpu")]
+#[derive(Clone)]
+struct GpuMetrics {
+    nvml: Arc<nvml_wrapper::Nvml>,
+    devices: Vec<(u32, Gauge, Gauge)>, // (Device index, Utilization, Memory used)
+}
+
+#[cfg(feature = "gpu")]
+impl GpuMetrics {
+    fn new(registry: &Registry, common_labels: &[(String, String)]) -> Option<Self> {
+        let nvml = match nvml_wrapper::Nvml::init() {
+            Ok(nvml) => nvml,
+            Err(e) => {
+                warn!("Failed to initialize NVML, not tracking GPUs: {}", e);
+                return None;
+            }
+        };
+        let device_count = nvml.device_count().unwrap_or(0);
+
+        let mut devices = Vec::new();
+        for index in 0..device_count {
+            let utilization = Gauge::with_opts(MetricsBuilder::opts_with_labels(
+                &format!("gpu{}_utilization", index),
+                &format!("Utilization percentage of GPU {}", index),
+                common_labels,
+            ))
+            .expect("Failed to create GPU utilization gauge");
+            let memory_used = Gauge::with_opts(MetricsBuilder::opts_with_labels(
+                &format!("gpu{}_memory_usage", index),
+                &format!("Used memory of GPU {} in bytes", index),
+                common_labels,
+            ))
+            .expect("Failed to create GPU memory gauge");
+
+            registry.register(Box::new(utilization.clone())).expect("Failed to register GPU utilization gauge");
+            registry.register(Box::new(memory_used.clone())).expect("Failed to register GPU memory gauge");
+            devices.push((index, utilization, memory_used));
+        }
+
+        Some(Self {
+            nvml: Arc::new(nvml),
+            devices,
+        })
+    }
+
+    fn update(&self) {
+        for (index, utilization, memory_used) in &self.devices {
+            let Ok(device) = self.nvml.device_by_index(*index) else {
+                continue;
+            };
+            if let Ok(rates) = device.utilization_rates() {
+                utilization.set(rates.gpu as f64);
+            }
+            if let Ok(memory) = device.memory_info() {
+                memory_used.set(memory.used as f64);
+            }
+        }
+    }
+}
+
+#[cfg(feature = "gpu")]
+impl std::fmt::Debug for GpuMetrics {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("GpuMetrics").field("devices", &self.devices).finish()
+    }
+}
+
+/// The variable label names of a metric vector and the label values of each of its series, in the same order.
+fn vec_series<T: MetricVecBuilder>(vec: &MetricVec<T>) -> (Vec<String>, Vec<Vec<String>>) {
+    let label_names = vec.desc().first().map(|desc| desc.variable_labels.clone()).unwrap_or_default();
+    let series = vec
+        .collect()
+        .iter()
+        .flat_map(|family| family.get_metric())
+        .map(|metric| {
+            label_names
+                .iter()
+                .map(|name| {
+                    metric
+                        .get_label()
+                        .iter()
+                        .find(|label| label.get_name() == name)
+                        .map(|label| label.get_value().to_string())
+                        .unwrap_or_default()
+                })
+                .collect()
+        })
+        .collect();
+    (label_names, series)
+}
+
+/// Remove the series of a metric vector whose `label_name` label has `label_value`, returns the label values of the removed series.
+fn remove_vec_series<T: MetricVecBuilder>(vec: &MetricVec<T>, label_name: &str, label_value: &str) -> Vec<Vec<String>> {
+    let (label_names, series) = vec_series(vec);
+    let Some(index) = label_names.iter().position(|name| name == label_name) else {
+        return Vec::new();
+    };
+
+    series
+        .into_iter()
+        .filter(|label_values| label_values[index] == label_value)
+        .filter(|label_values| {
+            let label_values: Vec<&str> = label_values.iter().map(String::as_str).collect();
+            vec.remove_label_values(&label_values).is_ok()
+        })
+        .collect()
+}
+
+/// Reset every series of a metric vector in place, so handles to the series keep being exported.
+fn reset_vec_series<T: MetricVecBuilder>(vec: &MetricVec<T>, reset: impl Fn(&T::M)) {
+    for label_values in vec_series(vec).1 {
+        let label_values: Vec<&str> = label_values.iter().map(String::as_str).collect();
+        if let Ok(metric) = vec.get_metric_with_label_values(&label_values) {
+            reset(&metric);
+        }
+    }
+}
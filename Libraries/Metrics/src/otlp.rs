@@ -0,0 +1,169 @@
+use prometheus::proto::{LabelPair, Metric, MetricFamily, MetricType};
+use serde_json::{json, Value};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tokio::time;
+use tracing::{debug, error, instrument};
+
+use crate::get_metrics;
+
+/// Settings to export the metrics to an OpenTelemetry collector over OTLP/HTTP.
+#[derive(Debug, Clone)]
+pub struct OtlpConfig {
+    /// Address of the collector, e.g. `http://localhost:4318`. The metrics are posted to `/v1/metrics`.
+    pub endpoint: String,
+    /// Interval between two exports.
+    pub interval: Duration,
+    /// Value of the `service.name` resource attribute.
+    pub service_name: String,
+}
+
+impl OtlpConfig {
+    /// Create a new `OtlpConfig` that exports every 5 seconds.
+    pub fn new(endpoint: &str, service_name: &str) -> Self {
+        Self {
+            endpoint: endpoint.to_string(),
+            interval: Duration::from_secs(5),
+            service_name: service_name.to_string(),
+        }
+    }
+
+    /// Set the interval between two exports.
+    pub fn with_interval(mut self, interval: Duration) -> Self {
+        self.interval = interval;
+        self
+    }
+
+    fn metrics_url(&self) -> String {
+        format!("{}/v1/metrics", self.endpoint.trim_end_matches('/'))
+    }
+}
+
+/// Periodically export the metrics to the OpenTelemetry collector.
+/// The metrics are sent as cumulative values, starting at the moment the exporter was started.
+/// Failed exports are logged and retried at the next interval.
+pub async fn start_otlp_exporter(config: OtlpConfig) {
+    let client = reqwest::Client::new();
+    let url = config.metrics_url();
+    let start_time = unix_nanos();
+
+    let mut interval = time::interval(config.interval);
+    loop {
+        interval.tick().await;
+
+        let metric_families = get_metrics().registry().gather();
+        let request = export_request(&config.service_name, &metric_families, start_time, unix_nanos());
+        match client.post(&url).json(&request).send().await {
+            Ok(response) if response.status().is_success() => debug!("Metrics exported to {}", url),
+            Ok(response) => error!("Failed to export metrics to {}: {}", url, response.status()),
+            Err(e) => error!("Failed to export metrics to {}: {}", url, e),
+        }
+    }
+}
+
+/// Build an OTLP `ExportMetricsServiceRequest` in its JSON encoding from the gathered Prometheus metrics.
+#[instrument(skip_all)]
+fn export_request(service_name: &str, metric_families: &[MetricFamily], start_time: u64, time: u64) -> Value {
+    let metrics: Vec<Value> = metric_families
+        .iter()
+        .filter_map(|family| otlp_metric(family, start_time, time))
+        .collect();
+
+    json!({
+        "resourceMetrics": [{
+            "resource": {
+                "attributes": [string_attribute("service.name", service_name)]
+            },
+            "scopeMetrics": [{
+                "scope": { "name": env!("CARGO_PKG_NAME"), "version": env!("CARGO_PKG_VERSION") },
+                "metrics": metrics
+            }]
+        }]
+    })
+}
+
+/// Convert a Prometheus metric family to an OTLP metric, counters become monotonic sums.
+fn otlp_metric(family: &MetricFamily, start_time: u64, time: u64) -> Option<Value> {
+    let data_point = |metric: &Metric, value: f64| {
+        json!({
+            "attributes": attributes(metric.get_label()),
+            "startTimeUnixNano": start_time.to_string(),
+            "timeUnixNano": time.to_string(),
+            "asDouble": value
+        })
+    };
+
+    let data = match family.get_field_type() {
+        MetricType::GAUGE => {
+            let data_points: Vec<Value> = family
+                .get_metric()
+                .iter()
+                .map(|metric| data_point(metric, metric.get_gauge().get_value()))
+                .collect();
+            json!({ "gauge": { "dataPoints": data_points } })
+        }
+        MetricType::COUNTER => {
+            let data_points: Vec<Value> = family
+                .get_metric()
+                .iter()
+                .map(|metric| data_point(metric, metric.get_counter().get_value()))
+                .collect();
+            json!({ "sum": { "aggregationTemporality": 2, "isMonotonic": true, "dataPoints": data_points } })
+        }
+        MetricType::HISTOGRAM => {
+            let data_points: Vec<Value> = family
+                .get_metric()
+                .iter()
+                .map(|metric| histogram_data_point(metric, start_time, time))
+                .collect();
+            json!({ "histogram": { "aggregationTemporality": 2, "dataPoints": data_points } })
+        }
+        _ => return None,
+    };
+
+    let mut metric = json!({ "name": family.get_name(), "description": family.get_help() });
+    metric.as_object_mut()?.extend(data.as_object()?.clone());
+    Some(metric)
+}
+
+/// Prometheus buckets hold cumulative counts, OTLP buckets hold the count of their own range plus a final +Inf bucket.
+fn histogram_data_point(metric: &Metric, start_time: u64, time: u64) -> Value {
+    let histogram = metric.get_histogram();
+    let buckets = histogram.get_bucket();
+
+    let explicit_bounds: Vec<f64> = buckets.iter().map(|bucket| bucket.get_upper_bound()).collect();
+    let mut bucket_counts = Vec::with_capacity(buckets.len() + 1);
+    let mut previous = 0;
+    for bucket in buckets {
+        bucket_counts.push((bucket.get_cumulative_count() - previous).to_string());
+        previous = bucket.get_cumulative_count();
+    }
+    bucket_counts.push((histogram.get_sample_count() - previous).to_string());
+
+    json!({
+        "attributes": attributes(metric.get_label()),
+        "startTimeUnixNano": start_time.to_string(),
+        "timeUnixNano": time.to_string(),
+        "count": histogram.get_sample_count().to_string(),
+        "sum": histogram.get_sample_sum(),
+        "bucketCounts": bucket_counts,
+        "explicitBounds": explicit_bounds
+    })
+}
+
+fn attributes(labels: &[LabelPair]) -> Vec<Value> {
+    labels
+        .iter()
+        .map(|label| string_attribute(label.get_name(), label.get_value()))
+        .collect()
+}
+
+fn string_attribute(key: &str, value: &str) -> Value {
+    json!({ "key": key, "value": { "stringValue": value } })
+}
+
+fn unix_nanos() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_nanos() as u64)
+        .unwrap_or(0)
+}
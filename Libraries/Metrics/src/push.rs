@@ -0,0 +1,74 @@
+use std::{collections::HashMap, time::Duration};
+use tokio::time;
+use tracing::{debug, error, instrument};
+
+use crate::get_metrics;
+
+/// Settings to push the metrics to a Prometheus Pushgateway.
+/// Used by short-lived processes that terminate before Prometheus can scrape them.
+#[derive(Debug, Clone)]
+pub struct PushConfig {
+    /// Address of the Pushgateway, e.g. `http://localhost:9091`.
+    pub endpoint: String,
+    /// Interval between two pushes of `start_pusher`.
+    pub interval: Duration,
+    /// Value of the `job` label of the pushed metrics.
+    pub job: String,
+    /// Value of the `instance` label of the pushed metrics, if any.
+    pub instance: Option<String>,
+}
+
+impl PushConfig {
+    /// Create a new `PushConfig` that pushes every 5 seconds without an instance label.
+    pub fn new(endpoint: &str, job: &str) -> Self {
+        Self {
+            endpoint: endpoint.to_string(),
+            interval: Duration::from_secs(5),
+            job: job.to_string(),
+            instance: None,
+        }
+    }
+
+    /// Set the interval between two pushes.
+    pub fn with_interval(mut self, interval: Duration) -> Self {
+        self.interval = interval;
+        self
+    }
+
+    /// Set the instance label of the pushed metrics.
+    pub fn with_instance(mut self, instance: &str) -> Self {
+        self.instance = Some(instance.to_string());
+        self
+    }
+}
+
+/// Push the current metrics to the Pushgateway once, e.g. right before the process exits.
+/// This call is blocking, use `tokio::task::spawn_blocking` from async code.
+#[instrument(skip_all)]
+pub fn push_metrics(config: &PushConfig) -> Result<(), String> {
+    let metric_families = get_metrics().registry().gather();
+
+    let mut grouping = HashMap::new();
+    if let Some(instance) = &config.instance {
+        grouping.insert("instance".to_string(), instance.clone());
+    }
+
+    prometheus::push_metrics(&config.job, grouping, &config.endpoint, metric_families, None)
+        .map_err(|e| format!("Failed to push metrics to {}: {}", config.endpoint, e))
+}
+
+/// Periodically push the metrics to the Pushgateway.
+/// Failed pushes are logged and retried at the next interval.
+pub async fn start_pusher(config: PushConfig) {
+    let mut interval = time::interval(config.interval);
+    loop {
+        interval.tick().await;
+
+        let config_clone = config.clone();
+        match tokio::task::spawn_blocking(move || push_metrics(&config_clone)).await {
+            Ok(Ok(())) => debug!("Metrics pushed to {}", config.endpoint),
+            Ok(Err(e)) => error!("{}", e),
+            Err(e) => error!("Metrics push task failed: {}", e),
+        }
+    }
+}
@@ -0,0 +1,60 @@
+use prometheus::Gauge;
+use std::{
+    collections::VecDeque,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+/// A gauge that exports the rate per second of the amounts it is fed, e.g. bytes/sec or frames/sec,
+/// averaged over a sliding window.
+#[derive(Debug, Clone)]
+pub struct RateGauge {
+    gauge: Gauge,
+    window: Duration,
+    samples: Arc<Mutex<VecDeque<(Instant, u64)>>>, // (Time added, Amount)
+}
+
+impl RateGauge {
+    /// Create a rate gauge that exports its rate through `gauge`.
+    pub fn new(gauge: Gauge, window: Duration) -> Self {
+        Self {
+            gauge,
+            window,
+            samples: Arc::new(Mutex::new(VecDeque::new())),
+        }
+    }
+
+    /// Add an amount, e.g. the number of bytes that were just sent, and update the rate.
+    pub fn add(&self, amount: u64) {
+        let now = Instant::now();
+        let mut samples = self.samples.lock().expect("Failed to lock rate samples");
+        samples.push_back((now, amount));
+        self.update_rate(&mut samples, now);
+    }
+
+    /// Drop the amounts that fell out of the window and update the rate.
+    /// Called by `Metrics::update`, so the rate drops to 0 when nothing is added anymore.
+    pub fn refresh(&self) {
+        let mut samples = self.samples.lock().expect("Failed to lock rate samples");
+        self.update_rate(&mut samples, Instant::now());
+    }
+
+    /// Forget all amounts, setting the rate to 0.
+    pub(crate) fn reset(&self) {
+        self.samples.lock().expect("Failed to lock rate samples").clear();
+        self.gauge.set(0.0);
+    }
+
+    /// The current rate per second.
+    pub fn rate(&self) -> f64 {
+        self.gauge.get()
+    }
+
+    fn update_rate(&self, samples: &mut VecDeque<(Instant, u64)>, now: Instant) {
+        while samples.front().is_some_and(|(added, _)| now.duration_since(*added) > self.window) {
+            samples.pop_front();
+        }
+        let total: u64 = samples.iter().map(|(_, amount)| amount).sum();
+        self.gauge.set(total as f64 / self.window.as_secs_f64());
+    }
+}
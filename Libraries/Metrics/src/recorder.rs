@@ -0,0 +1,245 @@
+use prometheus::proto::{LabelPair, MetricFamily, MetricType};
+use std::{
+    fs::{self, File},
+    io::{BufWriter, Write},
+    path::{Path, PathBuf},
+    sync::mpsc::{self, RecvTimeoutError},
+    thread::{self, JoinHandle},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+use tracing::{error, info, instrument};
+
+use crate::get_metrics;
+
+/// File format of the recorded samples.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RecordFormat {
+    /// Appended after every sample, so the file is usable while the recorder is running.
+    Csv,
+    /// Written when the recorder is stopped, as Parquet files cannot be appended to.
+    #[cfg(feature = "parquet")]
+    Parquet,
+}
+
+impl RecordFormat {
+    fn extension(&self) -> &'static str {
+        match self {
+            RecordFormat::Csv => "csv",
+            #[cfg(feature = "parquet")]
+            RecordFormat::Parquet => "parquet",
+        }
+    }
+}
+
+/// A single value of a metric at a point in time.
+struct Sample {
+    timestamp: i64,
+    metric: String,
+    labels: String,
+    value: f64,
+}
+
+/// Periodically samples all registered metrics to a timestamped file on disk, so experiments can be analyzed offline.
+/// Every row holds the timestamp (ms), the metric name, its labels and its value.
+/// Histograms are recorded as their `_count` and `_sum`.
+pub struct MetricsRecorder {
+    path: PathBuf,
+    shutdown_tx: Option<mpsc::Sender<()>>,
+    task_handle: Option<JoinHandle<()>>,
+}
+
+impl MetricsRecorder {
+    /// Start recording every `interval` to a new `metrics_<timestamp>` file in `folder_path`.
+    #[instrument(skip_all)]
+    pub fn start(folder_path: &Path, format: RecordFormat, interval: Duration) -> Result<Self, String> {
+        fs::create_dir_all(folder_path).map_err(|e| format!("Failed to create {:?}: {}", folder_path, e))?;
+        let path = folder_path.join(format!("metrics_{}.{}", unix_millis(), format.extension()));
+        let mut sink = Sink::create(&path, format)?;
+        info!("Recording metrics to {:?}", path);
+
+        let (shutdown_tx, shutdown_rx) = mpsc::channel();
+        let task_handle = thread::Builder::new()
+            .name("Metrics Recorder".to_string())
+            .spawn(move || {
+                loop {
+                    let metric_families = get_metrics().registry().gather();
+                    if let Err(e) = sink.record(samples(&metric_families, unix_millis())) {
+                        error!("{}", e);
+                    }
+
+                    match shutdown_rx.recv_timeout(interval) {
+                        Err(RecvTimeoutError::Timeout) => continue,
+                        _ => break,
+                    }
+                }
+                if let Err(e) = sink.finish() {
+                    error!("{}", e);
+                }
+            })
+            .map_err(|e| format!("Failed to spawn the metrics recorder: {}", e))?;
+
+        Ok(Self {
+            path,
+            shutdown_tx: Some(shutdown_tx),
+            task_handle: Some(task_handle),
+        })
+    }
+
+    /// Path of the file the samples are written to.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Stop recording and wait until the file is completely written.
+    pub fn stop(mut self) {
+        self.shutdown();
+    }
+
+    fn shutdown(&mut self) {
+        if let Some(shutdown_tx) = self.shutdown_tx.take() {
+            let _ = shutdown_tx.send(());
+        }
+        if let Some(task_handle) = self.task_handle.take() {
+            let _ = task_handle.join();
+        }
+    }
+}
+
+impl Drop for MetricsRecorder {
+    fn drop(&mut self) {
+        self.shutdown();
+    }
+}
+
+enum Sink {
+    Csv(BufWriter<File>),
+    #[cfg(feature = "parquet")]
+    Parquet { path: PathBuf, samples: Vec<Sample> },
+}
+
+impl Sink {
+    fn create(path: &Path, format: RecordFormat) -> Result<Self, String> {
+        match format {
+            RecordFormat::Csv => {
+                let file = File::create(path).map_err(|e| format!("Failed to create {:?}: {}", path, e))?;
+                let mut writer = BufWriter::new(file);
+                writeln!(writer, "timestamp,metric,labels,value").map_err(|e| format!("Failed to write CSV header: {}", e))?;
+                Ok(Sink::Csv(writer))
+            }
+            #[cfg(feature = "parquet")]
+            RecordFormat::Parquet => Ok(Sink::Parquet {
+                path: path.to_path_buf(),
+                samples: Vec::new(),
+            }),
+        }
+    }
+
+    fn record(&mut self, new_samples: Vec<Sample>) -> Result<(), String> {
+        match self {
+            Sink::Csv(writer) => {
+                for sample in new_samples {
+                    writeln!(
+                        writer,
+                        "{},{},\"{}\",{}",
+                        sample.timestamp,
+                        sample.metric,
+                        sample.labels.replace('"', "\"\""),
+                        sample.value
+                    )
+                    .map_err(|e| format!("Failed to write metrics sample: {}", e))?;
+                }
+                // Flush every sample, so the data survives a crash of the experiment
+                writer.flush().map_err(|e| format!("Failed to flush metrics samples: {}", e))
+            }
+            #[cfg(feature = "parquet")]
+            Sink::Parquet { samples, .. } => {
+                samples.extend(new_samples);
+                Ok(())
+            }
+        }
+    }
+
+    fn finish(self) -> Result<(), String> {
+        match self {
+            Sink::Csv(mut writer) => writer.flush().map_err(|e| format!("Failed to flush metrics samples: {}", e)),
+            #[cfg(feature = "parquet")]
+            Sink::Parquet { path, samples } => write_parquet(&path, samples),
+        }
+    }
+}
+
+#[cfg(feature = "parquet")]
+fn write_parquet(path: &Path, samples: Vec<Sample>) -> Result<(), String> {
+    use polars::prelude::*;
+
+    let mut timestamps = Vec::with_capacity(samples.len());
+    let mut metrics = Vec::with_capacity(samples.len());
+    let mut labels = Vec::with_capacity(samples.len());
+    let mut values = Vec::with_capacity(samples.len());
+    for sample in samples {
+        timestamps.push(sample.timestamp);
+        metrics.push(sample.metric);
+        labels.push(sample.labels);
+        values.push(sample.value);
+    }
+
+    let mut df = DataFrame::new(vec![
+        Column::new("timestamp".into(), timestamps),
+        Column::new("metric".into(), metrics),
+        Column::new("labels".into(), labels),
+        Column::new("value".into(), values),
+    ])
+    .map_err(|e| format!("Failed to create the metrics data frame: {}", e))?;
+
+    let file = File::create(path).map_err(|e| format!("Failed to create {:?}: {}", path, e))?;
+    ParquetWriter::new(file)
+        .finish(&mut df)
+        .map_err(|e| format!("Failed to write {:?}: {}", path, e))?;
+    Ok(())
+}
+
+/// Flatten the gathered metrics into one sample per time series.
+fn samples(metric_families: &[MetricFamily], timestamp: i64) -> Vec<Sample> {
+    let mut samples = Vec::new();
+    for family in metric_families {
+        for metric in family.get_metric() {
+            let labels = format_labels(metric.get_label());
+            let mut push = |name: String, value: f64| {
+                samples.push(Sample {
+                    timestamp,
+                    metric: name,
+                    labels: labels.clone(),
+                    value,
+                })
+            };
+
+            match family.get_field_type() {
+                MetricType::GAUGE => push(family.get_name().to_string(), metric.get_gauge().get_value()),
+                MetricType::COUNTER => push(family.get_name().to_string(), metric.get_counter().get_value()),
+                MetricType::HISTOGRAM => {
+                    let histogram = metric.get_histogram();
+                    push(format!("{}_count", family.get_name()), histogram.get_sample_count() as f64);
+                    push(format!("{}_sum", family.get_name()), histogram.get_sample_sum());
+                }
+                _ => {}
+            }
+        }
+    }
+    samples
+}
+
+/// Format labels like Prometheus does, e.g. `mode="server",egress="WS_E"`.
+fn format_labels(labels: &[LabelPair]) -> String {
+    labels
+        .iter()
+        .map(|label| format!("{}=\"{}\"", label.get_name(), label.get_value()))
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+fn unix_millis() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_millis() as i64)
+        .unwrap_or(0)
+}
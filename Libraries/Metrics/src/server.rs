@@ -1,7 +1,10 @@
-use axum::{http::StatusCode, routing::get, Router};
+use axum::{http::StatusCode, routing::get, Json, Router};
 use tower_http::cors::CorsLayer;
+use prometheus::proto::{Metric, MetricFamily, MetricType};
 use prometheus::{Encoder, TextEncoder};
+use serde_json::{json, Map, Value};
 use std::net::SocketAddr;
+use std::time::{SystemTime, UNIX_EPOCH};
 
 use crate::get_metrics;
 
@@ -27,10 +30,84 @@ pub async fn metrics_handler() -> Result<String, StatusCode> {
     }
 }
 
+/// Handler function for the /metrics.json endpoint.
+/// Returns the current values of all metrics, grouped by metric name, e.g.
+/// `{"timestamp": 1700000000000, "metrics": {"cpu_usage": {"type": "gauge", "help": "...", "values": [{"labels": {"mode": "server"}, "value": 12.5}]}}}`.
+/// Histograms have a `count`, `sum` and cumulative `buckets` instead of a `value`.
+pub async fn metrics_json_handler() -> Json<Value> {
+    let registry = {
+        let metrics = get_metrics();
+        metrics.registry().clone()
+    };
+
+    let metrics: Map<String, Value> = registry
+        .gather()
+        .iter()
+        .map(|family| (family.get_name().to_string(), metric_family_to_json(family)))
+        .collect();
+
+    let timestamp = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_millis() as u64)
+        .unwrap_or(0);
+
+    Json(json!({ "timestamp": timestamp, "metrics": metrics }))
+}
+
+fn metric_family_to_json(family: &MetricFamily) -> Value {
+    let metric_type = match family.get_field_type() {
+        MetricType::COUNTER => "counter",
+        MetricType::GAUGE => "gauge",
+        MetricType::HISTOGRAM => "histogram",
+        MetricType::SUMMARY => "summary",
+        MetricType::UNTYPED => "untyped",
+    };
+    let values: Vec<Value> = family
+        .get_metric()
+        .iter()
+        .map(|metric| metric_to_json(family.get_field_type(), metric))
+        .collect();
+
+    json!({ "type": metric_type, "help": family.get_help(), "values": values })
+}
+
+fn metric_to_json(metric_type: MetricType, metric: &Metric) -> Value {
+    let labels: Map<String, Value> = metric
+        .get_label()
+        .iter()
+        .map(|label| (label.get_name().to_string(), Value::from(label.get_value())))
+        .collect();
+
+    match metric_type {
+        MetricType::COUNTER => json!({ "labels": labels, "value": metric.get_counter().get_value() }),
+        MetricType::GAUGE => json!({ "labels": labels, "value": metric.get_gauge().get_value() }),
+        MetricType::HISTOGRAM => {
+            let histogram = metric.get_histogram();
+            let buckets: Vec<Value> = histogram
+                .get_bucket()
+                .iter()
+                .map(|bucket| json!({ "le": bucket.get_upper_bound(), "count": bucket.get_cumulative_count() }))
+                .collect();
+            json!({
+                "labels": labels,
+                "count": histogram.get_sample_count(),
+                "sum": histogram.get_sample_sum(),
+                "buckets": buckets
+            })
+        }
+        MetricType::SUMMARY => {
+            let summary = metric.get_summary();
+            json!({ "labels": labels, "count": summary.get_sample_count(), "sum": summary.get_sample_sum() })
+        }
+        MetricType::UNTYPED => json!({ "labels": labels, "value": metric.get_untyped().get_value() }),
+    }
+}
+
 /// Start an HTTP server to expose metrics.
 pub async fn start_server(port: u16) {
     let app = Router::new()
         .route("/metrics", get(metrics_handler))
+        .route("/metrics.json", get(metrics_json_handler))
         // Apply middleware
         .layer(
             // We allow cross-origin requests from any origin
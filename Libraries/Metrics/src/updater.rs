@@ -0,0 +1,50 @@
+use std::{
+    sync::mpsc::{self, RecvTimeoutError},
+    thread::{self, JoinHandle},
+    time::Duration,
+};
+use tracing::{debug, error};
+
+use crate::Metrics;
+
+/// Handle to the background thread started by `Metrics::start_updater`.
+/// Like a `JoinHandle`, dropping it detaches the updater instead of stopping it.
+#[derive(Debug)]
+pub struct UpdaterHandle {
+    shutdown_tx: mpsc::Sender<()>,
+    task_handle: Option<JoinHandle<()>>,
+}
+
+impl UpdaterHandle {
+    pub(crate) fn start(metrics: Metrics, interval: Duration) -> Self {
+        let (shutdown_tx, shutdown_rx) = mpsc::channel();
+        let task_handle = thread::Builder::new()
+            .name("Metrics Updater".to_string())
+            .spawn(move || loop {
+                metrics.update();
+                debug!("Metrics updated");
+
+                match shutdown_rx.recv_timeout(interval) {
+                    Ok(()) => break,
+                    Err(RecvTimeoutError::Timeout) => {}
+                    // The handle was dropped, keep updating until the process exits
+                    Err(RecvTimeoutError::Disconnected) => thread::sleep(interval),
+                }
+            })
+            .inspect_err(|e| error!("Failed to spawn the metrics updater: {}", e))
+            .ok();
+
+        Self {
+            shutdown_tx,
+            task_handle,
+        }
+    }
+
+    /// Stop the updater and wait until its last update has finished.
+    pub fn stop(mut self) {
+        let _ = self.shutdown_tx.send(());
+        if let Some(task_handle) = self.task_handle.take() {
+            let _ = task_handle.join();
+        }
+    }
+}
@@ -0,0 +1,114 @@
+//! Authentication applied to the MPD and segment requests of the player.
+
+use reqwest::{Client, RequestBuilder, Response, StatusCode};
+use std::sync::{Arc, Mutex};
+
+/// Returns a fresh bearer token. Called before the first request and whenever the origin rejects the current token.
+/// It runs on the player's tasks, so it should return quickly.
+pub type TokenRefresh = Arc<dyn Fn() -> Option<String> + Send + Sync>;
+
+/// Returns the query string (without leading `?`) to append to a segment URL,
+/// given the representation id and the unsigned URL.
+pub type QuerySigner = Arc<dyn Fn(&str, &str) -> Option<String> + Send + Sync>;
+
+/// Headers, cookies and tokens that are added to every request of the player.
+#[derive(Clone, Default)]
+pub struct RequestAuth {
+    headers: Vec<(String, String)>,
+    cookies: Vec<(String, String)>,
+    token_refresh: Option<TokenRefresh>,
+    query_signer: Option<QuerySigner>,
+    token: Arc<Mutex<Option<String>>>,
+}
+
+impl RequestAuth {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /**
+     * Add a static header to every request, e.g. ("Authorization", "Bearer ...").
+     */
+    pub fn header(mut self, name: &str, value: &str) -> Self {
+        self.headers.push((name.to_string(), value.to_string()));
+        self
+    }
+
+    /**
+     * Add a cookie to every request.
+     */
+    pub fn cookie(mut self, name: &str, value: &str) -> Self {
+        self.cookies.push((name.to_string(), value.to_string()));
+        self
+    }
+
+    /**
+     * Set the callback that provides the bearer token.
+     * The token is sent as `Authorization: Bearer <token>` and refreshed once when a request
+     * is answered with 401 Unauthorized or 403 Forbidden.
+     */
+    pub fn token_refresh(mut self, refresh: TokenRefresh) -> Self {
+        self.token_refresh = Some(refresh);
+        self
+    }
+
+    /**
+     * Set the callback that signs the segment URLs of a representation with a query string.
+     */
+    pub fn query_signer(mut self, signer: QuerySigner) -> Self {
+        self.query_signer = Some(signer);
+        self
+    }
+
+    /// Adds the headers, cookies and current token to a request.
+    pub fn apply(&self, mut request: RequestBuilder) -> RequestBuilder {
+        for (name, value) in &self.headers {
+            request = request.header(name, value);
+        }
+        if !self.cookies.is_empty() {
+            let cookies: Vec<String> = self.cookies.iter().map(|(name, value)| format!("{}={}", name, value)).collect();
+            request = request.header(reqwest::header::COOKIE, cookies.join("; "));
+        }
+        if let Some(token) = self.current_token() {
+            request = request.bearer_auth(token);
+        }
+        request
+    }
+
+    /// Appends the signature of the representation to the URL, if a signer is configured.
+    pub fn sign_url(&self, representation_id: &str, url: &str) -> String {
+        let Some(query) = self.query_signer.as_ref().and_then(|signer| signer(representation_id, url)) else {
+            return url.to_string();
+        };
+        let separator = if url.contains('?') { '&' } else { '?' };
+        format!("{}{}{}", url, separator, query.trim_start_matches('?'))
+    }
+
+    /// Sends an authenticated GET request. When the origin rejects the token, it is refreshed and the request is retried once.
+    pub async fn get(&self, client: &Client, url: &str) -> reqwest::Result<Response> {
+        let response = self.apply(client.get(url)).send().await?;
+        if matches!(response.status(), StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN) && self.refresh_token() {
+            return self.apply(client.get(url)).send().await;
+        }
+        Ok(response)
+    }
+
+    fn current_token(&self) -> Option<String> {
+        let token = self.token.lock().unwrap().clone();
+        if token.is_none() && self.refresh_token() {
+            return self.token.lock().unwrap().clone();
+        }
+        token
+    }
+
+    /// Fetches a new token, returns true if one was obtained.
+    fn refresh_token(&self) -> bool {
+        let Some(refresh) = self.token_refresh.as_ref() else {
+            return false;
+        };
+        let token = refresh();
+        let refreshed = token.is_some();
+        *self.token.lock().unwrap() = token;
+        refreshed
+    }
+}
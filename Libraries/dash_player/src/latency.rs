@@ -0,0 +1,74 @@
+//! Live latency control.
+//! The player speeds up (or slows down) playback to move the live latency towards the target latency.
+
+/// Gains and limits of the proportional playback-rate controller.
+#[derive(Debug, Clone, Copy)]
+pub struct LatencyControllerConfig {
+    /// Proportional gain. Higher values catch up quicker, lower values are smoother.
+    pub k_p: f64,
+    /// Latency errors (in seconds) smaller than this do not change the playback rate, to avoid jitter.
+    pub dead_zone: f64,
+    /// Lower bound of the rate adjustment, before it is added to the nominal rate of 1.0.
+    pub min_adjustment: f64,
+    /// Upper bound of the rate adjustment, before it is added to the nominal rate of 1.0.
+    pub max_adjustment: f64,
+    /// Lowest playback rate the controller will select.
+    pub min_rate: f64,
+    /// Highest playback rate the controller will select.
+    pub max_rate: f64,
+}
+
+impl Default for LatencyControllerConfig {
+    fn default() -> Self {
+        // Tuned for small segment durations and aggressive latency correction
+        Self {
+            k_p: 1.2,
+            dead_zone: 0.01,
+            min_adjustment: -0.2,
+            max_adjustment: 1.5,
+            min_rate: 0.8,
+            max_rate: 2.5,
+        }
+    }
+}
+
+impl LatencyControllerConfig {
+    /// Checks that the limits can be applied, `playback_rate` relies on this.
+    pub fn validate(&self) -> Result<(), String> {
+        let values = [self.k_p, self.dead_zone, self.min_adjustment, self.max_adjustment, self.min_rate, self.max_rate];
+        if values.iter().any(|value| !value.is_finite()) {
+            return Err(format!("The latency controller settings have to be finite, got {:?}", self));
+        }
+        if self.dead_zone < 0.0 {
+            return Err(format!("The dead zone can not be negative, got {}", self.dead_zone));
+        }
+        if self.min_adjustment > self.max_adjustment {
+            return Err(format!("The minimum rate adjustment {} is above the maximum {}", self.min_adjustment, self.max_adjustment));
+        }
+        if self.min_rate <= 0.0 || self.min_rate > self.max_rate {
+            return Err(format!("The playback rates have to be positive with the minimum at most the maximum, got {} to {}", self.min_rate, self.max_rate));
+        }
+        Ok(())
+    }
+
+    /// Returns the playback rate for the given latency error (current - target latency, in seconds).
+    pub fn playback_rate(&self, latency_diff: f64) -> f64 {
+        if latency_diff.abs() < self.dead_zone {
+            1.0
+        } else {
+            let adjustment = (latency_diff * self.k_p).clamp(self.min_adjustment, self.max_adjustment);
+            (1.0 + adjustment).clamp(self.min_rate, self.max_rate)
+        }
+    }
+}
+
+/// Latency controller state of one adaptation set, as of the most recent segment.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct LatencyStatus {
+    /// Live latency in seconds.
+    pub current_latency: f64,
+    /// Target latency in seconds.
+    pub target_latency: f64,
+    /// Playback rate selected by the controller.
+    pub playback_rate: f64,
+}
@@ -1,7 +1,11 @@
+pub mod auth;
+pub mod latency;
 pub mod mpd;
 pub mod segment;
 pub mod player;
+pub mod state;
 use bytes::Bytes;
+use segment::demuxer::Sample;
 
 /// Events emitted by the player
 pub enum DashEvent {
@@ -14,13 +18,33 @@ pub enum DashEvent {
         url: String,
         playback_rate: f64,
     },
+    /// A single sample of a media segment, emitted instead of `Segment` when demuxing is enabled.
+    Sample {
+        content_type: String,
+        /// Id of the adaptation set of the representation, representation ids only have to be unique within their adaptation set
+        adaptation_set_id: String,
+        representation_id: String,
+        segment_number: u64,
+        sample: Sample,
+        playback_rate: f64,
+    },
     DownloadError {
         url: String,
         reason: String,
     },
+    /// The latency controller state after selecting the playback rate for a segment.
+    Latency {
+        adaptation_id: String,
+        status: LatencyStatus,
+    },
+    /// All segments of a static presentation have been fetched.
+    EndOfStream,
     Info(String),
     Warning(String),
 }
 
 
+pub use auth::RequestAuth;
 pub use player::DashPlayer;
+pub use state::PlaybackState;
+pub use latency::{LatencyControllerConfig, LatencyStatus};
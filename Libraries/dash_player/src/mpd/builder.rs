@@ -14,6 +14,31 @@ pub struct RepresentationDef {
     pub media: String,
     pub availability_time_offset: Option<f64>,
     pub availability_time_complete: Option<bool>,
+    /// Representations with the same id share an adaptation set, e.g. the track of one tile.
+    /// Representations without one are put in the default adaptation set.
+    pub adaptation_set_id: Option<String>,
+}
+
+/// Latency targets and playback rate bounds of a low latency presentation, written as a ServiceDescription
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ServiceDescriptionDef {
+    pub latency_target_ms: u64,
+    pub latency_min_ms: Option<u64>,
+    pub latency_max_ms: Option<u64>,
+    pub playback_rate_min: Option<f64>,
+    pub playback_rate_max: Option<f64>,
+}
+
+/// A period that has ended, e.g. because the representations were reconfigured
+#[derive(Debug, Clone)]
+pub struct PeriodDef {
+    pub id: String,
+    /// Start of the period in seconds, relative to the availability start time
+    pub start: f64,
+    pub segment_duration: u64,
+    pub timescale: u64,
+    pub start_number: Option<u64>,
+    pub representations: Vec<RepresentationDef>,
 }
 
 /// Main MPD builder
@@ -25,6 +50,15 @@ pub struct MpdBuilder {
     pub suggested_presentation_delay: Option<f64>,
     pub segment_duration: u64,
     pub timescale: u64,
+    pub start_number: Option<u64>,
+    pub media_presentation_duration: Option<f64>,
+    pub service_description: Option<ServiceDescriptionDef>,
+    /// The periods before the current one, oldest first
+    pub previous_periods: Vec<PeriodDef>,
+    /// Id and start (in seconds) of the current period
+    pub period_id: String,
+    pub period_start: f64,
+    /// The representations of the current period
     pub representations: Vec<RepresentationDef>,
 }
 
@@ -37,10 +71,29 @@ impl MpdBuilder {
             suggested_presentation_delay: Some(2.0),
             segment_duration: 1,
             timescale: 1,
+            start_number: None,
+            media_presentation_duration: None,
+            service_description: None,
+            previous_periods: vec![],
+            period_id: "0".to_string(),
+            period_start: 0.0,
             representations: vec![],
         }
     }
 
+    /**
+     * Create a builder for a static MPD, e.g. of a recording.
+     * The duration is the total length of the presentation in seconds.
+     */
+    pub fn recording(duration: f64) -> Self {
+        Self {
+            minimum_update_period: None,
+            suggested_presentation_delay: None,
+            media_presentation_duration: Some(duration),
+            ..Self::live()
+        }
+    }
+
     /**
      * Set the availability start time for the MPD.
      * This is the time when the first segment is available for playback.
@@ -71,6 +124,15 @@ impl MpdBuilder {
         self
     }
 
+    /**
+     * Set the number of the first segment of every representation.
+     * This is needed when the oldest segments are no longer available, e.g. for a rolling recording.
+     */
+    pub fn start_number(mut self, start_number: u64) -> Self {
+        self.start_number = Some(start_number);
+        self
+    }
+
     /**
      * Set the minimum update period for the MPD.
      * This is the minimum time between updates to the MPD.
@@ -89,6 +151,43 @@ impl MpdBuilder {
         self
     }
 
+    /**
+     * Set the service description of the MPD.
+     * It tells low latency players which live latency to aim for, and how far they may change the playback rate to get there.
+     */
+    pub fn service_description(mut self, service_description: ServiceDescriptionDef) -> Self {
+        self.service_description = Some(service_description);
+        self
+    }
+
+    /**
+     * End the current period, and start a new one without representations.
+     * The start is in seconds relative to the availability start time, the start number is the number of its first segment.
+     * The segment duration and timescale of the new period are set with the builder fields afterwards.
+     */
+    pub fn start_period(&mut self, id: &str, start: f64, start_number: u64) {
+        self.previous_periods.push(PeriodDef {
+            id: self.period_id.clone(),
+            start: self.period_start,
+            segment_duration: self.segment_duration,
+            timescale: self.timescale,
+            start_number: self.start_number,
+            representations: std::mem::take(&mut self.representations),
+        });
+        self.period_id = id.to_string();
+        self.period_start = start;
+        self.start_number = Some(start_number);
+    }
+
+    /**
+     * Returns when the segment with the given number starts in the current period,
+     * in seconds relative to the availability start time.
+     */
+    pub fn segment_start(&self, number: u64) -> f64 {
+        let segments = number.saturating_sub(self.start_number.unwrap_or(0));
+        self.period_start + segments as f64 * self.segment_duration as f64 / self.timescale.max(1) as f64
+    }
+
     /**
      * Add a new representation to the MPD.
      * Each representation is a different quality level of the same content.
@@ -121,6 +220,7 @@ impl MpdBuilder {
             media: media.to_string(),
             availability_time_offset,
             availability_time_complete,
+            adaptation_set_id: None,
         });
         self
     }
@@ -135,15 +235,20 @@ impl MpdBuilder {
 
         let mut mpd = BytesStart::new("MPD");
         mpd.push_attribute(("xmlns", "urn:mpeg:dash:schema:mpd:2011"));
-        mpd.push_attribute(("type", "dynamic"));
-        mpd.push_attribute((
-            "availabilityStartTime",
-            self.availability_start_time.to_rfc3339().as_str(),
-        ));
-        mpd.push_attribute((
-            "timeShiftBufferDepth",
-            format!("PT{}S", self.time_shift_buffer_depth).as_str(),
-        ));
+        if let Some(duration) = self.media_presentation_duration {
+            mpd.push_attribute(("type", "static"));
+            mpd.push_attribute(("mediaPresentationDuration", format!("PT{}S", duration).as_str()));
+        } else {
+            mpd.push_attribute(("type", "dynamic"));
+            mpd.push_attribute((
+                "availabilityStartTime",
+                self.availability_start_time.to_rfc3339().as_str(),
+            ));
+            mpd.push_attribute((
+                "timeShiftBufferDepth",
+                format!("PT{}S", self.time_shift_buffer_depth).as_str(),
+            ));
+        }
         if let Some(v) = self.minimum_update_period {
             mpd.push_attribute(("minimumUpdatePeriod", format!("PT{}S", v).as_str()));
         }
@@ -153,15 +258,108 @@ impl MpdBuilder {
 
         writer.write_event(Event::Start(mpd))?;
 
-        writer.write_event(Event::Start(BytesStart::new("Period")))?;
+        if let Some(service_description) = &self.service_description {
+            let mut service = BytesStart::new("ServiceDescription");
+            service.push_attribute(("id", "0"));
+            writer.write_event(Event::Start(service))?;
 
+            let mut latency = BytesStart::new("Latency");
+            latency.push_attribute(("referenceId", "0"));
+            latency.push_attribute(("target", service_description.latency_target_ms.to_string().as_str()));
+            if let Some(min) = service_description.latency_min_ms {
+                latency.push_attribute(("min", min.to_string().as_str()));
+            }
+            if let Some(max) = service_description.latency_max_ms {
+                latency.push_attribute(("max", max.to_string().as_str()));
+            }
+            writer.write_event(Event::Empty(latency))?;
+
+            if service_description.playback_rate_min.is_some() || service_description.playback_rate_max.is_some() {
+                let mut playback_rate = BytesStart::new("PlaybackRate");
+                if let Some(min) = service_description.playback_rate_min {
+                    playback_rate.push_attribute(("min", min.to_string().as_str()));
+                }
+                if let Some(max) = service_description.playback_rate_max {
+                    playback_rate.push_attribute(("max", max.to_string().as_str()));
+                }
+                writer.write_event(Event::Empty(playback_rate))?;
+            }
+
+            writer.write_event(Event::End(BytesEnd::new("ServiceDescription")))?;
+        }
+
+        for period in &self.previous_periods {
+            self.write_period(&mut writer, period.id.as_str(), period.start, period.segment_duration, period.timescale, period.start_number, &period.representations)?;
+        }
+        self.write_period(&mut writer, &self.period_id, self.period_start, self.segment_duration, self.timescale, self.start_number, &self.representations)?;
+
+        writer.write_event(Event::End(BytesEnd::new("MPD")))?;
+
+        let result = writer.into_inner().into_inner();
+        Ok(String::from_utf8(result)?)
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn write_period(
+        &self,
+        writer: &mut Writer<Cursor<Vec<u8>>>,
+        id: &str,
+        start: f64,
+        segment_duration: u64,
+        timescale: u64,
+        start_number: Option<u64>,
+        representations: &[RepresentationDef],
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let mut period = BytesStart::new("Period");
+        period.push_attribute(("id", id));
+        // A single period of a live presentation starts with it, only later periods need a start
+        if start > 0.0 || !self.previous_periods.is_empty() {
+            period.push_attribute(("start", format!("PT{}S", start).as_str()));
+        }
+        writer.write_event(Event::Start(period))?;
+
+        // The adaptation sets are written in the order their first representation was added
+        let mut adaptation_set_ids: Vec<Option<&str>> = Vec::new();
+        for rep in representations {
+            if !adaptation_set_ids.contains(&rep.adaptation_set_id.as_deref()) {
+                adaptation_set_ids.push(rep.adaptation_set_id.as_deref());
+            }
+        }
+        if adaptation_set_ids.is_empty() {
+            adaptation_set_ids.push(None);
+        }
+
+        for adaptation_set_id in adaptation_set_ids {
+            let adaptation_representations: Vec<&RepresentationDef> = representations
+                .iter()
+                .filter(|rep| rep.adaptation_set_id.as_deref() == adaptation_set_id)
+                .collect();
+            self.write_adaptation_set(writer, adaptation_set_id, segment_duration, timescale, start_number, &adaptation_representations)?;
+        }
+
+        writer.write_event(Event::End(BytesEnd::new("Period")))?;
+        Ok(())
+    }
+
+    fn write_adaptation_set(
+        &self,
+        writer: &mut Writer<Cursor<Vec<u8>>>,
+        id: Option<&str>,
+        segment_duration: u64,
+        timescale: u64,
+        start_number: Option<u64>,
+        representations: &[&RepresentationDef],
+    ) -> Result<(), Box<dyn std::error::Error>> {
         let mut adaptation = BytesStart::new("AdaptationSet");
-        if let Some(first) = self.representations.first() {
+        if let Some(id) = id {
+            adaptation.push_attribute(("id", id));
+        }
+        if let Some(first) = representations.first() {
             adaptation.push_attribute(("mimeType", first.mime_type.as_str()));
         }
         writer.write_event(Event::Start(adaptation))?;
 
-        for rep in &self.representations {
+        for rep in representations {
             let mut rep_el = BytesStart::new("Representation");
             rep_el.push_attribute(("id", rep.id.as_str()));
             rep_el.push_attribute(("bandwidth", rep.bandwidth.to_string().as_str()));
@@ -177,8 +375,11 @@ impl MpdBuilder {
             writer.write_event(Event::Start(rep_el))?;
 
             let mut template = BytesStart::new("SegmentTemplate");
-            template.push_attribute(("timescale", self.timescale.to_string().as_str()));
-            template.push_attribute(("duration", self.segment_duration.to_string().as_str()));
+            template.push_attribute(("timescale", timescale.to_string().as_str()));
+            template.push_attribute(("duration", segment_duration.to_string().as_str()));
+            if let Some(start_number) = start_number {
+                template.push_attribute(("startNumber", start_number.to_string().as_str()));
+            }
             template.push_attribute(("initialization", rep.initialization.as_str()));
             template.push_attribute(("media", rep.media.as_str()));
             writer.write_event(Event::Empty(template))?;
@@ -187,10 +388,6 @@ impl MpdBuilder {
         }
 
         writer.write_event(Event::End(BytesEnd::new("AdaptationSet")))?;
-        writer.write_event(Event::End(BytesEnd::new("Period")))?;
-        writer.write_event(Event::End(BytesEnd::new("MPD")))?;
-
-        let result = writer.into_inner().into_inner();
-        Ok(String::from_utf8(result)?)
+        Ok(())
     }
 }
@@ -3,6 +3,7 @@
 
 pub mod parser;
 pub mod builder;
+pub mod patch;
 
 use std::collections::HashMap;
 use chrono::{DateTime, Utc};
@@ -35,6 +36,8 @@ pub struct Representation {
 /// An adaptation set groups representations with the same content type (e.g., audio or video).
 #[derive(Debug, Clone)]
 pub struct AdaptationSet {
+    /// Identifier of the adaptation set. Falls back to its index within the Period when the MPD does not specify one.
+    pub id: String,
     /// Content type of the adaptation set (e.g., "audio" or "video").
     pub content_type: String,
     /// MIME type of the media (e.g., "video/mp4").
@@ -45,13 +48,62 @@ pub struct AdaptationSet {
     pub segment_template: Option<HashMap<String, String>>,
 }
 
+/// Location of the MPD patch document, used to update the manifest without fetching it completely.
+#[derive(Debug, Clone)]
+pub struct PatchLocation {
+    /// URL of the patch document, possibly relative to the MPD URL.
+    pub url: String,
+    /// Number of seconds after the publish time of the MPD during which the patch location is valid.
+    pub ttl: Option<f64>,
+}
+
+/// Latency targets of a low latency presentation, from its `ServiceDescription`.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct ServiceDescription {
+    /// Live latency the player should aim for, in seconds.
+    pub target_latency: Option<f64>,
+    /// Lowest acceptable live latency, in seconds.
+    pub min_latency: Option<f64>,
+    /// Highest acceptable live latency, in seconds.
+    pub max_latency: Option<f64>,
+    /// Lowest playback rate the player may use to reach the target latency.
+    pub min_playback_rate: Option<f64>,
+    /// Highest playback rate the player may use to reach the target latency.
+    pub max_playback_rate: Option<f64>,
+}
+
+/// Whether the presentation is live (`dynamic`) or on demand (`static`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum PresentationType {
+    Static,
+    #[default]
+    Dynamic,
+}
+
 /// Top-level metadata parsed from an MPD file.
 #[derive(Debug, Clone)]
 pub struct MpdMetadata {
+    /// Identifier of the MPD, patches are only applied to an MPD with the same id.
+    pub id: Option<String>,
+    /// The wall-clock time at which this version of the MPD was published.
+    pub publish_time: Option<DateTime<Utc>>,
+    /// Where the patches for this MPD can be found, if the server supports MPD patching.
+    pub patch_location: Option<PatchLocation>,
+    /// Type of the presentation, taken from the `type` attribute.
+    pub presentation_type: PresentationType,
+    /// Total duration of the presentation in seconds, mostly used by static presentations.
+    pub media_presentation_duration: Option<f64>,
     /// The wall-clock time when the presentation became available (used to calculate live edge).
     pub availability_start_time: DateTime<Utc>,
     /// The wall-clock time when the presentation ends (used to calculate live edge).
     pub time_shift_buffer_depth: Option<f64>,
+    /// Latency targets of the presentation, if the MPD has a `ServiceDescription`.
+    pub service_description: Option<ServiceDescription>,
+    /// Id of the current (last) Period.
+    pub period_id: Option<String>,
+    /// Start of the current Period in seconds, relative to the availability start time.
+    pub period_start: f64,
     /// All adaptation sets (audio/video tracks) in the current Period.
+    /// Earlier periods are skipped, as only the current one can still be played live.
     pub adaptation_sets: Vec<AdaptationSet>,
 }
@@ -1,4 +1,4 @@
-use crate::mpd::{AdaptationSet, MpdMetadata, Representation};
+use crate::mpd::{AdaptationSet, MpdMetadata, PatchLocation, PresentationType, Representation, ServiceDescription};
 use quick_xml::events::Event;
 use quick_xml::Reader;
 use std::collections::HashMap;
@@ -21,7 +21,16 @@ pub fn parse_mpd(xml: &str) -> Result<MpdMetadata, Box<dyn std::error::Error + S
     let mut adaptation_sets = vec![];
     let mut availability_start_time = Utc::now();
     let mut time_shift_buffer_depth = None;
+    let mut mpd_id = None;
+    let mut presentation_type = PresentationType::default();
+    let mut media_presentation_duration = None;
+    let mut publish_time = None;
+    let mut patch_location = None;
+    let mut patch_location_ttl: Option<Option<f64>> = None;
     let mut inside_rep = false;
+    let mut service_description: Option<ServiceDescription> = None;
+    let mut period_id = None;
+    let mut period_start = 0.0;
 
     let mut current_adaptation: Option<AdaptationSet> = None;
     let mut current_rep: Option<Representation> = None;
@@ -45,15 +54,88 @@ pub fn parse_mpd(xml: &str) -> Result<MpdMetadata, Box<dyn std::error::Error + S
                             if key == b"timeShiftBufferDepth" {
                                 time_shift_buffer_depth = parse_duration(&value, 60.0);
                             }
+                            if key == b"id" {
+                                mpd_id = Some(value.to_string());
+                            }
+                            if key == b"type" && value == "static" {
+                                presentation_type = PresentationType::Static;
+                            }
+                            if key == b"mediaPresentationDuration" {
+                                media_presentation_duration = parse_duration(&value, 0.0);
+                            }
+                            if key == b"publishTime" {
+                                publish_time = value.parse::<DateTime<Utc>>().ok();
+                            }
+                        }
+                    }
+                    "Period" => {
+                        // Every Period replaces the adaptation sets of the previous one
+                        adaptation_sets.clear();
+                        period_id = None;
+                        period_start = 0.0;
+                        for attr in e.attributes() {
+                            let attr = attr?;
+                            match attr.key.as_ref() {
+                                b"id" => period_id = Some(attr.unescape_value()?.to_string()),
+                                b"start" => period_start = parse_duration(&attr.unescape_value()?, 0.0).unwrap_or(0.0),
+                                _ => {}
+                            }
+                        }
+                    }
+                    "Latency" => {
+                        let description = service_description.get_or_insert_with(ServiceDescription::default);
+                        for attr in e.attributes() {
+                            let attr = attr?;
+                            // The latencies are in milliseconds, negative or non-finite latencies are ignored
+                            let value = attr.unescape_value()?.parse::<f64>().ok()
+                                .filter(|ms| ms.is_finite() && *ms >= 0.0)
+                                .map(|ms| ms / 1000.0);
+                            match attr.key.as_ref() {
+                                b"target" => description.target_latency = value,
+                                b"min" => description.min_latency = value,
+                                b"max" => description.max_latency = value,
+                                _ => {}
+                            }
+                        }
+                    }
+                    "PlaybackRate" => {
+                        let description = service_description.get_or_insert_with(ServiceDescription::default);
+                        let (mut min, mut max) = (None, None);
+                        for attr in e.attributes() {
+                            let attr = attr?;
+                            // Only positive, finite rates can be played
+                            let value = attr.unescape_value()?.parse::<f64>().ok().filter(|rate| rate.is_finite() && *rate > 0.0);
+                            match attr.key.as_ref() {
+                                b"min" => min = value,
+                                b"max" => max = value,
+                                _ => {}
+                            }
+                        }
+                        if !matches!((min, max), (Some(min), Some(max)) if min > max) {
+                            description.min_playback_rate = min;
+                            description.max_playback_rate = max;
+                        }
+                    }
+                    "PatchLocation" => {
+                        let mut ttl = None;
+                        for attr in e.attributes() {
+                            let attr = attr?;
+                            if attr.key.as_ref() == b"ttl" {
+                                ttl = attr.unescape_value()?.parse::<f64>().ok();
+                            }
                         }
+                        // The URL itself is the text content of the element
+                        patch_location_ttl = Some(ttl);
                     }
                     "AdaptationSet" => {
+                        let mut id = adaptation_sets.len().to_string();
                         let mut mime = String::new();
                         let mut content = String::new();
 
                         for attr in e.attributes() {
                             let attr = attr?;
                             match attr.key.as_ref() {
+                                b"id" => id = attr.unescape_value()?.to_string(),
                                 b"mimeType" => mime = attr.unescape_value()?.to_string(),
                                 b"contentType" => content = attr.unescape_value()?.to_string(),
                                 _ => {}
@@ -62,6 +144,7 @@ pub fn parse_mpd(xml: &str) -> Result<MpdMetadata, Box<dyn std::error::Error + S
 
                         let fallback = infer_content_type(&mime).to_string();
                         current_adaptation = Some(AdaptationSet {
+                            id,
                             content_type: if !content.is_empty() { content } else { fallback },
                             mime_type: mime,
                             representations: vec![],
@@ -184,11 +267,23 @@ pub fn parse_mpd(xml: &str) -> Result<MpdMetadata, Box<dyn std::error::Error + S
                 }
             }
 
+            Event::Text(ref t) => {
+                if let Some(ttl) = patch_location_ttl {
+                    let url = t.unescape()?.trim().to_string();
+                    if !url.is_empty() {
+                        patch_location = Some(PatchLocation { url, ttl });
+                    }
+                }
+            }
+
             Event::End(ref e) => {
                 let name = e.name().to_owned();
                 let tag = std::str::from_utf8(name.as_ref())?;
 
                 match tag {
+                    "PatchLocation" => {
+                        patch_location_ttl = None;
+                    }
                     "Representation" => {
                         inside_rep = false;
                         if let Some(mut rep) = current_rep.take() {
@@ -278,16 +373,60 @@ pub fn parse_mpd(xml: &str) -> Result<MpdMetadata, Box<dyn std::error::Error + S
     }
 
     Ok(MpdMetadata {
+        id: mpd_id,
+        publish_time,
+        patch_location,
+        presentation_type,
+        media_presentation_duration,
         availability_start_time,
+        service_description,
+        period_id,
+        period_start,
         adaptation_sets,
         time_shift_buffer_depth,
     })
 }
 
-fn parse_duration(value: &str, fallback_seconds: f64) -> Option<f64> {
+pub(crate) fn parse_duration(value: &str, fallback_seconds: f64) -> Option<f64> {
     let iso = iso8601_duration::Duration::parse(value).ok()?;
     let seconds = iso.to_std()
         .or_else(|| Some(std::time::Duration::from_secs_f64(fallback_seconds)))
         .map(|d| d.as_secs_f64())?;
     Some(seconds)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn service_description(elements: &str) -> Option<ServiceDescription> {
+        parse_mpd(&format!(
+            r#"<MPD type="dynamic" availabilityStartTime="2025-01-01T00:00:00Z"><ServiceDescription id="0">{elements}</ServiceDescription><Period id="0"/></MPD>"#
+        ))
+        .unwrap()
+        .service_description
+    }
+
+    #[test]
+    fn service_description_values() {
+        let description = service_description(r#"<Latency target="3500" min="2000" max="6000"/><PlaybackRate min="0.9" max="1.1"/>"#).unwrap();
+        assert_eq!(description.target_latency, Some(3.5));
+        assert_eq!(description.min_latency, Some(2.0));
+        assert_eq!(description.max_latency, Some(6.0));
+        assert_eq!(description.min_playback_rate, Some(0.9));
+        assert_eq!(description.max_playback_rate, Some(1.1));
+    }
+
+    #[test]
+    fn hostile_service_description_values() {
+        let description = service_description(r#"<Latency target="-5" min="NaN" max="inf"/><PlaybackRate min="1.5" max="0.9"/>"#).unwrap();
+        assert_eq!(description, ServiceDescription::default());
+
+        let description = service_description(r#"<Latency target="NaN"/><PlaybackRate min="0" max="NaN"/>"#).unwrap();
+        assert_eq!(description, ServiceDescription::default());
+
+        let description = service_description(r#"<PlaybackRate min="-1" max="1.2"/>"#).unwrap();
+        assert_eq!(description.min_playback_rate, None);
+        assert_eq!(description.max_playback_rate, Some(1.2));
+    }
+}
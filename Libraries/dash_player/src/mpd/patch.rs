@@ -0,0 +1,521 @@
+//! Support for DASH MPD patches (`PatchLocation`).
+//! A patch document contains RFC 5261 style `add`, `replace` and `remove` operations.
+//! Instead of modifying the MPD XML, the operations are applied directly to the in-memory `MpdMetadata`.
+
+use crate::mpd::parser::{parse_duration, parse_mpd};
+use crate::mpd::{AdaptationSet, MpdMetadata, PatchLocation, Representation};
+use chrono::{DateTime, Utc};
+use quick_xml::events::Event;
+use quick_xml::name::QName;
+use quick_xml::Reader;
+use regex::Regex;
+
+type PatchResult<T> = Result<T, Box<dyn std::error::Error + Send + Sync>>;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PatchOperationKind {
+    Add,
+    Replace,
+    Remove,
+}
+
+/// A single operation of a patch document.
+#[derive(Debug, Clone)]
+pub struct PatchOperation {
+    pub kind: PatchOperationKind,
+    /// XPath-like selector of the element or attribute that is modified.
+    pub selector: String,
+    /// Name of the attribute to add, when adding an attribute (`type="@name"`).
+    pub attribute: Option<String>,
+    /// Raw content of the operation, either an attribute value or an XML fragment.
+    pub content: String,
+}
+
+/// A parsed MPD patch document.
+#[derive(Debug, Clone)]
+pub struct MpdPatch {
+    /// Id of the MPD this patch applies to.
+    pub mpd_id: Option<String>,
+    /// Publish time of the MPD this patch applies to.
+    pub original_publish_time: Option<DateTime<Utc>>,
+    /// Publish time of the MPD after applying this patch.
+    pub publish_time: Option<DateTime<Utc>>,
+    pub operations: Vec<PatchOperation>,
+}
+
+/// The element that is addressed by a selector.
+#[derive(Debug, Clone, Copy)]
+enum Target {
+    Mpd,
+    Period,
+    PatchLocation,
+    AdaptationSet(usize),
+    AdaptationTemplate(usize),
+    Representation(usize, usize),
+    RepresentationTemplate(usize, usize),
+}
+
+/// One step of a selector, e.g. `AdaptationSet[@id='1']`.
+struct Step {
+    name: String,
+    predicates: Vec<(String, String)>,
+}
+
+pub fn parse_patch(xml: &str) -> PatchResult<MpdPatch> {
+    let mut reader = Reader::from_str(xml);
+    let mut patch = MpdPatch {
+        mpd_id: None,
+        original_publish_time: None,
+        publish_time: None,
+        operations: vec![],
+    };
+
+    loop {
+        let (element, has_content) = match reader.read_event()? {
+            Event::Start(e) => (e, true),
+            Event::Empty(e) => (e, false),
+            Event::Eof => break,
+            _ => continue,
+        };
+        let tag = element.name().as_ref().to_vec();
+
+        if tag == b"Patch" {
+            for attr in element.attributes() {
+                let attr = attr?;
+                let value = attr.unescape_value()?;
+                match attr.key.as_ref() {
+                    b"mpdId" => patch.mpd_id = Some(value.to_string()),
+                    b"originalPublishTime" => patch.original_publish_time = Some(value.parse::<DateTime<Utc>>()?),
+                    b"publishTime" => patch.publish_time = Some(value.parse::<DateTime<Utc>>()?),
+                    _ => {}
+                }
+            }
+            continue;
+        }
+
+        let kind = match tag.as_slice() {
+            b"add" => PatchOperationKind::Add,
+            b"replace" => PatchOperationKind::Replace,
+            b"remove" => PatchOperationKind::Remove,
+            _ => continue,
+        };
+
+        let mut selector = None;
+        let mut attribute = None;
+        for attr in element.attributes() {
+            let attr = attr?;
+            match attr.key.as_ref() {
+                b"sel" => selector = Some(attr.unescape_value()?.to_string()),
+                b"type" => attribute = Some(attr.unescape_value()?.trim_start_matches('@').to_string()),
+                _ => {}
+            }
+        }
+        let selector = selector.ok_or("Patch operation without sel attribute")?;
+        let content = if has_content {
+            reader.read_text(QName(&tag))?.trim().to_string()
+        } else {
+            String::new()
+        };
+
+        patch.operations.push(PatchOperation { kind, selector, attribute, content });
+    }
+
+    Ok(patch)
+}
+
+/// Applies a patch to the MPD.
+/// Fails if the patch was made for another MPD (or another version of it), or if an operation is not supported.
+/// The MPD may be partially modified when an error is returned, so apply the patch to a copy when that matters.
+pub fn apply_patch(mpd: &mut MpdMetadata, patch: &MpdPatch) -> PatchResult<()> {
+    if patch.mpd_id.is_some() && patch.mpd_id != mpd.id {
+        return Err(format!("Patch is for MPD {:?}, not for {:?}", patch.mpd_id, mpd.id).into());
+    }
+    if let (Some(original), Some(current)) = (patch.original_publish_time, mpd.publish_time) {
+        if original != current {
+            return Err(format!("Patch is for the MPD published at {}, not at {}", original, current).into());
+        }
+    }
+
+    for operation in &patch.operations {
+        apply_operation(mpd, operation)?;
+    }
+
+    if patch.publish_time.is_some() {
+        mpd.publish_time = patch.publish_time;
+    }
+    Ok(())
+}
+
+fn apply_operation(mpd: &mut MpdMetadata, operation: &PatchOperation) -> PatchResult<()> {
+    let (steps, selected_attribute) = parse_selector(&operation.selector)?;
+    let target = resolve_target(mpd, &steps)?;
+
+    // Adding an attribute uses the `type` attribute of the operation instead of the selector
+    let attribute = match operation.kind {
+        PatchOperationKind::Add => operation.attribute.clone().or(selected_attribute),
+        _ => selected_attribute,
+    };
+
+    if let Some(attribute) = attribute {
+        let value = match operation.kind {
+            PatchOperationKind::Remove => None,
+            _ => Some(operation.content.as_str()),
+        };
+        return set_attribute(mpd, target, &attribute, value);
+    }
+
+    match (operation.kind, target) {
+        (PatchOperationKind::Add, Target::Mpd) => {
+            let fragment = parse_mpd(&format!("<MPD>{}</MPD>", operation.content))?;
+            if fragment.patch_location.is_some() {
+                mpd.patch_location = fragment.patch_location;
+            }
+            mpd.adaptation_sets.extend(fragment.adaptation_sets);
+        }
+        (PatchOperationKind::Add, Target::Period) => {
+            mpd.adaptation_sets.extend(parse_adaptation_sets(&operation.content)?);
+        }
+        (PatchOperationKind::Add, Target::AdaptationSet(a)) => {
+            let representations = parse_representations(&mpd.adaptation_sets[a], &operation.content)?;
+            mpd.adaptation_sets[a].representations.extend(representations);
+        }
+        (PatchOperationKind::Replace, Target::PatchLocation) => {
+            let fragment = parse_mpd(&format!("<MPD>{}</MPD>", operation.content))?;
+            mpd.patch_location = fragment.patch_location;
+        }
+        (PatchOperationKind::Replace, Target::AdaptationSet(a)) => {
+            let mut adaptation_sets = parse_adaptation_sets(&operation.content)?;
+            if adaptation_sets.len() != 1 {
+                return Err("AdaptationSet can only be replaced by a single AdaptationSet".into());
+            }
+            mpd.adaptation_sets[a] = adaptation_sets.remove(0);
+        }
+        (PatchOperationKind::Replace, Target::Representation(a, r)) => {
+            let mut representations = parse_representations(&mpd.adaptation_sets[a], &operation.content)?;
+            if representations.len() != 1 {
+                return Err("Representation can only be replaced by a single Representation".into());
+            }
+            mpd.adaptation_sets[a].representations[r] = representations.remove(0);
+        }
+        (PatchOperationKind::Remove, Target::PatchLocation) => {
+            mpd.patch_location = None;
+        }
+        (PatchOperationKind::Remove, Target::AdaptationSet(a)) => {
+            mpd.adaptation_sets.remove(a);
+        }
+        (PatchOperationKind::Remove, Target::Representation(a, r)) => {
+            mpd.adaptation_sets[a].representations.remove(r);
+        }
+        (kind, target) => {
+            return Err(format!("Unsupported patch operation {:?} on {:?} ({})", kind, target, operation.selector).into());
+        }
+    }
+    Ok(())
+}
+
+/// Splits a selector like `/MPD/Period/AdaptationSet[@id='1']/@mimeType` in its steps and the selected attribute.
+fn parse_selector(selector: &str) -> PatchResult<(Vec<Step>, Option<String>)> {
+    let predicate_re = Regex::new(r#"\[@([\w:]+)=['"]([^'"]*)['"]\]"#).unwrap();
+    let mut steps = vec![];
+    let mut attribute = None;
+
+    for part in selector.trim_start_matches('/').split('/') {
+        if attribute.is_some() {
+            return Err(format!("Invalid selector: {}", selector).into());
+        }
+        if let Some(name) = part.strip_prefix('@') {
+            attribute = Some(name.to_string());
+            continue;
+        }
+
+        let name = part.split('[').next().unwrap_or_default().to_string();
+        let predicates: Vec<(String, String)> = predicate_re
+            .captures_iter(part)
+            .map(|caps| (caps[1].to_string(), caps[2].to_string()))
+            .collect();
+        // Positional predicates and other XPath expressions are not supported
+        if predicate_re.replace_all(part, "") != name {
+            return Err(format!("Unsupported selector step: {}", part).into());
+        }
+        steps.push(Step { name, predicates });
+    }
+
+    Ok((steps, attribute))
+}
+
+fn resolve_target(mpd: &MpdMetadata, steps: &[Step]) -> PatchResult<Target> {
+    let mut steps = steps.iter();
+    match steps.next() {
+        Some(step) if step.name == "MPD" => {}
+        _ => return Err("Selector must start at the MPD element".into()),
+    }
+
+    let mut target = Target::Mpd;
+    for step in steps {
+        target = match (target, step.name.as_str()) {
+            // Only the current Period is tracked, so any Period selects it
+            (Target::Mpd, "Period") => Target::Period,
+            (Target::Mpd, "PatchLocation") => Target::PatchLocation,
+            (Target::Period, "AdaptationSet") => {
+                Target::AdaptationSet(find_index(&mpd.adaptation_sets, &step.predicates, adaptation_attribute)?)
+            }
+            (Target::AdaptationSet(a), "SegmentTemplate") => Target::AdaptationTemplate(a),
+            (Target::AdaptationSet(a), "Representation") => {
+                let representations = &mpd.adaptation_sets[a].representations;
+                Target::Representation(a, find_index(representations, &step.predicates, representation_attribute)?)
+            }
+            (Target::Representation(a, r), "SegmentTemplate") => Target::RepresentationTemplate(a, r),
+            // The segments are addressed by their number, so SegmentTimeline updates are not supported
+            (target, name) => return Err(format!("Unsupported element {} in {:?}", name, target).into()),
+        };
+    }
+    Ok(target)
+}
+
+/// Finds the only element matching all predicates.
+fn find_index<T>(items: &[T], predicates: &[(String, String)], attribute: fn(&T, &str) -> Option<String>) -> PatchResult<usize> {
+    let mut matches = items.iter().enumerate().filter(|(_, item)| {
+        predicates.iter().all(|(key, value)| attribute(item, key).as_deref() == Some(value.as_str()))
+    });
+    match (matches.next(), matches.next()) {
+        (Some((index, _)), None) => Ok(index),
+        (None, _) => Err(format!("No element matches {:?}", predicates).into()),
+        (Some(_), Some(_)) => Err(format!("Multiple elements match {:?}", predicates).into()),
+    }
+}
+
+fn adaptation_attribute(adaptation: &AdaptationSet, key: &str) -> Option<String> {
+    match key {
+        "id" => Some(adaptation.id.clone()),
+        "contentType" => Some(adaptation.content_type.clone()),
+        "mimeType" => Some(adaptation.mime_type.clone()),
+        _ => None,
+    }
+}
+
+fn representation_attribute(rep: &Representation, key: &str) -> Option<String> {
+    match key {
+        "id" => Some(rep.id.clone()),
+        "bandwidth" => Some(rep.bandwidth.to_string()),
+        _ => None,
+    }
+}
+
+/// Sets (or removes, when `value` is `None`) an attribute of the target element.
+/// Attributes that are not part of the in-memory model are ignored.
+fn set_attribute(mpd: &mut MpdMetadata, target: Target, key: &str, value: Option<&str>) -> PatchResult<()> {
+    match target {
+        Target::Mpd => match key {
+            "id" => mpd.id = value.map(str::to_string),
+            "publishTime" => mpd.publish_time = value.map(str::parse::<DateTime<Utc>>).transpose()?,
+            "availabilityStartTime" => {
+                mpd.availability_start_time = value.ok_or("availabilityStartTime can not be removed")?.parse()?;
+            }
+            "timeShiftBufferDepth" => mpd.time_shift_buffer_depth = value.and_then(|v| parse_duration(v, 60.0)),
+            _ => {}
+        },
+        Target::PatchLocation => {
+            if let (Some(location), "ttl") = (mpd.patch_location.as_mut(), key) {
+                location.ttl = value.map(str::parse::<f64>).transpose()?;
+            }
+        }
+        Target::AdaptationSet(a) => {
+            let adaptation = &mut mpd.adaptation_sets[a];
+            match (key, value) {
+                ("id", Some(v)) => adaptation.id = v.to_string(),
+                ("contentType", Some(v)) => adaptation.content_type = v.to_string(),
+                ("mimeType", Some(v)) => adaptation.mime_type = v.to_string(),
+                _ => {}
+            }
+        }
+        Target::AdaptationTemplate(a) => {
+            let adaptation = &mut mpd.adaptation_sets[a];
+            let template = adaptation.segment_template.get_or_insert_with(Default::default);
+            match value {
+                Some(v) => template.insert(key.to_string(), v.to_string()),
+                None => template.remove(key),
+            };
+            for rep in adaptation.representations.iter_mut() {
+                set_template_attribute(rep, key, value)?;
+            }
+        }
+        Target::Representation(a, r) => {
+            let rep = &mut mpd.adaptation_sets[a].representations[r];
+            match (key, value) {
+                ("id", Some(v)) => rep.id = v.to_string(),
+                ("bandwidth", Some(v)) => rep.bandwidth = v.parse()?,
+                ("availabilityTimeOffset", v) => rep.availability_time_offset = v.map(str::parse::<f64>).transpose()?,
+                ("availabilityTimeComplete", v) => rep.availability_time_complete = v.map(str::parse::<bool>).transpose()?,
+                _ => {}
+            }
+        }
+        Target::RepresentationTemplate(a, r) => {
+            set_template_attribute(&mut mpd.adaptation_sets[a].representations[r], key, value)?;
+        }
+        target => return Err(format!("Unsupported attribute {} on {:?}", key, target).into()),
+    }
+    Ok(())
+}
+
+fn set_template_attribute(rep: &mut Representation, key: &str, value: Option<&str>) -> PatchResult<()> {
+    match (key, value) {
+        ("media", Some(v)) => rep.media = v.replace("$RepresentationID$", &rep.id),
+        ("initialization", Some(v)) => rep.initialization = v.replace("$RepresentationID$", &rep.id),
+        ("duration", Some(v)) => rep.segment_duration = v.parse::<f64>()? / rep.timescale as f64,
+        ("timescale", Some(v)) => {
+            // The duration is expressed in the timescale, so keep it in sync
+            let duration = rep.segment_duration * rep.timescale as f64;
+            rep.timescale = v.parse::<u64>()?.max(1);
+            rep.segment_duration = duration / rep.timescale as f64;
+        }
+        ("availabilityTimeOffset", v) => rep.availability_time_offset = v.map(str::parse::<f64>).transpose()?,
+        ("availabilityTimeComplete", v) => rep.availability_time_complete = v.map(str::parse::<bool>).transpose()?,
+        _ => {}
+    }
+    Ok(())
+}
+
+fn parse_adaptation_sets(fragment: &str) -> PatchResult<Vec<AdaptationSet>> {
+    Ok(parse_mpd(&format!("<MPD><Period>{}</Period></MPD>", fragment))?.adaptation_sets)
+}
+
+/// Parses Representation elements in the context of their adaptation set,
+/// so that they inherit the SegmentTemplate of the set.
+fn parse_representations(adaptation: &AdaptationSet, fragment: &str) -> PatchResult<Vec<Representation>> {
+    let template = adaptation.segment_template.as_ref().map(|template| {
+        let attributes: String = template
+            .iter()
+            .map(|(key, value)| format!(" {}=\"{}\"", key, quick_xml::escape::escape(value.as_str())))
+            .collect();
+        format!("<SegmentTemplate{}/>", attributes)
+    });
+    let xml = format!(
+        "<MPD><Period><AdaptationSet mimeType=\"{}\">{}{}</AdaptationSet></Period></MPD>",
+        quick_xml::escape::escape(adaptation.mime_type.as_str()),
+        template.unwrap_or_default(),
+        fragment
+    );
+    let mut adaptation_sets = parse_mpd(&xml)?.adaptation_sets;
+    Ok(adaptation_sets.pop().map(|a| a.representations).unwrap_or_default())
+}
+
+/// Resolves the URL of the patch document relative to the MPD URL.
+pub fn resolve_patch_url(mpd_url: &str, location: &PatchLocation) -> PatchResult<String> {
+    Ok(reqwest::Url::parse(mpd_url)?.join(&location.url)?.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MPD: &str = r#"<MPD id="live" type="dynamic" availabilityStartTime="2025-01-01T00:00:00Z" publishTime="2025-01-01T00:01:00Z">
+  <PatchLocation ttl="60">patch.mpp</PatchLocation>
+  <Period id="0">
+    <AdaptationSet id="1" mimeType="video/mp4">
+      <Representation id="low" bandwidth="1000000">
+        <SegmentTemplate media="$RepresentationID$/$Number$.m4s" initialization="$RepresentationID$/init.mp4" duration="1000" timescale="1000"/>
+      </Representation>
+      <Representation id="high" bandwidth="5000000">
+        <SegmentTemplate media="$RepresentationID$/$Number$.m4s" initialization="$RepresentationID$/init.mp4" duration="1000" timescale="1000"/>
+      </Representation>
+    </AdaptationSet>
+  </Period>
+</MPD>"#;
+
+    fn patch(operations: &str) -> MpdPatch {
+        parse_patch(&format!(
+            r#"<Patch mpdId="live" originalPublishTime="2025-01-01T00:01:00Z" publishTime="2025-01-01T00:01:02Z">{operations}</Patch>"#
+        ))
+        .unwrap()
+    }
+
+    fn patched(operations: &str) -> PatchResult<MpdMetadata> {
+        let mut mpd = parse_mpd(MPD).unwrap();
+        apply_patch(&mut mpd, &patch(operations))?;
+        Ok(mpd)
+    }
+
+    fn representation<'a>(mpd: &'a MpdMetadata, id: &str) -> &'a Representation {
+        mpd.adaptation_sets[0].representations.iter().find(|rep| rep.id == id).unwrap()
+    }
+
+    #[test]
+    fn add_representation() {
+        let mpd = patched(
+            r#"<add sel="/MPD/Period/AdaptationSet[@id='1']"><Representation id="mid" bandwidth="2500000">
+              <SegmentTemplate media="$RepresentationID$/$Number$.m4s" initialization="$RepresentationID$/init.mp4" duration="2000" timescale="1000"/>
+            </Representation></add>"#,
+        )
+        .unwrap();
+        assert_eq!(mpd.adaptation_sets[0].representations.len(), 3);
+        let mid = representation(&mpd, "mid");
+        assert_eq!(mid.bandwidth, 2500000);
+        assert_eq!(mid.media, "mid/$Number$.m4s");
+        assert_eq!(mid.segment_duration, 2.0);
+        assert_eq!(mpd.publish_time, Some("2025-01-01T00:01:02Z".parse().unwrap()));
+    }
+
+    #[test]
+    fn add_attribute() {
+        let mpd = patched(
+            r#"<add sel="/MPD/Period/AdaptationSet[@id='1']/Representation[@id='low']" type="@availabilityTimeOffset">0.5</add>"#,
+        )
+        .unwrap();
+        assert_eq!(representation(&mpd, "low").availability_time_offset, Some(0.5));
+        assert_eq!(representation(&mpd, "high").availability_time_offset, None);
+    }
+
+    #[test]
+    fn replace_attributes() {
+        let mpd = patched(
+            r#"<replace sel="/MPD/Period/AdaptationSet[@id='1']/Representation[@id='high']/@bandwidth">8000000</replace>
+            <replace sel="/MPD/Period/AdaptationSet[@id='1']/Representation[@id='low']/SegmentTemplate/@media">$RepresentationID$/$Number%05d$.m4s</replace>
+            <replace sel="/MPD/Period/AdaptationSet[@id='1']/Representation[@id='low']/SegmentTemplate/@timescale">10000</replace>"#,
+        )
+        .unwrap();
+        assert_eq!(representation(&mpd, "high").bandwidth, 8000000);
+        let low = representation(&mpd, "low");
+        assert_eq!(low.media, "low/$Number%05d$.m4s");
+        // The duration is expressed in the timescale, so the same duration now lasts a tenth of a second
+        assert_eq!(low.timescale, 10000);
+        assert_eq!(low.segment_duration, 0.1);
+    }
+
+    #[test]
+    fn replace_patch_location() {
+        let mpd = patched(r#"<replace sel="/MPD/PatchLocation"><PatchLocation ttl="30">next.mpp</PatchLocation></replace>"#).unwrap();
+        let location = mpd.patch_location.unwrap();
+        assert_eq!(location.url, "next.mpp");
+        assert_eq!(location.ttl, Some(30.0));
+    }
+
+    #[test]
+    fn remove_elements() {
+        let mpd = patched(
+            r#"<remove sel="/MPD/Period/AdaptationSet[@id='1']/Representation[@id='low']"/>
+            <remove sel="/MPD/PatchLocation"/>"#,
+        )
+        .unwrap();
+        let ids: Vec<&str> = mpd.adaptation_sets[0].representations.iter().map(|rep| rep.id.as_str()).collect();
+        assert_eq!(ids, ["high"]);
+        assert!(mpd.patch_location.is_none());
+    }
+
+    #[test]
+    fn reject_patch_of_other_version() {
+        let mut mpd = parse_mpd(MPD).unwrap();
+        mpd.publish_time = Some("2025-01-01T00:00:58Z".parse().unwrap());
+        let operations = r#"<remove sel="/MPD/PatchLocation"/>"#;
+        assert!(apply_patch(&mut mpd, &patch(operations)).is_err());
+        assert!(mpd.patch_location.is_some());
+    }
+
+    #[test]
+    fn reject_segment_timeline() {
+        // The segments are addressed by number, so a timeline, e.g. with a huge repeat count, is not expanded
+        let result = patched(
+            r#"<add sel="/MPD/Period/AdaptationSet[@id='1']/Representation[@id='low']/SegmentTemplate/SegmentTimeline"><S t="0" d="1000" r="9223372036854775807"/></add>"#,
+        );
+        assert!(result.is_err());
+    }
+}
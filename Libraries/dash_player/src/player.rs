@@ -1,45 +1,104 @@
-use crate::mpd::MpdMetadata;
-use crate::segment::fetcher::{BandwidthEstimator, fetch_segment};
+use crate::mpd::{AdaptationSet, MpdMetadata, PatchLocation, PresentationType, Representation};
+use crate::mpd::patch::{apply_patch, parse_patch, resolve_patch_url};
+use crate::segment::demuxer::{demux_segment, parse_init_segment, TrackInfo};
+use crate::segment::fetcher::{BandwidthEstimator, FetchError, fetch_segment};
+use crate::segment::prefetcher::Prefetcher;
+use crate::state::{AdaptationState, PlaybackState};
+use crate::latency::{LatencyControllerConfig, LatencyStatus};
+use crate::auth::RequestAuth;
 use crate::DashEvent;
 use chrono::{DateTime, Utc};
 use reqwest::Client;
 use tracing::{debug, info};
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
 use std::sync::Arc;
 use std::time::{Duration, Instant};
 use tokio::sync::{Mutex, RwLock};
+use tokio::task::JoinHandle;
 use tokio::time::sleep;
 use tokio_util::sync::CancellationToken;
 use regex::Regex;
 
 pub type SegmentCallback = Arc<dyn Fn(DashEvent) + Send + Sync>;
+/// Decides whether the segments of an adaptation set should be fetched.
+pub type AdaptationFilter = Arc<dyn Fn(&AdaptationSet) -> bool + Send + Sync>;
 
 pub struct DashPlayer {
     mpd_url: String,
     client: Client,
+    auth: Arc<RequestAuth>,
     callback: SegmentCallback,
     mpd_data: Arc<RwLock<MpdMetadata>>,
     media_cache: Arc<Mutex<HashSet<String>>>,
     init_cache: Arc<Mutex<HashSet<String>>>,
     cancellation_token: Arc<CancellationToken>,
     target_latency: Arc<Mutex<Duration>>,
+    latency_controller: Arc<Mutex<LatencyControllerConfig>>,
+    /// Latency controller state, keyed by adaptation set id.
+    latency_status: Arc<Mutex<HashMap<String, LatencyStatus>>>,
+    adaptation_states: Arc<Mutex<HashMap<String, AdaptationState>>>,
+    adaptation_filter: Mutex<Option<AdaptationFilter>>,
+    /// Running segment fetchers, keyed by adaptation set id.
+    fetchers: Mutex<HashMap<String, (CancellationToken, JoinHandle<()>)>>,
+    remaining_fetchers: Arc<AtomicUsize>,
+    demux_segments: Arc<AtomicBool>,
+    prefetch_depth: Arc<AtomicUsize>,
+    /// Seconds of media downloaded ahead, keyed by adaptation set id.
+    buffer_levels: Arc<Mutex<HashMap<String, f64>>>,
 }
 
 impl DashPlayer {
     pub async fn new(url: &str, callback: SegmentCallback) -> Result<Self, Box<dyn std::error::Error + Send + Sync>> {
+        Self::new_with_auth(url, callback, RequestAuth::default()).await
+    }
+
+    /// Creates a player of which all MPD and segment requests are authenticated with `auth`.
+    pub async fn new_with_auth(url: &str, callback: SegmentCallback, auth: RequestAuth) -> Result<Self, Box<dyn std::error::Error + Send + Sync>> {
         let client = Client::new();
-        let mpd_text = client.get(url).send().await?.text().await?;
+        let mpd_text = auth.get(&client, url).await?.error_for_status()?.text().await?;
         let mpd_data = crate::mpd::parser::parse_mpd(&mpd_text)?;
 
+        // The service description of the MPD overrides the default latency target and playback rate bounds
+        let mut target_latency = Duration::from_secs_f64(3.0);
+        let mut latency_controller = LatencyControllerConfig::default();
+        if let Some(service_description) = mpd_data.service_description {
+            if let Some(target) = service_description.target_latency {
+                target_latency = Duration::from_secs_f64(target);
+            }
+            // A single bound can still conflict with the default of the other one
+            let mut bounded = latency_controller;
+            if let Some(min_rate) = service_description.min_playback_rate {
+                bounded.min_rate = min_rate;
+            }
+            if let Some(max_rate) = service_description.max_playback_rate {
+                bounded.max_rate = max_rate;
+            }
+            match bounded.validate() {
+                Ok(()) => latency_controller = bounded,
+                Err(e) => info!("Ignoring the playback rates of the service description: {}", e),
+            }
+        }
+
         Ok(Self {
             mpd_url: url.to_string(),
             client,
+            auth: Arc::new(auth),
             callback,
             mpd_data: Arc::new(RwLock::new(mpd_data)),
             media_cache: Arc::new(Mutex::new(HashSet::new())),
             init_cache: Arc::new(Mutex::new(HashSet::new())),
             cancellation_token: Arc::new(CancellationToken::new()),
-            target_latency: Arc::new(Mutex::new(Duration::from_secs_f64(3.0))),
+            target_latency: Arc::new(Mutex::new(target_latency)),
+            latency_controller: Arc::new(Mutex::new(latency_controller)),
+            latency_status: Arc::new(Mutex::new(HashMap::new())),
+            adaptation_states: Arc::new(Mutex::new(HashMap::new())),
+            adaptation_filter: Mutex::new(None),
+            fetchers: Mutex::new(HashMap::new()),
+            remaining_fetchers: Arc::new(AtomicUsize::new(0)),
+            demux_segments: Arc::new(AtomicBool::new(false)),
+            prefetch_depth: Arc::new(AtomicUsize::new(0)),
+            buffer_levels: Arc::new(Mutex::new(HashMap::new())),
         })
     }
 
@@ -48,19 +107,88 @@ impl DashPlayer {
 
         //info!("{mpd_data:?}");
 
+        if mpd_data.presentation_type == PresentationType::Static && mpd_data.media_presentation_duration.is_none() {
+            (self.callback)(DashEvent::Warning("Static MPD without mediaPresentationDuration, end of stream can not be detected".to_string()));
+        }
+
+        // Only fetch the adaptation sets accepted by the filter, and that are not running yet
+        let filter = self.adaptation_filter.lock().await.clone();
+        let mut fetchers = self.fetchers.lock().await;
+        let adaptations: Vec<&AdaptationSet> = mpd_data.adaptation_sets.iter()
+            .filter(|adaptation| filter.as_ref().is_none_or(|filter| filter(adaptation)))
+            .filter(|adaptation| !is_running(&fetchers, &adaptation.id))
+            .collect();
+        self.remaining_fetchers.fetch_add(adaptations.len(), Ordering::SeqCst);
+
         // We need to spawn one task per adaptation set
-        for adaptation in &mpd_data.adaptation_sets {
-            self.spawn_segment_fetcher(adaptation.clone(), mpd_data.availability_start_time, mpd_data.time_shift_buffer_depth.unwrap_or(f64::INFINITY)).await;
+        for adaptation in adaptations {
+            let fetcher = self.spawn_segment_fetcher(adaptation.clone(), &mpd_data);
+            fetchers.insert(adaptation.id.clone(), fetcher);
+        }
+        Ok(())
+    }
+
+    /// Sets the predicate that decides which adaptation sets are fetched by `start()`.
+    /// Adaptation sets can still be enabled or disabled at runtime afterwards.
+    pub async fn set_adaptation_filter(&self, filter: AdaptationFilter) {
+        *self.adaptation_filter.lock().await = Some(filter);
+    }
+
+    /// Starts fetching the segments of an adaptation set, if that is not happening yet.
+    pub async fn enable_adaptation_set(&self, id: &str) -> Result<(), Box<dyn std::error::Error>> {
+        // The lock is held until the fetcher is registered, so concurrent calls spawn only one fetcher
+        let mut fetchers = self.fetchers.lock().await;
+        if is_running(&fetchers, id) {
+            return Ok(());
         }
+
+        let mpd_data = self.mpd_data.read().await.clone();
+        let adaptation = mpd_data.adaptation_sets.iter()
+            .find(|adaptation| adaptation.id == id)
+            .ok_or_else(|| format!("Unknown adaptation set: {id}"))?
+            .clone();
+        self.remaining_fetchers.fetch_add(1, Ordering::SeqCst);
+        let fetcher = self.spawn_segment_fetcher(adaptation, &mpd_data);
+        fetchers.insert(id.to_string(), fetcher);
         Ok(())
     }
 
+    /// Stops fetching the segments of an adaptation set, aborting any ongoing download.
+    /// Its progress is kept, so enabling it again continues where it left off.
+    pub async fn disable_adaptation_set(&self, id: &str) {
+        if let Some((token, _)) = self.fetchers.lock().await.remove(id) {
+            token.cancel();
+        }
+    }
+
+    /// Returns the ids of the adaptation sets that are currently being fetched.
+    pub async fn active_adaptation_sets(&self) -> Vec<String> {
+        let fetchers = self.fetchers.lock().await;
+        fetchers.keys()
+            .filter(|id| is_running(&fetchers, id))
+            .cloned()
+            .collect()
+    }
+
     pub fn stop(&self) {
         self.cancellation_token.cancel();
     }
 
+    /// Refreshes the MPD. When the MPD advertises a `PatchLocation`, only the patch is downloaded
+    /// and applied. If that fails, the complete MPD is fetched instead.
     pub async fn refresh_mpd(&self) {
-        match self.client.get(&self.mpd_url).send().await {
+        let patch_location = self.mpd_data.read().await.patch_location.clone();
+        if let Some(location) = patch_location {
+            match self.patch_mpd(&location).await {
+                Ok(()) => {
+                    (self.callback)(DashEvent::Info("MPD patched".to_string()));
+                    return;
+                }
+                Err(e) => (self.callback)(DashEvent::Warning(format!("MPD patch failed, fetching full MPD: {e}"))),
+            }
+        }
+
+        match self.auth.get(&self.client, &self.mpd_url).await {
             Ok(resp) => match resp.text().await {
                 Ok(text) => match crate::mpd::parser::parse_mpd(&text) {
                     Ok(updated) => {
@@ -75,6 +203,27 @@ impl DashPlayer {
         }
     }
 
+    async fn patch_mpd(&self, location: &PatchLocation) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        // The patch location is only valid for `ttl` seconds after the MPD was published
+        let publish_time = self.mpd_data.read().await.publish_time;
+        if let (Some(ttl), Some(published)) = (location.ttl, publish_time) {
+            if Utc::now() > published + chrono::Duration::from_std(Duration::from_secs_f64(ttl))? {
+                return Err("Patch location expired".into());
+            }
+        }
+
+        let patch_url = resolve_patch_url(&self.mpd_url, location)?;
+        let patch_text = self.auth.get(&self.client, &patch_url).await?.error_for_status()?.text().await?;
+        let patch = parse_patch(&patch_text)?;
+
+        // Apply the patch to a copy, so a failing patch leaves the current MPD untouched
+        let mut mpd_data = self.mpd_data.write().await;
+        let mut updated = mpd_data.clone();
+        apply_patch(&mut updated, &patch)?;
+        *mpd_data = updated;
+        Ok(())
+    }
+
     pub async fn set_target_latency(&self, latency: f64) {
         let mut target_latency = self.target_latency.lock().await;
         *target_latency = Duration::from_secs_f64(latency);
@@ -85,25 +234,124 @@ impl DashPlayer {
         target_latency.as_secs_f64()
     }
 
-    async fn spawn_segment_fetcher(&self, adaptation: crate::mpd::AdaptationSet, availability_start_time: DateTime<Utc>, time_shift_buffer: f64) {
+    /// When enabled, media segments are parsed and emitted as `DashEvent::Sample` events (one per sample)
+    /// instead of `DashEvent::Segment` events. Initialization segments are then consumed by the player.
+    pub fn set_demux_segments(&self, demux: bool) {
+        self.demux_segments.store(demux, Ordering::Relaxed);
+    }
+
+    /// Number of upcoming segments per adaptation set that are downloaded in parallel with the current one.
+    /// Only segments that are already published are prefetched. 0 disables prefetching.
+    pub fn set_prefetch_depth(&self, depth: usize) {
+        self.prefetch_depth.store(depth, Ordering::Relaxed);
+    }
+
+    /// Returns how many seconds of media of an adaptation set have been downloaded ahead.
+    pub async fn get_buffer_level(&self, adaptation_id: &str) -> Option<f64> {
+        self.buffer_levels.lock().await.get(adaptation_id).copied()
+    }
+
+    /// Replaces the gains and limits of the playback-rate controller, a config that does not validate is not applied.
+    pub async fn set_latency_controller(&self, config: LatencyControllerConfig) -> Result<(), String> {
+        config.validate()?;
+        *self.latency_controller.lock().await = config;
+        Ok(())
+    }
+
+    pub async fn get_latency_controller(&self) -> LatencyControllerConfig {
+        *self.latency_controller.lock().await
+    }
+
+    /// Returns the latency controller state of an adaptation set, once it has fetched a segment.
+    pub async fn get_latency_status(&self, adaptation_id: &str) -> Option<LatencyStatus> {
+        self.latency_status.lock().await.get(adaptation_id).copied()
+    }
+
+    /// Captures the current session (segment pointers, selected representations and target latency),
+    /// so that it can later be continued with `resume_from_state`.
+    pub async fn export_state(&self) -> PlaybackState {
+        PlaybackState {
+            mpd_url: self.mpd_url.clone(),
+            adaptations: self.adaptation_states.lock().await.clone(),
+            target_latency: self.get_target_latency().await,
+        }
+    }
+
+    /// Restores a session that was captured with `export_state`.
+    /// Must be called before `start()`, the fetchers then continue from the stored segment pointers
+    /// instead of rejoining at the live edge.
+    pub async fn resume_from_state(&self, state: PlaybackState) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        if !(state.target_latency.is_finite() && state.target_latency >= 0.0) {
+            return Err(format!("Invalid target latency {} in the playback state", state.target_latency).into());
+        }
+        if state.mpd_url != self.mpd_url {
+            (self.callback)(DashEvent::Info(format!("Resuming session of {} on {}", state.mpd_url, self.mpd_url)));
+        }
+        *self.adaptation_states.lock().await = state.adaptations;
+        self.set_target_latency(state.target_latency).await;
+        Ok(())
+    }
+
+    /// Spawns the task that fetches the segments of one adaptation set, the caller registers it in `fetchers`.
+    /// For static presentations the task ends once all segments are fetched,
+    /// the last fetcher to finish emits `DashEvent::EndOfStream`.
+    fn spawn_segment_fetcher(&self, adaptation: AdaptationSet, mpd_data: &MpdMetadata) -> (CancellationToken, JoinHandle<()>) {
+        let availability_start_time = mpd_data.availability_start_time;
+        // A static presentation is played from the start until its end, instead of following the live edge
+        let (static_duration, time_shift_buffer) = match mpd_data.presentation_type {
+            PresentationType::Static => (Some(mpd_data.media_presentation_duration.unwrap_or(f64::INFINITY)), f64::INFINITY),
+            PresentationType::Dynamic => (None, mpd_data.time_shift_buffer_depth.unwrap_or(f64::INFINITY)),
+        };
+        let remaining_fetchers = self.remaining_fetchers.clone();
         let base_url = self.mpd_url.rsplit_once('/').map(|(base, _)| base).unwrap_or("").to_string();
         let callback = self.callback.clone();
         let media_cache = self.media_cache.clone();
         let init_cache = self.init_cache.clone();
         let client = self.client.clone();
-        let cancellation_token = self.cancellation_token.clone();
+        let auth = self.auth.clone();
+        let player_token = self.cancellation_token.clone();
+        // Each fetcher can be stopped on its own, or together with the player
+        let cancellation_token = self.cancellation_token.child_token();
+        let fetcher_token = cancellation_token.clone();
         let target_latency = self.target_latency.clone();
+        let latency_controller = self.latency_controller.clone();
+        let demux_segments = self.demux_segments.clone();
+        let prefetch_depth = self.prefetch_depth.clone();
+        let buffer_levels = self.buffer_levels.clone();
+        let latency_status = self.latency_status.clone();
+        let adaptation_states = self.adaptation_states.clone();
 
-        tokio::spawn(async move {
+        let handle = tokio::spawn(async move {
             let mut estimator = BandwidthEstimator::new(0.25);
             let reps = &adaptation.representations;
             if reps.is_empty() {
                 callback(DashEvent::Warning("No representations found".to_string()));
+                if static_duration.is_some() && remaining_fetchers.fetch_sub(1, Ordering::SeqCst) == 1 && !player_token.is_cancelled() {
+                    callback(DashEvent::EndOfStream);
+                }
                 return;
             }
 
             let mut segment_pointer: u64 = 0;
 
+            // Continue from a restored session, if there is one for this adaptation set
+            if let Some(restored) = adaptation_states.lock().await.get(&adaptation.id) {
+                segment_pointer = restored.segment_pointer;
+                let restored_rep = restored.representation_id.as_ref()
+                    .and_then(|id| reps.iter().find(|r| &r.id == id));
+                if let Some(estimate) = restored.bandwidth_estimate {
+                    estimator.seed(estimate);
+                } else if let Some(rep) = restored_rep {
+                    // Undo the 5% overhead margin so the same representation gets selected again
+                    estimator.seed(rep.bandwidth as f64 / 0.95);
+                }
+            }
+
+            let mut finished = false;
+            let mut prefetcher = Prefetcher::new(0);
+            // Track timing of the init segments, keyed by representation id (only used when demuxing)
+            let mut track_infos: HashMap<String, HashMap<u32, TrackInfo>> = HashMap::new();
+
             loop {
                 let loop_start = Instant::now(); 
                 tokio::select! {
@@ -113,11 +361,24 @@ impl DashPlayer {
                         break;
                     }
                     _ = async {
+                        let demux = demux_segments.load(Ordering::Relaxed);
                         let est_bw = estimator.estimate();
                         // This selects the best representation based on the estimated bandwidth
                         let selected = select_representation(reps, est_bw);
+                        adaptation_states.lock().await.insert(adaptation.id.clone(), AdaptationState {
+                            segment_pointer,
+                            representation_id: Some(selected.id.clone()),
+                            bandwidth_estimate: estimator.has_samples().then(|| estimator.estimate()),
+                        });
                         let seg_duration = selected.segment_duration;
                         let seg_start_time = segment_pointer as f64 * seg_duration;
+                        if let Some(presentation_duration) = static_duration {
+                            // Allow for rounding errors in the segment duration
+                            if seg_start_time >= presentation_duration - 1e-6 {
+                                finished = true;
+                                return;
+                            }
+                        }
                         let uptime = Utc::now().signed_duration_since(availability_start_time).to_std().unwrap_or_default().as_secs_f64();
 
                         let target_latency_seconds = {
@@ -135,20 +396,9 @@ impl DashPlayer {
 
                         let ato = selected.availability_time_offset.unwrap_or(0.0);
                         let atc = selected.availability_time_complete.unwrap_or(true);
+                        let available_at = segment_available_at(availability_start_time, selected, segment_pointer);
         
-                        let segment_wallclock_time = availability_start_time + chrono::Duration::from_std(Duration::from_secs_f64(seg_start_time)).unwrap();
-                        let available_at = if atc {
-                            segment_wallclock_time
-                        } else {
-                            let offset = seg_duration - ato;
-                            if offset >= 0.0 {
-                                segment_wallclock_time + chrono::Duration::from_std(Duration::from_secs_f64(offset)).unwrap()
-                            } else {
-                                segment_wallclock_time - chrono::Duration::from_std(Duration::from_secs_f64(offset * -1.0)).unwrap()
-                            }
-                        };
-        
-                        if Utc::now() < available_at {
+                        if static_duration.is_none() && Utc::now() < available_at {
                             // Calculate how long to wait until the segment is available
                             let wait_time = available_at.signed_duration_since(Utc::now()).to_std().unwrap_or_default();
                             let wait_time_ms = wait_time.as_millis();
@@ -177,10 +427,23 @@ impl DashPlayer {
                         let latency_diff = {
                             current_latency.as_secs_f64() - target_latency_seconds
                         };
-                        // Proportional gain tuned for small durations and aggressive latency correction
-                        // Higher value for quicker catch-up, lower for smoother
-                        let k_p = 1.2;
-                        let playback_rate = adjust_playback_rate(latency_diff, k_p);
+                        let playback_rate = if static_duration.is_some() {
+                            // There is no live edge to catch up with
+                            1.0
+                        } else {
+                            latency_controller.lock().await.playback_rate(latency_diff)
+                        };
+
+                        let status = LatencyStatus {
+                            current_latency: current_latency.as_secs_f64(),
+                            target_latency: target_latency_seconds,
+                            playback_rate,
+                        };
+                        latency_status.lock().await.insert(adaptation.id.clone(), status);
+                        callback(DashEvent::Latency {
+                            adaptation_id: adaptation.id.clone(),
+                            status,
+                        });
                 
                         /*
                         info!(
@@ -191,15 +454,7 @@ impl DashPlayer {
                         );
                         */
 
-                        let segment_url = format!(
-                            "{}/{}",
-                            base_url,
-                            replace_number_format(
-                                &selected.media
-                                    .replace("$Time$", &((segment_pointer as f64 * selected.timescale as f64).round() as u64).to_string())
-                                    .replace("$RepresentationID$", &selected.id),
-                                segment_pointer)
-                        );
+                        let segment_url = build_segment_url(&base_url, selected, segment_pointer);
 
                         {
                             // Prevent downloading the same segment multiple times
@@ -222,21 +477,32 @@ impl DashPlayer {
                             if !inits.contains(&init_key) {
                                 let init_url = format!("{}/{}", base_url, selected.initialization);
                                 // info!("Downloading initialization segment: {}", init_url);
-                                match fetch_segment(&client, &init_url).await {
+                                match fetch_segment(&client, &auth, &auth.sign_url(&selected.id, &init_url), &cancellation_token, None).await {
                                     Ok((init_data, dur)) => {
                                         let length = init_data.len();
-                                        callback(DashEvent::Segment {
-                                            data: init_data,
-                                            content_type: adaptation.content_type.clone(),
-                                            representation_id: selected.id.clone(),
-                                            segment_number: 0,
-                                            duration: 0.0,
-                                            url: init_url,
-                                            playback_rate,
-                                        });
+                                        if demux {
+                                            // The init segment is only needed for the timing of the samples
+                                            match parse_init_segment(&init_data) {
+                                                Ok(tracks) => {
+                                                    track_infos.insert(selected.id.clone(), tracks);
+                                                }
+                                                Err(e) => callback(DashEvent::Warning(format!("Failed to parse init segment {init_url}: {e}"))),
+                                            }
+                                        } else {
+                                            callback(DashEvent::Segment {
+                                                data: init_data,
+                                                content_type: adaptation.content_type.clone(),
+                                                representation_id: selected.id.clone(),
+                                                segment_number: 0,
+                                                duration: 0.0,
+                                                url: init_url,
+                                                playback_rate,
+                                            });
+                                        }
                                         estimator.record(length, dur);
                                         inits.insert(init_key);
                                     }
+                                    Err(FetchError::Cancelled) => return,
                                     Err(e) => {
                                         callback(DashEvent::DownloadError {
                                             url: init_url,
@@ -247,7 +513,72 @@ impl DashPlayer {
                             }
                         }
 
-                        match fetch_segment(&client, &segment_url).await {
+                        // Only allow the download to be abandoned if there is a lower representation to fall back to
+                        let lowest_bandwidth = reps.iter().map(|r| r.bandwidth).min().unwrap_or(0);
+                        let deadline = if selected.bandwidth > lowest_bandwidth {
+                            Some(Instant::now() + Duration::from_secs_f64(seg_duration / playback_rate))
+                        } else {
+                            None
+                        };
+
+                        // Keep up to `depth` upcoming segments downloading in the background
+                        let depth = prefetch_depth.load(Ordering::Relaxed);
+                        if prefetcher.max_concurrent() != depth {
+                            prefetcher = Prefetcher::new(depth);
+                        }
+                        for number in segment_pointer + 1..=segment_pointer + depth as u64 {
+                            if prefetcher.is_scheduled(number) {
+                                continue;
+                            }
+                            // Stop at the end of a static presentation, or at segments that are not published yet
+                            let beyond_end = match static_duration {
+                                Some(presentation_duration) => number as f64 * seg_duration >= presentation_duration - 1e-6,
+                                None => segment_available_at(availability_start_time, selected, number) > Utc::now(),
+                            };
+                            if beyond_end {
+                                break;
+                            }
+                            let url = build_segment_url(&base_url, selected, number);
+                            if media_cache.lock().await.contains(&url) {
+                                continue;
+                            }
+                            prefetcher.schedule(number, &selected.id, auth.sign_url(&selected.id, &url), &client, &auth, &cancellation_token);
+                        }
+
+                        let result = match prefetcher.take(segment_pointer, &selected.id).await {
+                            Some(result) => result,
+                            None => fetch_segment(&client, &auth, &auth.sign_url(&selected.id, &segment_url), &cancellation_token, deadline).await,
+                        };
+                        buffer_levels.lock().await.insert(adaptation.id.clone(), prefetcher.ready() as f64 * seg_duration);
+
+                        match result {
+                            Ok((media_data, dur)) if demux => {
+                                let length = media_data.len();
+                                let tracks = track_infos.get(&selected.id).cloned().unwrap_or_default();
+                                // Without timing in the segment, samples start at the segment's start time
+                                let segment_start = (seg_start_time * selected.timescale as f64).round() as u64;
+                                match demux_segment(&media_data, &tracks, selected.timescale as u32, segment_start) {
+                                    Ok(samples) => {
+                                        for sample in samples {
+                                            callback(DashEvent::Sample {
+                                                content_type: adaptation.content_type.clone(),
+                                                adaptation_set_id: adaptation.id.clone(),
+                                                representation_id: selected.id.clone(),
+                                                segment_number: segment_pointer,
+                                                sample,
+                                                playback_rate,
+                                            });
+                                        }
+                                    }
+                                    Err(e) => {
+                                        callback(DashEvent::DownloadError {
+                                            url: segment_url.clone(),
+                                            reason: format!("Failed to demux segment: {e}"),
+                                        });
+                                    }
+                                }
+                                estimator.record(length, dur);
+                            }
                             Ok((media_data, dur)) => {
                                 // info!("Estimated Bandwidth was: {}, rate: {}", est_bw, playback_rate);
                                 let length = media_data.len();
@@ -262,6 +593,22 @@ impl DashPlayer {
                                 });
                                 estimator.record(length, dur);
                             }
+                            Err(FetchError::Cancelled) => return,
+                            Err(FetchError::Abandoned { received, elapsed }) => {
+                                // Feed the partial download to the estimator, so the next attempt
+                                // for this segment switches down to a representation that fits.
+                                if received > 0 && elapsed > 0.0 {
+                                    estimator.record(received, elapsed);
+                                } else {
+                                    estimator.record(1, seg_duration);
+                                }
+                                media_cache.lock().await.remove(&segment_url);
+                                callback(DashEvent::Warning(format!(
+                                    "Abandoned segment {} of representation {}, it would not arrive in time",
+                                    segment_pointer, selected.id
+                                )));
+                                return;
+                            }
                             Err(e) => {
                                 callback(DashEvent::DownloadError {
                                     url: segment_url.clone(),
@@ -282,11 +629,28 @@ impl DashPlayer {
                         }
                     } => {}
                 }
+
+                if finished {
+                    callback(DashEvent::Info(format!("All segments of adaptation set {} fetched.", adaptation.id)));
+                    break;
+                }
+            }
+
+            // A disabled adaptation set no longer holds back the end of a static presentation,
+            // but only the fetchers that reached the end signal it
+            if static_duration.is_some() && remaining_fetchers.fetch_sub(1, Ordering::SeqCst) == 1 && finished && !player_token.is_cancelled() {
+                callback(DashEvent::EndOfStream);
             }
         });
+
+        (fetcher_token, handle)
     }
 }
 
+fn is_running(fetchers: &HashMap<String, (CancellationToken, JoinHandle<()>)>, id: &str) -> bool {
+    fetchers.get(id).is_some_and(|(_, handle)| !handle.is_finished())
+}
+
 fn select_representation<'a>(reps: &'a [crate::mpd::Representation], mut est_bw: f64) -> &'a crate::mpd::Representation {
     // Reduce the estimated bandwidth by 5% to account for overhead
     est_bw *= 0.95;
@@ -308,14 +672,35 @@ fn select_representation<'a>(reps: &'a [crate::mpd::Representation], mut est_bw:
         .unwrap_or(&reps[0])
 }
 
-fn adjust_playback_rate(latency_diff: f64, k_p: f64) -> f64 {
-    // Allow a small dead zone to avoid jitter
-    let dead_zone = 0.01;
-    if latency_diff.abs() < dead_zone {
-        1.0
+fn build_segment_url(base_url: &str, rep: &Representation, segment_number: u64) -> String {
+    format!(
+        "{}/{}",
+        base_url,
+        replace_number_format(
+            &rep.media
+                .replace("$Time$", &((segment_number as f64 * rep.timescale as f64).round() as u64).to_string())
+                .replace("$RepresentationID$", &rep.id),
+            segment_number)
+    )
+}
+
+/// Returns the wall-clock time at which a segment of a live presentation can be requested.
+fn segment_available_at(availability_start_time: DateTime<Utc>, rep: &Representation, segment_number: u64) -> DateTime<Utc> {
+    let seg_duration = rep.segment_duration;
+    let seg_start_time = segment_number as f64 * seg_duration;
+    let ato = rep.availability_time_offset.unwrap_or(0.0);
+    let atc = rep.availability_time_complete.unwrap_or(true);
+
+    let segment_wallclock_time = availability_start_time + chrono::Duration::from_std(Duration::from_secs_f64(seg_start_time)).unwrap();
+    if atc {
+        segment_wallclock_time
     } else {
-        let adjustment = (latency_diff * k_p).clamp(-0.2, 1.5);
-        (1.0 + adjustment).clamp(0.8, 2.5)
+        let offset = seg_duration - ato;
+        if offset >= 0.0 {
+            segment_wallclock_time + chrono::Duration::from_std(Duration::from_secs_f64(offset)).unwrap()
+        } else {
+            segment_wallclock_time - chrono::Duration::from_std(Duration::from_secs_f64(-offset)).unwrap()
+        }
     }
 }
 
@@ -0,0 +1,151 @@
+//! Splits fragmented MP4 segments into their samples, using mp4_box.
+
+use bytes::Bytes;
+use mp4_box::boxes::generic::Mp4Box;
+use mp4_box::boxes::moof::MoofBox;
+use mp4_box::boxes::moov::MoovBox;
+use std::collections::HashMap;
+
+/// Timing defaults of a track, taken from the initialization segment.
+#[derive(Debug, Clone, Copy)]
+pub struct TrackInfo {
+    pub timescale: u32,
+    pub default_sample_duration: u32,
+}
+
+/// A single sample of a media segment.
+#[derive(Debug, Clone)]
+pub struct Sample {
+    pub track_id: u32,
+    /// Decode time in timescale units.
+    pub decode_time: u64,
+    /// Presentation time in timescale units.
+    pub presentation_time: u64,
+    /// Duration in timescale units.
+    pub duration: u32,
+    pub timescale: u32,
+    pub data: Bytes,
+}
+
+/// Reads the timescale and default sample duration of every track in an initialization segment.
+pub fn parse_init_segment(data: &[u8]) -> Result<HashMap<u32, TrackInfo>, String> {
+    let mut tracks = HashMap::new();
+
+    for (offset, size, box_type) in top_level_boxes(data)? {
+        if &box_type != b"moov" {
+            continue;
+        }
+        let (moov, _) = MoovBox::read_box(&data[offset..offset + size])?;
+        for trak in &moov.traks {
+            let default_sample_duration = moov.mvex.as_ref()
+                .and_then(|mvex| mvex.trex_entries.iter().find(|trex| trex.track_id == trak.tkhd.track_id))
+                .map(|trex| trex.default_sample_duration)
+                .unwrap_or(0);
+            tracks.insert(trak.tkhd.track_id, TrackInfo {
+                timescale: trak.mdia.mdhd.timescale,
+                default_sample_duration,
+            });
+        }
+    }
+
+    Ok(tracks)
+}
+
+/// Extracts the samples of a media segment.
+/// Tracks that are missing from `tracks` use `fallback_timescale` and no default duration.
+/// Fragments without any timing information start at `fallback_decode_time` (in the track's timescale).
+/// A track fragment without a run is assumed to carry its single sample in the mdat box that follows the moof.
+pub fn demux_segment(
+    data: &Bytes,
+    tracks: &HashMap<u32, TrackInfo>,
+    fallback_timescale: u32,
+    fallback_decode_time: u64,
+) -> Result<Vec<Sample>, String> {
+    let mut samples = Vec::new();
+    // Decode time of the next sample per track, for fragments without a tfdt box
+    let mut next_decode_time: HashMap<u32, u64> = HashMap::new();
+    // Samples of which the payload is the next mdat box
+    let mut pending: Vec<Sample> = Vec::new();
+
+    for (offset, size, box_type) in top_level_boxes(data)? {
+        match &box_type {
+            b"moof" => {
+                let (moof, _) = MoofBox::read_box(&data[offset..offset + size])?;
+
+                for traf in &moof.trafs {
+                    let track_id = traf.tfhd.track_id;
+                    let track = tracks.get(&track_id).copied().unwrap_or(TrackInfo {
+                        timescale: fallback_timescale,
+                        default_sample_duration: 0,
+                    });
+
+                    let duration = traf.tfhd.default_sample_duration.unwrap_or(track.default_sample_duration);
+                    let decode_time = traf.tfdt.as_ref()
+                        .map(|tfdt| tfdt.base_decode_time)
+                        .or_else(|| next_decode_time.get(&track_id).copied())
+                        .unwrap_or(fallback_decode_time);
+                    next_decode_time.insert(track_id, decode_time + duration as u64);
+
+                    let mut sample = Sample {
+                        track_id,
+                        decode_time,
+                        // Runs do not carry composition offsets, so samples are presented in decode order
+                        presentation_time: decode_time,
+                        duration,
+                        timescale: track.timescale,
+                        data: Bytes::new(),
+                    };
+
+                    let Some(trun) = traf.trun.as_ref() else {
+                        pending.push(sample);
+                        continue;
+                    };
+
+                    // The data offset is relative to the base data offset, or to the start of the moof box
+                    let base = traf.tfhd.base_data_offset.map(|o| o as i64).unwrap_or(offset as i64);
+                    let start = base + trun.data_offset as i64;
+                    let end = start + trun.sample_size as i64;
+                    if start < 0 || end > data.len() as i64 {
+                        return Err(format!("Sample of track {} lies outside the segment ({}..{})", track_id, start, end));
+                    }
+                    sample.data = data.slice(start as usize..end as usize);
+                    samples.push(sample);
+                }
+            }
+            b"mdat" => {
+                for mut sample in pending.drain(..) {
+                    sample.data = data.slice(offset + 8..offset + size);
+                    samples.push(sample);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    if !pending.is_empty() {
+        return Err(format!("{} track fragment(s) without media data", pending.len()));
+    }
+
+    Ok(samples)
+}
+
+/// Returns the offset, size and type of every top-level box.
+fn top_level_boxes(data: &[u8]) -> Result<Vec<(usize, usize, [u8; 4])>, String> {
+    let mut boxes = Vec::new();
+    let mut offset = 0;
+
+    while offset + 8 <= data.len() {
+        let size = mp4_box::read_u32_be(data, offset)? as usize;
+        if size < 8 || offset + size > data.len() {
+            return Err(format!("Corrupted MP4 box size {} at offset {}", size, offset));
+        }
+        let box_type: [u8; 4] = data[offset + 4..offset + 8].try_into().unwrap();
+        boxes.push((offset, size, box_type));
+        offset += size;
+    }
+
+    if offset != data.len() {
+        return Err("Trailing incomplete box at end of buffer".into());
+    }
+    Ok(boxes)
+}
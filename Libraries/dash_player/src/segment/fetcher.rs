@@ -1,5 +1,7 @@
-use bytes::Bytes;
-use reqwest::{Client, StatusCode};
+use bytes::{Bytes, BytesMut};
+use reqwest::{Client, Response, StatusCode};
+use tokio_util::sync::CancellationToken;
+use crate::auth::RequestAuth;
 use tracing::error;
 use std::time::{Duration, Instant};
 
@@ -27,6 +29,22 @@ impl BandwidthEstimator {
         };
     }
 
+    /**
+     * Starts the estimator from a previously known estimate (in bits per second),
+     * as if it was the first recorded sample.
+     */
+    pub fn seed(&mut self, estimate: f64) {
+        self.ewma = estimate;
+        self.initialized = true;
+    }
+
+    /**
+     * Returns true if at least one sample was recorded (or the estimator was seeded).
+     */
+    pub fn has_samples(&self) -> bool {
+        self.initialized
+    }
+
     /**
      * Returns the estimated bandwidth in bits per second.
      * If no samples are recorded, returns 50 Mbps.
@@ -36,28 +54,64 @@ impl BandwidthEstimator {
     }
 }
 
+/// Reasons why a segment download did not produce any data.
+#[derive(Debug)]
+pub enum FetchError {
+    /// The download was aborted because the player was stopped.
+    Cancelled,
+    /// The download was abandoned because it would not complete before its deadline.
+    /// Contains the number of bytes received so far and the time spent downloading them.
+    Abandoned { received: usize, elapsed: f64 },
+    /// Any other failure (HTTP status, network error, ...).
+    Failed(String),
+}
+
+impl std::fmt::Display for FetchError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            FetchError::Cancelled => write!(f, "download cancelled"),
+            FetchError::Abandoned { received, elapsed } => {
+                write!(f, "download abandoned after {} bytes in {:.3} s", received, elapsed)
+            }
+            FetchError::Failed(reason) => write!(f, "{}", reason),
+        }
+    }
+}
+
+impl std::error::Error for FetchError {}
+
 /// Downloads a segment and returns (bytes, download_duration)
 /// Retries a few times with exponential backoff if needed.
+///
+/// The download is aborted as soon as `cancellation_token` is cancelled.
+/// When a `deadline` is given, the body is streamed and the download is abandoned
+/// once it becomes clear that the segment will not have arrived by then.
 pub async fn fetch_segment(
     client: &Client,
+    auth: &RequestAuth,
     url: &str,
-) -> Result<(Bytes, f64), Box<dyn std::error::Error>> {
+    cancellation_token: &CancellationToken,
+    deadline: Option<Instant>,
+) -> Result<(Bytes, f64), FetchError> {
     const MAX_RETRIES: usize = 0;
     const BASE_DELAY_MS: u64 = 500;
 
     for attempt in 0..=MAX_RETRIES {
         let start = Instant::now();
-        let result = client.get(url).send().await;
+        let result = tokio::select! {
+            _ = cancellation_token.cancelled() => return Err(FetchError::Cancelled),
+            result = auth.get(client, url) => result,
+        };
 
         match result {
             Ok(response) => {
                 if response.status().is_success() {
-                    let bytes = response.bytes().await?;
+                    let bytes = download_body(response, start, cancellation_token, deadline).await?;
                     let duration_secs = start.elapsed().as_secs_f64();
                     return Ok((bytes, duration_secs));
                 } else if response.status() == StatusCode::NOT_FOUND {
                     // 404: don't retry
-                    return Err(format!("404 Not Found: {}", url).into());
+                    return Err(FetchError::Failed(format!("404 Not Found: {}", url)));
                 } else {
                     error!("Warning: Received {} from {}", response.status(), url);
                 }
@@ -69,9 +123,61 @@ pub async fn fetch_segment(
         #[allow(clippy::absurd_extreme_comparisons)]
         if attempt + 1 < MAX_RETRIES {
             let delay = Duration::from_millis(BASE_DELAY_MS * 2u64.pow(attempt as u32));
-            tokio::time::sleep(delay).await;
+            tokio::select! {
+                _ = cancellation_token.cancelled() => return Err(FetchError::Cancelled),
+                _ = tokio::time::sleep(delay) => {}
+            }
+        }
+    }
+
+    Err(FetchError::Failed(format!("Failed to fetch segment after {} attempts: {}", MAX_RETRIES + 1, url)))
+}
+
+/// Reads the response body chunk by chunk, so that the download can be cancelled
+/// or abandoned halfway through.
+async fn download_body(
+    mut response: Response,
+    start: Instant,
+    cancellation_token: &CancellationToken,
+    deadline: Option<Instant>,
+) -> Result<Bytes, FetchError> {
+    // Give the connection some time to ramp up before projecting the completion time
+    const MIN_PROJECTION_TIME_S: f64 = 0.05;
+    // The content length is what the server claims, the buffer grows past this as the bytes arrive
+    const MAX_PREALLOCATION: u64 = 16 * 1024 * 1024;
+
+    let content_length = response.content_length();
+    let mut body = BytesMut::with_capacity(content_length.unwrap_or(0).min(MAX_PREALLOCATION) as usize);
+
+    loop {
+        let chunk = tokio::select! {
+            _ = cancellation_token.cancelled() => return Err(FetchError::Cancelled),
+            chunk = response.chunk() => chunk.map_err(|e| FetchError::Failed(e.to_string()))?,
+        };
+        let Some(chunk) = chunk else {
+            break;
+        };
+        body.extend_from_slice(&chunk);
+
+        let Some(deadline) = deadline else {
+            continue;
+        };
+        let now = Instant::now();
+        let elapsed = now.duration_since(start).as_secs_f64();
+        let too_late = match content_length {
+            // Project the completion time based on the throughput so far
+            Some(total) if elapsed >= MIN_PROJECTION_TIME_S && !body.is_empty() => {
+                let projected = elapsed * total as f64 / body.len() as f64;
+                start + Duration::from_secs_f64(projected) > deadline
+            }
+            Some(_) => false,
+            // Without a content length we can only detect that the deadline has passed
+            None => now > deadline,
+        };
+        if too_late && (body.len() as u64) < content_length.unwrap_or(u64::MAX) {
+            return Err(FetchError::Abandoned { received: body.len(), elapsed });
         }
     }
 
-    Err(format!("Failed to fetch segment after {} attempts: {}", MAX_RETRIES + 1, url).into())
+    Ok(body.freeze())
 }
@@ -1 +1,3 @@
+pub mod demuxer;
 pub mod fetcher;
+pub mod prefetcher;
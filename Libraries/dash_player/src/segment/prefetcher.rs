@@ -0,0 +1,100 @@
+use crate::auth::RequestAuth;
+use crate::segment::fetcher::{fetch_segment, FetchError};
+use bytes::Bytes;
+use reqwest::Client;
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use tokio::sync::Semaphore;
+use tokio::task::JoinHandle;
+use tokio_util::sync::CancellationToken;
+
+struct PendingSegment {
+    representation_id: String,
+    handle: JoinHandle<Result<(Bytes, f64), FetchError>>,
+}
+
+/// Downloads upcoming segments of one adaptation set in the background.
+/// At most `max_concurrent` downloads run at the same time. Waiting downloads acquire a slot
+/// in the order they were scheduled, so the segment with the closest deadline goes first.
+pub struct Prefetcher {
+    max_concurrent: usize,
+    semaphore: Arc<Semaphore>,
+    pending: BTreeMap<u64, PendingSegment>,
+}
+
+impl Prefetcher {
+    pub fn new(max_concurrent: usize) -> Self {
+        Self {
+            max_concurrent,
+            semaphore: Arc::new(Semaphore::new(max_concurrent)),
+            pending: BTreeMap::new(),
+        }
+    }
+
+    pub fn max_concurrent(&self) -> usize {
+        self.max_concurrent
+    }
+
+    pub fn is_scheduled(&self, segment_number: u64) -> bool {
+        self.pending.contains_key(&segment_number)
+    }
+
+    /// Starts downloading a segment in the background.
+    pub fn schedule(
+        &mut self,
+        segment_number: u64,
+        representation_id: &str,
+        url: String,
+        client: &Client,
+        auth: &Arc<RequestAuth>,
+        cancellation_token: &CancellationToken,
+    ) {
+        let semaphore = self.semaphore.clone();
+        let client = client.clone();
+        let auth = auth.clone();
+        let cancellation_token = cancellation_token.clone();
+
+        let handle = tokio::spawn(async move {
+            let _permit = semaphore.acquire_owned().await
+                .map_err(|_| FetchError::Cancelled)?;
+            fetch_segment(&client, &auth, &url, &cancellation_token, None).await
+        });
+
+        self.pending.insert(segment_number, PendingSegment {
+            representation_id: representation_id.to_string(),
+            handle,
+        });
+    }
+
+    /// Returns the prefetched segment, waiting for its download to complete if needed.
+    /// Returns `None` if the segment was not prefetched, or was prefetched for another representation.
+    /// Downloads of earlier segments are no longer useful and are aborted.
+    pub async fn take(&mut self, segment_number: u64, representation_id: &str) -> Option<Result<(Bytes, f64), FetchError>> {
+        let remaining = self.pending.split_off(&segment_number);
+        for (_, stale) in std::mem::replace(&mut self.pending, remaining) {
+            stale.handle.abort();
+        }
+
+        let pending = self.pending.remove(&segment_number)?;
+        if pending.representation_id != representation_id {
+            // The ABR switched representation since the segment was scheduled
+            pending.handle.abort();
+            return None;
+        }
+
+        Some(pending.handle.await.unwrap_or(Err(FetchError::Cancelled)))
+    }
+
+    /// Number of segments that have been downloaded ahead and are waiting to be played.
+    pub fn ready(&self) -> usize {
+        self.pending.values().filter(|pending| pending.handle.is_finished()).count()
+    }
+}
+
+impl Drop for Prefetcher {
+    fn drop(&mut self) {
+        for pending in self.pending.values() {
+            pending.handle.abort();
+        }
+    }
+}
@@ -0,0 +1,29 @@
+//! Snapshot of a running playback session.
+//! Allows a player to be restarted (or moved to another network path) without rejoining at the live edge.
+
+use std::collections::HashMap;
+use serde::{Deserialize, Serialize};
+
+/// Progress of the segment fetcher of a single adaptation set.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct AdaptationState {
+    /// Number of the next segment that will be requested.
+    pub segment_pointer: u64,
+    /// The representation that was selected for the most recent segment.
+    pub representation_id: Option<String>,
+    /// Bandwidth estimate in bits per second, if any samples were recorded.
+    pub bandwidth_estimate: Option<f64>,
+}
+
+/// Everything needed to continue a playback session where it left off.
+/// The downloaded segments are not part of it, the segment pointers tell where to continue,
+/// and a restarted player has to fetch the initialization segments again.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct PlaybackState {
+    /// URL of the MPD the session was playing.
+    pub mpd_url: String,
+    /// Fetcher progress, keyed by adaptation set id.
+    pub adaptations: HashMap<String, AdaptationState>,
+    /// Target latency in seconds.
+    pub target_latency: f64,
+}
@@ -1,4 +1,4 @@
-use crate::boxes::{ftyp::FtypBox, generic::Mp4Box, mdat::MdatBox, moof::MoofBox, moov::MoovBox, styp::StypBox, traf::TrafBox, trak::TrakBox, vmhd::VmhdBox};
+use crate::boxes::{ftyp::FtypBox, generic::Mp4Box, mdat::MdatBox, moof::MoofBox, moov::MoovBox, styp::StypBox, traf::TrafBox, trak::TrakBox, trex::TrexBox, vmhd::VmhdBox};
 
 #[derive(Clone, Debug)]
 pub struct Mp4StreamConfig {
@@ -13,7 +13,15 @@ pub struct Mp4StreamConfig {
 
 
 pub fn create_init_segment(config: &Mp4StreamConfig) -> Vec<u8> {
-    let mut buffer = Vec::with_capacity(2048);  // Pre-allocate for efficiency
+    create_multi_track_init_segment(std::slice::from_ref(config))
+}
+
+/// Creates an init segment with one track per config, e.g. one per tile of a point cloud.
+/// The media segments of every track are created with `create_media_segment` and its own config,
+/// so a player can fetch the tracks it needs while they share this init segment.
+/// The movie timescale is taken from the first config.
+pub fn create_multi_track_init_segment(configs: &[Mp4StreamConfig]) -> Vec<u8> {
+    let mut buffer = Vec::with_capacity(2048 * configs.len().max(1));  // Pre-allocate for efficiency
 
     // 1) Write FTYP Box
     let ftyp = FtypBox::default();
@@ -23,34 +31,44 @@ pub fn create_init_segment(config: &Mp4StreamConfig) -> Vec<u8> {
     let mut moov = MoovBox::default();
 
     // --- Override mvhd ---
-    moov.mvhd.timescale = config.timescale;
+    if let Some(first) = configs.first() {
+        moov.mvhd.timescale = first.timescale;
+    }
     moov.mvhd.duration = 3510080100; // A very long duration for testing
-
-    // --- Override tkhd ---
-    moov.traks.push(TrakBox::default());
-    moov.traks[0].tkhd.track_id = config.track_id;
-    moov.traks[0].tkhd.width = (config.width as u32) << 16;
-    moov.traks[0].tkhd.height = (config.height as u32) << 16;
-    moov.traks[0].mdia.minf.vmhd = Some(VmhdBox::default());
-
-    // --- Override mdhd ---
-    moov.traks[0].mdia.mdhd.timescale = config.timescale;
-
-    // --- Override stsd / codec info ---
-    let stsd = &mut moov.traks[0].mdia.minf.stbl.stsd;
-    if let Some(entry) = stsd.entries.get_mut(0) {
-        entry.data_format = config.codec_fourcc;
-        entry.width = config.width;
-        entry.height = config.height;
-        entry.compressor_name = config.codec_name.clone();
+    moov.mvhd.next_track_id = configs.iter().map(|config| config.track_id).max().unwrap_or(1) + 1;
+
+    let mut trex_entries = Vec::with_capacity(configs.len());
+    for config in configs {
+        // --- Override tkhd ---
+        let mut trak = TrakBox::default();
+        trak.tkhd.track_id = config.track_id;
+        trak.tkhd.width = (config.width as u32) << 16;
+        trak.tkhd.height = (config.height as u32) << 16;
+        trak.mdia.minf.vmhd = Some(VmhdBox::default());
+
+        // --- Override mdhd ---
+        trak.mdia.mdhd.timescale = config.timescale;
+
+        // --- Override stsd / codec info ---
+        let stsd = &mut trak.mdia.minf.stbl.stsd;
+        if let Some(entry) = stsd.entries.get_mut(0) {
+            entry.data_format = config.codec_fourcc;
+            entry.width = config.width;
+            entry.height = config.height;
+            entry.compressor_name = config.codec_name.clone();
+        }
+        moov.traks.push(trak);
+
+        // --- Override trex ---
+        trex_entries.push(TrexBox {
+            track_id: config.track_id,
+            default_sample_duration: config.default_sample_duration,
+            ..Default::default()
+        });
     }
 
-    // --- Override trex ---
     if let Some(mvex) = moov.mvex.as_mut() {
-        if let Some(trex) = mvex.trex_entries.get_mut(0) {
-            trex.track_id = config.track_id;
-            trex.default_sample_duration = config.default_sample_duration;
-        }
+        mvex.trex_entries = trex_entries;
     }
 
     // 3) Write MOOV Box
@@ -72,7 +90,24 @@ pub fn create_media_segment(
     let styp = StypBox::default();
     styp.write_box(&mut segment);
 
-    // 2) Initialize MOOF Box with defaults
+    // 2) Append the MOOF + MDAT of the frame
+    segment.extend_from_slice(&create_media_chunk(config, frame_data, sequence_number, base_decode_time));
+
+    segment
+}
+
+/// Creates a single CMAF chunk (MOOF + MDAT) without a STYP box.
+/// A chunked segment starts with the output of `create_media_segment`,
+/// followed by any number of chunks that are appended as they become available.
+pub fn create_media_chunk(
+    config: &Mp4StreamConfig,
+    frame_data: &[u8],
+    sequence_number: u32,
+    base_decode_time: u64
+) -> Vec<u8> {
+    let mut chunk = Vec::new();
+
+    // 1) Initialize MOOF Box with defaults
     let mut moof = MoofBox::default();
 
     // -- Set dynamic fields --
@@ -90,30 +125,30 @@ pub fn create_media_segment(
         trun.data_offset = 0;
     }
 
-    // 3) Serialize MOOF to temporary buffer
+    // 2) Serialize MOOF to temporary buffer
     let mut moof_buffer = Vec::new();
     moof.write_box(&mut moof_buffer);
 
     if let Some(trun) = moof.trafs[0].trun.as_mut() {
-        // 4) Calculate correct data_offset
+        // 3) Calculate correct data_offset
         let data_offset = moof_buffer.len() as i32 + 8;  // 8 bytes for mdat header
         // Update trun.data_offset
         trun.data_offset = data_offset;
-        // 5) Re-serialize MOOF with correct offset
+        // 4) Re-serialize MOOF with correct offset
         moof_buffer.clear();
         moof.write_box(&mut moof_buffer);
     }
 
-    // 6) Create MDAT Box
+    // 5) Create MDAT Box
     let mdat = MdatBox {
         data: frame_data.to_vec(),  // Copy frame data into MDAT
     };
     let mut mdat_buffer = Vec::new();
     mdat.write_box(&mut mdat_buffer);
 
-    // 7) Combine MOOF + MDAT
-    segment.extend_from_slice(&moof_buffer);
-    segment.extend_from_slice(&mdat_buffer);
+    // 6) Combine MOOF + MDAT
+    chunk.extend_from_slice(&moof_buffer);
+    chunk.extend_from_slice(&mdat_buffer);
 
-    segment
+    chunk
 }
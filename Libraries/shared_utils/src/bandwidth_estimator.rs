@@ -0,0 +1,192 @@
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use webrtc::rtcp::packet::Packet as RtcpPacket;
+use webrtc::rtcp::payload_feedbacks::receiver_estimated_maximum_bitrate::ReceiverEstimatedMaximumBitrate;
+use webrtc::rtcp::transport_feedbacks::transport_layer_cc::{PacketStatusChunk, SymbolTypeTcc, TransportLayerCc};
+
+/// Minimum time between two updates of the estimate, the TWCC feedback of this period is combined.
+const UPDATE_INTERVAL: Duration = Duration::from_millis(500);
+/// Minimum time between two increases of the estimate.
+const INCREASE_INTERVAL: Duration = Duration::from_secs(1);
+/// Below this loss fraction the estimate is increased, as in the loss-based controller of Google Congestion Control.
+const LOW_LOSS_FRACTION: f64 = 0.02;
+/// Above this loss fraction the estimate is decreased.
+const HIGH_LOSS_FRACTION: f64 = 0.1;
+const INCREASE_FACTOR: f64 = 1.08;
+
+/// The estimated available bitrate of a connection.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BandwidthEstimate {
+    /// Bits per second
+    pub bitrate: u64,
+    /// Fraction of the packets that were lost in the last update interval, between 0 and 1
+    pub loss_fraction: f64,
+    /// The maximum bitrate of the last REMB of the remote peer, if it sends them
+    pub remb_bitrate: Option<u64>,
+}
+
+/// Limits of the estimated bitrate, in bits per second.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BandwidthEstimatorConfig {
+    pub initial_bitrate: u64,
+    pub min_bitrate: u64,
+    pub max_bitrate: u64,
+}
+
+impl Default for BandwidthEstimatorConfig {
+    fn default() -> Self {
+        Self {
+            initial_bitrate: 10_000_000,
+            min_bitrate: 100_000,
+            max_bitrate: 1_000_000_000,
+        }
+    }
+}
+
+pub type OnBandwidthEstimateFn = Arc<dyn Fn(BandwidthEstimate) + Send + Sync>;
+
+#[derive(Debug)]
+struct EstimatorState {
+    bitrate: f64,
+    loss_fraction: f64,
+    remb_bitrate: Option<u64>,
+    received: u64,
+    lost: u64,
+    last_update: Instant,
+    last_increase: Instant,
+}
+
+/// Loss-based estimate of the available bitrate of a single connection,
+/// from the TWCC feedback and the REMB messages that are read from its RTP sender.
+pub struct BandwidthEstimator {
+    config: BandwidthEstimatorConfig,
+    state: Mutex<EstimatorState>,
+    on_estimate: Mutex<Option<OnBandwidthEstimateFn>>,
+}
+
+impl BandwidthEstimator {
+    pub fn new(config: BandwidthEstimatorConfig) -> Self {
+        let now = Instant::now();
+        Self {
+            config,
+            state: Mutex::new(EstimatorState {
+                bitrate: config.initial_bitrate.clamp(config.min_bitrate, config.max_bitrate) as f64,
+                loss_fraction: 0.0,
+                remb_bitrate: None,
+                received: 0,
+                lost: 0,
+                last_update: now,
+                last_increase: now,
+            }),
+            on_estimate: Mutex::new(None),
+        }
+    }
+
+    /// Set the callback that is called every time the estimate is updated.
+    pub fn on_estimate(&self, f: OnBandwidthEstimateFn) {
+        *self.on_estimate.lock().unwrap() = Some(f);
+    }
+
+    /// The current estimate.
+    pub fn estimate(&self) -> BandwidthEstimate {
+        let state = self.state.lock().unwrap();
+        self.estimate_from(&state)
+    }
+
+    /// Update the estimate with the RTCP packets that were read from the RTP sender of the connection.
+    pub fn handle_rtcp(&self, rtcp_packets: &[Box<dyn RtcpPacket + Send + Sync>]) {
+        let estimate = {
+            let mut state = self.state.lock().unwrap();
+            let mut updated = false;
+            for rtcp_packet in rtcp_packets {
+                if let Some(twcc) = rtcp_packet.as_any().downcast_ref::<TransportLayerCc>() {
+                    let (received, lost) = count_packet_statuses(twcc);
+                    state.received += received;
+                    state.lost += lost;
+                } else if let Some(remb) = rtcp_packet.as_any().downcast_ref::<ReceiverEstimatedMaximumBitrate>() {
+                    state.remb_bitrate = Some(remb.bitrate as u64);
+                    updated = true;
+                }
+            }
+
+            if state.last_update.elapsed() >= UPDATE_INTERVAL && state.received + state.lost > 0 {
+                self.update_bitrate(&mut state);
+                updated = true;
+            }
+            if !updated {
+                return;
+            }
+            self.estimate_from(&state)
+        };
+
+        let on_estimate = self.on_estimate.lock().unwrap().clone();
+        if let Some(on_estimate) = on_estimate {
+            (on_estimate)(estimate);
+        }
+    }
+
+    fn update_bitrate(&self, state: &mut EstimatorState) {
+        let now = Instant::now();
+        let loss_fraction = state.lost as f64 / (state.received + state.lost) as f64;
+        if loss_fraction > HIGH_LOSS_FRACTION {
+            state.bitrate *= 1.0 - 0.5 * loss_fraction;
+        } else if loss_fraction < LOW_LOSS_FRACTION && now.duration_since(state.last_increase) >= INCREASE_INTERVAL {
+            state.bitrate *= INCREASE_FACTOR;
+            state.last_increase = now;
+        }
+        state.bitrate = state.bitrate.clamp(self.config.min_bitrate as f64, self.config.max_bitrate as f64);
+        state.loss_fraction = loss_fraction;
+        state.received = 0;
+        state.lost = 0;
+        state.last_update = now;
+    }
+
+    fn estimate_from(&self, state: &EstimatorState) -> BandwidthEstimate {
+        // The remote peer knows best how much it can receive
+        let bitrate = match state.remb_bitrate {
+            Some(remb_bitrate) => (state.bitrate as u64).min(remb_bitrate.max(self.config.min_bitrate)),
+            None => state.bitrate as u64,
+        };
+        BandwidthEstimate {
+            bitrate,
+            loss_fraction: state.loss_fraction,
+            remb_bitrate: state.remb_bitrate,
+        }
+    }
+}
+
+/// Count the received and lost packets that are reported in a TWCC feedback packet.
+fn count_packet_statuses(twcc: &TransportLayerCc) -> (u64, u64) {
+    let mut received = 0;
+    let mut lost = 0;
+    // The last status vector chunk can be padded with symbols beyond the packet status count
+    let mut remaining = twcc.packet_status_count as u64;
+    for chunk in &twcc.packet_chunks {
+        if remaining == 0 {
+            break;
+        }
+        match chunk {
+            PacketStatusChunk::RunLengthChunk(run) => {
+                let count = (run.run_length as u64).min(remaining);
+                if run.packet_status_symbol == SymbolTypeTcc::PacketNotReceived {
+                    lost += count;
+                } else {
+                    received += count;
+                }
+                remaining -= count;
+            }
+            PacketStatusChunk::StatusVectorChunk(vector) => {
+                for symbol in vector.symbol_list.iter().take(remaining as usize) {
+                    if *symbol == SymbolTypeTcc::PacketNotReceived {
+                        lost += 1;
+                    } else {
+                        received += 1;
+                    }
+                    remaining -= 1;
+                }
+            }
+        }
+    }
+    (received, lost)
+}
@@ -0,0 +1,178 @@
+use std::sync::{Arc, Mutex};
+
+use tokio::task::JoinHandle;
+use tracing::{error, instrument};
+use webrtc::data_channel::data_channel_init::RTCDataChannelInit;
+use webrtc::data_channel::data_channel_message::DataChannelMessage;
+use webrtc::data_channel::data_channel_state::RTCDataChannelState;
+use webrtc::data_channel::RTCDataChannel;
+use webrtc::error::flatten_errs;
+use webrtc::peer_connection::RTCPeerConnection;
+use webrtc::rtp::packetizer::Payloader;
+
+use crate::error::{XrError, XrResult};
+use crate::pointcloud_payloader::PointCloudPayloader;
+use crate::track_remote_pointcloud_rtp::{FrameReassembler, STALE_FRAME_TIMEOUT};
+use crate::types::FrameTaskData;
+
+/// Label of the DataChannel that carries the point cloud frames.
+pub const POINT_CLOUD_DATA_CHANNEL_LABEL: &str = "pointcloud";
+
+/// Delivery settings of the point cloud DataChannel.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DataChannelConfig {
+    /// Deliver the chunks in the order they were sent
+    pub ordered: bool,
+    /// Number of times a lost chunk is sent again, `None` retransmits until it arrives
+    pub max_retransmits: Option<u16>,
+    /// Size of the SCTP messages the frames are split into, including the 32 bytes of header
+    pub chunk_size: usize,
+}
+
+impl Default for DataChannelConfig {
+    fn default() -> Self {
+        Self::reliable()
+    }
+}
+
+impl DataChannelConfig {
+    /// Ordered, and every chunk is retransmitted until it arrives.
+    pub fn reliable() -> Self {
+        Self {
+            ordered: true,
+            max_retransmits: None,
+            chunk_size: 16384,
+        }
+    }
+
+    /// Unordered, and a lost chunk is retransmitted at most `max_retransmits` times.
+    pub fn unreliable(max_retransmits: u16) -> Self {
+        Self {
+            ordered: false,
+            max_retransmits: Some(max_retransmits),
+            ..Self::reliable()
+        }
+    }
+
+    fn data_channel_init(&self) -> RTCDataChannelInit {
+        RTCDataChannelInit {
+            ordered: Some(self.ordered),
+            max_retransmits: self.max_retransmits,
+            ..Default::default()
+        }
+    }
+}
+
+/// Create the point cloud DataChannel on the peer connection, this has to happen before the offer is created.
+pub async fn create_pointcloud_data_channel(
+    pc: &RTCPeerConnection,
+    config: &DataChannelConfig,
+) -> XrResult<Arc<RTCDataChannel>> {
+    pc.create_data_channel(POINT_CLOUD_DATA_CHANNEL_LABEL, Some(config.data_channel_init()))
+        .await
+        .map_err(|e| XrError::Transport(format!("create_data_channel failed: {e}")))
+}
+
+/// Sends frames over a DataChannel, split into chunks with the same header as the RTP packets of `TrackLocalPointCloudRTP`.
+pub struct DataChannelPointCloudSender {
+    channel: Arc<RTCDataChannel>,
+    payloader: Mutex<PointCloudPayloader>,
+    chunk_size: usize,
+}
+
+impl DataChannelPointCloudSender {
+    pub fn new(channel: Arc<RTCDataChannel>, chunk_size: usize) -> Self {
+        Self {
+            channel,
+            payloader: Mutex::new(PointCloudPayloader::new()),
+            chunk_size,
+        }
+    }
+
+    pub fn channel(&self) -> &Arc<RTCDataChannel> {
+        &self.channel
+    }
+
+    /// Whether the channel is open, frames written before that fail.
+    pub fn is_open(&self) -> bool {
+        self.channel.ready_state() == RTCDataChannelState::Open
+    }
+
+    /// write_frame writes a frame to the DataChannel
+    #[instrument(skip_all)]
+    pub async fn write_frame(&self, frame: &FrameTaskData) -> Result<(), webrtc::Error> {
+        let raw_payload = bytes::Bytes::copy_from_slice(&frame.data);
+
+        let chunks = {
+            // Lock the payloader, otherwise the metadata of frames that are written at the same time gets mixed up
+            let mut payloader = self.payloader.lock().unwrap();
+            payloader.set_metadata(
+                frame.sfu_client_id.unwrap_or(0) as u32,
+                frame.send_time, // Just like the RTP track, we send the send time for metrics
+                frame.sfu_tile_index.unwrap_or(0),
+                0,
+            );
+            payloader.payload(self.chunk_size, &raw_payload)?
+        };
+
+        let mut write_errs = vec![];
+        for chunk in chunks {
+            if let Err(err) = self.channel.send(&chunk).await {
+                write_errs.push(err);
+            }
+        }
+        flatten_errs(write_errs)
+    }
+}
+
+/// Reassembles the frames that are received on a point cloud DataChannel.
+pub struct DataChannelPointCloudReceiver {
+    channel: Arc<RTCDataChannel>,
+    on_frame: Arc<dyn Fn(FrameTaskData) + Send + Sync>,
+    cleanup_task: Option<JoinHandle<()>>,
+}
+
+impl DataChannelPointCloudReceiver {
+    pub fn new(
+        channel: Arc<RTCDataChannel>,
+        on_frame: Arc<dyn Fn(FrameTaskData) + Send + Sync>
+    ) -> Self {
+        Self {
+            channel,
+            on_frame,
+            cleanup_task: None,
+        }
+    }
+
+    pub fn start(&mut self) {
+        let reassembler = Arc::new(FrameReassembler::default());
+
+        let reassembler_clone = reassembler.clone();
+        let on_frame_cb = self.on_frame.clone();
+        self.channel.on_message(Box::new(move |msg: DataChannelMessage| {
+            if msg.is_string {
+                error!("Ignoring text message on the point cloud DataChannel");
+            } else if let Some(frame) = reassembler_clone.insert_packet(&msg.data) {
+                (on_frame_cb)(frame);
+            }
+            Box::pin(async {})
+        }));
+
+        // A seperate task that periodically removes the frames that are too old
+        self.cleanup_task = Some(tokio::spawn(async move {
+            loop {
+                tokio::time::sleep(STALE_FRAME_TIMEOUT).await;
+                reassembler.remove_stale_frames(STALE_FRAME_TIMEOUT);
+            }
+        }));
+    }
+
+    pub fn stop(&mut self) {
+        self.channel.on_message(Box::new(|_| Box::pin(async {})));
+        if let Some(h) = self.cleanup_task.take() {
+            if !h.is_finished() {
+                h.abort();
+            }
+        }
+    }
+}
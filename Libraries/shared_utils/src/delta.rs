@@ -0,0 +1,369 @@
+use std::cmp::Ordering;
+use std::collections::VecDeque;
+
+use bitcode::{Decode, Encode};
+
+use crate::error::{XrError, XrResult};
+use crate::types::Point3D;
+
+/// Magic bytes at the start of a delta coded frame.
+/// Three bytes, like the magic of the point cloud encodings, so decoders can tell them apart.
+pub const DELTA_PAYLOAD_MAGIC: [u8; 3] = *b"DLT";
+
+/// Number of keyframes a decoder keeps, so deltas that arrive after the next keyframe can still be decoded
+const KEYFRAME_HISTORY: usize = 4;
+
+/// Settings of the delta encoder.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct DeltaConfig {
+    /// Size (m) of the voxels in which the points of a frame are compared with the keyframe
+    pub position_step: f32,
+    /// A keyframe is sent at least every `keyframe_interval` frames, so receivers can join the stream
+    pub keyframe_interval: u32,
+    /// Color changes up to the tolerance (per channel) are not sent
+    pub color_tolerance: u8,
+}
+
+impl Default for DeltaConfig {
+    fn default() -> Self {
+        Self {
+            position_step: 0.001,
+            keyframe_interval: 30,
+            color_tolerance: 4,
+        }
+    }
+}
+
+impl DeltaConfig {
+    pub fn validate(&self) -> XrResult<()> {
+        if !(self.position_step.is_finite() && self.position_step > 0.0) {
+            return Err(XrError::Config(format!("The delta position step has to be positive, got {}", self.position_step)));
+        }
+        if self.keyframe_interval == 0 {
+            return Err(XrError::Config("The delta keyframe interval has to be at least 1".to_string()));
+        }
+        Ok(())
+    }
+}
+
+/// The position of a point on the voxel grid, and its color.
+type Voxel = ([i32; 3], [u8; 3]);
+
+#[derive(Encode, Decode)]
+struct DeltaFrame {
+    /// Sequence number of the keyframe, of the frame itself for a keyframe
+    keyframe: u32,
+    is_keyframe: bool,
+    position_step: f32,
+    /// The points of a keyframe, or the points that were added since the keyframe, sorted by position
+    added: Vec<Voxel>,
+    /// Indices, in the keyframe, of the points that were removed since the keyframe
+    removed: Vec<u32>,
+    /// Indices, in the keyframe, and new colors of the points whose color changed since the keyframe
+    changed: Vec<(u32, [u8; 3])>,
+}
+
+/// Encodes the frames of one stream as the points that were added, removed or changed color since the last keyframe.
+/// Every delta refers to the keyframe instead of the previous frame, so a lost or late delta does not affect the next ones.
+#[derive(Debug)]
+pub struct DeltaEncoder {
+    config: DeltaConfig,
+    /// Sequence number and points of the last keyframe
+    keyframe: Option<(u32, Vec<Voxel>)>,
+    frames_since_keyframe: u32,
+}
+
+impl DeltaEncoder {
+    pub fn new(config: DeltaConfig) -> Self {
+        Self {
+            config,
+            keyframe: None,
+            frames_since_keyframe: 0,
+        }
+    }
+
+    pub fn encode(&mut self, points: &[Point3D]) -> XrResult<Vec<u8>> {
+        let step = self.config.position_step;
+        let mut voxels: Vec<Voxel> = points
+            .iter()
+            .map(|point| ([point.x, point.y, point.z].map(|coordinate| (coordinate / step).floor() as i32), [point.r, point.g, point.b]))
+            .collect();
+        voxels.sort_by_key(|(position, _)| *position);
+        voxels.dedup_by_key(|(position, _)| *position);
+
+        let delta = match &self.keyframe {
+            Some((sequence, keyframe)) if self.frames_since_keyframe < self.config.keyframe_interval => {
+                let delta = diff(*sequence, keyframe, &voxels, &self.config);
+                // A delta that touches most of the points is hardly smaller than a keyframe, and makes the next deltas larger
+                let delta_len = delta.added.len() + delta.removed.len() + delta.changed.len();
+                (delta_len < voxels.len().max(1) / 2).then_some(delta)
+            }
+            _ => None,
+        };
+
+        let frame = match delta {
+            Some(delta) => {
+                self.frames_since_keyframe += 1;
+                delta
+            }
+            None => {
+                let sequence = self.keyframe.as_ref().map_or(0, |(sequence, _)| sequence.wrapping_add(1));
+                self.keyframe = Some((sequence, voxels.clone()));
+                self.frames_since_keyframe = 1;
+                DeltaFrame {
+                    keyframe: sequence,
+                    is_keyframe: true,
+                    position_step: step,
+                    added: voxels,
+                    removed: Vec::new(),
+                    changed: Vec::new(),
+                }
+            }
+        };
+
+        let mut encoded = DELTA_PAYLOAD_MAGIC.to_vec();
+        encoded.extend_from_slice(&bitcode::encode(&frame));
+        Ok(encoded)
+    }
+}
+
+/// Compares the sorted voxels of a frame with those of the keyframe.
+fn diff(sequence: u32, keyframe: &[Voxel], voxels: &[Voxel], config: &DeltaConfig) -> DeltaFrame {
+    let mut frame = DeltaFrame {
+        keyframe: sequence,
+        is_keyframe: false,
+        position_step: config.position_step,
+        added: Vec::new(),
+        removed: Vec::new(),
+        changed: Vec::new(),
+    };
+    let (mut old, mut new) = (0, 0);
+    while old < keyframe.len() || new < voxels.len() {
+        let ordering = match (keyframe.get(old), voxels.get(new)) {
+            (Some((old_position, _)), Some((new_position, _))) => old_position.cmp(new_position),
+            (Some(_), None) => Ordering::Less,
+            _ => Ordering::Greater,
+        };
+        match ordering {
+            Ordering::Less => {
+                frame.removed.push(old as u32);
+                old += 1;
+            }
+            Ordering::Greater => {
+                frame.added.push(voxels[new]);
+                new += 1;
+            }
+            Ordering::Equal => {
+                let (old_color, new_color) = (keyframe[old].1, voxels[new].1);
+                if old_color.iter().zip(new_color).any(|(old, new)| old.abs_diff(new) > config.color_tolerance) {
+                    frame.changed.push((old as u32, new_color));
+                }
+                old += 1;
+                new += 1;
+            }
+        }
+    }
+    frame
+}
+
+/// Decodes the delta coded frames of one stream, and keeps the last keyframes the deltas refer to.
+#[derive(Debug, Default)]
+pub struct DeltaDecoder {
+    keyframes: VecDeque<(u32, Vec<Voxel>)>,
+}
+
+impl DeltaDecoder {
+    /// Returns the points of the frame, or None for a delta of a keyframe that was not received,
+    /// e.g. right after joining the stream, the points are then known again from the next keyframe.
+    pub fn decode(&mut self, data: &[u8]) -> XrResult<Option<Vec<Point3D>>> {
+        if data.len() < DELTA_PAYLOAD_MAGIC.len() || data[..3] != DELTA_PAYLOAD_MAGIC {
+            return Err(XrError::Codec("Not a delta coded frame".to_string()));
+        }
+        let frame: DeltaFrame = bitcode::decode(&data[3..])
+            .map_err(|e| XrError::Codec(format!("Failed to decode a delta coded frame: {e}")))?;
+        let step = frame.position_step;
+
+        if frame.is_keyframe {
+            let points = frame.added.iter().map(|voxel| to_point(voxel, step)).collect();
+            self.keyframes.retain(|(sequence, _)| *sequence != frame.keyframe);
+            self.keyframes.push_back((frame.keyframe, frame.added));
+            while self.keyframes.len() > KEYFRAME_HISTORY {
+                self.keyframes.pop_front();
+            }
+            return Ok(Some(points));
+        }
+
+        let Some((_, keyframe)) = self.keyframes.iter().find(|(sequence, _)| *sequence == frame.keyframe) else {
+            return Ok(None);
+        };
+        let mut voxels: Vec<Option<Voxel>> = keyframe.iter().copied().map(Some).collect();
+        for index in frame.removed {
+            let voxel = voxels.get_mut(index as usize)
+                .ok_or_else(|| XrError::Codec(format!("A delta removes point {index}, which is not in the keyframe")))?;
+            *voxel = None;
+        }
+        for (index, color) in frame.changed {
+            let voxel = voxels.get_mut(index as usize)
+                .ok_or_else(|| XrError::Codec(format!("A delta changes point {index}, which is not in the keyframe")))?;
+            if let Some((_, old_color)) = voxel {
+                *old_color = color;
+            }
+        }
+        let points = voxels
+            .iter()
+            .flatten()
+            .chain(&frame.added)
+            .map(|voxel| to_point(voxel, step))
+            .collect();
+        Ok(Some(points))
+    }
+}
+
+/// A point in the center of its voxel.
+fn to_point((position, color): &Voxel, step: f32) -> Point3D {
+    let [x, y, z] = position.map(|coordinate| (coordinate as f32 + 0.5) * step);
+    Point3D {
+        x,
+        y,
+        z,
+        r: color[0],
+        g: color[1],
+        b: color[2],
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CONFIG: DeltaConfig = DeltaConfig {
+        position_step: 1.0,
+        keyframe_interval: 30,
+        color_tolerance: 4,
+    };
+
+    fn point(x: f32, color: u8) -> Point3D {
+        Point3D {
+            x,
+            y: 0.5,
+            z: 0.5,
+            r: color,
+            g: color,
+            b: color,
+        }
+    }
+
+    /// Ten points in the voxels 0 to 9 along x
+    fn keyframe_points() -> Vec<Point3D> {
+        (0..10).map(|x| point(x as f32 + 0.2, 100)).collect()
+    }
+
+    fn frame(data: &[u8]) -> DeltaFrame {
+        bitcode::decode(&data[DELTA_PAYLOAD_MAGIC.len()..]).unwrap()
+    }
+
+    fn sorted(mut points: Vec<Point3D>) -> Vec<Point3D> {
+        points.sort_by(|a, b| a.x.total_cmp(&b.x));
+        points
+    }
+
+    #[test]
+    fn keyframe_round_trip() {
+        let mut encoder = DeltaEncoder::new(CONFIG);
+        let data = encoder.encode(&keyframe_points()).unwrap();
+        assert!(frame(&data).is_keyframe);
+
+        let points = DeltaDecoder::default().decode(&data).unwrap().unwrap();
+        let expected: Vec<Point3D> = (0..10).map(|x| point(x as f32 + 0.5, 100)).collect();
+        assert_eq!(points, expected);
+    }
+
+    #[test]
+    fn delta_with_added_removed_and_recolored_points() {
+        let mut encoder = DeltaEncoder::new(CONFIG);
+        let mut decoder = DeltaDecoder::default();
+        decoder.decode(&encoder.encode(&keyframe_points()).unwrap()).unwrap();
+
+        let mut points = keyframe_points();
+        points.remove(3);
+        points[0].r = 200;
+        // Within the color tolerance
+        points[1].g = 102;
+        points.push(point(12.7, 50));
+        let data = encoder.encode(&points).unwrap();
+
+        let delta = frame(&data);
+        assert!(!delta.is_keyframe);
+        assert_eq!(delta.removed, vec![3]);
+        assert_eq!(delta.changed, vec![(0, [200, 100, 100])]);
+        assert_eq!(delta.added, vec![([12, 0, 0], [50, 50, 50])]);
+
+        let mut expected: Vec<Point3D> = (0..10).filter(|x| *x != 3).map(|x| point(x as f32 + 0.5, 100)).collect();
+        expected[0].r = 200;
+        expected.push(point(12.5, 50));
+        assert_eq!(sorted(decoder.decode(&data).unwrap().unwrap()), expected);
+    }
+
+    #[test]
+    fn delta_of_unknown_keyframe() {
+        let mut encoder = DeltaEncoder::new(CONFIG);
+        encoder.encode(&keyframe_points()).unwrap();
+        let mut points = keyframe_points();
+        points.pop();
+        let data = encoder.encode(&points).unwrap();
+        assert!(!frame(&data).is_keyframe);
+
+        assert_eq!(DeltaDecoder::default().decode(&data).unwrap(), None);
+    }
+
+    #[test]
+    fn out_of_range_index() {
+        let mut decoder = DeltaDecoder::default();
+        decoder.decode(&DeltaEncoder::new(CONFIG).encode(&keyframe_points()).unwrap()).unwrap();
+
+        let delta = |removed: Vec<u32>, changed: Vec<(u32, [u8; 3])>| {
+            let mut data = DELTA_PAYLOAD_MAGIC.to_vec();
+            data.extend_from_slice(&bitcode::encode(&DeltaFrame {
+                keyframe: 0,
+                is_keyframe: false,
+                position_step: CONFIG.position_step,
+                added: Vec::new(),
+                removed,
+                changed,
+            }));
+            data
+        };
+        assert!(decoder.decode(&delta(vec![10], Vec::new())).is_err());
+        assert!(decoder.decode(&delta(Vec::new(), vec![(10, [0, 0, 0])])).is_err());
+        assert!(decoder.decode(&delta(vec![9], vec![(0, [0, 0, 0])])).unwrap().is_some());
+    }
+
+    #[test]
+    fn keyframe_interval() {
+        let mut encoder = DeltaEncoder::new(DeltaConfig {
+            keyframe_interval: 2,
+            ..CONFIG
+        });
+        let keyframes: Vec<(bool, u32)> = (0..5)
+            .map(|_| frame(&encoder.encode(&keyframe_points()).unwrap()))
+            .map(|frame| (frame.is_keyframe, frame.keyframe))
+            .collect();
+        assert_eq!(keyframes, vec![(true, 0), (false, 0), (true, 1), (false, 1), (true, 2)]);
+    }
+
+    #[test]
+    fn large_delta_is_a_keyframe() {
+        let mut encoder = DeltaEncoder::new(CONFIG);
+        encoder.encode(&keyframe_points()).unwrap();
+
+        // Half of the points moved, a delta would be about as large as a keyframe
+        let mut points = keyframe_points();
+        for point in &mut points[..5] {
+            point.x += 20.0;
+        }
+        let keyframe = frame(&encoder.encode(&points).unwrap());
+        assert!(keyframe.is_keyframe);
+        assert_eq!(keyframe.keyframe, 1);
+        assert_eq!(keyframe.added.len(), 10);
+    }
+}
@@ -0,0 +1,70 @@
+use std::fmt;
+
+/// Error shared by the server and the clients, categorized so callers can branch on the kind of failure.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum XrError {
+    /// Encoding or decoding a point cloud or frame failed, or its format is not supported
+    Codec(String),
+    /// Sending or receiving data failed, e.g. a closed socket or a failed WebRTC call
+    Transport(String),
+    /// A frame arrived too late, or a deadline passed
+    Timing(String),
+    /// A setting is missing or invalid
+    Config(String),
+}
+
+pub type XrResult<T> = Result<T, XrError>;
+
+impl XrError {
+    pub fn codec(e: impl fmt::Display) -> Self {
+        XrError::Codec(e.to_string())
+    }
+
+    pub fn transport(e: impl fmt::Display) -> Self {
+        XrError::Transport(e.to_string())
+    }
+
+    pub fn timing(e: impl fmt::Display) -> Self {
+        XrError::Timing(e.to_string())
+    }
+
+    pub fn config(e: impl fmt::Display) -> Self {
+        XrError::Config(e.to_string())
+    }
+
+    /// The message without the category.
+    pub fn message(&self) -> &str {
+        match self {
+            XrError::Codec(message)
+            | XrError::Transport(message)
+            | XrError::Timing(message)
+            | XrError::Config(message) => message,
+        }
+    }
+}
+
+impl fmt::Display for XrError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            XrError::Codec(message) => write!(f, "Codec error: {}", message),
+            XrError::Transport(message) => write!(f, "Transport error: {}", message),
+            XrError::Timing(message) => write!(f, "Timing error: {}", message),
+            XrError::Config(message) => write!(f, "Config error: {}", message),
+        }
+    }
+}
+
+impl std::error::Error for XrError {}
+
+impl From<webrtc::Error> for XrError {
+    fn from(e: webrtc::Error) -> Self {
+        XrError::transport(e)
+    }
+}
+
+/// Most of the older APIs return a `String` as error, this keeps `?` working in them.
+impl From<XrError> for String {
+    fn from(e: XrError) -> Self {
+        e.to_string()
+    }
+}
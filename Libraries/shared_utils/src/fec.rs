@@ -0,0 +1,61 @@
+use raptorq::{Decoder, Encoder, EncodingPacket, ObjectTransmissionInformation};
+
+/// Offset written in the header of a FEC packet, a regular chunk can never start at this offset.
+/// The data of a FEC packet holds the RaptorQ configuration, followed by a serialized RaptorQ packet.
+pub const FEC_PACKET_OFFSET: u32 = u32::MAX;
+
+const OTI_SIZE: usize = 12;
+const PAYLOAD_ID_SIZE: usize = 4;
+const SYMBOL_ALIGNMENT: usize = 8;
+
+/// Encode a frame into RaptorQ source and repair packets of at most `max_packet_size` bytes.
+/// For every 100 source packets, `repair_percentage` repair packets are generated (rounded up),
+/// so the frame can be decoded as long as slightly more packets than the number of source packets arrive.
+/// Returns `None` if the packets are too small to hold a symbol.
+pub fn encode(data: &[u8], max_packet_size: usize, repair_percentage: u32) -> Option<Vec<Vec<u8>>> {
+    let symbol_size = max_packet_size.checked_sub(OTI_SIZE + PAYLOAD_ID_SIZE)?.min(u16::MAX as usize);
+    if symbol_size < SYMBOL_ALIGNMENT {
+        return None;
+    }
+
+    let encoder = Encoder::with_defaults(data, symbol_size as u16);
+    let config = encoder.get_config().serialize();
+
+    let mut packets = vec![];
+    for block in encoder.get_block_encoders() {
+        let source_packets = block.source_packets();
+        let repair_count = (source_packets.len() as u32 * repair_percentage).div_ceil(100);
+        let repair_packets = block.repair_packets(0, repair_count);
+
+        for packet in source_packets.iter().chain(repair_packets.iter()) {
+            let serialized = packet.serialize();
+            let mut out = Vec::with_capacity(OTI_SIZE + serialized.len());
+            out.extend_from_slice(&config);
+            out.extend_from_slice(&serialized);
+            packets.push(out);
+        }
+    }
+    Some(packets)
+}
+
+/// Collects the FEC packets of a single frame until enough of them arrived to decode it.
+#[derive(Debug, Default)]
+pub struct FecDecoder {
+    decoder: Option<Decoder>,
+}
+
+impl FecDecoder {
+    /// Add the data of a FEC packet, returns the decoded frame once it can be reconstructed.
+    pub fn insert_packet(&mut self, data: &[u8]) -> Option<Vec<u8>> {
+        if data.len() <= OTI_SIZE + PAYLOAD_ID_SIZE {
+            return None;
+        }
+        let (config, packet) = data.split_at(OTI_SIZE);
+
+        let decoder = self.decoder.get_or_insert_with(|| {
+            let config: [u8; OTI_SIZE] = config.try_into().expect("Slice has the size of the RaptorQ configuration");
+            Decoder::new(ObjectTransmissionInformation::deserialize(&config))
+        });
+        decoder.decode(EncodingPacket::deserialize(packet))
+    }
+}
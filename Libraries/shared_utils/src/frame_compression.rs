@@ -0,0 +1,73 @@
+use std::io::{Read, Write};
+
+use flate2::read::DeflateDecoder;
+use flate2::write::DeflateEncoder;
+use serde::{Deserialize, Serialize};
+
+use crate::error::{XrError, XrResult};
+
+/// Compression of the encoded frames on the WebSocket egress, which every client chooses for itself.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FrameCompression {
+    #[default]
+    None,
+    Deflate,
+    Zstd,
+}
+
+impl FrameCompression {
+    pub const ALL: [FrameCompression; 3] = [FrameCompression::None, FrameCompression::Deflate, FrameCompression::Zstd];
+
+    /// Parses "none", "deflate" or "zstd" (case insensitive).
+    pub fn parse(value: &str) -> XrResult<Self> {
+        match value.trim().to_lowercase().as_str() {
+            "" | "none" => Ok(FrameCompression::None),
+            "deflate" => Ok(FrameCompression::Deflate),
+            "zstd" => Ok(FrameCompression::Zstd),
+            other => Err(XrError::Config(format!("Unknown frame compression {other}, expected none, deflate or zstd"))),
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            FrameCompression::None => "none",
+            FrameCompression::Deflate => "deflate",
+            FrameCompression::Zstd => "zstd",
+        }
+    }
+
+    /// Compresses an encoded frame. Point cloud encodings are already compact, so a fast level is used.
+    pub fn compress(&self, data: &[u8]) -> XrResult<Vec<u8>> {
+        match self {
+            FrameCompression::None => Ok(data.to_vec()),
+            FrameCompression::Deflate => {
+                let mut encoder = DeflateEncoder::new(Vec::with_capacity(data.len()), flate2::Compression::fast());
+                encoder.write_all(data)
+                    .and_then(|_| encoder.finish())
+                    .map_err(|e| XrError::Codec(format!("Failed to deflate frame: {e}")))
+            }
+            FrameCompression::Zstd => zstd::bulk::compress(data, 1)
+                .map_err(|e| XrError::Codec(format!("Failed to compress frame with zstd: {e}"))),
+        }
+    }
+
+    pub fn decompress(&self, data: &[u8]) -> XrResult<Vec<u8>> {
+        match self {
+            FrameCompression::None => Ok(data.to_vec()),
+            FrameCompression::Deflate => {
+                let mut decompressed = Vec::with_capacity(data.len() * 2);
+                DeflateDecoder::new(data)
+                    .read_to_end(&mut decompressed)
+                    .map_err(|e| XrError::Codec(format!("Failed to inflate frame: {e}")))?;
+                Ok(decompressed)
+            }
+            FrameCompression::Zstd => {
+                let mut decompressed = Vec::with_capacity(data.len() * 2);
+                zstd::stream::copy_decode(data, &mut decompressed)
+                    .map_err(|e| XrError::Codec(format!("Failed to decompress frame with zstd: {e}")))?;
+                Ok(decompressed)
+            }
+        }
+    }
+}
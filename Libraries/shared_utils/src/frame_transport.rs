@@ -0,0 +1,27 @@
+use async_trait::async_trait;
+
+use crate::datachannel_pointcloud::DataChannelPointCloudSender;
+use crate::error::XrResult;
+use crate::track_local_pointcloud_rtp::TrackLocalPointCloudRTP;
+use crate::types::FrameTaskData;
+
+/// A way of delivering point cloud frames to a remote peer,
+/// so the RTP track and the DataChannel can be used interchangeably.
+#[async_trait]
+pub trait FrameTransport: Send + Sync {
+    async fn write_frame(&self, frame: &FrameTaskData) -> XrResult<()>;
+}
+
+#[async_trait]
+impl FrameTransport for TrackLocalPointCloudRTP {
+    async fn write_frame(&self, frame: &FrameTaskData) -> XrResult<()> {
+        Ok(TrackLocalPointCloudRTP::write_frame(self, frame).await?)
+    }
+}
+
+#[async_trait]
+impl FrameTransport for DataChannelPointCloudSender {
+    async fn write_frame(&self, frame: &FrameTaskData) -> XrResult<()> {
+        Ok(DataChannelPointCloudSender::write_frame(self, frame).await?)
+    }
+}
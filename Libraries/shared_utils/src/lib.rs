@@ -1,9 +1,20 @@
+pub mod bandwidth_estimator;
 pub mod codec;
+pub mod datachannel_pointcloud;
+pub mod delta;
+pub mod error;
+pub mod fec;
+pub mod frame_compression;
+pub mod frame_transport;
+pub mod payload_crypto;
 pub mod peer_connection;
 pub mod pointcloud_payloader;
+pub mod quic_frame;
+pub mod retransmission;
 pub mod track_local_pointcloud_rtp;
 pub mod track_remote_pointcloud_rtp;
 pub mod types;
+pub mod wire;
 
 // Optionally re-export the relevant webrtc types
 pub use webrtc::{
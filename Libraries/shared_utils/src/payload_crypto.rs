@@ -0,0 +1,131 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::RwLock;
+
+use aes_gcm::aead::{Aead, AeadCore, KeyInit, OsRng, Payload};
+use aes_gcm::{Aes256Gcm, Key, Nonce};
+
+use crate::error::{XrError, XrResult};
+
+/// Magic bytes at the start of an encrypted payload.
+/// Three bytes, like the magic of the point cloud encodings, so decoders can tell them apart.
+pub const ENCRYPTED_PAYLOAD_MAGIC: [u8; 3] = *b"ENC";
+
+/// Magic (3 bytes), key id (u32 LE) and nonce (12 bytes), followed by the ciphertext and the 16 byte tag.
+const HEADER_SIZE: usize = 3 + 4 + NONCE_SIZE;
+const NONCE_SIZE: usize = 12;
+
+/// An AES-256-GCM key and the id that is sent along in the payload header,
+/// so the receiver knows which key to decrypt with when keys are rotated.
+#[derive(Clone)]
+pub struct PayloadKey {
+    pub id: u32,
+    cipher: Aes256Gcm,
+}
+
+impl fmt::Debug for PayloadKey {
+    // Never print the key itself
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("PayloadKey").field("id", &self.id).finish_non_exhaustive()
+    }
+}
+
+impl PayloadKey {
+    pub fn new(id: u32, key: &[u8; 32]) -> Self {
+        Self {
+            id,
+            cipher: Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(key)),
+        }
+    }
+
+    /// Parses a key of 64 hexadecimal characters.
+    pub fn from_hex(id: u32, hex: &str) -> XrResult<Self> {
+        let hex = hex.trim();
+        if hex.len() != 64 || !hex.is_ascii() {
+            return Err(XrError::Config(format!("Expected a payload key of 64 hexadecimal characters, got {} characters", hex.len())));
+        }
+        let mut key = [0u8; 32];
+        for (index, byte) in key.iter_mut().enumerate() {
+            *byte = u8::from_str_radix(&hex[index * 2..index * 2 + 2], 16)
+                .map_err(|e| XrError::Config(format!("Invalid payload key: {e}")))?;
+        }
+        Ok(Self::new(id, &key))
+    }
+
+    /// Encrypts an encoded frame payload, the header with the key id is authenticated as well.
+    pub fn encrypt(&self, payload: &[u8]) -> XrResult<Vec<u8>> {
+        let mut encrypted = Vec::with_capacity(HEADER_SIZE + payload.len() + 16);
+        encrypted.extend_from_slice(&ENCRYPTED_PAYLOAD_MAGIC);
+        encrypted.extend_from_slice(&self.id.to_le_bytes());
+
+        // A random nonce per payload, the key would have to encrypt billions of frames before a collision becomes likely
+        let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+        let ciphertext = self.cipher
+            .encrypt(&nonce, Payload { msg: payload, aad: &encrypted })
+            .map_err(|_| XrError::Codec("Failed to encrypt payload".to_string()))?;
+        encrypted.extend_from_slice(&nonce);
+        encrypted.extend_from_slice(&ciphertext);
+        Ok(encrypted)
+    }
+
+    /// Decrypts a payload that was encrypted with this key.
+    pub fn decrypt(&self, encrypted: &[u8]) -> XrResult<Vec<u8>> {
+        match encrypted_payload_key_id(encrypted) {
+            Some(id) if id == self.id => {}
+            Some(id) => return Err(XrError::Codec(format!("Payload is encrypted with key {id}, not with key {}", self.id))),
+            None => return Err(XrError::Codec("Payload is not encrypted".to_string())),
+        }
+        let (aad, rest) = encrypted.split_at(HEADER_SIZE - NONCE_SIZE);
+        let (nonce, ciphertext) = rest.split_at(NONCE_SIZE);
+        self.cipher
+            .decrypt(Nonce::from_slice(nonce), Payload { msg: ciphertext, aad })
+            .map_err(|_| XrError::Codec(format!("Failed to decrypt payload with key {}", self.id)))
+    }
+}
+
+/// Returns the id of the key that a payload is encrypted with, or `None` if it is not encrypted.
+pub fn encrypted_payload_key_id(payload: &[u8]) -> Option<u32> {
+    if payload.len() < HEADER_SIZE || payload[0..3] != ENCRYPTED_PAYLOAD_MAGIC {
+        return None;
+    }
+    Some(u32::from_le_bytes([payload[3], payload[4], payload[5], payload[6]]))
+}
+
+/// The keys a receiver can decrypt payloads with, by key id.
+#[derive(Debug, Default)]
+pub struct PayloadKeyRing {
+    keys: RwLock<HashMap<u32, PayloadKey>>,
+}
+
+impl PayloadKeyRing {
+    /// Adds keys like `1:<64 hex characters>,2:<64 hex characters>`.
+    pub fn insert_from_str(&self, value: &str) -> XrResult<()> {
+        for entry in value.split(',').map(str::trim).filter(|entry| !entry.is_empty()) {
+            let (id, hex) = entry.split_once(':')
+                .ok_or_else(|| XrError::Config("Expected payload keys like key_id:hex_key".to_string()))?;
+            let id = id.trim().parse::<u32>()
+                .map_err(|e| XrError::Config(format!("Invalid payload key id {id}: {e}")))?;
+            self.insert(PayloadKey::from_hex(id, hex)?);
+        }
+        Ok(())
+    }
+
+    pub fn insert(&self, key: PayloadKey) {
+        self.keys.write().unwrap().insert(key.id, key);
+    }
+
+    pub fn remove(&self, id: u32) {
+        self.keys.write().unwrap().remove(&id);
+    }
+
+    /// Decrypts the payload if it is encrypted, payloads that are not encrypted are returned as is.
+    pub fn decrypt(&self, payload: Vec<u8>) -> XrResult<Vec<u8>> {
+        let Some(id) = encrypted_payload_key_id(&payload) else {
+            return Ok(payload);
+        };
+        match self.keys.read().unwrap().get(&id) {
+            Some(key) => key.decrypt(&payload),
+            None => Err(XrError::Config(format!("No payload key with id {id}"))),
+        }
+    }
+}
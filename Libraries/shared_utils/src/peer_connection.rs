@@ -1,10 +1,87 @@
 use std::sync::Arc;
 
-use webrtc::{api::{interceptor_registry::{configure_nack, configure_rtcp_reports, configure_twcc}, media_engine::MediaEngine, APIBuilder}, ice_transport::ice_server::RTCIceServer, interceptor::registry::Registry, peer_connection::{configuration::RTCConfiguration, RTCPeerConnection}, rtp_transceiver::rtp_codec::{RTCRtpCodecParameters, RTPCodecType}};
+use webrtc::{api::{interceptor_registry::{configure_rtcp_reports, configure_twcc}, media_engine::MediaEngine, setting_engine::SettingEngine, APIBuilder}, ice::{mdns::MulticastDnsMode, udp_network::{EphemeralUDP, UDPNetwork}}, ice_transport::{ice_server::RTCIceServer, ice_transport_policy::RTCIceTransportPolicy}, interceptor::{nack::generator::Generator, registry::Registry}, peer_connection::{configuration::RTCConfiguration, RTCPeerConnection}, rtp_transceiver::{rtp_codec::{RTCRtpCodecParameters, RTPCodecType}, RTCPFeedback}};
 
 use crate::codec::video_codec_capability;
+use crate::error::{XrError, XrResult};
 
-pub async fn create_webrtc_peer_connection() -> Result<Arc<RTCPeerConnection>, String> {
+/// Network settings of the peer connections, shared by the server egress and the receiver ingress.
+#[derive(Debug, Clone)]
+pub struct PeerConnectionConfig {
+    /// STUN and TURN servers, TURN servers usually require a username and credential
+    pub ice_servers: Vec<RTCIceServer>,
+    /// Use `Relay` to only connect through the TURN servers
+    pub ice_transport_policy: RTCIceTransportPolicy,
+    /// Inclusive range of the local UDP ports that are used for the ICE candidates, e.g. to match the firewall rules
+    pub port_range: Option<(u16, u16)>,
+    /// Resolve the mDNS (`.local`) candidates of the remote peer
+    pub mdns: bool,
+}
+
+impl Default for PeerConnectionConfig {
+    fn default() -> Self {
+        Self {
+            ice_servers: vec![RTCIceServer {
+                urls: vec!["stun:stun.l.google.com:19302".to_owned()],
+                ..Default::default()
+            }],
+            ice_transport_policy: RTCIceTransportPolicy::All,
+            port_range: None,
+            mdns: true,
+        }
+    }
+}
+
+impl PeerConnectionConfig {
+    /// Replace the ICE servers by `urls`, e.g. `stun:stun.l.google.com:19302` or `turn:turn.example.com:3478`.
+    /// The username and credential are only used for the TURN servers.
+    pub fn with_ice_servers(mut self, urls: &[String], username: Option<&str>, credential: Option<&str>) -> Self {
+        self.ice_servers = urls
+            .iter()
+            .map(|url| {
+                let is_turn = url.starts_with("turn:") || url.starts_with("turns:");
+                RTCIceServer {
+                    urls: vec![url.clone()],
+                    username: if is_turn { username.unwrap_or_default().to_owned() } else { String::new() },
+                    credential: if is_turn { credential.unwrap_or_default().to_owned() } else { String::new() },
+                    ..Default::default()
+                }
+            })
+            .collect();
+        self
+    }
+
+    /// Only connect through the TURN servers, hiding the local and public addresses of this peer.
+    pub fn with_relay_only(mut self, relay_only: bool) -> Self {
+        self.ice_transport_policy = if relay_only { RTCIceTransportPolicy::Relay } else { RTCIceTransportPolicy::All };
+        self
+    }
+
+    /// Only use the local UDP ports from `port_min` up to and including `port_max`.
+    pub fn with_port_range(mut self, port_min: u16, port_max: u16) -> Self {
+        self.port_range = Some((port_min, port_max));
+        self
+    }
+
+    /// Enable or disable resolving mDNS candidates.
+    pub fn with_mdns(mut self, mdns: bool) -> Self {
+        self.mdns = mdns;
+        self
+    }
+
+    fn setting_engine(&self) -> XrResult<SettingEngine> {
+        let mut setting_engine = SettingEngine::default();
+        if let Some((port_min, port_max)) = self.port_range {
+            let ephemeral_udp = EphemeralUDP::new(port_min, port_max)
+                .map_err(|e| XrError::Config(format!("Invalid WebRTC port range {port_min}-{port_max}: {e}")))?;
+            setting_engine.set_udp_network(UDPNetwork::Ephemeral(ephemeral_udp));
+        }
+        setting_engine.set_ice_multicast_dns_mode(if self.mdns { MulticastDnsMode::QueryOnly } else { MulticastDnsMode::Disabled });
+        Ok(setting_engine)
+    }
+}
+
+pub async fn create_webrtc_peer_connection(config: &PeerConnectionConfig) -> XrResult<Arc<RTCPeerConnection>> {
     let mut m = MediaEngine::default();
     m.register_default_codecs().unwrap();
     let _ = m.register_codec(RTCRtpCodecParameters {
@@ -13,25 +90,50 @@ pub async fn create_webrtc_peer_connection() -> Result<Arc<RTCPeerConnection>, S
         ..Default::default()
     }, RTPCodecType::Video);
     let mut registry = Registry::new();
-    registry = configure_nack(registry, &mut m);
+    registry = configure_nack_generator(registry, &mut m);
     registry = configure_rtcp_reports(registry);
-    registry = configure_twcc(registry, &mut m).map_err(|e| format!("configure_twcc failed: {e}"))?;
+    // TWCC and REMB feedback is turned into an estimate of the available bitrate by the `BandwidthEstimator`
+    registry = configure_twcc(registry, &mut m).map_err(|e| XrError::Transport(format!("configure_twcc failed: {e}")))?;
+    configure_remb_feedback(&mut m);
     // registry = register_default_interceptors(registry, &mut m).unwrap();
     let api = APIBuilder::new()
         .with_media_engine(m)
         .with_interceptor_registry(registry)
+        .with_setting_engine(config.setting_engine()?)
         .build();
 
-    let config = RTCConfiguration {
-        ice_servers: vec![RTCIceServer {
-            urls: vec!["stun:stun.l.google.com:19302".to_owned()],
-            ..Default::default()
-        }],
+    let rtc_config = RTCConfiguration {
+        ice_servers: config.ice_servers.clone(),
+        ice_transport_policy: config.ice_transport_policy,
         ..Default::default()
     };
     Ok(Arc::new(
-        api.new_peer_connection(config)
+        api.new_peer_connection(rtc_config)
             .await
-            .map_err(|e| format!("new_peer_connection failed: {e}"))?,
+            .map_err(|e| XrError::Transport(format!("new_peer_connection failed: {e}")))?,
     ))
-}
\ No newline at end of file
+}
+
+/// Like `configure_nack`, but without the NACK responder interceptor.
+/// Lost point cloud packets are retransmitted by `TrackLocalPointCloudRTP::handle_rtcp` instead,
+/// so the retransmissions can be counted per track.
+fn configure_nack_generator(mut registry: Registry, media_engine: &mut MediaEngine) -> Registry {
+    media_engine.register_feedback(
+        RTCPFeedback { typ: "nack".to_owned(), parameter: "".to_owned() },
+        RTPCodecType::Video,
+    );
+    media_engine.register_feedback(
+        RTCPFeedback { typ: "nack".to_owned(), parameter: "pli".to_owned() },
+        RTPCodecType::Video,
+    );
+    registry.add(Box::new(Generator::builder()));
+    registry
+}
+
+/// Ask the remote peer to send REMB messages, browsers do so next to the TWCC feedback.
+fn configure_remb_feedback(media_engine: &mut MediaEngine) {
+    media_engine.register_feedback(
+        RTCPFeedback { typ: "goog-remb".to_owned(), parameter: "".to_owned() },
+        RTPCodecType::Video,
+    );
+}
@@ -4,12 +4,15 @@ use bytes::{BufMut, Bytes, BytesMut};
 use tracing::instrument;
 use webrtc::rtp::packetizer::Payloader;
 
+use crate::fec::{self, FEC_PACKET_OFFSET};
+
 #[derive(Debug)]
 struct PointCloudMetadata {
     client_id: u32,
     frame_nr: u64,
     tile_nr: u32,
     quality_nr: u32,
+    fec_repair_percentage: u32, // 0 disables FEC
 }
 
 #[derive(Debug, Clone)]
@@ -31,6 +34,7 @@ impl Default for PointCloudPayloader {
                 frame_nr: 0,
                 tile_nr: 0,
                 quality_nr: 0,
+                fec_repair_percentage: 0,
             })),
         }
     }
@@ -40,12 +44,12 @@ impl Payloader for PointCloudPayloader {
 
     #[instrument(skip_all)]
     fn payload(&mut self, mtu: usize, payload_data: &Bytes) -> Result<Vec<Bytes>, webrtc::rtp::Error> {
-        if payload_data.is_empty() || mtu <= 28 {
+        const HEADER_SIZE: usize = 32;
+        if payload_data.is_empty() || mtu <= HEADER_SIZE {
             return Ok(vec![]);
         }
 
         let payload_len = payload_data.len() as u32;
-        const HEADER_SIZE: usize = 28;
         let max_data_per_packet = mtu - HEADER_SIZE;
         let mut output = vec![];
         let mut payload_data_remaining = payload_data.len();
@@ -60,8 +64,28 @@ impl Payloader for PointCloudPayloader {
         let frame_nr = meta.frame_nr;
         let tile_nr = meta.tile_nr;
         let quality_nr = meta.quality_nr;
+        let fec_repair_percentage = meta.fec_repair_percentage;
         drop(meta);
 
+        if fec_repair_percentage > 0 {
+            if let Some(fec_packets) = fec::encode(payload_data, max_data_per_packet, fec_repair_percentage) {
+                for fec_packet in fec_packets {
+                    let mut out = BytesMut::with_capacity(HEADER_SIZE + fec_packet.len());
+
+                    out.put_u32_le(client_id); // client id
+                    out.put_u64_le(frame_nr); // Frame counter
+                    out.put_u32_le(payload_len); // payload len
+                    out.put_u32_le(FEC_PACKET_OFFSET); // marks this chunk as a FEC packet
+                    out.put_u32_le(fec_packet.len() as u32); // current chunk size
+                    out.put_u32_le(tile_nr); // tile
+                    out.put_u32_le(quality_nr); // quality
+                    out.put(&*fec_packet);
+
+                    output.push(out.freeze());
+                }
+                return Ok(output);
+            }
+        }
 
         while payload_data_remaining > 0 {
             let chunk_len = std::cmp::min(max_data_per_packet, payload_data.len() - offset);
@@ -102,4 +126,11 @@ impl PointCloudPayloader {
         meta.tile_nr   = tile_nr;
         meta.quality_nr = quality_nr;
     }
+
+    /// Generate `percentage` RaptorQ repair packets per 100 packets of a frame, so the remote track
+    /// can reconstruct frames under packet loss without retransmissions. 0 disables FEC.
+    pub fn set_fec_repair_percentage(&mut self, percentage: u32) {
+        let mut meta = self.metadata.lock().unwrap();
+        meta.fec_repair_percentage = percentage;
+    }
 }
@@ -0,0 +1,37 @@
+use crate::error::{XrError, XrResult};
+use crate::types::FrameTaskData;
+
+/// Stream id (u64 LE), tile index (u32 LE), send time (u64 LE) and presentation time (u64 LE).
+pub const QUIC_FRAME_HEADER_SIZE: usize = 28;
+
+/// Encode a frame for a QUIC unidirectional stream: a small header followed by the encoded point cloud.
+/// Every frame has its own stream, so the end of the stream marks the end of the frame.
+pub fn encode_quic_frame(frame: &FrameTaskData) -> Vec<u8> {
+    let mut encoded = Vec::with_capacity(QUIC_FRAME_HEADER_SIZE + frame.data.len());
+    encoded.extend_from_slice(&frame.sfu_client_id.unwrap_or(0).to_le_bytes());
+    encoded.extend_from_slice(&frame.sfu_tile_index.unwrap_or(0).to_le_bytes());
+    encoded.extend_from_slice(&frame.send_time.to_le_bytes());
+    encoded.extend_from_slice(&frame.presentation_time.to_le_bytes());
+    encoded.extend_from_slice(&frame.data);
+    encoded
+}
+
+/// Decode a frame that was read from a QUIC unidirectional stream until its end.
+pub fn decode_quic_frame(bytes: &[u8]) -> XrResult<FrameTaskData> {
+    if bytes.len() < QUIC_FRAME_HEADER_SIZE {
+        return Err(XrError::Codec(format!("QUIC frame of {} bytes is shorter than its header", bytes.len())));
+    }
+    let stream_id = u64::from_le_bytes(bytes[0..8].try_into().unwrap());
+    let tile_index = u32::from_le_bytes(bytes[8..12].try_into().unwrap());
+    let send_time = u64::from_le_bytes(bytes[12..20].try_into().unwrap());
+    let presentation_time = u64::from_le_bytes(bytes[20..28].try_into().unwrap());
+    let data = bytes[QUIC_FRAME_HEADER_SIZE..].to_vec();
+    Ok(FrameTaskData {
+        send_time,
+        presentation_time,
+        sfu_client_id: Some(stream_id),
+        sfu_frame_len: Some(data.len() as u32),
+        sfu_tile_index: Some(tile_index),
+        data,
+    })
+}
@@ -0,0 +1,71 @@
+use webrtc::rtp::packet::Packet;
+
+/// Number of sent packets that are kept for retransmission.
+/// A power of two, so that every sequence number maps to a fixed slot, also after the sequence numbers wrap around.
+pub const RETRANSMISSION_HISTORY_SIZE: usize = 4096;
+
+/// Counters of the NACK-driven retransmissions of a track.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct RetransmissionStats {
+    /// Number of NACK feedback packets received from the remote peers
+    pub nacks_received: u64,
+    /// Number of lost packets that were reported in the NACKs
+    pub packets_requested: u64,
+    /// Number of lost packets that were sent again
+    pub packets_retransmitted: u64,
+    /// Number of lost packets that were no longer in the history, and thus could not be sent again
+    pub packets_unavailable: u64,
+}
+
+impl std::ops::AddAssign for RetransmissionStats {
+    fn add_assign(&mut self, other: Self) {
+        self.nacks_received += other.nacks_received;
+        self.packets_requested += other.packets_requested;
+        self.packets_retransmitted += other.packets_retransmitted;
+        self.packets_unavailable += other.packets_unavailable;
+    }
+}
+
+/// Ring buffer of the most recently sent RTP packets, indexed by their sequence number.
+#[derive(Debug)]
+pub struct RetransmissionHistory {
+    packets: Vec<Option<Packet>>,
+    stats: RetransmissionStats,
+}
+
+impl Default for RetransmissionHistory {
+    fn default() -> Self {
+        Self {
+            packets: vec![None; RETRANSMISSION_HISTORY_SIZE],
+            stats: RetransmissionStats::default(),
+        }
+    }
+}
+
+impl RetransmissionHistory {
+    /// Store a sent packet, overwriting the packet that was sent `RETRANSMISSION_HISTORY_SIZE` packets ago.
+    pub fn insert(&mut self, packet: &Packet) {
+        let slot = packet.header.sequence_number as usize % RETRANSMISSION_HISTORY_SIZE;
+        self.packets[slot] = Some(packet.clone());
+    }
+
+    /// Look up the packets that were reported lost by a single NACK, and update the stats.
+    pub fn on_nack(&mut self, sequence_numbers: &[u16]) -> Vec<Packet> {
+        self.stats.nacks_received += 1;
+        self.stats.packets_requested += sequence_numbers.len() as u64;
+
+        let mut packets = vec![];
+        for &sequence_number in sequence_numbers {
+            match &self.packets[sequence_number as usize % RETRANSMISSION_HISTORY_SIZE] {
+                Some(packet) if packet.header.sequence_number == sequence_number => packets.push(packet.clone()),
+                _ => self.stats.packets_unavailable += 1,
+            }
+        }
+        self.stats.packets_retransmitted += packets.len() as u64;
+        packets
+    }
+
+    pub fn stats(&self) -> RetransmissionStats {
+        self.stats
+    }
+}
@@ -1,17 +1,53 @@
 use async_trait::async_trait;
 use tracing::instrument;
 use webrtc::error::flatten_errs;
+use webrtc::rtcp::packet::Packet as RtcpPacket;
+use webrtc::rtcp::transport_feedbacks::transport_layer_nack::TransportLayerNack;
+use webrtc::rtp::packet::Packet;
 use webrtc::rtp::packetizer::{Packetizer, new_packetizer};
 use webrtc::rtp::sequence::{new_random_sequencer, Sequencer};
 use webrtc::rtp_transceiver::rtp_codec::{RTCRtpCodecCapability, RTCRtpCodecParameters, RTPCodecType};
-use webrtc::track::track_local::{TrackLocal, TrackLocalContext};
+use webrtc::track::track_local::{TrackLocal, TrackLocalContext, TrackLocalWriter};
 use webrtc::track::track_local::track_local_static_rtp::TrackLocalStaticRTP;
-use std::sync::Mutex;
+use std::sync::{Arc, Mutex};
 // use std::time::Instant;
 
 use crate::types::FrameTaskData;
 
 use super::pointcloud_payloader::PointCloudPayloader;
+use super::retransmission::{RetransmissionHistory, RetransmissionStats};
+
+/// A quality layer of a track, the peers select the layer they receive by its RID.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct QualityLayer {
+    pub rid: String,
+    /// Maximum number of points in the frames of this layer
+    pub max_number_of_points: u64,
+}
+
+impl QualityLayer {
+    pub fn new(rid: &str, max_number_of_points: u64) -> Self {
+        Self {
+            rid: rid.to_owned(),
+            max_number_of_points,
+        }
+    }
+}
+
+/// A peer that the track is bound to.
+/// When the track has quality layers, the packets are written to each peer separately,
+/// so every peer gets contiguous sequence numbers for the layer it selected.
+#[derive(Debug)]
+struct LayerBinding {
+    id: String,
+    ssrc: u32,
+    payload_type: u8,
+    write_stream: Arc<dyn TrackLocalWriter + Send + Sync>,
+    sequencer: Box<dyn Sequencer + Send + Sync>,
+    retransmission: RetransmissionHistory,
+    /// Index of the selected quality layer
+    layer: usize,
+}
 
 #[derive(Debug)]
 struct TrackLocalPointCloudRTPInternal {
@@ -20,11 +56,16 @@ struct TrackLocalPointCloudRTPInternal {
     payloader: PointCloudPayloader,
     clock_rate: f64,
     fps: f64,
+    layers: Vec<QualityLayer>,
+    bindings: Vec<LayerBinding>,
+    /// Every peer gets its own sequence numbers, even without quality layers
+    per_peer: bool,
 }
 
 pub struct TrackLocalPointCloudRTP {
     rtp_track: TrackLocalStaticRTP,
     internal: Mutex<TrackLocalPointCloudRTPInternal>,
+    retransmission: Mutex<RetransmissionHistory>,
 }
 
 impl TrackLocalPointCloudRTP {
@@ -40,11 +81,15 @@ impl TrackLocalPointCloudRTP {
             payloader: PointCloudPayloader::new(),
             clock_rate: codec.clock_rate as f64, // This
             fps: fps as f64,
+            layers: vec![],
+            bindings: vec![],
+            per_peer: false,
         };
 
         Self {
             rtp_track,
             internal: Mutex::new(internal),
+            retransmission: Mutex::new(RetransmissionHistory::default()),
         }
     }
 
@@ -65,11 +110,15 @@ impl TrackLocalPointCloudRTP {
             payloader: PointCloudPayloader::new(),
             clock_rate: codec.clock_rate as f64,
             fps: fps as f64,
+            layers: vec![],
+            bindings: vec![],
+            per_peer: false,
         };
 
         Self {
             rtp_track: track_local_static,
             internal: Mutex::new(internal),
+            retransmission: Mutex::new(RetransmissionHistory::default()),
         }
     }
 
@@ -79,61 +128,33 @@ impl TrackLocalPointCloudRTP {
     }
 
     /// write_frame writes a frame to the track
-    /// 
+    /// When the track has quality layers, the frame is sent to every peer, regardless of the layer it selected.
     #[instrument(skip_all)]
     pub async fn write_frame(&self, frame: &FrameTaskData) -> Result<(), webrtc::Error> {
-        // 1) Convert your `frame` into a raw “payload” that includes the
-        //    28 bytes of header for the first chunk, etc.
-        //    But we already embed the custom header in the payloader. So here, we only pass the raw data
-        //    to the packetizer. But we must set client_id, tile, etc.
-        // Convert to vec of Bytes
-        let raw_payload = bytes::Bytes::copy_from_slice(&frame.data);   
-
-        // 2) Convert the frame into a vector of packets
-        let packets = {
-            // 2.0) Lock the internal state, otherwise we might corrupt the data when we write multiple frames at the same time
-            let mut internal = self.internal.lock().unwrap();
-
-            // 2.1) Extract payloader into a separate scope to avoid multiple mutable borrows
-            {
-                let mut payloader = internal.payloader.clone();
-                payloader.set_metadata(
-                    frame.sfu_client_id.unwrap_or(0) as u32,
-                    frame.send_time, // Instead of frame.send_time, we should use frame.presentation_time in WebRTC. (Just for metrics)
-                    frame.sfu_tile_index.unwrap_or(0),
-                    0,
-                );
-            }
-
-            // 2.2) “samples” is how many clock ticks we’re generating in one frame
-            let samples = 0; /*{
-                (internal.clock_rate / internal.fps) as u32
-            };*/
-
-            // 2.3) Ensure packetizer is initialized
-            let mut packetizer = if let Some(packetizer) = internal.packetizer.as_mut() {
-                packetizer.clone()
-            } else {
-                return Err(webrtc::Error::new("Packetizer is not initialized. Call `bind()` first.".to_owned()));
-            };
-            
-    
-            // 2.4) Packetize
-            packetizer.packetize(&raw_payload, samples)
-        };
+        let packets = self.packetize(frame, 0)?;
 
-        // 3) If the packetizer returns an error, return it
-        let packets = match packets {
-            Ok(p) => p,
-            Err(e) => return Err(webrtc::Error::from(e)),
+        let per_peer = {
+            let internal = self.internal.lock().unwrap();
+            !internal.layers.is_empty() || internal.per_peer
         };
+        if per_peer {
+            return self.write_to_bindings(&packets, |_| true).await;
+        }
 
         // Start a timer to measure the time taken for packetization
         //let start_time = Instant::now();
 
         // info!("Packetized {} packets", packets.len());
 
-        // 4) Write each packet
+        // Keep the packets, so they can be sent again when they are reported lost
+        {
+            let mut retransmission = self.retransmission.lock().unwrap();
+            for p in &packets {
+                retransmission.insert(p);
+            }
+        }
+
+        // Write each packet
         let mut write_errs = vec![];
         for p in packets {
             if let Err(err) = self
@@ -151,11 +172,218 @@ impl TrackLocalPointCloudRTP {
         flatten_errs(write_errs)
     }
 
+    /// write_layer_frame writes a frame of the quality layer with the given RID,
+    /// only the peers that selected this layer receive it.
+    #[instrument(skip_all)]
+    pub async fn write_layer_frame(&self, rid: &str, frame: &FrameTaskData) -> Result<(), webrtc::Error> {
+        let layer = self.layer_index(rid)?;
+        let packets = self.packetize(frame, layer as u32)?;
+        self.write_to_bindings(&packets, |binding| binding.layer == layer).await
+    }
+
+    /// write_frame_to_peers writes a frame to the peers with the given SSRCs only, regardless of the layer they selected.
+    /// This lets the sender decide per peer which frames and which quality it receives, see `set_per_peer_delivery`.
+    #[instrument(skip_all)]
+    pub async fn write_frame_to_peers(&self, frame: &FrameTaskData, quality_nr: u32, ssrcs: &[u32]) -> Result<(), webrtc::Error> {
+        let packets = self.packetize(frame, quality_nr)?;
+        self.write_to_bindings(&packets, |binding| ssrcs.contains(&binding.ssrc)).await
+    }
+
+    /// Give every peer its own sequence numbers, so frames can be skipped for one peer without the others noticing.
+    /// Must be enabled before `write_frame_to_peers` is used, so the retransmissions are looked up per peer.
+    #[instrument(skip_all)]
+    pub fn set_per_peer_delivery(&self, per_peer: bool) {
+        self.internal.lock().unwrap().per_peer = per_peer;
+    }
+
+    /// Convert a frame into RTP packets, the quality_nr is stored in the header of every packet.
+    fn packetize(&self, frame: &FrameTaskData, quality_nr: u32) -> Result<Vec<Packet>, webrtc::Error> {
+        // 1) Convert your `frame` into a raw “payload” that includes the
+        //    28 bytes of header for the first chunk, etc.
+        //    But we already embed the custom header in the payloader. So here, we only pass the raw data
+        //    to the packetizer. But we must set client_id, tile, etc.
+        // Convert to vec of Bytes
+        let raw_payload = bytes::Bytes::copy_from_slice(&frame.data);   
+
+        // 2) Convert the frame into a vector of packets
+        // 2.0) Lock the internal state, otherwise we might corrupt the data when we write multiple frames at the same time
+        let mut internal = self.internal.lock().unwrap();
+
+        // 2.1) Extract payloader into a separate scope to avoid multiple mutable borrows
+        {
+            let mut payloader = internal.payloader.clone();
+            payloader.set_metadata(
+                frame.sfu_client_id.unwrap_or(0) as u32,
+                frame.send_time, // Instead of frame.send_time, we should use frame.presentation_time in WebRTC. (Just for metrics)
+                frame.sfu_tile_index.unwrap_or(0),
+                quality_nr,
+            );
+        }
+
+        // 2.2) “samples” is how many clock ticks we’re generating in one frame
+        let samples = 0; /*{
+            (internal.clock_rate / internal.fps) as u32
+        };*/
+
+        // 2.3) Ensure packetizer is initialized
+        let mut packetizer = if let Some(packetizer) = internal.packetizer.as_mut() {
+            packetizer.clone()
+        } else {
+            return Err(webrtc::Error::new("Packetizer is not initialized. Call `bind()` first.".to_owned()));
+        };
+
+        // 2.4) Packetize
+        packetizer.packetize(&raw_payload, samples).map_err(webrtc::Error::from)
+    }
+
+    /// Write the packets to the peers that match the filter.
+    /// Every peer gets its own SSRC, payload type and sequence numbers, just like `TrackLocalStaticRTP` does.
+    async fn write_to_bindings<F>(&self, packets: &[Packet], filter: F) -> Result<(), webrtc::Error>
+    where
+        F: Fn(&LayerBinding) -> bool,
+    {
+        let writes = {
+            let mut internal = self.internal.lock().unwrap();
+            internal.bindings.iter_mut()
+                .filter(|binding| filter(binding))
+                .map(|binding| {
+                    let binding_packets = packets.iter().map(|p| {
+                        let mut p = p.clone();
+                        p.header.ssrc = binding.ssrc;
+                        p.header.payload_type = binding.payload_type;
+                        p.header.sequence_number = binding.sequencer.next_sequence_number();
+                        binding.retransmission.insert(&p);
+                        p
+                    }).collect::<Vec<_>>();
+                    (binding.write_stream.clone(), binding_packets)
+                })
+                .collect::<Vec<_>>()
+        };
+
+        let mut write_errs = vec![];
+        for (write_stream, packets) in writes {
+            for p in packets {
+                if let Err(err) = write_stream.write_rtp(&p).await {
+                    write_errs.push(err);
+                }
+            }
+        }
+        flatten_errs(write_errs)
+    }
+
+    /// Publish the frames in multiple quality layers, ordered from the highest to the lowest quality.
+    /// The peers receive the first layer until they select another one, an empty list disables the layers.
+    #[instrument(skip_all)]
+    pub fn set_quality_layers(&self, layers: Vec<QualityLayer>) {
+        let mut internal = self.internal.lock().unwrap();
+        let layer_count = layers.len();
+        internal.layers = layers;
+        for binding in internal.bindings.iter_mut() {
+            if binding.layer >= layer_count {
+                binding.layer = 0;
+            }
+        }
+    }
+
+    pub fn quality_layers(&self) -> Vec<QualityLayer> {
+        self.internal.lock().unwrap().layers.clone()
+    }
+
+    /// Switch the peer that is bound with the given SSRC to the quality layer with the given RID, without renegotiation.
+    #[instrument(skip_all)]
+    pub fn select_quality_layer(&self, ssrc: u32, rid: &str) -> Result<(), webrtc::Error> {
+        let layer = self.layer_index(rid)?;
+        let mut internal = self.internal.lock().unwrap();
+        let binding = internal.bindings.iter_mut()
+            .find(|binding| binding.ssrc == ssrc)
+            .ok_or_else(|| webrtc::Error::new(format!("Track is not bound with SSRC {ssrc}")))?;
+        binding.layer = layer;
+        Ok(())
+    }
+
+    /// The quality layer that the peer with the given SSRC receives.
+    pub fn selected_quality_layer(&self, ssrc: u32) -> Option<QualityLayer> {
+        let internal = self.internal.lock().unwrap();
+        let binding = internal.bindings.iter().find(|binding| binding.ssrc == ssrc)?;
+        internal.layers.get(binding.layer).cloned()
+    }
+
+    fn layer_index(&self, rid: &str) -> Result<usize, webrtc::Error> {
+        self.internal.lock().unwrap().layers.iter()
+            .position(|layer| layer.rid == rid)
+            .ok_or_else(|| webrtc::Error::new(format!("Unknown quality layer: {rid}")))
+    }
+
     #[instrument(skip_all)]
     pub fn set_fps(&self, fps: u32) {
         let mut internal = self.internal.lock().unwrap();
         internal.fps = fps as f64;
     }
+
+    /// Send `percentage` RaptorQ repair packets per 100 packets of a frame, 0 disables FEC.
+    #[instrument(skip_all)]
+    pub fn set_fec_repair_percentage(&self, percentage: u32) {
+        let mut internal = self.internal.lock().unwrap();
+        internal.payloader.set_fec_repair_percentage(percentage);
+    }
+
+    /// Handle the RTCP packets that were read from the RTP sender of this track,
+    /// the packets that are reported lost by NACK feedback are sent again.
+    /// The retransmissions are only written to the peer that reported the loss, found by its SSRC.
+    #[instrument(skip_all)]
+    pub async fn handle_rtcp(&self, rtcp_packets: &[Box<dyn RtcpPacket + Send + Sync>]) -> Result<(), webrtc::Error> {
+        let binding_packets = {
+            let mut internal = self.internal.lock().unwrap();
+            let layered = !internal.layers.is_empty() || internal.per_peer;
+            let mut retransmission = self.retransmission.lock().unwrap();
+            let mut binding_packets = vec![];
+            for rtcp_packet in rtcp_packets {
+                let Some(nack) = rtcp_packet.as_any().downcast_ref::<TransportLayerNack>() else {
+                    continue;
+                };
+                let Some(binding) = internal.bindings.iter_mut().find(|binding| binding.ssrc == nack.media_ssrc) else {
+                    continue;
+                };
+                for nack_pair in &nack.nacks {
+                    let lost = if layered {
+                        // With quality layers or per peer delivery, every peer has its own sequence numbers
+                        binding.retransmission.on_nack(&nack_pair.packet_list())
+                    } else {
+                        // The shared packets carry the sequence numbers of all peers, but the SSRC and payload type of none
+                        retransmission.on_nack(&nack_pair.packet_list())
+                            .into_iter()
+                            .map(|mut p| {
+                                p.header.ssrc = binding.ssrc;
+                                p.header.payload_type = binding.payload_type;
+                                p
+                            })
+                            .collect()
+                    };
+                    binding_packets.push((binding.write_stream.clone(), lost));
+                }
+            }
+            binding_packets
+        };
+
+        let mut write_errs = vec![];
+        for (write_stream, packets) in binding_packets {
+            for p in packets {
+                if let Err(err) = write_stream.write_rtp(&p).await {
+                    write_errs.push(err);
+                }
+            }
+        }
+        flatten_errs(write_errs)
+    }
+
+    /// Counters of the retransmissions of this track.
+    pub fn retransmission_stats(&self) -> RetransmissionStats {
+        let mut stats = self.retransmission.lock().unwrap().stats();
+        for binding in &self.internal.lock().unwrap().bindings {
+            stats += binding.retransmission.stats();
+        }
+        stats
+    }
 }
 
 // Implement the required trait for track binding/unbinding
@@ -166,6 +394,19 @@ impl TrackLocal for TrackLocalPointCloudRTP {
         let codec = self.rtp_track.bind(ctx).await?;
         let mut internal = self.internal.lock().unwrap();
 
+        // Keep the binding, so the quality layers can be written to this peer only
+        if let Some(write_stream) = ctx.write_stream() {
+            internal.bindings.push(LayerBinding {
+                id: ctx.id(),
+                ssrc: ctx.ssrc(),
+                payload_type: codec.payload_type,
+                write_stream,
+                sequencer: Box::new(new_random_sequencer()),
+                retransmission: RetransmissionHistory::default(),
+                layer: 0,
+            });
+        }
+
         // If packetizer is already initialized, return codec
         if internal.packetizer.is_some() {
             return Ok(codec);
@@ -190,6 +431,7 @@ impl TrackLocal for TrackLocalPointCloudRTP {
 
     #[instrument(skip_all)]
     async fn unbind(&self, ctx: &TrackLocalContext) -> Result<(), webrtc::Error> {
+        self.internal.lock().unwrap().bindings.retain(|binding| binding.id != ctx.id());
         self.rtp_track.unbind(ctx).await
     }
 
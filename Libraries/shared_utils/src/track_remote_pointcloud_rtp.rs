@@ -4,7 +4,8 @@ use webrtc::error::Error as RtcError;
 
 use tokio::sync::mpsc;
 use tokio::task::JoinHandle;
-use std::{sync::Arc, time::Instant};
+use std::{collections::VecDeque, sync::{Arc, Mutex}, time::{Duration, Instant}};
+use crate::fec::{FecDecoder, FEC_PACKET_OFFSET};
 use crate::types::FrameTaskData;
 use tracing::error;
 use dashmap::DashMap;
@@ -18,6 +19,7 @@ use dashmap::DashMap;
 ///   [20..24] tile_nr
 ///
 /// Then chunk_len bytes of data.
+/// If seq_offset is `FEC_PACKET_OFFSET`, the data is a RaptorQ packet instead of a chunk of the frame.
 #[derive(Clone, Debug, Default)]
 pub struct DepacketHeader {
     pub client_id: u32,
@@ -61,6 +63,7 @@ pub struct FrameReassembly {
     pub received_len: u32,
     pub buffer: Vec<u8>,
     pub received_mask: BitVec,
+    pub fec_decoder: Option<FecDecoder>,
 }
 
 impl FrameReassembly {
@@ -71,9 +74,36 @@ impl FrameReassembly {
             received_len: 0,
             buffer: vec![0; total_len as usize],
             received_mask: bitvec![0; total_len as usize],
+            fec_decoder: None,
         }
     }
 
+    /// Reassemble a frame that is sent as FEC packets, the buffer is only filled once the frame is decoded.
+    pub fn new_fec(total_len: u32) -> Self {
+        Self {
+            first_chunk_time: Instant::now(),
+            total_len,
+            received_len: 0,
+            buffer: vec![],
+            received_mask: BitVec::new(),
+            fec_decoder: Some(FecDecoder::default()),
+        }
+    }
+
+    /// Insert a FEC packet.
+    /// Return true if the frame could be decoded.
+    pub fn insert_fec_packet(&mut self, data: &[u8]) -> bool {
+        let Some(fec_decoder) = self.fec_decoder.as_mut() else {
+            return false;
+        };
+        if let Some(decoded) = fec_decoder.insert_packet(data) {
+            self.buffer = decoded;
+            self.received_len = self.total_len;
+            self.fec_decoder = None;
+        }
+        self.received_len >= self.total_len
+    }
+
     /// Insert a chunk into the buffer at the given offset.
     /// Return true if the frame is complete.
     pub fn insert_chunk(&mut self, offset: u32, data: &[u8]) -> bool {
@@ -97,6 +127,87 @@ impl FrameReassembly {
     }
 }
 
+/// Number of recently completed frames that are remembered to ignore their late packets
+const COMPLETED_FRAMES_CAPACITY: usize = 64;
+
+/// Frames that are still incomplete after this long are dropped
+pub const STALE_FRAME_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// (client_id, frame_nr, tile_nr, quality_nr)
+type FrameKey = (u32, u64, u32, u32);
+
+/// Reassembles frames from the packets of the `PointCloudPayloader`,
+/// shared by the RTP track and the DataChannel transport.
+#[derive(Debug, Default)]
+pub struct FrameReassembler {
+    frames: DashMap<FrameKey, FrameReassembly>,
+    // With FEC, packets keep arriving after a frame has been decoded, these should not start a new frame
+    completed_frames: Mutex<VecDeque<FrameKey>>,
+}
+
+impl FrameReassembler {
+    /// Insert a packet, returns the frame once all of its chunks arrived.
+    pub fn insert_packet(&self, packet: &[u8]) -> Option<FrameTaskData> {
+        let Some((hdr, chunk)) = DepacketHeader::parse(packet) else {
+            error!("Failed to parse custom header from packet with length = {}", packet.len());
+            return None;
+        };
+
+        let key = (hdr.client_id, hdr.frame_nr, hdr.tile_nr, hdr.quality_nr);
+        if self.completed_frames.lock().unwrap().contains(&key) {
+            return None;
+        }
+
+        let is_fec_packet = hdr.offset == FEC_PACKET_OFFSET;
+        let full_data = {
+            // Lock the entry for writing
+            let mut entry = self.frames.entry(key).or_insert_with(|| {
+                if is_fec_packet {
+                    FrameReassembly::new_fec(hdr.total_len)
+                } else {
+                    FrameReassembly::new(hdr.total_len)
+                }
+            });
+            let complete = if is_fec_packet {
+                entry.insert_fec_packet(chunk)
+            } else {
+                entry.insert_chunk(hdr.offset, chunk)
+            };
+            if !complete {
+                return None;
+            }
+            // let elapsed_reception_time = entry.first_chunk_time.elapsed();
+            // info!("Receiving all packets for this frame took: {:?} ms", elapsed_reception_time.as_millis());
+
+            // We have a full frame
+            std::mem::take(&mut entry.buffer)
+        };
+
+        self.frames.remove(&key);
+        {
+            let mut completed_frames = self.completed_frames.lock().unwrap();
+            if completed_frames.len() == COMPLETED_FRAMES_CAPACITY {
+                completed_frames.pop_front();
+            }
+            completed_frames.push_back(key);
+        }
+
+        Some(FrameTaskData {
+            presentation_time: hdr.frame_nr, // Normally we store the presentation time in the frame_nr field
+            send_time: hdr.frame_nr, // However, we actually store the send time in the frame_nr field, for metrics purposes
+            data: full_data,
+            sfu_client_id: Some(hdr.client_id as u64),
+            sfu_frame_len: Some(hdr.total_len),
+            sfu_tile_index: Some(hdr.tile_nr),
+        })
+    }
+
+    /// Drop the frames of which the first chunk arrived more than `max_age` ago, their missing chunks will not arrive anymore.
+    pub fn remove_stale_frames(&self, max_age: Duration) {
+        self.frames.retain(|_, frame| frame.first_chunk_time.elapsed() <= max_age);
+    }
+}
+
 pub struct TrackRemotePointCloudRTP {
     remote_track: Arc<TrackRemote>,
     on_frame: Arc<dyn Fn(FrameTaskData) + Send + Sync>,
@@ -122,7 +233,7 @@ impl TrackRemotePointCloudRTP {
     pub fn start(&mut self) {
         // Spawn a background task that reads from the remote track
         let remote_track = self.remote_track.clone();
-        let reassembly_map = Arc::new(DashMap::new());
+        let reassembler = Arc::new(FrameReassembler::default());
         let on_frame_cb = self.on_frame.clone();
         let (tx, mut rx) = mpsc::channel::<Vec<u8>>(200);
 
@@ -149,64 +260,21 @@ impl TrackRemotePointCloudRTP {
         });
 
 
-        let reassembly_map_clone = reassembly_map.clone();
+        let reassembler_clone = reassembler.clone();
         let parse_handle = tokio::spawn(async move {
-            let reassembly_map = reassembly_map_clone;
             while let Some(rtp_packet) = rx.recv().await {
-                let rtp_packet: Vec<u8> = rtp_packet; // Ensure rtp_packet is owned
-                // parse the payload
-                if let Some((hdr, chunk)) = DepacketHeader::parse(&rtp_packet) {
-                    let key = (hdr.client_id, hdr.frame_nr, hdr.tile_nr, hdr.quality_nr);
-                    let mut can_remove = false;
-                    {
-                        // Lock the map for writing
-                        let mut entry = reassembly_map.entry(key).or_insert_with(|| FrameReassembly::new(hdr.total_len));
-                        let complete = entry.insert_chunk(hdr.offset, chunk);
-                        if complete {
-                            can_remove = true;
-                            // let elapsed_reception_time = entry.first_chunk_time.elapsed();
-                        
-                            // We have a full frame
-                            let full_data = std::mem::take(&mut entry.buffer);
-
-                            // Build a FrameTaskData
-                            let ftd = FrameTaskData {
-                                presentation_time: hdr.frame_nr, // Normally we store the presentation time in the frame_nr field
-                                send_time: hdr.frame_nr, // However, we actually store the send time in the frame_nr field, for metrics purposes
-                                data: full_data,
-                                sfu_client_id: Some(hdr.client_id as u64),
-                                sfu_frame_len: Some(hdr.total_len),
-                                sfu_tile_index: Some(hdr.tile_nr),
-                            };
-
-                            // info!("Receiving all packets for this frame took: {:?} ms", elapsed_reception_time.as_millis());
-                            
-                            (on_frame_cb)(ftd);
-                        }
-                    }
-                    if can_remove {
-                        // Remove the entry from the map
-                        reassembly_map.remove(&key);
-                    }
-                } else {
-                    // parse failed
-                    error!("Failed to parse custom header from RTP packet with length = {}", rtp_packet.len());
+                if let Some(frame) = reassembler_clone.insert_packet(&rtp_packet) {
+                    (on_frame_cb)(frame);
                 }
             }
         });
 
-        // A seperate task that cleans up the reassembly_map
-        // periodically, removing entries that are too old
+        // A seperate task that periodically removes the frames that are too old
         let cleanup_handle = tokio::spawn(async move {
             loop {
                 // Sleep for a while before checking the map
-                tokio::time::sleep(tokio::time::Duration::from_secs(60)).await;
-                let now = Instant::now();
-                for entry in reassembly_map.iter() {
-                    if now.duration_since(entry.value().first_chunk_time).as_secs() > 60 {
-                        reassembly_map.remove(entry.key());
-                    }
-                }
+                tokio::time::sleep(STALE_FRAME_TIMEOUT).await;
+                reassembler.remove_stale_frames(STALE_FRAME_TIMEOUT);
             }
         });
 
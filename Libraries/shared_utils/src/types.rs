@@ -33,6 +33,8 @@ impl PropertyAccess for Point3D {
             ("red", Property::UChar(v)) => self.r = v,
             ("green", Property::UChar(v)) => self.g = v,
             ("blue", Property::UChar(v)) => self.b = v,
+            // Stored in `PointAttributes` instead
+            ("nx" | "ny" | "nz" | "intensity", _) => {},
             // Possibly handle other property types or names, e.g. "Property::Float"
             (k, _) => warn!("Ignoring unexpected key or property type: {}", k),
         }
@@ -77,9 +79,153 @@ impl PartialOrd for FrameTaskData {
     }
 }
 
+/// Version of the point cloud schema, increased whenever attributes are added to `PointAttributes`.
+/// Version 1 only supported the xyz + rgb of `Point3D`, version 2 added `PointAttributes`.
+pub const POINT_CLOUD_SCHEMA_VERSION: u32 = 2;
+
+/// A custom attribute of the points, e.g. a `reflectance` (1 component) or a `velocity` (3 components).
+#[derive(Clone, Debug, Deserialize, Serialize, EncodeBitcode, DecodeBitcode, PartialEq)]
+pub struct CustomAttribute {
+    pub name: String,
+    pub components: u32,
+    /// `components` values per point, in the order of the points
+    pub values: Vec<f32>,
+}
+
+/// Optional attributes of the points on top of the xyz + rgb of `Point3D`.
+/// Every attribute holds the values of all points, in the order of `PointCloudData::points`.
+#[derive(Clone, Debug, Deserialize, Serialize, EncodeBitcode, DecodeBitcode, PartialEq, Default)]
+pub struct PointAttributes {
+    pub normals: Option<Vec<[f32; 3]>>,
+    pub intensities: Option<Vec<f32>>,
+    pub custom: Vec<CustomAttribute>,
+}
+
+impl PointAttributes {
+    pub fn is_empty(&self) -> bool {
+        self.normals.is_none() && self.intensities.is_none() && self.custom.is_empty()
+    }
+
+    /// Check that every attribute holds a value for each of the `point_count` points.
+    pub fn validate(&self, point_count: usize) -> Result<(), String> {
+        if let Some(normals) = &self.normals {
+            if normals.len() != point_count {
+                return Err(format!("Expected {} normals, got {}", point_count, normals.len()));
+            }
+        }
+        if let Some(intensities) = &self.intensities {
+            if intensities.len() != point_count {
+                return Err(format!("Expected {} intensities, got {}", point_count, intensities.len()));
+            }
+        }
+        for attribute in &self.custom {
+            if attribute.values.len() != point_count * attribute.components as usize {
+                return Err(format!(
+                    "Expected {} values for attribute {}, got {}",
+                    point_count * attribute.components as usize, attribute.name, attribute.values.len()
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    /// Only keep the attributes of the points at `indices`, in that order.
+    pub fn select(&self, indices: &[usize]) -> Self {
+        Self {
+            normals: self.normals.as_ref().map(|normals| indices.iter().map(|&i| normals[i]).collect()),
+            intensities: self.intensities.as_ref().map(|intensities| indices.iter().map(|&i| intensities[i]).collect()),
+            custom: self
+                .custom
+                .iter()
+                .map(|attribute| {
+                    let components = attribute.components as usize;
+                    CustomAttribute {
+                        name: attribute.name.clone(),
+                        components: attribute.components,
+                        values: indices
+                            .iter()
+                            .flat_map(|&i| attribute.values[i * components..(i + 1) * components].iter().copied())
+                            .collect(),
+                    }
+                })
+                .collect(),
+        }
+    }
+
+    /// Names of the scalar properties that hold the attributes, e.g. the properties of a PLY file.
+    /// Normals become `nx`, `ny` and `nz`, custom attributes with multiple components become `<name>_<component>`.
+    pub fn scalar_property_names(&self) -> Vec<String> {
+        let mut names = vec![];
+        if self.normals.is_some() {
+            names.extend(["nx", "ny", "nz"].map(String::from));
+        }
+        if self.intensities.is_some() {
+            names.push("intensity".to_string());
+        }
+        for attribute in &self.custom {
+            if attribute.components == 1 {
+                names.push(attribute.name.clone());
+            } else {
+                names.extend((0..attribute.components).map(|component| format!("{}_{}", attribute.name, component)));
+            }
+        }
+        names
+    }
+
+    /// Values of the scalar properties of the point at `index`, in the order of `scalar_property_names`.
+    pub fn scalar_values(&self, index: usize) -> Vec<f32> {
+        let mut values = vec![];
+        if let Some(normals) = &self.normals {
+            values.extend(normals[index]);
+        }
+        if let Some(intensities) = &self.intensities {
+            values.push(intensities[index]);
+        }
+        for attribute in &self.custom {
+            let components = attribute.components as usize;
+            values.extend(&attribute.values[index * components..(index + 1) * components]);
+        }
+        values
+    }
+
+    /// The inverse of `scalar_property_names` and `scalar_values`, from the values of every property for all points.
+    /// Properties that are not a normal or intensity become custom attributes with a single component.
+    pub fn from_scalar_properties(mut properties: Vec<(String, Vec<f32>)>) -> Self {
+        let mut take = |name: &str| {
+            let position = properties.iter().position(|(property, _)| property == name)?;
+            Some(properties.remove(position).1)
+        };
+
+        let mut attributes = PointAttributes::default();
+        match (take("nx"), take("ny"), take("nz")) {
+            (Some(nx), Some(ny), Some(nz)) => {
+                attributes.normals = Some(nx.into_iter().zip(ny).zip(nz).map(|((x, y), z)| [x, y, z]).collect());
+            }
+            (nx, ny, nz) => {
+                // An incomplete normal is kept as custom attributes
+                for (name, values) in [("nx", nx), ("ny", ny), ("nz", nz)] {
+                    if let Some(values) = values {
+                        attributes.custom.push(CustomAttribute { name: name.to_string(), components: 1, values });
+                    }
+                }
+            }
+        }
+        attributes.intensities = take("intensity");
+        attributes.custom.extend(
+            properties
+                .into_iter()
+                .map(|(name, values)| CustomAttribute { name, components: 1, values }),
+        );
+        attributes
+    }
+}
+
 #[derive(Clone, Debug, Deserialize, Serialize)]
 pub struct PointCloudData {
     pub points: Vec<Point3D>,
+    /// Optional attributes on top of xyz + rgb, dropped by the encoders that do not support them
+    #[serde(default)]
+    pub attributes: PointAttributes,
     pub creation_time: u64,
     pub presentation_time: u64,
     pub error_count: u64,
@@ -97,9 +243,23 @@ impl Default for PointCloudData {
 
         Self {
             points: Vec::new(),
+            attributes: PointAttributes::default(),
             creation_time: current_time,
             presentation_time: current_time + presentation_tim_offset,
             error_count: 0,
         }
     }
-}
\ No newline at end of file
+}
+
+impl PointCloudData {
+    /// A copy that only holds the points at `indices` together with their attributes, e.g. after sampling.
+    pub fn select(&self, indices: &[usize]) -> Self {
+        Self {
+            points: indices.iter().map(|&i| self.points[i].clone()).collect(),
+            attributes: self.attributes.select(indices),
+            creation_time: self.creation_time,
+            presentation_time: self.presentation_time,
+            error_count: self.error_count,
+        }
+    }
+}
@@ -0,0 +1,74 @@
+use crate::error::{XrError, XrResult};
+use crate::types::FrameTaskData;
+
+/// Magic bytes at the start of a versioned frame.
+/// Frames without them are decoded as the unversioned bitcode encoding of older versions.
+pub const FRAME_MAGIC: [u8; 4] = *b"MPXF";
+
+/// Version of the envelope, increased when the layout of `FrameTaskData` changes.
+pub const FRAME_VERSION: u16 = 1;
+
+/// Capability flags that tell the receiver which optional parts of `FrameTaskData` a frame uses.
+pub mod capabilities {
+    /// The frame carries the SFU client id, frame length or tile index.
+    pub const SFU_METADATA: u32 = 1 << 0;
+
+    /// All capabilities this version can decode.
+    pub const SUPPORTED: u32 = SFU_METADATA;
+}
+
+/// Magic (4 bytes), version (u16 LE) and capability flags (u32 LE).
+const HEADER_SIZE: usize = 10;
+
+/// The header of a versioned frame.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FrameHeader {
+    pub version: u16,
+    pub capabilities: u32,
+}
+
+/// Encode a frame into the versioned wire format: a `FrameHeader` followed by the bitcode encoded frame.
+pub fn encode_frame(frame: &FrameTaskData) -> Vec<u8> {
+    let mut frame_capabilities = 0;
+    if frame.sfu_client_id.is_some() || frame.sfu_frame_len.is_some() || frame.sfu_tile_index.is_some() {
+        frame_capabilities |= capabilities::SFU_METADATA;
+    }
+
+    let payload = bitcode::encode(frame);
+    let mut encoded = Vec::with_capacity(HEADER_SIZE + payload.len());
+    encoded.extend_from_slice(&FRAME_MAGIC);
+    encoded.extend_from_slice(&FRAME_VERSION.to_le_bytes());
+    encoded.extend_from_slice(&frame_capabilities.to_le_bytes());
+    encoded.extend_from_slice(&payload);
+    encoded
+}
+
+/// Read the header of a versioned frame, returns `None` for the unversioned frames of older versions.
+pub fn decode_frame_header(bytes: &[u8]) -> Option<FrameHeader> {
+    if bytes.len() < HEADER_SIZE || bytes[0..4] != FRAME_MAGIC {
+        return None;
+    }
+    Some(FrameHeader {
+        version: u16::from_le_bytes([bytes[4], bytes[5]]),
+        capabilities: u32::from_le_bytes([bytes[6], bytes[7], bytes[8], bytes[9]]),
+    })
+}
+
+/// Decode a frame that was encoded by `encode_frame`, or by `bitcode::encode` in older versions.
+/// Frames of a newer version, or that use capabilities this version does not know, are rejected with an error
+/// instead of being decoded into garbage.
+pub fn decode_frame(bytes: &[u8]) -> XrResult<FrameTaskData> {
+    let Some(header) = decode_frame_header(bytes) else {
+        return bitcode::decode::<FrameTaskData>(bytes).map_err(|e| XrError::Codec(format!("Failed to decode unversioned frame: {}", e)));
+    };
+
+    if header.version > FRAME_VERSION {
+        return Err(XrError::Codec(format!("Unsupported frame version {}, expected at most {}", header.version, FRAME_VERSION)));
+    }
+    let unsupported = header.capabilities & !capabilities::SUPPORTED;
+    if unsupported != 0 {
+        return Err(XrError::Codec(format!("Unsupported frame capabilities {:#x}", unsupported)));
+    }
+
+    bitcode::decode::<FrameTaskData>(&bytes[HEADER_SIZE..]).map_err(|e| XrError::Codec(format!("Failed to decode frame version {}: {}", header.version, e)))
+}
@@ -0,0 +1,7 @@
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    // Generates the server of the gRPC egress
+    tonic_build::configure()
+        .build_client(false)
+        .compile_protos(&["proto/frames.proto"], &["proto"])?;
+    Ok(())
+}
@@ -0,0 +1,55 @@
+use tracing::{instrument, warn};
+
+use shared_utils::error::XrResult;
+use shared_utils::types::PointCloudData;
+
+use crate::encoders::bitcode::{BitcodeData, BitcodeDataV2};
+
+/// Decodes a `BC1` frame, the points without attributes.
+#[instrument(skip_all)]
+pub fn decode_bitcode(data: Vec<u8>) -> XrResult<PointCloudData> {
+    // Skip the identifier bytes
+    match bitcode::decode::<BitcodeData>(&data[3..]) {
+        Ok(decoded) => Ok(PointCloudData {
+            points: decoded.points,
+            ..Default::default()
+        }),
+        Err(e) => {
+            warn!("Failed to decode BC1 payload: {}", e);
+            // If there's an error, return 1 error and no points
+            Ok(PointCloudData {
+                error_count: 1,
+                ..Default::default()
+            })
+        }
+    }
+}
+
+/// Decodes a `BC2` frame, the points together with their attributes.
+#[instrument(skip_all)]
+pub fn decode_bitcode_v2(data: Vec<u8>) -> XrResult<PointCloudData> {
+    let decoded = match bitcode::decode::<BitcodeDataV2>(&data[3..]) {
+        Ok(decoded) => decoded,
+        Err(e) => {
+            warn!("Failed to decode BC2 payload: {}", e);
+            return Ok(PointCloudData {
+                error_count: 1,
+                ..Default::default()
+            });
+        }
+    };
+
+    let mut pcd = PointCloudData {
+        points: decoded.points,
+        ..Default::default()
+    };
+    // Attributes that do not match the points are dropped, the points themselves are still usable
+    match decoded.attributes.validate(pcd.points.len()) {
+        Ok(()) => pcd.attributes = decoded.attributes,
+        Err(e) => {
+            warn!("Dropping the attributes of a BC2 frame (schema version {}): {}", decoded.schema_version, e);
+            pcd.error_count += 1;
+        }
+    }
+    Ok(pcd)
+}
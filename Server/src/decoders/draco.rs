@@ -1,14 +1,16 @@
 use draco_wrapper::decode_draco as draco_decode;
 use tracing::{error, instrument, warn};
 
+use shared_utils::error::XrResult;
 use shared_utils::types::{Point3D, PointCloudData};
 
 
 
 #[instrument(skip_all)]
-pub fn decode_draco(data: Vec<u8>) -> Result<PointCloudData, Box<dyn std::error::Error>> {
-    match draco_decode(data) {
-        Ok((vertices, colors)) => {
+pub fn decode_draco(data: Vec<u8>) -> XrResult<PointCloudData> {
+    match draco_decode(&data) {
+        Ok(decoded) => {
+            let (vertices, colors) = (decoded.coords(), decoded.colors());
             // info!("Successfully decoded Draco data");
             // No errors, return 0 errors, along with decoded vertices and colors
 
@@ -0,0 +1,44 @@
+use std::fs;
+
+use tracing::instrument;
+
+use shared_utils::error::{XrError, XrResult};
+use shared_utils::types::PointCloudData;
+
+use crate::encoders::gpcc::{run_tmc3, WorkFiles, GPCC_HEADER_SIZE, GPCC_HEADER_VERSION};
+
+use super::ply::decode_ply;
+
+/// Decodes a G-PCC frame of the G-PCC encoder with the MPEG G-PCC reference decoder (TMC13),
+/// and moves the decoded positions from the quantization grid back to meters.
+#[instrument(skip_all)]
+pub fn decode_gpcc(data: Vec<u8>) -> XrResult<PointCloudData> {
+    if data.len() < GPCC_HEADER_SIZE {
+        return Err(XrError::Codec("Not enough data to contain the G-PCC header".to_string()));
+    }
+    if data[3] != GPCC_HEADER_VERSION {
+        return Err(XrError::Codec(format!("Unsupported G-PCC header version {}", data[3])));
+    }
+    let read_f32 = |offset: usize| f32::from_le_bytes(data[offset..offset + 4].try_into().unwrap());
+    let position_step = read_f32(4);
+    let origin = [read_f32(8), read_f32(12), read_f32(16)];
+
+    let work_files = WorkFiles::new();
+    fs::write(&work_files.bitstream, &data[GPCC_HEADER_SIZE..]).map_err(XrError::codec)?;
+    run_tmc3(&[
+        "--mode=1".to_string(),
+        format!("--compressedStreamPath={}", work_files.bitstream.display()),
+        format!("--reconstructedDataPath={}", work_files.ply.display()),
+        "--outputBinaryPly=1".to_string(),
+        "--convertPlyColourspace=1".to_string(),
+    ])?;
+    let ply = fs::read(&work_files.ply).map_err(XrError::codec)?;
+
+    let mut pcd = decode_ply(ply)?;
+    for point in &mut pcd.points {
+        point.x = point.x * position_step + origin[0];
+        point.y = point.y * position_step + origin[1];
+        point.z = point.z * position_step + origin[2];
+    }
+    Ok(pcd)
+}
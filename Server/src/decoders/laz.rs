@@ -0,0 +1,152 @@
+use laz::LazVlr;
+use tracing::{debug, instrument};
+
+use shared_utils::error::{XrError, XrResult};
+use shared_utils::types::{Point3D, PointAttributes, PointCloudData};
+
+use crate::encoders::laz::{COMPRESSED_POINT_FORMAT_BIT, LASZIP_VLR_RECORD_ID, LASZIP_VLR_USER_ID, VLR_HEADER_SIZE};
+
+/// Smallest header of any LAS version
+const MIN_HEADER_SIZE: usize = 227;
+/// LAS 1.4 headers hold a 64 bit point count, for files with more points than the legacy 32 bit count
+const LAS_14_HEADER_SIZE: usize = 375;
+
+/// Decodes a LAS file, with or without LASzip compressed points (LAZ), e.g. as written by the LASzip encoder or by LiDAR tools.
+/// Every point record format is accepted, the colors are only read from the formats that have them.
+#[instrument(skip_all)]
+pub fn decode_laz(data: Vec<u8>) -> XrResult<PointCloudData> {
+    if data.len() < MIN_HEADER_SIZE || &data[0..4] != b"LASF" {
+        return Err(XrError::Codec("Not a LAS file".to_string()));
+    }
+    let header_size = read_u16(&data, 94)? as usize;
+    let offset_to_points = read_u32(&data, 96)? as usize;
+    let vlr_count = read_u32(&data, 100)?;
+    let point_format_id = data[104];
+    let record_length = read_u16(&data, 105)? as usize;
+    let mut point_count = read_u32(&data, 107)? as u64;
+    if point_count == 0 && header_size >= LAS_14_HEADER_SIZE {
+        point_count = read_u64(&data, 247)?;
+    }
+    let scale = [read_f64(&data, 131)?, read_f64(&data, 139)?, read_f64(&data, 147)?];
+    let offset = [read_f64(&data, 155)?, read_f64(&data, 163)?, read_f64(&data, 171)?];
+
+    let compressed = point_format_id & COMPRESSED_POINT_FORMAT_BIT != 0;
+    // Bit 6 is set by some writers of LAZ files as well
+    let point_format = point_format_id & 0x3F;
+    let records_len = usize::try_from(point_count).ok()
+        .and_then(|point_count| point_count.checked_mul(record_length))
+        .ok_or_else(|| XrError::Codec(format!("Too many points in the LAS file: {point_count}")))?;
+    if offset_to_points > data.len() {
+        return Err(XrError::Codec(format!("The points start at {offset_to_points}, past the end of the data")));
+    }
+
+    let records = if compressed {
+        let vlr = find_laszip_vlr(&data, header_size, vlr_count)?;
+        let mut records = vec![0u8; records_len];
+        laz::decompress_buffer(&data[offset_to_points..], &mut records, vlr)
+            .map_err(|e| XrError::Codec(format!("Failed to decompress the LAZ points: {e}")))?;
+        records
+    } else {
+        data.get(offset_to_points..offset_to_points + records_len)
+            .ok_or_else(|| XrError::Codec(format!("The LAS file announces {point_count} points, but ends before them")))?
+            .to_vec()
+    };
+
+    let color_offset = color_offset(point_format);
+    if color_offset.is_some_and(|color_offset| color_offset + 6 > record_length) || record_length < 14 {
+        return Err(XrError::Codec(format!("Point records of {record_length} bytes are too short for format {point_format}")));
+    }
+    // Colors are 16 bit, but some writers store 8 bit values in them
+    let colors_16_bit = color_offset.is_some_and(|color_offset| {
+        records.chunks_exact(record_length).any(|record| (0..3).any(|channel| u16::from_le_bytes([record[color_offset + channel * 2], record[color_offset + channel * 2 + 1]]) > 255))
+    });
+
+    let mut points = Vec::with_capacity(point_count as usize);
+    let mut intensities = Vec::with_capacity(point_count as usize);
+    for record in records.chunks_exact(record_length) {
+        let coordinate = |axis: usize| {
+            let raw = i32::from_le_bytes(record[axis * 4..axis * 4 + 4].try_into().unwrap());
+            (raw as f64 * scale[axis] + offset[axis]) as f32
+        };
+        let color = |channel: usize| match color_offset {
+            Some(color_offset) => {
+                let value = u16::from_le_bytes([record[color_offset + channel * 2], record[color_offset + channel * 2 + 1]]);
+                if colors_16_bit { (value >> 8) as u8 } else { value as u8 }
+            }
+            None => 255,
+        };
+        points.push(Point3D {
+            x: coordinate(0),
+            y: coordinate(1),
+            z: coordinate(2),
+            r: color(0),
+            g: color(1),
+            b: color(2),
+        });
+        intensities.push(u16::from_le_bytes([record[12], record[13]]) as f32);
+    }
+    debug!("Decoded {} points from a LAS file with point format {}", points.len(), point_format);
+
+    let mut pcd = PointCloudData {
+        points,
+        ..Default::default()
+    };
+    if intensities.iter().any(|intensity| *intensity != 0.0) {
+        pcd.attributes = PointAttributes {
+            intensities: Some(intensities),
+            ..Default::default()
+        };
+    }
+    Ok(pcd)
+}
+
+/// Offset of the red, green and blue channels in the point records of the formats that have colors.
+fn color_offset(point_format: u8) -> Option<usize> {
+    match point_format {
+        2 => Some(20),
+        3 | 5 => Some(28),
+        7 | 8 | 10 => Some(30),
+        _ => None,
+    }
+}
+
+/// Reads the VLR that describes how the points are compressed.
+fn find_laszip_vlr(data: &[u8], header_size: usize, vlr_count: u32) -> XrResult<LazVlr> {
+    let mut position = header_size;
+    for _ in 0..vlr_count {
+        let vlr_header = data.get(position..position + VLR_HEADER_SIZE)
+            .ok_or_else(|| XrError::Codec("The LAS file ends within its VLRs".to_string()))?;
+        let user_id = &vlr_header[2..18];
+        let record_id = u16::from_le_bytes([vlr_header[18], vlr_header[19]]);
+        let record_len = u16::from_le_bytes([vlr_header[20], vlr_header[21]]) as usize;
+        let record = data.get(position + VLR_HEADER_SIZE..position + VLR_HEADER_SIZE + record_len)
+            .ok_or_else(|| XrError::Codec("The LAS file ends within its VLRs".to_string()))?;
+        if record_id == LASZIP_VLR_RECORD_ID && user_id.starts_with(LASZIP_VLR_USER_ID) {
+            return LazVlr::read_from(record).map_err(|e| XrError::Codec(format!("Invalid LASzip VLR: {e}")));
+        }
+        position += VLR_HEADER_SIZE + record_len;
+    }
+    Err(XrError::Codec("The LAZ file has no LASzip VLR".to_string()))
+}
+
+fn read_bytes<const N: usize>(data: &[u8], offset: usize) -> XrResult<[u8; N]> {
+    data.get(offset..offset + N)
+        .map(|bytes| bytes.try_into().unwrap())
+        .ok_or_else(|| XrError::Codec("The LAS header is truncated".to_string()))
+}
+
+fn read_u16(data: &[u8], offset: usize) -> XrResult<u16> {
+    read_bytes(data, offset).map(u16::from_le_bytes)
+}
+
+fn read_u32(data: &[u8], offset: usize) -> XrResult<u32> {
+    read_bytes(data, offset).map(u32::from_le_bytes)
+}
+
+fn read_u64(data: &[u8], offset: usize) -> XrResult<u64> {
+    read_bytes(data, offset).map(u64::from_le_bytes)
+}
+
+fn read_f64(data: &[u8], offset: usize) -> XrResult<f64> {
+    read_bytes(data, offset).map(f64::from_le_bytes)
+}
@@ -1,22 +1,37 @@
 use tracing::instrument;
 
+use shared_utils::error::{XrError, XrResult};
 use shared_utils::types::PointCloudData;
 
 pub mod ply;
 pub mod draco;
+pub mod bitcode;
+pub mod laz;
+pub mod tmf;
+pub mod gpcc;
+pub mod vpcc;
+pub mod octree;
 
 #[instrument(skip_all)]
-pub fn decode_data(raw_data: Vec<u8>) -> Result<PointCloudData, Box<dyn std::error::Error>> {
+pub fn decode_data(raw_data: Vec<u8>) -> XrResult<PointCloudData> {
     if raw_data.is_empty() || raw_data.len() < 3 {
-        return Err("Not enough data to contain header".into());
+        return Err(XrError::Codec("Not enough data to contain header".to_string()));
     }
 
 
     match &raw_data[0..3] {
         b"ply" => ply::decode_ply(raw_data),
         b"DRA" => draco::decode_draco(raw_data),
-        //b"TMF" => tmf::decode_tmf_from_bytes(data)?,
-        //b"BC1" => bitcode::decode_bc_one_from_bytes(data)?,
-        _ => return Err("Unsupported data format".into()),
+        // LAS files start with LASF, with or without LASzip compression
+        b"LAS" => laz::decode_laz(raw_data),
+        b"TMF" => tmf::decode_tmf(raw_data),
+        b"BC1" => bitcode::decode_bitcode(raw_data),
+        b"BC2" => bitcode::decode_bitcode_v2(raw_data),
+        b"GPC" => gpcc::decode_gpcc(raw_data),
+        b"VPC" => vpcc::decode_vpcc(raw_data),
+        b"OCT" => octree::decode_octree(raw_data),
+        // The server does not keep the keyframes, the receivers do
+        b"DLT" => Err(XrError::Codec("Delta coded frames are only decoded by the receivers".to_string())),
+        _ => Err(XrError::Codec("Unsupported data format".to_string())),
     }
 }
\ No newline at end of file
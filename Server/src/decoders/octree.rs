@@ -0,0 +1,104 @@
+use tracing::instrument;
+
+use shared_utils::error::{XrError, XrResult};
+use shared_utils::types::{Point3D, PointCloudData};
+
+use crate::encoders::octree::{MAX_OCTREE_DEPTH, OCTREE_HEADER_SIZE, OCTREE_HEADER_VERSION};
+
+/// An octree frame, with the Morton codes of its leaves.
+pub(crate) struct ParsedOctree {
+    pub depth: u8,
+    pub origin: [f32; 3],
+    /// Edge of the root cube
+    pub size: f32,
+    /// Number of nodes of every level, from the root to the leaves
+    pub level_nodes: Vec<usize>,
+    /// Sorted, as the nodes are written level by level in the order of their Morton codes
+    pub leaves: Vec<u64>,
+    pub colors: Vec<[u8; 3]>,
+}
+
+/// Reads the occupancy of every level of an octree frame, down to its leaves.
+pub(crate) fn parse_octree(data: &[u8]) -> XrResult<ParsedOctree> {
+    if data.len() < OCTREE_HEADER_SIZE {
+        return Err(XrError::Codec("Not enough data to contain the octree header".to_string()));
+    }
+    if data[3] != OCTREE_HEADER_VERSION {
+        return Err(XrError::Codec(format!("Unsupported octree header version {}", data[3])));
+    }
+    let depth = data[4];
+    if depth > MAX_OCTREE_DEPTH {
+        return Err(XrError::Codec(format!("Unsupported octree depth {depth}")));
+    }
+    let read_f32 = |offset: usize| f32::from_le_bytes(data[offset..offset + 4].try_into().unwrap());
+    let origin = [read_f32(5), read_f32(9), read_f32(13)];
+    let size = read_f32(17);
+
+    let mut offset = OCTREE_HEADER_SIZE;
+    let mut nodes: Vec<u64> = vec![0];
+    let mut level_nodes = vec![1];
+    for _ in 0..depth {
+        let occupancy = data.get(offset..offset + nodes.len())
+            .ok_or_else(|| XrError::Codec("The octree ends within its occupancy".to_string()))?;
+        offset += nodes.len();
+        let mut children = Vec::with_capacity(occupancy.iter().map(|byte| byte.count_ones() as usize).sum());
+        for (node, byte) in nodes.iter().zip(occupancy) {
+            for child in 0..8 {
+                if byte & (1 << child) != 0 {
+                    children.push((node << 3) | child);
+                }
+            }
+        }
+        nodes = children;
+        level_nodes.push(nodes.len());
+    }
+
+    let colors = data.get(offset..offset + nodes.len() * 3)
+        .ok_or_else(|| XrError::Codec(format!("The octree announces {} leaves, but ends before their colors", nodes.len())))?
+        .chunks_exact(3)
+        .map(|color| [color[0], color[1], color[2]])
+        .collect();
+    Ok(ParsedOctree {
+        depth,
+        origin,
+        size,
+        level_nodes,
+        leaves: nodes,
+        colors,
+    })
+}
+
+/// Decodes an octree frame of the octree encoder, every leaf is a point in the center of its cell.
+#[instrument(skip_all)]
+pub fn decode_octree(data: Vec<u8>) -> XrResult<PointCloudData> {
+    let octree = parse_octree(&data)?;
+    let cell_size = octree.size / (1u32 << octree.depth) as f32;
+
+    let points = octree.leaves
+        .iter()
+        .zip(&octree.colors)
+        .map(|(code, color)| {
+            // The bits of the Morton code are interleaved from the most significant bit, x first
+            let mut cell = [0u32; 3];
+            for bit in 0..octree.depth as u32 {
+                for (axis, coordinate) in cell.iter_mut().enumerate() {
+                    *coordinate |= (((code >> (3 * bit + 2 - axis as u32)) & 1) as u32) << bit;
+                }
+            }
+            let position = |axis: usize| octree.origin[axis] + (cell[axis] as f32 + 0.5) * cell_size;
+            Point3D {
+                x: position(0),
+                y: position(1),
+                z: position(2),
+                r: color[0],
+                g: color[1],
+                b: color[2],
+            }
+        })
+        .collect();
+
+    Ok(PointCloudData {
+        points,
+        ..Default::default()
+    })
+}
@@ -1,13 +1,18 @@
 use std::io::{Cursor, BufReader};
 use ply_rs::parser::Parser;
+use ply_rs::ply::{DefaultElement, ElementDef, Header, Property, PropertyAccess};
 use tracing::{warn, instrument};
 
-use shared_utils::types::{Point3D, PointCloudData};
+use shared_utils::error::{XrError, XrResult};
+use shared_utils::types::{Point3D, PointAttributes, PointCloudData};
+
+/// Properties that are stored in `Point3D`, all others are stored in `PointAttributes`.
+const POINT_PROPERTIES: [&str; 6] = ["x", "y", "z", "red", "green", "blue"];
 
 
 
 #[instrument(skip_all)]
-pub fn decode_ply(data: Vec<u8>) -> Result<PointCloudData, Box<dyn std::error::Error>> {
+pub fn decode_ply(data: Vec<u8>) -> XrResult<PointCloudData> {
     // Wrap the Vec<u8> in a Cursor, then in a BufReader to handle line reading.
     let cursor = Cursor::new(data);
     let mut reader = BufReader::new(cursor);
@@ -20,7 +25,7 @@ pub fn decode_ply(data: Vec<u8>) -> Result<PointCloudData, Box<dyn std::error::E
         Ok(h) => h,
         Err(_) => {
             // Return an error if the header cannot be read
-            return Err("Failed to read PLY header".into());
+            return Err(XrError::Codec("Failed to read PLY header".to_string()));
         }
     };
 
@@ -29,6 +34,18 @@ pub fn decode_ply(data: Vec<u8>) -> Result<PointCloudData, Box<dyn std::error::E
     // Parse payload based on the element type (vertex in this case)
     for element in &header.elements {
         match element.name.as_ref() {
+            "vertex" if has_attributes(element) => {
+                match read_points_with_attributes(&mut reader, element, &header) {
+                    Ok((points, attributes)) => {
+                        pcd.points = points;
+                        pcd.attributes = attributes;
+                    }
+                    Err(_) => {
+                        pcd.error_count += 1;
+                        continue;
+                    }
+                }
+            }
             "vertex" => {
                 // Handle potential errors in `read_payload_for_element`
                 let vertex_list = match parser.read_payload_for_element(&mut reader, element, &header) {
@@ -49,4 +66,61 @@ pub fn decode_ply(data: Vec<u8>) -> Result<PointCloudData, Box<dyn std::error::E
     }
 
     Ok(pcd)
-}
\ No newline at end of file
+}
+
+fn has_attributes(element: &ElementDef) -> bool {
+    element
+        .properties
+        .iter()
+        .any(|property| !POINT_PROPERTIES.contains(&property.name.as_str()))
+}
+
+/// Slower than parsing straight into `Point3D`, so only used when the points have other properties than xyz + rgb.
+fn read_points_with_attributes(
+    reader: &mut BufReader<Cursor<Vec<u8>>>,
+    element: &ElementDef,
+    header: &Header,
+) -> XrResult<(Vec<Point3D>, PointAttributes)> {
+    let parser = Parser::<DefaultElement>::new();
+    let elements = parser.read_payload_for_element(reader, element, header).map_err(XrError::codec)?;
+
+    let attribute_names: Vec<String> = element
+        .properties
+        .iter()
+        .map(|property| property.name.clone())
+        .filter(|name| !POINT_PROPERTIES.contains(&name.as_str()))
+        .collect();
+    let mut attribute_values = vec![Vec::with_capacity(elements.len()); attribute_names.len()];
+
+    let mut points = Vec::with_capacity(elements.len());
+    for element in elements {
+        let mut point = Point3D::default();
+        for name in POINT_PROPERTIES {
+            if let Some(property) = element.get(name) {
+                point.set_property(name, property.clone());
+            }
+        }
+        points.push(point);
+
+        for (name, values) in attribute_names.iter().zip(attribute_values.iter_mut()) {
+            values.push(element.get(name.as_str()).map(property_as_f32).unwrap_or(0.0));
+        }
+    }
+
+    let attributes = PointAttributes::from_scalar_properties(attribute_names.into_iter().zip(attribute_values).collect());
+    Ok((points, attributes))
+}
+
+fn property_as_f32(property: &Property) -> f32 {
+    match *property {
+        Property::Char(v) => v as f32,
+        Property::UChar(v) => v as f32,
+        Property::Short(v) => v as f32,
+        Property::UShort(v) => v as f32,
+        Property::Int(v) => v as f32,
+        Property::UInt(v) => v as f32,
+        Property::Float(v) => v,
+        Property::Double(v) => v as f32,
+        _ => 0.0, // Lists are not supported as attributes
+    }
+}
@@ -0,0 +1,54 @@
+use tmf::TMFMesh;
+use tracing::{error, instrument};
+
+use shared_utils::error::XrResult;
+use shared_utils::types::{Point3D, PointCloudData};
+
+/// Decodes a TMF mesh as written by the TMF encoder: the vertices are the points,
+/// and their colors are packed as 0x00RRGGBB in the "colors" custom data.
+#[instrument(skip_all)]
+pub fn decode_tmf(data: Vec<u8>) -> XrResult<PointCloudData> {
+    let (mesh, _name) = match TMFMesh::read_tmf_one(&mut &data[..]) {
+        Ok(mesh) => mesh,
+        Err(e) => {
+            error!("Error decoding TMF data: {}", e);
+            let pcd = PointCloudData {
+                error_count: 1,
+                ..Default::default()
+            };
+            // If there's an error, return 1 error and no points
+            return Ok(pcd);
+        }
+    };
+
+    let Some(vertices) = mesh.get_vertices().filter(|vertices| !vertices.is_empty()) else {
+        return Ok(PointCloudData::default());
+    };
+
+    // Points without a color, e.g. when the producer wrote fewer colors than vertices, are black
+    let colors = mesh.lookup_custom_data("colors")
+        .and_then(|custom_data| custom_data.as_intiger())
+        .map(|(colors, _max_value)| colors)
+        .unwrap_or_default();
+
+    let points = vertices
+        .iter()
+        .enumerate()
+        .map(|(index, vertex)| {
+            let packed = colors.get(index).copied().unwrap_or(0);
+            Point3D {
+                x: vertex.0,
+                y: vertex.1,
+                z: vertex.2,
+                r: ((packed >> 16) & 0xFF) as u8,
+                g: ((packed >> 8) & 0xFF) as u8,
+                b: (packed & 0xFF) as u8,
+            }
+        })
+        .collect();
+
+    Ok(PointCloudData {
+        points,
+        ..Default::default()
+    })
+}
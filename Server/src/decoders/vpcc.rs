@@ -0,0 +1,141 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+use openh264::decoder::Decoder;
+use openh264::formats::YUVSource;
+use tracing::instrument;
+
+use shared_utils::error::{XrError, XrResult};
+use shared_utils::types::{Point3D, PointCloudData};
+
+use crate::encoders::vpcc::{patch_axes, DEPTH_STEP, OCCUPANCY_THRESHOLD, PATCH_INFO_SIZE, PATCH_SIZE, VPCC_HEADER_SIZE, VPCC_HEADER_VERSION};
+
+/// Idle decoders that are kept per stream
+const MAX_IDLE_DECODERS: usize = 4;
+
+/// Decodes a V-PCC frame of the V-PCC encoder: the atlas video is decoded,
+/// and every occupied pixel of a patch is moved back to its voxel, with the color of the texture atlas.
+/// The atlas is decoded in software with OpenH264.
+///
+/// This creates a video decoder for the frame, see `VpccDecoders` to reuse the decoders of a stream.
+#[instrument(skip_all)]
+pub fn decode_vpcc(data: Vec<u8>) -> XrResult<PointCloudData> {
+    let mut decoder = Decoder::new().map_err(XrError::codec)?;
+    decode_vpcc_with(&mut decoder, &data)
+}
+
+/// The atlas decoders of every stream, as creating a decoder for every frame is about as slow as decoding it.
+/// Every frame is intra coded and starts with the parameter sets of its atlas,
+/// so the decoders of a stream follow the changes of the atlas resolution, and any idle decoder of the stream can decode the next frame.
+#[derive(Default)]
+pub struct VpccDecoders {
+    decoders: Mutex<HashMap<String, Vec<Decoder>>>,
+}
+
+impl std::fmt::Debug for VpccDecoders {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("VpccDecoders").finish_non_exhaustive()
+    }
+}
+
+impl VpccDecoders {
+    /// Decodes the frame with a decoder of the stream, see `decode_vpcc`.
+    #[instrument(skip_all)]
+    pub fn decode(&self, stream_id: &str, data: Vec<u8>) -> XrResult<PointCloudData> {
+        let idle = self.decoders.lock().unwrap().get_mut(stream_id).and_then(Vec::pop);
+        let mut decoder = match idle {
+            Some(decoder) => decoder,
+            None => Decoder::new().map_err(XrError::codec)?,
+        };
+        let result = decode_vpcc_with(&mut decoder, &data);
+
+        // A decoder that failed may be left in any state
+        if result.is_ok() {
+            let mut decoders = self.decoders.lock().unwrap();
+            let idle = decoders.entry(stream_id.to_string()).or_default();
+            if idle.len() < MAX_IDLE_DECODERS {
+                idle.push(decoder);
+            }
+        }
+        result
+    }
+}
+
+fn decode_vpcc_with(decoder: &mut Decoder, data: &[u8]) -> XrResult<PointCloudData> {
+    if data.len() < VPCC_HEADER_SIZE {
+        return Err(XrError::Codec("Not enough data to contain the V-PCC header".to_string()));
+    }
+    if data[3] != VPCC_HEADER_VERSION {
+        return Err(XrError::Codec(format!("Unsupported V-PCC header version {}", data[3])));
+    }
+    let read_f32 = |offset: usize| f32::from_le_bytes(data[offset..offset + 4].try_into().unwrap());
+    let voxel_size = read_f32(4);
+    let origin = [read_f32(8), read_f32(12), read_f32(16)];
+    let columns = u16::from_le_bytes([data[20], data[21]]) as usize;
+    let patch_count = u32::from_le_bytes(data[22..26].try_into().unwrap()) as usize;
+
+    let patches_end = patch_count.checked_mul(PATCH_INFO_SIZE)
+        .and_then(|len| len.checked_add(VPCC_HEADER_SIZE))
+        .filter(|end| *end <= data.len())
+        .ok_or_else(|| XrError::Codec(format!("The V-PCC frame announces {patch_count} patches, but ends before them")))?;
+    if patch_count == 0 {
+        return Ok(PointCloudData::default());
+    }
+    if columns == 0 {
+        return Err(XrError::Codec("The V-PCC atlas has no columns".to_string()));
+    }
+
+    let atlas = decoder.decode(&data[patches_end..])
+        .map_err(XrError::codec)?
+        .ok_or_else(|| XrError::Codec("The V-PCC atlas did not decode to a frame".to_string()))?;
+    let (width, height) = atlas.dimensions();
+    let (y_stride, u_stride, v_stride) = atlas.strides();
+    let rows = patch_count.div_ceil(columns);
+    if width < columns * PATCH_SIZE * 2 || height < rows * PATCH_SIZE {
+        return Err(XrError::Codec(format!("The V-PCC atlas of {width}x{height} is too small for its {patch_count} patches")));
+    }
+
+    let mut points = Vec::new();
+    for (index, patch) in data[VPCC_HEADER_SIZE..patches_end].chunks_exact(PATCH_INFO_SIZE).enumerate() {
+        let block = [0, 1, 2].map(|axis| u16::from_le_bytes([patch[axis * 2], patch[axis * 2 + 1]]) as usize);
+        let axis = (patch[6] as usize).min(2);
+        let (column_axis, row_axis) = patch_axes(axis);
+        let left = (index % columns) * PATCH_SIZE;
+        let top = (index / columns) * PATCH_SIZE;
+
+        for pixel in 0..PATCH_SIZE * PATCH_SIZE {
+            let row = top + pixel / PATCH_SIZE;
+            let column = left + pixel % PATCH_SIZE;
+            // The atlas is limited range video, the geometry was a gray level
+            let geometry = (atlas.y()[row * y_stride + column] as f32 - 16.0) * 255.0 / 219.0;
+            if geometry < OCCUPANCY_THRESHOLD {
+                continue;
+            }
+            let depth = ((geometry / DEPTH_STEP).round() - 1.0).clamp(0.0, (PATCH_SIZE - 1) as f32) as usize;
+
+            let texture_column = column + width / 2;
+            let luma = atlas.y()[row * y_stride + texture_column] as f32 - 16.0;
+            let cb = atlas.u()[(row / 2) * u_stride + texture_column / 2] as f32 - 128.0;
+            let cr = atlas.v()[(row / 2) * v_stride + texture_column / 2] as f32 - 128.0;
+
+            let mut voxel = [0usize; 3];
+            voxel[axis] = depth;
+            voxel[column_axis] = pixel % PATCH_SIZE;
+            voxel[row_axis] = pixel / PATCH_SIZE;
+            let position = |axis: usize| origin[axis] + ((block[axis] * PATCH_SIZE + voxel[axis]) as f32 + 0.5) * voxel_size;
+            points.push(Point3D {
+                x: position(0),
+                y: position(1),
+                z: position(2),
+                r: (1.164 * luma + 1.596 * cr).round().clamp(0.0, 255.0) as u8,
+                g: (1.164 * luma - 0.392 * cb - 0.813 * cr).round().clamp(0.0, 255.0) as u8,
+                b: (1.164 * luma + 2.017 * cb).round().clamp(0.0, 255.0) as u8,
+            });
+        }
+    }
+
+    Ok(PointCloudData {
+        points,
+        ..Default::default()
+    })
+}
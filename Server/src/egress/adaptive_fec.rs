@@ -0,0 +1,151 @@
+// egress/adaptive_fec.rs
+
+//! Chooses the FEC scheme and parity percentage of the FLUTE egress from the packet loss that the receivers observe.
+
+use std::time::{Duration, Instant};
+
+use shared_utils::error::{XrError, XrResult};
+
+/// The FEC settings of a FLUTE sender.
+#[derive(Clone, Debug, PartialEq)]
+pub struct FecSettings {
+    pub fec: String,
+    pub parity_percentage: f32,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct AdaptiveFecConfig {
+    /// Below this loss fraction no FEC is used at all
+    pub no_fec_below: f32,
+    /// The parity percentage is the smoothed loss multiplied by this margin, plus the minimum parity
+    pub margin: f32,
+    pub min_parity_percentage: f32,
+    pub max_parity_percentage: f32,
+    /// The sender is only recreated when the parity percentage changes more than this
+    pub hysteresis: f32,
+    /// Weight of a new loss report in the smoothed loss
+    pub smoothing: f32,
+}
+
+impl Default for AdaptiveFecConfig {
+    fn default() -> Self {
+        Self {
+            no_fec_below: 0.001,
+            margin: 2.0,
+            min_parity_percentage: 0.02,
+            max_parity_percentage: 0.5,
+            hysteresis: 0.02,
+            smoothing: 0.3,
+        }
+    }
+}
+
+/// Smooths the reported loss, and decides when the FEC settings have to change.
+#[derive(Clone, Debug)]
+pub struct AdaptiveFec {
+    config: AdaptiveFecConfig,
+    smoothed_loss: Option<f32>,
+    current: FecSettings,
+}
+
+impl AdaptiveFec {
+    pub fn new(config: AdaptiveFecConfig, current: FecSettings) -> Self {
+        Self {
+            config,
+            smoothed_loss: None,
+            current,
+        }
+    }
+
+    pub fn smoothed_loss(&self) -> Option<f32> {
+        self.smoothed_loss
+    }
+
+    /// Handles a loss report with the fraction (0 to 1) of the packets that a receiver lost.
+    /// Returns the new FEC settings if they differ enough from the current ones.
+    pub fn on_loss(&mut self, loss_fraction: f32) -> Option<FecSettings> {
+        let loss_fraction = loss_fraction.clamp(0.0, 1.0);
+        let smoothed_loss = match self.smoothed_loss {
+            Some(smoothed_loss) => smoothed_loss + self.config.smoothing * (loss_fraction - smoothed_loss),
+            None => loss_fraction,
+        };
+        self.smoothed_loss = Some(smoothed_loss);
+
+        let target = if smoothed_loss < self.config.no_fec_below {
+            FecSettings {
+                fec: "nocode".to_string(),
+                parity_percentage: 0.0,
+            }
+        } else {
+            FecSettings {
+                fec: "raptorq".to_string(),
+                parity_percentage: (smoothed_loss * self.config.margin + self.config.min_parity_percentage)
+                    .clamp(self.config.min_parity_percentage, self.config.max_parity_percentage),
+            }
+        };
+
+        let changed = target.fec != self.current.fec
+            || (target.parity_percentage - self.current.parity_percentage).abs() > self.config.hysteresis;
+        if !changed {
+            return None;
+        }
+        self.current = target.clone();
+        Some(target)
+    }
+}
+
+/// A configured sequence of loss fractions, which is replayed in a loop to emulate the loss of a network.
+#[derive(Clone, Debug)]
+pub struct LossProfile {
+    /// Loss fraction from the given offset until the next step
+    steps: Vec<(Duration, f32)>,
+    period: Duration,
+    started: Instant,
+}
+
+impl LossProfile {
+    /// Parses a profile like `0:0.01,10000:0.05,20000:0.01`, with the offsets in milliseconds.
+    /// The profile repeats after the last step, which lasts as long as the step before it.
+    pub fn parse(value: &str) -> XrResult<Self> {
+        let mut steps = value
+            .split(',')
+            .map(str::trim)
+            .filter(|step| !step.is_empty())
+            .map(|step| {
+                let (offset, loss) = step.split_once(':')
+                    .ok_or_else(|| XrError::Config(format!("Expected a loss profile step like offset_ms:loss_fraction, got {step}")))?;
+                let offset = offset.trim().parse::<u64>()
+                    .map_err(|e| XrError::Config(format!("Invalid offset in loss profile step {step}: {e}")))?;
+                let loss = loss.trim().parse::<f32>()
+                    .map_err(|e| XrError::Config(format!("Invalid loss fraction in loss profile step {step}: {e}")))?;
+                Ok((Duration::from_millis(offset), loss))
+            })
+            .collect::<XrResult<Vec<_>>>()?;
+        if steps.is_empty() {
+            return Err(XrError::Config("The loss profile has no steps".to_string()));
+        }
+        steps.sort_by_key(|(offset, _)| *offset);
+
+        let last_step = match steps.as_slice() {
+            [.., (before, _), (last, _)] => *last - *before,
+            _ => Duration::from_secs(1),
+        };
+        let period = steps.last().unwrap().0 + last_step;
+
+        Ok(Self {
+            steps,
+            period,
+            started: Instant::now(),
+        })
+    }
+
+    /// The loss fraction at this moment of the profile.
+    pub fn current_loss(&self) -> f32 {
+        let position = Duration::from_nanos((self.started.elapsed().as_nanos() % self.period.as_nanos().max(1)) as u64);
+        self.steps
+            .iter()
+            .rev()
+            .find(|(offset, _)| *offset <= position)
+            .map_or(self.steps[0].1, |(_, loss)| *loss)
+    }
+}
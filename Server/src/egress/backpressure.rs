@@ -0,0 +1,55 @@
+// egress/backpressure.rs
+
+//! Pauses the generation of combined frames while the egress buffer is nearly full,
+//! as frames that are encoded into a full buffer are dropped anyway.
+
+use shared_utils::error::{XrError, XrResult};
+
+/// Buffer occupancies, as a fraction of the capacity, at which the generation pauses and resumes.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct BackpressureWatermarks {
+    /// The generation pauses once the buffered and queued frames reach this fraction of the capacity
+    pub high: f64,
+    /// The generation resumes once the buffered and queued frames dropped to this fraction of the capacity
+    pub low: f64,
+}
+
+impl Default for BackpressureWatermarks {
+    fn default() -> Self {
+        Self { high: 0.8, low: 0.5 }
+    }
+}
+
+impl BackpressureWatermarks {
+    pub fn new(high: f64, low: f64) -> XrResult<Self> {
+        if !(0.0..=1.0).contains(&low) || !(low..=1.0).contains(&high) || high == 0.0 {
+            return Err(XrError::Config(format!("Expected backpressure watermarks with 0 <= low <= high <= 1 and high > 0, got low {low} and high {high}")));
+        }
+        Ok(Self { high, low })
+    }
+}
+
+/// Tracks whether the generator of one egress is paused.
+/// The gap between both watermarks keeps the generator from toggling on every frame.
+#[derive(Debug, Default)]
+pub struct Backpressure {
+    paused: bool,
+}
+
+impl Backpressure {
+    /// Returns whether the generator should skip this frame, given the number of frames that are
+    /// buffered or still being encoded, and the capacity of the buffer.
+    pub fn should_pause(&mut self, watermarks: BackpressureWatermarks, occupancy: usize, capacity: usize) -> bool {
+        let fill = occupancy as f64 / capacity.max(1) as f64;
+        if self.paused {
+            self.paused = fill > watermarks.low;
+        } else {
+            self.paused = fill >= watermarks.high;
+        }
+        self.paused
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused
+    }
+}
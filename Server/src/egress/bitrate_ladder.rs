@@ -0,0 +1,90 @@
+// egress/bitrate_ladder.rs
+
+//! Encodes one combined point cloud at several point budgets, for DASH representations and WebRTC simulcast layers.
+
+use rayon::prelude::*;
+use shared_utils::error::{XrError, XrResult};
+use shared_utils::track_local_pointcloud_rtp::QualityLayer;
+use shared_utils::types::PointCloudData;
+use tracing::error;
+
+use crate::encoders::octree::truncate_octree;
+use crate::encoders::EncodingFormat;
+use crate::processing::sampling::exact_random_sampling_indices;
+use crate::processing::ProcessingPipeline;
+
+/// Parses a ladder formatted as `id:max_number_of_points,...`, e.g. `high:100000,mid:50000,low:10000`.
+/// The layers are sorted from the highest to the lowest number of points, an empty value gives no layers.
+pub fn parse_ladder(value: &str) -> XrResult<Vec<QualityLayer>> {
+    let mut layers = value
+        .split(',')
+        .map(str::trim)
+        .filter(|layer| !layer.is_empty())
+        .map(|layer| {
+            let (id, points) = layer.split_once(':')
+                .ok_or_else(|| XrError::Config(format!("Expected a quality layer like id:max_number_of_points, got {layer}")))?;
+            let points = points.trim().parse::<u64>()
+                .map_err(|e| XrError::Config(format!("Invalid number of points in quality layer {layer}: {e}")))?;
+            Ok(QualityLayer::new(id.trim(), points))
+        })
+        .collect::<XrResult<Vec<_>>>()?;
+    layers.sort_by(|a, b| b.max_number_of_points.cmp(&a.max_number_of_points));
+    Ok(layers)
+}
+
+/// Down-samples the point cloud to the number of points of every layer, and encodes the layers in parallel.
+/// Octrees are encoded once, and cut at the level that fits every layer instead.
+/// Layers that fail to encode are left out.
+pub fn encode_ladder(
+    processing_pipeline: &ProcessingPipeline,
+    point_cloud: &PointCloudData,
+    encoding_format: EncodingFormat,
+    layers: &[QualityLayer],
+) -> Vec<(QualityLayer, Vec<u8>)> {
+    if encoding_format == EncodingFormat::Octree && !layers.is_empty() {
+        return encode_octree_ladder(processing_pipeline, point_cloud, layers);
+    }
+    processing_pipeline.thread_pool.install(|| {
+        layers
+            .par_iter()
+            .filter_map(|layer| {
+                let layer_point_cloud = if point_cloud.points.len() as u64 > layer.max_number_of_points {
+                    point_cloud.select(&exact_random_sampling_indices(point_cloud.points.len(), layer.max_number_of_points as usize))
+                } else {
+                    point_cloud.clone()
+                };
+                match processing_pipeline.encode(layer_point_cloud, encoding_format) {
+                    Ok(frame) => Some((layer.clone(), frame.data)),
+                    Err(e) => {
+                        error!("Failed to encode quality layer {}: {:?}", layer.rid, e);
+                        None
+                    }
+                }
+            })
+            .collect()
+    })
+}
+
+fn encode_octree_ladder(
+    processing_pipeline: &ProcessingPipeline,
+    point_cloud: &PointCloudData,
+    layers: &[QualityLayer],
+) -> Vec<(QualityLayer, Vec<u8>)> {
+    let octree = match processing_pipeline.encode(point_cloud.clone(), EncodingFormat::Octree) {
+        Ok(frame) => frame.data,
+        Err(e) => {
+            error!("Failed to encode the octree of the quality layers: {:?}", e);
+            return Vec::new();
+        }
+    };
+    layers
+        .iter()
+        .filter_map(|layer| match truncate_octree(&octree, layer.max_number_of_points) {
+            Ok(data) => Some((layer.clone(), data)),
+            Err(e) => {
+                error!("Failed to truncate the octree for quality layer {}: {:?}", layer.rid, e);
+                None
+            }
+        })
+        .collect()
+}
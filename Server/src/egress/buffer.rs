@@ -2,15 +2,20 @@
 
 use std::{collections::HashMap, sync::{atomic::{AtomicBool, Ordering}, Arc, Mutex}, time::{Duration, SystemTime, UNIX_EPOCH}};
 
-use crate::{encoders::EncodingFormat, processing::{aggregator::PointCloudAggregator, ProcessingPipeline}, services::{mpd_manager::MpdManager, stream_manager::StreamManager}};
-use mp4_box::writer::{create_media_segment, Mp4StreamConfig};
+use crate::{encoders::EncodingFormat, processing::{aggregator::PointCloudAggregator, ProcessingPipeline}, services::{hls_playlist::{build_media_playlist, build_multivariant_playlist, HlsMediaWindow}, mpd_manager::MpdManager, stream_manager::{StreamManager, COMBINED_STREAM_ID}}, types::EgressProtocolType};
+use mp4_box::writer::{create_init_segment, create_media_chunk, create_media_segment, create_multi_track_init_segment, Mp4StreamConfig};
+use shared_utils::error::XrResult;
+use shared_utils::track_local_pointcloud_rtp::QualityLayer;
 use shared_utils::types::{FrameTaskData, PointCloudData};
+use shared_utils::wire::encode_frame;
 use circular_buffer::CircularBuffer;
+use super::ring_buffer::RingBuffer;
 use bytes::Bytes;
 use tokio::time::sleep;
-use tracing::{debug, instrument};
+use tracing::{debug, instrument, warn};
 
-use super::egress_common::{push_preencoded_frame_data, EgressCommonMetrics, EgressProtocol};
+use super::bitrate_ladder::{encode_ladder, parse_ladder};
+use super::egress_common::{drain_frame_buffer, push_preencoded_frame_data, EgressCommonMetrics, EgressProtocol};
 
 #[derive(Clone, Debug)]
 pub struct BufferFrame {
@@ -21,7 +26,7 @@ pub struct BufferFrame {
 #[derive(Clone, Debug)]
 pub struct BufferEgress {
     processing_pipeline: Arc<ProcessingPipeline>,
-    frame_buffer: Arc<Mutex<CircularBuffer<10, FrameTaskData>>>,
+    frame_buffer: Arc<Mutex<RingBuffer<FrameTaskData>>>,
     aggregator: Arc<PointCloudAggregator>,
     threads_started: Arc<AtomicBool>,
     fps: Arc<Mutex<u32>>,
@@ -30,6 +35,12 @@ pub struct BufferEgress {
     egress_metrics: Arc<EgressCommonMetrics>,
     circular_storages: Arc<Mutex<HashMap<String, (CircularBuffer<60, BufferFrame>, u64, Mp4StreamConfig)>>>,
     mpd_manager: Arc<MpdManager>,
+    hls_parts_per_segment: u64,
+    dash_chunks_per_segment: u64,
+    /// The aggregated frames are published as one representation per layer, instead of a single representation
+    ladder: Arc<Mutex<Vec<QualityLayer>>>,
+    /// The tiles of a client are published as tracks of one presentation, with an adaptation set per tile and a shared init segment
+    tile_tracks: bool,
 }
 
 impl BufferEgress {
@@ -38,20 +49,27 @@ impl BufferEgress {
         stream_manager: Arc<StreamManager>,
         processing_pipeline: Arc<ProcessingPipeline>,
         mpd_manager: Arc<MpdManager>,
+        hls_parts_per_segment: u64,
+        dash_chunks_per_segment: u64,
+        tile_tracks: bool,
     ) {
         let aggregator = Arc::new(PointCloudAggregator::new(stream_manager.clone()));
 
         let instance = Arc::new(Self {
             processing_pipeline: processing_pipeline.clone(),
-            frame_buffer: Arc::new(Mutex::new(CircularBuffer::new())),
+            frame_buffer: Arc::new(Mutex::new(RingBuffer::new(stream_manager.frame_buffer_capacity(&EgressProtocolType::Buffer)))),
             aggregator: aggregator.clone(),
             threads_started: Arc::new(AtomicBool::new(false)),
             fps: Arc::new(Mutex::new(30)),
             encoding_format: Arc::new(Mutex::new(EncodingFormat::Draco)),
             max_number_of_points: Arc::new(Mutex::new(100000)),
-            egress_metrics: Arc::new(EgressCommonMetrics::new()),
+            egress_metrics: Arc::new(EgressCommonMetrics::new("BUF_E")),
             circular_storages: Arc::new(Mutex::new(HashMap::new())),
-            mpd_manager
+            mpd_manager,
+            hls_parts_per_segment: hls_parts_per_segment.max(1),
+            dash_chunks_per_segment: dash_chunks_per_segment.max(1),
+            ladder: Arc::new(Mutex::new(Vec::new())),
+            tile_tracks,
         });
 
         stream_manager.set_buffer_egress(instance.clone());
@@ -62,6 +80,29 @@ impl BufferEgress {
         storages.get(stream_id).map(|(_, _, config)| config.clone())
     }
 
+    /// Returns the init segment of a stream, or of a group when its tiles are published as tracks.
+    /// The init segment of a group has a track for every stream of the group, ordered by track ID.
+    pub fn get_init_segment(&self, id: &str) -> Option<Vec<u8>> {
+        let storages = self.circular_storages.lock().unwrap();
+        if let Some((_, _, config)) = storages.get(id) {
+            return Some(create_init_segment(config));
+        }
+        if !self.tile_tracks {
+            return None;
+        }
+
+        let mut configs: Vec<Mp4StreamConfig> = storages
+            .iter()
+            .filter(|(key, _)| key.starts_with(id))
+            .map(|(_, (_, _, config))| config.clone())
+            .collect();
+        if configs.is_empty() {
+            return None;
+        }
+        configs.sort_by_key(|config| config.track_id);
+        Some(create_multi_track_init_segment(&configs))
+    }
+
     pub async fn get_frame(&self, stream_id: &str, index: u64, timeout: Duration) -> Option<BufferFrame> {
         let deadline = tokio::time::Instant::now() + timeout;
         loop {
@@ -83,9 +124,11 @@ impl BufferEgress {
 
                     if let Some(min) = min_index {
                         // The requested index is lower than the minimum index in the buffer
-                        // This means that the frame will never be added
+                        // This means that the frame will never be added, but it may still be in the DVR window
                         if index < min {
-                            return None;
+                            return self.mpd_manager
+                                .get_dvr_chunk(stream_id, index)
+                                .map(|data| BufferFrame { index, data });
                         }
                     }
                 } else {
@@ -111,6 +154,181 @@ impl BufferEgress {
         storages.remove_entry(stream_id);
     }
 
+    /// Number of frames (CMAF chunks) in one DASH segment
+    pub fn dash_chunks_per_segment(&self) -> u64 {
+        self.dash_chunks_per_segment
+    }
+
+    /// Sets the bitrate ladder of the aggregated frames, formatted as `id:max_number_of_points,...`, e.g. `high:100000,low:10000`.
+    /// Every layer becomes a representation `client_0_<id>` in the `client_0_` group, so players can switch between them.
+    /// An empty value publishes the aggregated frames as a single representation again.
+    #[instrument(skip_all)]
+    pub fn set_bitrate_ladder(&self, value: &str) -> XrResult<()> {
+        let ladder = parse_ladder(value)?;
+        self.aggregator.set_keep_combined(!ladder.is_empty());
+        let changed = {
+            let mut current = self.ladder.lock().unwrap();
+            let changed = *current != ladder;
+            *current = ladder;
+            changed
+        };
+        if changed {
+            self.start_new_periods(*self.fps.lock().unwrap());
+        }
+        Ok(())
+    }
+
+    /// Stores an aggregated frame once for every layer of the ladder.
+    /// The layers are encoded from the combined point cloud of the frame, layers that fit the whole cloud reuse the frame.
+    fn emit_ladder_frame(&self, frame: FrameTaskData, ladder: &[QualityLayer]) {
+        let group_id = "client_0_";
+        let point_cloud = self.aggregator.combined_point_cloud(frame.presentation_time);
+        let encoded_layers = match &point_cloud {
+            Some(point_cloud) => {
+                let lower_layers: Vec<QualityLayer> = ladder.iter()
+                    .filter(|layer| (point_cloud.points.len() as u64) > layer.max_number_of_points)
+                    .cloned()
+                    .collect();
+                encode_ladder(&self.processing_pipeline, point_cloud, self.encoding_format(), &lower_layers)
+            }
+            None => {
+                // Every layer still gets a frame, so the segment numbers of the representations stay aligned
+                warn!("Combined point cloud of frame {} is no longer kept, using the full frame in every layer", frame.presentation_time);
+                vec![]
+            }
+        };
+
+        let stream_manager = self.aggregator.stream_manager();
+        for (index, layer) in ladder.iter().enumerate() {
+            let data = match encoded_layers.iter().find(|(encoded_layer, _)| encoded_layer.rid == layer.rid) {
+                Some((_, data)) => match stream_manager.encrypt_payload(COMBINED_STREAM_ID, data.clone()) {
+                    Some(data) => data,
+                    None => continue,
+                },
+                None => frame.data.clone(),
+            };
+            let layer_frame = FrameTaskData { data, ..frame.clone() };
+            self.store_frame(group_id, &format!("{}{}", group_id, layer.rid), index as u32 + 1, None, &layer_frame);
+        }
+    }
+
+    /// Packages the frame as a CMAF chunk, and stores it in the buffer of the stream.
+    /// The stream is added to the MPD of its group on its first frame, in the given adaptation set if any.
+    fn store_frame(&self, group_id: &str, stream_id: &str, track_id: u32, adaptation_set_id: Option<&str>, frame: &FrameTaskData) {
+        // Copy the first three bytes from the frame data
+        let codec = frame.data.clone()[0..3].to_ascii_lowercase().to_vec();
+        let encoded = {
+            let bytes_vec: Vec<u8> = encode_frame(frame);
+            let base64_encoded: String = rbase64::encode(&bytes_vec);
+            let bytes = Bytes::from(base64_encoded);
+            bytes.to_vec()
+
+        };
+
+        let dvr_chunk = {
+            let mut storages = self.circular_storages.lock().unwrap();
+        
+            let fps = *self.fps.lock().unwrap();
+
+            // Add the stream to the current period of the MPD, on its first frame or after a new period started
+            if !storages.contains_key(stream_id) || !self.mpd_manager.has_representation(group_id, stream_id) {
+                self.mpd_manager.add_stream_to_mpd(
+                    group_id,
+                    stream_id,
+                    "video/pc",
+                    &String::from_utf8_lossy(&codec),
+                    encoded.len().saturating_mul(fps.try_into().unwrap()).saturating_mul(8) as u64, // Bandwidth in bits
+                    fps as u64,
+                    self.dash_chunks_per_segment,
+                    adaptation_set_id,
+                );
+            }
+
+            // Check if the stream already exists
+            if !storages.contains_key(stream_id) {
+                // Create the Mp4StreamConfig
+                let config = Mp4StreamConfig {
+                    timescale: fps * 1000,
+                    width: 1920,   // Example defaults
+                    height: 1080,
+                    codec_fourcc: [codec[0], codec[1], codec[2], b' '],
+                    track_id,
+                    default_sample_duration: 1000, // This will be divided by the timescale
+                    codec_name: format!("PointCloudCodec_{}", String::from_utf8_lossy(&codec)),
+                };
+        
+                // Find the next available index within the group
+                let next_index = storages
+                    .iter()
+                    .filter(|(key, _)| key.starts_with(group_id))
+                    .map(|(_, (_, index, _))| *index)
+                    .max()
+                    .unwrap_or(0);
+        
+                // Insert a new circular buffer and index
+                storages.insert(stream_id.to_string(), (CircularBuffer::new(), next_index, config));
+            }
+        
+            // Get a mutable reference to the stream
+            let (buffer, index, config) = storages.get_mut(stream_id).unwrap();
+
+            // Decode time is the // Timeline position in timescale units
+            let decode_time = frame.presentation_time * config.timescale as u64 / 1000;
+            // Only the first chunk of a DASH segment starts with a STYP box,
+            // the other chunks are appended to it when the segment is served.
+            let segment_bytes = if *index % self.dash_chunks_per_segment == 0 {
+                create_media_segment(
+                    config,
+                    &encoded, // Use the encoded Bytes directly
+                    *index as u32,
+                    decode_time,
+                )
+            } else {
+                create_media_chunk(config, &encoded, *index as u32, decode_time)
+            };
+        
+            // Construct the buffer frame
+            let buffer_frame = BufferFrame {
+                index: *index,
+                data: segment_bytes, // TODO: instead of encoded, we should use the m4s file
+            };
+        
+            // The DVR window is written to disk once the buffer is unlocked
+            let dvr_chunk = self.mpd_manager.dvr_enabled().then(|| (buffer_frame.clone(), config.timescale as u64 / 1000));
+
+            // Increment the index and store the frame
+            *index += 1;
+            buffer.push_back(buffer_frame);
+        
+            debug!("Stored frame in buffer of stream {} at index {}", stream_id, *index - 1);
+            dvr_chunk
+        };
+
+        if let Some((chunk, fps)) = dvr_chunk {
+            self.mpd_manager.store_dvr_chunk(group_id, stream_id, chunk.index, &chunk.data, fps, self.dash_chunks_per_segment);
+        }
+    }
+
+    /// Starts a new period in the MPD of every group, as its representations changed.
+    /// Each period starts at the first segment that was not started yet, the streams are added again on their next frame.
+    fn start_new_periods(&self, fps: u32) {
+        let mut storages = self.circular_storages.lock().unwrap();
+        for group_id in self.mpd_manager.get_groups() {
+            let next_index = storages
+                .iter()
+                .filter(|(key, _)| key.starts_with(&group_id))
+                .map(|(_, (_, index, _))| *index)
+                .max()
+                .unwrap_or(0);
+            let next_segment_number = next_index.div_ceil(self.dash_chunks_per_segment);
+            self.mpd_manager.start_period(&group_id, next_segment_number, fps as u64, self.dash_chunks_per_segment);
+        }
+        // The timing of the CMAF chunks follows the frame rate of the new period
+        for (_, _, config) in storages.values_mut() {
+            config.timescale = fps * 1000;
+        }
+    }
+
     pub fn get_mpd(&self, group_id: &str) -> Option<String> {
         self.mpd_manager.get_mpd(group_id)
     }
@@ -118,10 +336,51 @@ impl BufferEgress {
     pub fn get_groups(&self) -> Vec<String> {
         self.mpd_manager.get_groups()
     }
+
+    pub fn get_hls_window(&self, stream_id: &str) -> Option<HlsMediaWindow> {
+        let storages = self.circular_storages.lock().unwrap();
+        let (storage, _, config) = storages.get(stream_id)?;
+        Some(HlsMediaWindow {
+            first_index: storage.front()?.index,
+            last_index: storage.back()?.index,
+            part_duration: config.default_sample_duration as f64 / config.timescale as f64,
+            parts_per_segment: self.hls_parts_per_segment,
+        })
+    }
+
+    pub fn get_hls_multivariant_playlist(&self, group_id: &str) -> Option<String> {
+        self.mpd_manager
+            .get_representations(group_id)
+            .map(|representations| build_multivariant_playlist(&representations))
+    }
+
+    pub fn get_hls_media_playlist(&self, stream_id: &str) -> Option<String> {
+        self.get_hls_window(stream_id).map(|window| build_media_playlist(&window))
+    }
+
+    /// Concatenate the CMAF chunks of all frames in a full HLS segment.
+    /// Returns None if one of the frames is no longer (or not yet) in the buffer.
+    pub fn get_hls_segment(&self, stream_id: &str, msn: u64) -> Option<Vec<u8>> {
+        let storages = self.circular_storages.lock().unwrap();
+        let (storage, _, _) = storages.get(stream_id)?;
+        let first = msn * self.hls_parts_per_segment;
+        let last = first + self.hls_parts_per_segment;
+        let mut segment = Vec::new();
+        for index in first..last {
+            let frame = storage.iter().find(|f| f.index == index)?;
+            segment.extend_from_slice(&frame.data);
+        }
+        Some(segment)
+    }
 }
 
 
 impl EgressProtocol for BufferEgress {
+    #[inline]
+    fn fps(&self) -> u32 {
+        *self.fps.lock().unwrap()
+    }
+
     #[inline]
     fn encoding_format(&self) -> EncodingFormat {
         *self.encoding_format.lock().unwrap()
@@ -193,95 +452,34 @@ impl EgressProtocol for BufferEgress {
             presentation_time,
             raw_data,
             bypass,
-            self.egress_metrics.bytes_to_send.clone(),
-            self.egress_metrics.frame_drops_full_egress_buffer.clone(),
-            self.egress_metrics.number_of_combined_frames.clone(),
+            &self.egress_metrics,
             client_id,
             tile_index,
         );
     }
     
     fn emit_frame_data(&self, frame: FrameTaskData) {
-        let stream_id = format!("client_{}_{}", frame.sfu_client_id.unwrap_or(0), frame.sfu_tile_index.unwrap_or(0));
-        // Copy the first three bytes from the frame data
-        let codec = frame.data.clone()[0..3].to_ascii_lowercase().to_vec();
-        let encoded = {
-            let bytes_vec: Vec<u8> = bitcode::encode(&frame);
-            let base64_encoded: String = rbase64::encode(&bytes_vec);
-            let bytes = Bytes::from(base64_encoded);
-            bytes.to_vec()
-
-        };
-
-        {
-            let mut storages = self.circular_storages.lock().unwrap();
-        
-            // Check if the stream already exists
-            if !storages.contains_key(&stream_id) {
-                let group_id = format!("client_{}_", frame.sfu_client_id.unwrap_or(0));
-                let fps = *self.fps.lock().unwrap();
-        
-                // Add stream to MPD
-                self.mpd_manager.add_stream_to_mpd(
-                    &group_id,
-                    &stream_id,
-                    "video/pc",
-                    &String::from_utf8_lossy(&codec),
-                    encoded.len().saturating_mul(fps.try_into().unwrap()).saturating_mul(8) as u64, // Bandwidth in bits
-                    fps as u64,
-                );
-
-                // Create the Mp4StreamConfig
-                let config = Mp4StreamConfig {
-                    timescale: fps * 1000,
-                    width: 1920,   // Example defaults
-                    height: 1080,
-                    codec_fourcc: [codec[0], codec[1], codec[2], b' '],
-                    track_id: frame.sfu_tile_index.unwrap_or(0) + 1, // The track ID starts at 1, so we add 1
-                    default_sample_duration: 1000, // This will be divided by the timescale
-                    codec_name: format!("PointCloudCodec_{}", String::from_utf8_lossy(&codec)),
-                };
-        
-                // Find the next available index within the group
-                let next_index = storages
-                    .iter()
-                    .filter(|(key, _)| key.starts_with(&group_id))
-                    .map(|(_, (_, index, _))| *index)
-                    .max()
-                    .unwrap_or(0);
-        
-                // Insert a new circular buffer and index
-                storages.insert(stream_id.clone(), (CircularBuffer::new(), next_index, config));
+        if frame.sfu_client_id.is_none() {
+            let ladder = self.ladder.lock().unwrap().clone();
+            if !ladder.is_empty() {
+                self.emit_ladder_frame(frame, &ladder);
+                return;
             }
-        
-            // Get a mutable reference to the stream
-            let (buffer, index, config) = storages.get_mut(&stream_id).unwrap();
-
-            // Decode time is the // Timeline position in timescale units
-            let decode_time = frame.presentation_time * config.timescale as u64 / 1000;
-            let segment_bytes = create_media_segment(
-                config,
-                &encoded, // Use the encoded Bytes directly
-                *index as u32,
-                decode_time,
-            );
-        
-            // Construct the buffer frame
-            let buffer_frame = BufferFrame {
-                index: *index,
-                data: segment_bytes, // TODO: instead of encoded, we should use the m4s file
-            };
-        
-            // Increment the index and store the frame
-            *index += 1;
-            buffer.push_back(buffer_frame);
-        
-            debug!("Stored frame in buffer of stream {} at index {}", stream_id, *index - 1);
         }
+
+        let tile_index = frame.sfu_tile_index.unwrap_or(0);
+        let stream_id = format!("client_{}_{}", frame.sfu_client_id.unwrap_or(0), tile_index);
+        let group_id = format!("client_{}_", frame.sfu_client_id.unwrap_or(0));
+        let adaptation_set_id = self.tile_tracks.then(|| tile_index.to_string());
+        // The track ID starts at 1, so we add 1
+        self.store_frame(&group_id, &stream_id, tile_index + 1, adaptation_set_id.as_deref(), &frame);
     }
 
     fn set_fps(&self, fps: u32) {
-        *self.fps.lock().unwrap() = fps;
+        let previous = std::mem::replace(&mut *self.fps.lock().unwrap(), fps);
+        if previous != fps {
+            self.start_new_periods(fps);
+        }
     }
 
     fn set_encoding_format(&self, encoding_format: EncodingFormat) {
@@ -291,4 +489,13 @@ impl EgressProtocol for BufferEgress {
     fn set_max_number_of_points(&self, max_number_of_points: u64) {
         *self.max_number_of_points.lock().unwrap() = max_number_of_points;
     }
+
+    fn set_frame_buffer_capacity(&self, capacity: usize) {
+        let dropped = self.frame_buffer.lock().unwrap().set_capacity(capacity);
+        self.egress_metrics.record_buffer_drops(&dropped);
+    }
+
+    fn flush_and_close(&self, timeout: Duration) {
+        drain_frame_buffer("BUF_E", &self.frame_buffer, timeout);
+    }
 }
\ No newline at end of file
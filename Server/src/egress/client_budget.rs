@@ -0,0 +1,59 @@
+// egress/client_budget.rs
+
+//! The point and frame rate budget of a single client, derived from the estimate of its available bitrate.
+
+/// The number of points and frames per second that a client can receive.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ClientBudget {
+    pub max_number_of_points: u64,
+    pub fps: u32,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct AdaptiveBudgetConfig {
+    /// Fraction of the estimated bitrate that the frames may use, the rest is left for retransmissions and FEC
+    pub headroom: f64,
+    /// The frame rate is only lowered once the point budget would drop below this number of points
+    pub min_number_of_points: u64,
+    /// The frame rate is never lowered below this
+    pub min_fps: u32,
+}
+
+impl Default for AdaptiveBudgetConfig {
+    fn default() -> Self {
+        Self {
+            headroom: 0.85,
+            min_number_of_points: 10_000,
+            min_fps: 5,
+        }
+    }
+}
+
+impl AdaptiveBudgetConfig {
+    /// Calculates the budget of a client with the given available bitrate.
+    /// The number of points is lowered first, as that keeps the motion smooth,
+    /// and only when the minimum number of points is reached, the frame rate is lowered as well.
+    pub fn budget(&self, bitrate: u64, bits_per_point: f64, max_number_of_points: u64, fps: u32) -> ClientBudget {
+        let full = ClientBudget { max_number_of_points, fps };
+        if bits_per_point <= 0.0 || fps == 0 {
+            return full;
+        }
+
+        let available_bits = bitrate as f64 * self.headroom;
+        let points = (available_bits / (bits_per_point * fps as f64)) as u64;
+        if points >= max_number_of_points {
+            return full;
+        }
+
+        let min_number_of_points = self.min_number_of_points.min(max_number_of_points);
+        if points >= min_number_of_points {
+            return ClientBudget { max_number_of_points: points, fps };
+        }
+
+        let frames = (available_bits / (bits_per_point * min_number_of_points as f64)) as u32;
+        ClientBudget {
+            max_number_of_points: min_number_of_points,
+            fps: frames.clamp(self.min_fps.min(fps), fps),
+        }
+    }
+}
@@ -1,47 +1,91 @@
+use std::collections::HashMap;
 use std::sync::{Arc, Mutex};
 use std::thread;
 use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 
 use crate::encoders::EncodingFormat;
 use crate::processing::aggregator::PointCloudAggregator;
-use crate::processing::ProcessingPipeline;
+use crate::processing::{encoder_key, ProcessingPipeline};
+use crate::services::shutdown::is_shutting_down;
+use crate::services::stream_manager::{StreamManager, COMBINED_STREAM_ID};
 use shared_utils::types::{FrameTaskData, PointCloudData};
-use circular_buffer::CircularBuffer;
-use metrics::get_metrics;
-use prometheus::IntGauge;
+use super::backpressure::Backpressure;
+use super::ring_buffer::RingBuffer;
+use super::tile_priority::get_tile_priorities;
+use metrics::{duration_buckets_us, get_metrics};
+use prometheus::{Histogram, HistogramVec, IntCounter, IntCounterVec, IntGauge, IntGaugeVec};
 //use rayon::ThreadPoolBuilder;
 use tracing::{debug, error, warn, instrument};
 
+/// The metrics that all egress protocols share, labeled with the name of the egress and the stream id,
+/// so the same stream can be compared across the egress paths.
 #[derive(Clone, Debug)]
 pub struct EgressCommonMetrics {
-    pub pc_combination_time: IntGauge,
-    pub pc_encoding_time: IntGauge,
+    pc_combination_time: HistogramVec,
+    pc_encoding_time: HistogramVec,
+    bytes_to_send: IntGaugeVec,
+    number_of_combined_frames: IntCounterVec,
+    frame_drops_full_egress_buffer: IntCounterVec,
+    total_processing_time: HistogramVec,
+    emission_time: HistogramVec,
+    frame_drops_before_emission: IntCounterVec,
+    frames_to_emit: IntCounterVec,
+    egress_name: String,
+}
+
+/// The metrics of a single stream on a single egress.
+#[derive(Clone, Debug)]
+pub struct EgressStreamMetrics {
+    pub pc_combination_time: Histogram,
+    pub pc_encoding_time: Histogram,
     pub bytes_to_send: IntGauge,
-    pub number_of_combined_frames: IntGauge,
-    pub frame_drops_full_egress_buffer: IntGauge,
+    pub number_of_combined_frames: IntCounter,
+    pub frame_drops_full_egress_buffer: IntCounter,
+    pub total_processing_time: Histogram,
+    pub emission_time: Histogram,
+    pub frame_drops_before_emission: IntCounter,
+    pub frames_to_emit: IntCounter,
 }
 
 impl EgressCommonMetrics {
-    pub fn new() -> Self {
+    /// Creates the metrics of a single egress protocol, labeled with its name.
+    pub fn new(egress_name: &str) -> Self {
         let metrics = get_metrics();
+        let labels = &["egress", "stream"];
         let pc_combination_time = metrics
-            .get_or_create_gauge("pc_combination_time", "Time taken to generate a combined point_cloud")
+            .get_or_create_histogram_vec("pc_combination_time", "Time (us) taken to generate a combined point_cloud", duration_buckets_us(), labels)
             .unwrap();
 
         let pc_encoding_time = metrics
-            .get_or_create_gauge("pc_encoding_time", "Time taken to encode a combined point_cloud")
+            .get_or_create_histogram_vec("pc_encoding_time", "Time (us) taken to encode a combined point_cloud", duration_buckets_us(), labels)
             .unwrap();
 
         let bytes_to_send = metrics
-            .get_or_create_gauge("bytes_to_send", "Number of bytes to send")
+            .get_or_create_gauge_vec("bytes_to_send", "Number of bytes to send", labels)
             .unwrap();
 
         let number_of_combined_frames = metrics
-            .get_or_create_gauge("number_of_combined_frames", "Number of combined frames generated and pushed to the egress buffer based on the frames in the aggregator")
+            .get_or_create_counter_vec("number_of_combined_frames", "Number of combined frames generated and pushed to the egress buffer based on the frames in the aggregator", labels)
             .unwrap();
 
         let frame_drops_full_egress_buffer = metrics
-            .get_or_create_gauge("frame_drops_full_egress_buffer", "Number of dropped frames due to a full egress buffer.")
+            .get_or_create_counter_vec("frame_drops_full_egress_buffer", "Number of dropped frames due to a full egress buffer.", labels)
+            .unwrap();
+
+        let total_processing_time = metrics
+            .get_or_create_histogram_vec("total_processing_time", "Total time (us) taken to process a frame. From the moment we started to create this frame, until we started to send it.", duration_buckets_us(), labels)
+            .unwrap();
+
+        let emission_time = metrics
+            .get_or_create_histogram_vec("emission_time", "Total time (us) taken to emit a frame. From the moment we started to send this frame, until we finished sending it.", duration_buckets_us(), labels)
+            .unwrap();
+
+        let frame_drops_before_emission = metrics
+            .get_or_create_counter_vec("frame_drops_before_emission", "Number of dropped frames.", labels)
+            .unwrap();
+
+        let frames_to_emit = metrics
+            .get_or_create_counter_vec("frames_to_emit", "Number of frames that we selected for emission.", labels)
             .unwrap();
 
         Self {
@@ -50,10 +94,52 @@ impl EgressCommonMetrics {
             bytes_to_send,
             number_of_combined_frames,
             frame_drops_full_egress_buffer,
+            total_processing_time,
+            emission_time,
+            frame_drops_before_emission,
+            frames_to_emit,
+            egress_name: egress_name.to_string(),
+        }
+    }
+
+    /// The metrics of one stream of this egress.
+    pub fn for_stream(&self, stream_id: &str) -> EgressStreamMetrics {
+        let labels = &[self.egress_name.as_str(), stream_id];
+        EgressStreamMetrics {
+            pc_combination_time: self.pc_combination_time.with_label_values(labels),
+            pc_encoding_time: self.pc_encoding_time.with_label_values(labels),
+            bytes_to_send: self.bytes_to_send.with_label_values(labels),
+            number_of_combined_frames: self.number_of_combined_frames.with_label_values(labels),
+            frame_drops_full_egress_buffer: self.frame_drops_full_egress_buffer.with_label_values(labels),
+            total_processing_time: self.total_processing_time.with_label_values(labels),
+            emission_time: self.emission_time.with_label_values(labels),
+            frame_drops_before_emission: self.frame_drops_before_emission.with_label_values(labels),
+            frames_to_emit: self.frames_to_emit.with_label_values(labels),
+        }
+    }
+
+    /// The metrics of the stream that a frame belongs to, see `frame_stream_id`.
+    pub fn for_frame(&self, frame: &FrameTaskData) -> EgressStreamMetrics {
+        self.for_stream(&frame_stream_id(frame))
+    }
+
+    /// Counts the frames that were dropped from the egress buffer, e.g. when its capacity was lowered.
+    pub fn record_buffer_drops(&self, frames: &[FrameTaskData]) {
+        for frame in frames {
+            self.for_frame(frame).frame_drops_full_egress_buffer.inc();
         }
     }
 }
 
+/// The stream id that the metrics of a frame are labeled with.
+/// The frames only carry the client and tile of the SFU, so the other frames are counted under the combined stream.
+pub fn frame_stream_id(frame: &FrameTaskData) -> String {
+    match frame.sfu_client_id {
+        Some(client_id) => format!("client_{}_{}", client_id, frame.sfu_tile_index.unwrap_or(0)),
+        None => COMBINED_STREAM_ID.to_string(),
+    }
+}
+
 /// Starts the generator thread that periodically generates combined point clouds
 /// and encodes them into frames.
 #[instrument(skip_all, fields(egress_name = %egress_name))]
@@ -61,7 +147,7 @@ pub fn start_generator_thread(
     egress_name: String,
     processing_pipeline: Arc<ProcessingPipeline>,
     aggregator: Arc<PointCloudAggregator>,
-    frame_buffer: Arc<Mutex<CircularBuffer<10, FrameTaskData>>>,
+    frame_buffer: Arc<Mutex<RingBuffer<FrameTaskData>>>,
     fps: Arc<Mutex<u32>>,
     encoding_format: Arc<Mutex<EncodingFormat>>,
     max_number_of_points: Arc<Mutex<u64>>,
@@ -93,18 +179,18 @@ fn generate_and_send_combined_point_clouds(
     egress_name: String,
     processing_pipeline: Arc<ProcessingPipeline>,
     aggregator: Arc<PointCloudAggregator>,
-    frame_buffer: Arc<Mutex<CircularBuffer<10, FrameTaskData>>>,
+    frame_buffer: Arc<Mutex<RingBuffer<FrameTaskData>>>,
     fps: Arc<Mutex<u32>>,
     encoding_format: Arc<Mutex<EncodingFormat>>,
     max_number_of_points: Arc<Mutex<u64>>,
 ) {
     let current_in_queue = Arc::new(Mutex::new(0));
-    let egress_common_metrics = EgressCommonMetrics::new();
-    let pc_combination_time = egress_common_metrics.pc_combination_time;
-    let pc_encoding_time = egress_common_metrics.pc_encoding_time;
-    let bytes_to_send = egress_common_metrics.bytes_to_send;
-    let number_of_combined_frames = egress_common_metrics.number_of_combined_frames;
-    let frame_drops_full_egress_buffer = egress_common_metrics.frame_drops_full_egress_buffer;
+    let egress_metrics = EgressCommonMetrics::new(&egress_name);
+    let mut backpressure = Backpressure::default();
+    let generation_paused = get_metrics()
+        .get_or_create_counter_vec("generation_paused_backpressure", "Number of frames that were not generated because the egress buffer was nearly full", &["egress"])
+        .unwrap()
+        .with_label_values(&[&egress_name]);
 
 
     //// Initialize thread pool
@@ -119,6 +205,12 @@ fn generate_and_send_combined_point_clouds(
     */
 
     loop {
+        // No new frames are generated during the shutdown, only the buffered ones are emitted
+        if is_shutting_down() {
+            debug!("Stopping the frame generation of {}", egress_name);
+            break;
+        }
+
         let fps_value = *fps.lock().unwrap();
         let frame_duration = Duration::from_micros(1_000_000 / fps_value as u64);
         let start_time = Instant::now();
@@ -136,6 +228,25 @@ fn generate_and_send_combined_point_clouds(
             continue;
         }
 
+        // Pause the generation while the buffer is nearly full, the frames would only push out the buffered ones.
+        // The frames that are still being encoded will end up in the buffer as well.
+        let (buffered, capacity) = {
+            let buffer = frame_buffer.lock().unwrap();
+            (buffer.len(), buffer.capacity())
+        };
+        let occupancy = buffered + current_in_queue_clone.max(0) as usize;
+        let was_paused = backpressure.is_paused();
+        if backpressure.should_pause(aggregator.stream_manager().backpressure_watermarks(), occupancy, capacity) {
+            if !was_paused {
+                debug!("({}) Egress buffer is nearly full ({}/{}), pausing the frame generation", egress_name, occupancy, capacity);
+            }
+            generation_paused.inc();
+            thread::sleep(frame_duration);
+            continue;
+        } else if was_paused {
+            debug!("({}) Egress buffer has room again ({}/{}), resuming the frame generation", egress_name, occupancy, capacity);
+        }
+
         // Generate the point cloud for the egress
         let generate_start_time = start_time;
         handle_point_cloud_generation(
@@ -146,11 +257,7 @@ fn generate_and_send_combined_point_clouds(
             &encoding_format,
             &max_number_of_points,
             &current_in_queue,
-            &pc_combination_time,
-            &pc_encoding_time,
-            &bytes_to_send,
-            &number_of_combined_frames,
-            &frame_drops_full_egress_buffer,
+            &egress_metrics,
             generate_start_time,
             false // to do; add ring buffer bypass
         );
@@ -186,15 +293,11 @@ fn handle_point_cloud_generation(
     egress_name: &str,
     processing_pipeline: &Arc<ProcessingPipeline>,
     aggregator: &Arc<PointCloudAggregator>,
-    frame_buffer: &Arc<Mutex<CircularBuffer<10, FrameTaskData>>>,
+    frame_buffer: &Arc<Mutex<RingBuffer<FrameTaskData>>>,
     encoding_format: &Arc<Mutex<EncodingFormat>>,
     max_number_of_points: &Arc<Mutex<u64>>,
     current_in_queue: &Arc<Mutex<i32>>,
-    pc_combination_time: &IntGauge,
-    pc_encoding_time: &IntGauge,
-    bytes_to_send: &IntGauge,
-    number_of_combined_frames: &IntGauge,
-    frame_drops_full_egress_buffer: &IntGauge,
+    egress_metrics: &EgressCommonMetrics,
     generate_start_time: Instant,
     ring_buffer_bypass: bool,
 ) {
@@ -211,7 +314,7 @@ fn handle_point_cloud_generation(
     let max_points = *max_number_of_points.lock().unwrap();
     let combined_point_cloud = aggregator.generate_combined_point_cloud(max_points);
 
-    pc_combination_time.set(generate_start_time.elapsed().as_micros() as i64);                
+    egress_metrics.for_stream(COMBINED_STREAM_ID).pc_combination_time.observe(generate_start_time.elapsed().as_micros() as f64);                
 
     // If the combined point cloud is empty, then skip
     if combined_point_cloud.points.is_empty() {
@@ -229,23 +332,19 @@ fn handle_point_cloud_generation(
     let frame_buffer = Arc::clone(frame_buffer);
     let encoding_format = Arc::clone(encoding_format);
     let current_in_queue = Arc::clone(current_in_queue);
-    let pc_encoding_time = pc_encoding_time.clone();
-    let bytes_to_send = bytes_to_send.clone();
-    let number_of_combined_frames = number_of_combined_frames.clone();
-    let frame_drops_full_egress_buffer = frame_drops_full_egress_buffer.clone();
+    let egress_metrics = egress_metrics.clone();
     let ring_buffer_bypass = ring_buffer_bypass.clone();
+    let stream_manager = Arc::clone(aggregator.stream_manager());
     thread_pool.spawn(move || {
         encode_point_cloud(
             egress_name,
             combined_point_cloud,
             processing_pipeline,
+            stream_manager,
             frame_buffer,
             encoding_format,
             current_in_queue,
-            pc_encoding_time,
-            bytes_to_send,
-            number_of_combined_frames,
-            frame_drops_full_egress_buffer,
+            egress_metrics,
             ring_buffer_bypass
         );
     });
@@ -260,13 +359,11 @@ fn encode_point_cloud(
     egress_name: String,
     combined_point_cloud: PointCloudData,
     processing_pipeline: Arc<ProcessingPipeline>,
-    frame_buffer: Arc<Mutex<CircularBuffer<10, FrameTaskData>>>,
+    stream_manager: Arc<StreamManager>,
+    frame_buffer: Arc<Mutex<RingBuffer<FrameTaskData>>>,
     encoding_format: Arc<Mutex<EncodingFormat>>,
     current_in_queue: Arc<Mutex<i32>>,
-    pc_encoding_time: IntGauge,
-    bytes_to_send: IntGauge,
-    number_of_combined_frames: IntGauge,
-    frame_drops_full_egress_buffer: IntGauge,
+    egress_metrics: EgressCommonMetrics,
     _ring_buffer_bypass: bool,
 ) {
 
@@ -274,18 +371,20 @@ fn encode_point_cloud(
     let encoding_start_time = Instant::now();
 
     let encoding_format = *encoding_format.lock().unwrap();
-    let encoded_point_cloud = processing_pipeline.encode(combined_point_cloud, encoding_format);
+    let encoded_point_cloud = processing_pipeline.encode_for_stream(combined_point_cloud, encoding_format, &stream_manager, COMBINED_STREAM_ID, &encoder_key(&egress_name, COMBINED_STREAM_ID));
     match encoded_point_cloud {
-        Ok(encoded_data) => {
-            push_encoded_frame_data(
-                &egress_name,
-                &frame_buffer,
-                encoded_data,
-                None,
-                &bytes_to_send,
-                &frame_drops_full_egress_buffer,
-                &number_of_combined_frames,
-            );
+        Ok(mut encoded_data) => {
+            // A frame that could not be encrypted is dropped, it must not be sent in the clear
+            if let Some(data) = stream_manager.encrypt_payload(COMBINED_STREAM_ID, std::mem::take(&mut encoded_data.data)) {
+                encoded_data.data = data;
+                push_encoded_frame_data(
+                    &egress_name,
+                    &frame_buffer,
+                    encoded_data,
+                    None,
+                    &egress_metrics,
+                );
+            }
         }
         Err(e) => {
             // Handle encoding error
@@ -293,7 +392,7 @@ fn encode_point_cloud(
         }
     };
 
-    pc_encoding_time.set(encoding_start_time.elapsed().as_micros() as i64);
+    egress_metrics.for_stream(COMBINED_STREAM_ID).pc_encoding_time.observe(encoding_start_time.elapsed().as_micros() as f64);
 
     // Decrease the current in queue count
     let mut current_in_queue = current_in_queue.lock().unwrap();
@@ -304,14 +403,13 @@ fn encode_point_cloud(
 /// or bypass it if `ring_buffer_bypass` is true.
 pub fn push_encoded_frame_data(
     egress_name: &str,
-    frame_buffer: &Arc<Mutex<CircularBuffer<10, FrameTaskData>>>,
+    frame_buffer: &Arc<Mutex<RingBuffer<FrameTaskData>>>,
     frame: FrameTaskData,
     ring_buffer_bypass: Option<Box<dyn Fn(FrameTaskData) + Send + 'static>>,
-    bytes_to_send: &IntGauge,
-    frame_drops_full_egress_buffer: &IntGauge,
-    number_of_combined_frames: &IntGauge,
+    egress_metrics: &EgressCommonMetrics,
 ) {
-    bytes_to_send.set(frame.data.len() as i64);
+    let stream_metrics = egress_metrics.for_frame(&frame);
+    stream_metrics.bytes_to_send.set(frame.data.len() as i64);
 
     if let Some(ref bypass_fn) = ring_buffer_bypass {
         // Bypass ring buffer => you could directly emit here (if you like)
@@ -328,11 +426,23 @@ pub fn push_encoded_frame_data(
     // Otherwise, push into the ring buffer as before:
     let mut buffer = frame_buffer.lock().unwrap();
     if buffer.is_full() {
-        debug!("({}) Frame buffer is full, dropping oldest frame", egress_name);
-        frame_drops_full_egress_buffer.inc();
+        // Make room by dropping the least visible tile, which is the oldest frame when no client reported on the tiles
+        match get_tile_priorities().frame_index_to_drop(&buffer, &frame) {
+            Some(index) => {
+                if let Some(dropped) = buffer.remove(index) {
+                    debug!("({}) Frame buffer is full, dropped the frame with the lowest priority", egress_name);
+                    egress_metrics.for_frame(&dropped).frame_drops_full_egress_buffer.inc();
+                }
+            }
+            None => {
+                debug!("({}) Frame buffer is full of frames with a higher priority, dropped the new frame", egress_name);
+                stream_metrics.frame_drops_full_egress_buffer.inc();
+                return;
+            }
+        }
     }
     buffer.push_back(frame);
-    number_of_combined_frames.inc();
+    stream_metrics.number_of_combined_frames.inc();
     debug!("({}) Pushed encoded frame to buffer", egress_name);
 }
 
@@ -343,14 +453,12 @@ pub fn push_encoded_frame_data(
 #[allow(clippy::too_many_arguments)]
 pub fn push_preencoded_frame_data(
     egress_name: &str,
-    frame_buffer: &Arc<Mutex<CircularBuffer<10, FrameTaskData>>>,
+    frame_buffer: &Arc<Mutex<RingBuffer<FrameTaskData>>>,
     creation_time: u64,
     presentation_time: u64,
     data: Vec<u8>,
     ring_buffer_bypass: Option<Box<dyn Fn(FrameTaskData) + Send + 'static>>,
-    bytes_to_send: IntGauge,
-    number_of_combined_frames: IntGauge,
-    frame_drops_full_egress_buffer: IntGauge,
+    egress_metrics: &EgressCommonMetrics,
     sfu_client_id: Option<u64>,
     sfu_tile_index: Option<u32>,
 ) {
@@ -372,9 +480,7 @@ pub fn push_preencoded_frame_data(
         frame_buffer,
         frame,
         ring_buffer_bypass,
-        &bytes_to_send,
-        &frame_drops_full_egress_buffer,
-        &number_of_combined_frames,
+        egress_metrics,
     );
 }
 
@@ -382,7 +488,7 @@ pub fn push_preencoded_frame_data(
 #[instrument(skip_all, fields(egress_name = %egress_name))]
 pub fn start_transmission_thread<F>(
     egress_name: String,
-    frame_buffer: Arc<Mutex<CircularBuffer<10, FrameTaskData>>>,
+    frame_buffer: Arc<Mutex<RingBuffer<FrameTaskData>>>,
     emit_frame_data: F,
     disable_frame_drops: bool
 ) where
@@ -403,31 +509,26 @@ pub fn start_transmission_thread<F>(
 #[instrument(skip_all, fields(egress_name = %egress_name))]
 fn send_frames_to_clients<F>(
     egress_name: String,
-    frame_buffer: Arc<Mutex<CircularBuffer<10, FrameTaskData>>>,
+    frame_buffer: Arc<Mutex<RingBuffer<FrameTaskData>>>,
     emit_frame_data: F,
     disable_frame_drops: bool,
 ) where
     F: Fn(FrameTaskData) + Send + 'static + Clone,
 {
     let metrics = get_metrics();
-    let total_processing_time = metrics
-        .get_or_create_gauge("total_processing_time", "Total time taken to process a frame. From the moment we started to create this frame, until we started to send it.")
-        .unwrap();
+    let egress_metrics = EgressCommonMetrics::new(&egress_name);
 
-    let emission_time = metrics
-        .get_or_create_gauge("emission_time", "Total time taken to emit a frame. From the moment we started to send this frame, until we finished sending it.")
+    let emitted_bytes_rate = metrics
+        .get_or_create_rate_gauge("emitted_bytes_rate", "Bytes per second emitted to the clients, over the last second.", Duration::from_secs(1), &[("egress", &egress_name)])
         .unwrap();
 
-    let frame_drops_before_emission = metrics
-        .get_or_create_gauge("frame_drops_before_emission", "Number of dropped frames.")
+    let emitted_frames_rate = metrics
+        .get_or_create_rate_gauge("emitted_frames_rate", "Frames per second emitted to the clients, over the last second.", Duration::from_secs(1), &[("egress", &egress_name)])
         .unwrap();
 
-    let frames_to_emit = metrics
-        .get_or_create_gauge("frames_to_emit", "Number of frames that we selected for emission.")
-        .unwrap();
-
-    let mut max_send_time: u64 = 0;
-    let mut _max_presentation_time: u64 = 0;
+    // The send time of the last emitted frame per stream, frames of different tiles are emitted by priority rather than in order
+    let mut max_send_times: HashMap<String, u64> = HashMap::new();
+    let tile_priorities = get_tile_priorities();
 
     loop {
         // Get the current time
@@ -439,28 +540,26 @@ fn send_frames_to_clients<F>(
         let frame_opt = {
             let mut buffer_lock = frame_buffer.lock().unwrap();
 
-            if !disable_frame_drops {
-                // Loop to drop frames older than the max send time
+            loop {
+                // The oldest frame of the tile with the highest priority, or simply the oldest frame
+                let Some(index) = tile_priorities.next_frame_index(&buffer_lock) else {
+                    break None;
+                };
+                let frame = buffer_lock.get(index).unwrap();
+                let max_send_time = max_send_times.get(&frame_stream_id(frame)).copied().unwrap_or(0);
+
+                // Drop frames older than the max send time of their stream
                 // This makes sure that only newer frames are emitted.
-                while let Some(frame) = buffer_lock.front() {
-                    let send_time = frame.send_time;
-
-                    // Check if the frame is too old, meaning it's older than the current max presentation time.
-                    if send_time <= max_send_time && buffer_lock.len() >= 1 {
-                        debug!("Dropped a frame that was older than a previously emitted frame");
-                        frame_drops_before_emission.inc();
-                        // This is non-ideal, but we assume that our clients their buffers are
-                        // not large enough and thus could have already rendered the previously emitted frame.
-                        // As such, this frame has become redundant and we can safely drop it to prevent unnecessary bandwidth usage.
-                        buffer_lock.pop_front(); // Remove the outdated frame
-                    } else {
-                        break; // Exit the loop if no more old frames are found
-                    }
+                if !disable_frame_drops && frame.send_time <= max_send_time {
+                    debug!("Dropped a frame that was older than a previously emitted frame");
+                    egress_metrics.for_frame(frame).frame_drops_before_emission.inc();
+                    // This is non-ideal, but we assume that our clients their buffers are
+                    // not large enough and thus could have already rendered the previously emitted frame.
+                    // As such, this frame has become redundant and we can safely drop it to prevent unnecessary bandwidth usage.
+                    buffer_lock.remove(index); // Remove the outdated frame
+                    continue;
                 }
-            }
 
-            // Check if there's a frame ready to be emitted
-            if let Some(frame) = buffer_lock.front() {
                 // TODO2: maybe add ability to overwrite the presentation time of the frame
                 // At this point, we already know that the frame is not older than any previously emitted frame, so we can safely overwrite the presentation time, as long as we make sure that the new presentation time is not smaller than the max_presentation_time.
                 // We could dynamically adjust the presentation time based on the actual
@@ -478,44 +577,44 @@ fn send_frames_to_clients<F>(
                 // We hope that the next frame is newer
                 // If there is only 1 frame in the buffer, we'll emit it anyway
                 // TODO: we should continue dropping such that we can catch up with the latest frame
-                // TODO: we should keep track of the presentation time of the latest emitted frame and drop frames that are older than that
                 if !disable_frame_drops && presentation_time < current_time && buffer_lock.len() > 1 {
-                    buffer_lock.pop_front();
+                    if let Some(dropped) = buffer_lock.remove(index) {
+                        egress_metrics.for_frame(&dropped).frame_drops_before_emission.inc();
+                    }
                     debug!("Dropped frame with presentation time: {}", presentation_time);
-                    frame_drops_before_emission.inc();
-                    None
-                } else {
-                    Some(buffer_lock.pop_front().unwrap())
+                    break None;
                 }
-            } else {
-                None
+                break buffer_lock.remove(index);
             }
         };
 
         // Emit the frame if available
         if let Some(mut frame) = frame_opt {
-            frames_to_emit.inc();
+            let stream_metrics = egress_metrics.for_frame(&frame);
+            stream_metrics.frames_to_emit.inc();
 
-            // Update the max send time and presentation time
-            max_send_time = frame.send_time;
-            _max_presentation_time = frame.presentation_time;
+            // Update the max send time of the stream
+            max_send_times.insert(frame_stream_id(&frame), frame.send_time);
                     // Get the current time
             let since_the_epoch = SystemTime::now()
                 .duration_since(UNIX_EPOCH)
                 .expect("Time went backwards");
             let current_time = since_the_epoch.as_micros() as u64;
-            total_processing_time.set((current_time - frame.send_time) as i64);
+            stream_metrics.total_processing_time.observe(current_time.saturating_sub(frame.send_time) as f64);
 
             let emit_start = Instant::now();
 
             // Update the send time, to be used by the client metrics
             frame.send_time = current_time;
+            let frame_size = frame.data.len() as u64;
 
             // Emit the frame
             emit_frame_data(frame);
 
-            let time_to_emit_frame = emit_start.elapsed().as_micros() as i64;
-            emission_time.set(time_to_emit_frame);
+            let time_to_emit_frame = emit_start.elapsed().as_micros() as f64;
+            stream_metrics.emission_time.observe(time_to_emit_frame);
+            emitted_bytes_rate.add(frame_size);
+            emitted_frames_rate.add(1);
 
             debug!("Emitted frame");
 
@@ -535,7 +634,9 @@ fn send_frames_to_clients<F>(
     }
 }
 
-pub trait EgressProtocol: Send + Sync {
+pub trait EgressProtocol: Send + Sync + std::fmt::Debug {
+    fn fps(&self) -> u32;
+
     fn encoding_format(&self) -> EncodingFormat;
 
     fn max_number_of_points(&self) -> u64;
@@ -571,4 +672,33 @@ pub trait EgressProtocol: Send + Sync {
     #[instrument(skip_all)]
     #[allow(unused_variables)]
     fn set_max_number_of_points(&self, max_number_of_points: u64);
+
+    /// Sets the number of frames that can wait in the buffer between the generator and the transmission thread.
+    /// A larger buffer rides out hiccups in the transmission, a smaller one keeps the latency low.
+    #[instrument(skip_all)]
+    #[allow(unused_variables)]
+    fn set_frame_buffer_capacity(&self, capacity: usize);
+
+    /// Called when the server shuts down: waits (at most `timeout`) until the buffered frames are emitted,
+    /// then finishes files and closes connections, so receivers and recordings are left in a consistent state.
+    #[instrument(skip_all)]
+    #[allow(unused_variables)]
+    fn flush_and_close(&self, timeout: Duration);
+}
+
+/// Waits until the transmission thread emitted all frames in the buffer, or until the timeout passes.
+/// Returns whether the buffer is empty.
+pub fn drain_frame_buffer(egress_name: &str, frame_buffer: &Arc<Mutex<RingBuffer<FrameTaskData>>>, timeout: Duration) -> bool {
+    let deadline = Instant::now() + timeout;
+    loop {
+        let remaining = frame_buffer.lock().unwrap().len();
+        if remaining == 0 {
+            return true;
+        }
+        if Instant::now() >= deadline {
+            warn!("({}) {} frames were not emitted before the shutdown", egress_name, remaining);
+            return false;
+        }
+        thread::sleep(Duration::from_millis(5));
+    }
 }
\ No newline at end of file
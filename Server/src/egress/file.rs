@@ -1,30 +1,203 @@
 // egress/file.rs
 
 use std::{
-    fs::{self, File}, io::Write, path::PathBuf, sync::{atomic::{AtomicBool, Ordering}, Arc, Mutex}, time::{SystemTime, UNIX_EPOCH}
+    collections::{HashMap, VecDeque}, fs::{self, File}, io::Write, path::PathBuf, sync::{atomic::{AtomicBool, Ordering}, Arc, Mutex}, time::{Duration, SystemTime, UNIX_EPOCH}
 };
 
 use crate::{
     encoders::EncodingFormat,
     processing::{aggregator::PointCloudAggregator, ProcessingPipeline},
-    services::stream_manager::StreamManager
+    services::stream_manager::StreamManager,
+    types::EgressProtocolType,
 };
+use dash_player::mpd::builder::MpdBuilder;
+use mp4_box::writer::{create_init_segment, create_media_chunk, create_media_segment, Mp4StreamConfig};
 use shared_utils::types::{FrameTaskData, PointCloudData};
-use circular_buffer::CircularBuffer;
-use tracing::{debug, error, info, instrument};
+use super::ring_buffer::RingBuffer;
+use tracing::{debug, error, info, instrument, warn};
 
-use super::egress_common::{push_preencoded_frame_data, EgressCommonMetrics, EgressProtocol};
+use super::egress_common::{drain_frame_buffer, push_preencoded_frame_data, EgressCommonMetrics, EgressProtocol};
+
+/// How the FileEgress stores the frames of a stream.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RecordingMode {
+    /// Every frame is written to its own file, named after its send time
+    Frames,
+    /// Frames are appended to fragmented MP4 segments, described by a static MPD
+    Segments,
+}
+
+impl RecordingMode {
+    pub fn parse(value: &str) -> Option<Self> {
+        match value.to_lowercase().as_str() {
+            "frames" => Some(RecordingMode::Frames),
+            "segments" => Some(RecordingMode::Segments),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct RecordingSettings {
+    pub mode: RecordingMode,
+    /// Duration of one segment, the number of frames per segment is derived from it with the fps
+    pub segment_duration_ms: u64,
+    /// Number of segments that are kept per stream, older segments are deleted. 0 keeps all segments.
+    pub max_segments: usize,
+}
+
+impl Default for RecordingSettings {
+    fn default() -> Self {
+        Self {
+            mode: RecordingMode::Frames,
+            segment_duration_ms: 2000,
+            max_segments: 0,
+        }
+    }
+}
+
+/// Writes the frames of a single stream to rotating fragmented MP4 segments.
+#[derive(Debug)]
+struct SegmentRecorder {
+    directory: PathBuf,
+    stream_id: String,
+    codec: String,
+    fps: u32,
+    config: Mp4StreamConfig,
+    frames_per_segment: u64,
+    max_segments: usize,
+    /// Number of the segment that is being written
+    segment_number: u64,
+    frames_in_segment: u64,
+    bytes_in_segment: u64,
+    /// Total number of frames written, used as decode time
+    frame_index: u64,
+    file: Option<File>,
+    /// Number and size of the completed segments that are still on disk
+    completed: VecDeque<(u64, u64)>,
+}
+
+impl SegmentRecorder {
+    fn new(directory: PathBuf, stream_id: String, codec: String, fps: u32, tile_index: u32, settings: &RecordingSettings) -> std::io::Result<Self> {
+        let config = Mp4StreamConfig {
+            timescale: fps * 1000,
+            width: 1920,
+            height: 1080,
+            codec_fourcc: {
+                let codec = codec.as_bytes();
+                [codec[0], codec[1], codec[2], b' ']
+            },
+            track_id: tile_index + 1, // The track ID starts at 1
+            default_sample_duration: 1000, // This will be divided by the timescale
+            codec_name: format!("PointCloudCodec_{}", codec),
+        };
+        fs::write(directory.join("init.mp4"), create_init_segment(&config))?;
+
+        Ok(Self {
+            directory,
+            stream_id,
+            codec,
+            fps,
+            config,
+            frames_per_segment: (settings.segment_duration_ms * fps as u64 / 1000).max(1),
+            max_segments: settings.max_segments,
+            segment_number: 1,
+            frames_in_segment: 0,
+            bytes_in_segment: 0,
+            frame_index: 0,
+            file: None,
+            completed: VecDeque::new(),
+        })
+    }
+
+    fn segment_path(&self, segment_number: u64) -> PathBuf {
+        self.directory.join(format!("segment_{:09}.m4s", segment_number))
+    }
+
+    fn write_frame(&mut self, data: &[u8]) -> std::io::Result<()> {
+        let decode_time = self.frame_index * self.config.default_sample_duration as u64;
+        let bytes = if self.file.is_none() {
+            self.file = Some(File::create(self.segment_path(self.segment_number))?);
+            create_media_segment(&self.config, data, self.frame_index as u32, decode_time)
+        } else {
+            create_media_chunk(&self.config, data, self.frame_index as u32, decode_time)
+        };
+
+        self.file.as_mut().unwrap().write_all(&bytes)?;
+        self.frame_index += 1;
+        self.frames_in_segment += 1;
+        self.bytes_in_segment += bytes.len() as u64;
+
+        if self.frames_in_segment >= self.frames_per_segment {
+            self.finish_segment()?;
+        }
+        Ok(())
+    }
+
+    /// Closes the current segment, removes the segments that fall outside the retention and updates the manifest.
+    fn finish_segment(&mut self) -> std::io::Result<()> {
+        let Some(mut file) = self.file.take() else {
+            return Ok(());
+        };
+        file.flush()?;
+
+        self.completed.push_back((self.segment_number, self.bytes_in_segment));
+        self.segment_number += 1;
+        self.frames_in_segment = 0;
+        self.bytes_in_segment = 0;
+
+        while self.max_segments > 0 && self.completed.len() > self.max_segments {
+            let (oldest, _) = self.completed.pop_front().unwrap();
+            if let Err(e) = fs::remove_file(self.segment_path(oldest)) {
+                warn!("Failed to remove segment {} of stream {}: {}", oldest, self.stream_id, e);
+            }
+        }
+
+        self.write_manifest()
+    }
+
+    /// Writes a static MPD that describes the segments that are on disk.
+    fn write_manifest(&self) -> std::io::Result<()> {
+        let Some((first_segment, _)) = self.completed.front() else {
+            return Ok(());
+        };
+        let segment_duration = self.frames_per_segment as f64 / self.fps as f64;
+        let duration = segment_duration * self.completed.len() as f64;
+        let total_bytes: u64 = self.completed.iter().map(|(_, bytes)| bytes).sum();
+        let bandwidth = (total_bytes as f64 * 8.0 / duration) as u64;
+
+        let mpd = MpdBuilder::recording(duration)
+            .segment_duration(self.frames_per_segment * 1000, self.fps as u64 * 1000)
+            .start_number(*first_segment)
+            .add_representation(
+                &self.stream_id,
+                "video/pc",
+                &self.codec,
+                bandwidth,
+                "init.mp4",
+                "segment_$Number%09d$.m4s",
+                None,
+                None,
+            )
+            .build_xml_string()
+            .map_err(|e| std::io::Error::other(e.to_string()))?;
+
+        fs::write(self.directory.join("manifest.mpd"), mpd)
+    }
+}
 
 #[derive(Clone, Debug)]
 pub struct FileEgress {
     processing_pipeline: Arc<ProcessingPipeline>,
-    frame_buffer: Arc<Mutex<CircularBuffer<10, FrameTaskData>>>,
+    frame_buffer: Arc<Mutex<RingBuffer<FrameTaskData>>>,
     aggregator: Arc<PointCloudAggregator>,
     threads_started: Arc<AtomicBool>,
     fps: Arc<Mutex<u32>>,
     encoding_format: Arc<Mutex<EncodingFormat>>,
     max_number_of_points: Arc<Mutex<u64>>,
     egress_metrics: Arc<EgressCommonMetrics>,
+    recording_settings: Arc<Mutex<RecordingSettings>>,
+    recorders: Arc<Mutex<HashMap<String, SegmentRecorder>>>,
 }
 
 impl FileEgress {
@@ -37,20 +210,73 @@ impl FileEgress {
 
         let instance = Arc::new(Self {
             processing_pipeline: processing_pipeline.clone(),
-            frame_buffer: Arc::new(Mutex::new(CircularBuffer::new())),
+            frame_buffer: Arc::new(Mutex::new(RingBuffer::new(stream_manager.frame_buffer_capacity(&EgressProtocolType::File)))),
             aggregator: aggregator.clone(),
             threads_started: Arc::new(AtomicBool::new(false)),
             fps: Arc::new(Mutex::new(30)),
             encoding_format: Arc::new(Mutex::new(EncodingFormat::Draco)),
             max_number_of_points: Arc::new(Mutex::new(100000)),
-            egress_metrics: Arc::new(EgressCommonMetrics::new()),
+            egress_metrics: Arc::new(EgressCommonMetrics::new("FILE_E")),
+            recording_settings: Arc::new(Mutex::new(RecordingSettings::default())),
+            recorders: Arc::new(Mutex::new(HashMap::new())),
         });
 
         stream_manager.set_file_egress(instance.clone());
     }
+
+    /// Changes how the frames are recorded.
+    /// The segments that are being written are closed, new recordings start with the next frame.
+    #[instrument(skip_all)]
+    pub fn set_recording_settings(&self, settings: RecordingSettings) {
+        *self.recording_settings.lock().unwrap() = settings;
+        self.finish_recordings();
+    }
+
+    pub fn recording_settings(&self) -> RecordingSettings {
+        self.recording_settings.lock().unwrap().clone()
+    }
+
+    /// Closes the segments of all streams, so that the manifests describe everything that was recorded.
+    #[instrument(skip_all)]
+    pub fn finish_recordings(&self) {
+        let mut recorders = self.recorders.lock().unwrap();
+        for (stream_id, mut recorder) in recorders.drain() {
+            if let Err(e) = recorder.finish_segment() {
+                error!("Failed to finish the recording of stream {}: {}", stream_id, e);
+            }
+        }
+    }
+
+    fn write_frame_segment(&self, directory: PathBuf, stream_id: String, extension: String, frame: &FrameTaskData) {
+        let mut recorders = self.recorders.lock().unwrap();
+        if !recorders.contains_key(&stream_id) {
+            let settings = self.recording_settings();
+            let fps = *self.fps.lock().unwrap();
+            match SegmentRecorder::new(directory, stream_id.clone(), extension, fps, frame.sfu_tile_index.unwrap_or(0), &settings) {
+                Ok(recorder) => {
+                    info!("FileEgress: started a segmented recording of stream {}", stream_id);
+                    recorders.insert(stream_id.clone(), recorder);
+                }
+                Err(e) => {
+                    error!("Failed to start the recording of stream {}: {}", stream_id, e);
+                    return;
+                }
+            }
+        }
+
+        let recorder = recorders.get_mut(&stream_id).unwrap();
+        if let Err(e) = recorder.write_frame(&frame.data) {
+            error!("Failed to write frame to the recording of stream {}: {}", stream_id, e);
+        }
+    }
 } 
 
 impl EgressProtocol for FileEgress {
+    #[inline]
+    fn fps(&self) -> u32 {
+        *self.fps.lock().unwrap()
+    }
+
     #[inline]
     fn encoding_format(&self) -> EncodingFormat {
         *self.encoding_format.lock().unwrap()
@@ -120,9 +346,7 @@ impl EgressProtocol for FileEgress {
             presentation_time,
             raw_data,
             bypass,
-            self.egress_metrics.bytes_to_send.clone(),
-            self.egress_metrics.frame_drops_full_egress_buffer.clone(),
-            self.egress_metrics.number_of_combined_frames.clone(),
+            &self.egress_metrics,
             client_id,
             tile_index,
         );
@@ -143,13 +367,18 @@ impl EgressProtocol for FileEgress {
         let send_time = frame.send_time;
         let stream_id = format!("client_{}_{}", client_id, tile);
         info!("FileEgress: stream_id: {}", stream_id);
-        path.push(stream_id);
+        path.push(&stream_id);
 
         if let Err(e) = fs::create_dir_all(&path) {
             error!("Failed to create directory {:?}: {}", path, e);
             return;
         }
 
+        if self.recording_settings.lock().unwrap().mode == RecordingMode::Segments {
+            self.write_frame_segment(path, stream_id, extension, &frame);
+            return;
+        }
+
         path.push(format!("{}.{}", send_time, extension));
 
         match File::create(&path) {
@@ -177,4 +406,15 @@ impl EgressProtocol for FileEgress {
     fn set_max_number_of_points(&self, max_number_of_points: u64) {
         *self.max_number_of_points.lock().unwrap() = max_number_of_points;
     }
+
+    fn set_frame_buffer_capacity(&self, capacity: usize) {
+        let dropped = self.frame_buffer.lock().unwrap().set_capacity(capacity);
+        self.egress_metrics.record_buffer_drops(&dropped);
+    }
+
+    fn flush_and_close(&self, timeout: Duration) {
+        drain_frame_buffer("FILE_E", &self.frame_buffer, timeout);
+        // The segments that are still open are written, so the manifests describe the whole recording
+        self.finish_recordings();
+    }
 }
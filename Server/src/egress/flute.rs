@@ -1,49 +1,138 @@
 // egress/flute.rs
 
 use std::{
-    net::UdpSocket, sync::{atomic::{AtomicBool, Ordering}, Arc, Mutex}, thread, time::{Duration, Instant, SystemTime, UNIX_EPOCH}
+    collections::{HashMap, VecDeque}, net::UdpSocket, sync::{atomic::{AtomicBool, Ordering}, Arc, Mutex}, thread::{self, JoinHandle}, time::{Duration, Instant, SystemTime, UNIX_EPOCH}
 };
 
 use crate::{
     encoders::EncodingFormat,
     processing::aggregator::PointCloudAggregator,
     processing::ProcessingPipeline,
+    services::shutdown::is_shutting_down,
     services::stream_manager::StreamManager,
+    types::EgressProtocolType,
 };
 
+use shared_utils::error::{XrError, XrResult};
 use shared_utils::types::{FrameTaskData, PointCloudData};
 
-use circular_buffer::CircularBuffer;
+use super::adaptive_fec::{AdaptiveFec, AdaptiveFecConfig, FecSettings, LossProfile};
+use super::flute_feedback::{bind_feedback_socket, FluteFeedback, FluteFeedbackMessage, FluteObjectReport, FluteReceiverFeedback, MAX_FEEDBACK_DATAGRAM_SIZE};
+use super::pacing::{PacingConfig, PacingController};
+use super::ring_buffer::{RingBuffer, DEFAULT_PACKET_QUEUE_CAPACITY};
 use flute::{
     core::{lct::{Cenc, LCTHeader}, Oti, UDPEndpoint},
     sender::{Config, ObjectDesc, Sender},
 };
-use tracing::{info, debug, error, instrument};
+use serde::Serialize;
+use tracing::{info, debug, error, instrument, warn};
+
+use super::egress_common::{drain_frame_buffer, push_preencoded_frame_data, EgressCommonMetrics, EgressProtocol};
+use super::route::{self, DeliveryMode};
+
+/// Transport Session Identifier of the FLUTE/ROUTE session, or of the first session when there are multiple
+const FLUTE_TSI: u64 = 1;
+/// How long a reconfiguration or shutdown waits for the queued packets to be transmitted
+pub const PACKET_QUEUE_DRAIN_TIMEOUT: Duration = Duration::from_secs(2);
+/// Key of the session in `SessionMode::Single`
+const SINGLE_SESSION_KEY: &str = "session";
+
+/// How the frames are divided over FLUTE transport sessions.
+/// Receivers can filter on the TSI, so they only process the streams or tiles they need.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SessionMode {
+    /// All frames are sent in a single session
+    Single,
+    /// Every stream (client) is sent in its own session
+    PerStream,
+    /// Every tile of every stream is sent in its own session
+    PerTile,
+}
+
+impl SessionMode {
+    pub fn parse(value: &str) -> Option<Self> {
+        match value.to_lowercase().as_str() {
+            "single" => Some(SessionMode::Single),
+            "stream" => Some(SessionMode::PerStream),
+            "tile" => Some(SessionMode::PerTile),
+            _ => None,
+        }
+    }
 
-use super::egress_common::{push_preencoded_frame_data, EgressCommonMetrics, EgressProtocol};
+    fn session_key(&self, frame: &FrameTaskData) -> String {
+        match self {
+            SessionMode::Single => SINGLE_SESSION_KEY.to_string(),
+            SessionMode::PerStream => format!("client_{}", frame.sfu_client_id.unwrap_or(0)),
+            SessionMode::PerTile => format!("client_{}_{}", frame.sfu_client_id.unwrap_or(0), frame.sfu_tile_index.unwrap_or(0)),
+        }
+    }
+}
+
+/// A FLUTE transport session.
+/// The sender is created on the first frame of the session, and recreated after `destroy_sender`.
+#[derive(Debug)]
+struct FluteSession {
+    tsi: u64,
+    sender: Option<Sender>,
+    /// FEC scheme and parity percentage of this session, the ones of the egress are used when `None`
+    fec: Option<(String, f32)>,
+    latest_toi: u128,
+    fdt_id: u32,
+}
+
+/// Description of a FLUTE transport session, for the receivers.
+#[derive(Clone, Debug, Serialize)]
+pub struct FluteSessionInfo {
+    pub key: String,
+    pub tsi: u64,
+    pub fec: String,
+    pub fec_parity_percentage: f32,
+}
 
 /// FLUTE Egress module responsible for sending frames over FLUTE protocol.
 #[derive(Clone, Debug)]
 pub struct FluteEgress {
     processing_pipeline: Arc<ProcessingPipeline>,
-    frame_buffer: Arc<Mutex<CircularBuffer<10, FrameTaskData>>>,
-    packet_queue: Arc<Mutex<CircularBuffer<20000, Vec<u8>>>>,
+    frame_buffer: Arc<Mutex<RingBuffer<FrameTaskData>>>,
+    packet_queue: Arc<Mutex<RingBuffer<Vec<u8>>>>,
     aggregator: Arc<PointCloudAggregator>,
     threads_started: Arc<AtomicBool>,
     fps: Arc<Mutex<u32>>,
     encoding_format: Arc<Mutex<EncodingFormat>>,
     max_number_of_points: Arc<Mutex<u64>>,
     endpoint: Arc<Mutex<UDPEndpoint>>,
-    sender: Arc<Mutex<Option<Sender>>>,
+    session_mode: Arc<Mutex<SessionMode>>,
+    /// The transport sessions by their key, see `SessionMode::session_key`
+    sessions: Arc<Mutex<HashMap<String, FluteSession>>>,
+    /// TSI of the next session, TSIs are never reused so receivers can not confuse the objects of two sessions
+    next_tsi: Arc<Mutex<u64>>,
     udp_socket: Arc<Mutex<Option<UdpSocket>>>,
     content_encoding: Arc<Mutex<Cenc>>,
     fec: Arc<Mutex<String>>,
     fec_parity_percentage: Arc<Mutex<f32>>,
+    /// Adapts the FEC scheme and parity percentage to the reported loss, `None` keeps the configured FEC
+    adaptive_fec: Arc<Mutex<Option<AdaptiveFec>>>,
+    /// Configured loss that is fed to the adaptive FEC instead of the loss reports of the receivers
+    loss_profile: Arc<Mutex<Option<LossProfile>>>,
+    /// Configured send rate in bits per second, the maximum rate when pacing adapts to receiver feedback
     bandwidth: Arc<Mutex<u32>>,
-    latest_toi: Arc<Mutex<u128>>,
-    fdt_id: Arc<Mutex<u32>>,
+    /// Adapts the send rate to the receiver feedback, `None` sends at the configured bandwidth
+    pacing: Arc<Mutex<Option<PacingController>>>,
+    /// What the receivers report about the objects they received
+    feedback: Arc<FluteFeedback>,
     md5: Arc<Mutex<bool>>,
     egress_metrics: Arc<EgressCommonMetrics>,
+    delivery_mode: Arc<Mutex<DeliveryMode>>,
+    route_codepoints: Arc<Mutex<HashMap<String, u8>>>,
+    /// The FDT is sent again every interval, zero disables the time based carousel
+    fdt_carousel_interval: Arc<Mutex<Duration>>,
+    /// The FDT is sent again after this number of data packets of an object, zero disables the packet based carousel
+    fdt_carousel_packets: Arc<Mutex<u32>>,
+    /// The packets of the latest FDT of every session, by TSI, which the carousel repeats
+    current_fdt: Arc<Mutex<HashMap<u64, Vec<Vec<u8>>>>>,
+    /// Stops the packet transmitter, after which no frames are emitted anymore
+    shutdown_flag: Arc<AtomicBool>,
+    packet_transmitter: Arc<Mutex<Option<JoinHandle<()>>>>,
 }
 
 impl FluteEgress {
@@ -54,39 +143,89 @@ impl FluteEgress {
         processing_pipeline: Arc<ProcessingPipeline>,
         endpoint_url: String,
         port: u16,
+        feedback_port: Option<u16>,
     ) {
         let aggregator = Arc::new(PointCloudAggregator::new(stream_manager.clone()));
 
         let endpoint = UDPEndpoint::new(None, endpoint_url, port);
-        let sender = None;
         let udp_socket = None;
 
         let instance = Arc::new(Self {
             processing_pipeline: processing_pipeline.clone(),
-            frame_buffer: Arc::new(Mutex::new(CircularBuffer::new())),
-            packet_queue: Arc::new(Mutex::new(CircularBuffer::new())),
+            frame_buffer: Arc::new(Mutex::new(RingBuffer::new(stream_manager.frame_buffer_capacity(&EgressProtocolType::Flute)))),
+            packet_queue: Arc::new(Mutex::new(RingBuffer::new(DEFAULT_PACKET_QUEUE_CAPACITY))),
             aggregator: aggregator.clone(),
             threads_started: Arc::new(AtomicBool::new(false)),
             fps: Arc::new(Mutex::new(30)),
             encoding_format: Arc::new(Mutex::new(EncodingFormat::Draco)),
             max_number_of_points: Arc::new(Mutex::new(100_000)),
             endpoint: Arc::new(Mutex::new(endpoint)),
-            sender: Arc::new(Mutex::new(sender)),
+            session_mode: Arc::new(Mutex::new(SessionMode::Single)),
+            sessions: Arc::new(Mutex::new(HashMap::new())),
+            next_tsi: Arc::new(Mutex::new(FLUTE_TSI + 1)),
             udp_socket: Arc::new(Mutex::new(udp_socket)),
             content_encoding: Arc::new(Mutex::new(Cenc::Null)),
             fec: Arc::new(Mutex::new("nocode".to_string())),
             fec_parity_percentage: Arc::new(Mutex::new(0.06)),
+            adaptive_fec: Arc::new(Mutex::new(None)),
+            loss_profile: Arc::new(Mutex::new(None)),
             bandwidth: Arc::new(Mutex::new(200_000_000)), // Default 200 Mbps
-            latest_toi: Arc::new(Mutex::new(1)), // Start from 1
-            fdt_id: Arc::new(Mutex::new(1)), // Start from 1
+            pacing: Arc::new(Mutex::new(None)),
+            feedback: Arc::new(FluteFeedback::default()),
             md5: Arc::new(Mutex::new(true)), // Start from 1
-            egress_metrics: Arc::new(EgressCommonMetrics::new()),
+            egress_metrics: Arc::new(EgressCommonMetrics::new("FLT_E")),
+            delivery_mode: Arc::new(Mutex::new(DeliveryMode::Flute)),
+            route_codepoints: Arc::new(Mutex::new(HashMap::new())),
+            fdt_carousel_interval: Arc::new(Mutex::new(Duration::ZERO)),
+            fdt_carousel_packets: Arc::new(Mutex::new(0)),
+            current_fdt: Arc::new(Mutex::new(HashMap::new())),
+            shutdown_flag: Arc::new(AtomicBool::new(false)),
+            packet_transmitter: Arc::new(Mutex::new(None)),
         });
 
+        if let Some(feedback_port) = feedback_port {
+            if let Err(e) = instance.start_feedback_listener(feedback_port) {
+                error!("Failed to start the FLUTE feedback listener: {}", e);
+            }
+        }
+
         // Store the instance in the StreamManager
         stream_manager.set_flute_egress(instance.clone());
     }
 
+    /// Receives the feedback datagrams of the receivers on a UDP port, until the server shuts down.
+    fn start_feedback_listener(&self, port: u16) -> XrResult<()> {
+        let socket = bind_feedback_socket(port)?;
+        info!("FLUTE egress receives feedback on UDP port {}", port);
+        let self_clone = self.clone();
+        thread::Builder::new()
+            .name("FLT_E Feedback Thread".to_string())
+            .spawn(move || {
+                let mut datagram = vec![0u8; MAX_FEEDBACK_DATAGRAM_SIZE];
+                while !is_shutting_down() && !self_clone.shutdown_flag.load(Ordering::Relaxed) {
+                    let (length, source) = match socket.recv_from(&mut datagram) {
+                        Ok(received) => received,
+                        Err(e) if matches!(e.kind(), std::io::ErrorKind::WouldBlock | std::io::ErrorKind::TimedOut) => {
+                            self_clone.feedback.expire_receivers();
+                            continue;
+                        }
+                        Err(e) => {
+                            error!("Failed to receive FLUTE feedback: {}", e);
+                            continue;
+                        }
+                    };
+                    match FluteFeedbackMessage::parse(&datagram[..length]) {
+                        // The address identifies the receiver, so a sender can not report as many receivers
+                        Ok(message) => self_clone.report_object_feedback(&source.to_string(), &message.objects),
+                        Err(e) => debug!("Ignoring feedback from {}: {}", source, e),
+                    }
+                }
+                debug!("Stopped receiving FLUTE feedback");
+            })
+            .map_err(XrError::transport)?;
+        Ok(())
+    }
+
     /// Emits frame data over FLUTE protocol.
     #[instrument(skip_all)]
     fn emit_frame_data(&self, frame: FrameTaskData) {
@@ -96,15 +235,25 @@ impl FluteEgress {
         );
 
 
+        if self.shutdown_flag.load(Ordering::Relaxed) {
+            debug!("FLUTE egress is shut down, dropping frame");
+            return;
+        }
+
+        let profile_loss = self.loss_profile.lock().unwrap().as_ref().map(LossProfile::current_loss);
+        if let Some(loss_fraction) = profile_loss {
+            self.report_loss(loss_fraction);
+        }
+
         //let start = std::time::Instant::now();
-        // Initialize the FLUTE sender and UDP socket if not already done
-        let mut sender_guard = self.sender.lock().unwrap();
+        // Initialize the UDP socket and the FLUTE sender of the session of this frame if not already done
+        let session_key = self.session_mode.lock().unwrap().session_key(&frame);
+        let mut sessions_guard = self.sessions.lock().unwrap();
         {
             let mut udp_socket_guard = self.udp_socket.lock().unwrap();
 
-            if sender_guard.is_none() || udp_socket_guard.is_none() {
+            if udp_socket_guard.is_none() {
 
-                // Create the FLUTE sender
                 // Create UDP Socket
                 let endpoint = self.endpoint.lock().unwrap().clone();
 
@@ -126,28 +275,44 @@ impl FluteEgress {
 
                 *udp_socket_guard = Some(socket);
 
-                // Create FLUTE Sender
-                let tsi = 1; // Transport Session Identifier
-                let oti = self.create_oti(self.fec.lock().unwrap().clone(), *self.fec_parity_percentage.lock().unwrap());
-                let config = Config {
-                    toi_initial_value: Some(*self.latest_toi.lock().unwrap()),
-                    fdt_start_id: *self.fdt_id.lock().unwrap(),
-                    // fdt_publish_mode: flute::sender::FDTPublishMode::Automatic,
-                    ..Default::default()
-                };
+                debug!("UDP socket initialized");
+            }
+        }
 
-                let sender = Sender::new(endpoint.clone(), tsi, &oti, &config);
+        let session = self.get_or_create_session(&mut sessions_guard, &session_key);
+        if session.sender.is_none() {
+            // Create FLUTE Sender
+            let endpoint = self.endpoint.lock().unwrap().clone();
+            let (fec, parity_percentage) = session.fec.clone().unwrap_or_else(|| {
+                (self.fec.lock().unwrap().clone(), *self.fec_parity_percentage.lock().unwrap())
+            });
+            let oti = self.create_oti(fec, parity_percentage);
+            let config = Config {
+                toi_initial_value: Some(session.latest_toi),
+                fdt_start_id: session.fdt_id,
+                // fdt_publish_mode: flute::sender::FDTPublishMode::Automatic,
+                ..Default::default()
+            };
 
-                *sender_guard = Some(sender);
+            session.sender = Some(Sender::new(endpoint, session.tsi, &oti, &config));
 
-                debug!("FLUTE sender and UDP socket initialized");
-            }
+            debug!("FLUTE sender of session {} with TSI {} initialized", session_key, session.tsi);
         }
 
-        let sender = sender_guard.as_mut().unwrap();
-        //let udp_socket = udp_socket_guard.as_mut().unwrap();
+        let tsi = session.tsi;
+        let sender = session.sender.as_mut().unwrap();
 
-        let content_encoding = *self.content_encoding.lock().unwrap();
+        let delivery_mode = *self.delivery_mode.lock().unwrap();
+        // In ROUTE mode there is no FDT to signal the content encoding in, so objects are sent as is
+        let content_encoding = match delivery_mode {
+            DeliveryMode::Flute => *self.content_encoding.lock().unwrap(),
+            DeliveryMode::Route => Cenc::Null,
+        };
+        let transfer_length = frame.data.len() as u64;
+        let codepoint = {
+            let media_type = String::from_utf8_lossy(&frame.data[..frame.data.len().min(3)]).to_lowercase();
+            route::codepoint_for(&mut self.route_codepoints.lock().unwrap(), &media_type)
+        };
 
         // Prepare the frame data as an ObjectDesc
         let now = SystemTime::now();
@@ -185,10 +350,9 @@ impl FluteEgress {
         //info!("Object added to FLUTE sender with TOI: {}", toi);
 
         // Update the latest TOI
-        let mut latest_toi = self.latest_toi.lock().unwrap();
         // If the TOI is greater than the latest TOI, update it
-        if toi > *latest_toi {
-            *latest_toi = toi;
+        if toi > session.latest_toi {
+            session.latest_toi = toi;
         }
         
 
@@ -204,29 +368,56 @@ impl FluteEgress {
         //*/
 
         // Increment the FDT ID
-        let mut fdt_id = self.fdt_id.lock().unwrap();
-        *fdt_id = (*fdt_id + 1) & 0xFFFFF;
+        session.fdt_id = (session.fdt_id + 1) & 0xFFFFF;
 
 
 
         //let elapsed = start.elapsed();
         //info!("Frame conversion took: {:?} ms", elapsed);
 
+        let fdt_carousel_packets = *self.fdt_carousel_packets.lock().unwrap();
+        let mut pkts_since_fdt = 0;
         let mut fdt_pkts: Vec<Vec<u8>> = vec![];
         let mut file_pkt_count = 0;
         while let Some(pkt) = sender.read(now) {
             if pkt.is_empty() {
                 break;
             }
+            let mut repeat_fdt = false;
             let lct_header = crate::egress::flute::FluteEgress::parse_lct_header(&pkt);
-            if let Ok(lct_header) = lct_header {
-                if lct_header.toi == 0 {
-                    // Clone the packet into the fdt_pkts vector
-                    fdt_pkts.push(pkt.clone());
-                } else {
-                    file_pkt_count += 1;
+            let pkt = if let Ok(lct_header) = lct_header {
+                match (delivery_mode, lct_header.toi) {
+                    (DeliveryMode::Flute, 0) => {
+                        // Clone the packet into the fdt_pkts vector
+                        fdt_pkts.push(pkt.clone());
+                        pkt
+                    }
+                    (DeliveryMode::Flute, _) => {
+                        file_pkt_count += 1;
+                        // Repeat the FDT in between the data packets, so a receiver that lost it can still recover the object
+                        pkts_since_fdt += 1;
+                        if fdt_carousel_packets > 0 && pkts_since_fdt >= fdt_carousel_packets {
+                            pkts_since_fdt = 0;
+                            repeat_fdt = true;
+                        }
+                        pkt
+                    }
+                    // The objects are described by the S-TSID, the FDT is not transmitted
+                    (DeliveryMode::Route, 0) => continue,
+                    (DeliveryMode::Route, _) => {
+                        file_pkt_count += 1;
+                        match route::to_route_packet(&pkt, &lct_header, codepoint, transfer_length, now) {
+                            Some(route_pkt) => route_pkt,
+                            None => {
+                                error!("Failed to convert FLUTE packet to ROUTE");
+                                continue;
+                            }
+                        }
+                    }
                 }
-            }
+            } else {
+                pkt
+            };
 
             let mut attempts = 0;
             loop {
@@ -250,11 +441,28 @@ impl FluteEgress {
                 error!("Packet queue is full and has not been emptied for a long time, dropping frame packets");
                 break;
             }
+
+            if repeat_fdt {
+                let mut queue = self.packet_queue.lock().unwrap();
+                for fdt_pkt in &fdt_pkts {
+                    if queue.is_full() {
+                        break;
+                    }
+                    queue.push_back(fdt_pkt.clone());
+                }
+            }
+        }
+
+        // Keep the FDT of this session for the time based carousel
+        if delivery_mode == DeliveryMode::Flute && !fdt_pkts.is_empty() {
+            self.current_fdt.lock().unwrap().insert(tsi, fdt_pkts.clone());
         }
         // Only retransmit FDT packets if they are worth sending.
         // Small files that only have a few packets, are probably not significant
         // and thus not worth the extra overhead.
-        if !fdt_pkts.is_empty() && file_pkt_count > 3 {
+        if delivery_mode == DeliveryMode::Route {
+            // Nothing to retransmit
+        } else if !fdt_pkts.is_empty() && file_pkt_count > 3 {
             // Retransmit the FDT packets by pushing them to the packet queue
             for pkt in fdt_pkts {
                 // Use a small scope to release the lock each iteration
@@ -288,23 +496,34 @@ impl FluteEgress {
         let mut last_send_instant = Instant::now();
 
         // Read the bandwidth from your Arc<Mutex<u32>> only once every few iterations.
-        let mut bandwidth_bps = {
-            *self.bandwidth.lock().unwrap()
-        };
+        let mut bandwidth_bps = self.send_rate();
         let mut iteration_count = 0;
 
+        // The FDT packets that the carousel is sending, they go before the packets in the queue
+        let mut carousel_packets: VecDeque<Vec<u8>> = VecDeque::new();
+        let mut last_carousel_instant = Instant::now();
+
         info!("Starting packet_transmitter_loop");
         loop {
-            // Optional: check for a shutdown
-            /*if self.shutdown_flag.load(Ordering::Relaxed) {
+            // `shutdown` drains the queue before raising the flag, the packets that are still left are dropped
+            if self.shutdown_flag.load(Ordering::Relaxed) {
                 break;
-            }*/
+            }
 
-            // 1) Pop a packet from the queue (if any).
-            let maybe_packet = {
+            // 1) Repeat the current FDTs if the carousel interval has passed
+            let fdt_carousel_interval = *self.fdt_carousel_interval.lock().unwrap();
+            if !fdt_carousel_interval.is_zero() && last_carousel_instant.elapsed() >= fdt_carousel_interval {
+                last_carousel_instant = Instant::now();
+                if carousel_packets.is_empty() {
+                    carousel_packets.extend(self.current_fdt.lock().unwrap().values().flatten().cloned());
+                }
+            }
+
+            // 2) Pop a packet from the carousel or the queue (if any).
+            let maybe_packet = carousel_packets.pop_front().or_else(|| {
                 let mut q = self.packet_queue.lock().unwrap();
                 q.pop_front()
-            };
+            });
 
             // If the queue is empty, sleep briefly and try again
             let packet = match maybe_packet {
@@ -317,7 +536,7 @@ impl FluteEgress {
 
             let packet_size_bytes = packet.len() as u64;
 
-            // 2) Send the packet over UDP.
+            // 3) Send the packet over UDP.
             {
                 let mut socket_guard = self.udp_socket.lock().unwrap();
                 if let Some(ref mut udp_socket) = *socket_guard {
@@ -329,14 +548,14 @@ impl FluteEgress {
                 }
             }
 
-            // 3) Bandwidth re-check every N iterations (e.g., 10)
+            // 4) Bandwidth re-check every N iterations (e.g., 10)
             iteration_count += 1;
             if iteration_count >= 100 {
                 iteration_count = 0;
-                bandwidth_bps = *self.bandwidth.lock().unwrap();
+                bandwidth_bps = self.send_rate();
             }
 
-            // 4) Calculate how long we *want* to wait, based on packet size & bandwidth
+            // 5) Calculate how long we *want* to wait, based on packet size & bandwidth
             //    Suppose bandwidth_bps is bits/second.  We'll compute time in milliseconds.
 
             // a) Compute how long, in ms, it *should* take to send `packet_size` bytes at `bandwidth_bps`.
@@ -366,8 +585,84 @@ impl FluteEgress {
             // d) Now update the "last send" instant to *right now* (after sleeping).
             last_send_instant = Instant::now();
         }
-        // End of loop
-        // info!("packet_transmitter_loop is exiting (shutdown or error).");
+        info!("packet_transmitter_loop is exiting");
+    }
+
+    /// Waits until the packet transmitter has sent all queued packets, or until the timeout passes.
+    /// Returns whether the queue is empty.
+    fn drain_packet_queue(&self, timeout: Duration) -> bool {
+        let deadline = Instant::now() + timeout;
+        loop {
+            if self.packet_queue.lock().unwrap().is_empty() {
+                return true;
+            }
+            // Without a running transmitter the queue would never drain
+            if self.packet_transmitter.lock().unwrap().is_none() || Instant::now() >= deadline {
+                return false;
+            }
+            thread::sleep(Duration::from_millis(1));
+        }
+    }
+
+    pub fn endpoint(&self) -> UDPEndpoint {
+        self.endpoint.lock().unwrap().clone()
+    }
+
+    /// Changes the endpoint and/or the FEC scheme and parity percentage of the senders at runtime.
+    /// The packets that are already queued are first transmitted with the old settings,
+    /// then the socket and the senders are recreated with the new ones on the next frame.
+    /// The TOIs and FDT instance IDs of the sessions continue, so receivers of the same TSI see no gap.
+    #[instrument(skip_all)]
+    pub fn reconfigure(&self, endpoint: Option<UDPEndpoint>, fec: Option<(String, f32)>) {
+        // Holding the sessions blocks `emit_frame_data`, so no new packets are queued while draining
+        let mut sessions_guard = self.sessions.lock().unwrap();
+
+        if !self.drain_packet_queue(PACKET_QUEUE_DRAIN_TIMEOUT) {
+            let mut queue = self.packet_queue.lock().unwrap();
+            warn!("Packet queue not drained before reconfiguring the FLUTE egress, dropping {} packets", queue.len());
+            while queue.pop_front().is_some() {}
+        }
+
+        if let Some((fec, parity_percentage)) = fec {
+            self.set_fec(fec);
+            self.set_fec_parity_percentage(parity_percentage);
+        }
+        if let Some(endpoint) = endpoint {
+            info!("FLUTE endpoint changed to {}:{}", endpoint.destination_group_address, endpoint.port);
+            *self.endpoint.lock().unwrap() = endpoint;
+        }
+
+        for session in sessions_guard.values_mut() {
+            session.sender = None;
+        }
+        // The FDTs describe objects of the old senders
+        self.current_fdt.lock().unwrap().clear();
+        *self.udp_socket.lock().unwrap() = None;
+    }
+
+    /// Stops the egress: the queued packets are transmitted, then the packet transmitter exits.
+    /// Frames that are pushed afterwards are dropped.
+    #[instrument(skip_all)]
+    pub fn shutdown(&self, timeout: Duration) {
+        if self.shutdown_flag.load(Ordering::Relaxed) {
+            return;
+        }
+        {
+            // Block `emit_frame_data` while draining, afterwards it sees the flag
+            let _sessions_guard = self.sessions.lock().unwrap();
+            if !self.drain_packet_queue(timeout) {
+                warn!("Packet queue not drained before shutting down the FLUTE egress, dropping {} packets", self.packet_queue.lock().unwrap().len());
+            }
+            self.shutdown_flag.store(true, Ordering::Relaxed);
+        }
+
+        if let Some(handle) = self.packet_transmitter.lock().unwrap().take() {
+            if handle.join().is_err() {
+                error!("FLUTE packet transmitter panicked");
+            }
+        }
+        self.destroy_sender();
+        info!("FLUTE egress shut down");
     }
 
     /// Sets the content encoding for the egress.
@@ -396,18 +691,208 @@ impl FluteEgress {
     #[instrument(skip_all)]
     pub fn set_bandwidth(&self, bandwidth: u32) {
         *self.bandwidth.lock().unwrap() = bandwidth;
+        if let Some(pacing) = self.pacing.lock().unwrap().as_mut() {
+            pacing.set_max_rate(bandwidth);
+        }
+    }
+
+    pub fn fec(&self) -> String {
+        self.fec.lock().unwrap().clone()
+    }
+
+    pub fn fec_parity_percentage(&self) -> f32 {
+        *self.fec_parity_percentage.lock().unwrap()
+    }
+
+    /// The configured send rate in bits per second.
+    pub fn bandwidth(&self) -> u32 {
+        *self.bandwidth.lock().unwrap()
+    }
+
+    /// The rate in bits per second at which the packet transmitter currently sends.
+    pub fn send_rate(&self) -> u32 {
+        match self.pacing.lock().unwrap().as_ref() {
+            Some(pacing) => pacing.rate(),
+            None => *self.bandwidth.lock().unwrap(),
+        }
+    }
+
+    /// Enables or disables the adaptation of the send rate to the receiver feedback.
+    /// The adaptation starts at the configured bandwidth, which it never exceeds.
+    #[instrument(skip_all)]
+    pub fn set_adaptive_pacing(&self, enabled: bool) {
+        let mut pacing = self.pacing.lock().unwrap();
+        if enabled == pacing.is_some() {
+            return;
+        }
+        *pacing = enabled.then(|| PacingController::new(PacingConfig::default(), *self.bandwidth.lock().unwrap()));
+    }
+
+    /// Handles the feedback of a receiver, or the network conditions pushed by a controller:
+    /// the fraction of packets (0 to 1) that was lost, and the goodput in bits per second if known.
+    /// Both the adaptive FEC and the adaptive pacing use it, when they are enabled.
+    #[instrument(skip_all)]
+    pub fn report_feedback(&self, loss_fraction: f32, goodput: Option<u64>) {
+        self.report_loss(loss_fraction);
+        let Some(rate) = self.pacing.lock().unwrap().as_mut().and_then(|pacing| pacing.on_feedback(loss_fraction, goodput)) else {
+            return;
+        };
+        info!(
+            "Adapting the FLUTE send rate to {} bps after a loss of {:.1}% and a goodput of {:?} bps",
+            rate, loss_fraction * 100.0, goodput
+        );
+    }
+
+    /// Handles the reports of a receiver on the objects it received, over UDP or the websocket.
+    /// The loss and goodput of the worst receiver are fed to the adaptive FEC and pacing.
+    pub fn report_object_feedback(&self, receiver_id: &str, reports: &[FluteObjectReport]) {
+        if let Some((loss_fraction, goodput)) = self.feedback.record(receiver_id, reports) {
+            // A configured loss profile replaces the loss reports of the receivers
+            if self.loss_profile.lock().unwrap().is_some() {
+                return;
+            }
+            self.report_feedback(loss_fraction, goodput);
+        }
+    }
+
+    /// Returns the feedback of the receivers that reported recently.
+    pub fn feedback_receivers(&self) -> Vec<FluteReceiverFeedback> {
+        self.feedback.receivers()
     }
 
     #[instrument(skip_all)]
     pub fn destroy_sender(&self) {
-        let mut sender_guard = self.sender.lock().unwrap();
+        let mut sessions_guard = self.sessions.lock().unwrap();
         let mut udp_socket_guard = self.udp_socket.lock().unwrap();
 
-        // Just forget about both by setting them to None
-        *sender_guard = None;
+        // Just forget about the senders and the socket by setting them to None
+        for session in sessions_guard.values_mut() {
+            session.sender = None;
+        }
         *udp_socket_guard = None;
     }
 
+    /// Enables or disables the adaptation of the FEC to the reported loss.
+    /// The adaptation starts from the current FEC scheme and parity percentage.
+    #[instrument(skip_all)]
+    pub fn set_adaptive_fec(&self, enabled: bool) {
+        let mut adaptive_fec = self.adaptive_fec.lock().unwrap();
+        if enabled == adaptive_fec.is_some() {
+            return;
+        }
+        *adaptive_fec = enabled.then(|| {
+            AdaptiveFec::new(AdaptiveFecConfig::default(), FecSettings {
+                fec: self.fec.lock().unwrap().clone(),
+                parity_percentage: *self.fec_parity_percentage.lock().unwrap(),
+            })
+        });
+    }
+
+    /// Sets a loss profile like `0:0.01,10000:0.05`, see `LossProfile::parse`, an empty profile removes it.
+    /// The profile replaces the loss reports of the receivers, and enables the adaptive FEC.
+    #[instrument(skip_all)]
+    pub fn set_loss_profile(&self, profile: &str) -> XrResult<()> {
+        if profile.trim().is_empty() {
+            *self.loss_profile.lock().unwrap() = None;
+            return Ok(());
+        }
+        let profile = LossProfile::parse(profile)?;
+        *self.loss_profile.lock().unwrap() = Some(profile);
+        self.set_adaptive_fec(true);
+        Ok(())
+    }
+
+    /// Handles the fraction of packets (0 to 1) that a receiver reported as lost.
+    /// When the adaptive FEC decides on other FEC settings, the senders of the sessions without their own FEC settings
+    /// are recreated, so the next objects use the new Oti.
+    #[instrument(skip_all)]
+    pub fn report_loss(&self, loss_fraction: f32) {
+        let Some(settings) = self.adaptive_fec.lock().unwrap().as_mut().and_then(|adaptive_fec| adaptive_fec.on_loss(loss_fraction)) else {
+            return;
+        };
+        info!(
+            "Adapting the FLUTE FEC to {} with {:.1}% parity after a loss of {:.1}%",
+            settings.fec, settings.parity_percentage * 100.0, loss_fraction * 100.0
+        );
+        self.set_fec(settings.fec);
+        self.set_fec_parity_percentage(settings.parity_percentage);
+
+        // The socket is kept, only the senders of the affected sessions are recreated with the new Oti
+        for session in self.sessions.lock().unwrap().values_mut() {
+            if session.fec.is_none() {
+                session.sender = None;
+            }
+        }
+    }
+
+    /// Returns the session with the given key, a new session gets the next free TSI.
+    fn get_or_create_session<'a>(&self, sessions: &'a mut HashMap<String, FluteSession>, key: &str) -> &'a mut FluteSession {
+        sessions.entry(key.to_string()).or_insert_with(|| {
+            let tsi = if key == SINGLE_SESSION_KEY {
+                FLUTE_TSI
+            } else {
+                let mut next_tsi = self.next_tsi.lock().unwrap();
+                *next_tsi += 1;
+                *next_tsi - 1
+            };
+            info!("New FLUTE transport session {} with TSI {}", key, tsi);
+            FluteSession {
+                tsi,
+                sender: None,
+                fec: None,
+                latest_toi: 1, // Start from 1
+                fdt_id: 1, // Start from 1
+            }
+        })
+    }
+
+    /// Changes how the frames are divided over transport sessions.
+    /// The current sessions are closed, the sessions of the new mode are created on their first frame.
+    #[instrument(skip_all)]
+    pub fn set_session_mode(&self, session_mode: SessionMode) {
+        *self.session_mode.lock().unwrap() = session_mode;
+        self.sessions.lock().unwrap().clear();
+        self.current_fdt.lock().unwrap().clear();
+    }
+
+    /// Sets the FEC scheme and parity percentage of a single session, e.g. `client_1_0` in `SessionMode::PerTile`.
+    /// Settings that are not given fall back to the ones of the egress, the sender of the session is recreated.
+    #[instrument(skip_all)]
+    pub fn set_session_fec(&self, key: &str, fec: Option<String>, parity_percentage: Option<f32>) -> XrResult<()> {
+        if key == SINGLE_SESSION_KEY && *self.session_mode.lock().unwrap() != SessionMode::Single {
+            return Err(XrError::Config(format!("Session {key} only exists in the single session mode")));
+        }
+        let mut sessions = self.sessions.lock().unwrap();
+        let session = self.get_or_create_session(&mut sessions, key);
+        let (current_fec, current_parity_percentage) = session.fec.clone().unwrap_or_else(|| {
+            (self.fec.lock().unwrap().clone(), *self.fec_parity_percentage.lock().unwrap())
+        });
+        session.fec = Some((fec.unwrap_or(current_fec), parity_percentage.unwrap_or(current_parity_percentage)));
+        session.sender = None;
+        Ok(())
+    }
+
+    /// Describes the transport sessions, so receivers know which TSI carries which stream or tile.
+    #[instrument(skip_all)]
+    pub fn sessions(&self) -> Vec<FluteSessionInfo> {
+        let fec = self.fec.lock().unwrap().clone();
+        let fec_parity_percentage = *self.fec_parity_percentage.lock().unwrap();
+        let mut sessions: Vec<FluteSessionInfo> = self.sessions.lock().unwrap()
+            .iter()
+            .map(|(key, session)| {
+                let (fec, fec_parity_percentage) = session.fec.clone().unwrap_or((fec.clone(), fec_parity_percentage));
+                FluteSessionInfo {
+                    key: key.clone(),
+                    tsi: session.tsi,
+                    fec,
+                    fec_parity_percentage,
+                }
+            })
+            .collect();
+        sessions.sort_by_key(|session| session.tsi);
+        sessions
+    }
+
     #[instrument(skip_all)]
     fn create_oti(&self, fec: String, parity_percentage: f32) -> Oti {
         let fec_encoding_symbol_length = 1400;
@@ -452,6 +937,47 @@ impl FluteEgress {
     }
 */
 
+    /// Switches between plain FLUTE and ROUTE delivery.
+    #[instrument(skip_all)]
+    pub fn set_delivery_mode(&self, delivery_mode: DeliveryMode) {
+        *self.delivery_mode.lock().unwrap() = delivery_mode;
+    }
+
+    /// Configures the FDT carousel, which repeats the FDT every `interval` and after every `packets` data packets of an object.
+    /// Zero disables the respective repetition, the FDT is then only repeated once after each object.
+    #[instrument(skip_all)]
+    pub fn set_fdt_carousel(&self, interval: Option<Duration>, packets: Option<u32>) {
+        if let Some(interval) = interval {
+            *self.fdt_carousel_interval.lock().unwrap() = interval;
+        }
+        if let Some(packets) = packets {
+            *self.fdt_carousel_packets.lock().unwrap() = packets;
+        }
+    }
+
+    /// Sets the number of packets that can wait for the rate limited transmitter.
+    #[instrument(skip_all)]
+    pub fn set_packet_queue_capacity(&self, capacity: usize) {
+        let dropped = self.packet_queue.lock().unwrap().set_capacity(capacity).len();
+        if dropped > 0 {
+            warn!("Dropped {} queued FLUTE packets after shrinking the packet queue", dropped);
+        }
+    }
+
+    /// Returns the S-TSID that ROUTE receivers need to interpret the session.
+    #[instrument(skip_all)]
+    pub fn route_stsid(&self) -> String {
+        let endpoint = self.endpoint.lock().unwrap().clone();
+        let bandwidth = *self.bandwidth.lock().unwrap();
+        let mut tsis: Vec<u64> = self.sessions.lock().unwrap().values().map(|session| session.tsi).collect();
+        if tsis.is_empty() {
+            tsis.push(FLUTE_TSI);
+        }
+        tsis.sort();
+        let codepoints = self.route_codepoints.lock().unwrap();
+        route::build_stsid(&endpoint, &tsis, bandwidth, &codepoints)
+    }
+
     /// Sets the MD5 flag.
     #[instrument(skip_all)]
     pub fn set_md5(&self, md5: bool) {
@@ -558,6 +1084,11 @@ impl FluteEgress {
 
 
 impl EgressProtocol for FluteEgress {
+    #[inline]
+    fn fps(&self) -> u32 {
+        *self.fps.lock().unwrap()
+    }
+
     #[inline]
     fn encoding_format(&self) -> EncodingFormat {
         *self.encoding_format.lock().unwrap()
@@ -599,9 +1130,9 @@ impl EgressProtocol for FluteEgress {
         );
 
         let self_clone = self.clone();
-        thread::spawn(move || {
+        *self.packet_transmitter.lock().unwrap() = Some(thread::spawn(move || {
             self_clone.packet_transmitter_loop();
-        });
+        }));
     }
 
     fn push_point_cloud(&self, point_cloud: PointCloudData, stream_id: String) {
@@ -637,9 +1168,7 @@ impl EgressProtocol for FluteEgress {
             presentation_time,
             raw_data, // data is moved
             bypass,
-            self.egress_metrics.bytes_to_send.clone(),
-            self.egress_metrics.frame_drops_full_egress_buffer.clone(),
-            self.egress_metrics.number_of_combined_frames.clone(),
+            &self.egress_metrics,
             client_id,
             tile_index,
         );
@@ -656,4 +1185,14 @@ impl EgressProtocol for FluteEgress {
     fn set_max_number_of_points(&self, max_number_of_points: u64) {
         *self.max_number_of_points.lock().unwrap() = max_number_of_points;
     }
+
+    fn set_frame_buffer_capacity(&self, capacity: usize) {
+        let dropped = self.frame_buffer.lock().unwrap().set_capacity(capacity);
+        self.egress_metrics.record_buffer_drops(&dropped);
+    }
+
+    fn flush_and_close(&self, timeout: Duration) {
+        drain_frame_buffer("FLT_E", &self.frame_buffer, timeout);
+        FluteEgress::shutdown(self, timeout);
+    }
 }
\ No newline at end of file
@@ -0,0 +1,244 @@
+// egress/flute_feedback.rs
+
+//! Aggregates what the FLUTE receivers report about the objects (TOIs) they received:
+//! whether an object could be completed, how many of its packets were lost, and how long decoding it took.
+//! The reports arrive as JSON over UDP or the websocket, and feed the metrics and the FEC/pacing adaptation.
+
+use std::collections::{HashMap, VecDeque};
+use std::net::{SocketAddr, UdpSocket};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use metrics::{duration_buckets_us, get_metrics};
+use prometheus::{HistogramVec, IntCounterVec, IntGaugeVec};
+use serde::{Deserialize, Serialize};
+use shared_utils::error::{XrError, XrResult};
+use tracing::debug;
+
+/// Largest feedback datagram that is accepted
+pub const MAX_FEEDBACK_DATAGRAM_SIZE: usize = 65_507;
+/// How long the reports of a receiver are taken into account
+const FEEDBACK_WINDOW: Duration = Duration::from_secs(2);
+/// Maximum number of receivers within the window, the reports of other receivers are ignored until one leaves it
+const MAX_FEEDBACK_RECEIVERS: usize = 256;
+/// Minimum time between two adaptations based on the aggregated feedback
+const FEEDBACK_INTERVAL: Duration = Duration::from_millis(500);
+
+/// What a receiver reports about one object of a FLUTE session.
+#[derive(Deserialize, Serialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct FluteObjectReport {
+    pub tsi: u64,
+    pub toi: u64,
+    /// Whether the object could be reconstructed, possibly with the help of the FEC
+    pub completed: bool,
+    /// Packets of the object that arrived, and that were sent (source and repair)
+    pub received_packets: u32,
+    pub expected_packets: u32,
+    #[serde(default)]
+    pub received_bytes: u64,
+    /// Time between the arrival of the last packet of the object and the decoded frame, in microseconds
+    pub decode_latency_us: Option<u64>,
+}
+
+/// A feedback message, with the reports on one or more objects.
+/// The receiver is identified by the address of the sender of the datagram.
+#[derive(Deserialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct FluteFeedbackMessage {
+    pub objects: Vec<FluteObjectReport>,
+}
+
+impl FluteFeedbackMessage {
+    pub fn parse(datagram: &[u8]) -> XrResult<Self> {
+        serde_json::from_slice(datagram).map_err(|e| XrError::Transport(format!("Invalid FLUTE feedback: {e}")))
+    }
+}
+
+/// The feedback of one receiver over the window.
+#[derive(Serialize, Clone, Debug, PartialEq)]
+pub struct FluteReceiverFeedback {
+    pub receiver_id: String,
+    pub objects: usize,
+    /// Fraction of the objects (0 to 1) that were completed
+    pub completion_rate: f32,
+    /// Fraction of the packets (0 to 1) that were lost
+    pub loss_fraction: f32,
+    pub goodput_bps: u64,
+    pub average_decode_latency_us: Option<u64>,
+}
+
+#[derive(Clone, Debug)]
+struct ReceivedReport {
+    received_at: Instant,
+    report: FluteObjectReport,
+}
+
+#[derive(Debug)]
+struct FeedbackState {
+    reports: HashMap<String, VecDeque<ReceivedReport>>,
+    last_adaptation: Option<Instant>,
+}
+
+/// The feedback of all receivers, over a sliding window.
+/// The FEC and pacing adapt to the worst receiver, as every receiver of the multicast gets the same packets.
+#[derive(Debug)]
+pub struct FluteFeedback {
+    state: Mutex<FeedbackState>,
+    objects: IntCounterVec,
+    lost_packets: IntCounterVec,
+    decode_latency: HistogramVec,
+    completion_rate: IntGaugeVec,
+    loss: IntGaugeVec,
+}
+
+impl Default for FluteFeedback {
+    fn default() -> Self {
+        let metrics = get_metrics();
+        let labels = &["receiver"];
+        Self {
+            state: Mutex::new(FeedbackState { reports: HashMap::new(), last_adaptation: None }),
+            objects: metrics
+                .get_or_create_counter_vec("flute_feedback_objects", "Number of FLUTE objects that receivers reported on, by outcome", &["receiver", "outcome"])
+                .unwrap(),
+            lost_packets: metrics
+                .get_or_create_counter_vec("flute_feedback_lost_packets", "Number of FLUTE packets that receivers reported as lost", labels)
+                .unwrap(),
+            decode_latency: metrics
+                .get_or_create_histogram_vec("flute_feedback_decode_latency", "Time (us) receivers took to decode a FLUTE object", duration_buckets_us(), labels)
+                .unwrap(),
+            completion_rate: metrics
+                .get_or_create_gauge_vec("flute_feedback_completion_permille", "Per mille of the FLUTE objects that a receiver completed over the feedback window", labels)
+                .unwrap(),
+            loss: metrics
+                .get_or_create_gauge_vec("flute_feedback_loss_permille", "Per mille of the FLUTE packets that a receiver lost over the feedback window", labels)
+                .unwrap(),
+        }
+    }
+}
+
+impl FluteFeedback {
+    /// Records the reports of a receiver.
+    /// Returns the loss fraction and goodput of the worst receiver when it is time to adapt the FEC and pacing to them.
+    pub fn record(&self, receiver_id: &str, reports: &[FluteObjectReport]) -> Option<(f32, Option<u64>)> {
+        let now = Instant::now();
+        let mut state = self.state.lock().unwrap();
+        self.expire(&mut state, now);
+
+        if reports.is_empty() {
+            return None;
+        }
+        // Every receiver gets its own metrics, so their number is limited
+        if !state.reports.contains_key(receiver_id) && state.reports.len() >= MAX_FEEDBACK_RECEIVERS {
+            debug!("Ignoring the FLUTE feedback of {}, {} receivers are reporting already", receiver_id, MAX_FEEDBACK_RECEIVERS);
+            return None;
+        }
+        let receiver_reports = state.reports.entry(receiver_id.to_string()).or_default();
+        for report in reports {
+            let outcome = if report.completed { "completed" } else { "incomplete" };
+            self.objects.with_label_values(&[receiver_id, outcome]).inc();
+            self.lost_packets
+                .with_label_values(&[receiver_id])
+                .inc_by(report.expected_packets.saturating_sub(report.received_packets) as u64);
+            if let Some(decode_latency_us) = report.decode_latency_us {
+                self.decode_latency.with_label_values(&[receiver_id]).observe(decode_latency_us as f64);
+            }
+            receiver_reports.push_back(ReceivedReport { received_at: now, report: report.clone() });
+        }
+
+        let receivers = summarize(&state.reports);
+        for receiver in &receivers {
+            self.completion_rate.with_label_values(&[&receiver.receiver_id]).set((receiver.completion_rate * 1000.0) as i64);
+            self.loss.with_label_values(&[&receiver.receiver_id]).set((receiver.loss_fraction * 1000.0) as i64);
+        }
+
+        if state.last_adaptation.is_some_and(|last| now.duration_since(last) < FEEDBACK_INTERVAL) {
+            return None;
+        }
+        let worst = receivers.iter().max_by(|a, b| a.loss_fraction.total_cmp(&b.loss_fraction))?;
+        state.last_adaptation = Some(now);
+        let goodput = receivers.iter().map(|receiver| receiver.goodput_bps).filter(|goodput| *goodput > 0).min();
+        Some((worst.loss_fraction, goodput))
+    }
+
+    /// Forgets the receivers that did not report within the window, also when no other receiver reports anymore.
+    pub fn expire_receivers(&self) {
+        let mut state = self.state.lock().unwrap();
+        self.expire(&mut state, Instant::now());
+    }
+
+    /// Receivers that stopped reporting no longer hold back the adaptation, nor keep their metrics.
+    fn expire(&self, state: &mut FeedbackState, now: Instant) {
+        for receiver_reports in state.reports.values_mut() {
+            while receiver_reports.front().is_some_and(|received| now.duration_since(received.received_at) > FEEDBACK_WINDOW) {
+                receiver_reports.pop_front();
+            }
+        }
+        let departed: Vec<String> = state.reports.iter()
+            .filter(|(_, receiver_reports)| receiver_reports.is_empty())
+            .map(|(receiver, _)| receiver.clone())
+            .collect();
+        for receiver in departed {
+            state.reports.remove(&receiver);
+            self.remove_metrics(&receiver);
+        }
+    }
+
+    /// Removes the metrics of a receiver that left the window.
+    fn remove_metrics(&self, receiver_id: &str) {
+        for outcome in ["completed", "incomplete"] {
+            let _ = self.objects.remove_label_values(&[receiver_id, outcome]);
+        }
+        let _ = self.lost_packets.remove_label_values(&[receiver_id]);
+        let _ = self.decode_latency.remove_label_values(&[receiver_id]);
+        let _ = self.completion_rate.remove_label_values(&[receiver_id]);
+        let _ = self.loss.remove_label_values(&[receiver_id]);
+    }
+
+    /// Returns the feedback of every receiver that reported within the window, sorted by receiver.
+    pub fn receivers(&self) -> Vec<FluteReceiverFeedback> {
+        let mut state = self.state.lock().unwrap();
+        self.expire(&mut state, Instant::now());
+        let mut receivers = summarize(&state.reports);
+        receivers.sort_by(|a, b| a.receiver_id.cmp(&b.receiver_id));
+        receivers
+    }
+}
+
+fn summarize(reports: &HashMap<String, VecDeque<ReceivedReport>>) -> Vec<FluteReceiverFeedback> {
+    reports
+        .iter()
+        .map(|(receiver_id, receiver_reports)| {
+            let objects = receiver_reports.len();
+            let completed = receiver_reports.iter().filter(|received| received.report.completed).count();
+            let expected: u64 = receiver_reports.iter().map(|received| received.report.expected_packets as u64).sum();
+            let received: u64 = receiver_reports.iter().map(|received| received.report.received_packets.min(received.report.expected_packets) as u64).sum();
+            let bytes: u64 = receiver_reports.iter().map(|received| received.report.received_bytes).sum();
+            let latencies: Vec<u64> = receiver_reports.iter().filter_map(|received| received.report.decode_latency_us).collect();
+            // The goodput is measured over the time the reports span, or the whole window for a single burst
+            let span = match (receiver_reports.front(), receiver_reports.back()) {
+                (Some(first), Some(last)) => last.received_at.duration_since(first.received_at),
+                _ => Duration::ZERO,
+            };
+            let span = if span.is_zero() { FEEDBACK_WINDOW } else { span };
+
+            FluteReceiverFeedback {
+                receiver_id: receiver_id.clone(),
+                objects,
+                completion_rate: if objects == 0 { 1.0 } else { completed as f32 / objects as f32 },
+                loss_fraction: if expected == 0 { 0.0 } else { 1.0 - received as f32 / expected as f32 },
+                goodput_bps: (bytes as f64 * 8.0 / span.as_secs_f64()) as u64,
+                average_decode_latency_us: (!latencies.is_empty()).then(|| latencies.iter().sum::<u64>() / latencies.len() as u64),
+            }
+        })
+        .collect()
+}
+
+/// Binds the UDP socket that the receivers send their feedback to.
+/// The socket times out regularly, so the listener can notice that the server shuts down.
+pub fn bind_feedback_socket(port: u16) -> XrResult<UdpSocket> {
+    let socket = UdpSocket::bind(SocketAddr::from(([0, 0, 0, 0], port)))
+        .map_err(|e| XrError::Transport(format!("Failed to bind the FLUTE feedback socket on port {port}: {e}")))?;
+    socket.set_read_timeout(Some(Duration::from_millis(500))).map_err(XrError::transport)?;
+    Ok(socket)
+}
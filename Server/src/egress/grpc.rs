@@ -0,0 +1,339 @@
+// egress/grpc.rs
+
+use std::fmt;
+use std::net::{Ipv6Addr, SocketAddr};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::encoders::EncodingFormat;
+use crate::processing::{aggregator::PointCloudAggregator, ProcessingPipeline};
+use crate::services::stream_manager::StreamManager;
+use crate::types::EgressProtocolType;
+use shared_utils::error::XrError;
+use shared_utils::types::{FrameTaskData, PointCloudData};
+
+use super::ring_buffer::RingBuffer;
+use metrics::get_metrics;
+use prometheus::IntCounter;
+use tokio::runtime::{self, Runtime};
+use tokio::sync::{broadcast, mpsc, watch};
+use tokio_stream::wrappers::ReceiverStream;
+use tonic::{Request, Response, Status};
+use tracing::{debug, error, info, instrument, warn};
+
+use super::egress_common::{drain_frame_buffer, frame_stream_id, push_preencoded_frame_data, EgressCommonMetrics, EgressProtocol};
+
+pub mod proto {
+    tonic::include_proto!("pointcloud.frames");
+}
+
+use proto::frame_service_server::{FrameService, FrameServiceServer};
+use proto::{Frame, SubscribeFramesRequest};
+
+static GRPC_RUNTIME: OnceLock<Arc<Runtime>> = OnceLock::new();
+
+/// Number of frames that can wait for a subscriber, older frames are skipped when it does not keep up
+const SUBSCRIBER_BUFFER_SIZE: usize = 8;
+
+/// gRPC Egress module responsible for streaming the encoded frames to the subscribers of the `FrameService`,
+/// for clients that are not browsers, e.g. analysis tools and robots.
+#[derive(Clone)]
+pub struct GrpcEgress {
+    processing_pipeline: Arc<ProcessingPipeline>,
+    frame_buffer: Arc<Mutex<RingBuffer<FrameTaskData>>>,
+    aggregator: Arc<PointCloudAggregator>,
+    threads_started: Arc<AtomicBool>,
+    fps: Arc<Mutex<u32>>,
+    encoding_format: Arc<Mutex<EncodingFormat>>,
+    max_number_of_points: Arc<Mutex<u64>>,
+    /// Every subscription receives all frames, and keeps the ones of its stream
+    frames: broadcast::Sender<Arc<Frame>>,
+    frame_number: Arc<AtomicU64>,
+    /// Set to true when the server shuts down, which ends the subscriptions and the gRPC server
+    closed: watch::Sender<bool>,
+    egress_metrics: Arc<EgressCommonMetrics>,
+}
+
+impl fmt::Debug for GrpcEgress {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("GrpcEgress")
+            .field("subscribers", &self.frames.receiver_count())
+            .field("processing_pipeline", &self.processing_pipeline)
+            .field("frame_buffer", &self.frame_buffer)
+            .field("aggregator", &self.aggregator)
+            .field("fps", &self.fps)
+            .field("encoding_format", &self.encoding_format)
+            .field("max_number_of_points", &self.max_number_of_points)
+            .field("egress_metrics", &self.egress_metrics)
+            .finish()
+    }
+}
+
+/// Implements the gRPC service on top of the frames that the egress broadcasts.
+struct FrameServiceImpl {
+    frames: broadcast::Sender<Arc<Frame>>,
+    closed: watch::Sender<bool>,
+    /// Frames a subscriber skipped because it did not read them fast enough
+    frame_drops_lagging: IntCounter,
+}
+
+impl GrpcEgress {
+    /// Initializes the gRPC Egress module, and starts serving the `FrameService` on `port`.
+    #[instrument(skip_all)]
+    pub fn initialize(
+        stream_manager: Arc<StreamManager>,
+        processing_pipeline: Arc<ProcessingPipeline>,
+        port: u16,
+    ) {
+        let frame_drops_lagging = match get_metrics().get_or_create_counter("grpc_frame_drops_lagging", "Number of frames gRPC subscribers skipped because they did not keep up") {
+            Ok(frame_drops_lagging) => frame_drops_lagging,
+            Err(e) => {
+                error!("Failed to create the gRPC metrics: {}", e);
+                return;
+            }
+        };
+
+        let aggregator = Arc::new(PointCloudAggregator::new(stream_manager.clone()));
+        let (frames, _) = broadcast::channel(SUBSCRIBER_BUFFER_SIZE);
+        let (closed, _) = watch::channel(false);
+
+        let instance = Arc::new(Self {
+            processing_pipeline: processing_pipeline.clone(),
+            frame_buffer: Arc::new(Mutex::new(RingBuffer::new(stream_manager.frame_buffer_capacity(&EgressProtocolType::Grpc)))),
+            aggregator: aggregator.clone(),
+            threads_started: Arc::new(AtomicBool::new(false)),
+            fps: Arc::new(Mutex::new(30)),
+            encoding_format: Arc::new(Mutex::new(EncodingFormat::Draco)),
+            max_number_of_points: Arc::new(Mutex::new(100000)),
+            frames,
+            frame_number: Arc::new(AtomicU64::new(0)),
+            closed,
+            egress_metrics: Arc::new(EgressCommonMetrics::new("GRPC_E")),
+        });
+
+        let service = FrameServiceImpl {
+            frames: instance.frames.clone(),
+            closed: instance.closed.clone(),
+            frame_drops_lagging,
+        };
+        let mut closed = instance.closed.subscribe();
+        instance.get_runtime().spawn(async move {
+            let address = SocketAddr::from((Ipv6Addr::UNSPECIFIED, port));
+            let result = tonic::transport::Server::builder()
+                .add_service(FrameServiceServer::new(service))
+                .serve_with_shutdown(address, async move {
+                    let _ = closed.wait_for(|closed| *closed).await;
+                })
+                .await
+                .map_err(|e| XrError::Transport(format!("Failed to serve gRPC: {e}")));
+            if let Err(e) = result {
+                error!("gRPC egress stopped: {}", e);
+            }
+        });
+
+        info!("gRPC egress listening on port {}", port);
+
+        // Store the instance in the StreamManager
+        stream_manager.set_grpc_egress(instance.clone());
+    }
+
+    #[instrument(skip_all)]
+    pub fn get_runtime(&self) -> Arc<Runtime> {
+        GRPC_RUNTIME.get_or_init(|| {
+            let rt = runtime::Builder::new_multi_thread()
+                .worker_threads(2)
+                .thread_name_fn(|| {
+                    static ATOMIC_GRPC_ID: std::sync::atomic::AtomicUsize = std::sync::atomic::AtomicUsize::new(0);
+                    let id = ATOMIC_GRPC_ID.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+                    format!("GRPC_R w-{}", id)
+                })
+                .enable_all()
+                .build().unwrap();
+            Arc::new(rt)
+        }).clone()
+    }
+}
+
+#[tonic::async_trait]
+impl FrameService for FrameServiceImpl {
+    type SubscribeFramesStream = ReceiverStream<Result<Frame, Status>>;
+
+    async fn subscribe_frames(
+        &self,
+        request: Request<SubscribeFramesRequest>,
+    ) -> Result<Response<Self::SubscribeFramesStream>, Status> {
+        if *self.closed.borrow() {
+            return Err(Status::unavailable("The server is shutting down"));
+        }
+
+        let stream_id = request.into_inner().stream_id;
+        info!("gRPC subscriber for stream {:?}", stream_id);
+
+        let mut frames = self.frames.subscribe();
+        let mut closed = self.closed.subscribe();
+        let frame_drops_lagging = self.frame_drops_lagging.clone();
+        let (tx, rx) = mpsc::channel(SUBSCRIBER_BUFFER_SIZE);
+        tokio::spawn(async move {
+            loop {
+                let frame = tokio::select! {
+                    frame = frames.recv() => frame,
+                    _ = closed.wait_for(|closed| *closed) => break,
+                };
+                match frame {
+                    Ok(frame) => {
+                        if !stream_id.is_empty() && frame.stream_id != stream_id {
+                            continue;
+                        }
+                        // Fails when the client cancelled the subscription
+                        if tx.send(Ok(Frame::clone(&frame))).await.is_err() {
+                            break;
+                        }
+                    }
+                    Err(broadcast::error::RecvError::Lagged(skipped)) => {
+                        debug!("gRPC subscriber skipped {} frames", skipped);
+                        frame_drops_lagging.inc_by(skipped);
+                    }
+                    Err(broadcast::error::RecvError::Closed) => break,
+                }
+            }
+            info!("gRPC subscription for stream {:?} ended", stream_id);
+        });
+
+        Ok(Response::new(ReceiverStream::new(rx)))
+    }
+}
+
+impl EgressProtocol for GrpcEgress {
+    #[inline]
+    fn fps(&self) -> u32 {
+        *self.fps.lock().unwrap()
+    }
+
+    #[inline]
+    fn encoding_format(&self) -> EncodingFormat {
+        *self.encoding_format.lock().unwrap()
+    }
+
+    #[inline]
+    fn max_number_of_points(&self) -> u64 {
+        *self.max_number_of_points.lock().unwrap()
+    }
+
+    fn ensure_threads_started(&self) {
+        let already_started = self.threads_started.load(Ordering::Relaxed);
+        if already_started {
+            return;
+        }
+
+        // Set the threads as started
+        self.threads_started.store(true, Ordering::Relaxed);
+
+        // Start background threads using the common module
+        crate::egress::egress_common::start_generator_thread(
+            "GRPC_E".to_string(),
+            self.processing_pipeline.clone(),
+            self.aggregator.clone(),
+            self.frame_buffer.clone(),
+            self.fps.clone(),
+            self.encoding_format.clone(),
+            self.max_number_of_points.clone(),
+        );
+
+        let self_clone = self.clone();
+        crate::egress::egress_common::start_transmission_thread(
+            "GRPC_E".to_string(),
+            self.frame_buffer.clone(),
+            move |frame| {
+                self_clone.emit_frame_data(frame);
+            },
+            false,
+        );
+    }
+
+    fn push_point_cloud(&self, point_cloud: PointCloudData, stream_id: String) {
+        self.ensure_threads_started();
+        self.aggregator.update_point_cloud(stream_id, point_cloud);
+    }
+
+    // Process and sends a frame, this raw version bypasses the aggregation
+    fn push_encoded_frame(&self, raw_data: Vec<u8>, _stream_id: String, mut creation_time: u64, presentation_time: u64, ring_buffer_bypass: bool, client_id: Option<u64>, tile_index: Option<u32>) {
+        // Ensure the threads are started
+        self.ensure_threads_started();
+
+        let self_clone = self.clone();
+        let bypass = if ring_buffer_bypass {
+
+            let since_the_epoch = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .expect("Time went backwards");
+            creation_time = since_the_epoch.as_micros() as u64;
+
+            Some(Box::new(move |frame| {
+                self_clone.emit_frame_data(frame);
+            }) as Box<dyn Fn(FrameTaskData) + Send + 'static>)
+        } else {
+            None
+        };
+
+        push_preencoded_frame_data(
+            "GRPC_E",
+            &self.frame_buffer,
+            creation_time,
+            presentation_time,
+            raw_data, // data is moved
+            bypass,
+            &self.egress_metrics,
+            client_id,
+            tile_index,
+        );
+    }
+
+    /// Hands the frame to all subscriptions, each of them forwards it to its client at its own pace.
+    fn emit_frame_data(&self, frame: FrameTaskData) {
+        debug!("Emitting frame with presentation time: {}", frame.presentation_time);
+
+        if self.frames.receiver_count() == 0 {
+            debug!("No gRPC subscribers to emit frame");
+            return;
+        }
+
+        let frame = Frame {
+            stream_id: frame_stream_id(&frame),
+            frame_number: self.frame_number.fetch_add(1, Ordering::Relaxed),
+            send_time: frame.send_time,
+            presentation_time: frame.presentation_time,
+            data: frame.data,
+            client_id: frame.sfu_client_id,
+            tile_index: frame.sfu_tile_index,
+        };
+        // Only fails when the last subscriber just left
+        let _ = self.frames.send(Arc::new(frame));
+    }
+
+    fn set_fps(&self, fps: u32) {
+        *self.fps.lock().unwrap() = fps;
+    }
+
+    fn set_encoding_format(&self, encoding_format: EncodingFormat) {
+        *self.encoding_format.lock().unwrap() = encoding_format;
+    }
+
+    fn set_max_number_of_points(&self, max_number_of_points: u64) {
+        *self.max_number_of_points.lock().unwrap() = max_number_of_points;
+    }
+
+    fn set_frame_buffer_capacity(&self, capacity: usize) {
+        let dropped = self.frame_buffer.lock().unwrap().set_capacity(capacity);
+        self.egress_metrics.record_buffer_drops(&dropped);
+    }
+
+    fn flush_and_close(&self, timeout: Duration) {
+        drain_frame_buffer("GRPC_E", &self.frame_buffer, timeout);
+        if self.frames.receiver_count() > 0 {
+            warn!("Ending {} gRPC subscriptions", self.frames.receiver_count());
+        }
+        // Ends the subscriptions, after the frames they already received, and stops the server
+        self.closed.send_replace(true);
+    }
+}
@@ -0,0 +1,417 @@
+// egress/local.rs
+
+use std::fs;
+use std::io::{self, Write};
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::encoders::EncodingFormat;
+use crate::processing::{aggregator::PointCloudAggregator, ProcessingPipeline};
+use crate::services::shutdown::is_shutting_down;
+use crate::services::stream_manager::StreamManager;
+use crate::types::EgressProtocolType;
+use shared_utils::error::{XrError, XrResult};
+use shared_utils::types::{FrameTaskData, PointCloudData};
+use shared_utils::wire::encode_frame;
+
+use super::ring_buffer::RingBuffer;
+use tracing::{debug, error, info, instrument, warn};
+
+use super::egress_common::{drain_frame_buffer, frame_stream_id, push_preencoded_frame_data, EgressCommonMetrics, EgressProtocol};
+
+/// A consumer that does not accept a frame within this time is disconnected
+const WRITE_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// Magic bytes at the start of every message header
+const MESSAGE_MAGIC: [u8; 4] = *b"XRLC";
+const HEADER_SIZE: usize = 32;
+/// Size of a decoded point: x, y and z as f32 followed by r, g and b
+const POINT_SIZE: usize = 15;
+
+/// How the payloads reach the local consumers.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LocalTransport {
+    /// The payload follows the header on the socket
+    Inline,
+    /// The payload is written once to a sealed memfd, of which the file descriptor is passed along with the header,
+    /// so every consumer maps the same memory instead of reading a copy from the socket
+    Memfd,
+}
+
+impl LocalTransport {
+    pub fn parse(value: &str) -> XrResult<Self> {
+        match value.to_lowercase().as_str() {
+            "inline" => Ok(LocalTransport::Inline),
+            "memfd" if cfg!(target_os = "linux") => Ok(LocalTransport::Memfd),
+            "memfd" => Err(XrError::Config("The memfd transport of the local egress is only available on Linux".to_string())),
+            other => Err(XrError::Config(format!("Unknown local egress transport {other}, expected inline or memfd"))),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct LocalConfig {
+    /// Path of the Unix domain socket that the consumers connect to
+    pub socket_path: PathBuf,
+    pub transport: LocalTransport,
+    /// Deliver the decoded points instead of the encoded frames, so the consumer needs no decoder
+    pub decoded: bool,
+}
+
+/// Local Egress module responsible for delivering frames to consumer processes on the same machine,
+/// e.g. a co-located renderer, over a Unix domain socket instead of the network.
+///
+/// Every frame starts with a 32 byte little endian header:
+/// magic `XRLC`, kind (u8, 0 = encoded frame in the wire format, 1 = decoded points),
+/// transport (u8, 0 = the payload follows, 1 = the payload is in the memfd passed with SCM_RIGHTS), 2 reserved bytes,
+/// send time (u64), presentation time (u64), payload length (u32) and number of points (u32, 0 for encoded frames).
+/// Decoded points are packed as x, y, z (f32) and r, g, b (u8).
+#[derive(Clone, Debug)]
+pub struct LocalEgress {
+    processing_pipeline: Arc<ProcessingPipeline>,
+    frame_buffer: Arc<Mutex<RingBuffer<FrameTaskData>>>,
+    aggregator: Arc<PointCloudAggregator>,
+    threads_started: Arc<AtomicBool>,
+    fps: Arc<Mutex<u32>>,
+    encoding_format: Arc<Mutex<EncodingFormat>>,
+    max_number_of_points: Arc<Mutex<u64>>,
+    config: LocalConfig,
+    /// The connected consumers
+    consumers: Arc<Mutex<Vec<UnixStream>>>,
+    egress_metrics: Arc<EgressCommonMetrics>,
+}
+
+impl LocalEgress {
+    /// Initializes the Local Egress module, and starts accepting consumers on the socket path.
+    #[instrument(skip_all)]
+    pub fn initialize(
+        stream_manager: Arc<StreamManager>,
+        processing_pipeline: Arc<ProcessingPipeline>,
+        config: Option<LocalConfig>,
+    ) {
+        let Some(config) = config else {
+            info!("No local socket path configured, the local egress is disabled");
+            return;
+        };
+
+        // A socket file that is left behind by a previous run would make the bind fail
+        if config.socket_path.exists() {
+            if let Err(e) = fs::remove_file(&config.socket_path) {
+                error!("Failed to remove the old local egress socket {}: {}", config.socket_path.display(), e);
+                return;
+            }
+        }
+        let listener = match UnixListener::bind(&config.socket_path) {
+            Ok(listener) => listener,
+            Err(e) => {
+                error!("Failed to listen on {}: {}", config.socket_path.display(), e);
+                return;
+            }
+        };
+        info!("Local egress listening on {} ({:?}, decoded: {})", config.socket_path.display(), config.transport, config.decoded);
+
+        let aggregator = Arc::new(PointCloudAggregator::new(stream_manager.clone()));
+
+        let instance = Arc::new(Self {
+            processing_pipeline: processing_pipeline.clone(),
+            frame_buffer: Arc::new(Mutex::new(RingBuffer::new(stream_manager.frame_buffer_capacity(&EgressProtocolType::Local)))),
+            aggregator: aggregator.clone(),
+            threads_started: Arc::new(AtomicBool::new(false)),
+            fps: Arc::new(Mutex::new(30)),
+            encoding_format: Arc::new(Mutex::new(EncodingFormat::Draco)),
+            max_number_of_points: Arc::new(Mutex::new(100000)),
+            config,
+            consumers: Arc::new(Mutex::new(Vec::new())),
+            egress_metrics: Arc::new(EgressCommonMetrics::new("LOCAL_E")),
+        });
+
+        let consumers = instance.consumers.clone();
+        let _ = thread::Builder::new().name("LOCAL_E Accept Thread".to_string()).spawn(move || {
+            Self::accept_consumers(listener, consumers);
+        });
+
+        // Store the instance in the StreamManager
+        stream_manager.set_local_egress(instance.clone());
+    }
+
+    /// Accepts consumers until the server shuts down, every consumer receives all frames.
+    fn accept_consumers(listener: UnixListener, consumers: Arc<Mutex<Vec<UnixStream>>>) {
+        for stream in listener.incoming() {
+            if is_shutting_down() {
+                break;
+            }
+            match stream.and_then(|stream| stream.set_write_timeout(Some(WRITE_TIMEOUT)).map(|_| stream)) {
+                Ok(stream) => {
+                    info!("Local consumer connected");
+                    consumers.lock().unwrap().push(stream);
+                }
+                Err(e) => {
+                    error!("Failed to accept a local consumer: {}", e);
+                    thread::sleep(Duration::from_secs(1));
+                }
+            }
+        }
+    }
+
+    /// Returns the kind, payload and number of points of a frame.
+    fn payload(&self, frame: &FrameTaskData) -> XrResult<(u8, Vec<u8>, u32)> {
+        if !self.config.decoded {
+            return Ok((0, encode_frame(frame), 0));
+        }
+        let point_cloud = self.processing_pipeline.decode(frame.data.clone(), &frame_stream_id(frame))?;
+        let mut payload = Vec::with_capacity(point_cloud.points.len() * POINT_SIZE);
+        for point in &point_cloud.points {
+            payload.extend_from_slice(&point.x.to_le_bytes());
+            payload.extend_from_slice(&point.y.to_le_bytes());
+            payload.extend_from_slice(&point.z.to_le_bytes());
+            payload.extend_from_slice(&[point.r, point.g, point.b]);
+        }
+        Ok((1, payload, point_cloud.points.len() as u32))
+    }
+
+    fn header(frame: &FrameTaskData, kind: u8, transport: LocalTransport, payload_length: usize, number_of_points: u32) -> [u8; HEADER_SIZE] {
+        let mut header = [0u8; HEADER_SIZE];
+        header[0..4].copy_from_slice(&MESSAGE_MAGIC);
+        header[4] = kind;
+        header[5] = match transport {
+            LocalTransport::Inline => 0,
+            LocalTransport::Memfd => 1,
+        };
+        header[8..16].copy_from_slice(&frame.send_time.to_le_bytes());
+        header[16..24].copy_from_slice(&frame.presentation_time.to_le_bytes());
+        header[24..28].copy_from_slice(&(payload_length as u32).to_le_bytes());
+        header[28..32].copy_from_slice(&number_of_points.to_le_bytes());
+        header
+    }
+}
+
+/// Writes the payload to a new memfd, and seals it so the consumers can map it without it changing underneath them.
+#[cfg(target_os = "linux")]
+fn create_sealed_memfd(payload: &[u8]) -> io::Result<std::os::fd::OwnedFd> {
+    use std::os::fd::{AsRawFd, FromRawFd, OwnedFd};
+
+    // Safety: the name is a valid C string, the returned descriptor is owned from here on
+    let fd = unsafe { libc::memfd_create(b"xr_frame\0".as_ptr() as *const libc::c_char, libc::MFD_CLOEXEC | libc::MFD_ALLOW_SEALING) };
+    if fd < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    let mut file = fs::File::from(unsafe { OwnedFd::from_raw_fd(fd) });
+    file.write_all(payload)?;
+
+    let seals = libc::F_SEAL_SHRINK | libc::F_SEAL_GROW | libc::F_SEAL_WRITE | libc::F_SEAL_SEAL;
+    // Safety: the descriptor is valid while the file is alive
+    if unsafe { libc::fcntl(file.as_raw_fd(), libc::F_ADD_SEALS, seals) } < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(OwnedFd::from(file))
+}
+
+/// Sends the header with the file descriptor of the memfd as SCM_RIGHTS ancillary data.
+#[cfg(target_os = "linux")]
+fn send_with_fd(stream: &UnixStream, header: &[u8], fd: std::os::fd::BorrowedFd<'_>) -> io::Result<()> {
+    use std::os::fd::AsRawFd;
+
+    let mut iov = libc::iovec {
+        iov_base: header.as_ptr() as *mut libc::c_void,
+        iov_len: header.len(),
+    };
+    // u64 elements keep the control messages aligned
+    let mut control = [0u64; 4];
+    // Safety: msghdr is a plain C struct, the pointers stay valid during the sendmsg call,
+    // and the control buffer is large enough for a single descriptor
+    unsafe {
+        let mut message: libc::msghdr = std::mem::zeroed();
+        message.msg_iov = &mut iov;
+        message.msg_iovlen = 1;
+        message.msg_control = control.as_mut_ptr() as *mut libc::c_void;
+        message.msg_controllen = libc::CMSG_SPACE(std::mem::size_of::<libc::c_int>() as u32) as _;
+
+        let control_message = libc::CMSG_FIRSTHDR(&message);
+        (*control_message).cmsg_level = libc::SOL_SOCKET;
+        (*control_message).cmsg_type = libc::SCM_RIGHTS;
+        (*control_message).cmsg_len = libc::CMSG_LEN(std::mem::size_of::<libc::c_int>() as u32) as _;
+        std::ptr::write_unaligned(libc::CMSG_DATA(control_message) as *mut libc::c_int, fd.as_raw_fd());
+
+        let sent = libc::sendmsg(stream.as_raw_fd(), &message, libc::MSG_NOSIGNAL);
+        if sent < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        if (sent as usize) < header.len() {
+            return Err(io::Error::new(io::ErrorKind::WriteZero, "Partially sent the frame header"));
+        }
+    }
+    Ok(())
+}
+
+impl EgressProtocol for LocalEgress {
+    #[inline]
+    fn fps(&self) -> u32 {
+        *self.fps.lock().unwrap()
+    }
+
+    #[inline]
+    fn encoding_format(&self) -> EncodingFormat {
+        *self.encoding_format.lock().unwrap()
+    }
+
+    #[inline]
+    fn max_number_of_points(&self) -> u64 {
+        *self.max_number_of_points.lock().unwrap()
+    }
+
+    fn ensure_threads_started(&self) {
+        let already_started = self.threads_started.load(Ordering::Relaxed);
+        if already_started {
+            return;
+        }
+
+        // Set the threads as started
+        self.threads_started.store(true, Ordering::Relaxed);
+
+        // Start background threads using the common module
+        crate::egress::egress_common::start_generator_thread(
+            "LOCAL_E".to_string(),
+            self.processing_pipeline.clone(),
+            self.aggregator.clone(),
+            self.frame_buffer.clone(),
+            self.fps.clone(),
+            self.encoding_format.clone(),
+            self.max_number_of_points.clone(),
+        );
+
+        let self_clone = self.clone();
+        crate::egress::egress_common::start_transmission_thread(
+            "LOCAL_E".to_string(),
+            self.frame_buffer.clone(),
+            move |frame| {
+                self_clone.emit_frame_data(frame);
+            },
+            false,
+        );
+    }
+
+    fn push_point_cloud(&self, point_cloud: PointCloudData, stream_id: String) {
+        self.ensure_threads_started();
+        self.aggregator.update_point_cloud(stream_id, point_cloud);
+    }
+
+    // Process and sends a frame, this raw version bypasses the aggregation
+    fn push_encoded_frame(&self, raw_data: Vec<u8>, _stream_id: String, mut creation_time: u64, presentation_time: u64, ring_buffer_bypass: bool, client_id: Option<u64>, tile_index: Option<u32>) {
+        // Ensure the threads are started
+        self.ensure_threads_started();
+
+        let self_clone = self.clone();
+        let bypass = if ring_buffer_bypass {
+
+            let since_the_epoch = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .expect("Time went backwards");
+            creation_time = since_the_epoch.as_micros() as u64;
+
+            Some(Box::new(move |frame| {
+                self_clone.emit_frame_data(frame);
+            }) as Box<dyn Fn(FrameTaskData) + Send + 'static>)
+        } else {
+            None
+        };
+
+        push_preencoded_frame_data(
+            "LOCAL_E",
+            &self.frame_buffer,
+            creation_time,
+            presentation_time,
+            raw_data, // data is moved
+            bypass,
+            &self.egress_metrics,
+            client_id,
+            tile_index,
+        );
+    }
+
+    /// Delivers the frame to every connected consumer, consumers that fail are dropped.
+    fn emit_frame_data(&self, frame: FrameTaskData) {
+        debug!("Emitting frame with presentation time: {}", frame.presentation_time);
+
+        let mut consumers = self.consumers.lock().unwrap();
+        if consumers.is_empty() {
+            debug!("No local consumer connected to emit frame");
+            return;
+        }
+
+        let (kind, payload, number_of_points) = match self.payload(&frame) {
+            Ok(payload) => payload,
+            Err(e) => {
+                error!("Failed to prepare frame for the local consumers: {}", e);
+                return;
+            }
+        };
+        let header = Self::header(&frame, kind, self.config.transport, payload.len(), number_of_points);
+
+        // The memfd is shared by all consumers, every consumer gets its own descriptor with the message
+        #[cfg(target_os = "linux")]
+        let memfd = match self.config.transport {
+            LocalTransport::Memfd => match create_sealed_memfd(&payload) {
+                Ok(memfd) => Some(memfd),
+                Err(e) => {
+                    error!("Failed to create the memfd of a frame: {}", e);
+                    return;
+                }
+            },
+            LocalTransport::Inline => None,
+        };
+
+        consumers.retain_mut(|stream| {
+            #[cfg(target_os = "linux")]
+            let result = match memfd.as_ref() {
+                Some(memfd) => {
+                    use std::os::fd::AsFd;
+                    send_with_fd(stream, &header, memfd.as_fd())
+                }
+                None => stream.write_all(&header).and_then(|_| stream.write_all(&payload)),
+            };
+            #[cfg(not(target_os = "linux"))]
+            let result = stream.write_all(&header).and_then(|_| stream.write_all(&payload));
+
+            match result {
+                Ok(()) => true,
+                Err(e) => {
+                    info!("Local consumer disconnected: {}", e);
+                    false
+                }
+            }
+        });
+    }
+
+    fn set_fps(&self, fps: u32) {
+        *self.fps.lock().unwrap() = fps;
+    }
+
+    fn set_encoding_format(&self, encoding_format: EncodingFormat) {
+        *self.encoding_format.lock().unwrap() = encoding_format;
+    }
+
+    fn set_max_number_of_points(&self, max_number_of_points: u64) {
+        *self.max_number_of_points.lock().unwrap() = max_number_of_points;
+    }
+
+    fn set_frame_buffer_capacity(&self, capacity: usize) {
+        let dropped = self.frame_buffer.lock().unwrap().set_capacity(capacity);
+        self.egress_metrics.record_buffer_drops(&dropped);
+    }
+
+    fn flush_and_close(&self, timeout: Duration) {
+        drain_frame_buffer("LOCAL_E", &self.frame_buffer, timeout);
+        for stream in self.consumers.lock().unwrap().drain(..) {
+            if let Err(e) = stream.shutdown(std::net::Shutdown::Both) {
+                warn!("Failed to close the connection of a local consumer: {}", e);
+            }
+        }
+        if let Err(e) = fs::remove_file(&self.config.socket_path) {
+            warn!("Failed to remove the local egress socket {}: {}", self.config.socket_path.display(), e);
+        }
+    }
+}
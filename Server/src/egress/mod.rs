@@ -1,31 +1,81 @@
 // egress/mod.rs
 
 use std::sync::Arc;
+use shared_utils::peer_connection::PeerConnectionConfig;
 use tracing::instrument;
 
 use crate::services::mpd_manager::MpdManager;
 use crate::services::stream_manager::StreamManager;
 use crate::processing::ProcessingPipeline;
 
+pub mod adaptive_fec;
+pub mod backpressure;
+pub mod bitrate_ladder;
+pub mod client_budget;
 pub mod egress_common;
 pub mod flute;
+pub mod flute_feedback;
+pub mod grpc;
+#[cfg(unix)]
+pub mod local;
+pub mod mqtt;
+pub mod ndi;
+pub mod pacing;
+pub mod preview;
+pub mod projection;
+pub mod quic;
+pub mod registry;
+pub mod ring_buffer;
+pub mod route;
+pub mod rtp;
+pub mod rtsp;
+pub mod srt;
+pub mod tcp;
+pub mod tile_priority;
+pub mod video_encoder;
 pub mod webrtc;
 pub mod websocket;
+pub mod webtransport;
+pub mod zenoh;
 pub mod file;
 pub mod buffer;
 // Add other egress protocols as needed
 
+/// The settings of the built-in egress protocols, the registered egress protocols are configured by their factories.
+pub struct EgressConfig {
+    pub flute_endpoint_url: String,
+    pub flute_port: u16,
+    pub flute_feedback_port: Option<u16>,
+    pub peer_connection_config: PeerConnectionConfig,
+    pub webtransport_port: u16,
+    pub quic_port: u16,
+    pub srt_config: srt::SrtConfig,
+    pub hls_parts_per_segment: u64,
+    pub dash_chunks_per_segment: u64,
+    pub dash_tile_tracks: bool,
+    pub rtp_config: rtp::RtpMulticastConfig,
+    pub rtsp_config: Option<rtsp::RtspConfig>,
+    pub tcp_mode: Option<tcp::TcpMode>,
+    pub mqtt_config: mqtt::MqttConfig,
+    pub zenoh_config: zenoh::ZenohConfig,
+    pub grpc_port: u16,
+    pub ndi_config: Option<ndi::NdiConfig>,
+    pub projection_config: Option<projection::ProjectionConfig>,
+    #[cfg(unix)]
+    pub local_config: Option<local::LocalConfig>,
+}
+
 #[instrument(skip_all)]
 pub fn initialize_egress_protocols(
     stream_manager: Arc<StreamManager>,
     mpd_manager: Arc<MpdManager>,
     processing_pipeline: Arc<ProcessingPipeline>,
-    flute_endpoint_url: String,
-    flute_port: u16,
+    config: EgressConfig,
 ) {
     webrtc::WebRTCEgress::initialize(
         stream_manager.clone(),
         processing_pipeline.clone(),
+        config.peer_connection_config.clone(),
     );
 
     websocket::WebSocketEgress::initialize(
@@ -33,11 +83,86 @@ pub fn initialize_egress_protocols(
         processing_pipeline.clone(),
     );
 
+    webtransport::WebTransportEgress::initialize(
+        stream_manager.clone(),
+        processing_pipeline.clone(),
+        config.webtransport_port,
+    );
+
+    quic::QuicEgress::initialize(
+        stream_manager.clone(),
+        processing_pipeline.clone(),
+        config.quic_port,
+    );
+
+    srt::SrtEgress::initialize(
+        stream_manager.clone(),
+        processing_pipeline.clone(),
+        config.srt_config,
+    );
+
+    tcp::TcpEgress::initialize(
+        stream_manager.clone(),
+        processing_pipeline.clone(),
+        config.tcp_mode,
+    );
+
+    mqtt::MqttEgress::initialize(
+        stream_manager.clone(),
+        processing_pipeline.clone(),
+        config.mqtt_config,
+    );
+
+    zenoh::ZenohEgress::initialize(
+        stream_manager.clone(),
+        processing_pipeline.clone(),
+        config.zenoh_config,
+    );
+
+    grpc::GrpcEgress::initialize(
+        stream_manager.clone(),
+        processing_pipeline.clone(),
+        config.grpc_port,
+    );
+
+    ndi::NdiEgress::initialize(
+        stream_manager.clone(),
+        processing_pipeline.clone(),
+        config.ndi_config,
+    );
+
+    projection::ProjectionEgress::initialize(
+        stream_manager.clone(),
+        processing_pipeline.clone(),
+        config.peer_connection_config,
+        config.projection_config,
+    );
+
+    #[cfg(unix)]
+    local::LocalEgress::initialize(
+        stream_manager.clone(),
+        processing_pipeline.clone(),
+        config.local_config,
+    );
+
+    rtp::RtpMulticastEgress::initialize(
+        stream_manager.clone(),
+        processing_pipeline.clone(),
+        config.rtp_config,
+    );
+
+    rtsp::RtspEgress::initialize(
+        stream_manager.clone(),
+        processing_pipeline.clone(),
+        config.rtsp_config,
+    );
+
     flute::FluteEgress::initialize(
         stream_manager.clone(),
         processing_pipeline.clone(),
-        flute_endpoint_url,
-        flute_port,
+        config.flute_endpoint_url,
+        config.flute_port,
+        config.flute_feedback_port,
     );
 
     file::FileEgress::initialize(
@@ -49,6 +174,14 @@ pub fn initialize_egress_protocols(
         stream_manager.clone(),
         processing_pipeline.clone(),
         mpd_manager.clone(),
+        config.hls_parts_per_segment,
+        config.dash_chunks_per_segment,
+        config.dash_tile_tracks,
     );
 
+    // The egress protocols that were registered at startup
+    registry::get_egress_registry().initialize_all(
+        stream_manager.clone(),
+        processing_pipeline.clone(),
+    );
 }
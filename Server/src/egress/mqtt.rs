@@ -0,0 +1,335 @@
+// egress/mqtt.rs
+
+use std::collections::HashSet;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::encoders::EncodingFormat;
+use crate::processing::{aggregator::PointCloudAggregator, ProcessingPipeline};
+use crate::services::stream_manager::StreamManager;
+use crate::types::EgressProtocolType;
+use shared_utils::error::{XrError, XrResult};
+use shared_utils::types::{FrameTaskData, PointCloudData};
+use shared_utils::wire::encode_frame;
+
+use super::ring_buffer::RingBuffer;
+use rumqttc::{AsyncClient, EventLoop, MqttOptions, QoS};
+use serde_json::json;
+use tokio::runtime::{self, Runtime};
+use tracing::{debug, error, info, instrument, warn};
+
+use super::egress_common::{drain_frame_buffer, push_preencoded_frame_data, EgressCommonMetrics, EgressProtocol};
+
+static MQTT_RUNTIME: OnceLock<Arc<Runtime>> = OnceLock::new();
+
+/// Largest MQTT packet the client sends, point cloud frames are far larger than the default of 10 kB
+const MQTT_MAX_PACKET_SIZE: usize = 64 * 1024 * 1024;
+
+/// Settings of the MQTT egress.
+#[derive(Clone, Debug)]
+pub struct MqttConfig {
+    /// Address of the broker, e.g. localhost:1883, the egress is disabled when `None`
+    pub broker: Option<String>,
+    pub client_id: String,
+    /// Frames are published to `<topic_prefix>/<stream>/frames`, the metadata to `<topic_prefix>/<stream>/init`
+    pub topic_prefix: String,
+    /// QoS level (0, 1 or 2) of the frames
+    pub qos: u8,
+}
+
+/// MQTT Egress module responsible for publishing frames to an MQTT broker.
+/// Every stream (client and tile) gets its own topic, so subscribers can select the tiles they need.
+#[derive(Clone)]
+pub struct MqttEgress {
+    processing_pipeline: Arc<ProcessingPipeline>,
+    frame_buffer: Arc<Mutex<RingBuffer<FrameTaskData>>>,
+    aggregator: Arc<PointCloudAggregator>,
+    threads_started: Arc<AtomicBool>,
+    fps: Arc<Mutex<u32>>,
+    encoding_format: Arc<Mutex<EncodingFormat>>,
+    max_number_of_points: Arc<Mutex<u64>>,
+    client: AsyncClient,
+    topic_prefix: String,
+    qos: Arc<Mutex<QoS>>,
+    /// Streams of which the init metadata has been published
+    announced_streams: Arc<Mutex<HashSet<String>>>,
+    egress_metrics: Arc<EgressCommonMetrics>,
+}
+
+impl std::fmt::Debug for MqttEgress {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("MqttEgress")
+            .field("topic_prefix", &self.topic_prefix)
+            .field("qos", &self.qos)
+            .field("processing_pipeline", &self.processing_pipeline)
+            .field("frame_buffer", &self.frame_buffer)
+            .field("aggregator", &self.aggregator)
+            .field("fps", &self.fps)
+            .field("encoding_format", &self.encoding_format)
+            .field("max_number_of_points", &self.max_number_of_points)
+            .field("egress_metrics", &self.egress_metrics)
+            .finish()
+    }
+}
+
+impl MqttEgress {
+    /// Initializes the MQTT Egress module, and connects to the broker of `config`.
+    #[instrument(skip_all)]
+    pub fn initialize(
+        stream_manager: Arc<StreamManager>,
+        processing_pipeline: Arc<ProcessingPipeline>,
+        config: MqttConfig,
+    ) {
+        let Some(broker) = config.broker.clone() else {
+            info!("No MQTT broker configured, the MQTT egress is disabled");
+            return;
+        };
+
+        let (options, qos) = match Self::parse_config(&broker, &config) {
+            Ok(parsed) => parsed,
+            Err(e) => {
+                error!("Failed to initialize the MQTT egress: {}", e);
+                return;
+            }
+        };
+
+        let (client, event_loop) = AsyncClient::new(options, 10);
+
+        let aggregator = Arc::new(PointCloudAggregator::new(stream_manager.clone()));
+
+        let instance = Arc::new(Self {
+            processing_pipeline: processing_pipeline.clone(),
+            frame_buffer: Arc::new(Mutex::new(RingBuffer::new(stream_manager.frame_buffer_capacity(&EgressProtocolType::Mqtt)))),
+            aggregator: aggregator.clone(),
+            threads_started: Arc::new(AtomicBool::new(false)),
+            fps: Arc::new(Mutex::new(30)),
+            encoding_format: Arc::new(Mutex::new(EncodingFormat::Draco)),
+            max_number_of_points: Arc::new(Mutex::new(100000)),
+            client,
+            topic_prefix: config.topic_prefix.trim_end_matches('/').to_string(),
+            qos: Arc::new(Mutex::new(qos)),
+            announced_streams: Arc::new(Mutex::new(HashSet::new())),
+            egress_metrics: Arc::new(EgressCommonMetrics::new("MQTT_E")),
+        });
+
+        let instance_clone = instance.clone();
+        Self::runtime().spawn(async move {
+            instance_clone.drive_event_loop(event_loop).await;
+        });
+
+        info!("MQTT egress publishing to {} under {}", broker, instance.topic_prefix);
+
+        // Store the instance in the StreamManager
+        stream_manager.set_mqtt_egress(instance.clone());
+    }
+
+    fn runtime() -> Arc<Runtime> {
+        MQTT_RUNTIME.get_or_init(|| {
+            let rt = runtime::Builder::new_multi_thread()
+                .worker_threads(2)
+                .thread_name_fn(|| {
+                    static ATOMIC_MQTT_ID: std::sync::atomic::AtomicUsize = std::sync::atomic::AtomicUsize::new(0);
+                    let id = ATOMIC_MQTT_ID.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+                    format!("MQTT_R w-{}", id)
+                })
+                .enable_all()
+                .build().unwrap();
+            Arc::new(rt)
+        }).clone()
+    }
+
+    fn parse_config(broker: &str, config: &MqttConfig) -> XrResult<(MqttOptions, QoS)> {
+        let (host, port) = broker.rsplit_once(':')
+            .ok_or_else(|| XrError::Config(format!("Expected an MQTT broker like host:port, got {broker}")))?;
+        let port = port.parse::<u16>()
+            .map_err(|e| XrError::Config(format!("Invalid MQTT broker port {port}: {e}")))?;
+
+        let mut options = MqttOptions::new(config.client_id.clone(), host, port);
+        options.set_keep_alive(Duration::from_secs(5));
+        options.set_max_packet_size(MQTT_MAX_PACKET_SIZE, MQTT_MAX_PACKET_SIZE);
+
+        Ok((options, Self::parse_qos(config.qos)?))
+    }
+
+    fn parse_qos(qos: u8) -> XrResult<QoS> {
+        rumqttc::qos(qos).map_err(|_| XrError::Config(format!("Invalid MQTT QoS {qos}, expected 0, 1 or 2")))
+    }
+
+    /// The event loop performs the actual network I/O of the client, and reconnects when polled after an error.
+    async fn drive_event_loop(&self, mut event_loop: EventLoop) {
+        loop {
+            if let Err(e) = event_loop.poll().await {
+                warn!("MQTT connection error: {}", e);
+                // Everything has to be announced again, the broker may have lost the retained messages
+                self.announced_streams.lock().unwrap().clear();
+                tokio::time::sleep(Duration::from_secs(1)).await;
+            }
+        }
+    }
+
+    /// Sets the QoS level of the published frames.
+    #[instrument(skip_all)]
+    pub fn set_qos(&self, qos: u8) -> XrResult<()> {
+        *self.qos.lock().unwrap() = Self::parse_qos(qos)?;
+        Ok(())
+    }
+
+    fn stream_topic(&self, frame: &FrameTaskData) -> String {
+        format!(
+            "{}/client_{}_{}",
+            self.topic_prefix,
+            frame.sfu_client_id.unwrap_or(0),
+            frame.sfu_tile_index.unwrap_or(0)
+        )
+    }
+}
+
+impl EgressProtocol for MqttEgress {
+    #[inline]
+    fn fps(&self) -> u32 {
+        *self.fps.lock().unwrap()
+    }
+
+    #[inline]
+    fn encoding_format(&self) -> EncodingFormat {
+        *self.encoding_format.lock().unwrap()
+    }
+
+    #[inline]
+    fn max_number_of_points(&self) -> u64 {
+        *self.max_number_of_points.lock().unwrap()
+    }
+
+    fn ensure_threads_started(&self) {
+        let already_started = self.threads_started.load(Ordering::Relaxed);
+        if already_started {
+            return;
+        }
+
+        // Set the threads as started
+        self.threads_started.store(true, Ordering::Relaxed);
+
+        // Start background threads using the common module
+        crate::egress::egress_common::start_generator_thread(
+            "MQTT_E".to_string(),
+            self.processing_pipeline.clone(),
+            self.aggregator.clone(),
+            self.frame_buffer.clone(),
+            self.fps.clone(),
+            self.encoding_format.clone(),
+            self.max_number_of_points.clone(),
+        );
+
+        let self_clone = self.clone();
+        crate::egress::egress_common::start_transmission_thread(
+            "MQTT_E".to_string(),
+            self.frame_buffer.clone(),
+            move |frame| {
+                self_clone.emit_frame_data(frame);
+            },
+            false,
+        );
+    }
+
+    fn push_point_cloud(&self, point_cloud: PointCloudData, stream_id: String) {
+        self.ensure_threads_started();
+        self.aggregator.update_point_cloud(stream_id, point_cloud);
+    }
+
+    // Process and sends a frame, this raw version bypasses the aggregation
+    fn push_encoded_frame(&self, raw_data: Vec<u8>, _stream_id: String, mut creation_time: u64, presentation_time: u64, ring_buffer_bypass: bool, client_id: Option<u64>, tile_index: Option<u32>) {
+        // Ensure the threads are started
+        self.ensure_threads_started();
+
+        let self_clone = self.clone();
+        let bypass = if ring_buffer_bypass {
+
+            let since_the_epoch = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .expect("Time went backwards");
+            creation_time = since_the_epoch.as_micros() as u64;
+
+            Some(Box::new(move |frame| {
+                self_clone.emit_frame_data(frame);
+            }) as Box<dyn Fn(FrameTaskData) + Send + 'static>)
+        } else {
+            None
+        };
+
+        push_preencoded_frame_data(
+            "MQTT_E",
+            &self.frame_buffer,
+            creation_time,
+            presentation_time,
+            raw_data, // data is moved
+            bypass,
+            &self.egress_metrics,
+            client_id,
+            tile_index,
+        );
+    }
+
+    /// Publishes the frame to the topic of its stream.
+    /// The first frame of a stream is preceded by a retained init message, so late subscribers know how to decode it.
+    fn emit_frame_data(&self, frame: FrameTaskData) {
+        debug!("Emitting frame with presentation time: {}", frame.presentation_time);
+
+        let topic = self.stream_topic(&frame);
+        let qos = *self.qos.lock().unwrap();
+
+        let init = if self.announced_streams.lock().unwrap().insert(topic.clone()) {
+            let codec = String::from_utf8_lossy(&frame.data[..frame.data.len().min(3)]).to_lowercase();
+            Some(json!({
+                "encoding_format": self.encoding_format(),
+                "codec": codec,
+                "fps": *self.fps.lock().unwrap(),
+                "client_id": frame.sfu_client_id,
+                "tile_index": frame.sfu_tile_index,
+            }).to_string())
+        } else {
+            None
+        };
+
+        let payload = encode_frame(&frame);
+        let client = self.client.clone();
+
+        Self::runtime().block_on(async move {
+            if let Some(init) = init {
+                if let Err(e) = client.publish(format!("{topic}/init"), QoS::AtLeastOnce, true, init).await {
+                    error!("Failed to publish init metadata to {}: {}", topic, e);
+                }
+            }
+            if let Err(e) = client.publish(format!("{topic}/frames"), qos, false, payload).await {
+                error!("Failed to publish frame to {}: {}", topic, e);
+            }
+        });
+    }
+
+    fn set_fps(&self, fps: u32) {
+        *self.fps.lock().unwrap() = fps;
+    }
+
+    fn set_encoding_format(&self, encoding_format: EncodingFormat) {
+        *self.encoding_format.lock().unwrap() = encoding_format;
+    }
+
+    fn set_max_number_of_points(&self, max_number_of_points: u64) {
+        *self.max_number_of_points.lock().unwrap() = max_number_of_points;
+    }
+
+    fn set_frame_buffer_capacity(&self, capacity: usize) {
+        let dropped = self.frame_buffer.lock().unwrap().set_capacity(capacity);
+        self.egress_metrics.record_buffer_drops(&dropped);
+    }
+
+    fn flush_and_close(&self, timeout: Duration) {
+        drain_frame_buffer("MQTT_E", &self.frame_buffer, timeout);
+        let client = self.client.clone();
+        Self::runtime().block_on(async move {
+            if let Err(e) = client.disconnect().await {
+                warn!("Failed to disconnect from the MQTT broker: {}", e);
+            }
+        });
+    }
+}
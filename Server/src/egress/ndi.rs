@@ -0,0 +1,370 @@
+// egress/ndi.rs
+
+use std::ffi::{c_char, c_int, c_void, CString};
+use std::fmt;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::encoders::EncodingFormat;
+use crate::processing::{aggregator::PointCloudAggregator, ProcessingPipeline};
+use crate::services::shutdown::is_shutting_down;
+use crate::services::stream_manager::{StreamManager, COMBINED_STREAM_ID};
+use shared_utils::error::{XrError, XrResult};
+use shared_utils::types::{FrameTaskData, PointCloudData};
+
+use libloading::Library;
+use tracing::{debug, error, info, instrument, warn};
+
+use super::egress_common::{frame_stream_id, EgressCommonMetrics, EgressProtocol};
+use super::preview::{render_preview, PreviewCamera};
+
+/// 'BGRA' as the FourCC of the NDI video frames
+const NDI_FOURCC_BGRA: u32 = u32::from_le_bytes(*b"BGRA");
+const NDI_FRAME_FORMAT_PROGRESSIVE: c_int = 1;
+/// Lets the NDI runtime fill in the timecode
+const NDI_TIMECODE_SYNTHESIZE: i64 = i64::MAX;
+
+/// `NDIlib_send_create_t`
+#[repr(C)]
+struct NdiSendCreate {
+    p_ndi_name: *const c_char,
+    p_groups: *const c_char,
+    clock_video: bool,
+    clock_audio: bool,
+}
+
+/// `NDIlib_video_frame_v2_t`
+#[repr(C)]
+struct NdiVideoFrameV2 {
+    xres: c_int,
+    yres: c_int,
+    four_cc: u32,
+    frame_rate_n: c_int,
+    frame_rate_d: c_int,
+    picture_aspect_ratio: f32,
+    frame_format_type: c_int,
+    timecode: i64,
+    p_data: *mut u8,
+    line_stride_in_bytes: c_int,
+    p_metadata: *const c_char,
+    timestamp: i64,
+}
+
+/// The functions of the NDI runtime that the egress uses.
+/// The runtime is loaded when the egress starts, as the NDI SDK license does not allow to ship it with the server.
+struct NdiLibrary {
+    send_create: unsafe extern "C" fn(*const NdiSendCreate) -> *mut c_void,
+    send_send_video_v2: unsafe extern "C" fn(*mut c_void, *const NdiVideoFrameV2),
+    send_get_no_connections: unsafe extern "C" fn(*mut c_void, u32) -> c_int,
+    send_destroy: unsafe extern "C" fn(*mut c_void),
+    // Keeps the functions above valid
+    _library: Library,
+}
+
+impl NdiLibrary {
+    /// Loads the NDI runtime from the directory of the NDI_RUNTIME_DIR_V6/V5 environment variables, or from the library path.
+    fn load() -> XrResult<Self> {
+        let file_name = if cfg!(target_os = "windows") {
+            "Processing.NDI.Lib.x64.dll"
+        } else if cfg!(target_os = "macos") {
+            "libndi.dylib"
+        } else {
+            "libndi.so"
+        };
+        let mut candidates: Vec<std::path::PathBuf> = ["NDI_RUNTIME_DIR_V6", "NDI_RUNTIME_DIR_V5"]
+            .iter()
+            .filter_map(|variable| std::env::var_os(variable))
+            .map(|directory| std::path::PathBuf::from(directory).join(file_name))
+            .collect();
+        if cfg!(target_os = "linux") {
+            candidates.push("libndi.so.6".into());
+            candidates.push("libndi.so.5".into());
+        }
+        candidates.push(file_name.into());
+
+        // Safety: loading the NDI runtime runs its initializers, which have no requirements
+        let library = candidates
+            .iter()
+            .find_map(|candidate| unsafe { Library::new(candidate) }.ok())
+            .ok_or_else(|| XrError::Config(format!("Could not load the NDI runtime ({file_name}), install the NDI runtime or set NDI_RUNTIME_DIR_V6")))?;
+
+        // Safety: the signatures match the declarations in Processing.NDI.Lib.h
+        unsafe {
+            let initialize = *library.get::<unsafe extern "C" fn() -> bool>(b"NDIlib_initialize\0").map_err(XrError::config)?;
+            if !initialize() {
+                return Err(XrError::Config("The NDI runtime does not support this CPU".to_string()));
+            }
+            Ok(Self {
+                send_create: *library.get(b"NDIlib_send_create\0").map_err(XrError::config)?,
+                send_send_video_v2: *library.get(b"NDIlib_send_send_video_v2\0").map_err(XrError::config)?,
+                send_get_no_connections: *library.get(b"NDIlib_send_get_no_connections\0").map_err(XrError::config)?,
+                send_destroy: *library.get(b"NDIlib_send_destroy\0").map_err(XrError::config)?,
+                _library: library,
+            })
+        }
+    }
+}
+
+/// An NDI source that the previews are published on.
+struct NdiSender {
+    library: NdiLibrary,
+    instance: *mut c_void,
+}
+
+// Safety: an NDI sender may be used from any thread, the egress only uses it from one thread at a time
+unsafe impl Send for NdiSender {}
+
+impl NdiSender {
+    fn new(source_name: &str) -> XrResult<Self> {
+        let library = NdiLibrary::load()?;
+        let name = CString::new(source_name).map_err(XrError::config)?;
+        let settings = NdiSendCreate {
+            p_ndi_name: name.as_ptr(),
+            p_groups: std::ptr::null(),
+            // The preview thread paces the frames itself
+            clock_video: false,
+            clock_audio: false,
+        };
+        // Safety: the settings and the name outlive the call, the runtime copies them
+        let instance = unsafe { (library.send_create)(&settings) };
+        if instance.is_null() {
+            return Err(XrError::Transport(format!("Failed to create the NDI source {source_name}")));
+        }
+        Ok(Self { library, instance })
+    }
+
+    fn connections(&self) -> usize {
+        // Safety: the instance is valid until the sender is dropped
+        unsafe { (self.library.send_get_no_connections)(self.instance, 0) }.max(0) as usize
+    }
+
+    /// Sends a BGRA image, the runtime has copied it once this returns.
+    fn send_video(&self, image: &mut [u8], width: u32, height: u32, fps: u32) {
+        let frame = NdiVideoFrameV2 {
+            xres: width as c_int,
+            yres: height as c_int,
+            four_cc: NDI_FOURCC_BGRA,
+            frame_rate_n: fps as c_int,
+            frame_rate_d: 1,
+            picture_aspect_ratio: width as f32 / height as f32,
+            frame_format_type: NDI_FRAME_FORMAT_PROGRESSIVE,
+            timecode: NDI_TIMECODE_SYNTHESIZE,
+            p_data: image.as_mut_ptr(),
+            line_stride_in_bytes: (width * 4) as c_int,
+            p_metadata: std::ptr::null(),
+            timestamp: 0,
+        };
+        // Safety: the image holds width * height BGRA pixels, and the synchronous send copies it
+        unsafe { (self.library.send_send_video_v2)(self.instance, &frame) };
+    }
+}
+
+impl Drop for NdiSender {
+    fn drop(&mut self) {
+        // Safety: the instance is not used after this
+        unsafe { (self.library.send_destroy)(self.instance) };
+    }
+}
+
+/// Settings of the NDI preview.
+#[derive(Clone, Debug)]
+pub struct NdiConfig {
+    /// Name of the NDI source, as shown in the production tooling
+    pub source_name: String,
+    pub camera: PreviewCamera,
+    pub width: u32,
+    pub height: u32,
+    /// Size of a point in pixels
+    pub point_size: u32,
+}
+
+/// NDI Egress module responsible for rendering the combined point cloud to a 2D preview,
+/// and publishing it as an NDI video source, so operators can monitor the stream content on standard production tooling.
+#[derive(Clone)]
+pub struct NdiEgress {
+    processing_pipeline: Arc<ProcessingPipeline>,
+    aggregator: Arc<PointCloudAggregator>,
+    threads_started: Arc<AtomicBool>,
+    fps: Arc<Mutex<u32>>,
+    encoding_format: Arc<Mutex<EncodingFormat>>,
+    max_number_of_points: Arc<Mutex<u64>>,
+    config: Arc<Mutex<NdiConfig>>,
+    sender: Arc<Mutex<Option<NdiSender>>>,
+    egress_metrics: Arc<EgressCommonMetrics>,
+}
+
+impl fmt::Debug for NdiEgress {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("NdiEgress")
+            .field("processing_pipeline", &self.processing_pipeline)
+            .field("aggregator", &self.aggregator)
+            .field("fps", &self.fps)
+            .field("max_number_of_points", &self.max_number_of_points)
+            .field("config", &self.config)
+            .field("egress_metrics", &self.egress_metrics)
+            .finish()
+    }
+}
+
+impl NdiEgress {
+    /// Initializes the NDI Egress module, the NDI source is announced as soon as the runtime is loaded.
+    #[instrument(skip_all)]
+    pub fn initialize(
+        stream_manager: Arc<StreamManager>,
+        processing_pipeline: Arc<ProcessingPipeline>,
+        config: Option<NdiConfig>,
+    ) {
+        let Some(config) = config else {
+            info!("No NDI source name configured, the NDI egress is disabled");
+            return;
+        };
+
+        let sender = match NdiSender::new(&config.source_name) {
+            Ok(sender) => sender,
+            Err(e) => {
+                error!("Failed to start the NDI egress: {}", e);
+                return;
+            }
+        };
+        info!("NDI egress publishes the source {}", config.source_name);
+
+        let instance = Arc::new(Self {
+            processing_pipeline,
+            aggregator: Arc::new(PointCloudAggregator::new(stream_manager.clone())),
+            threads_started: Arc::new(AtomicBool::new(false)),
+            fps: Arc::new(Mutex::new(30)),
+            encoding_format: Arc::new(Mutex::new(EncodingFormat::Draco)),
+            max_number_of_points: Arc::new(Mutex::new(100000)),
+            config: Arc::new(Mutex::new(config)),
+            sender: Arc::new(Mutex::new(Some(sender))),
+            egress_metrics: Arc::new(EgressCommonMetrics::new("NDI_E")),
+        });
+
+        // Store the instance in the StreamManager
+        stream_manager.set_ndi_egress(instance.clone());
+    }
+
+    /// Renders the preview from another camera from now on.
+    pub fn set_camera(&self, camera: PreviewCamera) {
+        self.config.lock().unwrap().camera = camera;
+    }
+
+    /// Renders and publishes the combined point cloud at the frame rate of the egress, until the server shuts down.
+    fn send_previews(&self) {
+        let stream_metrics = self.egress_metrics.for_stream(COMBINED_STREAM_ID);
+        loop {
+            if is_shutting_down() {
+                debug!("Stopping the NDI previews");
+                break;
+            }
+
+            let fps = (*self.fps.lock().unwrap()).max(1);
+            let frame_duration = Duration::from_micros(1_000_000 / fps as u64);
+            let start_time = Instant::now();
+
+            let has_receivers = match self.sender.lock().unwrap().as_ref() {
+                Some(sender) => sender.connections() > 0,
+                None => break,
+            };
+            // Nothing is rendered while no receiver watches the source
+            if has_receivers {
+                let point_cloud = self.aggregator.generate_combined_point_cloud(*self.max_number_of_points.lock().unwrap());
+                stream_metrics.pc_combination_time.observe(start_time.elapsed().as_micros() as f64);
+
+                if !point_cloud.points.is_empty() {
+                    let config = self.config.lock().unwrap().clone();
+                    let render_start = Instant::now();
+                    let mut image = render_preview(&point_cloud, config.camera, config.width, config.height, config.point_size);
+                    stream_metrics.pc_encoding_time.observe(render_start.elapsed().as_micros() as f64);
+
+                    let emit_start = Instant::now();
+                    if let Some(sender) = self.sender.lock().unwrap().as_ref() {
+                        sender.send_video(&mut image, config.width.max(1), config.height.max(1), fps);
+                    }
+                    stream_metrics.emission_time.observe(emit_start.elapsed().as_micros() as f64);
+                    stream_metrics.frames_to_emit.inc();
+                }
+            }
+
+            if let Some(sleep_duration) = frame_duration.checked_sub(start_time.elapsed()) {
+                thread::sleep(sleep_duration);
+            }
+        }
+    }
+}
+
+impl EgressProtocol for NdiEgress {
+    #[inline]
+    fn fps(&self) -> u32 {
+        *self.fps.lock().unwrap()
+    }
+
+    #[inline]
+    fn encoding_format(&self) -> EncodingFormat {
+        *self.encoding_format.lock().unwrap()
+    }
+
+    #[inline]
+    fn max_number_of_points(&self) -> u64 {
+        *self.max_number_of_points.lock().unwrap()
+    }
+
+    fn ensure_threads_started(&self) {
+        let already_started = self.threads_started.swap(true, Ordering::Relaxed);
+        if already_started {
+            return;
+        }
+
+        // The previews are rendered from the point clouds, so there is no generator and frame buffer
+        let self_clone = self.clone();
+        let _ = thread::Builder::new().name("NDI_E Preview Thread".to_string()).spawn(move || {
+            self_clone.send_previews();
+        });
+    }
+
+    fn push_point_cloud(&self, point_cloud: PointCloudData, stream_id: String) {
+        self.ensure_threads_started();
+        self.aggregator.update_point_cloud(stream_id, point_cloud);
+    }
+
+    // Encoded frames are decoded again, as the preview is rendered from the points
+    fn push_encoded_frame(&self, raw_data: Vec<u8>, stream_id: String, _creation_time: u64, _presentation_time: u64, _ring_buffer_bypass: bool, _client_id: Option<u64>, _tile_index: Option<u32>) {
+        self.ensure_threads_started();
+        match self.processing_pipeline.decode(raw_data, &stream_id) {
+            Ok(point_cloud) => self.aggregator.update_point_cloud(stream_id, point_cloud),
+            Err(e) => debug!("Failed to decode frame for the NDI preview: {}", e),
+        }
+    }
+
+    fn emit_frame_data(&self, frame: FrameTaskData) {
+        let stream_id = frame_stream_id(&frame);
+        match self.processing_pipeline.decode(frame.data, &stream_id) {
+            Ok(point_cloud) => self.aggregator.update_point_cloud(stream_id, point_cloud),
+            Err(e) => debug!("Failed to decode frame for the NDI preview: {}", e),
+        }
+    }
+
+    fn set_fps(&self, fps: u32) {
+        *self.fps.lock().unwrap() = fps;
+    }
+
+    // The preview is rendered rather than encoded, the encoding format is only kept for the settings API
+    fn set_encoding_format(&self, encoding_format: EncodingFormat) {
+        *self.encoding_format.lock().unwrap() = encoding_format;
+    }
+
+    fn set_max_number_of_points(&self, max_number_of_points: u64) {
+        *self.max_number_of_points.lock().unwrap() = max_number_of_points;
+    }
+
+    fn set_frame_buffer_capacity(&self, _capacity: usize) {
+        warn!("The NDI egress has no frame buffer");
+    }
+
+    fn flush_and_close(&self, _timeout: Duration) {
+        // Removes the NDI source from the network
+        self.sender.lock().unwrap().take();
+    }
+}
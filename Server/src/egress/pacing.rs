@@ -0,0 +1,95 @@
+// egress/pacing.rs
+
+//! Adjusts the send rate of a paced egress to the goodput and loss that the receivers report.
+
+use std::time::{Duration, Instant};
+
+#[derive(Clone, Copy, Debug)]
+pub struct PacingConfig {
+    /// The rate is never lowered below this, in bits per second
+    pub min_rate: u32,
+    /// Above this loss fraction the rate is decreased
+    pub high_loss: f32,
+    /// Below this loss fraction the rate is increased, in between the rate is kept
+    pub low_loss: f32,
+    /// Fraction of the rate that is added when there is (almost) no loss
+    pub increase: f32,
+    /// A new rate is only applied when it differs this fraction from the current one
+    pub hysteresis: f32,
+    /// Minimum time between two rate changes, so the effect of a change shows up in the next reports
+    pub hold_time: Duration,
+}
+
+impl Default for PacingConfig {
+    fn default() -> Self {
+        Self {
+            min_rate: 1_000_000,
+            high_loss: 0.02,
+            low_loss: 0.005,
+            increase: 0.05,
+            hysteresis: 0.03,
+            hold_time: Duration::from_millis(500),
+        }
+    }
+}
+
+/// Additive increase, multiplicative decrease of the send rate, bounded by the configured bandwidth.
+#[derive(Clone, Debug)]
+pub struct PacingController {
+    config: PacingConfig,
+    max_rate: u32,
+    rate: u32,
+    last_change: Option<Instant>,
+}
+
+impl PacingController {
+    /// Starts sending at the configured bandwidth, which remains the maximum rate.
+    pub fn new(config: PacingConfig, max_rate: u32) -> Self {
+        Self {
+            config,
+            max_rate,
+            rate: max_rate,
+            last_change: None,
+        }
+    }
+
+    pub fn rate(&self) -> u32 {
+        self.rate
+    }
+
+    pub fn set_max_rate(&mut self, max_rate: u32) {
+        self.max_rate = max_rate;
+        self.rate = self.rate.min(max_rate);
+    }
+
+    /// Handles a receiver report with the fraction of lost packets, and the goodput in bits per second if known.
+    /// Returns the new rate if it changed.
+    pub fn on_feedback(&mut self, loss_fraction: f32, goodput: Option<u64>) -> Option<u32> {
+        if self.last_change.is_some_and(|last_change| last_change.elapsed() < self.config.hold_time) {
+            return None;
+        }
+
+        let rate = self.rate as f64;
+        let target = if loss_fraction > self.config.high_loss {
+            // Back off to what actually arrived, or proportionally to the loss when the goodput is unknown
+            match goodput {
+                Some(goodput) => (goodput as f64 * 0.95).min(rate),
+                None => rate * (1.0 - (loss_fraction as f64).min(0.5)),
+            }
+        } else if loss_fraction < self.config.low_loss {
+            rate * (1.0 + self.config.increase as f64)
+        } else {
+            rate
+        };
+        let target = (target as u32).clamp(self.config.min_rate.min(self.max_rate), self.max_rate);
+
+        let change = (target as f64 - rate).abs() / rate.max(1.0);
+        // Reaching the maximum is always allowed, otherwise the last few percent would never be used
+        if target == self.rate || (change < self.config.hysteresis as f64 && target != self.max_rate) {
+            return None;
+        }
+        self.rate = target;
+        self.last_change = Some(Instant::now());
+        Some(target)
+    }
+}
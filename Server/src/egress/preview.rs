@@ -0,0 +1,136 @@
+// egress/preview.rs
+
+//! Renders a point cloud to a 2D image, for previews of the stream content on video tooling.
+
+use glam::{Mat4, Vec3, Vec4};
+use shared_utils::error::{XrError, XrResult};
+use shared_utils::types::PointCloudData;
+
+/// The camera the preview is rendered from.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum PreviewCamera {
+    /// Looks straight down the Y axis, fitted to the bounds of the point cloud
+    TopDown,
+    /// Looks from the front, along the negative Z axis, fitted to the bounds of the point cloud
+    Front,
+    /// A perspective camera at `position` that looks at `target`, with a vertical field of view in degrees
+    Perspective { position: Vec3, target: Vec3, fov_degrees: f32 },
+}
+
+impl PreviewCamera {
+    /// Parses "top-down", "front" or "perspective:x,y,z:target_x,target_y,target_z:fov_degrees".
+    pub fn parse(value: &str) -> XrResult<Self> {
+        let value = value.trim().to_lowercase();
+        match value.as_str() {
+            "top-down" | "topdown" => return Ok(PreviewCamera::TopDown),
+            "front" => return Ok(PreviewCamera::Front),
+            _ => {}
+        }
+
+        let parts: Vec<&str> = value.split(':').collect();
+        if parts.len() != 4 || parts[0] != "perspective" {
+            return Err(XrError::Config(format!("Expected a preview camera like top-down, front or perspective:x,y,z:x,y,z:fov, got {value}")));
+        }
+        let fov_degrees = parts[3].trim().parse::<f32>()
+            .map_err(|e| XrError::Config(format!("Invalid preview camera field of view {}: {e}", parts[3])))?;
+        Ok(PreviewCamera::Perspective {
+            position: parse_vec3(parts[1])?,
+            target: parse_vec3(parts[2])?,
+            fov_degrees,
+        })
+    }
+
+    /// Returns the matrix that maps the points to clip space.
+    fn view_projection(&self, point_cloud: &PointCloudData, aspect_ratio: f32) -> Mat4 {
+        match self {
+            PreviewCamera::TopDown | PreviewCamera::Front => {
+                let (min, max) = bounds(point_cloud);
+                let center = (min + max) * 0.5;
+                let extent = (max - min) * 0.5;
+                let (view, half_width, half_height, depth) = if *self == PreviewCamera::TopDown {
+                    // Looking down, with the negative Z axis pointing up in the image
+                    (Mat4::look_at_rh(center + Vec3::Y * (extent.y + 1.0), center, Vec3::NEG_Z), extent.x, extent.z, extent.y)
+                } else {
+                    (Mat4::look_at_rh(center + Vec3::Z * (extent.z + 1.0), center, Vec3::Y), extent.x, extent.y, extent.z)
+                };
+                // Fit the bounds into the image, keeping the aspect ratio of the point cloud
+                let half_height = half_height.max(half_width / aspect_ratio).max(0.001) * 1.05;
+                let half_width = half_height * aspect_ratio;
+                let projection = Mat4::orthographic_rh(-half_width, half_width, -half_height, half_height, 0.0, depth * 2.0 + 2.0);
+                projection * view
+            }
+            PreviewCamera::Perspective { position, target, fov_degrees } => {
+                let view = Mat4::look_at_rh(*position, *target, Vec3::Y);
+                let projection = Mat4::perspective_rh(fov_degrees.to_radians(), aspect_ratio, 0.01, 1000.0);
+                projection * view
+            }
+        }
+    }
+}
+
+fn parse_vec3(value: &str) -> XrResult<Vec3> {
+    let coordinates = value
+        .split(',')
+        .map(|coordinate| coordinate.trim().parse::<f32>())
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| XrError::Config(format!("Invalid preview camera coordinates {value}: {e}")))?;
+    match coordinates[..] {
+        [x, y, z] => Ok(Vec3::new(x, y, z)),
+        _ => Err(XrError::Config(format!("Expected 3 preview camera coordinates, got {value}"))),
+    }
+}
+
+fn bounds(point_cloud: &PointCloudData) -> (Vec3, Vec3) {
+    if point_cloud.points.is_empty() {
+        return (Vec3::ZERO, Vec3::ZERO);
+    }
+    point_cloud.points.iter().fold((Vec3::splat(f32::MAX), Vec3::splat(f32::MIN)), |(min, max), point| {
+        let position = Vec3::new(point.x, point.y, point.z);
+        (min.min(position), max.max(position))
+    })
+}
+
+/// Renders the points as squares of `point_size` pixels, the nearest point wins.
+/// Returns the image as BGRA, row by row, on a black background.
+pub fn render_preview(point_cloud: &PointCloudData, camera: PreviewCamera, width: u32, height: u32, point_size: u32) -> Vec<u8> {
+    let (width, height) = (width.max(1) as usize, height.max(1) as usize);
+    let mut image = vec![0u8; width * height * 4];
+    for pixel in image.chunks_exact_mut(4) {
+        pixel[3] = 255;
+    }
+    let mut depth_buffer = vec![f32::INFINITY; width * height];
+
+    let view_projection = camera.view_projection(point_cloud, width as f32 / height as f32);
+    let half_size = (point_size.max(1) / 2) as i64;
+    for point in &point_cloud.points {
+        let clip = view_projection * Vec4::new(point.x, point.y, point.z, 1.0);
+        if clip.w <= 0.0 {
+            continue;
+        }
+        let ndc = clip.truncate() / clip.w;
+        if !(-1.0..=1.0).contains(&ndc.z) {
+            continue;
+        }
+        let center_x = ((ndc.x + 1.0) * 0.5 * width as f32) as i64;
+        let center_y = ((1.0 - ndc.y) * 0.5 * height as f32) as i64;
+
+        for y in (center_y - half_size)..=(center_y + half_size) {
+            if y < 0 || y >= height as i64 {
+                continue;
+            }
+            for x in (center_x - half_size)..=(center_x + half_size) {
+                if x < 0 || x >= width as i64 {
+                    continue;
+                }
+                let index = y as usize * width + x as usize;
+                if ndc.z < depth_buffer[index] {
+                    depth_buffer[index] = ndc.z;
+                    image[index * 4] = point.b;
+                    image[index * 4 + 1] = point.g;
+                    image[index * 4 + 2] = point.r;
+                }
+            }
+        }
+    }
+    image
+}
@@ -0,0 +1,473 @@
+// egress/projection.rs
+
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::encoders::EncodingFormat;
+use crate::processing::{aggregator::PointCloudAggregator, ProcessingPipeline};
+use crate::services::shutdown::is_shutting_down;
+use crate::services::stream_manager::{StreamManager, COMBINED_STREAM_ID};
+use crate::types::{WebRtcIceCandidate, WebRtcOffer};
+use shared_utils::error::{XrError, XrResult};
+use shared_utils::peer_connection::{create_webrtc_peer_connection, PeerConnectionConfig};
+use shared_utils::types::{FrameTaskData, PointCloudData};
+
+use bytes::Bytes;
+use glam::Vec3;
+use serde::Deserialize;
+use serde_json::Value;
+use socketioxide::extract::SocketRef;
+use tokio::runtime::{self, Runtime};
+use tracing::{debug, error, info, instrument, warn};
+
+use webrtc::ice_transport::ice_candidate::{RTCIceCandidate, RTCIceCandidateInit};
+use webrtc::media::Sample;
+use webrtc::peer_connection::peer_connection_state::RTCPeerConnectionState;
+use webrtc::peer_connection::sdp::session_description::RTCSessionDescription;
+use webrtc::peer_connection::RTCPeerConnection;
+use webrtc::rtcp::payload_feedbacks::full_intra_request::FullIntraRequest;
+use webrtc::rtcp::payload_feedbacks::picture_loss_indication::PictureLossIndication;
+use webrtc::rtp_transceiver::rtp_codec::RTCRtpCodecCapability;
+use webrtc::track::track_local::track_local_static_sample::TrackLocalStaticSample;
+use webrtc::track::track_local::TrackLocal;
+
+use super::egress_common::{frame_stream_id, EgressCommonMetrics, EgressProtocol};
+use super::preview::{render_preview, PreviewCamera};
+use super::video_encoder::{create_video_encoder, I420Frame, VideoCodec, VideoEncoder, VideoEncoderConfig};
+
+static PROJECTION_RUNTIME: OnceLock<Arc<Runtime>> = OnceLock::new();
+
+/// Settings of the projected video.
+#[derive(Clone, Copy, Debug)]
+pub struct ProjectionConfig {
+    pub codec: VideoCodec,
+    pub width: u32,
+    pub height: u32,
+    /// Target bitrate of the video of every client, in bits per second
+    pub bitrate: u32,
+    /// Size of a point in pixels
+    pub point_size: u32,
+}
+
+/// The camera pose a client renders the point cloud from, sent as "projection:pose".
+#[derive(Deserialize, Clone, Copy, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct ProjectionPose {
+    pub position: [f32; 3],
+    pub target: [f32; 3],
+    #[serde(default = "default_fov_degrees")]
+    pub fov_degrees: f32,
+}
+
+fn default_fov_degrees() -> f32 {
+    60.0
+}
+
+impl ProjectionPose {
+    fn camera(&self) -> PreviewCamera {
+        PreviewCamera::Perspective {
+            position: Vec3::from_array(self.position),
+            target: Vec3::from_array(self.target),
+            fov_degrees: self.fov_degrees,
+        }
+    }
+}
+
+/// The video of one client.
+#[derive(Clone)]
+struct ProjectionSession {
+    peer_connection: Arc<RTCPeerConnection>,
+    track: Arc<TrackLocalStaticSample>,
+    encoder: Arc<Mutex<Box<dyn VideoEncoder>>>,
+    /// Frames are only rendered once the peer connection is connected
+    connected: Arc<AtomicBool>,
+    /// Set when the client lost the reference frames, the next frame is then a key frame
+    keyframe_requested: Arc<AtomicBool>,
+}
+
+/// Projection Egress module responsible for rendering the combined point cloud from the camera pose of every client,
+/// and streaming it as H.264 or AV1 video over WebRTC, as a low bandwidth fallback for thin clients that can not render point clouds.
+#[derive(Clone)]
+pub struct ProjectionEgress {
+    processing_pipeline: Arc<ProcessingPipeline>,
+    aggregator: Arc<PointCloudAggregator>,
+    threads_started: Arc<AtomicBool>,
+    fps: Arc<Mutex<u32>>,
+    encoding_format: Arc<Mutex<EncodingFormat>>,
+    max_number_of_points: Arc<Mutex<u64>>,
+    config: ProjectionConfig,
+    peer_connection_config: PeerConnectionConfig,
+    /// The video of every client: socket_id -> session
+    sessions: Arc<Mutex<HashMap<String, ProjectionSession>>>,
+    /// The camera pose of every client, clients without a pose see the point cloud from the front
+    cameras: Arc<Mutex<HashMap<String, PreviewCamera>>>,
+    /// Temporary storage of ICE candidates if the peer connection does not exist yet
+    pending_ice: Arc<Mutex<HashMap<String, Vec<RTCIceCandidateInit>>>>,
+    egress_metrics: Arc<EgressCommonMetrics>,
+}
+
+impl fmt::Debug for ProjectionEgress {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ProjectionEgress")
+            .field("processing_pipeline", &self.processing_pipeline)
+            .field("aggregator", &self.aggregator)
+            .field("fps", &self.fps)
+            .field("max_number_of_points", &self.max_number_of_points)
+            .field("config", &self.config)
+            .field("sessions", &self.sessions.lock().unwrap().len())
+            .field("egress_metrics", &self.egress_metrics)
+            .finish()
+    }
+}
+
+impl ProjectionEgress {
+    /// Initializes the Projection Egress module, clients connect to it with a "projection_offer".
+    #[instrument(skip_all)]
+    pub fn initialize(
+        stream_manager: Arc<StreamManager>,
+        processing_pipeline: Arc<ProcessingPipeline>,
+        peer_connection_config: PeerConnectionConfig,
+        config: Option<ProjectionConfig>,
+    ) {
+        let Some(config) = config else {
+            info!("No projection codec configured, the projection egress is disabled");
+            return;
+        };
+        if config.width % 2 != 0 || config.height % 2 != 0 {
+            error!("The projection resolution {}x{} has to be even, the projection egress is disabled", config.width, config.height);
+            return;
+        }
+        info!("Projection egress streams {:?} video of {}x{}", config.codec, config.width, config.height);
+
+        let instance = Arc::new(Self {
+            processing_pipeline,
+            aggregator: Arc::new(PointCloudAggregator::new(stream_manager.clone())),
+            threads_started: Arc::new(AtomicBool::new(false)),
+            fps: Arc::new(Mutex::new(30)),
+            encoding_format: Arc::new(Mutex::new(EncodingFormat::Draco)),
+            max_number_of_points: Arc::new(Mutex::new(100000)),
+            config,
+            peer_connection_config,
+            sessions: Arc::new(Mutex::new(HashMap::new())),
+            cameras: Arc::new(Mutex::new(HashMap::new())),
+            pending_ice: Arc::new(Mutex::new(HashMap::new())),
+            egress_metrics: Arc::new(EgressCommonMetrics::new("PROJ_E")),
+        });
+
+        // Store the instance in the StreamManager
+        stream_manager.set_projection_egress(instance.clone());
+    }
+
+    #[instrument(skip_all)]
+    pub fn get_runtime(&self) -> Arc<Runtime> {
+        PROJECTION_RUNTIME.get_or_init(|| {
+            let rt = runtime::Builder::new_multi_thread()
+                .worker_threads(2)
+                .thread_name_fn(|| {
+                    static ATOMIC_PROJECTION_ID: std::sync::atomic::AtomicUsize = std::sync::atomic::AtomicUsize::new(0);
+                    let id = ATOMIC_PROJECTION_ID.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+                    format!("PROJ_R w-{}", id)
+                })
+                .enable_all()
+                .build().unwrap();
+            Arc::new(rt)
+        }).clone()
+    }
+
+    /// Renders the video of a client from another camera pose from now on.
+    pub fn set_pose(&self, client_id: &str, pose: ProjectionPose) {
+        self.cameras.lock().unwrap().insert(client_id.to_string(), pose.camera());
+    }
+
+    /// Answers the offer of a client with a video track, on which the projection is streamed once connected.
+    #[instrument(skip_all)]
+    pub async fn handle_client_offer(self: Arc<Self>, socket_id: String, offer: WebRtcOffer, socket: SocketRef) -> XrResult<()> {
+        let encoder = create_video_encoder(&VideoEncoderConfig {
+            codec: self.config.codec,
+            width: self.config.width,
+            height: self.config.height,
+            fps: *self.fps.lock().unwrap(),
+            bitrate: self.config.bitrate,
+        })?;
+
+        let pc = create_webrtc_peer_connection(&self.peer_connection_config).await?;
+
+        // Forward the ICE candidates of the server to the client
+        let s_clone = socket.clone();
+        pc.on_ice_candidate(Box::new(move |cand: Option<RTCIceCandidate>| {
+            let s_clone = s_clone.clone();
+            Box::pin(async move {
+                if let Some(c) = cand {
+                    if let Ok(json_candidate) = c.to_json() {
+                        let json_val = serde_json::json!({
+                            "candidate": json_candidate.candidate,
+                            "sdpMid": json_candidate.sdp_mid,
+                            "sdpMLineIndex": json_candidate.sdp_mline_index,
+                        });
+                        let _ = s_clone.emit("projection_ice_candidate", &json_val);
+                    }
+                }
+            })
+        }));
+
+        let track = Arc::new(TrackLocalStaticSample::new(
+            RTCRtpCodecCapability {
+                mime_type: self.config.codec.mime_type().to_owned(),
+                clock_rate: 90_000,
+                ..Default::default()
+            },
+            format!("projection_{}", socket_id),
+            "projection".to_owned(),
+        ));
+        let rtp_sender = pc.add_track(track.clone() as Arc<dyn TrackLocal + Send + Sync>).await?;
+
+        let session = ProjectionSession {
+            peer_connection: pc.clone(),
+            track,
+            encoder: Arc::new(Mutex::new(encoder)),
+            connected: Arc::new(AtomicBool::new(false)),
+            keyframe_requested: Arc::new(AtomicBool::new(true)),
+        };
+
+        // The client asks for a key frame with a PLI or FIR when it can not decode the video anymore
+        let keyframe_requested = session.keyframe_requested.clone();
+        tokio::spawn(async move {
+            let mut rtcp_buffer = vec![0; 1500];
+            while let Ok((rtcp_packets, _)) = rtp_sender.read(&mut rtcp_buffer).await {
+                let requests_keyframe = rtcp_packets.iter().any(|packet| {
+                    packet.as_any().downcast_ref::<PictureLossIndication>().is_some()
+                        || packet.as_any().downcast_ref::<FullIntraRequest>().is_some()
+                });
+                if requests_keyframe {
+                    keyframe_requested.store(true, Ordering::Relaxed);
+                }
+            }
+        });
+
+        let connected = session.connected.clone();
+        let keyframe_requested = session.keyframe_requested.clone();
+        let socket_id_clone = socket_id.clone();
+        pc.on_peer_connection_state_change(Box::new(move |s: RTCPeerConnectionState| {
+            info!("Projection peer connection of {} has changed: {s}", socket_id_clone);
+            let is_connected = s == RTCPeerConnectionState::Connected;
+            if is_connected {
+                keyframe_requested.store(true, Ordering::Relaxed);
+            }
+            connected.store(is_connected, Ordering::Relaxed);
+            Box::pin(async move {})
+        }));
+
+        let offer_sdp = serde_json::from_str::<RTCSessionDescription>(&offer.sdp)
+            .map_err(|e| XrError::Transport(format!("Invalid offer SDP: {}", e)))?;
+        pc.set_remote_description(offer_sdp).await?;
+        let answer = pc.create_answer(None).await?;
+        let answer_obj = serde_json::json!({
+            "sdp": serde_json::to_string(&answer).map_err(XrError::transport)?,
+            "clientId": socket_id,
+        });
+        match socket.emit_with_ack::<Value, Value>("projection_answer", &answer_obj) {
+            Ok(ack_stream) => {
+                if let Err(err) = ack_stream.await {
+                    error!("Ack error from socket {}: {:?}", socket_id, err);
+                }
+            }
+            Err(err) => error!("Socket error when emitting the projection answer: {:?}", err),
+        }
+        pc.set_local_description(answer).await?;
+
+        let pending_list = self.pending_ice.lock().unwrap().remove(&socket_id);
+        for candidate in pending_list.unwrap_or_default() {
+            if let Err(e) = pc.add_ice_candidate(candidate).await {
+                error!("Failed to add previously cached ICE candidate: {}", e);
+            }
+        }
+
+        // A new offer of the same client replaces its previous session
+        if let Some(previous) = self.sessions.lock().unwrap().insert(socket_id, session) {
+            let _ = previous.peer_connection.close().await;
+        }
+        self.ensure_threads_started();
+        Ok(())
+    }
+
+    #[instrument(skip_all)]
+    pub async fn handle_client_ice_candidate(&self, socket_id: String, candidate: WebRtcIceCandidate) -> XrResult<()> {
+        let c = RTCIceCandidateInit {
+            candidate: candidate.candidate,
+            sdp_mid: candidate.sdp_mid,
+            sdp_mline_index: candidate.sdp_mline_index,
+            ..Default::default()
+        };
+
+        let pc = self.sessions.lock().unwrap().get(&socket_id).map(|session| session.peer_connection.clone());
+        match pc {
+            Some(pc) if pc.remote_description().await.is_some() => pc.add_ice_candidate(c).await?,
+            _ => {
+                self.pending_ice.lock().unwrap().entry(socket_id.clone()).or_default().push(c);
+                debug!("No projection peer connection for {} yet, caching ICE candidate in the meantime.", socket_id);
+            }
+        }
+        Ok(())
+    }
+
+    /// Closes the video of a client, e.g. when its socket disconnects.
+    pub fn close_session(&self, client_id: &str) {
+        self.cameras.lock().unwrap().remove(client_id);
+        self.pending_ice.lock().unwrap().remove(client_id);
+        if let Some(session) = self.sessions.lock().unwrap().remove(client_id) {
+            self.get_runtime().spawn(async move {
+                let _ = session.peer_connection.close().await;
+            });
+        }
+    }
+
+    /// Renders, encodes and sends the video of every connected client at the frame rate of the egress, until the server shuts down.
+    fn send_projections(&self) {
+        let stream_metrics = self.egress_metrics.for_stream(COMBINED_STREAM_ID);
+        let runtime = self.get_runtime();
+        loop {
+            if is_shutting_down() {
+                debug!("Stopping the projections");
+                break;
+            }
+
+            let fps = (*self.fps.lock().unwrap()).max(1);
+            let frame_duration = Duration::from_micros(1_000_000 / fps as u64);
+            let start_time = Instant::now();
+
+            let sessions: Vec<(String, ProjectionSession)> = self.sessions
+                .lock()
+                .unwrap()
+                .iter()
+                .filter(|(_, session)| session.connected.load(Ordering::Relaxed))
+                .map(|(client_id, session)| (client_id.clone(), session.clone()))
+                .collect();
+            // Nothing is rendered while no client is connected
+            if !sessions.is_empty() {
+                let point_cloud = self.aggregator.generate_combined_point_cloud(*self.max_number_of_points.lock().unwrap());
+                stream_metrics.pc_combination_time.observe(start_time.elapsed().as_micros() as f64);
+
+                for (client_id, session) in sessions {
+                    let camera = self.cameras.lock().unwrap().get(&client_id).copied().unwrap_or(PreviewCamera::Front);
+                    let encode_start = Instant::now();
+                    let image = render_preview(&point_cloud, camera, self.config.width, self.config.height, self.config.point_size);
+                    let frame = I420Frame::from_bgra(&image, self.config.width as usize, self.config.height as usize);
+                    let force_keyframe = session.keyframe_requested.swap(false, Ordering::Relaxed);
+                    let bitstream = match session.encoder.lock().unwrap().encode(&frame, force_keyframe) {
+                        Ok(bitstream) => bitstream,
+                        Err(e) => {
+                            warn!("Failed to encode the projection of {}: {}", client_id, e);
+                            continue;
+                        }
+                    };
+                    stream_metrics.pc_encoding_time.observe(encode_start.elapsed().as_micros() as f64);
+                    if bitstream.is_empty() {
+                        continue;
+                    }
+
+                    let emit_start = Instant::now();
+                    stream_metrics.bytes_to_send.set(bitstream.len() as i64);
+                    let sample = Sample {
+                        data: Bytes::from(bitstream),
+                        duration: frame_duration,
+                        ..Default::default()
+                    };
+                    if let Err(e) = runtime.block_on(session.track.write_sample(&sample)) {
+                        debug!("Failed to send the projection of {}: {}", client_id, e);
+                    }
+                    stream_metrics.emission_time.observe(emit_start.elapsed().as_micros() as f64);
+                    stream_metrics.frames_to_emit.inc();
+                }
+            }
+
+            if let Some(sleep_duration) = frame_duration.checked_sub(start_time.elapsed()) {
+                thread::sleep(sleep_duration);
+            }
+        }
+    }
+}
+
+impl EgressProtocol for ProjectionEgress {
+    #[inline]
+    fn fps(&self) -> u32 {
+        *self.fps.lock().unwrap()
+    }
+
+    #[inline]
+    fn encoding_format(&self) -> EncodingFormat {
+        *self.encoding_format.lock().unwrap()
+    }
+
+    #[inline]
+    fn max_number_of_points(&self) -> u64 {
+        *self.max_number_of_points.lock().unwrap()
+    }
+
+    fn ensure_threads_started(&self) {
+        let already_started = self.threads_started.swap(true, Ordering::Relaxed);
+        if already_started {
+            return;
+        }
+
+        // The projections are rendered from the point clouds, so there is no generator and frame buffer
+        let self_clone = self.clone();
+        let _ = thread::Builder::new().name("PROJ_E Render Thread".to_string()).spawn(move || {
+            self_clone.send_projections();
+        });
+    }
+
+    fn push_point_cloud(&self, point_cloud: PointCloudData, stream_id: String) {
+        self.ensure_threads_started();
+        self.aggregator.update_point_cloud(stream_id, point_cloud);
+    }
+
+    // Encoded frames are decoded again, as the projection is rendered from the points
+    fn push_encoded_frame(&self, raw_data: Vec<u8>, stream_id: String, _creation_time: u64, _presentation_time: u64, _ring_buffer_bypass: bool, _client_id: Option<u64>, _tile_index: Option<u32>) {
+        self.ensure_threads_started();
+        match self.processing_pipeline.decode(raw_data, &stream_id) {
+            Ok(point_cloud) => self.aggregator.update_point_cloud(stream_id, point_cloud),
+            Err(e) => debug!("Failed to decode frame for the projection: {}", e),
+        }
+    }
+
+    fn emit_frame_data(&self, frame: FrameTaskData) {
+        let stream_id = frame_stream_id(&frame);
+        match self.processing_pipeline.decode(frame.data, &stream_id) {
+            Ok(point_cloud) => self.aggregator.update_point_cloud(stream_id, point_cloud),
+            Err(e) => debug!("Failed to decode frame for the projection: {}", e),
+        }
+    }
+
+    // The encoders of the connected clients keep the frame rate they were created with, until they reconnect
+    fn set_fps(&self, fps: u32) {
+        *self.fps.lock().unwrap() = fps;
+    }
+
+    // The projection is encoded as video, the encoding format is only kept for the settings API
+    fn set_encoding_format(&self, encoding_format: EncodingFormat) {
+        *self.encoding_format.lock().unwrap() = encoding_format;
+    }
+
+    fn set_max_number_of_points(&self, max_number_of_points: u64) {
+        *self.max_number_of_points.lock().unwrap() = max_number_of_points;
+    }
+
+    fn set_frame_buffer_capacity(&self, _capacity: usize) {
+        warn!("The projection egress has no frame buffer");
+    }
+
+    fn flush_and_close(&self, _timeout: Duration) {
+        let sessions: Vec<ProjectionSession> = self.sessions.lock().unwrap().drain().map(|(_, session)| session).collect();
+        // Closing is left to the runtime, as the server may shut down from within another runtime
+        let runtime = self.get_runtime();
+        for session in sessions {
+            runtime.spawn(async move {
+                let _ = session.peer_connection.close().await;
+            });
+        }
+    }
+}
@@ -0,0 +1,417 @@
+// egress/quic.rs
+
+use std::collections::HashMap;
+use std::fmt;
+use std::net::{Ipv6Addr, SocketAddr};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, OnceLock, RwLock};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::encoders::EncodingFormat;
+use crate::processing::{aggregator::PointCloudAggregator, ProcessingPipeline};
+use crate::services::stream_manager::StreamManager;
+use crate::types::EgressProtocolType;
+use shared_utils::error::{XrError, XrResult};
+use shared_utils::quic_frame::encode_quic_frame;
+use shared_utils::types::{FrameTaskData, PointCloudData};
+
+use super::ring_buffer::RingBuffer;
+use metrics::get_metrics;
+use prometheus::IntCounter;
+use quinn::crypto::rustls::QuicServerConfig;
+use quinn::{Connection, Endpoint, ServerConfig, TransportConfig, VarInt};
+use rustls::pki_types::{CertificateDer, PrivatePkcs8KeyDer};
+use tokio::runtime::{self, Runtime};
+use tracing::{debug, error, info, instrument};
+
+use super::egress_common::{drain_frame_buffer, push_preencoded_frame_data, EgressCommonMetrics, EgressProtocol};
+
+static QUIC_RUNTIME: OnceLock<Arc<Runtime>> = OnceLock::new();
+
+/// ALPN protocol of the point cloud frames, clients have to offer it in the TLS handshake.
+pub const QUIC_ALPN: &[u8] = b"mpxr-frames";
+
+/// A connected QUIC client, with the number of its frames that are not yet acknowledged.
+#[derive(Debug)]
+struct QuicClient {
+    connection: Connection,
+    frames_in_flight: AtomicUsize,
+}
+
+#[derive(Clone, Debug)]
+struct QuicMetrics {
+    /// Frames that were not sent because too many frames of the client were still in flight
+    frame_drops_congestion: IntCounter,
+    /// Frames that were abandoned because they were not acknowledged in time
+    frame_drops_expired: IntCounter,
+}
+
+/// QUIC Egress module responsible for sending every frame on its own unidirectional stream of a raw QUIC connection.
+/// A lower-level alternative to the WebTransport egress, without the HTTP/3 session.
+#[derive(Clone)]
+pub struct QuicEgress {
+    processing_pipeline: Arc<ProcessingPipeline>,
+    frame_buffer: Arc<Mutex<RingBuffer<FrameTaskData>>>,
+    aggregator: Arc<PointCloudAggregator>,
+    threads_started: Arc<AtomicBool>,
+    fps: Arc<Mutex<u32>>,
+    encoding_format: Arc<Mutex<EncodingFormat>>,
+    max_number_of_points: Arc<Mutex<u64>>,
+    /// Frames of a client that are sent while this many of its frames are not yet acknowledged are dropped
+    max_frames_in_flight: Arc<Mutex<usize>>,
+    /// Streams of frames that are not acknowledged within this time are reset, so newer frames get the bandwidth
+    max_frame_age: Arc<Mutex<Duration>>,
+    /// Number of the next frame, used as the priority of its streams
+    frame_number: Arc<AtomicUsize>,
+    /// The map of all connected clients: stable connection id -> client
+    clients: Arc<RwLock<HashMap<usize, Arc<QuicClient>>>>,
+    quic_metrics: Arc<QuicMetrics>,
+    egress_metrics: Arc<EgressCommonMetrics>,
+}
+
+impl fmt::Debug for QuicEgress {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("QuicEgress")
+            .field("clients", &self.clients.read().unwrap().len())
+            .field("processing_pipeline", &self.processing_pipeline)
+            .field("frame_buffer", &self.frame_buffer)
+            .field("aggregator", &self.aggregator)
+            .field("fps", &self.fps)
+            .field("encoding_format", &self.encoding_format)
+            .field("max_number_of_points", &self.max_number_of_points)
+            .field("max_frames_in_flight", &self.max_frames_in_flight)
+            .field("max_frame_age", &self.max_frame_age)
+            .field("egress_metrics", &self.egress_metrics)
+            .finish()
+    }
+}
+
+impl QuicEgress {
+    /// Initializes the QUIC Egress module, and starts accepting connections on `port`.
+    #[instrument(skip_all)]
+    pub fn initialize(
+        stream_manager: Arc<StreamManager>,
+        processing_pipeline: Arc<ProcessingPipeline>,
+        port: u16,
+    ) {
+        let server_config = match create_server_config() {
+            Ok(server_config) => server_config,
+            Err(e) => {
+                error!("Failed to create the QUIC server config: {}", e);
+                return;
+            }
+        };
+
+        let frame_drops = match get_metrics().get_or_create_counter_vec("quic_frame_drops", "Number of frames the QUIC egress did not deliver because of congestion", &["reason"]) {
+            Ok(frame_drops) => frame_drops,
+            Err(e) => {
+                error!("Failed to create the QUIC metrics: {}", e);
+                return;
+            }
+        };
+
+        let aggregator = Arc::new(PointCloudAggregator::new(stream_manager.clone()));
+
+        let instance = Arc::new(Self {
+            processing_pipeline: processing_pipeline.clone(),
+            frame_buffer: Arc::new(Mutex::new(RingBuffer::new(stream_manager.frame_buffer_capacity(&EgressProtocolType::Quic)))),
+            aggregator: aggregator.clone(),
+            threads_started: Arc::new(AtomicBool::new(false)),
+            fps: Arc::new(Mutex::new(30)),
+            encoding_format: Arc::new(Mutex::new(EncodingFormat::Draco)),
+            max_number_of_points: Arc::new(Mutex::new(100000)),
+            max_frames_in_flight: Arc::new(Mutex::new(3)),
+            max_frame_age: Arc::new(Mutex::new(Duration::from_millis(500))),
+            frame_number: Arc::new(AtomicUsize::new(0)),
+            clients: Arc::new(RwLock::new(HashMap::new())),
+            quic_metrics: Arc::new(QuicMetrics {
+                frame_drops_congestion: frame_drops.with_label_values(&["congestion"]),
+                frame_drops_expired: frame_drops.with_label_values(&["expired"]),
+            }),
+            egress_metrics: Arc::new(EgressCommonMetrics::new("QUIC_E")),
+        });
+
+        // The endpoint has to be created inside the runtime
+        let instance_clone = instance.clone();
+        instance.get_runtime().spawn(async move {
+            if let Err(e) = instance_clone.accept_connections(server_config, port).await {
+                error!("QUIC egress stopped: {}", e);
+            }
+        });
+
+        info!("QUIC egress listening on port {}", port);
+
+        // Store the instance in the StreamManager
+        stream_manager.set_quic_egress(instance.clone());
+    }
+
+    #[instrument(skip_all)]
+    pub fn get_runtime(&self) -> Arc<Runtime> {
+        QUIC_RUNTIME.get_or_init(|| {
+            let rt = runtime::Builder::new_multi_thread()
+                .worker_threads(2)
+                .thread_name_fn(|| {
+                    static ATOMIC_QUIC_ID: std::sync::atomic::AtomicUsize = std::sync::atomic::AtomicUsize::new(0);
+                    let id = ATOMIC_QUIC_ID.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+                    format!("QUIC_R w-{}", id)
+                })
+                .enable_all()
+                .build().unwrap();
+            Arc::new(rt)
+        }).clone()
+    }
+
+    /// Sets the number of unacknowledged frames of a client above which its new frames are dropped.
+    #[instrument(skip_all)]
+    pub fn set_max_frames_in_flight(&self, max_frames_in_flight: usize) {
+        *self.max_frames_in_flight.lock().unwrap() = max_frames_in_flight.max(1);
+    }
+
+    /// Sets the time after which the stream of an unacknowledged frame is reset.
+    #[instrument(skip_all)]
+    pub fn set_max_frame_age(&self, max_frame_age: Duration) {
+        *self.max_frame_age.lock().unwrap() = max_frame_age;
+    }
+
+    /// Accepts the incoming connections, until the endpoint is closed.
+    async fn accept_connections(&self, server_config: ServerConfig, port: u16) -> XrResult<()> {
+        let endpoint = Endpoint::server(server_config, SocketAddr::from((Ipv6Addr::UNSPECIFIED, port)))
+            .map_err(|e| XrError::Transport(format!("Failed to create the QUIC endpoint: {e}")))?;
+
+        while let Some(incoming) = endpoint.accept().await {
+            let clients = self.clients.clone();
+            tokio::spawn(async move {
+                let connection = match incoming.await {
+                    Ok(connection) => connection,
+                    Err(e) => {
+                        error!("Failed to accept the QUIC connection: {}", e);
+                        return;
+                    }
+                };
+                let client_id = connection.stable_id();
+                info!("QUIC client {} connected from {}", client_id, connection.remote_address());
+                clients.write().unwrap().insert(client_id, Arc::new(QuicClient {
+                    connection: connection.clone(),
+                    frames_in_flight: AtomicUsize::new(0),
+                }));
+
+                // Keep the client until it closes the connection or times out
+                let reason = connection.closed().await;
+                info!("QUIC client {} disconnected: {}", client_id, reason);
+                clients.write().unwrap().remove(&client_id);
+            });
+        }
+        Ok(())
+    }
+
+    /// Sends the encoded frame to a single client on a new unidirectional stream,
+    /// and resets the stream if the frame is not acknowledged within `max_frame_age`.
+    async fn send_to_client(client: Arc<QuicClient>, bytes: Arc<Vec<u8>>, priority: i32, max_frame_age: Duration) -> XrResult<bool> {
+        let mut stream = client.connection.open_uni().await
+            .map_err(|e| XrError::Transport(format!("Failed to open the stream: {e}")))?;
+        // Newer frames are sent before the remaining data of older frames
+        stream.set_priority(priority)
+            .map_err(|e| XrError::Transport(format!("Failed to set the stream priority: {e}")))?;
+
+        let send = async {
+            stream.write_all(&bytes).await
+                .map_err(|e| XrError::Transport(format!("Failed to write the frame: {e}")))?;
+            stream.finish()
+                .map_err(|e| XrError::Transport(format!("Failed to finish the stream: {e}")))?;
+            // Resolves once all data of the stream is acknowledged
+            stream.stopped().await
+                .map_err(|e| XrError::Transport(format!("Failed to deliver the frame: {e}")))?;
+            Ok(())
+        };
+
+        match tokio::time::timeout(max_frame_age, send).await {
+            Ok(result) => result.map(|_| true),
+            Err(_) => {
+                // Dropping the stream would finish it, resetting it stops the retransmissions of the expired frame
+                let _ = stream.reset(VarInt::from_u32(0));
+                Ok(false)
+            }
+        }
+    }
+}
+
+/// Creates the server config with a self-signed certificate, clients have to skip its verification or pin it.
+fn create_server_config() -> XrResult<ServerConfig> {
+    let certificate = rcgen::generate_simple_self_signed(vec!["localhost".to_string()])
+        .map_err(|e| XrError::Config(format!("Failed to generate the certificate: {e}")))?;
+    let certificate_der = CertificateDer::from(certificate.cert);
+    let private_key = PrivatePkcs8KeyDer::from(certificate.key_pair.serialize_der());
+
+    let mut crypto = rustls::ServerConfig::builder_with_provider(Arc::new(rustls::crypto::ring::default_provider()))
+        .with_protocol_versions(&[&rustls::version::TLS13])
+        .map_err(|e| XrError::Config(format!("Failed to configure TLS: {e}")))?
+        .with_no_client_auth()
+        .with_single_cert(vec![certificate_der], private_key.into())
+        .map_err(|e| XrError::Config(format!("Invalid certificate: {e}")))?;
+    crypto.alpn_protocols = vec![QUIC_ALPN.to_vec()];
+
+    let quic_crypto = QuicServerConfig::try_from(crypto)
+        .map_err(|e| XrError::Config(format!("Failed to configure QUIC: {e}")))?;
+    let mut server_config = ServerConfig::with_crypto(Arc::new(quic_crypto));
+
+    let mut transport_config = TransportConfig::default();
+    // Clients only receive, so they cannot open streams
+    transport_config.max_concurrent_uni_streams(0_u8.into());
+    transport_config.max_concurrent_bidi_streams(0_u8.into());
+    transport_config.keep_alive_interval(Some(Duration::from_secs(3)));
+    server_config.transport_config(Arc::new(transport_config));
+    Ok(server_config)
+}
+
+impl EgressProtocol for QuicEgress {
+    #[inline]
+    fn fps(&self) -> u32 {
+        *self.fps.lock().unwrap()
+    }
+
+    #[inline]
+    fn encoding_format(&self) -> EncodingFormat {
+        *self.encoding_format.lock().unwrap()
+    }
+
+    #[inline]
+    fn max_number_of_points(&self) -> u64 {
+        *self.max_number_of_points.lock().unwrap()
+    }
+
+    fn ensure_threads_started(&self) {
+        let already_started = self.threads_started.load(Ordering::Relaxed);
+        if already_started {
+            return;
+        }
+
+        // Set the threads as started
+        self.threads_started.store(true, Ordering::Relaxed);
+
+        // Start background threads using the common module
+        crate::egress::egress_common::start_generator_thread(
+            "QUIC_E".to_string(),
+            self.processing_pipeline.clone(),
+            self.aggregator.clone(),
+            self.frame_buffer.clone(),
+            self.fps.clone(),
+            self.encoding_format.clone(),
+            self.max_number_of_points.clone(),
+        );
+
+        let self_clone = self.clone();
+        crate::egress::egress_common::start_transmission_thread(
+            "QUIC_E".to_string(),
+            self.frame_buffer.clone(),
+            move |frame| {
+                self_clone.emit_frame_data(frame);
+            },
+            false,
+        );
+    }
+
+    fn push_point_cloud(&self, point_cloud: PointCloudData, stream_id: String) {
+        self.ensure_threads_started();
+        self.aggregator.update_point_cloud(stream_id, point_cloud);
+    }
+
+    // Process and sends a frame, this raw version bypasses the aggregation
+    fn push_encoded_frame(&self, raw_data: Vec<u8>, _stream_id: String, mut creation_time: u64, presentation_time: u64, ring_buffer_bypass: bool, client_id: Option<u64>, tile_index: Option<u32>) {
+        // Ensure the threads are started
+        self.ensure_threads_started();
+
+        let self_clone = self.clone();
+        let bypass = if ring_buffer_bypass {
+
+            let since_the_epoch = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .expect("Time went backwards");
+            creation_time = since_the_epoch.as_micros() as u64;
+
+            Some(Box::new(move |frame| {
+                self_clone.emit_frame_data(frame);
+            }) as Box<dyn Fn(FrameTaskData) + Send + 'static>)
+        } else {
+            None
+        };
+
+        push_preencoded_frame_data(
+            "QUIC_E",
+            &self.frame_buffer,
+            creation_time,
+            presentation_time,
+            raw_data, // data is moved
+            bypass,
+            &self.egress_metrics,
+            client_id,
+            tile_index,
+        );
+    }
+
+    /// Emits frame data to all connected QUIC clients, skipping the clients that are congested.
+    fn emit_frame_data(&self, frame: FrameTaskData) {
+        debug!("Emitting frame with presentation time: {}", frame.presentation_time);
+
+        let clients: Vec<Arc<QuicClient>> = self.clients.read().unwrap().values().cloned().collect();
+        if clients.is_empty() {
+            debug!("No QUIC clients connected to emit frame");
+            return;
+        }
+
+        let bytes = Arc::new(encode_quic_frame(&frame));
+        let max_frames_in_flight = *self.max_frames_in_flight.lock().unwrap();
+        let max_frame_age = *self.max_frame_age.lock().unwrap();
+        // Newer frames get a higher priority
+        let priority = (self.frame_number.fetch_add(1, Ordering::Relaxed) % i32::MAX as usize) as i32;
+
+        // The frames are sent in the background, the in-flight limit keeps a slow client from piling them up
+        let runtime = self.get_runtime();
+        for client in clients {
+            if client.frames_in_flight.fetch_add(1, Ordering::AcqRel) >= max_frames_in_flight {
+                client.frames_in_flight.fetch_sub(1, Ordering::AcqRel);
+                debug!("Dropping frame for congested QUIC client {}", client.connection.stable_id());
+                self.quic_metrics.frame_drops_congestion.inc();
+                continue;
+            }
+
+            let bytes = bytes.clone();
+            let quic_metrics = self.quic_metrics.clone();
+            runtime.spawn(async move {
+                match Self::send_to_client(client.clone(), bytes, priority, max_frame_age).await {
+                    Ok(true) => {}
+                    Ok(false) => {
+                        debug!("Frame for QUIC client {} expired before it was acknowledged", client.connection.stable_id());
+                        quic_metrics.frame_drops_expired.inc();
+                    }
+                    Err(e) => error!("Failed to write frame: {}", e),
+                }
+                client.frames_in_flight.fetch_sub(1, Ordering::AcqRel);
+            });
+        }
+    }
+
+    fn set_fps(&self, fps: u32) {
+        *self.fps.lock().unwrap() = fps;
+    }
+
+    fn set_encoding_format(&self, encoding_format: EncodingFormat) {
+        *self.encoding_format.lock().unwrap() = encoding_format;
+    }
+
+    fn set_max_number_of_points(&self, max_number_of_points: u64) {
+        *self.max_number_of_points.lock().unwrap() = max_number_of_points;
+    }
+
+    fn set_frame_buffer_capacity(&self, capacity: usize) {
+        let dropped = self.frame_buffer.lock().unwrap().set_capacity(capacity);
+        self.egress_metrics.record_buffer_drops(&dropped);
+    }
+
+    fn flush_and_close(&self, timeout: Duration) {
+        drain_frame_buffer("QUIC_E", &self.frame_buffer, timeout);
+        for (client_id, client) in self.clients.write().unwrap().drain() {
+            info!("Closing the connection of QUIC client {}", client_id);
+            client.connection.close(VarInt::from_u32(0), b"server shutdown");
+        }
+    }
+}
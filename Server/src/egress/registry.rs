@@ -0,0 +1,92 @@
+// egress/registry.rs
+
+//! Egress protocols that are registered by name at startup, next to the built-in ones.
+//! A fork adds a transport by implementing `EgressProtocol` and registering a factory for it
+//! before `initialize_egress_protocols` runs; streams then enable it by listing its name in their egress protocols.
+
+use std::collections::HashMap;
+use std::sync::{Arc, OnceLock, RwLock};
+
+use shared_utils::error::{XrError, XrResult};
+use tracing::{error, info};
+
+use crate::processing::ProcessingPipeline;
+use crate::services::stream_manager::StreamManager;
+use crate::types::EgressProtocolType;
+
+use super::egress_common::EgressProtocol;
+
+/// Creates the instance of a registered egress protocol, once the stream manager and processing pipeline exist.
+pub type EgressFactory = Box<dyn Fn(Arc<StreamManager>, Arc<ProcessingPipeline>) -> XrResult<Arc<dyn EgressProtocol>> + Send + Sync>;
+
+static EGRESS_REGISTRY: OnceLock<EgressRegistry> = OnceLock::new();
+
+/// Returns the registry of the egress protocols that are not built in.
+pub fn get_egress_registry() -> &'static EgressRegistry {
+    EGRESS_REGISTRY.get_or_init(EgressRegistry::default)
+}
+
+#[derive(Default)]
+pub struct EgressRegistry {
+    /// The factories by lowercase name
+    factories: RwLock<HashMap<String, Arc<EgressFactory>>>,
+}
+
+impl std::fmt::Debug for EgressRegistry {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("EgressRegistry").field("names", &self.names()).finish()
+    }
+}
+
+impl EgressRegistry {
+    /// Registers an egress protocol under a name, which may not be the name of a built-in or another registered egress.
+    pub fn register(&self, name: &str, factory: EgressFactory) -> XrResult<()> {
+        let name = name.trim().to_lowercase();
+        if name.is_empty() {
+            return Err(XrError::Config("An egress protocol needs a name".to_string()));
+        }
+        if EgressProtocolType::from_builtin_name(&name).is_some() {
+            return Err(XrError::Config(format!("{name} is the name of a built-in egress protocol")));
+        }
+        let mut factories = self.factories.write().unwrap();
+        if factories.contains_key(&name) {
+            return Err(XrError::Config(format!("An egress protocol named {name} is already registered")));
+        }
+        factories.insert(name, Arc::new(factory));
+        Ok(())
+    }
+
+    /// Whether an egress protocol is registered under the (case insensitive) name.
+    pub fn contains(&self, name: &str) -> bool {
+        self.factories.read().unwrap().contains_key(&name.to_lowercase())
+    }
+
+    /// Returns the names of the registered egress protocols, sorted.
+    pub fn names(&self) -> Vec<String> {
+        let mut names: Vec<String> = self.factories.read().unwrap().keys().cloned().collect();
+        names.sort();
+        names
+    }
+
+    /// Creates every registered egress protocol and hands it to the stream manager.
+    /// An egress that fails to start is left out, the others still start.
+    pub fn initialize_all(&self, stream_manager: Arc<StreamManager>, processing_pipeline: Arc<ProcessingPipeline>) {
+        // The factories are cloned, so a factory may use the registry itself
+        let factories: Vec<(String, Arc<EgressFactory>)> = self.factories
+            .read()
+            .unwrap()
+            .iter()
+            .map(|(name, factory)| (name.clone(), factory.clone()))
+            .collect();
+
+        for (name, factory) in factories {
+            match factory(stream_manager.clone(), processing_pipeline.clone()) {
+                Ok(egress) => {
+                    info!("Initialized the {} egress", name);
+                    stream_manager.set_plugin_egress(&name, egress);
+                }
+                Err(e) => error!("Failed to initialize the {} egress: {}", name, e),
+            }
+        }
+    }
+}
@@ -0,0 +1,82 @@
+// egress/ring_buffer.rs
+
+use std::collections::VecDeque;
+
+/// Default number of frames an egress buffers between the generator and the transmission thread
+pub const DEFAULT_FRAME_BUFFER_CAPACITY: usize = 10;
+/// Default number of packets the FLUTE egress queues for its rate limited transmitter
+pub const DEFAULT_PACKET_QUEUE_CAPACITY: usize = 20000;
+
+/// A ring buffer of which the capacity can be changed at runtime.
+/// When the buffer is full, pushing a new element drops the oldest one.
+#[derive(Clone, Debug)]
+pub struct RingBuffer<T> {
+    items: VecDeque<T>,
+    capacity: usize,
+}
+
+impl<T> RingBuffer<T> {
+    pub fn new(capacity: usize) -> Self {
+        let capacity = capacity.max(1);
+        Self {
+            items: VecDeque::with_capacity(capacity.min(DEFAULT_PACKET_QUEUE_CAPACITY)),
+            capacity,
+        }
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// Changes the capacity, the oldest elements are dropped when the buffer holds more than the new capacity.
+    /// Returns the dropped elements.
+    pub fn set_capacity(&mut self, capacity: usize) -> Vec<T> {
+        self.capacity = capacity.max(1);
+        let overflow = self.items.len().saturating_sub(self.capacity);
+        self.items.drain(..overflow).collect()
+    }
+
+    pub fn len(&self) -> usize {
+        self.items.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.items.is_empty()
+    }
+
+    pub fn is_full(&self) -> bool {
+        self.items.len() >= self.capacity
+    }
+
+    /// Appends an element, and returns the oldest element if it had to be dropped to make room.
+    pub fn push_back(&mut self, item: T) -> Option<T> {
+        let dropped = if self.is_full() {
+            self.items.pop_front()
+        } else {
+            None
+        };
+        self.items.push_back(item);
+        dropped
+    }
+
+    pub fn front(&self) -> Option<&T> {
+        self.items.front()
+    }
+
+    pub fn pop_front(&mut self) -> Option<T> {
+        self.items.pop_front()
+    }
+
+    pub fn get(&self, index: usize) -> Option<&T> {
+        self.items.get(index)
+    }
+
+    pub fn remove(&mut self, index: usize) -> Option<T> {
+        self.items.remove(index)
+    }
+
+    /// Iterates from the oldest to the newest element.
+    pub fn iter(&self) -> std::collections::vec_deque::Iter<'_, T> {
+        self.items.iter()
+    }
+}
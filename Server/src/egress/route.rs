@@ -0,0 +1,145 @@
+// egress/route.rs
+
+//! Helpers to turn the packets of the FLUTE sender into ROUTE (ATSC A/331) packets.
+//! ROUTE reuses the ALC/LCT framing of FLUTE, but describes the delivered objects in the
+//! S-TSID instead of an in-band FDT, and carries the sender time in an EXT_TIME header extension.
+
+use std::{collections::HashMap, time::{SystemTime, UNIX_EPOCH}};
+
+use flute::core::{lct::LCTHeader, UDPEndpoint};
+
+/// Header Extension Type of EXT_TIME (RFC 5651)
+const EXT_TIME: u8 = 2;
+/// Header Extension Type of the 48-bit EXT_TOL (ATSC A/331)
+const EXT_TOL: u8 = 67;
+/// Seconds between the NTP epoch (1900) and the Unix epoch (1970)
+const NTP_UNIX_OFFSET: u64 = 2_208_988_800;
+/// First codepoint of the range of which the meaning is defined by the S-TSID
+pub const ROUTE_FIRST_DYNAMIC_CODEPOINT: u8 = 128;
+/// Object names of the source flow, the receiver replaces $TOI$ by the TOI of the LCT packet
+pub const ROUTE_FILE_TEMPLATE: &str = "frame_$TOI$.bin";
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DeliveryMode {
+    /// Plain FLUTE (RFC 6726), objects are described by an in-band FDT
+    Flute,
+    /// ROUTE (ATSC 3.0), objects are described by the S-TSID
+    Route,
+}
+
+impl DeliveryMode {
+    pub fn parse(value: &str) -> Option<Self> {
+        match value.to_lowercase().as_str() {
+            "flute" => Some(DeliveryMode::Flute),
+            "route" => Some(DeliveryMode::Route),
+            _ => None,
+        }
+    }
+}
+
+/// Returns the codepoint of the given media type, allocating a new one from the dynamic range if needed.
+/// The media type of a frame is the codec tag in its first three bytes.
+pub fn codepoint_for(codepoints: &mut HashMap<String, u8>, media_type: &str) -> u8 {
+    let next = ROUTE_FIRST_DYNAMIC_CODEPOINT.saturating_add(codepoints.len() as u8);
+    *codepoints.entry(media_type.to_string()).or_insert(next)
+}
+
+/// Rewrites a FLUTE packet of a file object into a ROUTE packet.
+/// The codepoint is replaced, and EXT_TIME (sender current time) and EXT_TOL (transfer object length)
+/// are inserted in front of the header extensions the FLUTE sender already added.
+pub fn to_route_packet(
+    packet: &[u8],
+    header: &LCTHeader,
+    codepoint: u8,
+    transfer_length: u64,
+    now: SystemTime,
+) -> Option<Vec<u8>> {
+    let insert_at = header.header_ext_offset as usize;
+    let hdr_len_words = packet[2] as usize + 5;
+    if insert_at > packet.len() || hdr_len_words > u8::MAX as usize {
+        return None;
+    }
+
+    let since_the_epoch = now.duration_since(UNIX_EPOCH).unwrap_or_default();
+    let ntp_seconds = (since_the_epoch.as_secs() + NTP_UNIX_OFFSET) as u32;
+    let ntp_fraction = ((since_the_epoch.subsec_nanos() as u64) << 32) / 1_000_000_000;
+
+    let mut route_packet = Vec::with_capacity(packet.len() + 20);
+    route_packet.extend_from_slice(&packet[..insert_at]);
+    route_packet[2] = hdr_len_words as u8;
+    route_packet[3] = codepoint;
+
+    // EXT_TIME with the SCT-High and SCT-Low flags set, 3 words
+    route_packet.extend_from_slice(&[EXT_TIME, 3, 0xC0, 0x00]);
+    route_packet.extend_from_slice(&ntp_seconds.to_be_bytes());
+    route_packet.extend_from_slice(&(ntp_fraction as u32).to_be_bytes());
+
+    // EXT_TOL with a 48 bit transfer length, 2 words
+    route_packet.extend_from_slice(&[EXT_TOL, 2]);
+    route_packet.extend_from_slice(&transfer_length.to_be_bytes()[2..]);
+
+    route_packet.extend_from_slice(&packet[insert_at..]);
+    Some(route_packet)
+}
+
+/// Builds the S-TSID that describes the ROUTE sessions, every session (TSI) has a single source flow.
+pub fn build_stsid(endpoint: &UDPEndpoint, tsis: &[u64], bandwidth: u32, codepoints: &HashMap<String, u8>) -> String {
+    let source_address = endpoint
+        .source_address
+        .as_ref()
+        .map(|address| format!(" sIpAddr=\"{}\"", address))
+        .unwrap_or_default();
+
+    let mut payloads: Vec<(&String, &u8)> = codepoints.iter().collect();
+    payloads.sort_by_key(|(_, codepoint)| **codepoint);
+    let payloads: String = payloads
+        .into_iter()
+        .map(|(media_type, codepoint)| {
+            format!(
+                "        <!-- {} -->\n        <Payload codePoint=\"{}\" formatId=\"1\" frag=\"0\" order=\"true\"/>\n",
+                media_type, codepoint
+            )
+        })
+        .collect();
+
+    // The sessions share the bandwidth of the egress
+    let session_bandwidth = bandwidth / tsis.len().max(1) as u32;
+    let sessions: String = tsis
+        .iter()
+        .map(|tsi| {
+            format!(
+                concat!(
+                    "    <LS tsi=\"{}\" bw=\"{}\">\n",
+                    "      <SrcFlow rt=\"true\">\n",
+                    "        <EFDT>\n",
+                    "          <fdt:FDT-Instance Expires=\"4294967295\" afdt:fileTemplate=\"{}\"/>\n",
+                    "        </EFDT>\n",
+                    "{}",
+                    "      </SrcFlow>\n",
+                    "    </LS>\n",
+                ),
+                tsi,
+                session_bandwidth,
+                ROUTE_FILE_TEMPLATE,
+                payloads,
+            )
+        })
+        .collect();
+
+    format!(
+        concat!(
+            "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n",
+            "<S-TSID xmlns=\"tag:atsc.org,2016:XMLSchemas/ATSC3/Delivery/S-TSID/1.0/\" ",
+            "xmlns:afdt=\"tag:atsc.org,2016:XMLSchemas/ATSC3/Delivery/ATSC-FDT/1.0/\" ",
+            "xmlns:fdt=\"urn:ietf:params:xml:ns:fdt\">\n",
+            "  <RS dIpAddr=\"{}\" dPort=\"{}\"{}>\n",
+            "{}",
+            "  </RS>\n",
+            "</S-TSID>\n",
+        ),
+        endpoint.destination_group_address,
+        endpoint.port,
+        source_address,
+        sessions,
+    )
+}
@@ -0,0 +1,293 @@
+// egress/rtp.rs
+
+use std::net::{SocketAddr, UdpSocket};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::encoders::EncodingFormat;
+use crate::processing::{aggregator::PointCloudAggregator, ProcessingPipeline};
+use crate::services::stream_manager::StreamManager;
+use crate::types::EgressProtocolType;
+use shared_utils::error::{XrError, XrResult};
+use shared_utils::pointcloud_payloader::PointCloudPayloader;
+use shared_utils::types::{FrameTaskData, PointCloudData};
+
+use bytes::Bytes;
+use super::ring_buffer::RingBuffer;
+use tracing::{debug, error, info, instrument};
+use webrtc::rtp::packetizer::{new_packetizer, Packetizer};
+use webrtc::rtp::sequence::new_random_sequencer;
+use webrtc::util::Marshal;
+
+use super::egress_common::{drain_frame_buffer, push_preencoded_frame_data, EgressCommonMetrics, EgressProtocol};
+
+/// Dynamic payload type of the point cloud RTP packets
+pub(crate) const RTP_PAYLOAD_TYPE: u8 = 96;
+/// Clock rate of the RTP timestamps, the same as for video
+pub(crate) const RTP_CLOCK_RATE: u32 = 90_000;
+
+pub(crate) type PointCloudPacketizer = Arc<Mutex<Box<dyn Packetizer + Send + Sync>>>;
+
+/// Creates a packetizer with a random SSRC around the point cloud payloader.
+pub(crate) fn new_point_cloud_packetizer(mtu: usize, payloader: &PointCloudPayloader) -> PointCloudPacketizer {
+    let packetizer = new_packetizer(
+        mtu,
+        RTP_PAYLOAD_TYPE,
+        rand::random::<u32>(), // SSRC
+        Box::new(payloader.clone()),
+        Box::new(new_random_sequencer()),
+        RTP_CLOCK_RATE,
+    );
+    Arc::new(Mutex::new(Box::new(packetizer)))
+}
+
+/// Packetizes a frame with the point cloud payloader, and returns the marshalled RTP packets.
+pub(crate) fn packetize_frame(packetizer: &PointCloudPacketizer, payloader: &PointCloudPayloader, frame: FrameTaskData, fps: u32) -> XrResult<Vec<Bytes>> {
+    let packets = {
+        let mut packetizer = packetizer.lock().unwrap();
+        // The payloader shares its metadata with the one inside the packetizer
+        let mut payloader = payloader.clone();
+        payloader.set_metadata(
+            frame.sfu_client_id.unwrap_or(0) as u32,
+            frame.send_time,
+            frame.sfu_tile_index.unwrap_or(0),
+            0,
+        );
+        let samples = RTP_CLOCK_RATE / fps.max(1);
+        packetizer.packetize(&Bytes::from(frame.data), samples)
+            .map_err(|e| XrError::Transport(format!("Failed to packetize frame: {e}")))?
+    };
+    packets
+        .iter()
+        .map(|packet| packet.marshal().map_err(|e| XrError::Transport(format!("Failed to marshal RTP packet: {e}"))))
+        .collect()
+}
+
+/// Settings of the plain RTP egress.
+#[derive(Clone, Debug)]
+pub struct RtpMulticastConfig {
+    /// Every packet is sent to each of these (multicast) addresses
+    pub groups: Vec<SocketAddr>,
+    pub ttl: u32,
+    /// Maximum size of an RTP packet, including the RTP and point cloud headers
+    pub mtu: usize,
+}
+
+/// RTP Egress module responsible for sending frames as plain RTP over UDP multicast.
+/// It uses the same payload format as the WebRTC track, but without the FLUTE/FDT overhead or any
+/// feedback channel, which makes it a baseline for the other multicast egresses.
+#[derive(Clone)]
+pub struct RtpMulticastEgress {
+    processing_pipeline: Arc<ProcessingPipeline>,
+    frame_buffer: Arc<Mutex<RingBuffer<FrameTaskData>>>,
+    aggregator: Arc<PointCloudAggregator>,
+    threads_started: Arc<AtomicBool>,
+    fps: Arc<Mutex<u32>>,
+    encoding_format: Arc<Mutex<EncodingFormat>>,
+    max_number_of_points: Arc<Mutex<u64>>,
+    config: RtpMulticastConfig,
+    socket: Arc<UdpSocket>,
+    payloader: PointCloudPayloader,
+    packetizer: PointCloudPacketizer,
+    egress_metrics: Arc<EgressCommonMetrics>,
+}
+
+impl std::fmt::Debug for RtpMulticastEgress {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("RtpMulticastEgress")
+            .field("config", &self.config)
+            .field("processing_pipeline", &self.processing_pipeline)
+            .field("frame_buffer", &self.frame_buffer)
+            .field("aggregator", &self.aggregator)
+            .field("fps", &self.fps)
+            .field("encoding_format", &self.encoding_format)
+            .field("max_number_of_points", &self.max_number_of_points)
+            .field("egress_metrics", &self.egress_metrics)
+            .finish()
+    }
+}
+
+impl RtpMulticastEgress {
+    /// Initializes the RTP Egress module.
+    #[instrument(skip_all)]
+    pub fn initialize(
+        stream_manager: Arc<StreamManager>,
+        processing_pipeline: Arc<ProcessingPipeline>,
+        config: RtpMulticastConfig,
+    ) {
+        if config.groups.is_empty() {
+            info!("No RTP multicast groups configured, the RTP egress is disabled");
+            return;
+        }
+
+        let socket = match Self::bind_socket(&config) {
+            Ok(socket) => socket,
+            Err(e) => {
+                error!("Failed to initialize the RTP egress: {}", e);
+                return;
+            }
+        };
+
+        let aggregator = Arc::new(PointCloudAggregator::new(stream_manager.clone()));
+        let payloader = PointCloudPayloader::new();
+        let packetizer = new_point_cloud_packetizer(config.mtu, &payloader);
+
+        info!("RTP egress sending to {:?}", config.groups);
+
+        let instance = Arc::new(Self {
+            processing_pipeline: processing_pipeline.clone(),
+            frame_buffer: Arc::new(Mutex::new(RingBuffer::new(stream_manager.frame_buffer_capacity(&EgressProtocolType::Rtp)))),
+            aggregator: aggregator.clone(),
+            threads_started: Arc::new(AtomicBool::new(false)),
+            fps: Arc::new(Mutex::new(30)),
+            encoding_format: Arc::new(Mutex::new(EncodingFormat::Draco)),
+            max_number_of_points: Arc::new(Mutex::new(100000)),
+            config,
+            socket: Arc::new(socket),
+            payloader,
+            packetizer,
+            egress_metrics: Arc::new(EgressCommonMetrics::new("RTP_E")),
+        });
+
+        // Store the instance in the StreamManager
+        stream_manager.set_rtp_egress(instance.clone());
+    }
+
+    fn bind_socket(config: &RtpMulticastConfig) -> XrResult<UdpSocket> {
+        let socket = UdpSocket::bind("0.0.0.0:0")
+            .map_err(|e| XrError::Transport(format!("Failed to bind UDP socket: {e}")))?;
+        socket.set_multicast_ttl_v4(config.ttl)
+            .map_err(|e| XrError::Config(format!("Invalid multicast TTL {}: {e}", config.ttl)))?;
+        Ok(socket)
+    }
+}
+
+impl EgressProtocol for RtpMulticastEgress {
+    #[inline]
+    fn fps(&self) -> u32 {
+        *self.fps.lock().unwrap()
+    }
+
+    #[inline]
+    fn encoding_format(&self) -> EncodingFormat {
+        *self.encoding_format.lock().unwrap()
+    }
+
+    #[inline]
+    fn max_number_of_points(&self) -> u64 {
+        *self.max_number_of_points.lock().unwrap()
+    }
+
+    fn ensure_threads_started(&self) {
+        let already_started = self.threads_started.load(Ordering::Relaxed);
+        if already_started {
+            return;
+        }
+
+        // Set the threads as started
+        self.threads_started.store(true, Ordering::Relaxed);
+
+        // Start background threads using the common module
+        crate::egress::egress_common::start_generator_thread(
+            "RTP_E".to_string(),
+            self.processing_pipeline.clone(),
+            self.aggregator.clone(),
+            self.frame_buffer.clone(),
+            self.fps.clone(),
+            self.encoding_format.clone(),
+            self.max_number_of_points.clone(),
+        );
+
+        let self_clone = self.clone();
+        crate::egress::egress_common::start_transmission_thread(
+            "RTP_E".to_string(),
+            self.frame_buffer.clone(),
+            move |frame| {
+                self_clone.emit_frame_data(frame);
+            },
+            false,
+        );
+    }
+
+    fn push_point_cloud(&self, point_cloud: PointCloudData, stream_id: String) {
+        self.ensure_threads_started();
+        self.aggregator.update_point_cloud(stream_id, point_cloud);
+    }
+
+    // Process and sends a frame, this raw version bypasses the aggregation
+    fn push_encoded_frame(&self, raw_data: Vec<u8>, _stream_id: String, mut creation_time: u64, presentation_time: u64, ring_buffer_bypass: bool, client_id: Option<u64>, tile_index: Option<u32>) {
+        // Ensure the threads are started
+        self.ensure_threads_started();
+
+        let self_clone = self.clone();
+        let bypass = if ring_buffer_bypass {
+
+            let since_the_epoch = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .expect("Time went backwards");
+            creation_time = since_the_epoch.as_micros() as u64;
+
+            Some(Box::new(move |frame| {
+                self_clone.emit_frame_data(frame);
+            }) as Box<dyn Fn(FrameTaskData) + Send + 'static>)
+        } else {
+            None
+        };
+
+        push_preencoded_frame_data(
+            "RTP_E",
+            &self.frame_buffer,
+            creation_time,
+            presentation_time,
+            raw_data, // data is moved
+            bypass,
+            &self.egress_metrics,
+            client_id,
+            tile_index,
+        );
+    }
+
+    /// Packetizes the frame with the point cloud payloader, and sends every packet to all multicast groups.
+    fn emit_frame_data(&self, frame: FrameTaskData) {
+        debug!("Emitting frame with presentation time: {}", frame.presentation_time);
+
+        let fps = *self.fps.lock().unwrap();
+        let packets = match packetize_frame(&self.packetizer, &self.payloader, frame, fps) {
+            Ok(packets) => packets,
+            Err(e) => {
+                error!("{}", e);
+                return;
+            }
+        };
+
+        for raw in packets {
+            for group in &self.config.groups {
+                if let Err(e) = self.socket.send_to(&raw, group) {
+                    error!("Failed to send RTP packet to {}: {}", group, e);
+                }
+            }
+        }
+    }
+
+    fn set_fps(&self, fps: u32) {
+        *self.fps.lock().unwrap() = fps;
+    }
+
+    fn set_encoding_format(&self, encoding_format: EncodingFormat) {
+        *self.encoding_format.lock().unwrap() = encoding_format;
+    }
+
+    fn set_max_number_of_points(&self, max_number_of_points: u64) {
+        *self.max_number_of_points.lock().unwrap() = max_number_of_points;
+    }
+
+    fn set_frame_buffer_capacity(&self, capacity: usize) {
+        let dropped = self.frame_buffer.lock().unwrap().set_capacity(capacity);
+        self.egress_metrics.record_buffer_drops(&dropped);
+    }
+
+    fn flush_and_close(&self, timeout: Duration) {
+        drain_frame_buffer("RTP_E", &self.frame_buffer, timeout);
+    }
+}
@@ -0,0 +1,597 @@
+// egress/rtsp.rs
+
+use std::collections::HashMap;
+use std::net::{SocketAddr, UdpSocket};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::encoders::EncodingFormat;
+use crate::processing::{aggregator::PointCloudAggregator, ProcessingPipeline};
+use crate::services::stream_manager::StreamManager;
+use crate::types::EgressProtocolType;
+use shared_utils::error::{XrError, XrResult};
+use shared_utils::pointcloud_payloader::PointCloudPayloader;
+use shared_utils::types::{FrameTaskData, PointCloudData};
+
+use super::ring_buffer::RingBuffer;
+use super::rtp::{new_point_cloud_packetizer, packetize_frame, PointCloudPacketizer, RTP_CLOCK_RATE, RTP_PAYLOAD_TYPE};
+use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+use tokio::net::tcp::OwnedWriteHalf;
+use tokio::net::{TcpListener, TcpStream};
+use tokio::runtime::{self, Runtime};
+use tracing::{debug, error, info, instrument, warn};
+
+use super::egress_common::{drain_frame_buffer, push_preencoded_frame_data, EgressCommonMetrics, EgressProtocol};
+
+static RTSP_RUNTIME: OnceLock<Arc<Runtime>> = OnceLock::new();
+
+/// A client that does not accept an interleaved packet within this time is disconnected
+const WRITE_TIMEOUT: Duration = Duration::from_secs(1);
+/// Advertised in the Session header, clients send a keepalive (e.g. GET_PARAMETER) within it
+const SESSION_TIMEOUT_SECONDS: u32 = 60;
+const SUPPORTED_METHODS: &str = "OPTIONS, DESCRIBE, SETUP, PLAY, PAUSE, TEARDOWN, GET_PARAMETER";
+
+/// Settings of the RTSP egress.
+#[derive(Clone, Debug)]
+pub struct RtspConfig {
+    /// TCP port of the RTSP server
+    pub port: u16,
+    /// Maximum size of an RTP packet, including the RTP and point cloud headers
+    pub mtu: usize,
+}
+
+/// How the RTP packets of a session reach the client.
+#[derive(Clone)]
+enum RtspTransport {
+    /// Sent over UDP to the RTP port of the client
+    Udp(SocketAddr),
+    /// Interleaved on the RTSP connection, on the given channel
+    Interleaved { channel: u8, writer: Arc<tokio::sync::Mutex<OwnedWriteHalf>> },
+}
+
+#[derive(Clone)]
+struct RtspSession {
+    transport: RtspTransport,
+    playing: bool,
+    /// The RTSP connection that created the session, its sessions end with it
+    connection_id: u64,
+}
+
+/// A parsed RTSP request.
+struct RtspRequest {
+    method: String,
+    url: String,
+    headers: HashMap<String, String>,
+}
+
+impl RtspRequest {
+    fn header(&self, name: &str) -> Option<&str> {
+        self.headers.get(&name.to_lowercase()).map(|value| value.as_str())
+    }
+}
+
+/// RTSP Egress module responsible for serving the frames to RTSP clients, for interop checks with standard tooling.
+/// The stream is described as a single application track, of which the RTP packets use the same point cloud
+/// payload format as the RTP and WebRTC egresses. Clients pick UDP or TCP interleaved delivery in their SETUP.
+#[derive(Clone)]
+pub struct RtspEgress {
+    processing_pipeline: Arc<ProcessingPipeline>,
+    frame_buffer: Arc<Mutex<RingBuffer<FrameTaskData>>>,
+    aggregator: Arc<PointCloudAggregator>,
+    threads_started: Arc<AtomicBool>,
+    fps: Arc<Mutex<u32>>,
+    encoding_format: Arc<Mutex<EncodingFormat>>,
+    max_number_of_points: Arc<Mutex<u64>>,
+    config: RtspConfig,
+    /// Sends the RTP packets of the UDP sessions
+    socket: Arc<UdpSocket>,
+    payloader: PointCloudPayloader,
+    packetizer: PointCloudPacketizer,
+    /// The sessions by their id
+    sessions: Arc<Mutex<HashMap<String, RtspSession>>>,
+    egress_metrics: Arc<EgressCommonMetrics>,
+}
+
+impl std::fmt::Debug for RtspEgress {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("RtspEgress")
+            .field("config", &self.config)
+            .field("processing_pipeline", &self.processing_pipeline)
+            .field("frame_buffer", &self.frame_buffer)
+            .field("aggregator", &self.aggregator)
+            .field("fps", &self.fps)
+            .field("encoding_format", &self.encoding_format)
+            .field("max_number_of_points", &self.max_number_of_points)
+            .field("egress_metrics", &self.egress_metrics)
+            .finish()
+    }
+}
+
+impl RtspEgress {
+    /// Initializes the RTSP Egress module, and starts serving RTSP clients.
+    #[instrument(skip_all)]
+    pub fn initialize(
+        stream_manager: Arc<StreamManager>,
+        processing_pipeline: Arc<ProcessingPipeline>,
+        config: Option<RtspConfig>,
+    ) {
+        let Some(config) = config else {
+            info!("No RTSP port configured, the RTSP egress is disabled");
+            return;
+        };
+
+        let socket = match UdpSocket::bind("0.0.0.0:0") {
+            Ok(socket) => socket,
+            Err(e) => {
+                error!("Failed to initialize the RTSP egress, could not bind UDP socket: {}", e);
+                return;
+            }
+        };
+
+        let aggregator = Arc::new(PointCloudAggregator::new(stream_manager.clone()));
+        let payloader = PointCloudPayloader::new();
+        let packetizer = new_point_cloud_packetizer(config.mtu, &payloader);
+
+        let instance = Arc::new(Self {
+            processing_pipeline: processing_pipeline.clone(),
+            frame_buffer: Arc::new(Mutex::new(RingBuffer::new(stream_manager.frame_buffer_capacity(&EgressProtocolType::Rtsp)))),
+            aggregator: aggregator.clone(),
+            threads_started: Arc::new(AtomicBool::new(false)),
+            fps: Arc::new(Mutex::new(30)),
+            encoding_format: Arc::new(Mutex::new(EncodingFormat::Draco)),
+            max_number_of_points: Arc::new(Mutex::new(100000)),
+            config,
+            socket: Arc::new(socket),
+            payloader,
+            packetizer,
+            sessions: Arc::new(Mutex::new(HashMap::new())),
+            egress_metrics: Arc::new(EgressCommonMetrics::new("RTSP_E")),
+        });
+
+        let instance_clone = instance.clone();
+        instance.get_runtime().spawn(async move {
+            if let Err(e) = instance_clone.accept_clients().await {
+                error!("RTSP egress stopped: {}", e);
+            }
+        });
+
+        // Store the instance in the StreamManager
+        stream_manager.set_rtsp_egress(instance.clone());
+    }
+
+    #[instrument(skip_all)]
+    pub fn get_runtime(&self) -> Arc<Runtime> {
+        RTSP_RUNTIME.get_or_init(|| {
+            let rt = runtime::Builder::new_multi_thread()
+                .worker_threads(2)
+                .thread_name_fn(|| {
+                    static ATOMIC_RTSP_ID: std::sync::atomic::AtomicUsize = std::sync::atomic::AtomicUsize::new(0);
+                    let id = ATOMIC_RTSP_ID.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+                    format!("RTSP_R w-{}", id)
+                })
+                .enable_all()
+                .build().unwrap();
+            Arc::new(rt)
+        }).clone()
+    }
+
+    async fn accept_clients(&self) -> XrResult<()> {
+        let address = SocketAddr::from(([0, 0, 0, 0], self.config.port));
+        let listener = TcpListener::bind(address).await
+            .map_err(|e| XrError::Transport(format!("Failed to listen on {address}: {e}")))?;
+        info!("RTSP egress listening on rtsp://{}/", address);
+
+        let mut next_connection_id = 0;
+        loop {
+            match listener.accept().await {
+                Ok((stream, peer)) => {
+                    info!("RTSP client {} connected", peer);
+                    next_connection_id += 1;
+                    let self_clone = self.clone();
+                    let connection_id = next_connection_id;
+                    tokio::spawn(async move {
+                        if let Err(e) = self_clone.serve_client(stream, peer, connection_id).await {
+                            debug!("RTSP connection with {} ended: {}", peer, e);
+                        }
+                        self_clone.sessions.lock().unwrap().retain(|_, session| session.connection_id != connection_id);
+                        info!("RTSP client {} disconnected", peer);
+                    });
+                }
+                Err(e) => {
+                    error!("Failed to accept an RTSP client: {}", e);
+                    tokio::time::sleep(Duration::from_secs(1)).await;
+                }
+            }
+        }
+    }
+
+    /// Answers the requests on one RTSP connection until the client closes it.
+    async fn serve_client(&self, stream: TcpStream, peer: SocketAddr, connection_id: u64) -> XrResult<()> {
+        let local_address = stream.local_addr().map_err(|e| XrError::Transport(e.to_string()))?;
+        let (reader, writer) = stream.into_split();
+        let mut reader = BufReader::new(reader);
+        let writer = Arc::new(tokio::sync::Mutex::new(writer));
+
+        while let Some(request) = Self::read_request(&mut reader).await? {
+            debug!("RTSP {} {} from {}", request.method, request.url, peer);
+            let (status, headers, body) = self.handle_request(&request, peer, local_address, connection_id, &writer);
+
+            let mut response = format!("RTSP/1.0 {status}\r\nCSeq: {}\r\nServer: Multi-path-XR\r\n", request.header("cseq").unwrap_or("0"));
+            for (name, value) in headers {
+                response.push_str(&format!("{name}: {value}\r\n"));
+            }
+            if !body.is_empty() {
+                response.push_str(&format!("Content-Length: {}\r\n", body.len()));
+            }
+            response.push_str("\r\n");
+            response.push_str(&body);
+
+            writer.lock().await.write_all(response.as_bytes()).await
+                .map_err(|e| XrError::Transport(format!("Failed to write RTSP response: {e}")))?;
+        }
+        Ok(())
+    }
+
+    /// Reads the next request, skipping the interleaved (RTCP) packets of the client.
+    /// Returns None once the client closed the connection.
+    async fn read_request<R: tokio::io::AsyncBufRead + Unpin>(reader: &mut R) -> XrResult<Option<RtspRequest>> {
+        let transport_error = |e: std::io::Error| XrError::Transport(format!("Failed to read RTSP request: {e}"));
+        loop {
+            let buffer = reader.fill_buf().await.map_err(transport_error)?;
+            if buffer.is_empty() {
+                return Ok(None);
+            }
+            if buffer[0] != b'$' {
+                break;
+            }
+            let mut header = [0u8; 4];
+            reader.read_exact(&mut header).await.map_err(transport_error)?;
+            let mut packet = vec![0u8; u16::from_be_bytes([header[2], header[3]]) as usize];
+            reader.read_exact(&mut packet).await.map_err(transport_error)?;
+        }
+
+        let mut request_line = String::new();
+        if reader.read_line(&mut request_line).await.map_err(transport_error)? == 0 {
+            return Ok(None);
+        }
+        let mut parts = request_line.split_whitespace();
+        let (Some(method), Some(url)) = (parts.next(), parts.next()) else {
+            return Err(XrError::Transport(format!("Invalid RTSP request line: {}", request_line.trim())));
+        };
+
+        let mut headers = HashMap::new();
+        loop {
+            let mut line = String::new();
+            if reader.read_line(&mut line).await.map_err(transport_error)? == 0 {
+                return Ok(None);
+            }
+            let line = line.trim_end();
+            if line.is_empty() {
+                break;
+            }
+            if let Some((name, value)) = line.split_once(':') {
+                headers.insert(name.trim().to_lowercase(), value.trim().to_string());
+            }
+        }
+
+        // The body of e.g. SET_PARAMETER is not used, but has to be read
+        let content_length = headers.get("content-length").and_then(|value| value.parse::<usize>().ok()).unwrap_or(0);
+        let mut body = vec![0u8; content_length];
+        reader.read_exact(&mut body).await.map_err(transport_error)?;
+
+        Ok(Some(RtspRequest { method: method.to_uppercase(), url: url.to_string(), headers }))
+    }
+
+    /// Returns the status, headers and body of the response to a request.
+    fn handle_request(
+        &self,
+        request: &RtspRequest,
+        peer: SocketAddr,
+        local_address: SocketAddr,
+        connection_id: u64,
+        writer: &Arc<tokio::sync::Mutex<OwnedWriteHalf>>,
+    ) -> (&'static str, Vec<(&'static str, String)>, String) {
+        match request.method.as_str() {
+            "OPTIONS" => ("200 OK", vec![("Public", SUPPORTED_METHODS.to_string())], String::new()),
+            "DESCRIBE" => {
+                let base = request.url.trim_end_matches('/');
+                let headers = vec![
+                    ("Content-Type", "application/sdp".to_string()),
+                    ("Content-Base", format!("{base}/")),
+                ];
+                ("200 OK", headers, self.session_description(local_address))
+            }
+            "SETUP" => {
+                let Some(transport) = request.header("transport") else {
+                    return ("461 Unsupported Transport", vec![], String::new());
+                };
+                let (session_transport, response_transport) = if transport.contains("RTP/AVP/TCP") {
+                    let channel = transport_parameter(transport, "interleaved").unwrap_or((0, 1)).0 as u8;
+                    (
+                        RtspTransport::Interleaved { channel, writer: writer.clone() },
+                        format!("RTP/AVP/TCP;unicast;interleaved={}-{}", channel, channel.wrapping_add(1)),
+                    )
+                } else {
+                    let Some((rtp_port, rtcp_port)) = transport_parameter(transport, "client_port") else {
+                        return ("461 Unsupported Transport", vec![], String::new());
+                    };
+                    let server_port = self.socket.local_addr().map(|address| address.port()).unwrap_or(0);
+                    (
+                        RtspTransport::Udp(SocketAddr::new(peer.ip(), rtp_port)),
+                        format!("RTP/AVP;unicast;client_port={}-{};server_port={}-{}", rtp_port, rtcp_port, server_port, server_port.wrapping_add(1)),
+                    )
+                };
+
+                // Clients that set up again within their session keep the session id
+                let session_id = request.header("session")
+                    .map(|session| session.split(';').next().unwrap_or_default().to_string())
+                    .unwrap_or_else(|| format!("{:016X}", rand::random::<u64>()));
+                self.sessions.lock().unwrap().insert(session_id.clone(), RtspSession {
+                    transport: session_transport,
+                    playing: false,
+                    connection_id,
+                });
+                info!("RTSP session {} set up for {} ({})", session_id, peer, response_transport);
+
+                let headers = vec![
+                    ("Transport", response_transport),
+                    ("Session", format!("{session_id};timeout={SESSION_TIMEOUT_SECONDS}")),
+                ];
+                ("200 OK", headers, String::new())
+            }
+            "PLAY" | "PAUSE" | "TEARDOWN" | "GET_PARAMETER" => {
+                let Some(session_id) = request.header("session").map(|session| session.split(';').next().unwrap_or_default().to_string()) else {
+                    // Keepalives without a session are answered as well
+                    if request.method == "GET_PARAMETER" {
+                        return ("200 OK", vec![], String::new());
+                    }
+                    return ("454 Session Not Found", vec![], String::new());
+                };
+                let mut sessions = self.sessions.lock().unwrap();
+                if !sessions.contains_key(&session_id) {
+                    return ("454 Session Not Found", vec![], String::new());
+                }
+                let mut headers = vec![("Session", session_id.clone())];
+                match request.method.as_str() {
+                    "PLAY" => {
+                        sessions.get_mut(&session_id).unwrap().playing = true;
+                        info!("RTSP session {} playing", session_id);
+                        headers.push(("Range", "npt=now-".to_string()));
+                    }
+                    "PAUSE" => sessions.get_mut(&session_id).unwrap().playing = false,
+                    "TEARDOWN" => {
+                        sessions.remove(&session_id);
+                        info!("RTSP session {} torn down", session_id);
+                    }
+                    _ => {}
+                }
+                ("200 OK", headers, String::new())
+            }
+            _ => ("501 Not Implemented", vec![("Public", SUPPORTED_METHODS.to_string())], String::new()),
+        }
+    }
+
+    /// Describes the stream as one application track with the point cloud RTP payload format.
+    fn session_description(&self, local_address: SocketAddr) -> String {
+        let session_version = SystemTime::now().duration_since(UNIX_EPOCH).map(|duration| duration.as_secs()).unwrap_or(0);
+        let address_type = if local_address.is_ipv4() { "IP4" } else { "IP6" };
+        [
+            "v=0".to_string(),
+            format!("o=- {session_version} {session_version} IN {address_type} {}", local_address.ip()),
+            "s=Multi-path XR point cloud".to_string(),
+            format!("c=IN {address_type} {}", local_address.ip()),
+            "t=0 0".to_string(),
+            "a=control:*".to_string(),
+            format!("m=application 0 RTP/AVP {RTP_PAYLOAD_TYPE}"),
+            format!("a=rtpmap:{RTP_PAYLOAD_TYPE} pointcloud/{RTP_CLOCK_RATE}"),
+            format!("a=framerate:{}", *self.fps.lock().unwrap()),
+            "a=control:trackID=0".to_string(),
+        ]
+        .join("\r\n") + "\r\n"
+    }
+}
+
+/// Parses a port or channel range like `client_port=5000-5001` from a Transport header.
+/// A single value `x` is read as `x-(x+1)`.
+fn transport_parameter(transport: &str, name: &str) -> Option<(u16, u16)> {
+    let value = transport
+        .split(';')
+        .find_map(|parameter| parameter.trim().strip_prefix(name)?.strip_prefix('='))?;
+    match value.split_once('-') {
+        Some((first, second)) => Some((first.parse().ok()?, second.parse().ok()?)),
+        None => {
+            let first: u16 = value.parse().ok()?;
+            Some((first, first.wrapping_add(1)))
+        }
+    }
+}
+
+impl EgressProtocol for RtspEgress {
+    #[inline]
+    fn fps(&self) -> u32 {
+        *self.fps.lock().unwrap()
+    }
+
+    #[inline]
+    fn encoding_format(&self) -> EncodingFormat {
+        *self.encoding_format.lock().unwrap()
+    }
+
+    #[inline]
+    fn max_number_of_points(&self) -> u64 {
+        *self.max_number_of_points.lock().unwrap()
+    }
+
+    fn ensure_threads_started(&self) {
+        let already_started = self.threads_started.load(Ordering::Relaxed);
+        if already_started {
+            return;
+        }
+
+        // Set the threads as started
+        self.threads_started.store(true, Ordering::Relaxed);
+
+        // Start background threads using the common module
+        crate::egress::egress_common::start_generator_thread(
+            "RTSP_E".to_string(),
+            self.processing_pipeline.clone(),
+            self.aggregator.clone(),
+            self.frame_buffer.clone(),
+            self.fps.clone(),
+            self.encoding_format.clone(),
+            self.max_number_of_points.clone(),
+        );
+
+        let self_clone = self.clone();
+        crate::egress::egress_common::start_transmission_thread(
+            "RTSP_E".to_string(),
+            self.frame_buffer.clone(),
+            move |frame| {
+                self_clone.emit_frame_data(frame);
+            },
+            false,
+        );
+    }
+
+    fn push_point_cloud(&self, point_cloud: PointCloudData, stream_id: String) {
+        self.ensure_threads_started();
+        self.aggregator.update_point_cloud(stream_id, point_cloud);
+    }
+
+    // Process and sends a frame, this raw version bypasses the aggregation
+    fn push_encoded_frame(&self, raw_data: Vec<u8>, _stream_id: String, mut creation_time: u64, presentation_time: u64, ring_buffer_bypass: bool, client_id: Option<u64>, tile_index: Option<u32>) {
+        // Ensure the threads are started
+        self.ensure_threads_started();
+
+        let self_clone = self.clone();
+        let bypass = if ring_buffer_bypass {
+
+            let since_the_epoch = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .expect("Time went backwards");
+            creation_time = since_the_epoch.as_micros() as u64;
+
+            Some(Box::new(move |frame| {
+                self_clone.emit_frame_data(frame);
+            }) as Box<dyn Fn(FrameTaskData) + Send + 'static>)
+        } else {
+            None
+        };
+
+        push_preencoded_frame_data(
+            "RTSP_E",
+            &self.frame_buffer,
+            creation_time,
+            presentation_time,
+            raw_data, // data is moved
+            bypass,
+            &self.egress_metrics,
+            client_id,
+            tile_index,
+        );
+    }
+
+    /// Packetizes the frame once, and sends the packets to every playing session.
+    /// Interleaved sessions that cannot keep up are dropped.
+    fn emit_frame_data(&self, frame: FrameTaskData) {
+        debug!("Emitting frame with presentation time: {}", frame.presentation_time);
+
+        let playing: Vec<(String, RtspTransport)> = self.sessions
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|(_, session)| session.playing)
+            .map(|(session_id, session)| (session_id.clone(), session.transport.clone()))
+            .collect();
+        if playing.is_empty() {
+            debug!("No RTSP session playing to emit frame");
+            return;
+        }
+
+        let fps = *self.fps.lock().unwrap();
+        let packets = match packetize_frame(&self.packetizer, &self.payloader, frame, fps) {
+            Ok(packets) => packets,
+            Err(e) => {
+                error!("{}", e);
+                return;
+            }
+        };
+
+        let mut failed = vec![];
+        for (session_id, transport) in playing {
+            match transport {
+                RtspTransport::Udp(address) => {
+                    for packet in &packets {
+                        if let Err(e) = self.socket.send_to(packet, address) {
+                            error!("Failed to send RTP packet to RTSP session {}: {}", session_id, e);
+                            break;
+                        }
+                    }
+                }
+                RtspTransport::Interleaved { channel, writer } => {
+                    // Every packet is framed as '$', the channel and a 2 byte big endian length
+                    let mut message = Vec::with_capacity(packets.iter().map(|packet| packet.len() + 4).sum());
+                    for packet in &packets {
+                        message.push(b'$');
+                        message.push(channel);
+                        message.extend_from_slice(&(packet.len() as u16).to_be_bytes());
+                        message.extend_from_slice(packet);
+                    }
+                    let result = self.get_runtime().block_on(async move {
+                        tokio::time::timeout(WRITE_TIMEOUT, async { writer.lock().await.write_all(&message).await }).await
+                    });
+                    match result {
+                        Ok(Ok(())) => {}
+                        Ok(Err(e)) => {
+                            error!("Failed to write RTP packets to RTSP session {}: {}", session_id, e);
+                            failed.push(session_id);
+                        }
+                        Err(_) => {
+                            error!("RTSP session {} is too slow, dropping it", session_id);
+                            failed.push(session_id);
+                        }
+                    }
+                }
+            }
+        }
+
+        if !failed.is_empty() {
+            let mut sessions = self.sessions.lock().unwrap();
+            for session_id in failed {
+                sessions.remove(&session_id);
+            }
+        }
+    }
+
+    fn set_fps(&self, fps: u32) {
+        *self.fps.lock().unwrap() = fps;
+    }
+
+    fn set_encoding_format(&self, encoding_format: EncodingFormat) {
+        *self.encoding_format.lock().unwrap() = encoding_format;
+    }
+
+    fn set_max_number_of_points(&self, max_number_of_points: u64) {
+        *self.max_number_of_points.lock().unwrap() = max_number_of_points;
+    }
+
+    fn set_frame_buffer_capacity(&self, capacity: usize) {
+        let dropped = self.frame_buffer.lock().unwrap().set_capacity(capacity);
+        self.egress_metrics.record_buffer_drops(&dropped);
+    }
+
+    fn flush_and_close(&self, timeout: Duration) {
+        drain_frame_buffer("RTSP_E", &self.frame_buffer, timeout);
+        let sessions: Vec<RtspSession> = self.sessions.lock().unwrap().drain().map(|(_, session)| session).collect();
+        self.get_runtime().block_on(async move {
+            for session in sessions {
+                if let RtspTransport::Interleaved { writer, .. } = session.transport {
+                    if let Err(e) = writer.lock().await.shutdown().await {
+                        warn!("Failed to close an RTSP connection: {}", e);
+                    }
+                }
+            }
+        });
+    }
+}
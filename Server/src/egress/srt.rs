@@ -0,0 +1,315 @@
+// egress/srt.rs
+
+use std::fmt;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use crate::encoders::EncodingFormat;
+use crate::processing::{aggregator::PointCloudAggregator, ProcessingPipeline};
+use crate::services::stream_manager::StreamManager;
+use crate::types::EgressProtocolType;
+use shared_utils::error::{XrError, XrResult};
+use shared_utils::types::{FrameTaskData, PointCloudData};
+use shared_utils::wire::encode_frame;
+
+use bytes::Bytes;
+use super::ring_buffer::RingBuffer;
+use futures::SinkExt;
+use srt_tokio::SrtSocket;
+use tokio::runtime::{self, Runtime};
+use tokio::sync::Notify;
+use tracing::{debug, error, info, instrument};
+
+use super::egress_common::{drain_frame_buffer, push_preencoded_frame_data, EgressCommonMetrics, EgressProtocol};
+
+static SRT_RUNTIME: OnceLock<Arc<Runtime>> = OnceLock::new();
+
+/// Settings of the SRT listener, they are applied during the handshake with the receiver.
+#[derive(Clone)]
+pub struct SrtConfig {
+    pub port: u16,
+    /// Packets that cannot be recovered by retransmission within this time are dropped, the receiver buffers this long
+    pub latency: Duration,
+    /// Encrypt the stream with AES, the receiver needs the same passphrase (10 to 79 characters)
+    pub passphrase: Option<String>,
+}
+
+impl fmt::Debug for SrtConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SrtConfig")
+            .field("port", &self.port)
+            .field("latency", &self.latency)
+            .field("passphrase", &self.passphrase.as_ref().map(|_| "<hidden>"))
+            .finish()
+    }
+}
+
+impl SrtConfig {
+    fn validate(&self) -> XrResult<()> {
+        if let Some(passphrase) = &self.passphrase {
+            if !(10..=79).contains(&passphrase.len()) {
+                return Err(XrError::Config(format!("The SRT passphrase has {} characters, expected 10 to 79", passphrase.len())));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// SRT Egress module responsible for sending frames to a single SRT receiver that calls the listener of the server.
+/// SRT retransmits lost packets within the latency budget, which suits links where FLUTE multicast or WebRTC struggle.
+#[derive(Clone)]
+pub struct SrtEgress {
+    processing_pipeline: Arc<ProcessingPipeline>,
+    frame_buffer: Arc<Mutex<RingBuffer<FrameTaskData>>>,
+    aggregator: Arc<PointCloudAggregator>,
+    threads_started: Arc<AtomicBool>,
+    fps: Arc<Mutex<u32>>,
+    encoding_format: Arc<Mutex<EncodingFormat>>,
+    max_number_of_points: Arc<Mutex<u64>>,
+    config: SrtConfig,
+    /// The socket of the connected receiver, `None` while waiting for a receiver
+    socket: Arc<tokio::sync::Mutex<Option<SrtSocket>>>,
+    /// Notified when the receiver is gone, so the listener accepts the next one
+    disconnected: Arc<Notify>,
+    egress_metrics: Arc<EgressCommonMetrics>,
+}
+
+impl fmt::Debug for SrtEgress {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SrtEgress")
+            .field("config", &self.config)
+            .field("processing_pipeline", &self.processing_pipeline)
+            .field("frame_buffer", &self.frame_buffer)
+            .field("aggregator", &self.aggregator)
+            .field("fps", &self.fps)
+            .field("encoding_format", &self.encoding_format)
+            .field("max_number_of_points", &self.max_number_of_points)
+            .field("egress_metrics", &self.egress_metrics)
+            .finish()
+    }
+}
+
+impl SrtEgress {
+    /// Initializes the SRT Egress module, and starts listening for a receiver on the port of `config`.
+    #[instrument(skip_all)]
+    pub fn initialize(
+        stream_manager: Arc<StreamManager>,
+        processing_pipeline: Arc<ProcessingPipeline>,
+        config: SrtConfig,
+    ) {
+        if let Err(e) = config.validate() {
+            error!("Failed to initialize the SRT egress: {}", e);
+            return;
+        }
+
+        let aggregator = Arc::new(PointCloudAggregator::new(stream_manager.clone()));
+
+        let instance = Arc::new(Self {
+            processing_pipeline: processing_pipeline.clone(),
+            frame_buffer: Arc::new(Mutex::new(RingBuffer::new(stream_manager.frame_buffer_capacity(&EgressProtocolType::Srt)))),
+            aggregator: aggregator.clone(),
+            threads_started: Arc::new(AtomicBool::new(false)),
+            fps: Arc::new(Mutex::new(30)),
+            encoding_format: Arc::new(Mutex::new(EncodingFormat::Draco)),
+            max_number_of_points: Arc::new(Mutex::new(100000)),
+            config,
+            socket: Arc::new(tokio::sync::Mutex::new(None)),
+            disconnected: Arc::new(Notify::new()),
+            egress_metrics: Arc::new(EgressCommonMetrics::new("SRT_E")),
+        });
+
+        let instance_clone = instance.clone();
+        instance.get_runtime().spawn(async move {
+            instance_clone.accept_receivers().await;
+        });
+
+        info!("SRT egress listening on port {} with a latency of {:?}", instance.config.port, instance.config.latency);
+
+        // Store the instance in the StreamManager
+        stream_manager.set_srt_egress(instance.clone());
+    }
+
+    #[instrument(skip_all)]
+    pub fn get_runtime(&self) -> Arc<Runtime> {
+        SRT_RUNTIME.get_or_init(|| {
+            let rt = runtime::Builder::new_multi_thread()
+                .worker_threads(2)
+                .thread_name_fn(|| {
+                    static ATOMIC_SRT_ID: std::sync::atomic::AtomicUsize = std::sync::atomic::AtomicUsize::new(0);
+                    let id = ATOMIC_SRT_ID.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+                    format!("SRT_R w-{}", id)
+                })
+                .enable_all()
+                .build().unwrap();
+            Arc::new(rt)
+        }).clone()
+    }
+
+    /// Waits for a receiver to call the listener, and for the next one once it is gone.
+    async fn accept_receivers(&self) {
+        loop {
+            match self.listen().await {
+                Ok(socket) => {
+                    info!("SRT receiver connected");
+                    *self.socket.lock().await = Some(socket);
+                    self.disconnected.notified().await;
+                    info!("SRT receiver disconnected");
+                }
+                Err(e) => {
+                    error!("Failed to accept an SRT receiver: {}", e);
+                    tokio::time::sleep(Duration::from_secs(1)).await;
+                }
+            }
+        }
+    }
+
+    async fn listen(&self) -> XrResult<SrtSocket> {
+        let mut builder = SrtSocket::builder().latency(self.config.latency);
+        if let Some(passphrase) = &self.config.passphrase {
+            // A key size of 0 lets the receiver pick the AES key length
+            builder = builder.encryption(0, passphrase.clone());
+        }
+        builder.listen_on(format!(":{}", self.config.port).as_str()).await
+            .map_err(|e| XrError::Transport(format!("SRT listener failed: {e}")))
+    }
+}
+
+impl EgressProtocol for SrtEgress {
+    #[inline]
+    fn fps(&self) -> u32 {
+        *self.fps.lock().unwrap()
+    }
+
+    #[inline]
+    fn encoding_format(&self) -> EncodingFormat {
+        *self.encoding_format.lock().unwrap()
+    }
+
+    #[inline]
+    fn max_number_of_points(&self) -> u64 {
+        *self.max_number_of_points.lock().unwrap()
+    }
+
+    fn ensure_threads_started(&self) {
+        let already_started = self.threads_started.load(Ordering::Relaxed);
+        if already_started {
+            return;
+        }
+
+        // Set the threads as started
+        self.threads_started.store(true, Ordering::Relaxed);
+
+        // Start background threads using the common module
+        crate::egress::egress_common::start_generator_thread(
+            "SRT_E".to_string(),
+            self.processing_pipeline.clone(),
+            self.aggregator.clone(),
+            self.frame_buffer.clone(),
+            self.fps.clone(),
+            self.encoding_format.clone(),
+            self.max_number_of_points.clone(),
+        );
+
+        let self_clone = self.clone();
+        crate::egress::egress_common::start_transmission_thread(
+            "SRT_E".to_string(),
+            self.frame_buffer.clone(),
+            move |frame| {
+                self_clone.emit_frame_data(frame);
+            },
+            false,
+        );
+    }
+
+    fn push_point_cloud(&self, point_cloud: PointCloudData, stream_id: String) {
+        self.ensure_threads_started();
+        self.aggregator.update_point_cloud(stream_id, point_cloud);
+    }
+
+    // Process and sends a frame, this raw version bypasses the aggregation
+    fn push_encoded_frame(&self, raw_data: Vec<u8>, _stream_id: String, mut creation_time: u64, presentation_time: u64, ring_buffer_bypass: bool, client_id: Option<u64>, tile_index: Option<u32>) {
+        // Ensure the threads are started
+        self.ensure_threads_started();
+
+        let self_clone = self.clone();
+        let bypass = if ring_buffer_bypass {
+
+            let since_the_epoch = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .expect("Time went backwards");
+            creation_time = since_the_epoch.as_micros() as u64;
+
+            Some(Box::new(move |frame| {
+                self_clone.emit_frame_data(frame);
+            }) as Box<dyn Fn(FrameTaskData) + Send + 'static>)
+        } else {
+            None
+        };
+
+        push_preencoded_frame_data(
+            "SRT_E",
+            &self.frame_buffer,
+            creation_time,
+            presentation_time,
+            raw_data, // data is moved
+            bypass,
+            &self.egress_metrics,
+            client_id,
+            tile_index,
+        );
+    }
+
+    /// Emits frame data to the connected SRT receiver, every frame is a single SRT message.
+    fn emit_frame_data(&self, frame: FrameTaskData) {
+        debug!("Emitting frame with presentation time: {}", frame.presentation_time);
+
+        let bytes = Bytes::from(encode_frame(&frame));
+        let socket = self.socket.clone();
+        let disconnected = self.disconnected.clone();
+
+        let runtime = self.get_runtime();
+        runtime.block_on(async move {
+            let mut socket_guard = socket.lock().await;
+            let Some(srt_socket) = socket_guard.as_mut() else {
+                debug!("No SRT receiver connected to emit frame");
+                return;
+            };
+            // The send time of the message is used by SRT to drop it once the latency budget has passed
+            if let Err(e) = srt_socket.send((Instant::now(), bytes)).await {
+                error!("Failed to write frame: {}", e);
+                *socket_guard = None;
+                disconnected.notify_one();
+            }
+        });
+    }
+
+    fn set_fps(&self, fps: u32) {
+        *self.fps.lock().unwrap() = fps;
+    }
+
+    fn set_encoding_format(&self, encoding_format: EncodingFormat) {
+        *self.encoding_format.lock().unwrap() = encoding_format;
+    }
+
+    fn set_max_number_of_points(&self, max_number_of_points: u64) {
+        *self.max_number_of_points.lock().unwrap() = max_number_of_points;
+    }
+
+    fn set_frame_buffer_capacity(&self, capacity: usize) {
+        let dropped = self.frame_buffer.lock().unwrap().set_capacity(capacity);
+        self.egress_metrics.record_buffer_drops(&dropped);
+    }
+
+    fn flush_and_close(&self, timeout: Duration) {
+        drain_frame_buffer("SRT_E", &self.frame_buffer, timeout);
+        let socket = self.socket.clone();
+        self.get_runtime().block_on(async move {
+            if let Some(mut srt_socket) = socket.lock().await.take() {
+                if let Err(e) = srt_socket.close().await {
+                    error!("Failed to close the SRT connection: {}", e);
+                }
+            }
+        });
+    }
+}
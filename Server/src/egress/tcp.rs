@@ -0,0 +1,323 @@
+// egress/tcp.rs
+
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::encoders::EncodingFormat;
+use crate::processing::{aggregator::PointCloudAggregator, ProcessingPipeline};
+use crate::services::stream_manager::StreamManager;
+use crate::types::EgressProtocolType;
+use shared_utils::error::{XrError, XrResult};
+use shared_utils::types::{FrameTaskData, PointCloudData};
+use shared_utils::wire::encode_frame;
+
+use super::ring_buffer::RingBuffer;
+use tokio::io::AsyncWriteExt;
+use tokio::net::{TcpListener, TcpStream};
+use tokio::runtime::{self, Runtime};
+use tokio::sync::Notify;
+use tracing::{debug, error, info, instrument, warn};
+
+use super::egress_common::{drain_frame_buffer, push_preencoded_frame_data, EgressCommonMetrics, EgressProtocol};
+
+static TCP_RUNTIME: OnceLock<Arc<Runtime>> = OnceLock::new();
+
+/// A consumer that does not accept a frame within this time is disconnected,
+/// as a partially written frame cannot be skipped without breaking the framing.
+const WRITE_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// How the TCP egress reaches its consumers.
+#[derive(Clone, Debug)]
+pub enum TcpMode {
+    /// Accept any number of consumers on this address
+    Listen(SocketAddr),
+    /// Connect to a single consumer on this address, and reconnect whenever the connection is lost
+    Connect(SocketAddr),
+}
+
+/// TCP Egress module responsible for writing frames to plain TCP connections.
+/// Every frame is written as a 4 byte big endian length, followed by the frame encoded with `encode_frame`,
+/// so that external tools can consume the stream without WebRTC or HTTP.
+#[derive(Clone, Debug)]
+pub struct TcpEgress {
+    processing_pipeline: Arc<ProcessingPipeline>,
+    frame_buffer: Arc<Mutex<RingBuffer<FrameTaskData>>>,
+    aggregator: Arc<PointCloudAggregator>,
+    threads_started: Arc<AtomicBool>,
+    fps: Arc<Mutex<u32>>,
+    encoding_format: Arc<Mutex<EncodingFormat>>,
+    max_number_of_points: Arc<Mutex<u64>>,
+    /// The connected consumers and their addresses
+    connections: Arc<tokio::sync::Mutex<Vec<(SocketAddr, TcpStream)>>>,
+    /// Notified when a consumer is gone, so connect mode can reconnect
+    disconnected: Arc<Notify>,
+    egress_metrics: Arc<EgressCommonMetrics>,
+}
+
+impl TcpEgress {
+    /// Initializes the TCP Egress module, and starts accepting or connecting to consumers.
+    #[instrument(skip_all)]
+    pub fn initialize(
+        stream_manager: Arc<StreamManager>,
+        processing_pipeline: Arc<ProcessingPipeline>,
+        mode: Option<TcpMode>,
+    ) {
+        let Some(mode) = mode else {
+            info!("No TCP listen or connect address configured, the TCP egress is disabled");
+            return;
+        };
+
+        let aggregator = Arc::new(PointCloudAggregator::new(stream_manager.clone()));
+
+        let instance = Arc::new(Self {
+            processing_pipeline: processing_pipeline.clone(),
+            frame_buffer: Arc::new(Mutex::new(RingBuffer::new(stream_manager.frame_buffer_capacity(&EgressProtocolType::Tcp)))),
+            aggregator: aggregator.clone(),
+            threads_started: Arc::new(AtomicBool::new(false)),
+            fps: Arc::new(Mutex::new(30)),
+            encoding_format: Arc::new(Mutex::new(EncodingFormat::Draco)),
+            max_number_of_points: Arc::new(Mutex::new(100000)),
+            connections: Arc::new(tokio::sync::Mutex::new(Vec::new())),
+            disconnected: Arc::new(Notify::new()),
+            egress_metrics: Arc::new(EgressCommonMetrics::new("TCP_E")),
+        });
+
+        let instance_clone = instance.clone();
+        instance.get_runtime().spawn(async move {
+            let result = match mode {
+                TcpMode::Listen(address) => instance_clone.accept_consumers(address).await,
+                TcpMode::Connect(address) => {
+                    instance_clone.connect_consumer(address).await;
+                    Ok(())
+                }
+            };
+            if let Err(e) = result {
+                error!("TCP egress stopped: {}", e);
+            }
+        });
+
+        // Store the instance in the StreamManager
+        stream_manager.set_tcp_egress(instance.clone());
+    }
+
+    #[instrument(skip_all)]
+    pub fn get_runtime(&self) -> Arc<Runtime> {
+        TCP_RUNTIME.get_or_init(|| {
+            let rt = runtime::Builder::new_multi_thread()
+                .worker_threads(2)
+                .thread_name_fn(|| {
+                    static ATOMIC_TCP_ID: std::sync::atomic::AtomicUsize = std::sync::atomic::AtomicUsize::new(0);
+                    let id = ATOMIC_TCP_ID.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+                    format!("TCP_R w-{}", id)
+                })
+                .enable_all()
+                .build().unwrap();
+            Arc::new(rt)
+        }).clone()
+    }
+
+    /// Accepts consumers on the listen address, every consumer receives all frames.
+    async fn accept_consumers(&self, address: SocketAddr) -> XrResult<()> {
+        let listener = TcpListener::bind(address).await
+            .map_err(|e| XrError::Transport(format!("Failed to listen on {address}: {e}")))?;
+        info!("TCP egress listening on {}", address);
+
+        loop {
+            match listener.accept().await {
+                Ok((stream, peer)) => {
+                    info!("TCP consumer {} connected", peer);
+                    self.add_connection(peer, stream).await;
+                }
+                Err(e) => {
+                    error!("Failed to accept a TCP consumer: {}", e);
+                    tokio::time::sleep(Duration::from_secs(1)).await;
+                }
+            }
+        }
+    }
+
+    /// Connects to the consumer, and reconnects once the connection is lost.
+    async fn connect_consumer(&self, address: SocketAddr) {
+        loop {
+            match TcpStream::connect(address).await {
+                Ok(stream) => {
+                    info!("Connected to TCP consumer {}", address);
+                    self.add_connection(address, stream).await;
+                    self.disconnected.notified().await;
+                }
+                Err(e) => {
+                    warn!("Failed to connect to TCP consumer {}: {}", address, e);
+                    tokio::time::sleep(Duration::from_secs(1)).await;
+                }
+            }
+        }
+    }
+
+    async fn add_connection(&self, peer: SocketAddr, stream: TcpStream) {
+        // Frames are written in one go, waiting for more data would only add latency
+        if let Err(e) = stream.set_nodelay(true) {
+            warn!("Failed to disable Nagle's algorithm for {}: {}", peer, e);
+        }
+        self.connections.lock().await.push((peer, stream));
+    }
+}
+
+impl EgressProtocol for TcpEgress {
+    #[inline]
+    fn fps(&self) -> u32 {
+        *self.fps.lock().unwrap()
+    }
+
+    #[inline]
+    fn encoding_format(&self) -> EncodingFormat {
+        *self.encoding_format.lock().unwrap()
+    }
+
+    #[inline]
+    fn max_number_of_points(&self) -> u64 {
+        *self.max_number_of_points.lock().unwrap()
+    }
+
+    fn ensure_threads_started(&self) {
+        let already_started = self.threads_started.load(Ordering::Relaxed);
+        if already_started {
+            return;
+        }
+
+        // Set the threads as started
+        self.threads_started.store(true, Ordering::Relaxed);
+
+        // Start background threads using the common module
+        crate::egress::egress_common::start_generator_thread(
+            "TCP_E".to_string(),
+            self.processing_pipeline.clone(),
+            self.aggregator.clone(),
+            self.frame_buffer.clone(),
+            self.fps.clone(),
+            self.encoding_format.clone(),
+            self.max_number_of_points.clone(),
+        );
+
+        let self_clone = self.clone();
+        crate::egress::egress_common::start_transmission_thread(
+            "TCP_E".to_string(),
+            self.frame_buffer.clone(),
+            move |frame| {
+                self_clone.emit_frame_data(frame);
+            },
+            false,
+        );
+    }
+
+    fn push_point_cloud(&self, point_cloud: PointCloudData, stream_id: String) {
+        self.ensure_threads_started();
+        self.aggregator.update_point_cloud(stream_id, point_cloud);
+    }
+
+    // Process and sends a frame, this raw version bypasses the aggregation
+    fn push_encoded_frame(&self, raw_data: Vec<u8>, _stream_id: String, mut creation_time: u64, presentation_time: u64, ring_buffer_bypass: bool, client_id: Option<u64>, tile_index: Option<u32>) {
+        // Ensure the threads are started
+        self.ensure_threads_started();
+
+        let self_clone = self.clone();
+        let bypass = if ring_buffer_bypass {
+
+            let since_the_epoch = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .expect("Time went backwards");
+            creation_time = since_the_epoch.as_micros() as u64;
+
+            Some(Box::new(move |frame| {
+                self_clone.emit_frame_data(frame);
+            }) as Box<dyn Fn(FrameTaskData) + Send + 'static>)
+        } else {
+            None
+        };
+
+        push_preencoded_frame_data(
+            "TCP_E",
+            &self.frame_buffer,
+            creation_time,
+            presentation_time,
+            raw_data, // data is moved
+            bypass,
+            &self.egress_metrics,
+            client_id,
+            tile_index,
+        );
+    }
+
+    /// Writes the length-prefixed frame to every connected consumer, consumers that fail are dropped.
+    fn emit_frame_data(&self, frame: FrameTaskData) {
+        debug!("Emitting frame with presentation time: {}", frame.presentation_time);
+
+        let encoded = encode_frame(&frame);
+        let mut message = Vec::with_capacity(4 + encoded.len());
+        message.extend_from_slice(&(encoded.len() as u32).to_be_bytes());
+        message.extend_from_slice(&encoded);
+
+        let connections = self.connections.clone();
+        let disconnected = self.disconnected.clone();
+
+        let runtime = self.get_runtime();
+        runtime.block_on(async move {
+            let mut connections = connections.lock().await;
+            if connections.is_empty() {
+                debug!("No TCP consumer connected to emit frame");
+                return;
+            }
+
+            let mut failed = vec![];
+            for (i, (peer, stream)) in connections.iter_mut().enumerate() {
+                match tokio::time::timeout(WRITE_TIMEOUT, stream.write_all(&message)).await {
+                    Ok(Ok(())) => {}
+                    Ok(Err(e)) => {
+                        error!("Failed to write frame to TCP consumer {}: {}", peer, e);
+                        failed.push(i);
+                    }
+                    Err(_) => {
+                        error!("TCP consumer {} is too slow, disconnecting", peer);
+                        failed.push(i);
+                    }
+                }
+            }
+
+            for i in failed.into_iter().rev() {
+                let (peer, _) = connections.remove(i);
+                info!("TCP consumer {} disconnected", peer);
+                disconnected.notify_one();
+            }
+        });
+    }
+
+    fn set_fps(&self, fps: u32) {
+        *self.fps.lock().unwrap() = fps;
+    }
+
+    fn set_encoding_format(&self, encoding_format: EncodingFormat) {
+        *self.encoding_format.lock().unwrap() = encoding_format;
+    }
+
+    fn set_max_number_of_points(&self, max_number_of_points: u64) {
+        *self.max_number_of_points.lock().unwrap() = max_number_of_points;
+    }
+
+    fn set_frame_buffer_capacity(&self, capacity: usize) {
+        let dropped = self.frame_buffer.lock().unwrap().set_capacity(capacity);
+        self.egress_metrics.record_buffer_drops(&dropped);
+    }
+
+    fn flush_and_close(&self, timeout: Duration) {
+        drain_frame_buffer("TCP_E", &self.frame_buffer, timeout);
+        let connections = self.connections.clone();
+        self.get_runtime().block_on(async move {
+            for (peer, mut stream) in connections.lock().await.drain(..) {
+                if let Err(e) = stream.shutdown().await {
+                    warn!("Failed to close the connection of TCP consumer {}: {}", peer, e);
+                }
+            }
+        });
+    }
+}
@@ -0,0 +1,152 @@
+// egress/tile_priority.rs
+
+//! Orders the tiles that compete for one egress by how visible and important the clients report them to be,
+//! so the egress buffers send the most relevant tiles first and drop the least relevant ones under pressure.
+
+use std::collections::HashMap;
+use std::sync::{OnceLock, RwLock};
+
+use serde::{Deserialize, Serialize};
+use shared_utils::types::FrameTaskData;
+
+use super::ring_buffer::RingBuffer;
+
+/// Priority of the frames of tiles that no client reported on, and of the combined frames
+pub const DEFAULT_TILE_PRIORITY: f32 = 1.0;
+
+static TILE_PRIORITIES: OnceLock<TilePriorities> = OnceLock::new();
+
+/// Returns the tile priorities that are shared by all egresses.
+pub fn get_tile_priorities() -> &'static TilePriorities {
+    TILE_PRIORITIES.get_or_init(TilePriorities::default)
+}
+
+/// A tile of the stream of an SFU client.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct TileKey {
+    pub client_id: u64,
+    pub tile_index: u32,
+}
+
+impl TileKey {
+    /// Returns the tile of a frame, or `None` for frames that are not a tile, e.g. the combined frames.
+    pub fn of_frame(frame: &FrameTaskData) -> Option<Self> {
+        Some(Self {
+            client_id: frame.sfu_client_id?,
+            tile_index: frame.sfu_tile_index?,
+        })
+    }
+}
+
+/// What a viewer reports about one tile.
+#[derive(Deserialize, Serialize, Clone, Copy, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct TilePriorityReport {
+    pub client_id: u64,
+    pub tile_index: u32,
+    /// Fraction of the tile that is in the viewport of the viewer, between 0 and 1
+    pub visibility: f32,
+    /// Relative importance of the tile, e.g. higher for the tile with the face of a person
+    #[serde(default = "default_importance")]
+    pub importance: f32,
+}
+
+fn default_importance() -> f32 {
+    1.0
+}
+
+impl TilePriorityReport {
+    pub fn priority(&self) -> f32 {
+        self.visibility.clamp(0.0, 1.0) * self.importance.max(0.0)
+    }
+}
+
+/// The priorities of the tiles, per viewer that reported them.
+/// A tile gets the highest priority any viewer gave it, as every viewer receives the frames of the same egress.
+#[derive(Debug, Default)]
+pub struct TilePriorities {
+    reports: RwLock<HashMap<String, HashMap<TileKey, f32>>>,
+    priorities: RwLock<HashMap<TileKey, f32>>,
+}
+
+impl TilePriorities {
+    /// Replaces the priorities of the tiles a viewer reported on, its other tiles keep their priority.
+    pub fn report(&self, viewer_id: &str, reports: &[TilePriorityReport]) {
+        let mut all_reports = self.reports.write().unwrap();
+        let viewer_reports = all_reports.entry(viewer_id.to_string()).or_default();
+        for report in reports {
+            let tile = TileKey { client_id: report.client_id, tile_index: report.tile_index };
+            viewer_reports.insert(tile, report.priority());
+        }
+        self.update_priorities(&all_reports);
+    }
+
+    /// Forgets the reports of a viewer, e.g. when it disconnects.
+    pub fn remove_viewer(&self, viewer_id: &str) {
+        let mut all_reports = self.reports.write().unwrap();
+        if all_reports.remove(viewer_id).is_some() {
+            self.update_priorities(&all_reports);
+        }
+    }
+
+    fn update_priorities(&self, all_reports: &HashMap<String, HashMap<TileKey, f32>>) {
+        let mut priorities = HashMap::new();
+        for (tile, priority) in all_reports.values().flatten() {
+            let entry = priorities.entry(*tile).or_insert(*priority);
+            *entry = entry.max(*priority);
+        }
+        *self.priorities.write().unwrap() = priorities;
+    }
+
+    /// Whether any viewer reported on a tile, otherwise the egress buffers stay in FIFO order.
+    pub fn is_active(&self) -> bool {
+        !self.priorities.read().unwrap().is_empty()
+    }
+
+    /// Returns the priority of the tile of a frame.
+    pub fn priority(&self, frame: &FrameTaskData) -> f32 {
+        TileKey::of_frame(frame)
+            .and_then(|tile| self.priorities.read().unwrap().get(&tile).copied())
+            .unwrap_or(DEFAULT_TILE_PRIORITY)
+    }
+
+    /// Returns the tiles with their priority, the highest priority first.
+    pub fn list(&self) -> Vec<(TileKey, f32)> {
+        let mut priorities: Vec<_> = self.priorities.read().unwrap().iter().map(|(tile, priority)| (*tile, *priority)).collect();
+        priorities.sort_by(|a, b| b.1.total_cmp(&a.1));
+        priorities
+    }
+
+    /// Returns the index of the frame to emit next: the oldest frame of the highest priority.
+    pub fn next_frame_index(&self, frames: &RingBuffer<FrameTaskData>) -> Option<usize> {
+        if !self.is_active() {
+            return if frames.is_empty() { None } else { Some(0) };
+        }
+        let mut next: Option<(usize, f32)> = None;
+        for (index, frame) in frames.iter().enumerate() {
+            let priority = self.priority(frame);
+            if !matches!(next, Some((_, highest)) if priority <= highest) {
+                next = Some((index, priority));
+            }
+        }
+        next.map(|(index, _)| index)
+    }
+
+    /// Returns the index of the frame to drop to make room for `frame` in a full buffer:
+    /// the oldest frame of the lowest priority, or `None` when `frame` itself has a lower priority than all of them.
+    pub fn frame_index_to_drop(&self, frames: &RingBuffer<FrameTaskData>, frame: &FrameTaskData) -> Option<usize> {
+        if !self.is_active() {
+            return if frames.is_empty() { None } else { Some(0) };
+        }
+        let mut lowest: Option<(usize, f32)> = None;
+        for (index, buffered) in frames.iter().enumerate() {
+            let priority = self.priority(buffered);
+            if !matches!(lowest, Some((_, lowest)) if priority >= lowest) {
+                lowest = Some((index, priority));
+            }
+        }
+        lowest
+            .filter(|(_, lowest)| *lowest <= self.priority(frame))
+            .map(|(index, _)| index)
+    }
+}
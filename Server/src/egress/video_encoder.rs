@@ -0,0 +1,372 @@
+// egress/video_encoder.rs
+
+//! Encodes rendered previews as video, for the egresses that stream the point cloud as 2D video,
+//! and the patch atlases of the V-PCC encoder.
+
+use std::sync::OnceLock;
+
+use shared_utils::error::{XrError, XrResult};
+use tracing::warn;
+
+use openh264::encoder::{BitRate, Encoder, EncoderConfig, FrameRate, RateControlMode};
+use openh264::formats::YUVSlices;
+use openh264::OpenH264API;
+use webrtc::api::media_engine::{MIME_TYPE_AV1, MIME_TYPE_H264};
+
+/// The video codecs a rendered preview can be encoded with.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum VideoCodec {
+    H264,
+    /// Only available when the server is built with the `av1` feature
+    Av1,
+}
+
+impl VideoCodec {
+    pub fn parse(value: &str) -> XrResult<Self> {
+        match value.trim().to_lowercase().as_str() {
+            "h264" | "avc" => Ok(VideoCodec::H264),
+            "av1" if cfg!(feature = "av1") => Ok(VideoCodec::Av1),
+            "av1" => Err(XrError::Config("AV1 requires a server that is built with the av1 feature".to_string())),
+            other => Err(XrError::Config(format!("Unsupported video codec {other}, expected h264 or av1"))),
+        }
+    }
+
+    /// The MIME type of the WebRTC track that carries the video
+    pub fn mime_type(&self) -> &'static str {
+        match self {
+            VideoCodec::H264 => MIME_TYPE_H264,
+            VideoCodec::Av1 => MIME_TYPE_AV1,
+        }
+    }
+}
+
+/// The hardware H.264 encoders that FFmpeg wraps, which take the frames from system memory.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum HardwareVideoEncoder {
+    /// NVIDIA NVENC
+    Nvenc,
+    /// Intel Quick Sync Video
+    Qsv,
+}
+
+impl HardwareVideoEncoder {
+    /// Parses the encoder, None for "none" which keeps the software encoders.
+    pub fn parse(value: &str) -> XrResult<Option<Self>> {
+        let encoder = match value.trim().to_lowercase().as_str() {
+            "none" => return Ok(None),
+            "nvenc" => HardwareVideoEncoder::Nvenc,
+            "qsv" => HardwareVideoEncoder::Qsv,
+            other => return Err(XrError::Config(format!("Unsupported hardware video encoder {other}, expected nvenc, qsv or none"))),
+        };
+        if !cfg!(feature = "hw-video") {
+            return Err(XrError::Config("Hardware video encoding requires a server that is built with the hw-video feature".to_string()));
+        }
+        Ok(Some(encoder))
+    }
+
+    /// The name of the H.264 encoder in FFmpeg
+    pub fn codec_name(&self) -> &'static str {
+        match self {
+            HardwareVideoEncoder::Nvenc => "h264_nvenc",
+            HardwareVideoEncoder::Qsv => "h264_qsv",
+        }
+    }
+}
+
+static HARDWARE_ENCODER: OnceLock<HardwareVideoEncoder> = OnceLock::new();
+
+/// Makes `create_video_encoder` use the hardware encoder for H.264, OpenH264 is used otherwise.
+pub fn set_hardware_encoder(encoder: HardwareVideoEncoder) {
+    let _ = HARDWARE_ENCODER.set(encoder);
+}
+
+/// Settings of a video encoder, the width and height have to be even.
+#[derive(Clone, Copy, Debug)]
+pub struct VideoEncoderConfig {
+    pub codec: VideoCodec,
+    pub width: u32,
+    pub height: u32,
+    pub fps: u32,
+    /// Target bitrate in bits per second
+    pub bitrate: u32,
+}
+
+/// Encodes I420 frames, one access unit per frame.
+pub trait VideoEncoder: Send {
+    /// Encodes a frame, and returns the bitstream of the frame, which may be empty while the encoder buffers.
+    fn encode(&mut self, frame: &I420Frame, force_keyframe: bool) -> XrResult<Vec<u8>>;
+}
+
+/// Creates the encoder of the configured codec.
+/// H.264 is encoded by the hardware encoder that was set with `set_hardware_encoder`,
+/// and by OpenH264 when there is none or it can not be opened, e.g. on a host without the GPU or its driver.
+pub fn create_video_encoder(config: &VideoEncoderConfig) -> XrResult<Box<dyn VideoEncoder>> {
+    if config.width % 2 != 0 || config.height % 2 != 0 {
+        return Err(XrError::Config(format!("The video resolution {}x{} has to be even", config.width, config.height)));
+    }
+    match config.codec {
+        VideoCodec::H264 => {
+            if let Some(hardware) = HARDWARE_ENCODER.get() {
+                match create_hardware_encoder(*hardware, config) {
+                    Ok(encoder) => return Ok(encoder),
+                    Err(e) => warn!("Encoding {}x{} H.264 with OpenH264, {:?} is not available: {}", config.width, config.height, hardware, e),
+                }
+            }
+            Ok(Box::new(H264Encoder::new(config)?))
+        }
+        #[cfg(feature = "av1")]
+        VideoCodec::Av1 => Ok(Box::new(av1::Av1Encoder::new(config)?)),
+        #[cfg(not(feature = "av1"))]
+        VideoCodec::Av1 => Err(XrError::Config("AV1 requires a server that is built with the av1 feature".to_string())),
+    }
+}
+
+#[cfg(feature = "hw-video")]
+fn create_hardware_encoder(hardware: HardwareVideoEncoder, config: &VideoEncoderConfig) -> XrResult<Box<dyn VideoEncoder>> {
+    Ok(Box::new(hardware::HardwareH264Encoder::new(hardware, config)?))
+}
+
+#[cfg(not(feature = "hw-video"))]
+fn create_hardware_encoder(_hardware: HardwareVideoEncoder, _config: &VideoEncoderConfig) -> XrResult<Box<dyn VideoEncoder>> {
+    Err(XrError::Config("Hardware video encoding requires a server that is built with the hw-video feature".to_string()))
+}
+
+/// A frame in the planar YUV 4:2:0 format that the encoders take.
+#[derive(Clone, Debug)]
+pub struct I420Frame {
+    pub width: usize,
+    pub height: usize,
+    pub y: Vec<u8>,
+    pub u: Vec<u8>,
+    pub v: Vec<u8>,
+}
+
+impl I420Frame {
+    /// Converts a BGRA image, as rendered by `render_preview`, with BT.601 limited range coefficients.
+    /// The chroma is the average of every 2x2 block of pixels.
+    pub fn from_bgra(image: &[u8], width: usize, height: usize) -> Self {
+        let mut y = vec![0u8; width * height];
+        let mut u = vec![0u8; (width / 2) * (height / 2)];
+        let mut v = vec![0u8; (width / 2) * (height / 2)];
+        for row in 0..height {
+            for column in 0..width {
+                let pixel = &image[(row * width + column) * 4..];
+                let (b, g, r) = (pixel[0] as i32, pixel[1] as i32, pixel[2] as i32);
+                y[row * width + column] = (((66 * r + 129 * g + 25 * b + 128) >> 8) + 16).clamp(0, 255) as u8;
+            }
+        }
+        for row in 0..height / 2 {
+            for column in 0..width / 2 {
+                let (mut r, mut g, mut b) = (0i32, 0i32, 0i32);
+                for (dy, dx) in [(0, 0), (0, 1), (1, 0), (1, 1)] {
+                    let pixel = &image[((row * 2 + dy) * width + column * 2 + dx) * 4..];
+                    b += pixel[0] as i32;
+                    g += pixel[1] as i32;
+                    r += pixel[2] as i32;
+                }
+                let (r, g, b) = (r / 4, g / 4, b / 4);
+                u[row * (width / 2) + column] = (((-38 * r - 74 * g + 112 * b + 128) >> 8) + 128).clamp(0, 255) as u8;
+                v[row * (width / 2) + column] = (((112 * r - 94 * g - 18 * b + 128) >> 8) + 128).clamp(0, 255) as u8;
+            }
+        }
+        Self { width, height, y, u, v }
+    }
+}
+
+/// H.264 through OpenH264, which produces an Annex B bitstream.
+struct H264Encoder {
+    encoder: Encoder,
+}
+
+impl H264Encoder {
+    fn new(config: &VideoEncoderConfig) -> XrResult<Self> {
+        let encoder_config = EncoderConfig::new()
+            .bitrate(BitRate::from_bps(config.bitrate))
+            .max_frame_rate(FrameRate::from_hz(config.fps.max(1) as f32))
+            .rate_control_mode(RateControlMode::Bitrate);
+        let encoder = Encoder::with_api_config(OpenH264API::from_source(), encoder_config).map_err(XrError::codec)?;
+        Ok(Self { encoder })
+    }
+}
+
+impl VideoEncoder for H264Encoder {
+    fn encode(&mut self, frame: &I420Frame, force_keyframe: bool) -> XrResult<Vec<u8>> {
+        if force_keyframe {
+            self.encoder.force_intra_frame();
+        }
+        let source = YUVSlices::new(
+            (&frame.y, &frame.u, &frame.v),
+            (frame.width, frame.height),
+            (frame.width, frame.width / 2, frame.width / 2),
+        );
+        let bitstream = self.encoder.encode(&source).map_err(XrError::codec)?;
+        Ok(bitstream.to_vec())
+    }
+}
+
+#[cfg(feature = "av1")]
+mod av1 {
+    use rav1e::prelude::*;
+    use shared_utils::error::{XrError, XrResult};
+
+    use super::{I420Frame, VideoEncoder, VideoEncoderConfig};
+
+    /// AV1 through rav1e, in low latency mode so every frame is returned as soon as it is encoded.
+    pub struct Av1Encoder {
+        context: Context<u8>,
+    }
+
+    impl Av1Encoder {
+        pub fn new(config: &VideoEncoderConfig) -> XrResult<Self> {
+            let encoder_config = EncoderConfig {
+                width: config.width as usize,
+                height: config.height as usize,
+                time_base: Rational::new(1, config.fps.max(1) as u64),
+                bitrate: (config.bitrate / 1000) as i32,
+                low_latency: true,
+                max_key_frame_interval: config.fps.max(1) as u64 * 2,
+                speed_settings: SpeedSettings::from_preset(10),
+                ..Default::default()
+            };
+            let context = Config::new()
+                .with_encoder_config(encoder_config)
+                .new_context()
+                .map_err(XrError::codec)?;
+            Ok(Self { context })
+        }
+    }
+
+    impl VideoEncoder for Av1Encoder {
+        fn encode(&mut self, frame: &I420Frame, force_keyframe: bool) -> XrResult<Vec<u8>> {
+            let mut input = self.context.new_frame();
+            input.planes[0].copy_from_raw_u8(&frame.y, frame.width, 1);
+            input.planes[1].copy_from_raw_u8(&frame.u, frame.width / 2, 1);
+            input.planes[2].copy_from_raw_u8(&frame.v, frame.width / 2, 1);
+            let parameters = FrameParameters {
+                frame_type_override: if force_keyframe { FrameTypeOverride::Key } else { FrameTypeOverride::No },
+                ..Default::default()
+            };
+            self.context.send_frame((input, parameters)).map_err(XrError::codec)?;
+
+            let mut bitstream = Vec::new();
+            loop {
+                match self.context.receive_packet() {
+                    Ok(packet) => bitstream.extend_from_slice(&packet.data),
+                    Err(EncoderStatus::Encoded) => continue,
+                    Err(EncoderStatus::NeedMoreData) => break,
+                    Err(e) => return Err(XrError::codec(e)),
+                }
+            }
+            Ok(bitstream)
+        }
+    }
+}
+
+#[cfg(feature = "hw-video")]
+mod hardware {
+    use ffmpeg_next as ffmpeg;
+    use ffmpeg::codec::context::Context;
+    use ffmpeg::codec::encoder::video::Encoder;
+    use ffmpeg::util::error::EAGAIN;
+    use ffmpeg::util::format::Pixel;
+    use ffmpeg::util::frame::video::Video;
+    use ffmpeg::util::picture;
+    use ffmpeg::{Dictionary, Packet, Rational};
+    use shared_utils::error::{XrError, XrResult};
+
+    use super::{HardwareVideoEncoder, I420Frame, VideoEncoder, VideoEncoderConfig};
+
+    /// H.264 through the FFmpeg wrapper of a hardware encoder, which produces an Annex B bitstream
+    /// that repeats the parameter sets in every keyframe, as OpenH264 does.
+    pub struct HardwareH264Encoder {
+        encoder: Encoder,
+        width: u32,
+        height: u32,
+        pts: i64,
+    }
+
+    impl HardwareH264Encoder {
+        pub fn new(hardware: HardwareVideoEncoder, config: &VideoEncoderConfig) -> XrResult<Self> {
+            ffmpeg::init().map_err(XrError::codec)?;
+            let codec = ffmpeg::encoder::find_by_name(hardware.codec_name())
+                .ok_or_else(|| XrError::Codec(format!("FFmpeg has no {} encoder", hardware.codec_name())))?;
+            let mut video = Context::new_with_codec(codec).encoder().video().map_err(XrError::codec)?;
+            let fps = config.fps.max(1) as i32;
+            video.set_width(config.width);
+            video.set_height(config.height);
+            // Both encoders take NV12 from system memory
+            video.set_format(Pixel::NV12);
+            video.set_time_base(Rational::new(1, fps));
+            video.set_frame_rate(Some(Rational::new(fps, 1)));
+            video.set_bit_rate(config.bitrate as usize);
+            video.set_max_b_frames(0);
+            video.set_gop(fps as u32 * 2);
+
+            // Return every frame as soon as it is encoded, and make the forced keyframes IDR frames
+            let mut options = Dictionary::new();
+            match hardware {
+                HardwareVideoEncoder::Nvenc => {
+                    options.set("preset", "p1");
+                    options.set("tune", "ull");
+                    options.set("zerolatency", "1");
+                    options.set("delay", "0");
+                    options.set("forced-idr", "1");
+                }
+                HardwareVideoEncoder::Qsv => {
+                    options.set("preset", "veryfast");
+                    options.set("async_depth", "1");
+                    options.set("look_ahead", "0");
+                    options.set("forced_idr", "1");
+                }
+            }
+            let encoder = video.open_with(options).map_err(XrError::codec)?;
+            Ok(Self {
+                encoder,
+                width: config.width,
+                height: config.height,
+                pts: 0,
+            })
+        }
+    }
+
+    impl VideoEncoder for HardwareH264Encoder {
+        fn encode(&mut self, frame: &I420Frame, force_keyframe: bool) -> XrResult<Vec<u8>> {
+            if (frame.width as u32, frame.height as u32) != (self.width, self.height) {
+                return Err(XrError::Codec(format!("A {}x{} frame for a {}x{} encoder", frame.width, frame.height, self.width, self.height)));
+            }
+            // The encoder may still hold a reference to the previous frame, so every frame gets its own buffer
+            let mut nv12 = Video::new(Pixel::NV12, self.width, self.height);
+            let y_stride = nv12.stride(0);
+            for (row, luma) in frame.y.chunks_exact(frame.width).enumerate() {
+                nv12.data_mut(0)[row * y_stride..row * y_stride + frame.width].copy_from_slice(luma);
+            }
+            // The chroma of NV12 is a single plane with the u and v samples interleaved
+            let uv_stride = nv12.stride(1);
+            let chroma_width = frame.width / 2;
+            for row in 0..frame.height / 2 {
+                let line = &mut nv12.data_mut(1)[row * uv_stride..row * uv_stride + chroma_width * 2];
+                for column in 0..chroma_width {
+                    line[column * 2] = frame.u[row * chroma_width + column];
+                    line[column * 2 + 1] = frame.v[row * chroma_width + column];
+                }
+            }
+            nv12.set_pts(Some(self.pts));
+            self.pts += 1;
+            if force_keyframe {
+                nv12.set_kind(picture::Type::I);
+            }
+            self.encoder.send_frame(&nv12).map_err(XrError::codec)?;
+
+            let mut bitstream = Vec::new();
+            let mut packet = Packet::empty();
+            loop {
+                match self.encoder.receive_packet(&mut packet) {
+                    Ok(()) => bitstream.extend_from_slice(packet.data().unwrap_or_default()),
+                    Err(ffmpeg::Error::Other { errno: EAGAIN }) => break,
+                    Err(e) => return Err(XrError::codec(e)),
+                }
+            }
+            Ok(bitstream)
+        }
+    }
+}
@@ -1,27 +1,34 @@
 // egress/webrtc.rs
 
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 use std::fmt;
 use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::{Arc, Mutex, OnceLock, RwLock};
-use std::time::{SystemTime, UNIX_EPOCH};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
 use crate::encoders::EncodingFormat;
 use crate::processing::aggregator::PointCloudAggregator;
 use crate::processing::ProcessingPipeline;
-use crate::services::stream_manager::StreamManager;
+use crate::services::stream_manager::{StreamManager, COMBINED_STREAM_ID};
+use crate::types::EgressProtocolType;
 use crate::types::{WebRtcIceCandidate, WebRtcOffer};
 
+use shared_utils::bandwidth_estimator::{BandwidthEstimate, BandwidthEstimator, BandwidthEstimatorConfig};
 use shared_utils::codec::video_codec_capability;
-use shared_utils::peer_connection::create_webrtc_peer_connection;
+use shared_utils::datachannel_pointcloud::{DataChannelConfig, DataChannelPointCloudSender, POINT_CLOUD_DATA_CHANNEL_LABEL};
+use shared_utils::error::{XrError, XrResult};
+use shared_utils::frame_transport::FrameTransport;
+use shared_utils::peer_connection::{create_webrtc_peer_connection, PeerConnectionConfig};
 use shared_utils::types::{FrameTaskData, PointCloudData};
 
-use circular_buffer::CircularBuffer;
+use super::ring_buffer::RingBuffer;
+use metrics::{get_metrics, Metrics};
 use serde_json::Value;
 use socketioxide::extract::SocketRef;
 use tokio::runtime::{self, Runtime};
 use tracing::{debug, error, info, instrument};
 
+use webrtc::data_channel::RTCDataChannel;
 use webrtc::ice_transport::ice_candidate::{RTCIceCandidate, RTCIceCandidateInit};
 use webrtc::peer_connection::peer_connection_state::RTCPeerConnectionState;
 use webrtc::peer_connection::sdp::session_description::RTCSessionDescription;
@@ -29,20 +36,34 @@ use webrtc::peer_connection::RTCPeerConnection;
 use webrtc::rtp_transceiver::rtp_sender::RTCRtpSender;
 use webrtc::track::track_local::TrackLocal;
 
-use shared_utils::track_local_pointcloud_rtp::TrackLocalPointCloudRTP;
+use shared_utils::track_local_pointcloud_rtp::{QualityLayer, TrackLocalPointCloudRTP};
 
-use super::egress_common::{push_preencoded_frame_data, EgressCommonMetrics, EgressProtocol};
+use super::bitrate_ladder::{encode_ladder, parse_ladder};
+use super::client_budget::{AdaptiveBudgetConfig, ClientBudget};
+use super::egress_common::{drain_frame_buffer, push_preencoded_frame_data, EgressCommonMetrics, EgressProtocol};
 
 static WEBRTC_RUNTIME: OnceLock<Arc<Runtime>> = OnceLock::new();
 
+/// The budget of a client, and how much of it is used.
+#[derive(Clone, Debug)]
+struct ClientBudgetState {
+    budget: ClientBudget,
+    /// SSRC of the broadcast track towards this client, `None` until the track is negotiated
+    ssrc: Option<u32>,
+    /// Frames the client may still receive, every frame of the egress adds `budget.fps / fps`
+    credit: f64,
+}
+
 /// WebRTC Egress module responsible for sending frames over WebRTC data channels.
 #[derive(Clone)]
 pub struct WebRTCEgress {
     stream_manager: Arc<StreamManager>,
     tracks: Arc<RwLock<HashMap<String, Arc<TrackLocalPointCloudRTP>>>>,
     rtp_senders: Arc<RwLock<HashMap<String, HashMap<String, Arc<RTCRtpSender>>>>>,
+    /// The point cloud DataChannels of the clients that receive the frames over SCTP instead of RTP: socket_id -> sender
+    data_channels: Arc<RwLock<HashMap<String, Arc<DataChannelPointCloudSender>>>>,
     processing_pipeline: Arc<ProcessingPipeline>,
-    frame_buffer: Arc<Mutex<CircularBuffer<10, FrameTaskData>>>,
+    frame_buffer: Arc<Mutex<RingBuffer<FrameTaskData>>>,
     aggregator: Arc<PointCloudAggregator>,
     threads_started: Arc<AtomicBool>,
     fps: Arc<Mutex<u32>>,
@@ -52,6 +73,19 @@ pub struct WebRTCEgress {
     peer_connections: Arc<RwLock<HashMap<String, Arc<RTCPeerConnection>>>>,
     /// Temporary storage of ICE candidates if the `remote_description` is not yet set
     pending_ice: Arc<RwLock<HashMap<String, Vec<RTCIceCandidateInit>>>>,
+    peer_connection_config: PeerConnectionConfig,
+    /// The latest estimate of the available bitrate of every client: socket_id -> estimate
+    bandwidth_estimates: Arc<RwLock<HashMap<String, BandwidthEstimate>>>,
+    /// Adapt the number of points and the frame rate to the estimated bitrate of every client,
+    /// instead of sending the same frames to all of them
+    adaptive_budget: Arc<AtomicBool>,
+    budget_config: AdaptiveBudgetConfig,
+    /// The quality layers that the adaptive budget picks from, ordered from the highest to the lowest quality
+    quality_layers: Arc<RwLock<Vec<QualityLayer>>>,
+    /// The budget of every client: socket_id -> budget
+    client_budgets: Arc<RwLock<HashMap<String, ClientBudgetState>>>,
+    /// Moving average of the size of the emitted frames, in bits
+    average_frame_bits: Arc<Mutex<f64>>,
     egress_metrics: Arc<EgressCommonMetrics>,
 }
 
@@ -76,6 +110,7 @@ impl WebRTCEgress {
     pub fn initialize(
         stream_manager: Arc<StreamManager>,
         processing_pipeline: Arc<ProcessingPipeline>,
+        peer_connection_config: PeerConnectionConfig,
     ) {
         let aggregator = Arc::new(PointCloudAggregator::new(stream_manager.clone()));
 
@@ -83,8 +118,9 @@ impl WebRTCEgress {
             stream_manager: stream_manager.clone(),
             tracks: Arc::new(RwLock::new(HashMap::new())),
             rtp_senders: Arc::new(RwLock::new(HashMap::new())),
+            data_channels: Arc::new(RwLock::new(HashMap::new())),
             processing_pipeline: processing_pipeline.clone(),
-            frame_buffer: Arc::new(Mutex::new(CircularBuffer::new())),
+            frame_buffer: Arc::new(Mutex::new(RingBuffer::new(stream_manager.frame_buffer_capacity(&EgressProtocolType::WebRTC)))),
             aggregator: aggregator.clone(),
             threads_started: Arc::new(AtomicBool::new(false)),
             fps: Arc::new(Mutex::new(30)),
@@ -92,9 +128,49 @@ impl WebRTCEgress {
             max_number_of_points: Arc::new(Mutex::new(100000)),
             peer_connections: Arc::new(RwLock::new(HashMap::new())),
             pending_ice: Arc::new(RwLock::new(HashMap::new())),
-            egress_metrics: Arc::new(EgressCommonMetrics::new()),
+            peer_connection_config,
+            bandwidth_estimates: Arc::new(RwLock::new(HashMap::new())),
+            adaptive_budget: Arc::new(AtomicBool::new(false)),
+            budget_config: AdaptiveBudgetConfig::default(),
+            quality_layers: Arc::new(RwLock::new(Vec::new())),
+            client_budgets: Arc::new(RwLock::new(HashMap::new())),
+            average_frame_bits: Arc::new(Mutex::new(0.0)),
+            egress_metrics: Arc::new(EgressCommonMetrics::new("WRTC_E")),
         });
 
+        // Export the retransmission counters of every track, on every metrics update
+        let weak_tracks = Arc::downgrade(&instance.tracks);
+        get_metrics().add_update_callback(Arc::new(move |metrics: &Metrics| {
+            let Some(tracks) = weak_tracks.upgrade() else {
+                return;
+            };
+            if let Err(e) = update_retransmission_metrics(metrics, &tracks.read().unwrap()) {
+                error!("Failed to update the retransmission metrics: {}", e);
+            }
+        }));
+
+        // Export the estimated available bitrate of every client
+        let weak_bandwidth_estimates = Arc::downgrade(&instance.bandwidth_estimates);
+        get_metrics().add_update_callback(Arc::new(move |metrics: &Metrics| {
+            let Some(bandwidth_estimates) = weak_bandwidth_estimates.upgrade() else {
+                return;
+            };
+            if let Err(e) = update_bandwidth_metrics(metrics, &bandwidth_estimates.read().unwrap()) {
+                error!("Failed to update the bandwidth metrics: {}", e);
+            }
+        }));
+
+        // Export the budget of every client
+        let weak_client_budgets = Arc::downgrade(&instance.client_budgets);
+        get_metrics().add_update_callback(Arc::new(move |metrics: &Metrics| {
+            let Some(client_budgets) = weak_client_budgets.upgrade() else {
+                return;
+            };
+            if let Err(e) = update_budget_metrics(metrics, &client_budgets.read().unwrap()) {
+                error!("Failed to update the budget metrics: {}", e);
+            }
+        }));
+
         // Store the instance in the StreamManager
         stream_manager.set_webrtc_egress(instance.clone());
     }
@@ -178,6 +254,169 @@ impl WebRTCEgress {
         }
     }
 
+    pub fn add_data_channel(&self, client_id: String, data_channel: Arc<RTCDataChannel>) {
+        info!("New WebRTC point cloud data channel created for client: {}", client_id);
+        let sender = DataChannelPointCloudSender::new(data_channel, DataChannelConfig::default().chunk_size);
+        self.data_channels.write().unwrap().insert(client_id, Arc::new(sender));
+    }
+
+    pub fn remove_data_channel(&self, client_id: &str) {
+        self.data_channels.write().unwrap().remove(client_id);
+    }
+
+    /// The lowest estimated available bitrate of the connected clients, as all of them receive the same frames.
+    #[allow(dead_code)]
+    pub fn bandwidth_estimate(&self) -> Option<BandwidthEstimate> {
+        self.bandwidth_estimates.read().unwrap().values()
+            .min_by_key(|estimate| estimate.bitrate)
+            .copied()
+    }
+
+    /// Enables or disables the per client budget.
+    /// While enabled, every client has its own RTP sequence numbers, so frames can be skipped for a single client.
+    #[instrument(skip_all)]
+    pub fn set_adaptive_budget(&self, enabled: bool) {
+        self.adaptive_budget.store(enabled, Ordering::Relaxed);
+        for track in self.tracks.read().unwrap().values() {
+            track.set_per_peer_delivery(enabled);
+        }
+    }
+
+    /// Sets the quality layers the adaptive budget picks from, formatted as `rid:max_number_of_points,...`, e.g. `high:100000,low:20000`.
+    /// An empty value disables the layers, the clients then only get a lower frame rate when their bitrate is too low.
+    #[instrument(skip_all)]
+    pub fn set_quality_layers(&self, value: &str) -> XrResult<()> {
+        let layers = parse_ladder(value)?;
+        // The layers are encoded from the same combined point cloud as the full quality frame
+        self.aggregator.set_keep_combined(!layers.is_empty());
+        *self.quality_layers.write().unwrap() = layers;
+        Ok(())
+    }
+
+    /// Recalculates the budget of a client after a new estimate of its available bitrate.
+    fn update_client_budget(&self, client_id: &str, bitrate: u64) {
+        let max_number_of_points = self.max_number_of_points();
+        let fps = *self.fps.lock().unwrap();
+        // The frames are assumed to be at the full budget, their size per point is what a point costs
+        let bits_per_point = *self.average_frame_bits.lock().unwrap() / max_number_of_points.max(1) as f64;
+        let budget = self.budget_config.budget(bitrate, bits_per_point, max_number_of_points, fps);
+
+        let mut client_budgets = self.client_budgets.write().unwrap();
+        let state = client_budgets.entry(client_id.to_string()).or_insert(ClientBudgetState {
+            budget,
+            ssrc: None,
+            credit: 0.0,
+        });
+        if state.budget != budget {
+            debug!("Budget of client {} changed to {} points at {} fps", client_id, budget.max_number_of_points, budget.fps);
+            state.budget = budget;
+        }
+    }
+
+    fn set_client_ssrc(&self, client_id: &str, ssrc: u32) {
+        let full = ClientBudget {
+            max_number_of_points: self.max_number_of_points(),
+            fps: *self.fps.lock().unwrap(),
+        };
+        self.client_budgets.write().unwrap()
+            .entry(client_id.to_string())
+            .or_insert(ClientBudgetState { budget: full, ssrc: None, credit: 0.0 })
+            .ssrc = Some(ssrc);
+    }
+
+    /// Encodes the combined point cloud of the frame again, with the number of points of a lower quality layer.
+    fn encode_layer_frame(&self, frame: &FrameTaskData, layer: &QualityLayer) -> Option<FrameTaskData> {
+        let Some(point_cloud) = self.aggregator.combined_point_cloud(frame.presentation_time) else {
+            debug!("Combined point cloud of frame {} is no longer kept, skipping quality layer {}", frame.presentation_time, layer.rid);
+            return None;
+        };
+        let (_, data) = encode_ladder(&self.processing_pipeline, &point_cloud, self.encoding_format(), std::slice::from_ref(layer)).pop()?;
+        Some(FrameTaskData {
+            data: self.aggregator.stream_manager().encrypt_payload(COMBINED_STREAM_ID, data)?,
+            ..frame.clone()
+        })
+    }
+
+    /// Sends the frame to the clients of which the budget allows another frame, in the quality layer that fits their budget.
+    /// Pre-encoded frames of the SFU path can not be encoded again, so for those only the frame rate is adapted.
+    fn emit_adaptive_frame_data(&self, frame: FrameTaskData, track: Option<Arc<TrackLocalPointCloudRTP>>) {
+        let fps = (*self.fps.lock().unwrap()).max(1);
+        let max_number_of_points = self.max_number_of_points();
+        {
+            let bits = frame.data.len() as f64 * 8.0;
+            let mut average_frame_bits = self.average_frame_bits.lock().unwrap();
+            *average_frame_bits = if *average_frame_bits == 0.0 { bits } else { *average_frame_bits * 0.9 + bits * 0.1 };
+        }
+
+        let layers = if frame.sfu_client_id.is_none() {
+            self.quality_layers.read().unwrap().clone()
+        } else {
+            vec![]
+        };
+
+        // The clients that receive this frame, grouped by the index of their quality layer
+        let mut receivers: BTreeMap<usize, Vec<(String, Option<u32>)>> = BTreeMap::new();
+        {
+            let client_ids: Vec<String> = self.peer_connections.read().unwrap().keys().cloned().collect();
+            let mut client_budgets = self.client_budgets.write().unwrap();
+            for client_id in client_ids {
+                let state = client_budgets.entry(client_id.clone()).or_insert(ClientBudgetState {
+                    budget: ClientBudget { max_number_of_points, fps },
+                    ssrc: None,
+                    credit: 0.0,
+                });
+                state.credit = (state.credit + state.budget.fps.min(fps) as f64 / fps as f64).min(1.0);
+                if state.credit < 1.0 {
+                    continue;
+                }
+                state.credit -= 1.0;
+
+                let layer = layers.iter()
+                    .position(|layer| layer.max_number_of_points <= state.budget.max_number_of_points)
+                    .unwrap_or(layers.len().saturating_sub(1));
+                receivers.entry(layer).or_default().push((client_id, state.ssrc));
+            }
+        }
+
+        let mut writes = vec![];
+        for (layer, clients) in receivers {
+            let layer_frame = match layers.get(layer) {
+                Some(quality_layer) if quality_layer.max_number_of_points < max_number_of_points => {
+                    match self.encode_layer_frame(&frame, quality_layer) {
+                        Some(layer_frame) => layer_frame,
+                        None => continue,
+                    }
+                }
+                _ => frame.clone(),
+            };
+            let data_channels: Vec<Arc<DataChannelPointCloudSender>> = {
+                let data_channels = self.data_channels.read().unwrap();
+                clients.iter()
+                    .filter_map(|(client_id, _)| data_channels.get(client_id).cloned())
+                    .filter(|data_channel| data_channel.is_open())
+                    .collect()
+            };
+            let ssrcs: Vec<u32> = clients.iter().filter_map(|(_, ssrc)| *ssrc).collect();
+            writes.push((layer as u32, layer_frame, ssrcs, data_channels));
+        }
+
+        let runtime = self.get_runtime();
+        runtime.block_on(async move {
+            for (quality_nr, layer_frame, ssrcs, data_channels) in writes {
+                if let Some(track) = track.as_ref().filter(|_| !ssrcs.is_empty()) {
+                    if let Err(e) = track.write_frame_to_peers(&layer_frame, quality_nr, &ssrcs).await {
+                        error!("Failed to write frame: {}", e);
+                    }
+                }
+                for data_channel in data_channels {
+                    if let Err(e) = data_channel.write_frame(&layer_frame).await {
+                        error!("Failed to write frame: {}", e);
+                    }
+                }
+            }
+        });
+    }
+
     // Remove all tracks with the given track id
     pub fn remove_track(&self, track_id: &str) {
         let mut tracks = self.tracks.write().unwrap();
@@ -207,6 +446,7 @@ impl WebRTCEgress {
                 fps,
             )
         );
+        new_track.set_per_peer_delivery(self.adaptive_budget.load(Ordering::Relaxed));
 
         let mut tracks = self.tracks.write().unwrap();
         tracks.insert(track_id.to_string(), new_track.clone());
@@ -230,6 +470,12 @@ impl WebRTCEgress {
         }
 
         self.remove_rtp_senders_for_client(client_id);
+        self.remove_data_channel(client_id);
+        let had_estimate = self.bandwidth_estimates.write().unwrap().remove(client_id).is_some();
+        let had_budget = self.client_budgets.write().unwrap().remove(client_id).is_some();
+        if had_estimate || had_budget {
+            let _ = get_metrics().remove_series("client", client_id);
+        }
         self.peer_connections.write().unwrap().remove(client_id);
         self.pending_ice.write().unwrap().remove(client_id);
 
@@ -245,10 +491,10 @@ impl WebRTCEgress {
         socket_id: String,
         offer: WebRtcOffer,
         socket: SocketRef,
-    ) -> Result<(), Box<dyn std::error::Error>> {
+    ) -> XrResult<()> {
 
         // 1) Create PeerConnection
-        let pc = create_webrtc_peer_connection().await?;
+        let pc = create_webrtc_peer_connection(&self.peer_connection_config).await?;
 
         // 2) **Forward server-side ICE to client**:  
         //    Whenever the server finds a new ICE candidate,
@@ -279,39 +525,85 @@ impl WebRTCEgress {
             })
         }));
 
-        // 3) Get or reate a broadcast track
-        let track_id = "client_0_0".to_string();
-        let broadcast_track = self.get_or_create_track(&track_id.clone());
-
-        // Add the track to the PeerConnection
-        let rtp_sender = pc.add_track(broadcast_track.clone() as Arc<dyn TrackLocal + Send + Sync>).await?;
-
-
-        // Read incomming RTCP packets
-        // Before these packets are returned, they are processed by interceptors.
-        // This is required for things such as NACK and RTCP feedback.
-        let rtp_sender_clone = rtp_sender.clone();
-        tokio::spawn(async move {
-            let mut rtcp_buffer = vec![0; 1500];
-            while let Ok((_, _)) = rtp_sender_clone.read(&mut rtcp_buffer).await {}
-            Result::<_, ()>::Ok(())
-        });
-
-        // Set the handler for Peer connection state
-        // This will notify you when the peer has connected/disconnected
+        // The client either receives the frames on the DataChannel it created, or on the broadcast track
         let socket_id_clone = socket_id.clone();
-        let track_id_clone = track_id.clone();
         let self_clone = self.clone();
-        pc.on_peer_connection_state_change(Box::new(
-            move |s: RTCPeerConnectionState| {
-                info!("Peer Connection State has changed: {s}");
-                if s == RTCPeerConnectionState::Connected {
-                    // 9) Store the broadcast track to the tracks
-                    self_clone.add_rtp_sender(track_id_clone.clone(), socket_id_clone.clone(), rtp_sender.clone());
+        pc.on_data_channel(Box::new(move |data_channel: Arc<RTCDataChannel>| {
+            if data_channel.label() == POINT_CLOUD_DATA_CHANNEL_LABEL {
+                self_clone.add_data_channel(socket_id_clone.clone(), data_channel);
+            } else {
+                debug!("Ignoring data channel with label: {}", data_channel.label());
+            }
+            Box::pin(async move {})
+        }));
+
+        // Only clients that offer a video transceiver receive the broadcast track
+        if offer.sdp.contains("m=video") {
+            // 3) Get or reate a broadcast track
+            let track_id = "client_0_0".to_string();
+            let broadcast_track = self.get_or_create_track(&track_id.clone());
+
+            // Add the track to the PeerConnection
+            let rtp_sender = pc.add_track(broadcast_track.clone() as Arc<dyn TrackLocal + Send + Sync>).await?;
+
+
+            // Read incomming RTCP packets
+            // Before these packets are returned, they are processed by interceptors.
+            // This is required for things such as NACK and RTCP feedback.
+            // The track itself retransmits the packets that are reported lost by NACKs.
+            // The TWCC and REMB feedback is used to estimate the available bitrate of this client.
+            // The estimate also updates the budget of the client, which is used when the adaptive budget is enabled.
+            let bandwidth_estimator = BandwidthEstimator::new(BandwidthEstimatorConfig::default());
+            let bandwidth_estimates = self.bandwidth_estimates.clone();
+            let socket_id_clone = socket_id.clone();
+            let self_clone = self.clone();
+            bandwidth_estimator.on_estimate(Arc::new(move |estimate: BandwidthEstimate| {
+                debug!("Estimated bitrate of client {}: {} bps", socket_id_clone, estimate.bitrate);
+                bandwidth_estimates.write().unwrap().insert(socket_id_clone.clone(), estimate);
+                self_clone.update_client_budget(&socket_id_clone, estimate.bitrate);
+            }));
+            let rtp_sender_clone = rtp_sender.clone();
+            let broadcast_track_clone = broadcast_track.clone();
+            let socket_id_clone = socket_id.clone();
+            let self_clone = self.clone();
+            tokio::spawn(async move {
+                // The SSRC identifies this client when frames are written to some of the clients only
+                if let Some(encoding) = rtp_sender_clone.get_parameters().await.encodings.first() {
+                    self_clone.set_client_ssrc(&socket_id_clone, encoding.ssrc);
                 }
-                Box::pin(async move {})
-            },
-        ));
+                let mut rtcp_buffer = vec![0; 1500];
+                while let Ok((rtcp_packets, _)) = rtp_sender_clone.read(&mut rtcp_buffer).await {
+                    bandwidth_estimator.handle_rtcp(&rtcp_packets);
+                    if let Err(e) = broadcast_track_clone.handle_rtcp(&rtcp_packets).await {
+                        debug!("Failed to retransmit packets: {}", e);
+                    }
+                }
+                Result::<_, ()>::Ok(())
+            });
+
+            // Set the handler for Peer connection state
+            // This will notify you when the peer has connected/disconnected
+            let socket_id_clone = socket_id.clone();
+            let track_id_clone = track_id.clone();
+            let self_clone = self.clone();
+            pc.on_peer_connection_state_change(Box::new(
+                move |s: RTCPeerConnectionState| {
+                    info!("Peer Connection State has changed: {s}");
+                    if s == RTCPeerConnectionState::Connected {
+                        // 9) Store the broadcast track to the tracks
+                        self_clone.add_rtp_sender(track_id_clone.clone(), socket_id_clone.clone(), rtp_sender.clone());
+                    }
+                    Box::pin(async move {})
+                },
+            ));
+        } else {
+            pc.on_peer_connection_state_change(Box::new(
+                move |s: RTCPeerConnectionState| {
+                    info!("Peer Connection State has changed: {s}");
+                    Box::pin(async move {})
+                },
+            ));
+        }
 
         debug!("Created new PeerConnection for client: {} with sdp: {}", socket_id.clone(), offer.sdp);
 
@@ -319,7 +611,7 @@ impl WebRTCEgress {
         let offer_sdp = serde_json::from_str::<RTCSessionDescription>(&offer.sdp);
         if let Err(e) = offer_sdp {
             error!("Failed to create offer SDP: {}", e);
-            return Err(Box::new(e));
+            return Err(XrError::Transport(format!("Invalid offer SDP: {}", e)));
         }
         let offer_sdp = offer_sdp.unwrap();
         pc.set_remote_description(offer_sdp).await.unwrap();
@@ -380,12 +672,37 @@ impl WebRTCEgress {
         Ok(())
     }
 
+    /// Switch a client to another quality layer of the tracks it receives, without renegotiation.
+    #[instrument(skip_all)]
+    pub async fn select_quality_layer(&self, client_id: &str, rid: &str) -> XrResult<()> {
+        let senders = {
+            let rtp_senders = self.rtp_senders.read().unwrap();
+            let tracks = self.tracks.read().unwrap();
+            rtp_senders.iter()
+                .filter_map(|(track_id, track_senders)| Some((tracks.get(track_id)?.clone(), track_senders.get(client_id)?.clone())))
+                .collect::<Vec<_>>()
+        };
+        if senders.is_empty() {
+            return Err(XrError::Config(format!("No WebRTC track found for client: {}", client_id)));
+        }
+
+        for (track, rtp_sender) in senders {
+            let parameters = rtp_sender.get_parameters().await;
+            let Some(encoding) = parameters.encodings.first() else {
+                return Err(XrError::Transport(format!("The RTP sender of track {} has no encodings", track.id())));
+            };
+            track.select_quality_layer(encoding.ssrc, rid)?;
+        }
+        info!("Client {} switched to quality layer {}", client_id, rid);
+        Ok(())
+    }
+
     #[instrument(skip_all)]
     pub async fn handle_client_ice_candidate(
         &self,
         socket_id: String,
         candidate: WebRtcIceCandidate
-    ) -> Result<(), Box<dyn std::error::Error>> {
+    ) -> XrResult<()> {
         debug!("Received ICE candidate from client (WS: {}): {:?}", socket_id.clone(), candidate);
         let c = RTCIceCandidateInit {
             candidate: candidate.candidate,
@@ -408,7 +725,7 @@ impl WebRTCEgress {
                 debug!("No remote description set for {}, caching ICE candidate in the meantime.", socket_id);
             } else if let Err(e) = pc.add_ice_candidate(c).await {
                 error!("Failed to add ICE candidate: {}", e);
-                return Err(Box::new(e));
+                return Err(e.into());
             }
         } else {
             let mut map = self.pending_ice.write().unwrap();
@@ -423,6 +740,11 @@ impl WebRTCEgress {
 
 
 impl EgressProtocol for WebRTCEgress {
+    #[inline]
+    fn fps(&self) -> u32 {
+        *self.fps.lock().unwrap()
+    }
+
     #[inline]
     fn encoding_format(&self) -> EncodingFormat {
         *self.encoding_format.lock().unwrap()
@@ -498,9 +820,7 @@ impl EgressProtocol for WebRTCEgress {
             presentation_time,
             raw_data, // data is moved
             bypass,
-            self.egress_metrics.bytes_to_send.clone(),
-            self.egress_metrics.frame_drops_full_egress_buffer.clone(),
-            self.egress_metrics.number_of_combined_frames.clone(),
+            &self.egress_metrics,
             client_id,
             tile_index,
         );
@@ -512,28 +832,38 @@ impl EgressProtocol for WebRTCEgress {
 
         let track_id = format!("client_{}_{}", frame.sfu_client_id.unwrap_or(0), frame.sfu_tile_index.unwrap_or(0));
 
-        let track = {
-            let tracks = self.tracks.read().unwrap();
-            tracks.get(&track_id).cloned()
-        };
-        
-        if track.is_none() {
-            debug!("No track found with id: {}", track_id);
+        if self.adaptive_budget.load(Ordering::Relaxed) {
+            let track = self.tracks.read().unwrap().get(&track_id).cloned();
+            self.emit_adaptive_frame_data(frame, track);
             return;
         }
 
-        let track = track.unwrap();
+        let mut transports: Vec<Arc<dyn FrameTransport>> = vec![];
+        if let Some(track) = self.tracks.read().unwrap().get(&track_id) {
+            transports.push(track.clone());
+        }
+        // Frames written before the data channel is open would fail
+        transports.extend(
+            self.data_channels.read().unwrap().values()
+                .filter(|data_channel| data_channel.is_open())
+                .map(|data_channel| data_channel.clone() as Arc<dyn FrameTransport>)
+        );
+
+        if transports.is_empty() {
+            debug!("No track or data channel found for: {}", track_id);
+            return;
+        }
 
-        // debug!("Sending frame to {} tracks", selected_tracks.len());
+        // debug!("Sending frame to {} transports", transports.len());
 
-        // Send the frame to all data channels
+        // Send the frame to the track and all data channels
         let runtime = self.get_runtime();
-        let frame_clone = frame.clone();
-        let track_clone = track.clone();
         runtime.block_on(async move {
-            let result = track_clone.write_frame(&frame_clone).await;
-            if let Err(e) = result {
-                error!("Failed to write frame to track: {}", e);
+            for transport in transports {
+                let result = transport.write_frame(&frame).await;
+                if let Err(e) = result {
+                    error!("Failed to write frame: {}", e);
+                }
             }
         });
         //debug!("Frame sent to all tracks");
@@ -551,4 +881,72 @@ impl EgressProtocol for WebRTCEgress {
         *self.max_number_of_points.lock().unwrap() = max_number_of_points;
     }
 
-}
\ No newline at end of file
+    fn set_frame_buffer_capacity(&self, capacity: usize) {
+        let dropped = self.frame_buffer.lock().unwrap().set_capacity(capacity);
+        self.egress_metrics.record_buffer_drops(&dropped);
+    }
+
+    fn flush_and_close(&self, timeout: Duration) {
+        drain_frame_buffer("WRTC_E", &self.frame_buffer, timeout);
+
+        // Close the connections before returning, `close_peer_connection` only spawns the close
+        let peer_connections: Vec<(String, Arc<RTCPeerConnection>)> = self.peer_connections.read().unwrap()
+            .iter()
+            .map(|(client_id, peer_connection)| (client_id.clone(), peer_connection.clone()))
+            .collect();
+        for (client_id, peer_connection) in peer_connections {
+            info!("Closing the peer connection of client {}", client_id);
+            if let Err(e) = self.get_runtime().block_on(peer_connection.close()) {
+                error!("Failed to close the peer connection of client {}: {}", client_id, e);
+            }
+            self.close_peer_connection(&client_id);
+        }
+    }
+}
+
+/// Export the retransmission counters of the tracks, labeled with their track id.
+fn update_retransmission_metrics(metrics: &Metrics, tracks: &HashMap<String, Arc<TrackLocalPointCloudRTP>>) -> Result<(), String> {
+    let nacks_received = metrics.get_or_create_counter_vec("webrtc_nacks_received", "Number of NACK feedback packets received for a WebRTC track", &["track"])?;
+    let packets_requested = metrics.get_or_create_counter_vec("webrtc_packets_requested", "Number of lost RTP packets reported in the NACKs of a WebRTC track", &["track"])?;
+    let packets_retransmitted = metrics.get_or_create_counter_vec("webrtc_packets_retransmitted", "Number of lost RTP packets of a WebRTC track that were sent again", &["track"])?;
+    let packets_unavailable = metrics.get_or_create_counter_vec("webrtc_packets_unavailable", "Number of lost RTP packets of a WebRTC track that were no longer available for retransmission", &["track"])?;
+
+    for (track_id, track) in tracks {
+        let stats = track.retransmission_stats();
+        // The stats of the track are totals, so only add the difference with the exported counter
+        for (counter_vec, value) in [
+            (&nacks_received, stats.nacks_received),
+            (&packets_requested, stats.packets_requested),
+            (&packets_retransmitted, stats.packets_retransmitted),
+            (&packets_unavailable, stats.packets_unavailable),
+        ] {
+            let counter = counter_vec.with_label_values(&[track_id.as_str()]);
+            counter.inc_by(value.saturating_sub(counter.get()));
+        }
+    }
+    Ok(())
+}
+
+/// Export the point and frame rate budget of the clients, labeled with their socket id.
+fn update_budget_metrics(metrics: &Metrics, client_budgets: &HashMap<String, ClientBudgetState>) -> Result<(), String> {
+    let point_budget = metrics.get_or_create_gauge_vec("webrtc_client_point_budget", "Maximum number of points a WebRTC client receives per frame", &["client"])?;
+    let fps_budget = metrics.get_or_create_gauge_vec("webrtc_client_fps_budget", "Number of frames per second a WebRTC client receives", &["client"])?;
+
+    for (client_id, state) in client_budgets {
+        point_budget.with_label_values(&[client_id.as_str()]).set(state.budget.max_number_of_points as i64);
+        fps_budget.with_label_values(&[client_id.as_str()]).set(state.budget.fps as i64);
+    }
+    Ok(())
+}
+
+/// Export the estimated available bitrate and loss of the clients, labeled with their socket id.
+fn update_bandwidth_metrics(metrics: &Metrics, bandwidth_estimates: &HashMap<String, BandwidthEstimate>) -> Result<(), String> {
+    let estimated_bitrate = metrics.get_or_create_gauge_vec("webrtc_estimated_bitrate", "Estimated available bitrate of a WebRTC client in bits per second", &["client"])?;
+    let estimated_loss = metrics.get_or_create_gauge_vec("webrtc_estimated_loss_permille", "Packet loss of a WebRTC client in the last estimate, in permille", &["client"])?;
+
+    for (client_id, estimate) in bandwidth_estimates {
+        estimated_bitrate.with_label_values(&[client_id.as_str()]).set(estimate.bitrate as i64);
+        estimated_loss.with_label_values(&[client_id.as_str()]).set((estimate.loss_fraction * 1000.0).round() as i64);
+    }
+    Ok(())
+}
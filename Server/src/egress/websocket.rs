@@ -7,23 +7,34 @@ use std::time::{Duration, SystemTime, UNIX_EPOCH};
 use crate::encoders::EncodingFormat;
 use crate::processing::{aggregator::PointCloudAggregator, ProcessingPipeline};
 use crate::services::stream_manager::StreamManager;
+use crate::types::EgressProtocolType;
+use shared_utils::frame_compression::FrameCompression;
 use shared_utils::types::{FrameTaskData, PointCloudData};
+use shared_utils::wire::encode_frame;
 
-use circular_buffer::CircularBuffer;
+use super::ring_buffer::RingBuffer;
 use serde_json::Value;
 use tokio::runtime::{self, Runtime};
 use tracing::{debug, error, instrument};
 use bytes::Bytes;
-use rbase64;
 
-use super::egress_common::{push_preencoded_frame_data, EgressCommonMetrics, EgressProtocol};
+use super::egress_common::{drain_frame_buffer, push_preencoded_frame_data, EgressCommonMetrics, EgressProtocol};
+
+/// Returns the room of the clients that receive the frames with `compression`.
+pub fn compression_room(compression: FrameCompression) -> &'static str {
+    match compression {
+        FrameCompression::None => "broadcast",
+        FrameCompression::Deflate => "broadcast:deflate",
+        FrameCompression::Zstd => "broadcast:zstd",
+    }
+}
 
 /// WebSocket Egress module responsible for sending frames over WebSocket connections.
 #[derive(Clone, Debug)]
 pub struct WebSocketEgress {
     stream_manager: Arc<StreamManager>,
     processing_pipeline: Arc<ProcessingPipeline>,
-    frame_buffer: Arc<Mutex<CircularBuffer<10, FrameTaskData>>>,
+    frame_buffer: Arc<Mutex<RingBuffer<FrameTaskData>>>,
     aggregator: Arc<PointCloudAggregator>,
     threads_started: Arc<AtomicBool>,
     fps: Arc<Mutex<u32>>,
@@ -48,7 +59,7 @@ impl WebSocketEgress {
         let instance = Arc::new(Self {
             stream_manager: stream_manager.clone(),
             processing_pipeline: processing_pipeline.clone(),
-            frame_buffer: Arc::new(Mutex::new(CircularBuffer::new())),
+            frame_buffer: Arc::new(Mutex::new(RingBuffer::new(stream_manager.frame_buffer_capacity(&EgressProtocolType::WebSocket)))),
             aggregator: aggregator.clone(),
             threads_started: Arc::new(AtomicBool::new(false)),
             fps: Arc::new(Mutex::new(30)),
@@ -56,7 +67,7 @@ impl WebSocketEgress {
             max_number_of_points: Arc::new(Mutex::new(100000)),
             emit_with_ack: Arc::new(Mutex::new(true)),
             runtime: Arc::new(Mutex::new(runtime)),
-            egress_metrics: Arc::new(EgressCommonMetrics::new()),
+            egress_metrics: Arc::new(EgressCommonMetrics::new("WS_E")),
         });
 
         // Store the instance in the StreamManager
@@ -72,6 +83,11 @@ impl WebSocketEgress {
 
 
 impl EgressProtocol for WebSocketEgress {
+    #[inline]
+    fn fps(&self) -> u32 {
+        *self.fps.lock().unwrap()
+    }
+
     #[inline]
     fn encoding_format(&self) -> EncodingFormat {
         *self.encoding_format.lock().unwrap()
@@ -146,9 +162,7 @@ impl EgressProtocol for WebSocketEgress {
             presentation_time,
             raw_data, // data is moved
             bypass,
-            self.egress_metrics.bytes_to_send.clone(),
-            self.egress_metrics.frame_drops_full_egress_buffer.clone(),
-            self.egress_metrics.number_of_combined_frames.clone(),
+            &self.egress_metrics,
             client_id,
             tile_index,
         );
@@ -172,14 +186,33 @@ impl EgressProtocol for WebSocketEgress {
             }
         };
 
-        // Convert to base64 bytes using the bitcode and rbase64 crates
-        let bytes: Bytes = {
-            let bytes_vec: Vec<u8> = bitcode::encode(&frame);
-            let base64_encoded: String = rbase64::encode(&bytes_vec);
-            Bytes::from(base64_encoded)
-        };
-        debug!("Bytes created");
-        debug!("Encoded frame to {} bytes", bytes.len());
+        // The frames are sent as binary attachments, every client room receives them with the compression it asked for
+        let encoded_frame: Vec<u8> = encode_frame(&frame);
+        let rooms: Vec<(&'static str, Bytes)> = FrameCompression::ALL
+            .into_iter()
+            .filter(|compression| {
+                io.to(compression_room(*compression))
+                    .sockets()
+                    .map(|sockets| !sockets.is_empty())
+                    .unwrap_or(false)
+            })
+            .filter_map(|compression| match compression.compress(&encoded_frame) {
+                Ok(compressed) => Some((compression_room(compression), Bytes::from(compressed))),
+                Err(e) => {
+                    error!("Failed to compress frame: {}", e);
+                    None
+                }
+            })
+            .collect();
+
+        // Check that at least one client is connected
+        if rooms.is_empty() {
+            debug!("No clients connected to emit frame");
+            return;
+        }
+        for (room, bytes) in &rooms {
+            debug!("Encoded frame to {} bytes for room {}", bytes.len(), room);
+        }
 
         if emit_with_ack {
             // Calculate the difference between the send time and the presentation time
@@ -196,12 +229,6 @@ impl EgressProtocol for WebSocketEgress {
                 timeout
             );
 
-            // Check that at least one client is connected
-            if io.sockets().unwrap().is_empty() {
-                debug!("No clients connected to emit frame");
-                return;
-            }
-
             // Check if the runtime already exists
             let mut runtime_guard = self.runtime.lock().unwrap();
             if runtime_guard.is_none() {
@@ -212,23 +239,29 @@ impl EgressProtocol for WebSocketEgress {
                 }).enable_all().build().unwrap());
             }
             runtime_guard.as_ref().unwrap().block_on(async {
-
-                match io
-                    .to("broadcast")
-                    .timeout(timeout)
-                    .emit_with_ack::<Bytes, Value>(
-                        "frame:broadcast:ack",
-                        &bytes,
-                    ) {
-                    Ok(ack_stream) => match ack_stream.await {
+                // Emit to all rooms first, then wait for the acknowledgments of all of them
+                let mut ack_streams = Vec::with_capacity(rooms.len());
+                for (room, bytes) in &rooms {
+                    match io
+                        .to(*room)
+                        .timeout(timeout)
+                        .emit_with_ack::<Bytes, Value>(
+                            "frame:broadcast:ack",
+                            bytes,
+                        ) {
+                        Ok(ack_stream) => ack_streams.push(ack_stream),
+                        Err(err) => {
+                            error!("Socket error during emit with ack: {:?}", err);
+                        }
+                    }
+                }
+                for ack_stream in ack_streams {
+                    match ack_stream.await {
                         Ok(_) => debug!(
                             "Ack received for frame with presentation time: {}",
                             frame.presentation_time
                         ),
                         Err(err) => error!("Ack error: {:?}", err),
-                    },
-                    Err(err) => {
-                        error!("Socket error during emit with ack: {:?}", err);
                     }
                 }
             });
@@ -236,15 +269,17 @@ impl EgressProtocol for WebSocketEgress {
             debug!("Emitting frame without acknowledgment");
 
             // Emit the frame without acknowledgment
-            match io.to("broadcast").emit::<Bytes>(
-                "frame:broadcast",
-                &bytes,
-            ) {
-                Ok(_) => debug!(
-                    "Frame emitted without acknowledgment with presentation time: {}",
-                    frame.presentation_time
-                ),
-                Err(err) => error!("Socket error during emit without ack: {:?}", err),
+            for (room, bytes) in &rooms {
+                match io.to(*room).emit::<Bytes>(
+                    "frame:broadcast",
+                    bytes,
+                ) {
+                    Ok(_) => debug!(
+                        "Frame emitted without acknowledgment with presentation time: {}",
+                        frame.presentation_time
+                    ),
+                    Err(err) => error!("Socket error during emit without ack: {:?}", err),
+                }
             }
         }
     }
@@ -260,4 +295,18 @@ impl EgressProtocol for WebSocketEgress {
     fn set_max_number_of_points(&self, max_number_of_points: u64) {
         *self.max_number_of_points.lock().unwrap() = max_number_of_points;
     }
+
+    fn set_frame_buffer_capacity(&self, capacity: usize) {
+        let dropped = self.frame_buffer.lock().unwrap().set_capacity(capacity);
+        self.egress_metrics.record_buffer_drops(&dropped);
+    }
+
+    fn flush_and_close(&self, timeout: Duration) {
+        drain_frame_buffer("WS_E", &self.frame_buffer, timeout);
+        if let Some(io) = self.stream_manager.get_socket_io() {
+            for socket in io.sockets().unwrap_or_default() {
+                let _ = socket.disconnect();
+            }
+        }
+    }
 }
\ No newline at end of file
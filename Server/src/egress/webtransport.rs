@@ -1,25 +1,210 @@
-// egress/protocols/webtransport.rs
-// (WebTransport supports prioritization)
+// egress/webtransport.rs
+
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, OnceLock, RwLock};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
 use crate::encoders::EncodingFormat;
-use crate::protocols::traits::EgressProtocol;
-use webtransport::session::WebTransportSession;
-use std::sync::Arc;
+use crate::processing::{aggregator::PointCloudAggregator, ProcessingPipeline};
+use crate::services::stream_manager::StreamManager;
+use crate::types::EgressProtocolType;
+use shared_utils::error::{XrError, XrResult};
+use shared_utils::types::{FrameTaskData, PointCloudData};
+use shared_utils::wire::encode_frame;
+
+use super::ring_buffer::RingBuffer;
+use futures::future::join_all;
+use tokio::runtime::{self, Runtime};
+use tracing::{debug, error, info, instrument};
+use wtransport::{Connection, Endpoint, Identity, ServerConfig, VarInt};
+
+use super::egress_common::{drain_frame_buffer, push_preencoded_frame_data, EgressCommonMetrics, EgressProtocol};
 
-use super::egress_common::EgressProtocol;
+static WEBTRANSPORT_RUNTIME: OnceLock<Arc<Runtime>> = OnceLock::new();
 
-#[derive(Clone, Debug)]
+/// WebTransport Egress module responsible for sending frames over HTTP/3 WebTransport sessions,
+/// so browsers can receive the point clouds without WebRTC signaling.
+#[derive(Clone)]
 pub struct WebTransportEgress {
-    session: Arc<WebTransportSession>,
-    priority: u8,
+    processing_pipeline: Arc<ProcessingPipeline>,
+    frame_buffer: Arc<Mutex<RingBuffer<FrameTaskData>>>,
+    aggregator: Arc<PointCloudAggregator>,
+    threads_started: Arc<AtomicBool>,
+    fps: Arc<Mutex<u32>>,
+    encoding_format: Arc<Mutex<EncodingFormat>>,
+    max_number_of_points: Arc<Mutex<u64>>,
+    /// Send the frames that fit in a single datagram as a datagram instead of on a unidirectional stream
+    use_datagrams: Arc<Mutex<bool>>,
+    /// The map of all connected WebTransport sessions: stable connection id -> session
+    sessions: Arc<RwLock<HashMap<usize, Arc<Connection>>>>,
+    /// Base64 encoded SHA-256 hash of the self-signed certificate, browsers need it in `serverCertificateHashes`
+    certificate_hash: String,
+    egress_metrics: Arc<EgressCommonMetrics>,
+}
+
+impl fmt::Debug for WebTransportEgress {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("WebTransportEgress")
+            .field("sessions", &self.sessions.read().unwrap().len())
+            .field("processing_pipeline", &self.processing_pipeline)
+            .field("frame_buffer", &self.frame_buffer)
+            .field("aggregator", &self.aggregator)
+            .field("fps", &self.fps)
+            .field("encoding_format", &self.encoding_format)
+            .field("max_number_of_points", &self.max_number_of_points)
+            .field("use_datagrams", &self.use_datagrams)
+            .field("egress_metrics", &self.egress_metrics)
+            .finish()
+    }
 }
 
 impl WebTransportEgress {
-    pub fn new(session: Arc<WebTransportSession>) -> Self {
-        Self { session, priority: 0 }
+    /// Initializes the WebTransport Egress module, and starts accepting sessions on `port`.
+    #[instrument(skip_all)]
+    pub fn initialize(
+        stream_manager: Arc<StreamManager>,
+        processing_pipeline: Arc<ProcessingPipeline>,
+        port: u16,
+    ) {
+        // Browsers only accept a self-signed certificate that is valid for at most 14 days, which is what wtransport generates
+        let identity = match Identity::self_signed(["localhost", "127.0.0.1", "::1"]) {
+            Ok(identity) => identity,
+            Err(e) => {
+                error!("Failed to generate the WebTransport certificate: {}", e);
+                return;
+            }
+        };
+        let certificate_digest = identity.certificate_chain().as_slice()[0].hash();
+        let certificate_bytes: &[u8; 32] = certificate_digest.as_ref();
+        let certificate_hash = rbase64::encode(certificate_bytes);
+
+        let aggregator = Arc::new(PointCloudAggregator::new(stream_manager.clone()));
+
+        let instance = Arc::new(Self {
+            processing_pipeline: processing_pipeline.clone(),
+            frame_buffer: Arc::new(Mutex::new(RingBuffer::new(stream_manager.frame_buffer_capacity(&EgressProtocolType::WebTransport)))),
+            aggregator: aggregator.clone(),
+            threads_started: Arc::new(AtomicBool::new(false)),
+            fps: Arc::new(Mutex::new(30)),
+            encoding_format: Arc::new(Mutex::new(EncodingFormat::Draco)),
+            max_number_of_points: Arc::new(Mutex::new(100000)),
+            use_datagrams: Arc::new(Mutex::new(false)),
+            sessions: Arc::new(RwLock::new(HashMap::new())),
+            certificate_hash,
+            egress_metrics: Arc::new(EgressCommonMetrics::new("WT_E")),
+        });
+
+        let config = ServerConfig::builder()
+            .with_bind_default(port)
+            .with_identity(identity)
+            .keep_alive_interval(Some(Duration::from_secs(3)))
+            .build();
+
+        // The endpoint has to be created inside the runtime
+        let instance_clone = instance.clone();
+        instance.get_runtime().spawn(async move {
+            if let Err(e) = instance_clone.accept_sessions(config).await {
+                error!("WebTransport egress stopped: {}", e);
+            }
+        });
+
+        info!("WebTransport egress listening on port {} with certificate hash {}", port, instance.certificate_hash);
+
+        // Store the instance in the StreamManager
+        stream_manager.set_webtransport_egress(instance.clone());
+    }
+
+    #[instrument(skip_all)]
+    pub fn get_runtime(&self) -> Arc<Runtime> {
+        WEBTRANSPORT_RUNTIME.get_or_init(|| {
+            let rt = runtime::Builder::new_multi_thread()
+                .worker_threads(2)
+                .thread_name_fn(|| {
+                    static ATOMIC_WT_ID: std::sync::atomic::AtomicUsize = std::sync::atomic::AtomicUsize::new(0);
+                    let id = ATOMIC_WT_ID.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+                    format!("WT_R w-{}", id)
+                })
+                .enable_all()
+                .build().unwrap();
+            Arc::new(rt)
+        }).clone()
+    }
+
+    /// Base64 encoded SHA-256 hash of the certificate of the WebTransport endpoint.
+    pub fn certificate_hash(&self) -> &str {
+        &self.certificate_hash
+    }
+
+    /// Sets whether the frames that fit in a single datagram are sent as a datagram.
+    #[instrument(skip_all)]
+    pub fn set_use_datagrams(&self, use_datagrams: bool) {
+        *self.use_datagrams.lock().unwrap() = use_datagrams;
+    }
+
+    /// Accepts the incoming sessions, until the endpoint fails.
+    async fn accept_sessions(&self, config: ServerConfig) -> XrResult<()> {
+        let endpoint = Endpoint::server(config)
+            .map_err(|e| XrError::Transport(format!("Failed to create the WebTransport endpoint: {e}")))?;
+
+        loop {
+            let incoming_session = endpoint.accept().await;
+            let sessions = self.sessions.clone();
+            tokio::spawn(async move {
+                let session_request = match incoming_session.await {
+                    Ok(session_request) => session_request,
+                    Err(e) => {
+                        error!("Failed to receive the WebTransport session request: {}", e);
+                        return;
+                    }
+                };
+                debug!("WebTransport session requested for path: {}", session_request.path());
+
+                let connection = match session_request.accept().await {
+                    Ok(connection) => Arc::new(connection),
+                    Err(e) => {
+                        error!("Failed to accept the WebTransport session: {}", e);
+                        return;
+                    }
+                };
+                let session_id = connection.stable_id();
+                info!("WebTransport session {} connected from {}", session_id, connection.remote_address());
+                sessions.write().unwrap().insert(session_id, connection.clone());
+
+                // Keep the session until the client closes it or times out
+                let reason = connection.closed().await;
+                info!("WebTransport session {} closed: {}", session_id, reason);
+                sessions.write().unwrap().remove(&session_id);
+            });
+        }
+    }
+
+    /// Sends the encoded frame to a single session, on its own unidirectional stream or as a datagram.
+    async fn send_to_session(connection: Arc<Connection>, bytes: Arc<Vec<u8>>, use_datagrams: bool) -> XrResult<()> {
+        if use_datagrams && connection.max_datagram_size().is_some_and(|max_size| bytes.len() <= max_size) {
+            return connection.send_datagram(bytes.as_slice())
+                .map_err(|e| XrError::Transport(format!("Failed to send the datagram: {e}")));
+        }
+
+        // A stream per frame, so a lost packet only delays the frame it belongs to
+        let mut stream = connection.open_uni().await
+            .map_err(|e| XrError::Transport(format!("Failed to request a stream: {e}")))?
+            .await
+            .map_err(|e| XrError::Transport(format!("Failed to open the stream: {e}")))?;
+        stream.write_all(&bytes).await
+            .map_err(|e| XrError::Transport(format!("Failed to write the frame: {e}")))?;
+        stream.finish().await
+            .map_err(|e| XrError::Transport(format!("Failed to finish the stream: {e}")))
     }
 }
 
 impl EgressProtocol for WebTransportEgress {
+    #[inline]
+    fn fps(&self) -> u32 {
+        *self.fps.lock().unwrap()
+    }
+
     #[inline]
     fn encoding_format(&self) -> EncodingFormat {
         *self.encoding_format.lock().unwrap()
@@ -29,4 +214,125 @@ impl EgressProtocol for WebTransportEgress {
     fn max_number_of_points(&self) -> u64 {
         *self.max_number_of_points.lock().unwrap()
     }
-}
\ No newline at end of file
+
+    fn ensure_threads_started(&self) {
+        let already_started = self.threads_started.load(Ordering::Relaxed);
+        if already_started {
+            return;
+        }
+
+        // Set the threads as started
+        self.threads_started.store(true, Ordering::Relaxed);
+
+        // Start background threads using the common module
+        crate::egress::egress_common::start_generator_thread(
+            "WT_E".to_string(),
+            self.processing_pipeline.clone(),
+            self.aggregator.clone(),
+            self.frame_buffer.clone(),
+            self.fps.clone(),
+            self.encoding_format.clone(),
+            self.max_number_of_points.clone(),
+        );
+
+        let self_clone = self.clone();
+        crate::egress::egress_common::start_transmission_thread(
+            "WT_E".to_string(),
+            self.frame_buffer.clone(),
+            move |frame| {
+                self_clone.emit_frame_data(frame);
+            },
+            false,
+        );
+    }
+
+    fn push_point_cloud(&self, point_cloud: PointCloudData, stream_id: String) {
+        self.ensure_threads_started();
+        self.aggregator.update_point_cloud(stream_id, point_cloud);
+    }
+
+    // Process and sends a frame, this raw version bypasses the aggregation
+    fn push_encoded_frame(&self, raw_data: Vec<u8>, _stream_id: String, mut creation_time: u64, presentation_time: u64, ring_buffer_bypass: bool, client_id: Option<u64>, tile_index: Option<u32>) {
+        // Ensure the threads are started
+        self.ensure_threads_started();
+
+        let self_clone = self.clone();
+        let bypass = if ring_buffer_bypass {
+
+            let since_the_epoch = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .expect("Time went backwards");
+            creation_time = since_the_epoch.as_micros() as u64;
+
+            Some(Box::new(move |frame| {
+                self_clone.emit_frame_data(frame);
+            }) as Box<dyn Fn(FrameTaskData) + Send + 'static>)
+        } else {
+            None
+        };
+
+        push_preencoded_frame_data(
+            "WT_E",
+            &self.frame_buffer,
+            creation_time,
+            presentation_time,
+            raw_data, // data is moved
+            bypass,
+            &self.egress_metrics,
+            client_id,
+            tile_index,
+        );
+    }
+
+    /// Emits frame data to all connected WebTransport sessions.
+    fn emit_frame_data(&self, frame: FrameTaskData) {
+        debug!("Emitting frame with presentation time: {}", frame.presentation_time);
+
+        let sessions: Vec<Arc<Connection>> = self.sessions.read().unwrap().values().cloned().collect();
+        if sessions.is_empty() {
+            debug!("No WebTransport sessions connected to emit frame");
+            return;
+        }
+
+        let bytes = Arc::new(encode_frame(&frame));
+        let use_datagrams = *self.use_datagrams.lock().unwrap();
+
+        // Send the frame to all sessions at the same time, so a slow session does not delay the others
+        let runtime = self.get_runtime();
+        runtime.block_on(async move {
+            let results = join_all(sessions.into_iter().map(|connection| {
+                Self::send_to_session(connection, bytes.clone(), use_datagrams)
+            })).await;
+            for result in results {
+                if let Err(e) = result {
+                    error!("Failed to write frame: {}", e);
+                }
+            }
+        });
+    }
+
+    fn set_fps(&self, fps: u32) {
+        *self.fps.lock().unwrap() = fps;
+    }
+
+    fn set_encoding_format(&self, encoding_format: EncodingFormat) {
+        *self.encoding_format.lock().unwrap() = encoding_format;
+    }
+
+    fn set_max_number_of_points(&self, max_number_of_points: u64) {
+        *self.max_number_of_points.lock().unwrap() = max_number_of_points;
+    }
+
+    fn set_frame_buffer_capacity(&self, capacity: usize) {
+        let dropped = self.frame_buffer.lock().unwrap().set_capacity(capacity);
+        self.egress_metrics.record_buffer_drops(&dropped);
+    }
+
+    fn flush_and_close(&self, timeout: Duration) {
+        drain_frame_buffer("WT_E", &self.frame_buffer, timeout);
+        for (session_id, connection) in self.sessions.write().unwrap().drain() {
+            info!("Closing WebTransport session {}", session_id);
+            connection.close(VarInt::from_u32(0), b"server shutdown");
+        }
+    }
+}
@@ -0,0 +1,346 @@
+// egress/zenoh.rs
+
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::encoders::EncodingFormat;
+use crate::processing::{aggregator::PointCloudAggregator, ProcessingPipeline};
+use crate::services::stream_manager::StreamManager;
+use crate::types::EgressProtocolType;
+use shared_utils::error::{XrError, XrResult};
+use shared_utils::types::{FrameTaskData, PointCloudData};
+use shared_utils::wire::encode_frame;
+
+use super::ring_buffer::RingBuffer;
+use tokio::runtime::{self, Runtime};
+use tracing::{debug, error, info, instrument};
+use ::zenoh::pubsub::Publisher;
+use ::zenoh::qos::CongestionControl;
+use ::zenoh::Session;
+
+use super::egress_common::{drain_frame_buffer, push_preencoded_frame_data, EgressCommonMetrics, EgressProtocol};
+
+static ZENOH_RUNTIME: OnceLock<Arc<Runtime>> = OnceLock::new();
+
+/// Settings of the Zenoh egress.
+#[derive(Clone, Debug)]
+pub struct ZenohConfig {
+    pub enabled: bool,
+    /// Frames of a stream are published on `<key_prefix>/client_<id>/tile_<index>`
+    pub key_prefix: String,
+    /// "peer" or "client"
+    pub mode: String,
+    /// Endpoints to connect to, e.g. tcp/192.168.1.10:7447, scouting is used when empty
+    pub connect: Vec<String>,
+    /// Endpoints to listen on
+    pub listen: Vec<String>,
+}
+
+/// Zenoh Egress module responsible for publishing frames on Zenoh key expressions.
+/// Every stream (client and tile) is published on its own key expression.
+#[derive(Clone)]
+pub struct ZenohEgress {
+    processing_pipeline: Arc<ProcessingPipeline>,
+    frame_buffer: Arc<Mutex<RingBuffer<FrameTaskData>>>,
+    aggregator: Arc<PointCloudAggregator>,
+    threads_started: Arc<AtomicBool>,
+    fps: Arc<Mutex<u32>>,
+    encoding_format: Arc<Mutex<EncodingFormat>>,
+    max_number_of_points: Arc<Mutex<u64>>,
+    session: Session,
+    key_prefix: String,
+    /// Drop frames when the network cannot keep up, or block the transmission thread until it can
+    congestion_control: Arc<Mutex<CongestionControl>>,
+    /// Send frames without waiting to batch them with other messages
+    express: Arc<Mutex<bool>>,
+    publishers: Arc<tokio::sync::Mutex<HashMap<String, Publisher<'static>>>>,
+    egress_metrics: Arc<EgressCommonMetrics>,
+}
+
+impl std::fmt::Debug for ZenohEgress {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ZenohEgress")
+            .field("key_prefix", &self.key_prefix)
+            .field("congestion_control", &self.congestion_control)
+            .field("express", &self.express)
+            .field("processing_pipeline", &self.processing_pipeline)
+            .field("frame_buffer", &self.frame_buffer)
+            .field("aggregator", &self.aggregator)
+            .field("fps", &self.fps)
+            .field("encoding_format", &self.encoding_format)
+            .field("max_number_of_points", &self.max_number_of_points)
+            .field("egress_metrics", &self.egress_metrics)
+            .finish()
+    }
+}
+
+impl ZenohEgress {
+    /// Initializes the Zenoh Egress module, and opens the Zenoh session.
+    #[instrument(skip_all)]
+    pub fn initialize(
+        stream_manager: Arc<StreamManager>,
+        processing_pipeline: Arc<ProcessingPipeline>,
+        config: ZenohConfig,
+    ) {
+        if !config.enabled {
+            info!("The Zenoh egress is disabled");
+            return;
+        }
+
+        let session = match Self::runtime().block_on(Self::open_session(&config)) {
+            Ok(session) => session,
+            Err(e) => {
+                error!("Failed to initialize the Zenoh egress: {}", e);
+                return;
+            }
+        };
+
+        let aggregator = Arc::new(PointCloudAggregator::new(stream_manager.clone()));
+
+        let instance = Arc::new(Self {
+            processing_pipeline: processing_pipeline.clone(),
+            frame_buffer: Arc::new(Mutex::new(RingBuffer::new(stream_manager.frame_buffer_capacity(&EgressProtocolType::Zenoh)))),
+            aggregator: aggregator.clone(),
+            threads_started: Arc::new(AtomicBool::new(false)),
+            fps: Arc::new(Mutex::new(30)),
+            encoding_format: Arc::new(Mutex::new(EncodingFormat::Draco)),
+            max_number_of_points: Arc::new(Mutex::new(100000)),
+            session,
+            key_prefix: config.key_prefix.trim_end_matches('/').to_string(),
+            congestion_control: Arc::new(Mutex::new(CongestionControl::Drop)),
+            express: Arc::new(Mutex::new(true)),
+            publishers: Arc::new(tokio::sync::Mutex::new(HashMap::new())),
+            egress_metrics: Arc::new(EgressCommonMetrics::new("ZNH_E")),
+        });
+
+        info!("Zenoh egress publishing under {}", instance.key_prefix);
+
+        // Store the instance in the StreamManager
+        stream_manager.set_zenoh_egress(instance.clone());
+    }
+
+    fn runtime() -> Arc<Runtime> {
+        ZENOH_RUNTIME.get_or_init(|| {
+            let rt = runtime::Builder::new_multi_thread()
+                .worker_threads(2)
+                .thread_name_fn(|| {
+                    static ATOMIC_ZENOH_ID: std::sync::atomic::AtomicUsize = std::sync::atomic::AtomicUsize::new(0);
+                    let id = ATOMIC_ZENOH_ID.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+                    format!("ZNH_R w-{}", id)
+                })
+                .enable_all()
+                .build().unwrap();
+            Arc::new(rt)
+        }).clone()
+    }
+
+    async fn open_session(config: &ZenohConfig) -> XrResult<Session> {
+        open_session(&config.mode, &config.connect, &config.listen).await
+    }
+
+    /// Sets whether frames are dropped ("drop") or the sender waits ("block") when the network is congested.
+    #[instrument(skip_all)]
+    pub fn set_congestion_control(&self, congestion_control: &str) -> XrResult<()> {
+        let congestion_control = match congestion_control.to_lowercase().as_str() {
+            "drop" => CongestionControl::Drop,
+            "block" => CongestionControl::Block,
+            other => return Err(XrError::Config(format!("Unknown Zenoh congestion control {other}, expected drop or block"))),
+        };
+        *self.congestion_control.lock().unwrap() = congestion_control;
+        self.clear_publishers();
+        Ok(())
+    }
+
+    /// Sets whether frames are sent immediately instead of being batched.
+    #[instrument(skip_all)]
+    pub fn set_express(&self, express: bool) {
+        *self.express.lock().unwrap() = express;
+        self.clear_publishers();
+    }
+
+    /// The publishers are declared with the settings of that moment, so they are redeclared on the next frame.
+    fn clear_publishers(&self) {
+        let publishers = self.publishers.clone();
+        Self::runtime().spawn(async move {
+            publishers.lock().await.clear();
+        });
+    }
+
+    fn key_expr(&self, frame: &FrameTaskData) -> String {
+        format!(
+            "{}/client_{}/tile_{}",
+            self.key_prefix,
+            frame.sfu_client_id.unwrap_or(0),
+            frame.sfu_tile_index.unwrap_or(0)
+        )
+    }
+}
+
+/// Opens a Zenoh session in `mode` ("peer" or "client"), scouting is used when there are no endpoints to connect to.
+/// The Zenoh ingresses open their own session with this as well.
+pub(crate) async fn open_session(mode: &str, connect: &[String], listen: &[String]) -> XrResult<Session> {
+    let mut zenoh_config = ::zenoh::Config::default();
+    let settings = [
+        ("mode", serde_json::to_string(mode)),
+        ("connect/endpoints", serde_json::to_string(connect)),
+        ("listen/endpoints", serde_json::to_string(listen)),
+    ];
+    for (key, value) in settings {
+        let value = value.map_err(|e| XrError::Config(format!("Invalid Zenoh {key}: {e}")))?;
+        zenoh_config.insert_json5(key, &value)
+            .map_err(|e| XrError::Config(format!("Invalid Zenoh {key} {value}: {e}")))?;
+    }
+    ::zenoh::open(zenoh_config).await
+        .map_err(|e| XrError::Transport(format!("Failed to open Zenoh session: {e}")))
+}
+
+impl EgressProtocol for ZenohEgress {
+    #[inline]
+    fn fps(&self) -> u32 {
+        *self.fps.lock().unwrap()
+    }
+
+    #[inline]
+    fn encoding_format(&self) -> EncodingFormat {
+        *self.encoding_format.lock().unwrap()
+    }
+
+    #[inline]
+    fn max_number_of_points(&self) -> u64 {
+        *self.max_number_of_points.lock().unwrap()
+    }
+
+    fn ensure_threads_started(&self) {
+        let already_started = self.threads_started.load(Ordering::Relaxed);
+        if already_started {
+            return;
+        }
+
+        // Set the threads as started
+        self.threads_started.store(true, Ordering::Relaxed);
+
+        // Start background threads using the common module
+        crate::egress::egress_common::start_generator_thread(
+            "ZNH_E".to_string(),
+            self.processing_pipeline.clone(),
+            self.aggregator.clone(),
+            self.frame_buffer.clone(),
+            self.fps.clone(),
+            self.encoding_format.clone(),
+            self.max_number_of_points.clone(),
+        );
+
+        let self_clone = self.clone();
+        crate::egress::egress_common::start_transmission_thread(
+            "ZNH_E".to_string(),
+            self.frame_buffer.clone(),
+            move |frame| {
+                self_clone.emit_frame_data(frame);
+            },
+            false,
+        );
+    }
+
+    fn push_point_cloud(&self, point_cloud: PointCloudData, stream_id: String) {
+        self.ensure_threads_started();
+        self.aggregator.update_point_cloud(stream_id, point_cloud);
+    }
+
+    // Process and sends a frame, this raw version bypasses the aggregation
+    fn push_encoded_frame(&self, raw_data: Vec<u8>, _stream_id: String, mut creation_time: u64, presentation_time: u64, ring_buffer_bypass: bool, client_id: Option<u64>, tile_index: Option<u32>) {
+        // Ensure the threads are started
+        self.ensure_threads_started();
+
+        let self_clone = self.clone();
+        let bypass = if ring_buffer_bypass {
+
+            let since_the_epoch = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .expect("Time went backwards");
+            creation_time = since_the_epoch.as_micros() as u64;
+
+            Some(Box::new(move |frame| {
+                self_clone.emit_frame_data(frame);
+            }) as Box<dyn Fn(FrameTaskData) + Send + 'static>)
+        } else {
+            None
+        };
+
+        push_preencoded_frame_data(
+            "ZNH_E",
+            &self.frame_buffer,
+            creation_time,
+            presentation_time,
+            raw_data, // data is moved
+            bypass,
+            &self.egress_metrics,
+            client_id,
+            tile_index,
+        );
+    }
+
+    /// Publishes the frame on the key expression of its stream, the publisher is declared on the first frame.
+    fn emit_frame_data(&self, frame: FrameTaskData) {
+        debug!("Emitting frame with presentation time: {}", frame.presentation_time);
+
+        let key_expr = self.key_expr(&frame);
+        let payload = encode_frame(&frame);
+        let congestion_control = *self.congestion_control.lock().unwrap();
+        let express = *self.express.lock().unwrap();
+        let session = self.session.clone();
+        let publishers = self.publishers.clone();
+
+        Self::runtime().block_on(async move {
+            let mut publishers = publishers.lock().await;
+            if !publishers.contains_key(&key_expr) {
+                let publisher = session
+                    .declare_publisher(key_expr.clone())
+                    .congestion_control(congestion_control)
+                    .express(express)
+                    .await;
+                match publisher {
+                    Ok(publisher) => {
+                        info!("Declared Zenoh publisher on {}", key_expr);
+                        publishers.insert(key_expr.clone(), publisher);
+                    }
+                    Err(e) => {
+                        error!("Failed to declare Zenoh publisher on {}: {}", key_expr, e);
+                        return;
+                    }
+                }
+            }
+
+            if let Err(e) = publishers[&key_expr].put(payload).await {
+                error!("Failed to publish frame on {}: {}", key_expr, e);
+            }
+        });
+    }
+
+    fn set_fps(&self, fps: u32) {
+        *self.fps.lock().unwrap() = fps;
+    }
+
+    fn set_encoding_format(&self, encoding_format: EncodingFormat) {
+        *self.encoding_format.lock().unwrap() = encoding_format;
+    }
+
+    fn set_max_number_of_points(&self, max_number_of_points: u64) {
+        *self.max_number_of_points.lock().unwrap() = max_number_of_points;
+    }
+
+    fn set_frame_buffer_capacity(&self, capacity: usize) {
+        let dropped = self.frame_buffer.lock().unwrap().set_capacity(capacity);
+        self.egress_metrics.record_buffer_drops(&dropped);
+    }
+
+    fn flush_and_close(&self, timeout: Duration) {
+        drain_frame_buffer("ZNH_E", &self.frame_buffer, timeout);
+        // Undeclaring the publishers lets the subscribers know that the streams ended
+        let publishers = self.publishers.clone();
+        Self::runtime().block_on(async move {
+            publishers.lock().await.clear();
+        });
+    }
+}
@@ -1,16 +1,28 @@
 use bitcode::{Decode, Encode, encode as bt_encode};
 use tracing::{debug, instrument};
 
-use shared_utils::types::{Point3D, PointCloudData};
+use shared_utils::error::{XrError, XrResult};
+use shared_utils::types::{Point3D, PointAttributes, PointCloudData, POINT_CLOUD_SCHEMA_VERSION};
 
 #[derive(Encode, Decode)]
 pub struct BitcodeData {
     pub points: Vec<Point3D>,
 }
 
+/// Used instead of `BitcodeData` when the points have attributes, identified by `BC2`.
+#[derive(Encode, Decode)]
+pub struct BitcodeDataV2 {
+    pub schema_version: u32,
+    pub points: Vec<Point3D>,
+    pub attributes: PointAttributes,
+}
+
 #[instrument(skip_all)]
-pub fn encode_bitcode(point_cloud: PointCloudData) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
-   
+pub fn encode_bitcode(point_cloud: PointCloudData) -> XrResult<Vec<u8>> {
+    if !point_cloud.attributes.is_empty() {
+        return encode_bitcode_v2(point_cloud);
+    }
+
     let bitcode_data = BitcodeData {
         points: point_cloud.points,
     };
@@ -30,3 +42,25 @@ pub fn encode_bitcode(point_cloud: PointCloudData) -> Result<Vec<u8>, Box<dyn st
     Ok(encoded)
 }
 
+/// Encode the points together with their attributes.
+/// Kept separate from `BC1`, so receivers that do not know the attributes can still decode frames without them.
+#[instrument(skip_all)]
+fn encode_bitcode_v2(point_cloud: PointCloudData) -> XrResult<Vec<u8>> {
+    point_cloud.attributes.validate(point_cloud.points.len()).map_err(XrError::Codec)?;
+
+    let bitcode_data = BitcodeDataV2 {
+        schema_version: POINT_CLOUD_SCHEMA_VERSION,
+        points: point_cloud.points,
+        attributes: point_cloud.attributes,
+    };
+
+    debug!("Bitcode data will have {} vertices with attributes", bitcode_data.points.len());
+
+    let bitcode_raw = bt_encode(&bitcode_data);
+
+    let mut encoded = Vec::with_capacity(3 + bitcode_raw.len());
+    encoded.extend_from_slice(b"BC2");
+    encoded.extend_from_slice(&bitcode_raw);
+
+    Ok(encoded)
+}
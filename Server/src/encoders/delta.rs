@@ -0,0 +1,48 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, OnceLock};
+
+use tracing::instrument;
+
+use shared_utils::delta::{DeltaConfig, DeltaEncoder};
+use shared_utils::error::XrResult;
+use shared_utils::types::PointCloudData;
+
+static DELTA_CONFIG: OnceLock<DeltaConfig> = OnceLock::new();
+
+/// Sets the settings of the delta encoders, the defaults are used otherwise.
+pub fn set_delta_config(config: DeltaConfig) -> XrResult<()> {
+    config.validate()?;
+    let _ = DELTA_CONFIG.set(config);
+    Ok(())
+}
+
+fn delta_config() -> DeltaConfig {
+    *DELTA_CONFIG.get_or_init(DeltaConfig::default)
+}
+
+/// Encodes a frame on its own, as a keyframe, for the callers that do not keep the keyframes of the streams.
+#[instrument(skip_all)]
+pub fn encode_delta_keyframe(point_cloud: PointCloudData) -> XrResult<Vec<u8>> {
+    DeltaEncoder::new(delta_config()).encode(&point_cloud.points)
+}
+
+/// The delta encoder of every stream of every egress, as the receivers of each egress hold their own keyframes.
+#[derive(Debug, Default)]
+pub struct DeltaEncoders {
+    encoders: Mutex<HashMap<String, Arc<Mutex<DeltaEncoder>>>>,
+}
+
+impl DeltaEncoders {
+    /// Encodes the frame as a delta against the last keyframe of the encoder with the given key, or as a new keyframe.
+    #[instrument(skip_all)]
+    pub fn encode(&self, key: &str, point_cloud: &PointCloudData) -> XrResult<Vec<u8>> {
+        let encoder = self.encoders
+            .lock()
+            .unwrap()
+            .entry(key.to_string())
+            .or_insert_with(|| Arc::new(Mutex::new(DeltaEncoder::new(delta_config()))))
+            .clone();
+        let mut encoder = encoder.lock().unwrap();
+        encoder.encode(&point_cloud.points)
+    }
+}
@@ -1,10 +1,11 @@
 use draco_wrapper::encode_draco as DW_encode;
 use tracing::instrument;
 
+use shared_utils::error::{XrError, XrResult};
 use shared_utils::types::PointCloudData;
 
 #[instrument(skip_all)]
-pub fn encode_draco(point_cloud: PointCloudData) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+pub fn encode_draco(point_cloud: PointCloudData) -> XrResult<Vec<u8>> {
     // Use draco compression library
 
     // Convert PointCloudData to Vec<f32> and Vec<u8>
@@ -21,8 +22,8 @@ pub fn encode_draco(point_cloud: PointCloudData) -> Result<Vec<u8>, Box<dyn std:
         colors_rgb.push(point.b);
     }
 
-    let compressed_data = DW_encode(vertices, colors_rgb)?;
+    let compressed_data = DW_encode(vertices, colors_rgb).map_err(XrError::codec)?;
 
-    Ok(compressed_data)
+    Ok(compressed_data.into())
 }
 
@@ -0,0 +1,158 @@
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::sync::OnceLock;
+
+use serde::{Deserialize, Serialize};
+use tracing::{debug, instrument};
+use uuid::Uuid;
+
+use shared_utils::error::{XrError, XrResult};
+use shared_utils::types::PointCloudData;
+
+/// Magic bytes of a G-PCC frame, followed by the header and the G-PCC bitstream.
+/// The bitstream itself has no magic bytes, so decoders could not tell it apart from the other encodings without them
+pub(crate) const GPCC_MAGIC: &[u8; 3] = b"GPC";
+pub(crate) const GPCC_HEADER_VERSION: u8 = 1;
+/// Magic (3 bytes), version (1 byte), position step (f32 LE) and origin (3 x f32 LE)
+pub(crate) const GPCC_HEADER_SIZE: usize = 3 + 1 + 4 + 3 * 4;
+
+static TMC3_PATH: OnceLock<PathBuf> = OnceLock::new();
+
+/// Sets the path of the `tmc3` executable of the MPEG G-PCC reference software (TMC13), `tmc3` is looked up on the PATH otherwise.
+pub fn set_tmc3_path(path: PathBuf) {
+    let _ = TMC3_PATH.set(path);
+}
+
+fn tmc3_path() -> &'static Path {
+    TMC3_PATH.get_or_init(|| PathBuf::from("tmc3"))
+}
+
+/// Rate and quantization settings of the G-PCC encoder, set per stream.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct GpccParams {
+    /// Size (m) of the grid the positions are quantized to, the geometry is coded losslessly on that grid
+    pub position_step: f32,
+    /// Quantization parameter of the colors (4 to 51), higher values give a lower rate
+    pub attribute_qp: u8,
+}
+
+impl Default for GpccParams {
+    fn default() -> Self {
+        Self {
+            position_step: 0.001,
+            attribute_qp: 22,
+        }
+    }
+}
+
+impl GpccParams {
+    pub fn validate(&self) -> XrResult<()> {
+        if !(self.position_step.is_finite() && self.position_step > 0.0) {
+            return Err(XrError::Config(format!("The G-PCC position step has to be positive, got {}", self.position_step)));
+        }
+        if !(4..=51).contains(&self.attribute_qp) {
+            return Err(XrError::Config(format!("The G-PCC attribute QP has to be between 4 and 51, got {}", self.attribute_qp)));
+        }
+        Ok(())
+    }
+}
+
+/// Encodes a point cloud with the MPEG G-PCC reference encoder (TMC13).
+/// TMC13 has no library interface, so the `tmc3` executable encodes a PLY file of the quantized positions.
+/// Starting a process and writing files for every frame is too slow for live streams,
+/// so only the file egress accepts G-PCC, to record streams that are compared with the other encodings afterwards.
+#[instrument(skip_all)]
+pub fn encode_gpcc(point_cloud: PointCloudData, params: &GpccParams) -> XrResult<Vec<u8>> {
+    params.validate()?;
+
+    // G-PCC codes non-negative integer positions, relative to the corner of the bounding box
+    let mut origin = [f32::MAX; 3];
+    for point in &point_cloud.points {
+        origin[0] = origin[0].min(point.x);
+        origin[1] = origin[1].min(point.y);
+        origin[2] = origin[2].min(point.z);
+    }
+    if point_cloud.points.is_empty() {
+        origin = [0.0; 3];
+    }
+
+    let mut ply = Vec::with_capacity(200 + point_cloud.points.len() * 15);
+    write!(
+        ply,
+        "ply\nformat binary_little_endian 1.0\nelement vertex {}\nproperty float x\nproperty float y\nproperty float z\nproperty uchar red\nproperty uchar green\nproperty uchar blue\nend_header\n",
+        point_cloud.points.len()
+    ).map_err(XrError::codec)?;
+    for point in &point_cloud.points {
+        for (coordinate, origin) in [point.x, point.y, point.z].into_iter().zip(origin) {
+            ply.extend_from_slice(&((coordinate - origin) / params.position_step).round().to_le_bytes());
+        }
+        ply.extend_from_slice(&[point.r, point.g, point.b]);
+    }
+
+    let work_files = WorkFiles::new();
+    fs::write(&work_files.ply, &ply).map_err(XrError::codec)?;
+    run_tmc3(&[
+        "--mode=0".to_string(),
+        format!("--uncompressedDataPath={}", work_files.ply.display()),
+        format!("--compressedStreamPath={}", work_files.bitstream.display()),
+        "--positionQuantizationScale=1".to_string(),
+        "--mergeDuplicatedPoints=1".to_string(),
+        "--convertPlyColourspace=1".to_string(),
+        "--attribute=color".to_string(),
+        format!("--qp={}", params.attribute_qp),
+    ])?;
+    let bitstream = fs::read(&work_files.bitstream).map_err(XrError::codec)?;
+
+    let mut encoded = Vec::with_capacity(GPCC_HEADER_SIZE + bitstream.len());
+    encoded.extend_from_slice(GPCC_MAGIC);
+    encoded.push(GPCC_HEADER_VERSION);
+    encoded.extend_from_slice(&params.position_step.to_le_bytes());
+    for origin in origin {
+        encoded.extend_from_slice(&origin.to_le_bytes());
+    }
+    encoded.extend_from_slice(&bitstream);
+    debug!("Encoded {} points to {} G-PCC bytes", point_cloud.points.len(), encoded.len());
+    Ok(encoded)
+}
+
+/// Runs `tmc3` with the given arguments, and fails with its output when it does not succeed.
+pub(crate) fn run_tmc3(args: &[String]) -> XrResult<()> {
+    let output = Command::new(tmc3_path())
+        .args(args)
+        .output()
+        .map_err(|e| XrError::Codec(format!("Failed to run the G-PCC reference software {}: {e}", tmc3_path().display())))?;
+    if !output.status.success() {
+        return Err(XrError::Codec(format!(
+            "The G-PCC reference software failed with {}: {}",
+            output.status,
+            String::from_utf8_lossy(&output.stderr).trim()
+        )));
+    }
+    Ok(())
+}
+
+/// The files `tmc3` reads and writes, removed when the frame is done.
+pub(crate) struct WorkFiles {
+    pub ply: PathBuf,
+    pub bitstream: PathBuf,
+}
+
+impl WorkFiles {
+    pub fn new() -> Self {
+        let name = format!("pc-server-gpcc-{}", Uuid::new_v4());
+        let dir = std::env::temp_dir();
+        Self {
+            ply: dir.join(format!("{name}.ply")),
+            bitstream: dir.join(format!("{name}.bin")),
+        }
+    }
+}
+
+impl Drop for WorkFiles {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.ply);
+        let _ = fs::remove_file(&self.bitstream);
+    }
+}
@@ -0,0 +1,124 @@
+use std::io::{Cursor, Write};
+
+use byteorder::{LittleEndian, WriteBytesExt};
+use laz::{LazItemRecordBuilder, LazVlr};
+use tracing::{debug, instrument};
+
+use shared_utils::error::{XrError, XrResult};
+use shared_utils::types::PointCloudData;
+
+/// Size of a LAS 1.2 header
+pub(crate) const LAS_HEADER_SIZE: u16 = 227;
+/// Size of the header of a variable length record
+pub(crate) const VLR_HEADER_SIZE: usize = 54;
+/// Point data record format 2: xyz, intensity, classification and rgb
+pub(crate) const POINT_FORMAT: u8 = 2;
+pub(crate) const POINT_RECORD_LENGTH: u16 = 26;
+/// Bit that LASzip sets in the point data format id of compressed files
+pub(crate) const COMPRESSED_POINT_FORMAT_BIT: u8 = 0x80;
+/// User id and record id of the VLR that describes the LASzip compression
+pub(crate) const LASZIP_VLR_USER_ID: &[u8] = b"laszip encoded";
+pub(crate) const LASZIP_VLR_RECORD_ID: u16 = 22204;
+/// The points are stored as integers in units of 0.1 mm, relative to the origin
+pub(crate) const COORDINATE_SCALE: f64 = 0.0001;
+
+/// Encodes a point cloud as a LAS 1.2 file with LASzip compressed points (LAZ), which LiDAR tools such as PDAL or CloudCompare open natively.
+/// The colors are scaled to 16 bit, and the intensities of the points are kept when they have them.
+#[instrument(skip_all)]
+pub fn encode_laz(point_cloud: PointCloudData) -> XrResult<Vec<u8>> {
+    let point_count = point_cloud.points.len();
+    let point_count_u32 = u32::try_from(point_count)
+        .map_err(|_| XrError::Codec(format!("LAS 1.2 holds at most {} points, got {}", u32::MAX, point_count)))?;
+    let intensities = point_cloud.attributes.intensities.as_ref().filter(|intensities| intensities.len() == point_count);
+
+    let items = LazItemRecordBuilder::default_for_point_format_id(POINT_FORMAT, 0)
+        .map_err(|e| XrError::Codec(format!("Failed to describe the LAZ points: {e}")))?;
+    let vlr = LazVlr::from_laz_items(items);
+    let mut vlr_data = Vec::new();
+    vlr.write_to(&mut vlr_data).map_err(XrError::codec)?;
+    let vlr_data_len = u16::try_from(vlr_data.len())
+        .map_err(|_| XrError::Codec("The LASzip VLR is too large".to_string()))?;
+
+    // The uncompressed point records, and the bounds for the header
+    let mut min = [f64::MAX; 3];
+    let mut max = [f64::MIN; 3];
+    let mut records = Vec::with_capacity(point_count * POINT_RECORD_LENGTH as usize);
+    for (index, point) in point_cloud.points.iter().enumerate() {
+        for (axis, coordinate) in [point.x as f64, point.y as f64, point.z as f64].into_iter().enumerate() {
+            min[axis] = min[axis].min(coordinate);
+            max[axis] = max[axis].max(coordinate);
+            records.write_i32::<LittleEndian>((coordinate / COORDINATE_SCALE).round() as i32).map_err(XrError::codec)?;
+        }
+        let intensity = intensities.map_or(0, |intensities| intensities[index].round().clamp(0.0, u16::MAX as f32) as u16);
+        records.write_u16::<LittleEndian>(intensity).map_err(XrError::codec)?;
+        // Return number 1 of 1, unclassified, no scan angle, no user data and no point source
+        records.write_all(&[0b0000_1001, 1, 0, 0]).map_err(XrError::codec)?;
+        records.write_u16::<LittleEndian>(0).map_err(XrError::codec)?;
+        for color in [point.r, point.g, point.b] {
+            records.write_u16::<LittleEndian>(color as u16 * 257).map_err(XrError::codec)?;
+        }
+    }
+    if point_count == 0 {
+        min = [0.0; 3];
+        max = [0.0; 3];
+    }
+
+    let offset_to_points = LAS_HEADER_SIZE as u32 + VLR_HEADER_SIZE as u32 + vlr_data_len as u32;
+    let mut output = Cursor::new(Vec::with_capacity(offset_to_points as usize + records.len() / 4));
+    write_header(&mut output, offset_to_points, point_count_u32, min, max).map_err(XrError::codec)?;
+
+    // The VLR that tells readers how the points are compressed
+    output.write_u16::<LittleEndian>(0).map_err(XrError::codec)?;
+    output.write_all(&padded::<16>(LASZIP_VLR_USER_ID)).map_err(XrError::codec)?;
+    output.write_u16::<LittleEndian>(LASZIP_VLR_RECORD_ID).map_err(XrError::codec)?;
+    output.write_u16::<LittleEndian>(vlr_data_len).map_err(XrError::codec)?;
+    output.write_all(&padded::<32>(b"LASzip compression")).map_err(XrError::codec)?;
+    output.write_all(&vlr_data).map_err(XrError::codec)?;
+
+    laz::compress_buffer(&mut output, &records, vlr).map_err(XrError::codec)?;
+
+    let encoded = output.into_inner();
+    debug!("Encoded {} points to {} LAZ bytes", point_count, encoded.len());
+    Ok(encoded)
+}
+
+fn write_header(output: &mut Cursor<Vec<u8>>, offset_to_points: u32, point_count: u32, min: [f64; 3], max: [f64; 3]) -> std::io::Result<()> {
+    output.write_all(b"LASF")?;
+    // File source id, global encoding and project id
+    output.write_all(&[0u8; 2 + 2 + 16])?;
+    // Version 1.2
+    output.write_all(&[1, 2])?;
+    output.write_all(&padded::<32>(b"OTHER"))?;
+    output.write_all(&padded::<32>(b"pc-server"))?;
+    // Creation day of year and year are unknown
+    output.write_u16::<LittleEndian>(0)?;
+    output.write_u16::<LittleEndian>(0)?;
+    output.write_u16::<LittleEndian>(LAS_HEADER_SIZE)?;
+    output.write_u32::<LittleEndian>(offset_to_points)?;
+    output.write_u32::<LittleEndian>(1)?;
+    output.write_u8(POINT_FORMAT | COMPRESSED_POINT_FORMAT_BIT)?;
+    output.write_u16::<LittleEndian>(POINT_RECORD_LENGTH)?;
+    output.write_u32::<LittleEndian>(point_count)?;
+    // Number of points by return, every point is a first return
+    output.write_u32::<LittleEndian>(point_count)?;
+    output.write_all(&[0u8; 4 * 4])?;
+    for _ in 0..3 {
+        output.write_f64::<LittleEndian>(COORDINATE_SCALE)?;
+    }
+    for _ in 0..3 {
+        output.write_f64::<LittleEndian>(0.0)?;
+    }
+    for (max, min) in max.into_iter().zip(min) {
+        output.write_f64::<LittleEndian>(max)?;
+        output.write_f64::<LittleEndian>(min)?;
+    }
+    Ok(())
+}
+
+/// The fixed size, zero padded text fields of the LAS header.
+fn padded<const N: usize>(text: &[u8]) -> [u8; N] {
+    let mut field = [0u8; N];
+    let len = text.len().min(N);
+    field[..len].copy_from_slice(&text[..len]);
+    field
+}
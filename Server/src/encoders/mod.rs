@@ -1,12 +1,20 @@
 pub mod draco;
+pub mod laz;
 pub mod ply;
 pub mod tmf;
 pub mod bitcode;
+pub mod gpcc;
+pub mod vpcc;
+pub mod octree;
+pub mod delta;
 use serde::{Deserialize, Serialize};
-use tracing::instrument;
+use tracing::{debug, instrument};
 
+use shared_utils::error::XrResult;
 use shared_utils::types::PointCloudData;
 
+use gpcc::GpccParams;
+
 
 // Provide an enum to represent the different encoding formats
 #[derive(Debug, Serialize, Deserialize, PartialEq, Copy, Clone)]
@@ -15,21 +23,56 @@ pub enum EncodingFormat {
     Draco,
     LASzip,
     Tmf,
-    Bitcode
+    Bitcode,
+    /// Only for recordings of the file egress, see `gpcc::encode_gpcc`
+    Gpcc,
+    /// Experimental, the atlas is coded as H.264 video, see `vpcc::encode_vpcc`
+    Vpcc,
+    Octree,
+    /// Stateful, see `delta::DeltaEncoders`
+    Delta
+}
+
+impl EncodingFormat {
+    /// Whether the format can hold the `PointAttributes` on top of xyz + rgb.
+    pub fn supports_point_attributes(&self) -> bool {
+        matches!(self, EncodingFormat::Ply | EncodingFormat::Bitcode)
+    }
+
+    /// Whether the format is too slow to send frames live, the file egress is then the only egress that accepts it.
+    pub fn is_recording_only(&self) -> bool {
+        matches!(self, EncodingFormat::Gpcc)
+    }
 }
 
 #[instrument(skip_all)]
 pub fn encode_data(
     point_cloud: PointCloudData,
     encoding: EncodingFormat,
-) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+) -> XrResult<Vec<u8>> {
+    encode_data_with_params(point_cloud, encoding, &GpccParams::default())
+}
 
+/// Encodes with the given settings for the formats that are configurable per stream.
+#[instrument(skip_all)]
+pub fn encode_data_with_params(
+    point_cloud: PointCloudData,
+    encoding: EncodingFormat,
+    gpcc_params: &GpccParams,
+) -> XrResult<Vec<u8>> {
+    if !point_cloud.attributes.is_empty() && !encoding.supports_point_attributes() {
+        debug!("{:?} does not support point attributes, only xyz + rgb is encoded", encoding);
+    }
 
     match encoding {
         EncodingFormat::Ply => ply::encode_ply(point_cloud),
         EncodingFormat::Draco => draco::encode_draco(point_cloud),
+        EncodingFormat::LASzip => laz::encode_laz(point_cloud),
         EncodingFormat::Tmf => tmf::encode_tmf(point_cloud),
         EncodingFormat::Bitcode => bitcode::encode_bitcode(point_cloud),
-        _ => Err("Unsupported encoding format".into()),
+        EncodingFormat::Gpcc => gpcc::encode_gpcc(point_cloud, gpcc_params),
+        EncodingFormat::Vpcc => vpcc::encode_vpcc(point_cloud),
+        EncodingFormat::Octree => octree::encode_octree(point_cloud),
+        EncodingFormat::Delta => delta::encode_delta_keyframe(point_cloud),
     }
 }
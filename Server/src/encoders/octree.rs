@@ -0,0 +1,157 @@
+use std::sync::OnceLock;
+
+use tracing::{debug, instrument};
+
+use shared_utils::error::{XrError, XrResult};
+use shared_utils::types::PointCloudData;
+
+use crate::decoders::octree::parse_octree;
+
+/// Magic bytes of an octree frame, followed by the header, the occupancy bytes of every level and the colors of the leaves.
+pub(crate) const OCTREE_MAGIC: &[u8; 3] = b"OCT";
+pub(crate) const OCTREE_HEADER_VERSION: u8 = 1;
+/// Magic (3 bytes), version (1 byte), depth (1 byte), origin (3 x f32 LE) and edge of the root cube (f32 LE)
+pub(crate) const OCTREE_HEADER_SIZE: usize = 3 + 1 + 1 + 3 * 4 + 4;
+/// The Morton codes of the leaves are 64 bit, 3 bits per level
+pub(crate) const MAX_OCTREE_DEPTH: u8 = 21;
+const DEFAULT_OCTREE_DEPTH: u8 = 10;
+
+static OCTREE_DEPTH: OnceLock<u8> = OnceLock::new();
+
+/// Sets the depth of the octrees, the leaves are 2^depth times smaller than the bounding cube of the point cloud.
+pub fn set_octree_depth(depth: u8) -> XrResult<()> {
+    if !(1..=MAX_OCTREE_DEPTH).contains(&depth) {
+        return Err(XrError::Config(format!("The octree depth has to be between 1 and {MAX_OCTREE_DEPTH}, got {depth}")));
+    }
+    let _ = OCTREE_DEPTH.set(depth);
+    Ok(())
+}
+
+/// Encodes a point cloud as an octree, without any dependencies.
+/// The occupancy of the nodes is written level by level, one byte per node with a bit per child,
+/// followed by the color of every leaf, which is the average color of the points in the leaf.
+/// The first levels of the octree are a coarser version of the point cloud, see `truncate_octree`.
+#[instrument(skip_all)]
+pub fn encode_octree(point_cloud: PointCloudData) -> XrResult<Vec<u8>> {
+    let depth = *OCTREE_DEPTH.get_or_init(|| DEFAULT_OCTREE_DEPTH);
+
+    let mut min = [f32::MAX; 3];
+    let mut max = [f32::MIN; 3];
+    for point in &point_cloud.points {
+        for (axis, coordinate) in [point.x, point.y, point.z].into_iter().enumerate() {
+            min[axis] = min[axis].min(coordinate);
+            max[axis] = max[axis].max(coordinate);
+        }
+    }
+    let origin = if point_cloud.points.is_empty() { [0.0; 3] } else { min };
+    let size = (0..3).map(|axis| max[axis] - min[axis]).fold(0.0f32, f32::max).max(f32::EPSILON);
+
+    // Sorting the Morton codes of the leaves orders them as the nodes of every level
+    let cells = 1u32 << depth;
+    let mut leaves: Vec<(u64, [u8; 3])> = point_cloud.points
+        .iter()
+        .map(|point| {
+            let cell = |coordinate: f32, axis: usize| (((coordinate - origin[axis]) / size * cells as f32) as u32).min(cells - 1);
+            (morton_code([cell(point.x, 0), cell(point.y, 1), cell(point.z, 2)], depth), [point.r, point.g, point.b])
+        })
+        .collect();
+    leaves.sort_unstable_by_key(|(code, _)| *code);
+
+    let mut codes: Vec<u64> = Vec::new();
+    let mut colors: Vec<u8> = Vec::new();
+    let mut sum = [0u32; 3];
+    let mut count = 0u32;
+    for (index, (code, color)) in leaves.iter().enumerate() {
+        for (sum, value) in sum.iter_mut().zip(color) {
+            *sum += *value as u32;
+        }
+        count += 1;
+        if leaves.get(index + 1).is_none_or(|(next, _)| next != code) {
+            codes.push(*code);
+            colors.extend(sum.map(|sum| (sum / count) as u8));
+            sum = [0; 3];
+            count = 0;
+        }
+    }
+
+    let mut encoded = Vec::with_capacity(OCTREE_HEADER_SIZE + codes.len() * 5);
+    encoded.extend_from_slice(OCTREE_MAGIC);
+    encoded.push(OCTREE_HEADER_VERSION);
+    encoded.push(depth);
+    for origin in origin {
+        encoded.extend_from_slice(&origin.to_le_bytes());
+    }
+    encoded.extend_from_slice(&size.to_le_bytes());
+    if codes.is_empty() {
+        // A root without children
+        encoded.push(0);
+        return Ok(encoded);
+    }
+
+    for level in 0..depth {
+        let shift = 3 * (depth - level - 1) as u32;
+        let mut parent = codes[0] >> shift >> 3;
+        let mut occupancy = 0u8;
+        for code in &codes {
+            let child = code >> shift;
+            if child >> 3 != parent {
+                encoded.push(occupancy);
+                parent = child >> 3;
+                occupancy = 0;
+            }
+            occupancy |= 1 << (child & 7);
+        }
+        encoded.push(occupancy);
+    }
+    encoded.extend_from_slice(&colors);
+    debug!("Encoded {} points to an octree of depth {} with {} leaves in {} bytes", point_cloud.points.len(), depth, codes.len(), encoded.len());
+    Ok(encoded)
+}
+
+/// Cuts an encoded octree at the deepest level that has at most `max_number_of_points` nodes, without decoding the points.
+/// The occupancy of the remaining levels is kept as is, the colors of their leaves are the average colors of the cut leaves.
+#[instrument(skip_all)]
+pub fn truncate_octree(data: &[u8], max_number_of_points: u64) -> XrResult<Vec<u8>> {
+    let octree = parse_octree(data)?;
+    let Some(level) = (0..=octree.depth).rev().find(|level| octree.level_nodes[*level as usize] as u64 <= max_number_of_points) else {
+        return Err(XrError::Codec("The root of an octree can not be truncated".to_string()));
+    };
+    if level == octree.depth {
+        return Ok(data.to_vec());
+    }
+
+    let occupancy_len: usize = octree.level_nodes[..level as usize].iter().sum();
+    let mut truncated = Vec::with_capacity(OCTREE_HEADER_SIZE + occupancy_len + octree.level_nodes[level as usize] * 3);
+    truncated.extend_from_slice(&data[..OCTREE_HEADER_SIZE]);
+    truncated[4] = level;
+    truncated.extend_from_slice(&data[OCTREE_HEADER_SIZE..OCTREE_HEADER_SIZE + occupancy_len]);
+
+    // The leaves of a node are next to each other, as they share the prefix of their Morton codes
+    let shift = 3 * (octree.depth - level) as u32;
+    let mut index = 0;
+    while index < octree.leaves.len() {
+        let node = octree.leaves[index] >> shift;
+        let mut sum = [0u32; 3];
+        let mut count = 0u32;
+        while index < octree.leaves.len() && octree.leaves[index] >> shift == node {
+            for (sum, value) in sum.iter_mut().zip(octree.colors[index]) {
+                *sum += value as u32;
+            }
+            count += 1;
+            index += 1;
+        }
+        truncated.extend(sum.map(|sum| (sum / count) as u8));
+    }
+    Ok(truncated)
+}
+
+/// Interleaves the bits of the cell coordinates, from the most significant bit, x first.
+fn morton_code(cell: [u32; 3], depth: u8) -> u64 {
+    let mut code = 0u64;
+    for bit in (0..depth).rev() {
+        for coordinate in cell {
+            code = (code << 1) | ((coordinate >> bit) & 1) as u64;
+        }
+    }
+    code
+}
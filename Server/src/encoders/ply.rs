@@ -1,11 +1,11 @@
 use ply_rs::{ply::{DefaultElement, ElementDef, Encoding, Ply, Property, PropertyDef, PropertyType, ScalarType}, writer::Writer};
 use tracing::instrument;
 
+use shared_utils::error::{XrError, XrResult};
 use shared_utils::types::PointCloudData;
-use std::error::Error;
 
 #[instrument(skip_all)]
-pub fn encode_ply(point_cloud: PointCloudData) -> Result<Vec<u8>, Box<dyn Error>> {
+pub fn encode_ply(point_cloud: PointCloudData) -> XrResult<Vec<u8>> {
     let mut buf = Vec::<u8>::new();
 
     // Create a ply object
@@ -27,12 +27,19 @@ pub fn encode_ply(point_cloud: PointCloudData) -> Result<Vec<u8>, Box<dyn Error>
     point_element.properties.push(p);
     let p = PropertyDef::new("blue", PropertyType::Scalar(ScalarType::UChar));
     point_element.properties.push(p);
+    // Optional attributes, e.g. nx, ny, nz and intensity
+    point_cloud.attributes.validate(point_cloud.points.len()).map_err(XrError::Codec)?;
+    let attribute_names = point_cloud.attributes.scalar_property_names();
+    for name in &attribute_names {
+        let p = PropertyDef::new(name, PropertyType::Scalar(ScalarType::Float));
+        point_element.properties.push(p);
+    }
     ply.header.elements.push(point_element);
 
     let mut points = Vec::with_capacity(point_cloud.points.len());
 
     // Fill the points with DefaultElements
-    for point in &point_cloud.points {
+    for (index, point) in point_cloud.points.iter().enumerate() {
         let mut point_element = DefaultElement::new();
         point_element.insert("x".to_string(), Property::Float(point.x));
         point_element.insert("y".to_string(), Property::Float(point.y));
@@ -40,6 +47,11 @@ pub fn encode_ply(point_cloud: PointCloudData) -> Result<Vec<u8>, Box<dyn Error>
         point_element.insert("red".to_string(), Property::UChar(point.r));
         point_element.insert("green".to_string(), Property::UChar(point.g));
         point_element.insert("blue".to_string(), Property::UChar(point.b));
+        if !attribute_names.is_empty() {
+            for (name, value) in attribute_names.iter().zip(point_cloud.attributes.scalar_values(index)) {
+                point_element.insert(name.clone(), Property::Float(value));
+            }
+        }
         points.push(point_element);
     }
 
@@ -1,10 +1,11 @@
 use tracing::instrument;
 use tmf::{FloatType, TMFMesh, TMFPrecisionInfo};
 
+use shared_utils::error::{XrError, XrResult};
 use shared_utils::types::PointCloudData;
 
 #[instrument(skip_all)]
-pub fn encode_tmf(point_cloud: PointCloudData) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+pub fn encode_tmf(point_cloud: PointCloudData) -> XrResult<Vec<u8>> {
     // Convert your PointCloudData into TMFMesh
     let mut mesh = TMFMesh::empty();
 
@@ -39,7 +40,7 @@ pub fn encode_tmf(point_cloud: PointCloudData) -> Result<Vec<u8>, Box<dyn std::e
     let precision_info = TMFPrecisionInfo::default(); 
     // For a pure point cloud, the “shortest_edge” logic in tmf is not that critical,
     // but we still call `write_tmf_one(...)` with a name
-    mesh.write_tmf_one(&mut buffer, &precision_info, "pc")?;
+    mesh.write_tmf_one(&mut buffer, &precision_info, "pc").map_err(XrError::codec)?;
 
     Ok(buffer)
 
@@ -0,0 +1,291 @@
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Mutex, OnceLock};
+
+use tracing::{debug, instrument, warn};
+
+use shared_utils::error::{XrError, XrResult};
+use shared_utils::types::PointCloudData;
+
+use crate::egress::video_encoder::{create_video_encoder, I420Frame, VideoCodec, VideoEncoder, VideoEncoderConfig};
+
+/// Magic bytes of a V-PCC frame, followed by the header, the patches and the H.264 bitstream of the atlas.
+pub(crate) const VPCC_MAGIC: &[u8; 3] = b"VPC";
+pub(crate) const VPCC_HEADER_VERSION: u8 = 1;
+/// Magic (3 bytes), version (1 byte), voxel size (f32 LE), origin (3 x f32 LE), atlas columns (u16 LE) and patch count (u32 LE)
+pub(crate) const VPCC_HEADER_SIZE: usize = 3 + 1 + 4 + 3 * 4 + 2 + 4;
+/// Block index (3 x u16 LE) and projection axis (1 byte) of a patch
+pub(crate) const PATCH_INFO_SIZE: usize = 3 * 2 + 1;
+/// Edge of the blocks of voxels that are projected to a patch, and of the patches in the atlas (in pixels)
+pub(crate) const PATCH_SIZE: usize = 16;
+/// The depth d of a pixel is stored as (d + 1) * DEPTH_STEP, so the video coding errors stay below half a voxel
+pub(crate) const DEPTH_STEP: f32 = 15.0;
+/// Geometry values below the threshold are unoccupied pixels
+pub(crate) const OCCUPANCY_THRESHOLD: f32 = DEPTH_STEP / 2.0;
+
+/// The atlas is at most 3840x2048 pixels, the geometry and the texture side by side
+const MAX_ATLAS_COLUMNS: usize = 3840 / (2 * PATCH_SIZE);
+const MAX_ATLAS_ROWS: usize = 2048 / PATCH_SIZE;
+/// The atlas grows in steps of this many patches, so the frames of a stream mostly keep the resolution, and thus the encoder, of their atlas
+const ATLAS_STEP: usize = 4;
+/// Idle encoders that are kept per key, e.g. for the atlases of the quality layers
+const MAX_IDLE_ENCODERS: usize = 4;
+
+static VPCC_CONFIG: OnceLock<VpccConfig> = OnceLock::new();
+
+/// Settings of the V-PCC encoder.
+#[derive(Clone, Copy, Debug)]
+pub struct VpccConfig {
+    /// Size (m) of the voxels the points are projected from
+    pub voxel_size: f32,
+    /// Target bitrate of the atlas video at 30 frames per second, in bits per second
+    pub bitrate: u32,
+}
+
+impl Default for VpccConfig {
+    fn default() -> Self {
+        Self {
+            voxel_size: 0.002,
+            bitrate: 20_000_000,
+        }
+    }
+}
+
+/// Sets the settings of the V-PCC encoder, the defaults are used otherwise.
+pub fn set_vpcc_config(config: VpccConfig) -> XrResult<()> {
+    if !(config.voxel_size.is_finite() && config.voxel_size > 0.0) {
+        return Err(XrError::Config(format!("The V-PCC voxel size has to be positive, got {}", config.voxel_size)));
+    }
+    let _ = VPCC_CONFIG.set(config);
+    Ok(())
+}
+
+/// The axes of the atlas pixels (column, row) of a patch that is projected along the given axis.
+pub(crate) fn patch_axes(axis: usize) -> (usize, usize) {
+    match axis {
+        0 => (1, 2),
+        1 => (0, 2),
+        _ => (0, 1),
+    }
+}
+
+/// The voxels of one block, projected along the axis in which the block is thinnest.
+struct Patch {
+    block: [u16; 3],
+    axis: u8,
+    /// Depth of the nearest voxel of every pixel, None for the unoccupied pixels
+    depths: [Option<u8>; PATCH_SIZE * PATCH_SIZE],
+    colors: [[u8; 3]; PATCH_SIZE * PATCH_SIZE],
+}
+
+/// Experimental video-based encoding, along the lines of MPEG V-PCC, for dense point clouds.
+/// The points are voxelized, every occupied block of voxels is projected to a patch,
+/// and the patches are packed in a geometry atlas (the depths) and a texture atlas (the colors).
+/// Both atlases are encoded as one H.264 frame, which the buffer egress packages for DASH as any other frame.
+/// The atlas is encoded with the hardware encoder of `video_encoder::set_hardware_encoder`, or with OpenH264 without one.
+/// Every frame is intra coded, so frames can be decoded on their own.
+/// Only the nearest voxel of every pixel is kept, the voxels it hides are lost.
+///
+/// This creates a video encoder for the frame, see `VpccEncoders` to reuse the encoders of a stream.
+#[instrument(skip_all)]
+pub fn encode_vpcc(point_cloud: PointCloudData) -> XrResult<Vec<u8>> {
+    let (mut encoded, atlas) = build_atlas(&point_cloud)?;
+    if let Some(atlas) = atlas {
+        let mut encoder = create_atlas_encoder(&atlas)?;
+        encode_atlas(encoder.as_mut(), &mut encoded, &atlas)?;
+    }
+    Ok(encoded)
+}
+
+/// The atlas encoders of every stream of every egress, as creating an encoder for every frame is about as slow as encoding it.
+/// As every frame is intra coded, the frames of a key can use any idle encoder of the resolution of their atlas.
+#[derive(Default)]
+pub struct VpccEncoders {
+    /// The idle encoders of every key, with the resolution of their atlas, the least recently used first
+    encoders: Mutex<HashMap<String, VecDeque<((usize, usize), Box<dyn VideoEncoder>)>>>,
+}
+
+impl std::fmt::Debug for VpccEncoders {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("VpccEncoders").finish_non_exhaustive()
+    }
+}
+
+impl VpccEncoders {
+    /// Encodes the frame with an encoder of the given key, see `encode_vpcc`.
+    #[instrument(skip_all)]
+    pub fn encode(&self, key: &str, point_cloud: &PointCloudData) -> XrResult<Vec<u8>> {
+        let (mut encoded, atlas) = build_atlas(point_cloud)?;
+        let Some(atlas) = atlas else {
+            return Ok(encoded);
+        };
+
+        let resolution = (atlas.width, atlas.height);
+        let idle = self.encoders.lock().unwrap().get_mut(key).and_then(|idle| {
+            let index = idle.iter().position(|(idle_resolution, _)| *idle_resolution == resolution)?;
+            idle.remove(index).map(|(_, encoder)| encoder)
+        });
+        let mut encoder = match idle {
+            Some(encoder) => encoder,
+            None => create_atlas_encoder(&atlas)?,
+        };
+        let result = encode_atlas(encoder.as_mut(), &mut encoded, &atlas);
+
+        if result.is_ok() {
+            let mut encoders = self.encoders.lock().unwrap();
+            let idle = encoders.entry(key.to_string()).or_default();
+            idle.push_back((resolution, encoder));
+            if idle.len() > MAX_IDLE_ENCODERS {
+                idle.pop_front();
+            }
+        }
+        result.map(|_| encoded)
+    }
+}
+
+/// The patches of a frame, packed side by side in a BGRA image.
+struct Atlas {
+    image: Vec<u8>,
+    width: usize,
+    height: usize,
+}
+
+/// Projects the point cloud to patches, and returns the header and patches of the V-PCC frame, with the atlas of the patches.
+/// There is no atlas when the point cloud has no points.
+fn build_atlas(point_cloud: &PointCloudData) -> XrResult<(Vec<u8>, Option<Atlas>)> {
+    let config = *VPCC_CONFIG.get_or_init(VpccConfig::default);
+
+    let mut origin = [f32::MAX; 3];
+    for point in &point_cloud.points {
+        origin[0] = origin[0].min(point.x);
+        origin[1] = origin[1].min(point.y);
+        origin[2] = origin[2].min(point.z);
+    }
+    if point_cloud.points.is_empty() {
+        origin = [0.0; 3];
+    }
+
+    // Project the voxels of every block
+    let mut blocks: HashMap<[u16; 3], Vec<([usize; 3], [u8; 3])>> = HashMap::new();
+    for point in &point_cloud.points {
+        let mut voxel = [0usize; 3];
+        for (axis, coordinate) in [point.x, point.y, point.z].into_iter().enumerate() {
+            voxel[axis] = ((coordinate - origin[axis]) / config.voxel_size) as usize;
+        }
+        let block = voxel.map(|coordinate| coordinate / PATCH_SIZE);
+        if block.iter().any(|index| *index > u16::MAX as usize) {
+            return Err(XrError::Codec(format!("The point cloud is too large for V-PCC with voxels of {} m", config.voxel_size)));
+        }
+        let local = voxel.map(|coordinate| coordinate % PATCH_SIZE);
+        blocks.entry(block.map(|index| index as u16)).or_default().push((local, [point.r, point.g, point.b]));
+    }
+
+    let mut block_indices: Vec<[u16; 3]> = blocks.keys().copied().collect();
+    // Neighbouring blocks end up next to each other in the atlas, which the video encoder predicts better
+    block_indices.sort_unstable_by_key(|block| [block[2], block[1], block[0]]);
+    let mut patches: Vec<Patch> = block_indices.into_iter().map(|block| project_block(block, &blocks[&block])).collect();
+
+    let columns = ((patches.len() as f64).sqrt().ceil() as usize).next_multiple_of(ATLAS_STEP).clamp(ATLAS_STEP, MAX_ATLAS_COLUMNS);
+    let rows = patches.len().div_ceil(columns).next_multiple_of(ATLAS_STEP).clamp(ATLAS_STEP, MAX_ATLAS_ROWS);
+    if patches.len() > columns * rows {
+        warn!("The atlas holds {} patches, {} patches of the point cloud are dropped", columns * rows, patches.len() - columns * rows);
+        patches.truncate(columns * rows);
+    }
+
+    let mut encoded = Vec::with_capacity(VPCC_HEADER_SIZE + patches.len() * PATCH_INFO_SIZE);
+    encoded.extend_from_slice(VPCC_MAGIC);
+    encoded.push(VPCC_HEADER_VERSION);
+    encoded.extend_from_slice(&config.voxel_size.to_le_bytes());
+    for origin in origin {
+        encoded.extend_from_slice(&origin.to_le_bytes());
+    }
+    encoded.extend_from_slice(&(columns as u16).to_le_bytes());
+    encoded.extend_from_slice(&(patches.len() as u32).to_le_bytes());
+    for patch in &patches {
+        for index in patch.block {
+            encoded.extend_from_slice(&index.to_le_bytes());
+        }
+        encoded.push(patch.axis);
+    }
+    if patches.is_empty() {
+        return Ok((encoded, None));
+    }
+
+    // The geometry atlas is the left half of the frame, the texture atlas the right half
+    let width = columns * PATCH_SIZE * 2;
+    let height = rows * PATCH_SIZE;
+    let mut image = vec![0u8; width * height * 4];
+    for (index, patch) in patches.iter().enumerate() {
+        let left = (index % columns) * PATCH_SIZE;
+        let top = (index / columns) * PATCH_SIZE;
+        // Unoccupied texture pixels get the average color of the patch, so they do not add edges to the video
+        let occupied: Vec<usize> = (0..PATCH_SIZE * PATCH_SIZE).filter(|pixel| patch.depths[*pixel].is_some()).collect();
+        let mut average = [0usize; 3];
+        for pixel in &occupied {
+            for (channel, sum) in average.iter_mut().enumerate() {
+                *sum += patch.colors[*pixel][channel] as usize;
+            }
+        }
+        let average = average.map(|sum| (sum / occupied.len().max(1)) as u8);
+
+        for pixel in 0..PATCH_SIZE * PATCH_SIZE {
+            let row = top + pixel / PATCH_SIZE;
+            let column = left + pixel % PATCH_SIZE;
+            let geometry = patch.depths[pixel].map_or(0, |depth| ((depth as f32 + 1.0) * DEPTH_STEP) as u8);
+            let [r, g, b] = if patch.depths[pixel].is_some() { patch.colors[pixel] } else { average };
+            let geometry_offset = (row * width + column) * 4;
+            image[geometry_offset..geometry_offset + 4].copy_from_slice(&[geometry, geometry, geometry, 255]);
+            let texture_offset = (row * width + width / 2 + column) * 4;
+            image[texture_offset..texture_offset + 4].copy_from_slice(&[b, g, r, 255]);
+        }
+    }
+    debug!("Projected {} points to {} patches in a {}x{} atlas", point_cloud.points.len(), patches.len(), width, height);
+    Ok((encoded, Some(Atlas { image, width, height })))
+}
+
+fn create_atlas_encoder(atlas: &Atlas) -> XrResult<Box<dyn VideoEncoder>> {
+    let config = *VPCC_CONFIG.get_or_init(VpccConfig::default);
+    create_video_encoder(&VideoEncoderConfig {
+        codec: VideoCodec::H264,
+        width: atlas.width as u32,
+        height: atlas.height as u32,
+        fps: 30,
+        bitrate: config.bitrate,
+    })
+}
+
+/// Encodes the atlas as an intra coded frame, after the header and patches of the V-PCC frame.
+fn encode_atlas(encoder: &mut dyn VideoEncoder, encoded: &mut Vec<u8>, atlas: &Atlas) -> XrResult<()> {
+    let bitstream = encoder.encode(&I420Frame::from_bgra(&atlas.image, atlas.width, atlas.height), true)?;
+    if bitstream.is_empty() {
+        return Err(XrError::Codec("The video encoder did not return the V-PCC atlas".to_string()));
+    }
+    encoded.extend_from_slice(&bitstream);
+    Ok(())
+}
+
+/// Projects the voxels of a block along the axis in which the voxels spread the least.
+fn project_block(block: [u16; 3], voxels: &[([usize; 3], [u8; 3])]) -> Patch {
+    let extent = |axis: usize| {
+        let min = voxels.iter().map(|(local, _)| local[axis]).min().unwrap_or(0);
+        let max = voxels.iter().map(|(local, _)| local[axis]).max().unwrap_or(0);
+        max - min
+    };
+    let axis = (0..3).min_by_key(|axis| extent(*axis)).unwrap_or(2);
+    let (column_axis, row_axis) = patch_axes(axis);
+
+    let mut patch = Patch {
+        block,
+        axis: axis as u8,
+        depths: [None; PATCH_SIZE * PATCH_SIZE],
+        colors: [[0; 3]; PATCH_SIZE * PATCH_SIZE],
+    };
+    for (local, color) in voxels {
+        let pixel = local[row_axis] * PATCH_SIZE + local[column_axis];
+        let depth = local[axis] as u8;
+        if patch.depths[pixel].is_none_or(|nearest| depth < nearest) {
+            patch.depths[pixel] = Some(depth);
+            patch.colors[pixel] = *color;
+        }
+    }
+    patch
+}
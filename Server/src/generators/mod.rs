@@ -4,7 +4,7 @@ use rayon::iter::{IntoParallelIterator, ParallelIterator};
 use serde::{Deserialize, Serialize};
 use tracing::instrument;
 
-use shared_utils::types::{Point3D, PointCloudData};
+use shared_utils::types::{Point3D, PointAttributes, PointCloudData};
 use std::time::{SystemTime, UNIX_EPOCH};
 
 // For vector/quaternion math
@@ -68,6 +68,7 @@ pub fn generate_basic_point_cloud() -> PointCloudData {
 
     PointCloudData {
         points,
+        attributes: PointAttributes::default(),
         creation_time: current_time_us,
         presentation_time: current_time_us,
         error_count: 0,
@@ -227,6 +228,7 @@ pub fn generate_shaded_cube_point_cloud(
     // Wrap up in your PointCloudData
     PointCloudData {
         points,
+        attributes: PointAttributes::default(),
         creation_time: current_time_us,
         presentation_time: current_time_us,
         error_count: 0,
@@ -1,9 +1,10 @@
 // handlers/dash.rs
 
-use std::{fs, path::PathBuf, time::Duration};
+use std::{fs, path::PathBuf, sync::Arc, time::Duration};
 
 use axum::{extract::{Path, State}, response::{IntoResponse, Response}, http::StatusCode};
-use crate::types::AppState;
+use futures::{stream, StreamExt};
+use crate::{egress::buffer::BufferEgress, types::AppState};
 use tracing::{debug, error, instrument};
 
 #[instrument(skip_all)]
@@ -24,8 +25,7 @@ pub async fn fetch_dash_segment(
     };
 
     if segment_name == "init.mp4" {
-        if let Some(config) = egress.get_stream_config(&stream_id) {
-            let init_segment = mp4_box::writer::create_init_segment(&config);
+        if let Some(init_segment) = egress.get_init_segment(&stream_id) {
             return Response::builder()
                 .status(StatusCode::OK)
                 .header("Content-Type", "video/mp4")
@@ -38,6 +38,13 @@ pub async fn fetch_dash_segment(
     }
 
     if let Some(index_str) = segment_name.strip_suffix(".m4s").or_else(|| segment_name.strip_suffix(".mp4")) {
+        let chunks_per_segment = egress.dash_chunks_per_segment();
+        if chunks_per_segment > 1 {
+            if let Ok(segment_number) = index_str.parse::<u64>() {
+                return fetch_chunked_segment(egress, stream_id, segment_number, chunks_per_segment).await;
+            }
+        }
+
         let start_time = std::time::Instant::now();
 
         if let Ok(index) = index_str.parse::<u64>() {
@@ -63,6 +70,41 @@ pub async fn fetch_dash_segment(
     StatusCode::BAD_REQUEST.into_response()
 }
 
+/// Serve a segment that consists of multiple CMAF chunks.
+/// The response uses chunked transfer encoding, each chunk is sent as soon as its frame has been buffered.
+async fn fetch_chunked_segment(
+    egress: Arc<BufferEgress>,
+    stream_id: String,
+    segment_number: u64,
+    chunks_per_segment: u64,
+) -> Response {
+    let first_index = segment_number * chunks_per_segment;
+    let Some(first_chunk) = egress.get_frame(&stream_id, first_index, Duration::from_millis(500)).await else {
+        error!("Frame index {} not found in buffer", first_index);
+        return StatusCode::NOT_FOUND.into_response();
+    };
+
+    let next_indices = first_index + 1..first_index + chunks_per_segment;
+    let remaining_chunks = stream::unfold((egress, stream_id, next_indices), |(egress, stream_id, mut indices)| async move {
+        let index = indices.next()?;
+        match egress.get_frame(&stream_id, index, Duration::from_millis(500)).await {
+            Some(frame) => Some((Ok::<_, std::io::Error>(frame.data), (egress, stream_id, indices))),
+            None => {
+                // The segment is cut short, the player will continue with the next segment
+                error!("Chunk {} of stream {} not found in buffer", index, stream_id);
+                None
+            }
+        }
+    });
+    let chunks = stream::once(async move { Ok(first_chunk.data) }).chain(remaining_chunks);
+
+    Response::builder()
+        .status(StatusCode::OK)
+        .header("Content-Type", "video/iso.segment")
+        .body(axum::body::Body::from_stream(chunks))
+        .unwrap()
+}
+
 #[instrument(skip_all)]
 pub async fn fetch_dash_mpd(
     State(app_state): State<AppState>,
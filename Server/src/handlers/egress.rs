@@ -1,10 +1,18 @@
-use axum::extract::{Query, State};
+use axum::extract::{Path, Query, State};
 use axum::Json;
+use axum::http::StatusCode;
+use axum::response::{IntoResponse, Response};
+use std::time::Duration;
 use serde::{Deserialize, Serialize};
-use crate::types::AppState;
+use crate::types::{AppState, EgressProtocolType};
 use crate::encoders::EncodingFormat;
 use tracing::{info, instrument, warn};
 use crate::egress::egress_common::EgressProtocol;
+use crate::egress::flute::SessionMode;
+use flute::core::UDPEndpoint;
+use crate::egress::route::DeliveryMode;
+use crate::egress::file::RecordingMode;
+use crate::egress::preview::PreviewCamera;
 
 #[derive(Deserialize, Debug)]
 pub struct UpdateEgressSettingsRequest {
@@ -12,15 +20,49 @@ pub struct UpdateEgressSettingsRequest {
     pub fps: Option<u32>,
     pub encoding_format: Option<EncodingFormat>,
     pub max_number_of_points: Option<u64>,
+    pub frame_buffer_capacity: Option<usize>,
     // WebSocket-specific settings
     pub emit_with_ack: Option<bool>,
+    // WebRTC-specific settings
+    pub adaptive_budget: Option<bool>,
+    pub quality_layers: Option<String>, // e.g. "high:100000,low:20000"
     // FLUTE-specific settings
     pub content_encoding: Option<String>,
     pub fec: Option<String>,
     pub fec_percentage: Option<f32>,
     pub bandwidth: Option<u32>,
     pub md5: Option<bool>,
-    // Target egress protocol
+    pub delivery_mode: Option<String>, // "flute" or "route"
+    pub packet_queue_capacity: Option<usize>,
+    pub session_mode: Option<String>, // "single", "stream" or "tile"
+    pub fdt_carousel_interval_ms: Option<u64>, // 0 disables the time based FDT carousel
+    pub fdt_carousel_packets: Option<u32>, // 0 disables the packet based FDT carousel
+    pub session: Option<String>, // Key of the session that the fec settings apply to, e.g. "client_1_0"
+    pub adaptive_fec: Option<bool>,
+    pub adaptive_pacing: Option<bool>, // Adapt the send rate to the receiver feedback, up to the bandwidth
+    pub endpoint_address: Option<String>, // Destination (multicast) address of the FLUTE packets
+    pub endpoint_port: Option<u16>,
+    pub loss_profile: Option<String>, // e.g. "0:0.01,10000:0.05" (offset_ms:loss_fraction), empty to remove it
+    // WebTransport-specific settings
+    pub use_datagrams: Option<bool>,
+    // QUIC-specific settings
+    pub max_frames_in_flight: Option<usize>,
+    pub max_frame_age_ms: Option<u64>,
+    // MQTT-specific settings
+    pub qos: Option<u8>,
+    // Zenoh-specific settings
+    pub congestion_control: Option<String>, // "drop" or "block"
+    pub express: Option<bool>,
+    // File-specific settings
+    pub recording_mode: Option<String>, // "frames" or "segments"
+    pub segment_duration_ms: Option<u64>,
+    pub max_segments: Option<usize>,
+    // Buffer-specific settings
+    pub bitrate_ladder: Option<String>, // e.g. "high:100000,mid:50000,low:10000", empty for a single representation
+    // NDI-specific settings
+    pub preview_camera: Option<String>, // "top-down", "front" or "perspective:x,y,z:x,y,z:fov"
+    // Target egress protocol, taken from the path by `set_egress_settings`
+    #[serde(default)]
     pub egress_protocol: String, // "WebSocket", "WebRTC or "FLUTE"
 }
 
@@ -36,6 +78,24 @@ pub async fn update_egress_settings(
 ) -> Json<UpdateEgressSettingsResponse> {
     let egress_protocol = params.egress_protocol.to_lowercase();
 
+    if let Some(encoding_format) = params.encoding_format.filter(|format| format.is_recording_only() && egress_protocol != "file") {
+        warn!("{:?} can only be recorded by the file egress", encoding_format);
+        return Json(UpdateEgressSettingsResponse {
+            message: format!("{:?} can only be recorded by the file egress", encoding_format),
+        });
+    }
+
+    // The frame buffer capacity is remembered by the StreamManager, so it also applies to egresses that start later
+    if let Some(capacity) = params.frame_buffer_capacity {
+        match EgressProtocolType::from_name(&egress_protocol) {
+            Some(kind) => {
+                state.stream_manager.set_frame_buffer_capacity(kind.clone(), capacity);
+                info!("{:?} frame buffer capacity updated to {}", kind, capacity);
+            }
+            None => warn!("Unknown egress protocol: {}", egress_protocol),
+        }
+    }
+
     match egress_protocol.as_str() {
         "websocket" => {
             if let Some(websocket_egress) = state.stream_manager.get_websocket_egress() { // Arc<WebSocketEgress>
@@ -87,6 +147,18 @@ pub async fn update_egress_settings(
                     webrtc_egress.set_max_number_of_points(max_points);
                     info!("WebRTCEgress max_number_of_points updated to {}", max_points);
                 }
+                // Update the quality layers of the adaptive budget
+                if let Some(quality_layers) = params.quality_layers {
+                    match webrtc_egress.set_quality_layers(&quality_layers) {
+                        Ok(()) => info!("WebRTCEgress quality layers updated to {}", quality_layers),
+                        Err(e) => warn!("Invalid WebRTCEgress quality layers: {}", e),
+                    }
+                }
+                // Enable or disable the per client budget
+                if let Some(adaptive_budget) = params.adaptive_budget {
+                    webrtc_egress.set_adaptive_budget(adaptive_budget);
+                    info!("WebRTCEgress adaptive budget updated to {}", adaptive_budget);
+                }
 
                 Json(UpdateEgressSettingsResponse {
                     message: "WebRTCEgress settings updated".to_string(),
@@ -134,20 +206,98 @@ pub async fn update_egress_settings(
 
                 let mut should_destroy_sender = false;
 
-                if let Some(fec) = params.fec {
+                if let Some(delivery_mode) = params.delivery_mode {
+                    match DeliveryMode::parse(&delivery_mode) {
+                        Some(mode) => {
+                            flute_egress.set_delivery_mode(mode);
+                            info!("FluteEgress delivery mode updated to {:?}", mode);
+                            should_destroy_sender = true;
+                        }
+                        None => warn!("Unknown FLUTE delivery mode: {}", delivery_mode),
+                    }
+                }
+
+                if let Some(capacity) = params.packet_queue_capacity {
+                    flute_egress.set_packet_queue_capacity(capacity);
+                    info!("FluteEgress packet queue capacity updated to {}", capacity);
+                }
+
+                if params.fdt_carousel_interval_ms.is_some() || params.fdt_carousel_packets.is_some() {
+                    flute_egress.set_fdt_carousel(
+                        params.fdt_carousel_interval_ms.map(Duration::from_millis),
+                        params.fdt_carousel_packets,
+                    );
+                    info!(
+                        "FluteEgress FDT carousel updated to every {:?} ms and every {:?} packets",
+                        params.fdt_carousel_interval_ms, params.fdt_carousel_packets
+                    );
+                }
+
+                if let Some(session_mode) = params.session_mode {
+                    match SessionMode::parse(&session_mode) {
+                        Some(mode) => {
+                            flute_egress.set_session_mode(mode);
+                            info!("FluteEgress session mode updated to {:?}", mode);
+                        }
+                        None => warn!("Unknown FLUTE session mode: {}", session_mode),
+                    }
+                }
+
+                // The FEC settings of a single session, the other sessions keep their sender
+                if let Some(session) = params.session {
+                    match flute_egress.set_session_fec(&session, params.fec.clone(), params.fec_percentage) {
+                        Ok(()) => info!("FluteEgress FEC of session {} updated", session),
+                        Err(e) => warn!("Failed to update the FEC of FLUTE session {}: {}", session, e),
+                    }
+                } else if let Some(fec) = params.fec {
                     flute_egress.set_fec(fec.clone());
                     info!("FluteEgress FEC updated to {}", fec);
                     should_destroy_sender = true;
                 }
 
-                if let Some(fec_percentage) = params.fec_percentage {
-                    flute_egress.set_fec_parity_percentage(fec_percentage);
-                    info!("FluteEgress FEC percentage updated to {}", fec_percentage);
-                    should_destroy_sender = true;
+                if params.session.is_none() {
+                    if let Some(fec_percentage) = params.fec_percentage {
+                        flute_egress.set_fec_parity_percentage(fec_percentage);
+                        info!("FluteEgress FEC percentage updated to {}", fec_percentage);
+                        should_destroy_sender = true;
+                    }
+                }
+
+                // Applied after the FEC settings, so the adaptation starts from the new ones
+                if let Some(adaptive_fec) = params.adaptive_fec {
+                    flute_egress.set_adaptive_fec(adaptive_fec);
+                    info!("FluteEgress adaptive FEC updated to {}", adaptive_fec);
                 }
 
-                if should_destroy_sender {
-                    flute_egress.destroy_sender();
+                if let Some(adaptive_pacing) = params.adaptive_pacing {
+                    flute_egress.set_adaptive_pacing(adaptive_pacing);
+                    info!("FluteEgress adaptive pacing updated to {}", adaptive_pacing);
+                }
+
+                if let Some(loss_profile) = params.loss_profile {
+                    match flute_egress.set_loss_profile(&loss_profile) {
+                        Ok(()) => info!("FluteEgress loss profile updated to {}", loss_profile),
+                        Err(e) => warn!("Failed to update the FLUTE loss profile: {}", e),
+                    }
+                }
+
+                let endpoint = if params.endpoint_address.is_some() || params.endpoint_port.is_some() {
+                    let current = flute_egress.endpoint();
+                    Some(UDPEndpoint::new(
+                        None,
+                        params.endpoint_address.unwrap_or(current.destination_group_address),
+                        params.endpoint_port.unwrap_or(current.port),
+                    ))
+                } else {
+                    None
+                };
+
+                // The queued packets are sent with the old settings first, which blocks for a while
+                if should_destroy_sender || endpoint.is_some() {
+                    let flute_egress = flute_egress.clone();
+                    if let Err(e) = tokio::task::spawn_blocking(move || flute_egress.reconfigure(endpoint, None)).await {
+                        warn!("Failed to reconfigure the FLUTE egress: {}", e);
+                    }
                 }
 
                 Json(UpdateEgressSettingsResponse {
@@ -177,6 +327,24 @@ pub async fn update_egress_settings(
                     file_egress.set_max_number_of_points(max_points);
                     info!("FileEgress max_number_of_points updated to {}", max_points);
                 }
+                // Update the recording settings, this closes the segments that are being written
+                if params.recording_mode.is_some() || params.segment_duration_ms.is_some() || params.max_segments.is_some() {
+                    let mut settings = file_egress.recording_settings();
+                    if let Some(recording_mode) = params.recording_mode {
+                        match RecordingMode::parse(&recording_mode) {
+                            Some(mode) => settings.mode = mode,
+                            None => warn!("Unknown FileEgress recording mode: {}", recording_mode),
+                        }
+                    }
+                    if let Some(segment_duration_ms) = params.segment_duration_ms {
+                        settings.segment_duration_ms = segment_duration_ms;
+                    }
+                    if let Some(max_segments) = params.max_segments {
+                        settings.max_segments = max_segments;
+                    }
+                    info!("FileEgress recording settings updated to {:?}", settings);
+                    file_egress.set_recording_settings(settings);
+                }
 
                 Json(UpdateEgressSettingsResponse {
                     message: "FileEgress settings updated".to_string(),
@@ -205,6 +373,13 @@ pub async fn update_egress_settings(
                     buffer_egress.set_max_number_of_points(max_points);
                     info!("BufferEgress max_number_of_points updated to {}", max_points);
                 }
+                // Update the bitrate ladder of the aggregated frames
+                if let Some(bitrate_ladder) = params.bitrate_ladder {
+                    match buffer_egress.set_bitrate_ladder(&bitrate_ladder) {
+                        Ok(()) => info!("BufferEgress bitrate ladder updated to {}", bitrate_ladder),
+                        Err(e) => warn!("Failed to update the BufferEgress bitrate ladder: {}", e),
+                    }
+                }
 
                 Json(UpdateEgressSettingsResponse {
                     message: "BufferEgress settings updated".to_string(),
@@ -216,6 +391,397 @@ pub async fn update_egress_settings(
                 })
             }
         }
+        "webtransport" => {
+            if let Some(webtransport_egress) = state.stream_manager.get_webtransport_egress() {
+                // Update FPS
+                if let Some(fps) = params.fps {
+                    webtransport_egress.set_fps(fps);
+                    info!("WebTransportEgress FPS updated to {}", fps);
+                }
+                // Update encoding format
+                if let Some(encoding_format) = params.encoding_format {
+                    webtransport_egress.set_encoding_format(encoding_format);
+                    info!("WebTransportEgress encoding format updated to {:?}", encoding_format);
+                }
+                // Update max number of points
+                if let Some(max_points) = params.max_number_of_points {
+                    webtransport_egress.set_max_number_of_points(max_points);
+                    info!("WebTransportEgress max_number_of_points updated to {}", max_points);
+                }
+                // Update use_datagrams
+                if let Some(use_datagrams) = params.use_datagrams {
+                    webtransport_egress.set_use_datagrams(use_datagrams);
+                    info!("WebTransportEgress use_datagrams updated to {}", use_datagrams);
+                }
+
+                Json(UpdateEgressSettingsResponse {
+                    message: "WebTransportEgress settings updated".to_string(),
+                })
+            } else {
+                warn!("WebTransportEgress not initialized");
+                Json(UpdateEgressSettingsResponse {
+                    message: "WebTransportEgress not initialized".to_string(),
+                })
+            }
+        },
+        "quic" => {
+            if let Some(quic_egress) = state.stream_manager.get_quic_egress() {
+                // Update FPS
+                if let Some(fps) = params.fps {
+                    quic_egress.set_fps(fps);
+                    info!("QuicEgress FPS updated to {}", fps);
+                }
+                // Update encoding format
+                if let Some(encoding_format) = params.encoding_format {
+                    quic_egress.set_encoding_format(encoding_format);
+                    info!("QuicEgress encoding format updated to {:?}", encoding_format);
+                }
+                // Update max number of points
+                if let Some(max_points) = params.max_number_of_points {
+                    quic_egress.set_max_number_of_points(max_points);
+                    info!("QuicEgress max_number_of_points updated to {}", max_points);
+                }
+                // Update max_frames_in_flight
+                if let Some(max_frames_in_flight) = params.max_frames_in_flight {
+                    quic_egress.set_max_frames_in_flight(max_frames_in_flight);
+                    info!("QuicEgress max_frames_in_flight updated to {}", max_frames_in_flight);
+                }
+                // Update max_frame_age
+                if let Some(max_frame_age_ms) = params.max_frame_age_ms {
+                    quic_egress.set_max_frame_age(Duration::from_millis(max_frame_age_ms));
+                    info!("QuicEgress max_frame_age updated to {} ms", max_frame_age_ms);
+                }
+
+                Json(UpdateEgressSettingsResponse {
+                    message: "QuicEgress settings updated".to_string(),
+                })
+            } else {
+                warn!("QuicEgress not initialized");
+                Json(UpdateEgressSettingsResponse {
+                    message: "QuicEgress not initialized".to_string(),
+                })
+            }
+        },
+        "srt" => {
+            if let Some(srt_egress) = state.stream_manager.get_srt_egress() {
+                // Update FPS
+                if let Some(fps) = params.fps {
+                    srt_egress.set_fps(fps);
+                    info!("SrtEgress FPS updated to {}", fps);
+                }
+                // Update encoding format
+                if let Some(encoding_format) = params.encoding_format {
+                    srt_egress.set_encoding_format(encoding_format);
+                    info!("SrtEgress encoding format updated to {:?}", encoding_format);
+                }
+                // Update max number of points
+                if let Some(max_points) = params.max_number_of_points {
+                    srt_egress.set_max_number_of_points(max_points);
+                    info!("SrtEgress max_number_of_points updated to {}", max_points);
+                }
+
+                Json(UpdateEgressSettingsResponse {
+                    message: "SrtEgress settings updated".to_string(),
+                })
+            } else {
+                warn!("SrtEgress not initialized");
+                Json(UpdateEgressSettingsResponse {
+                    message: "SrtEgress not initialized".to_string(),
+                })
+            }
+        },
+        "rtp" => {
+            if let Some(rtp_egress) = state.stream_manager.get_rtp_egress() {
+                // Update FPS
+                if let Some(fps) = params.fps {
+                    rtp_egress.set_fps(fps);
+                    info!("RtpMulticastEgress FPS updated to {}", fps);
+                }
+                // Update encoding format
+                if let Some(encoding_format) = params.encoding_format {
+                    rtp_egress.set_encoding_format(encoding_format);
+                    info!("RtpMulticastEgress encoding format updated to {:?}", encoding_format);
+                }
+                // Update max number of points
+                if let Some(max_points) = params.max_number_of_points {
+                    rtp_egress.set_max_number_of_points(max_points);
+                    info!("RtpMulticastEgress max_number_of_points updated to {}", max_points);
+                }
+
+                Json(UpdateEgressSettingsResponse {
+                    message: "RtpMulticastEgress settings updated".to_string(),
+                })
+            } else {
+                warn!("RtpMulticastEgress not initialized");
+                Json(UpdateEgressSettingsResponse {
+                    message: "RtpMulticastEgress not initialized".to_string(),
+                })
+            }
+        },
+        "tcp" => {
+            if let Some(tcp_egress) = state.stream_manager.get_tcp_egress() {
+                // Update FPS
+                if let Some(fps) = params.fps {
+                    tcp_egress.set_fps(fps);
+                    info!("TcpEgress FPS updated to {}", fps);
+                }
+                // Update encoding format
+                if let Some(encoding_format) = params.encoding_format {
+                    tcp_egress.set_encoding_format(encoding_format);
+                    info!("TcpEgress encoding format updated to {:?}", encoding_format);
+                }
+                // Update max number of points
+                if let Some(max_points) = params.max_number_of_points {
+                    tcp_egress.set_max_number_of_points(max_points);
+                    info!("TcpEgress max_number_of_points updated to {}", max_points);
+                }
+
+                Json(UpdateEgressSettingsResponse {
+                    message: "TcpEgress settings updated".to_string(),
+                })
+            } else {
+                warn!("TcpEgress not initialized");
+                Json(UpdateEgressSettingsResponse {
+                    message: "TcpEgress not initialized".to_string(),
+                })
+            }
+        },
+        "mqtt" => {
+            if let Some(mqtt_egress) = state.stream_manager.get_mqtt_egress() {
+                // Update FPS
+                if let Some(fps) = params.fps {
+                    mqtt_egress.set_fps(fps);
+                    info!("MqttEgress FPS updated to {}", fps);
+                }
+                // Update encoding format
+                if let Some(encoding_format) = params.encoding_format {
+                    mqtt_egress.set_encoding_format(encoding_format);
+                    info!("MqttEgress encoding format updated to {:?}", encoding_format);
+                }
+                // Update max number of points
+                if let Some(max_points) = params.max_number_of_points {
+                    mqtt_egress.set_max_number_of_points(max_points);
+                    info!("MqttEgress max_number_of_points updated to {}", max_points);
+                }
+                // Update QoS
+                if let Some(qos) = params.qos {
+                    match mqtt_egress.set_qos(qos) {
+                        Ok(()) => info!("MqttEgress QoS updated to {}", qos),
+                        Err(e) => warn!("MqttEgress QoS not updated: {}", e),
+                    }
+                }
+
+                Json(UpdateEgressSettingsResponse {
+                    message: "MqttEgress settings updated".to_string(),
+                })
+            } else {
+                warn!("MqttEgress not initialized");
+                Json(UpdateEgressSettingsResponse {
+                    message: "MqttEgress not initialized".to_string(),
+                })
+            }
+        },
+        "zenoh" => {
+            if let Some(zenoh_egress) = state.stream_manager.get_zenoh_egress() {
+                // Update FPS
+                if let Some(fps) = params.fps {
+                    zenoh_egress.set_fps(fps);
+                    info!("ZenohEgress FPS updated to {}", fps);
+                }
+                // Update encoding format
+                if let Some(encoding_format) = params.encoding_format {
+                    zenoh_egress.set_encoding_format(encoding_format);
+                    info!("ZenohEgress encoding format updated to {:?}", encoding_format);
+                }
+                // Update max number of points
+                if let Some(max_points) = params.max_number_of_points {
+                    zenoh_egress.set_max_number_of_points(max_points);
+                    info!("ZenohEgress max_number_of_points updated to {}", max_points);
+                }
+                // Update congestion control
+                if let Some(congestion_control) = params.congestion_control {
+                    match zenoh_egress.set_congestion_control(&congestion_control) {
+                        Ok(()) => info!("ZenohEgress congestion control updated to {}", congestion_control),
+                        Err(e) => warn!("ZenohEgress congestion control not updated: {}", e),
+                    }
+                }
+                // Update express
+                if let Some(express) = params.express {
+                    zenoh_egress.set_express(express);
+                    info!("ZenohEgress express updated to {}", express);
+                }
+
+                Json(UpdateEgressSettingsResponse {
+                    message: "ZenohEgress settings updated".to_string(),
+                })
+            } else {
+                warn!("ZenohEgress not initialized");
+                Json(UpdateEgressSettingsResponse {
+                    message: "ZenohEgress not initialized".to_string(),
+                })
+            }
+        },
+        "grpc" => {
+            if let Some(grpc_egress) = state.stream_manager.get_grpc_egress() {
+                // Update FPS
+                if let Some(fps) = params.fps {
+                    grpc_egress.set_fps(fps);
+                    info!("GrpcEgress FPS updated to {}", fps);
+                }
+                // Update encoding format
+                if let Some(encoding_format) = params.encoding_format {
+                    grpc_egress.set_encoding_format(encoding_format);
+                    info!("GrpcEgress encoding format updated to {:?}", encoding_format);
+                }
+                // Update max number of points
+                if let Some(max_points) = params.max_number_of_points {
+                    grpc_egress.set_max_number_of_points(max_points);
+                    info!("GrpcEgress max_number_of_points updated to {}", max_points);
+                }
+
+                Json(UpdateEgressSettingsResponse {
+                    message: "GrpcEgress settings updated".to_string(),
+                })
+            } else {
+                warn!("GrpcEgress not initialized");
+                Json(UpdateEgressSettingsResponse {
+                    message: "GrpcEgress not initialized".to_string(),
+                })
+            }
+        },
+        "ndi" => {
+            if let Some(ndi_egress) = state.stream_manager.get_ndi_egress() {
+                // Update FPS
+                if let Some(fps) = params.fps {
+                    ndi_egress.set_fps(fps);
+                    info!("NdiEgress FPS updated to {}", fps);
+                }
+                // Update max number of points
+                if let Some(max_points) = params.max_number_of_points {
+                    ndi_egress.set_max_number_of_points(max_points);
+                    info!("NdiEgress max_number_of_points updated to {}", max_points);
+                }
+                // Update the camera of the preview
+                if let Some(preview_camera) = params.preview_camera.as_deref() {
+                    match PreviewCamera::parse(preview_camera) {
+                        Ok(camera) => {
+                            ndi_egress.set_camera(camera);
+                            info!("NdiEgress preview camera updated to {:?}", camera);
+                        }
+                        Err(e) => warn!("Invalid NdiEgress preview camera: {}", e),
+                    }
+                }
+
+                Json(UpdateEgressSettingsResponse {
+                    message: "NdiEgress settings updated".to_string(),
+                })
+            } else {
+                warn!("NdiEgress not initialized");
+                Json(UpdateEgressSettingsResponse {
+                    message: "NdiEgress not initialized".to_string(),
+                })
+            }
+        },
+        "projection" => {
+            if let Some(projection_egress) = state.stream_manager.get_projection_egress() {
+                // Update FPS
+                if let Some(fps) = params.fps {
+                    projection_egress.set_fps(fps);
+                    info!("ProjectionEgress FPS updated to {}", fps);
+                }
+                // Update max number of points
+                if let Some(max_points) = params.max_number_of_points {
+                    projection_egress.set_max_number_of_points(max_points);
+                    info!("ProjectionEgress max_number_of_points updated to {}", max_points);
+                }
+
+                Json(UpdateEgressSettingsResponse {
+                    message: "ProjectionEgress settings updated".to_string(),
+                })
+            } else {
+                warn!("ProjectionEgress not initialized");
+                Json(UpdateEgressSettingsResponse {
+                    message: "ProjectionEgress not initialized".to_string(),
+                })
+            }
+        },
+        "rtsp" => {
+            if let Some(rtsp_egress) = state.stream_manager.get_rtsp_egress() {
+                // Update FPS
+                if let Some(fps) = params.fps {
+                    rtsp_egress.set_fps(fps);
+                    info!("RtspEgress FPS updated to {}", fps);
+                }
+                // Update encoding format
+                if let Some(encoding_format) = params.encoding_format {
+                    rtsp_egress.set_encoding_format(encoding_format);
+                    info!("RtspEgress encoding format updated to {:?}", encoding_format);
+                }
+                // Update max number of points
+                if let Some(max_points) = params.max_number_of_points {
+                    rtsp_egress.set_max_number_of_points(max_points);
+                    info!("RtspEgress max_number_of_points updated to {}", max_points);
+                }
+
+                Json(UpdateEgressSettingsResponse {
+                    message: "RtspEgress settings updated".to_string(),
+                })
+            } else {
+                warn!("RtspEgress not initialized");
+                Json(UpdateEgressSettingsResponse {
+                    message: "RtspEgress not initialized".to_string(),
+                })
+            }
+        },
+        #[cfg(unix)]
+        "local" => {
+            if let Some(local_egress) = state.stream_manager.get_local_egress() {
+                // Update FPS
+                if let Some(fps) = params.fps {
+                    local_egress.set_fps(fps);
+                    info!("LocalEgress FPS updated to {}", fps);
+                }
+                // Update encoding format
+                if let Some(encoding_format) = params.encoding_format {
+                    local_egress.set_encoding_format(encoding_format);
+                    info!("LocalEgress encoding format updated to {:?}", encoding_format);
+                }
+                // Update max number of points
+                if let Some(max_points) = params.max_number_of_points {
+                    local_egress.set_max_number_of_points(max_points);
+                    info!("LocalEgress max_number_of_points updated to {}", max_points);
+                }
+
+                Json(UpdateEgressSettingsResponse {
+                    message: "LocalEgress settings updated".to_string(),
+                })
+            } else {
+                warn!("LocalEgress not initialized");
+                Json(UpdateEgressSettingsResponse {
+                    message: "LocalEgress not initialized".to_string(),
+                })
+            }
+        },
+        name if state.stream_manager.get_plugin_egress(name).is_some() => {
+            let plugin_egress = state.stream_manager.get_plugin_egress(name).unwrap();
+            // Registered egresses only have the common settings
+            if let Some(fps) = params.fps {
+                plugin_egress.set_fps(fps);
+                info!("{} egress FPS updated to {}", name, fps);
+            }
+            if let Some(encoding_format) = params.encoding_format {
+                plugin_egress.set_encoding_format(encoding_format);
+                info!("{} egress encoding format updated to {:?}", name, encoding_format);
+            }
+            if let Some(max_points) = params.max_number_of_points {
+                plugin_egress.set_max_number_of_points(max_points);
+                info!("{} egress max_number_of_points updated to {}", name, max_points);
+            }
+
+            Json(UpdateEgressSettingsResponse {
+                message: format!("{} egress settings updated", name),
+            })
+        },
         _ => {
             warn!("Unknown egress protocol: {}", params.egress_protocol);
             Json(UpdateEgressSettingsResponse {
@@ -224,3 +790,194 @@ pub async fn update_egress_settings(
         }
     }
 }
+
+/// The current settings of an egress, the FLUTE specific ones are only set for the FLUTE egress.
+#[derive(Serialize, Debug)]
+pub struct EgressSettingsResponse {
+    pub egress_protocol: EgressProtocolType,
+    pub fps: u32,
+    pub encoding_format: EncodingFormat,
+    pub max_number_of_points: u64,
+    pub frame_buffer_capacity: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub fec: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub fec_percentage: Option<f32>,
+    /// Configured send rate in bits per second
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub bandwidth: Option<u32>,
+    /// Rate in bits per second at which the egress currently sends, lower than the bandwidth when pacing adapts
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub send_rate: Option<u32>,
+}
+
+fn egress_settings(state: &AppState, kind: EgressProtocolType) -> Option<EgressSettingsResponse> {
+    let egress = state.stream_manager.get_egress(&kind)?;
+    let flute_egress = match kind {
+        EgressProtocolType::Flute => state.stream_manager.get_flute_egress(),
+        _ => None,
+    };
+    Some(EgressSettingsResponse {
+        fps: egress.fps(),
+        encoding_format: egress.encoding_format(),
+        max_number_of_points: egress.max_number_of_points(),
+        frame_buffer_capacity: state.stream_manager.frame_buffer_capacity(&kind),
+        fec: flute_egress.as_ref().map(|flute_egress| flute_egress.fec()),
+        fec_percentage: flute_egress.as_ref().map(|flute_egress| flute_egress.fec_parity_percentage()),
+        bandwidth: flute_egress.as_ref().map(|flute_egress| flute_egress.bandwidth()),
+        send_rate: flute_egress.as_ref().map(|flute_egress| flute_egress.send_rate()),
+        egress_protocol: kind,
+    })
+}
+
+/// Lists the current settings of every running egress.
+#[instrument(skip_all)]
+pub async fn list_egress_settings(
+    State(state): State<AppState>,
+) -> Json<Vec<EgressSettingsResponse>> {
+    let settings = state.stream_manager
+        .egress_protocol_types()
+        .into_iter()
+        .filter_map(|kind| egress_settings(&state, kind))
+        .collect();
+    Json(settings)
+}
+
+/// Returns the current settings of one egress, e.g. `/egress/flute/settings`.
+#[instrument(skip_all)]
+pub async fn get_egress_settings(
+    Path(egress_protocol): Path<String>,
+    State(state): State<AppState>,
+) -> Response {
+    let Some(kind) = EgressProtocolType::from_name(&egress_protocol) else {
+        warn!("Unknown egress protocol: {}", egress_protocol);
+        return StatusCode::NOT_FOUND.into_response();
+    };
+    match egress_settings(&state, kind) {
+        Some(settings) => Json(settings).into_response(),
+        None => {
+            warn!("{} egress not initialized", egress_protocol);
+            StatusCode::NOT_FOUND.into_response()
+        }
+    }
+}
+
+/// Updates the settings of one egress from a JSON body, with the same fields as `update_egress_settings`,
+/// and returns the settings that are in effect afterwards.
+#[instrument(skip_all)]
+pub async fn set_egress_settings(
+    Path(egress_protocol): Path<String>,
+    State(state): State<AppState>,
+    Json(mut params): Json<UpdateEgressSettingsRequest>,
+) -> Response {
+    let Some(kind) = EgressProtocolType::from_name(&egress_protocol) else {
+        warn!("Unknown egress protocol: {}", egress_protocol);
+        return StatusCode::NOT_FOUND.into_response();
+    };
+    if state.stream_manager.get_egress(&kind).is_none() {
+        warn!("{} egress not initialized", egress_protocol);
+        return StatusCode::NOT_FOUND.into_response();
+    }
+    if let Some(encoding_format) = params.encoding_format.filter(|format| format.is_recording_only() && kind != EgressProtocolType::File) {
+        warn!("{:?} can only be recorded by the file egress", encoding_format);
+        return StatusCode::BAD_REQUEST.into_response();
+    }
+
+    params.egress_protocol = egress_protocol;
+    update_egress_settings(Query(params), State(state.clone())).await;
+    match egress_settings(&state, kind) {
+        Some(settings) => Json(settings).into_response(),
+        None => StatusCode::NOT_FOUND.into_response(),
+    }
+}
+
+#[derive(Serialize, Debug)]
+pub struct WebTransportCertificateHashResponse {
+    /// Base64 encoded SHA-256 hash, to pass as `serverCertificateHashes` to the `WebTransport` constructor of the browser
+    pub certificate_hash: Option<String>,
+}
+
+#[instrument(skip_all)]
+pub async fn get_webtransport_certificate_hash(
+    State(state): State<AppState>,
+) -> Json<WebTransportCertificateHashResponse> {
+    let certificate_hash = state.stream_manager.get_webtransport_egress()
+        .map(|webtransport_egress| webtransport_egress.certificate_hash().to_string());
+    if certificate_hash.is_none() {
+        warn!("WebTransportEgress not initialized");
+    }
+    Json(WebTransportCertificateHashResponse { certificate_hash })
+}
+
+/// Returns the S-TSID of the FLUTE egress, which ROUTE receivers need to interpret the session
+#[instrument(skip_all)]
+pub async fn get_route_stsid(
+    State(state): State<AppState>,
+) -> Response {
+    match state.stream_manager.get_flute_egress() {
+        Some(flute_egress) => Response::builder()
+            .status(StatusCode::OK)
+            .header("Content-Type", "application/s-tsid")
+            .body(axum::body::Body::from(flute_egress.route_stsid()))
+            .unwrap(),
+        None => {
+            warn!("FluteEgress not initialized");
+            StatusCode::NOT_FOUND.into_response()
+        }
+    }
+}
+
+/// Lists the FLUTE transport sessions, so receivers can join the TSIs of the streams they need.
+#[instrument(skip_all)]
+pub async fn get_flute_sessions(
+    State(state): State<AppState>,
+) -> Response {
+    match state.stream_manager.get_flute_egress() {
+        Some(flute_egress) => Json(flute_egress.sessions()).into_response(),
+        None => {
+            warn!("FluteEgress not initialized");
+            StatusCode::NOT_FOUND.into_response()
+        }
+    }
+}
+
+/// Lists the feedback of the FLUTE receivers that reported recently.
+#[instrument(skip_all)]
+pub async fn get_flute_feedback(
+    State(state): State<AppState>,
+) -> Response {
+    match state.stream_manager.get_flute_egress() {
+        Some(flute_egress) => Json(flute_egress.feedback_receivers()).into_response(),
+        None => {
+            warn!("FluteEgress not initialized");
+            StatusCode::NOT_FOUND.into_response()
+        }
+    }
+}
+
+#[derive(Deserialize, Debug)]
+pub struct FluteLossReportRequest {
+    /// Fraction of the packets (0 to 1) that the receiver lost since its previous report
+    pub loss_fraction: f32,
+    /// Bits per second that the receiver received since its previous report
+    pub goodput_bps: Option<u64>,
+}
+
+/// Receives a loss report of a FLUTE receiver, or the network conditions pushed by a controller,
+/// which the adaptive FEC and the adaptive pacing use to choose the FEC and the send rate.
+#[instrument(skip_all)]
+pub async fn report_flute_loss(
+    State(state): State<AppState>,
+    Json(report): Json<FluteLossReportRequest>,
+) -> StatusCode {
+    match state.stream_manager.get_flute_egress() {
+        Some(flute_egress) => {
+            flute_egress.report_feedback(report.loss_fraction, report.goodput_bps);
+            StatusCode::OK
+        },
+        None => {
+            warn!("FluteEgress not initialized");
+            StatusCode::NOT_FOUND
+        }
+    }
+}
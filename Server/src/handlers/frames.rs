@@ -1,7 +1,10 @@
 // handlers/frames.rs
 
 use axum::extract::State;
-use tracing::instrument;
+use axum::http::{header::AUTHORIZATION, HeaderMap, StatusCode};
+use axum::response::{IntoResponse, Response};
+use tracing::{instrument, warn};
+use crate::services::auth::bearer_token;
 use crate::types::AppState;
 use axum::Json;
 
@@ -9,8 +12,17 @@ use axum::Json;
 #[instrument(skip_all)]
 pub async fn receive_frame(
     State(state): State<AppState>,
+    headers: HeaderMap,
     frame_data: String,
-) -> Json<serde_json::Value> {
+) -> Response {
+    let token = headers.get(AUTHORIZATION)
+        .and_then(|header| header.to_str().ok())
+        .and_then(bearer_token);
+    if let Err(e) = state.stream_manager.authorize_producer("manual", token) {
+        warn!("Rejecting a frame: {}", e);
+        return StatusCode::UNAUTHORIZED.into_response();
+    }
+
     // Convert String to Vec<u8>
     let data = frame_data.as_bytes().to_vec();
 
@@ -20,5 +32,5 @@ pub async fn receive_frame(
         "manual".to_string()
     );
 
-    Json(serde_json::json!({"status": "Frame pushed to processor"}))
+    Json(serde_json::json!({"status": "Frame pushed to processor"})).into_response()
 }
@@ -0,0 +1,146 @@
+// handlers/hls.rs
+
+use std::time::Duration;
+
+use axum::{extract::{Path, Query, State}, response::{IntoResponse, Response}, http::StatusCode};
+use serde::Deserialize;
+use crate::types::AppState;
+use tracing::{error, instrument};
+
+#[derive(Deserialize, Debug)]
+pub struct BlockingReloadQuery {
+    #[serde(rename = "_HLS_msn")]
+    pub msn: Option<u64>,
+    #[serde(rename = "_HLS_part")]
+    pub part: Option<u64>,
+}
+
+#[instrument(skip_all)]
+pub async fn fetch_hls_multivariant_playlist(
+    State(app_state): State<AppState>,
+    Path(group_id): Path<String>,
+) -> Response {
+    let egress = match app_state.stream_manager.get_buffer_egress() {
+        Some(e) => e,
+        None => {
+            error!("Buffer egress not initialized");
+            return StatusCode::NOT_FOUND.into_response();
+        }
+    };
+
+    // Remove .m3u8 from group_id if present
+    let group_id = group_id.strip_suffix(".m3u8").unwrap_or(&group_id).to_string();
+
+    match egress.get_hls_multivariant_playlist(&group_id) {
+        Some(playlist) => playlist_response(playlist),
+        None => {
+            error!("HLS playlist for group {} not found", group_id);
+            StatusCode::NOT_FOUND.into_response()
+        }
+    }
+}
+
+#[instrument(skip_all)]
+pub async fn fetch_hls_file(
+    State(app_state): State<AppState>,
+    Path((stream_id, file_name)): Path<(String, String)>,
+    Query(query): Query<BlockingReloadQuery>,
+) -> Response {
+    let egress = match app_state.stream_manager.get_buffer_egress() {
+        Some(e) => e,
+        None => {
+            error!("Buffer egress not initialized");
+            return StatusCode::NOT_FOUND.into_response();
+        }
+    };
+
+    if file_name == "playlist.m3u8" {
+        let window = match egress.get_hls_window(&stream_id) {
+            Some(w) => w,
+            None => {
+                error!("HLS playlist for stream {} not found", stream_id);
+                return StatusCode::NOT_FOUND.into_response();
+            }
+        };
+
+        // Blocking playlist reload, hold the request until the requested part has been buffered
+        if let Some(msn) = query.msn {
+            // The spec asks to reject requests that are more than two segments ahead
+            if msn > window.segment_of(window.last_index) + 2 {
+                return StatusCode::BAD_REQUEST.into_response();
+            }
+            let index = match query.part {
+                Some(part) => window.index_of(msn, part),
+                None => window.index_of(msn + 1, 0) - 1,
+            };
+            let timeout = Duration::from_secs_f64(window.segment_duration() * 3.0);
+            if egress.get_frame(&stream_id, index, timeout).await.is_none() && index > window.last_index {
+                return StatusCode::SERVICE_UNAVAILABLE.into_response();
+            }
+        }
+
+        return match egress.get_hls_media_playlist(&stream_id) {
+            Some(playlist) => playlist_response(playlist),
+            None => StatusCode::NOT_FOUND.into_response(),
+        };
+    }
+
+    if file_name == "init.mp4" {
+        return match egress.get_stream_config(&stream_id) {
+            Some(config) => Response::builder()
+                .status(StatusCode::OK)
+                .header("Content-Type", "video/mp4")
+                .body(axum::body::Body::from(mp4_box::writer::create_init_segment(&config)))
+                .unwrap(),
+            None => {
+                error!("Stream config for {} not found", stream_id);
+                StatusCode::NOT_FOUND.into_response()
+            }
+        };
+    }
+
+    if let Some(msn_str) = file_name.strip_prefix("segment_").and_then(|s| s.strip_suffix(".m4s")) {
+        if let Ok(msn) = msn_str.parse::<u64>() {
+            return match egress.get_hls_segment(&stream_id, msn) {
+                Some(segment) => segment_response(segment),
+                None => {
+                    error!("HLS segment {} of stream {} not found in buffer", msn, stream_id);
+                    StatusCode::NOT_FOUND.into_response()
+                }
+            };
+        }
+    }
+
+    // Partial segments are the individual frames, these may be requested ahead of time through the preload hint
+    if let Some(index_str) = file_name.strip_suffix(".m4s") {
+        if let Ok(index) = index_str.parse::<u64>() {
+            return match egress.get_frame(&stream_id, index, Duration::from_millis(500)).await {
+                Some(frame) => segment_response(frame.data),
+                None => {
+                    error!("Frame index {} not found in buffer", index);
+                    StatusCode::NOT_FOUND.into_response()
+                }
+            };
+        }
+    }
+
+    error!("Invalid HLS file requested: {}", file_name);
+    StatusCode::BAD_REQUEST.into_response()
+}
+
+fn playlist_response(playlist: String) -> Response {
+    Response::builder()
+        .status(StatusCode::OK)
+        .header("Content-Type", "application/vnd.apple.mpegurl")
+        .header("Cache-Control", "no-cache")
+        .body(axum::body::Body::from(playlist))
+        .unwrap()
+}
+
+fn segment_response(data: Vec<u8>) -> Response {
+    Response::builder()
+        .status(StatusCode::OK)
+        .header("Content-Type", "video/iso.segment")
+        .body(axum::body::Body::from(data))
+        .unwrap()
+}
@@ -0,0 +1,18 @@
+use axum::{extract::State, Json};
+use tracing::{instrument, warn};
+
+use crate::handlers::egress::WebTransportCertificateHashResponse;
+use crate::types::AppState;
+
+/// Returns the hash of the certificate of the WebTransport ingress, which browsers need to push frames to it
+#[instrument(skip_all)]
+pub async fn get_webtransport_ingress_certificate_hash(
+    State(state): State<AppState>,
+) -> Json<WebTransportCertificateHashResponse> {
+    let certificate_hash = state.stream_manager.get_webtransport_ingress()
+        .map(|webtransport_ingress| webtransport_ingress.certificate_hash().to_string());
+    if certificate_hash.is_none() {
+        warn!("WebTransportIngress not initialized");
+    }
+    Json(WebTransportCertificateHashResponse { certificate_hash })
+}
@@ -1,7 +1,11 @@
 pub mod dash;
 pub mod datasets;
 pub mod egress;
+pub mod hls;
+pub mod ingress;
 pub mod scheduler;
 pub mod websocket;
 pub mod frames;
-pub mod streams;
\ No newline at end of file
+pub mod streams;
+pub mod shutdown;
+pub mod tiles;
@@ -0,0 +1,24 @@
+// handlers/shutdown.rs
+
+use axum::extract::State;
+use axum::Json;
+use serde::Serialize;
+use tracing::instrument;
+
+use crate::types::AppState;
+
+#[derive(Serialize, Debug)]
+pub struct ShutdownResponse {
+    pub message: String,
+}
+
+/// Shuts the server down gracefully, the response is sent before the egresses are flushed.
+#[instrument(skip_all)]
+pub async fn shutdown_server(State(app_state): State<AppState>) -> Json<ShutdownResponse> {
+    let message = if app_state.shutdown.trigger("REST request") {
+        "Shutting down".to_string()
+    } else {
+        "Already shutting down".to_string()
+    };
+    Json(ShutdownResponse { message })
+}
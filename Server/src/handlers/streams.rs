@@ -1,5 +1,8 @@
 use axum::extract::{Json, Query, State};
-use tracing::instrument;
+use shared_utils::payload_crypto::PayloadKey;
+use tracing::{info, instrument, warn};
+use crate::encoders::gpcc::GpccParams;
+use crate::processing::admission::DropPolicy;
 use crate::types::{AppState, EgressProtocolType};
 use serde::{de, Deserialize, Deserializer, Serialize};
 
@@ -35,6 +38,9 @@ pub struct UpdateStreamSettingsRequest {
     pub ring_buffer_bypass: Option<bool>,
     #[serde(default, deserialize_with = "deserialize_csv_u8")]
     pub max_point_percentages: Option<Vec<u8>>,   // e.g. [15, 25, 60]
+    pub max_frames_per_second: Option<f64>, // 0 removes the limit
+    pub max_bytes_per_second: Option<u64>, // 0 removes the limit
+    pub drop_policy: Option<DropPolicy>, // drop-newest, drop-oldest or downsample
 }
 
 #[derive(Serialize, Debug)]
@@ -98,6 +104,23 @@ pub async fn update_stream_settings(
         settings.max_point_percentages = Some(max_point_percentages);
     }
 
+    if let Some(max_frames_per_second) = request.max_frames_per_second {
+        settings.max_frames_per_second = Some(max_frames_per_second).filter(|max| *max > 0.0);
+    }
+
+    if let Some(max_bytes_per_second) = request.max_bytes_per_second {
+        settings.max_bytes_per_second = Some(max_bytes_per_second).filter(|max| *max > 0);
+    }
+
+    if let Some(drop_policy) = request.drop_policy {
+        settings.drop_policy = drop_policy;
+    }
+
+    // The budget of the stream starts over with the new limits
+    if request.max_frames_per_second.is_some() || request.max_bytes_per_second.is_some() || request.drop_policy.is_some() {
+        state.processing_pipeline.admission.reset(&request.stream_id);
+    }
+
 
     // Update the stream settings in StreamManager
     stream_manager.update_stream_settings(settings);
@@ -135,8 +158,93 @@ pub async fn list_streams(State(state): State<AppState>) -> Json<StreamListRespo
             aggregator_bypass: Some(settings.aggregator_bypass),
             ring_buffer_bypass: Some(settings.ring_buffer_bypass),
             max_point_percentages: settings.max_point_percentages.clone(),
+            max_frames_per_second: settings.max_frames_per_second,
+            max_bytes_per_second: settings.max_bytes_per_second,
+            drop_policy: Some(settings.drop_policy),
         })
         .collect();
 
     Json(StreamListResponse { streams: all_settings })
 }
+
+#[derive(Deserialize, Debug)]
+pub struct UpdatePayloadKeyRequest {
+    pub stream_id: String, // Use "combined" for the aggregated frames
+    pub key_id: Option<u32>,
+    pub key: Option<String>, // 64 hexadecimal characters, the encryption of the stream is disabled if not set
+}
+
+/// Sets or removes the AES-GCM key the encoded frames of a stream are encrypted with.
+#[instrument(skip_all)]
+pub async fn update_payload_key(
+    Query(request): Query<UpdatePayloadKeyRequest>,
+    State(state): State<AppState>,
+) -> Json<UpdateStreamSettingsResponse> {
+    let Some(key) = request.key else {
+        state.stream_manager.remove_payload_key(&request.stream_id);
+        info!("Payload encryption disabled for stream_id {}", request.stream_id);
+        return Json(UpdateStreamSettingsResponse {
+            message: format!("Payload encryption disabled for stream_id {}", request.stream_id),
+        });
+    };
+
+    let key_id = request.key_id.unwrap_or(0);
+    match PayloadKey::from_hex(key_id, &key) {
+        Ok(key) => {
+            state.stream_manager.set_payload_key(&request.stream_id, key);
+            info!("Payload encryption enabled for stream_id {} with key {}", request.stream_id, key_id);
+            Json(UpdateStreamSettingsResponse {
+                message: format!("Payload encryption enabled for stream_id {} with key {}", request.stream_id, key_id),
+            })
+        }
+        Err(e) => {
+            warn!("Invalid payload key for stream_id {}: {}", request.stream_id, e);
+            Json(UpdateStreamSettingsResponse {
+                message: format!("Invalid payload key: {}", e),
+            })
+        }
+    }
+}
+
+#[derive(Deserialize, Debug)]
+pub struct UpdateGpccParamsRequest {
+    pub stream_id: String, // Use "combined" for the aggregated frames
+    pub position_step: Option<f32>,
+    pub attribute_qp: Option<u8>, // The stream falls back to the default settings if neither is set
+}
+
+/// Sets the rate and quantization settings the frames of a stream are encoded with when the egress uses G-PCC.
+#[instrument(skip_all)]
+pub async fn update_gpcc_params(
+    Query(request): Query<UpdateGpccParamsRequest>,
+    State(state): State<AppState>,
+) -> Json<UpdateStreamSettingsResponse> {
+    if request.position_step.is_none() && request.attribute_qp.is_none() {
+        state.stream_manager.remove_gpcc_params(&request.stream_id);
+        info!("Default G-PCC settings restored for stream_id {}", request.stream_id);
+        return Json(UpdateStreamSettingsResponse {
+            message: format!("Default G-PCC settings restored for stream_id {}", request.stream_id),
+        });
+    }
+
+    let current = state.stream_manager.gpcc_params(&request.stream_id);
+    let params = GpccParams {
+        position_step: request.position_step.unwrap_or(current.position_step),
+        attribute_qp: request.attribute_qp.unwrap_or(current.attribute_qp),
+    };
+    match params.validate() {
+        Ok(()) => {
+            state.stream_manager.set_gpcc_params(&request.stream_id, params);
+            info!("G-PCC settings of stream_id {} set to {:?}", request.stream_id, params);
+            Json(UpdateStreamSettingsResponse {
+                message: format!("G-PCC settings of stream_id {} set to {:?}", request.stream_id, params),
+            })
+        }
+        Err(e) => {
+            warn!("Invalid G-PCC settings for stream_id {}: {}", request.stream_id, e);
+            Json(UpdateStreamSettingsResponse {
+                message: format!("Invalid G-PCC settings: {}", e),
+            })
+        }
+    }
+}
@@ -0,0 +1,45 @@
+// handlers/tiles.rs
+
+use axum::Json;
+use serde::{Deserialize, Serialize};
+use tracing::{debug, instrument};
+
+use crate::egress::tile_priority::{get_tile_priorities, TilePriorityReport};
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct TilePriorityRequest {
+    /// Identifies the viewer, its previous reports on the same tiles are replaced
+    pub viewer_id: String,
+    pub tiles: Vec<TilePriorityReport>,
+}
+
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct TilePriority {
+    pub client_id: u64,
+    pub tile_index: u32,
+    pub priority: f32,
+}
+
+/// Reports the visibility and importance of tiles for a viewer that is not connected over Socket.IO.
+#[instrument(skip_all)]
+pub async fn report_tile_priorities(Json(request): Json<TilePriorityRequest>) -> Json<Vec<TilePriority>> {
+    debug!("Viewer {} reported on {} tiles", request.viewer_id, request.tiles.len());
+    get_tile_priorities().report(&request.viewer_id, &request.tiles);
+    list_tile_priorities().await
+}
+
+/// Lists the priority of every tile a viewer reported on, the highest priority first.
+#[instrument(skip_all)]
+pub async fn list_tile_priorities() -> Json<Vec<TilePriority>> {
+    Json(get_tile_priorities()
+        .list()
+        .into_iter()
+        .map(|(tile, priority)| TilePriority {
+            client_id: tile.client_id,
+            tile_index: tile.tile_index,
+            priority,
+        })
+        .collect())
+}
@@ -1,10 +1,16 @@
 // handlers/websocket.rs
 
-use axum::{extract::{Query, State}, Json};
+use axum::{extract::{Query, State}, http::header::AUTHORIZATION, Json};
 use serde_json::Value;
-use tracing::{debug, error, info, instrument};
+use tracing::{debug, error, info, instrument, warn};
 use std::sync::Arc;
-use crate::{services, types::{AppState, WebRtcOffer, WebRtcIceCandidate}};
+use crate::egress::flute_feedback::FluteObjectReport;
+use crate::egress::projection::ProjectionPose;
+use crate::egress::tile_priority::{get_tile_priorities, TilePriorityReport};
+use crate::egress::websocket::compression_room;
+use crate::services::auth::bearer_token;
+use shared_utils::frame_compression::FrameCompression;
+use crate::{services, types::{AppState, WebRtcOffer, WebRtcIceCandidate, WebRtcQualitySelection}};
 use socketioxide::{extract::{Data, SocketRef}, layer::SocketIoLayer, socket::DisconnectReason, SendError, SocketError, SocketIo};
 use serde::{Deserialize, Serialize};
 
@@ -72,16 +78,38 @@ pub fn create_websocket_router_layer(stream_manager: Arc<services::stream_manage
     // Track connections and disconnections in "/" the namespace
     let io_clone = io.clone();
     //let io_clone2 = io.clone();
-    io_clone.ns("/", move |socket: SocketRef| async move {
+    io_clone.ns("/", move |socket: SocketRef, Data(auth): Data<Value>| async move {
         let socket_id = socket.id.to_string();
         debug!("Setting up websocket connection with id {:#?}", socket_id);
 
+        // Viewers connect without a token as well, so an unauthenticated socket stays connected but its frames are not accepted
+        // The WebRTC ingress publishes the frames of the data channels of the socket under the socket id
+        let token = handshake_token(&socket, &auth);
+        let stream_id = format!("ws_{}", socket_id);
+        let authorized = stream_manager.authorize_producer(&stream_id, token.as_deref())
+            .and_then(|_| stream_manager.authorize_producer(&socket_id, token.as_deref()));
+        if let Err(e) = &authorized {
+            if token.is_some() {
+                warn!("Socket {} is not allowed to publish frames: {}", socket_id, e);
+            } else {
+                debug!("Socket {} is not allowed to publish frames: {}", socket_id, e);
+            }
+        }
+
         let stream_manager_clone = Arc::clone(&stream_manager);
         //let socket_id_clone = socket_id.clone();
         socket.on_disconnect(move |socket: SocketRef, reason: DisconnectReason| async move {
             let stream_manager = Arc::clone(&stream_manager_clone);
             info!("Socket {} on ns {} disconnected, reason: {:?}", socket.id, socket.ns(), reason);
-            socket.leave("broadcast").unwrap();
+            for compression in FrameCompression::ALL {
+                let _ = socket.leave(compression_room(compression));
+            }
+            get_tile_priorities().remove_viewer(&socket.id.to_string());
+            stream_manager.revoke_producer(&format!("ws_{}", socket.id));
+            stream_manager.revoke_producer(&socket.id.to_string());
+            if let Some(projection_egress) = stream_manager.get_projection_egress() {
+                projection_egress.close_session(&socket.id.to_string());
+            }
 
             // Clean up our data channels
             {
@@ -97,16 +125,100 @@ pub fn create_websocket_router_layer(stream_manager: Arc<services::stream_manage
         });
 
         // Setup websocket ingress
-        let socket_id_clone = socket_id.clone();
         let stream_manager_clone = Arc::clone(&stream_manager);
-        let stream_id = format!("ws_{}", socket_id_clone);
-        if let Some(ws_ingress) = stream_manager_clone.get_websocket_ingress() {
+        if let Some(ws_ingress) = stream_manager_clone.get_websocket_ingress().filter(|_| authorized.is_ok()) {
             ws_ingress.add_socket(stream_id.clone(), socket.clone().into());
         };
 
         // Setup websocket egress
         let _ = socket.join("broadcast"); // Join the broadcast room
 
+        // Client -> Server: "frame:compression", the compression of the broadcasted frames: none, deflate or zstd
+        socket.on("frame:compression", |s: SocketRef, Data::<String>(compression)| async move {
+            let compression = match FrameCompression::parse(&compression) {
+                Ok(compression) => compression,
+                Err(e) => {
+                    warn!("Socket {} requested an unsupported compression: {}", s.id, e);
+                    FrameCompression::None
+                }
+            };
+            for other in FrameCompression::ALL {
+                let _ = s.leave(compression_room(other));
+            }
+            let _ = s.join(compression_room(compression));
+            info!("Socket {} receives the frames with {} compression", s.id, compression.name());
+        });
+
+        // Client -> Server: "tile:priority", the visibility and importance of the tiles in the viewport of the client
+        socket.on("tile:priority", |s: SocketRef, Data::<Vec<TilePriorityReport>>(reports)| async move {
+            debug!("Socket {} reported on {} tiles", s.id, reports.len());
+            get_tile_priorities().report(&s.id.to_string(), &reports);
+        });
+
+        // Client -> Server: "flute:feedback", the objects the client received over FLUTE
+        socket.on("flute:feedback", {
+            let stream_manager_clone = stream_manager.clone();
+            move |s: SocketRef, Data::<Vec<FluteObjectReport>>(reports)| {
+                let stream_manager_clone = stream_manager_clone.clone();
+                async move {
+                    if let Some(flute_egress) = stream_manager_clone.get_flute_egress() {
+                        flute_egress.report_object_feedback(&s.id.to_string(), &reports);
+                    }
+                }
+            }
+        });
+
+        // Client -> Server: "projection_offer", the WebRTC offer of a client that receives the point cloud as video
+        socket.on("projection_offer", {
+            let stream_manager_clone = stream_manager.clone();
+            move |s: SocketRef, Data::<WebRtcOffer>(offer)| {
+                let socket_id = s.id.to_string();
+                let stream_manager_clone = stream_manager_clone.clone();
+                async move {
+                    if let Some(projection_egress) = stream_manager_clone.get_projection_egress() {
+                        let rt = projection_egress.get_runtime();
+                        rt.spawn(async move {
+                            if let Err(e) = projection_egress.handle_client_offer(socket_id, offer, s).await {
+                                error!("Error in the projection offer: {:?}", e);
+                            }
+                        });
+                    } else {
+                        warn!("Socket {} requested a projection, but the projection egress is disabled", socket_id);
+                    }
+                }
+            }
+        });
+
+        // Client -> Server: "projection_ice_candidate"
+        socket.on("projection_ice_candidate", {
+            let stream_manager_clone = stream_manager.clone();
+            move |s: SocketRef, Data(candidate): Data<WebRtcIceCandidate>| {
+                let socket_id = s.id.to_string();
+                let stream_manager_clone = stream_manager_clone.clone();
+                async move {
+                    if let Some(projection_egress) = stream_manager_clone.get_projection_egress() {
+                        let rt = projection_egress.get_runtime();
+                        rt.spawn(async move {
+                            let _ = projection_egress.handle_client_ice_candidate(socket_id, candidate).await;
+                        });
+                    }
+                }
+            }
+        });
+
+        // Client -> Server: "projection:pose", the camera pose the projection of the client is rendered from
+        socket.on("projection:pose", {
+            let stream_manager_clone = stream_manager.clone();
+            move |s: SocketRef, Data::<ProjectionPose>(pose)| {
+                let stream_manager_clone = stream_manager_clone.clone();
+                async move {
+                    if let Some(projection_egress) = stream_manager_clone.get_projection_egress() {
+                        projection_egress.set_pose(&s.id.to_string(), pose);
+                    }
+                }
+            }
+        });
+
         // 1) Client -> Server: "webrtc_offer"
         //    Contains { sdp, client_id }
         let stream_manager_clone = stream_manager.clone();
@@ -157,6 +269,25 @@ pub fn create_websocket_router_layer(stream_manager: Arc<services::stream_manage
             }
         });
 
+        // 3) Quality layer selection from the client
+        socket.on("webrtc_select_quality", {
+            let stream_manager_clone = stream_manager.clone();
+            move |s: SocketRef, Data(selection): Data<WebRtcQualitySelection>| {
+                let socket_id = s.id.to_string();
+                async move {
+                    if let Some(webrtc_egress) = stream_manager_clone.get_webrtc_egress() {
+                        let rt = webrtc_egress.get_runtime();
+                        let _g = rt.enter();
+                        rt.spawn(async move {
+                            if let Err(e) = webrtc_egress.select_quality_layer(&socket_id, &selection.rid).await {
+                                error!("Error in select_quality_layer: {}", e);
+                            }
+                        });
+                    }
+                }
+            }
+        });
+
         // There are two issues with the Rust socket.io libraries for the server and the client:
         // 1. The server library (socketioxide) -for some reason- occasionaly closes the first socket connection some short time after the client connects. It is not clear why this happens. Luckily, the client library (rust-socketio) is able to reconnect automatically. However, the server leaves the closed socket in the active list and sometimes does not detect the closed connection.
         // 2. The client library (rust-socketio) does not provide any ability to get the socket id of the client.
@@ -221,4 +352,16 @@ pub fn create_websocket_router_layer(stream_manager: Arc<services::stream_manage
 
     let io_clone = io.clone();
     (layer, io_clone)
-}
\ No newline at end of file
+}
+
+/// Returns the token a client presented in its socket.io handshake, either as `auth: { token }` or as an `Authorization: Bearer` header.
+fn handshake_token(socket: &SocketRef, auth: &Value) -> Option<String> {
+    if let Some(token) = auth.get("token").and_then(Value::as_str) {
+        return Some(token.to_string());
+    }
+    socket.req_parts().headers
+        .get(AUTHORIZATION)
+        .and_then(|header| header.to_str().ok())
+        .and_then(bearer_token)
+        .map(str::to_string)
+}
@@ -0,0 +1,777 @@
+// ingress/capture.rs
+
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+use crate::processing::ProcessingPipeline;
+use crate::services::shutdown::is_shutting_down;
+use crate::services::stream_manager::StreamManager;
+use shared_utils::error::{XrError, XrResult};
+use shared_utils::types::{Point3D, PointCloudData};
+
+use tracing::{debug, error, info, instrument, warn};
+
+/// How long to wait for a frame of the camera before checking whether the server shuts down
+const CAPTURE_TIMEOUT: Duration = Duration::from_millis(500);
+/// How many frames may fail in a row before the camera is opened again
+const MAX_CONSECUTIVE_ERRORS: u32 = 30;
+/// How long to wait before opening the camera again after it was lost
+const REOPEN_DELAY: Duration = Duration::from_secs(2);
+
+/// The depth camera the capture ingress reads from.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CaptureDeviceKind {
+    /// Intel RealSense D400/L500 series, through librealsense2
+    RealSense,
+    /// Azure Kinect DK, through the Azure Kinect Sensor SDK (k4a)
+    AzureKinect,
+}
+
+impl CaptureDeviceKind {
+    /// Only available when the server is built with the `capture` feature
+    pub fn parse(value: &str) -> XrResult<Self> {
+        let device = match value.trim().to_lowercase().as_str() {
+            "realsense" => CaptureDeviceKind::RealSense,
+            "kinect" | "azure-kinect" | "k4a" => CaptureDeviceKind::AzureKinect,
+            other => return Err(XrError::Config(format!("Unsupported capture device {other}, expected realsense or kinect"))),
+        };
+        if !cfg!(feature = "capture") {
+            return Err(XrError::Config("Capturing from a depth camera requires a server that is built with the capture feature".to_string()));
+        }
+        Ok(device)
+    }
+}
+
+/// Settings of the capture ingress.
+#[derive(Clone, Debug)]
+pub struct CaptureConfig {
+    pub device: CaptureDeviceKind,
+    /// Frame rate of the depth and color streams, the Azure Kinect supports 5, 15 and 30
+    pub fps: u32,
+    /// Points further away from the camera are dropped, in meters
+    pub max_depth: f32,
+    /// Only every n-th depth pixel of every n-th row becomes a point, to keep the point count manageable
+    pub decimation: u32,
+    /// Stream id the captured point clouds are pushed on
+    pub stream_id: String,
+}
+
+/// A depth camera that delivers colored point clouds.
+trait CaptureDevice: Send {
+    /// Waits for the next depth and color frame, `None` when no frame arrived in time.
+    /// The points are in meters, with y pointing up and the camera looking towards -z.
+    fn capture(&mut self, config: &CaptureConfig, timeout: Duration) -> XrResult<Option<Vec<Point3D>>>;
+}
+
+/// Capture Ingress module responsible for reading depth and color frames of a locally attached depth camera,
+/// so the server can act as the capture node without an external capture bridge.
+/// The vendor runtimes are loaded when the ingress starts, they do not have to be installed to build the server.
+#[derive(Debug)]
+pub struct CaptureIngress {
+    processing_pipeline: Arc<ProcessingPipeline>,
+    stream_manager: Arc<StreamManager>,
+    config: CaptureConfig,
+}
+
+impl CaptureIngress {
+    /// Initializes the Capture Ingress module, and starts capturing from the configured camera.
+    #[instrument(skip_all)]
+    pub fn initialize(
+        stream_manager: Arc<StreamManager>,
+        processing_pipeline: Arc<ProcessingPipeline>,
+        config: Option<CaptureConfig>,
+    ) {
+        let Some(config) = config else {
+            info!("No capture device configured, the capture ingress is disabled");
+            return;
+        };
+
+        // The camera is opened here, so a missing runtime or camera is reported at startup
+        let device = match open_device(&config) {
+            Ok(device) => device,
+            Err(e) => {
+                error!("Failed to start the capture ingress: {}", e);
+                return;
+            }
+        };
+        info!("Capturing from the {:?} camera at {} fps on stream {}", config.device, config.fps, config.stream_id);
+
+        let instance = Arc::new(Self {
+            processing_pipeline,
+            stream_manager: stream_manager.clone(),
+            config,
+        });
+
+        let instance_clone = instance.clone();
+        let _ = thread::Builder::new().name("CAP_I Thread".to_string()).spawn(move || {
+            instance_clone.capture(device);
+        });
+
+        // Store the instance in the StreamManager
+        stream_manager.set_capture_ingress(instance.clone());
+    }
+
+    /// Pushes every captured point cloud into the pipeline, and opens the camera again when it is lost, until the server shuts down.
+    fn capture(&self, mut device: Box<dyn CaptureDevice>) {
+        let mut consecutive_errors = 0;
+        loop {
+            if is_shutting_down() {
+                debug!("Stopping the capture ingress");
+                break;
+            }
+
+            match device.capture(&self.config, CAPTURE_TIMEOUT) {
+                Ok(Some(points)) => {
+                    consecutive_errors = 0;
+                    self.push_point_cloud(points);
+                }
+                Ok(None) => {}
+                Err(e) => {
+                    consecutive_errors += 1;
+                    debug!("Failed to capture a frame: {}", e);
+                    if consecutive_errors < MAX_CONSECUTIVE_ERRORS {
+                        continue;
+                    }
+                    warn!("Lost the {:?} camera: {}", self.config.device, e);
+                    // The old device has to be closed before the camera can be opened again
+                    drop(device);
+                    device = loop {
+                        if is_shutting_down() {
+                            return;
+                        }
+                        thread::sleep(REOPEN_DELAY);
+                        match open_device(&self.config) {
+                            Ok(device) => break device,
+                            Err(e) => debug!("Failed to open the {:?} camera again: {}", self.config.device, e),
+                        }
+                    };
+                    info!("Reopened the {:?} camera", self.config.device);
+                    consecutive_errors = 0;
+                }
+            }
+        }
+    }
+
+    /// The captured point clouds are not encoded, so they skip the decoder and are processed right away.
+    fn push_point_cloud(&self, points: Vec<Point3D>) {
+        let settings = self.stream_manager.get_stream_settings(&self.config.stream_id);
+        if !settings.process_incoming_frames {
+            return;
+        }
+        let mut point_cloud = PointCloudData {
+            points,
+            ..Default::default()
+        };
+        if let Some(offset) = settings.presentation_time_offset {
+            point_cloud.presentation_time = point_cloud.creation_time.saturating_add(offset);
+        }
+        self.processing_pipeline.process_frame(point_cloud, self.stream_manager.clone(), self.config.stream_id.clone());
+    }
+}
+
+#[cfg(feature = "capture")]
+fn open_device(config: &CaptureConfig) -> XrResult<Box<dyn CaptureDevice>> {
+    match config.device {
+        CaptureDeviceKind::RealSense => Ok(Box::new(realsense::RealSenseDevice::open(config)?)),
+        CaptureDeviceKind::AzureKinect => Ok(Box::new(kinect::KinectDevice::open(config)?)),
+    }
+}
+
+#[cfg(not(feature = "capture"))]
+fn open_device(config: &CaptureConfig) -> XrResult<Box<dyn CaptureDevice>> {
+    Err(XrError::Config(format!("Capturing from the {:?} camera requires a server that is built with the capture feature", config.device)))
+}
+
+/// Loads the first of the candidates that can be loaded.
+#[cfg(feature = "capture")]
+fn load_library(candidates: &[&str], runtime_name: &str) -> XrResult<libloading::Library> {
+    // Safety: loading the runtime runs its initializers, which have no requirements
+    candidates
+        .iter()
+        .find_map(|candidate| unsafe { libloading::Library::new(candidate) }.ok())
+        .ok_or_else(|| XrError::Config(format!("Could not load the {runtime_name} runtime ({}), is it installed?", candidates[0])))
+}
+
+#[cfg(feature = "capture")]
+mod realsense {
+    use std::ffi::{c_char, c_float, c_int, c_uint, c_void, CStr};
+    use std::time::Duration;
+
+    use libloading::Library;
+    use shared_utils::error::{XrError, XrResult};
+    use shared_utils::types::Point3D;
+
+    use super::{load_library, CaptureConfig, CaptureDevice};
+
+    const RS2_STREAM_DEPTH: c_int = 1;
+    const RS2_STREAM_COLOR: c_int = 2;
+    const RS2_FORMAT_Z16: c_int = 1;
+    const RS2_FORMAT_RGB8: c_int = 5;
+    const WIDTH: c_int = 640;
+    const HEIGHT: c_int = 480;
+
+    type Rs2Error = *mut c_void;
+
+    /// `rs2_intrinsics`
+    #[repr(C)]
+    #[derive(Default)]
+    struct Rs2Intrinsics {
+        width: c_int,
+        height: c_int,
+        ppx: c_float,
+        ppy: c_float,
+        fx: c_float,
+        fy: c_float,
+        model: c_int,
+        coeffs: [c_float; 5],
+    }
+
+    /// `rs2_extrinsics`, the rotation is column-major
+    #[repr(C)]
+    #[derive(Default)]
+    struct Rs2Extrinsics {
+        rotation: [c_float; 9],
+        translation: [c_float; 3],
+    }
+
+    /// The functions of librealsense2 that the ingress uses.
+    struct RealSenseLibrary {
+        get_api_version: unsafe extern "C" fn(*mut Rs2Error) -> c_int,
+        create_context: unsafe extern "C" fn(c_int, *mut Rs2Error) -> *mut c_void,
+        delete_context: unsafe extern "C" fn(*mut c_void),
+        create_pipeline: unsafe extern "C" fn(*mut c_void, *mut Rs2Error) -> *mut c_void,
+        delete_pipeline: unsafe extern "C" fn(*mut c_void),
+        create_config: unsafe extern "C" fn(*mut Rs2Error) -> *mut c_void,
+        delete_config: unsafe extern "C" fn(*mut c_void),
+        config_enable_stream: unsafe extern "C" fn(*mut c_void, c_int, c_int, c_int, c_int, c_int, c_int, *mut Rs2Error),
+        pipeline_start_with_config: unsafe extern "C" fn(*mut c_void, *mut c_void, *mut Rs2Error) -> *mut c_void,
+        delete_pipeline_profile: unsafe extern "C" fn(*mut c_void),
+        pipeline_stop: unsafe extern "C" fn(*mut c_void, *mut Rs2Error),
+        pipeline_try_wait_for_frames: unsafe extern "C" fn(*mut c_void, *mut *mut c_void, c_uint, *mut Rs2Error) -> c_int,
+        embedded_frames_count: unsafe extern "C" fn(*mut c_void, *mut Rs2Error) -> c_int,
+        extract_frame: unsafe extern "C" fn(*mut c_void, c_int, *mut Rs2Error) -> *mut c_void,
+        release_frame: unsafe extern "C" fn(*mut c_void),
+        get_frame_stream_profile: unsafe extern "C" fn(*const c_void, *mut Rs2Error) -> *const c_void,
+        get_stream_profile_data: unsafe extern "C" fn(*const c_void, *mut c_int, *mut c_int, *mut c_int, *mut c_int, *mut c_int, *mut Rs2Error),
+        get_video_stream_intrinsics: unsafe extern "C" fn(*const c_void, *mut Rs2Intrinsics, *mut Rs2Error),
+        get_extrinsics: unsafe extern "C" fn(*const c_void, *const c_void, *mut Rs2Extrinsics, *mut Rs2Error),
+        get_frame_data: unsafe extern "C" fn(*const c_void, *mut Rs2Error) -> *const c_void,
+        get_frame_width: unsafe extern "C" fn(*const c_void, *mut Rs2Error) -> c_int,
+        get_frame_height: unsafe extern "C" fn(*const c_void, *mut Rs2Error) -> c_int,
+        get_frame_stride_in_bytes: unsafe extern "C" fn(*const c_void, *mut Rs2Error) -> c_int,
+        depth_frame_get_units: unsafe extern "C" fn(*const c_void, *mut Rs2Error) -> c_float,
+        get_error_message: unsafe extern "C" fn(Rs2Error) -> *const c_char,
+        free_error: unsafe extern "C" fn(Rs2Error),
+        // Keeps the functions above valid
+        _library: Library,
+    }
+
+    impl RealSenseLibrary {
+        fn load() -> XrResult<Self> {
+            let candidates: &[&str] = if cfg!(target_os = "windows") {
+                &["realsense2.dll"]
+            } else if cfg!(target_os = "macos") {
+                &["librealsense2.dylib"]
+            } else {
+                &["librealsense2.so", "librealsense2.so.2"]
+            };
+            let library = load_library(candidates, "librealsense2")?;
+
+            // Safety: the signatures match the declarations in librealsense2/rs.h
+            unsafe {
+                Ok(Self {
+                    get_api_version: *library.get(b"rs2_get_api_version\0").map_err(XrError::config)?,
+                    create_context: *library.get(b"rs2_create_context\0").map_err(XrError::config)?,
+                    delete_context: *library.get(b"rs2_delete_context\0").map_err(XrError::config)?,
+                    create_pipeline: *library.get(b"rs2_create_pipeline\0").map_err(XrError::config)?,
+                    delete_pipeline: *library.get(b"rs2_delete_pipeline\0").map_err(XrError::config)?,
+                    create_config: *library.get(b"rs2_create_config\0").map_err(XrError::config)?,
+                    delete_config: *library.get(b"rs2_delete_config\0").map_err(XrError::config)?,
+                    config_enable_stream: *library.get(b"rs2_config_enable_stream\0").map_err(XrError::config)?,
+                    pipeline_start_with_config: *library.get(b"rs2_pipeline_start_with_config\0").map_err(XrError::config)?,
+                    delete_pipeline_profile: *library.get(b"rs2_delete_pipeline_profile\0").map_err(XrError::config)?,
+                    pipeline_stop: *library.get(b"rs2_pipeline_stop\0").map_err(XrError::config)?,
+                    pipeline_try_wait_for_frames: *library.get(b"rs2_pipeline_try_wait_for_frames\0").map_err(XrError::config)?,
+                    embedded_frames_count: *library.get(b"rs2_embedded_frames_count\0").map_err(XrError::config)?,
+                    extract_frame: *library.get(b"rs2_extract_frame\0").map_err(XrError::config)?,
+                    release_frame: *library.get(b"rs2_release_frame\0").map_err(XrError::config)?,
+                    get_frame_stream_profile: *library.get(b"rs2_get_frame_stream_profile\0").map_err(XrError::config)?,
+                    get_stream_profile_data: *library.get(b"rs2_get_stream_profile_data\0").map_err(XrError::config)?,
+                    get_video_stream_intrinsics: *library.get(b"rs2_get_video_stream_intrinsics\0").map_err(XrError::config)?,
+                    get_extrinsics: *library.get(b"rs2_get_extrinsics\0").map_err(XrError::config)?,
+                    get_frame_data: *library.get(b"rs2_get_frame_data\0").map_err(XrError::config)?,
+                    get_frame_width: *library.get(b"rs2_get_frame_width\0").map_err(XrError::config)?,
+                    get_frame_height: *library.get(b"rs2_get_frame_height\0").map_err(XrError::config)?,
+                    get_frame_stride_in_bytes: *library.get(b"rs2_get_frame_stride_in_bytes\0").map_err(XrError::config)?,
+                    depth_frame_get_units: *library.get(b"rs2_depth_frame_get_units\0").map_err(XrError::config)?,
+                    get_error_message: *library.get(b"rs2_get_error_message\0").map_err(XrError::config)?,
+                    free_error: *library.get(b"rs2_free_error\0").map_err(XrError::config)?,
+                    _library: library,
+                })
+            }
+        }
+
+        /// Makes a call that reports its errors through an `rs2_error`, and turns that error into an `XrError`.
+        unsafe fn call<T>(&self, name: &str, call: impl FnOnce(*mut Rs2Error) -> T) -> XrResult<T> {
+            let mut error: Rs2Error = std::ptr::null_mut();
+            let result = call(&mut error);
+            if error.is_null() {
+                return Ok(result);
+            }
+            // Safety: the error was returned by librealsense2, and is freed exactly once
+            let message = CStr::from_ptr((self.get_error_message)(error)).to_string_lossy().into_owned();
+            (self.free_error)(error);
+            Err(XrError::Transport(format!("{name} failed: {message}")))
+        }
+    }
+
+    /// A RealSense camera that streams depth and color.
+    pub struct RealSenseDevice {
+        library: RealSenseLibrary,
+        context: *mut c_void,
+        pipeline: *mut c_void,
+        profile: *mut c_void,
+    }
+
+    // Safety: a RealSense pipeline may be used from any thread, the ingress only uses it from its capture thread
+    unsafe impl Send for RealSenseDevice {}
+
+    impl RealSenseDevice {
+        pub fn open(config: &CaptureConfig) -> XrResult<Self> {
+            let library = RealSenseLibrary::load()?;
+            // Safety: every handle is checked before it is used, and deleted by `Drop` or below
+            unsafe {
+                let api_version = library.call("rs2_get_api_version", |error| (library.get_api_version)(error))?;
+                let context = library.call("rs2_create_context", |error| (library.create_context)(api_version, error))?;
+                let mut device = Self {
+                    library,
+                    context,
+                    pipeline: std::ptr::null_mut(),
+                    profile: std::ptr::null_mut(),
+                };
+                let library = &device.library;
+                device.pipeline = library.call("rs2_create_pipeline", |error| (library.create_pipeline)(context, error))?;
+
+                let rs_config = library.call("rs2_create_config", |error| (library.create_config)(error))?;
+                let fps = config.fps as c_int;
+                let result = library
+                    .call("rs2_config_enable_stream", |error| {
+                        (library.config_enable_stream)(rs_config, RS2_STREAM_DEPTH, -1, WIDTH, HEIGHT, RS2_FORMAT_Z16, fps, error)
+                    })
+                    .and_then(|_| {
+                        library.call("rs2_config_enable_stream", |error| {
+                            (library.config_enable_stream)(rs_config, RS2_STREAM_COLOR, -1, WIDTH, HEIGHT, RS2_FORMAT_RGB8, fps, error)
+                        })
+                    })
+                    .and_then(|_| {
+                        library.call("rs2_pipeline_start_with_config", |error| (library.pipeline_start_with_config)(device.pipeline, rs_config, error))
+                    });
+                (library.delete_config)(rs_config);
+                device.profile = result?;
+                Ok(device)
+            }
+        }
+
+        /// Reads the stream type of a frame, its profile and its intrinsics.
+        unsafe fn frame_profile(&self, frame: *mut c_void) -> XrResult<(c_int, *const c_void, Rs2Intrinsics)> {
+            let library = &self.library;
+            let profile = library.call("rs2_get_frame_stream_profile", |error| (library.get_frame_stream_profile)(frame, error))?;
+            let (mut stream, mut format, mut index, mut unique_id, mut framerate) = (0, 0, 0, 0, 0);
+            library.call("rs2_get_stream_profile_data", |error| {
+                (library.get_stream_profile_data)(profile, &mut stream, &mut format, &mut index, &mut unique_id, &mut framerate, error)
+            })?;
+            let mut intrinsics = Rs2Intrinsics::default();
+            library.call("rs2_get_video_stream_intrinsics", |error| (library.get_video_stream_intrinsics)(profile, &mut intrinsics, error))?;
+            Ok((stream, profile, intrinsics))
+        }
+
+        /// Reads the pixels of a frame, with its width, height and stride.
+        unsafe fn frame_pixels(&self, frame: *mut c_void) -> XrResult<(&[u8], usize, usize, usize)> {
+            let library = &self.library;
+            let width = library.call("rs2_get_frame_width", |error| (library.get_frame_width)(frame, error))? as usize;
+            let height = library.call("rs2_get_frame_height", |error| (library.get_frame_height)(frame, error))? as usize;
+            let stride = library.call("rs2_get_frame_stride_in_bytes", |error| (library.get_frame_stride_in_bytes)(frame, error))? as usize;
+            let data = library.call("rs2_get_frame_data", |error| (library.get_frame_data)(frame, error))? as *const u8;
+            Ok((std::slice::from_raw_parts(data, stride * height), width, height, stride))
+        }
+
+        /// Deprojects the depth frame, and looks up the color of every point by projecting it onto the color frame.
+        unsafe fn to_points(&self, config: &CaptureConfig, depth: *mut c_void, color: *mut c_void) -> XrResult<Vec<Point3D>> {
+            let library = &self.library;
+            let (_, depth_profile, depth_intrinsics) = self.frame_profile(depth)?;
+            let (_, color_profile, color_intrinsics) = self.frame_profile(color)?;
+            let mut extrinsics = Rs2Extrinsics::default();
+            library.call("rs2_get_extrinsics", |error| (library.get_extrinsics)(depth_profile, color_profile, &mut extrinsics, error))?;
+            let units = library.call("rs2_depth_frame_get_units", |error| (library.depth_frame_get_units)(depth, error))?;
+            let (depth_data, depth_width, depth_height, depth_stride) = self.frame_pixels(depth)?;
+            let (color_data, color_width, color_height, color_stride) = self.frame_pixels(color)?;
+
+            let rotation = &extrinsics.rotation;
+            let translation = &extrinsics.translation;
+            let step = config.decimation.max(1) as usize;
+            let mut points = Vec::with_capacity(depth_width * depth_height / (step * step));
+            for v in (0..depth_height).step_by(step) {
+                for u in (0..depth_width).step_by(step) {
+                    let offset = v * depth_stride + u * 2;
+                    let z = u16::from_le_bytes([depth_data[offset], depth_data[offset + 1]]) as f32 * units;
+                    if z <= 0.0 || z > config.max_depth {
+                        continue;
+                    }
+                    let x = (u as f32 - depth_intrinsics.ppx) / depth_intrinsics.fx * z;
+                    let y = (v as f32 - depth_intrinsics.ppy) / depth_intrinsics.fy * z;
+
+                    // Project the point onto the color frame, distortion is ignored
+                    let cx = rotation[0] * x + rotation[3] * y + rotation[6] * z + translation[0];
+                    let cy = rotation[1] * x + rotation[4] * y + rotation[7] * z + translation[1];
+                    let cz = rotation[2] * x + rotation[5] * y + rotation[8] * z + translation[2];
+                    if cz <= 0.0 {
+                        continue;
+                    }
+                    let cu = (cx / cz * color_intrinsics.fx + color_intrinsics.ppx).round();
+                    let cv = (cy / cz * color_intrinsics.fy + color_intrinsics.ppy).round();
+                    if cu < 0.0 || cv < 0.0 || cu as usize >= color_width || cv as usize >= color_height {
+                        continue;
+                    }
+                    let color_offset = cv as usize * color_stride + cu as usize * 3;
+                    points.push(Point3D {
+                        x,
+                        y: -y,
+                        z: -z,
+                        r: color_data[color_offset],
+                        g: color_data[color_offset + 1],
+                        b: color_data[color_offset + 2],
+                    });
+                }
+            }
+            Ok(points)
+        }
+    }
+
+    impl CaptureDevice for RealSenseDevice {
+        fn capture(&mut self, config: &CaptureConfig, timeout: Duration) -> XrResult<Option<Vec<Point3D>>> {
+            let library = &self.library;
+            // Safety: the frames are released once the points are computed
+            unsafe {
+                let mut frames: *mut c_void = std::ptr::null_mut();
+                let received = library.call("rs2_pipeline_try_wait_for_frames", |error| {
+                    (library.pipeline_try_wait_for_frames)(self.pipeline, &mut frames, timeout.as_millis() as c_uint, error)
+                })?;
+                if received == 0 || frames.is_null() {
+                    return Ok(None);
+                }
+
+                let mut depth = std::ptr::null_mut();
+                let mut color = std::ptr::null_mut();
+                let mut result = library.call("rs2_embedded_frames_count", |error| (library.embedded_frames_count)(frames, error));
+                if let Ok(count) = result {
+                    for index in 0..count {
+                        let frame = match library.call("rs2_extract_frame", |error| (library.extract_frame)(frames, index, error)) {
+                            Ok(frame) => frame,
+                            Err(e) => {
+                                result = Err(e);
+                                break;
+                            }
+                        };
+                        match self.frame_profile(frame) {
+                            Ok((RS2_STREAM_DEPTH, _, _)) if depth.is_null() => depth = frame,
+                            Ok((RS2_STREAM_COLOR, _, _)) if color.is_null() => color = frame,
+                            Ok(_) => (library.release_frame)(frame),
+                            Err(e) => {
+                                (library.release_frame)(frame);
+                                result = Err(e);
+                                break;
+                            }
+                        }
+                    }
+                }
+
+                let points = match result {
+                    Ok(_) if !depth.is_null() && !color.is_null() => self.to_points(config, depth, color).map(Some),
+                    Ok(_) => Ok(None),
+                    Err(e) => Err(e),
+                };
+                for frame in [depth, color, frames] {
+                    if !frame.is_null() {
+                        (library.release_frame)(frame);
+                    }
+                }
+                points
+            }
+        }
+    }
+
+    impl Drop for RealSenseDevice {
+        fn drop(&mut self) {
+            let library = &self.library;
+            // Safety: the handles are not used after they are deleted
+            unsafe {
+                if !self.profile.is_null() {
+                    let _ = library.call("rs2_pipeline_stop", |error| (library.pipeline_stop)(self.pipeline, error));
+                    (library.delete_pipeline_profile)(self.profile);
+                }
+                if !self.pipeline.is_null() {
+                    (library.delete_pipeline)(self.pipeline);
+                }
+                (library.delete_context)(self.context);
+            }
+        }
+    }
+}
+
+#[cfg(feature = "capture")]
+mod kinect {
+    use std::ffi::{c_int, c_void};
+    use std::time::Duration;
+
+    use libloading::Library;
+    use shared_utils::error::{XrError, XrResult};
+    use shared_utils::types::Point3D;
+
+    use super::{load_library, CaptureConfig, CaptureDevice};
+
+    const K4A_RESULT_SUCCEEDED: c_int = 0;
+    const K4A_WAIT_RESULT_SUCCEEDED: c_int = 0;
+    const K4A_WAIT_RESULT_TIMEOUT: c_int = 2;
+    const K4A_IMAGE_FORMAT_COLOR_BGRA32: c_int = 3;
+    const K4A_IMAGE_FORMAT_CUSTOM: c_int = 8;
+    const K4A_COLOR_RESOLUTION_720P: c_int = 1;
+    const K4A_DEPTH_MODE_NFOV_UNBINNED: c_int = 2;
+    const K4A_WIRED_SYNC_MODE_STANDALONE: c_int = 0;
+    const K4A_CALIBRATION_TYPE_DEPTH: c_int = 0;
+
+    /// `k4a_device_configuration_t`
+    #[repr(C)]
+    struct K4aDeviceConfiguration {
+        color_format: c_int,
+        color_resolution: c_int,
+        depth_mode: c_int,
+        camera_fps: c_int,
+        synchronized_images_only: bool,
+        depth_delay_off_color_usec: i32,
+        wired_sync_mode: c_int,
+        subordinate_delay_off_master_usec: u32,
+        disable_streaming_indicator: bool,
+    }
+
+    /// `k4a_calibration_t`, only the SDK reads it, so it is kept as an opaque buffer that is larger than the struct
+    #[repr(C, align(8))]
+    struct K4aCalibration([u8; 4096]);
+
+    /// The functions of the Azure Kinect Sensor SDK that the ingress uses.
+    struct KinectLibrary {
+        device_open: unsafe extern "C" fn(u32, *mut *mut c_void) -> c_int,
+        device_close: unsafe extern "C" fn(*mut c_void),
+        device_start_cameras: unsafe extern "C" fn(*mut c_void, *const K4aDeviceConfiguration) -> c_int,
+        device_stop_cameras: unsafe extern "C" fn(*mut c_void),
+        device_get_calibration: unsafe extern "C" fn(*mut c_void, c_int, c_int, *mut K4aCalibration) -> c_int,
+        device_get_capture: unsafe extern "C" fn(*mut c_void, *mut *mut c_void, i32) -> c_int,
+        capture_get_depth_image: unsafe extern "C" fn(*mut c_void) -> *mut c_void,
+        capture_get_color_image: unsafe extern "C" fn(*mut c_void) -> *mut c_void,
+        capture_release: unsafe extern "C" fn(*mut c_void),
+        image_create: unsafe extern "C" fn(c_int, c_int, c_int, c_int, *mut *mut c_void) -> c_int,
+        image_get_buffer: unsafe extern "C" fn(*mut c_void) -> *mut u8,
+        image_get_width_pixels: unsafe extern "C" fn(*mut c_void) -> c_int,
+        image_get_height_pixels: unsafe extern "C" fn(*mut c_void) -> c_int,
+        image_release: unsafe extern "C" fn(*mut c_void),
+        transformation_create: unsafe extern "C" fn(*const K4aCalibration) -> *mut c_void,
+        transformation_destroy: unsafe extern "C" fn(*mut c_void),
+        transformation_color_image_to_depth_camera: unsafe extern "C" fn(*mut c_void, *mut c_void, *mut c_void, *mut c_void) -> c_int,
+        transformation_depth_image_to_point_cloud: unsafe extern "C" fn(*mut c_void, *mut c_void, c_int, *mut c_void) -> c_int,
+        // Keeps the functions above valid
+        _library: Library,
+    }
+
+    impl KinectLibrary {
+        fn load() -> XrResult<Self> {
+            let candidates: &[&str] = if cfg!(target_os = "windows") {
+                &["k4a.dll"]
+            } else {
+                &["libk4a.so", "libk4a.so.1.4"]
+            };
+            let library = load_library(candidates, "Azure Kinect Sensor SDK")?;
+
+            // Safety: the signatures match the declarations in k4a/k4a.h
+            unsafe {
+                Ok(Self {
+                    device_open: *library.get(b"k4a_device_open\0").map_err(XrError::config)?,
+                    device_close: *library.get(b"k4a_device_close\0").map_err(XrError::config)?,
+                    device_start_cameras: *library.get(b"k4a_device_start_cameras\0").map_err(XrError::config)?,
+                    device_stop_cameras: *library.get(b"k4a_device_stop_cameras\0").map_err(XrError::config)?,
+                    device_get_calibration: *library.get(b"k4a_device_get_calibration\0").map_err(XrError::config)?,
+                    device_get_capture: *library.get(b"k4a_device_get_capture\0").map_err(XrError::config)?,
+                    capture_get_depth_image: *library.get(b"k4a_capture_get_depth_image\0").map_err(XrError::config)?,
+                    capture_get_color_image: *library.get(b"k4a_capture_get_color_image\0").map_err(XrError::config)?,
+                    capture_release: *library.get(b"k4a_capture_release\0").map_err(XrError::config)?,
+                    image_create: *library.get(b"k4a_image_create\0").map_err(XrError::config)?,
+                    image_get_buffer: *library.get(b"k4a_image_get_buffer\0").map_err(XrError::config)?,
+                    image_get_width_pixels: *library.get(b"k4a_image_get_width_pixels\0").map_err(XrError::config)?,
+                    image_get_height_pixels: *library.get(b"k4a_image_get_height_pixels\0").map_err(XrError::config)?,
+                    image_release: *library.get(b"k4a_image_release\0").map_err(XrError::config)?,
+                    transformation_create: *library.get(b"k4a_transformation_create\0").map_err(XrError::config)?,
+                    transformation_destroy: *library.get(b"k4a_transformation_destroy\0").map_err(XrError::config)?,
+                    transformation_color_image_to_depth_camera: *library.get(b"k4a_transformation_color_image_to_depth_camera\0").map_err(XrError::config)?,
+                    transformation_depth_image_to_point_cloud: *library.get(b"k4a_transformation_depth_image_to_point_cloud\0").map_err(XrError::config)?,
+                    _library: library,
+                })
+            }
+        }
+    }
+
+    /// An Azure Kinect that streams depth and BGRA color, the color is transformed to the depth camera by the SDK.
+    pub struct KinectDevice {
+        library: KinectLibrary,
+        device: *mut c_void,
+        transformation: *mut c_void,
+    }
+
+    // Safety: a k4a device may be used from any thread, the ingress only uses it from its capture thread
+    unsafe impl Send for KinectDevice {}
+
+    impl KinectDevice {
+        pub fn open(config: &CaptureConfig) -> XrResult<Self> {
+            let camera_fps = match config.fps {
+                5 => 0,
+                15 => 1,
+                30 => 2,
+                other => return Err(XrError::Config(format!("The Azure Kinect does not support {other} fps, expected 5, 15 or 30"))),
+            };
+            let library = KinectLibrary::load()?;
+            // Safety: every handle is checked before it is used, and released by `Drop`
+            unsafe {
+                let mut device = std::ptr::null_mut();
+                if (library.device_open)(0, &mut device) != K4A_RESULT_SUCCEEDED {
+                    return Err(XrError::Transport("Failed to open the Azure Kinect, is it connected?".to_string()));
+                }
+                let mut kinect = Self {
+                    library,
+                    device,
+                    transformation: std::ptr::null_mut(),
+                };
+                let library = &kinect.library;
+                let device_config = K4aDeviceConfiguration {
+                    color_format: K4A_IMAGE_FORMAT_COLOR_BGRA32,
+                    color_resolution: K4A_COLOR_RESOLUTION_720P,
+                    depth_mode: K4A_DEPTH_MODE_NFOV_UNBINNED,
+                    camera_fps,
+                    // Every point needs a color
+                    synchronized_images_only: true,
+                    depth_delay_off_color_usec: 0,
+                    wired_sync_mode: K4A_WIRED_SYNC_MODE_STANDALONE,
+                    subordinate_delay_off_master_usec: 0,
+                    disable_streaming_indicator: false,
+                };
+                let mut calibration = K4aCalibration([0; 4096]);
+                if (library.device_get_calibration)(device, device_config.depth_mode, device_config.color_resolution, &mut calibration) != K4A_RESULT_SUCCEEDED {
+                    return Err(XrError::Transport("Failed to read the calibration of the Azure Kinect".to_string()));
+                }
+                kinect.transformation = (library.transformation_create)(&calibration);
+                if kinect.transformation.is_null() {
+                    return Err(XrError::Transport("Failed to create the Azure Kinect transformation".to_string()));
+                }
+                if (library.device_start_cameras)(device, &device_config) != K4A_RESULT_SUCCEEDED {
+                    // The cameras are stopped by `Drop`, which is harmless when they did not start
+                    return Err(XrError::Transport("Failed to start the cameras of the Azure Kinect".to_string()));
+                }
+                Ok(kinect)
+            }
+        }
+
+        /// Turns the depth image into a point cloud, and colors it with the color image transformed to the depth camera.
+        unsafe fn to_points(&self, config: &CaptureConfig, depth: *mut c_void, color: *mut c_void) -> XrResult<Vec<Point3D>> {
+            let library = &self.library;
+            let width = (library.image_get_width_pixels)(depth);
+            let height = (library.image_get_height_pixels)(depth);
+
+            let mut transformed_color = std::ptr::null_mut();
+            let mut xyz = std::ptr::null_mut();
+            let mut result = Ok(());
+            if (library.image_create)(K4A_IMAGE_FORMAT_COLOR_BGRA32, width, height, width * 4, &mut transformed_color) != K4A_RESULT_SUCCEEDED
+                || (library.image_create)(K4A_IMAGE_FORMAT_CUSTOM, width, height, width * 3 * 2, &mut xyz) != K4A_RESULT_SUCCEEDED
+            {
+                result = Err(XrError::Transport("Failed to allocate the Azure Kinect images".to_string()));
+            } else if (library.transformation_color_image_to_depth_camera)(self.transformation, depth, color, transformed_color) != K4A_RESULT_SUCCEEDED
+                || (library.transformation_depth_image_to_point_cloud)(self.transformation, depth, K4A_CALIBRATION_TYPE_DEPTH, xyz) != K4A_RESULT_SUCCEEDED
+            {
+                result = Err(XrError::Transport("Failed to transform the Azure Kinect images".to_string()));
+            }
+
+            let points = result.map(|_| {
+                let (width, height) = (width as usize, height as usize);
+                // The point cloud holds x, y and z per pixel as 16 bit millimeters
+                let xyz_data = std::slice::from_raw_parts((library.image_get_buffer)(xyz) as *const i16, width * height * 3);
+                let color_data = std::slice::from_raw_parts((library.image_get_buffer)(transformed_color), width * height * 4);
+                let step = config.decimation.max(1) as usize;
+                let mut points = Vec::with_capacity(width * height / (step * step));
+                for v in (0..height).step_by(step) {
+                    for u in (0..width).step_by(step) {
+                        let index = v * width + u;
+                        let z = xyz_data[index * 3 + 2] as f32 / 1000.0;
+                        // Pixels without depth, or without color (fully transparent), are skipped
+                        if z <= 0.0 || z > config.max_depth || color_data[index * 4 + 3] == 0 {
+                            continue;
+                        }
+                        points.push(Point3D {
+                            x: xyz_data[index * 3] as f32 / 1000.0,
+                            y: -(xyz_data[index * 3 + 1] as f32 / 1000.0),
+                            z: -z,
+                            r: color_data[index * 4 + 2],
+                            g: color_data[index * 4 + 1],
+                            b: color_data[index * 4],
+                        });
+                    }
+                }
+                points
+            });
+
+            for image in [transformed_color, xyz] {
+                if !image.is_null() {
+                    (library.image_release)(image);
+                }
+            }
+            points
+        }
+    }
+
+    impl CaptureDevice for KinectDevice {
+        fn capture(&mut self, config: &CaptureConfig, timeout: Duration) -> XrResult<Option<Vec<Point3D>>> {
+            let library = &self.library;
+            // Safety: the capture and its images are released once the points are computed
+            unsafe {
+                let mut capture = std::ptr::null_mut();
+                match (library.device_get_capture)(self.device, &mut capture, timeout.as_millis() as i32) {
+                    K4A_WAIT_RESULT_SUCCEEDED => {}
+                    K4A_WAIT_RESULT_TIMEOUT => return Ok(None),
+                    _ => return Err(XrError::Transport("Failed to read a capture of the Azure Kinect".to_string())),
+                }
+                let depth = (library.capture_get_depth_image)(capture);
+                let color = (library.capture_get_color_image)(capture);
+                let points = if depth.is_null() || color.is_null() {
+                    Ok(None)
+                } else {
+                    self.to_points(config, depth, color).map(Some)
+                };
+                for image in [depth, color] {
+                    if !image.is_null() {
+                        (library.image_release)(image);
+                    }
+                }
+                (library.capture_release)(capture);
+                points
+            }
+        }
+    }
+
+    impl Drop for KinectDevice {
+        fn drop(&mut self) {
+            // Safety: the handles are not used after they are released
+            unsafe {
+                (self.library.device_stop_cameras)(self.device);
+                if !self.transformation.is_null() {
+                    (self.library.transformation_destroy)(self.transformation);
+                }
+                (self.library.device_close)(self.device);
+            }
+        }
+    }
+}
@@ -0,0 +1,149 @@
+// ingress/dash.rs
+
+use std::fmt;
+use std::sync::{Arc, OnceLock};
+use std::time::Duration;
+
+use crate::processing::ProcessingPipeline;
+use crate::services::shutdown::is_shutting_down;
+use crate::services::stream_manager::StreamManager;
+
+use dash_player::{DashEvent, DashPlayer};
+use tokio::runtime::{self, Runtime};
+use tokio::sync::Mutex;
+use tracing::{debug, error, info, instrument, warn};
+
+static DASH_INGRESS_RUNTIME: OnceLock<Arc<Runtime>> = OnceLock::new();
+
+/// How long to wait before fetching the MPD again when the upstream server cannot be reached
+const RETRY_DELAY: Duration = Duration::from_secs(2);
+
+/// Settings of the DASH ingress.
+#[derive(Clone, Debug)]
+pub struct DashIngressConfig {
+    /// MPD of the upstream server, e.g. http://192.168.1.20:3001/dash/client_0_0.mpd
+    pub mpd_url: String,
+    /// Latency the player aims for, the service description of the MPD is used without it
+    pub target_latency: Option<Duration>,
+    /// Prefix of the stream ids, every adaptation set of the MPD becomes the stream `{prefix}_{adaptation set id}`
+    pub stream_id_prefix: String,
+}
+
+/// DASH Ingress module responsible for subscribing to the DASH output of another server, so servers can be cascaded into relay topologies.
+/// The segments are demuxed by the embedded DASH player, and every sample is decoded and egressed again like frames of any other ingress.
+pub struct DashIngress {
+    processing_pipeline: Arc<ProcessingPipeline>,
+    stream_manager: Arc<StreamManager>,
+    config: DashIngressConfig,
+    /// The player of the upstream MPD, `None` until the MPD could be fetched
+    player: Mutex<Option<DashPlayer>>,
+}
+
+impl fmt::Debug for DashIngress {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("DashIngress")
+            .field("processing_pipeline", &self.processing_pipeline)
+            .field("config", &self.config)
+            .finish()
+    }
+}
+
+impl DashIngress {
+    /// Initializes the DASH Ingress module, and starts playing the upstream MPD.
+    #[instrument(skip_all)]
+    pub fn initialize(
+        stream_manager: Arc<StreamManager>,
+        processing_pipeline: Arc<ProcessingPipeline>,
+        config: Option<DashIngressConfig>,
+    ) {
+        let Some(config) = config else {
+            info!("No upstream MPD configured, the DASH ingress is disabled");
+            return;
+        };
+
+        let instance = Arc::new(Self {
+            processing_pipeline,
+            stream_manager: stream_manager.clone(),
+            config,
+            player: Mutex::new(None),
+        });
+
+        let instance_clone = instance.clone();
+        instance.get_runtime().spawn(async move {
+            instance_clone.start_player().await;
+        });
+
+        // Store the instance in the StreamManager
+        stream_manager.set_dash_ingress(instance.clone());
+    }
+
+    #[instrument(skip_all)]
+    pub fn get_runtime(&self) -> Arc<Runtime> {
+        DASH_INGRESS_RUNTIME.get_or_init(|| {
+            let rt = runtime::Builder::new_multi_thread()
+                .worker_threads(2)
+                .thread_name_fn(|| {
+                    static ATOMIC_DASH_I_ID: std::sync::atomic::AtomicUsize = std::sync::atomic::AtomicUsize::new(0);
+                    let id = ATOMIC_DASH_I_ID.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+                    format!("DASH_I w-{}", id)
+                })
+                .enable_all()
+                .build().unwrap();
+            Arc::new(rt)
+        }).clone()
+    }
+
+    /// Fetches the upstream MPD until it is available, and starts fetching the segments of all adaptation sets.
+    async fn start_player(self: Arc<Self>) {
+        let self_clone = self.clone();
+        let callback = Arc::new(move |event: DashEvent| self_clone.handle_event(event));
+
+        let player = loop {
+            if is_shutting_down() {
+                return;
+            }
+            match DashPlayer::new(&self.config.mpd_url, callback.clone()).await {
+                Ok(player) => break player,
+                Err(e) => {
+                    warn!("Failed to fetch the upstream MPD {}: {}", self.config.mpd_url, e);
+                    tokio::time::sleep(RETRY_DELAY).await;
+                }
+            }
+        };
+
+        // The samples are the encoded frames, the segments themselves are of no use to the decoder
+        player.set_demux_segments(true);
+        if let Some(target_latency) = self.config.target_latency {
+            player.set_target_latency(target_latency.as_secs_f64()).await;
+        }
+        if let Err(e) = player.start().await {
+            error!("Failed to start playing the upstream MPD {}: {}", self.config.mpd_url, e);
+            return;
+        }
+        info!("DASH ingress relays {}", self.config.mpd_url);
+        *self.player.lock().await = Some(player);
+    }
+
+    fn handle_event(&self, event: DashEvent) {
+        match event {
+            DashEvent::Sample { adaptation_set_id, sample, .. } => {
+                // One stream per adaptation set, so switching between its representations does not start a new stream,
+                // and representations with the same id in different adaptation sets do not end up in one stream
+                let stream_id = format!("{}_{}", self.config.stream_id_prefix, adaptation_set_id);
+                self.processing_pipeline.push_to_decoder(sample.data.to_vec(), self.stream_manager.clone(), stream_id);
+            }
+            DashEvent::DownloadError { url, reason } => warn!("Failed to download {} from upstream: {}", url, reason),
+            DashEvent::EndOfStream => info!("Upstream MPD {} ended", self.config.mpd_url),
+            DashEvent::Warning(message) => warn!("Upstream DASH player: {}", message),
+            DashEvent::Info(message) => debug!("Upstream DASH player: {}", message),
+            _ => {}
+        }
+    }
+
+    /// Stops fetching segments from the upstream server, must not be called from an async context.
+    pub fn stop(&self) {
+        if let Some(player) = self.player.blocking_lock().take() {
+            player.stop();
+        }
+    }
+}
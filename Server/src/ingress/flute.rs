@@ -0,0 +1,154 @@
+// ingress/flute.rs
+
+use std::net::{Ipv4Addr, SocketAddrV4, UdpSocket};
+use std::rc::Rc;
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, SystemTime};
+
+use crate::processing::ProcessingPipeline;
+use crate::services::shutdown::is_shutting_down;
+use crate::services::stream_manager::StreamManager;
+use shared_utils::error::{XrError, XrResult};
+
+use flute::core::UDPEndpoint;
+use flute::receiver::{writer, MultiReceiver};
+use metrics::{duration_buckets_us, get_metrics};
+use socket2::{Domain, Protocol, Socket, Type};
+use tracing::{debug, error, info, instrument};
+
+/// Largest FLUTE packet that is accepted
+const MAX_PACKET_SIZE: usize = 65_535;
+
+/// Settings of the FLUTE ingress.
+#[derive(Clone, Debug)]
+pub struct FluteIngressConfig {
+    /// Multicast group (or unicast address) the objects are sent to
+    pub address: Ipv4Addr,
+    pub port: u16,
+    /// Interface the multicast group is joined on, all interfaces without it
+    pub interface: Option<Ipv4Addr>,
+}
+
+/// FLUTE Ingress module responsible for receiving the objects of FLUTE sessions, e.g. of the FLUTE egress of another server.
+/// Every complete object is a frame, which lets the server act as a multicast-to-unicast gateway
+/// that publishes the received frames again over WebRTC, DASH or any other egress.
+#[derive(Debug)]
+pub struct FluteIngress {
+    processing_pipeline: Arc<ProcessingPipeline>,
+    stream_manager: Arc<StreamManager>,
+    config: FluteIngressConfig,
+}
+
+impl FluteIngress {
+    /// Initializes the FLUTE Ingress module, and starts receiving on the configured group.
+    #[instrument(skip_all)]
+    pub fn initialize(
+        stream_manager: Arc<StreamManager>,
+        processing_pipeline: Arc<ProcessingPipeline>,
+        config: Option<FluteIngressConfig>,
+    ) {
+        let Some(config) = config else {
+            info!("No FLUTE group configured, the FLUTE ingress is disabled");
+            return;
+        };
+
+        let socket = match bind_socket(&config) {
+            Ok(socket) => socket,
+            Err(e) => {
+                error!("Failed to start the FLUTE ingress: {}", e);
+                return;
+            }
+        };
+        info!("FLUTE ingress receiving on {}:{}", config.address, config.port);
+
+        let instance = Arc::new(Self {
+            processing_pipeline,
+            stream_manager: stream_manager.clone(),
+            config,
+        });
+
+        let instance_clone = instance.clone();
+        let _ = thread::Builder::new().name("FLT_I Receive Thread".to_string()).spawn(move || {
+            instance_clone.receive(socket);
+        });
+
+        // Store the instance in the StreamManager
+        stream_manager.set_flute_ingress(instance.clone());
+    }
+
+    /// Pushes the packets to the FLUTE receiver, and every completed object to the decoder, until the server shuts down.
+    /// The receiver is not `Send`, so it lives on this thread only.
+    fn receive(&self, socket: UdpSocket) {
+        let reception_time = get_metrics()
+            .get_or_create_histogram("flute_ingress_reception_time", "Time (us) it took to receive a FLUTE object", duration_buckets_us())
+            .unwrap();
+
+        let endpoint = UDPEndpoint::new(None, self.config.address.to_string(), self.config.port);
+        let stream_id = format!("flute_{}:{}", self.config.address, self.config.port);
+        let writer = Rc::new(writer::ObjectWriterBufferBuilder::new());
+        let mut receiver = MultiReceiver::new(writer.clone(), None, false);
+        let mut packet = vec![0u8; MAX_PACKET_SIZE];
+
+        loop {
+            if is_shutting_down() {
+                debug!("Stopping the FLUTE ingress");
+                break;
+            }
+
+            match socket.recv_from(&mut packet) {
+                Ok((len, _)) => {
+                    if let Err(e) = receiver.push(&endpoint, &packet[..len], SystemTime::now()) {
+                        debug!("Dropping an invalid FLUTE packet: {:?}", e);
+                    }
+                }
+                Err(e) if matches!(e.kind(), std::io::ErrorKind::WouldBlock | std::io::ErrorKind::TimedOut) => {}
+                Err(e) => {
+                    error!("Failed to receive on the FLUTE ingress: {}", e);
+                    break;
+                }
+            }
+            receiver.cleanup(SystemTime::now());
+
+            let mut objects = writer.objects.borrow_mut();
+            for object in objects.iter() {
+                let object = object.borrow();
+                if !object.complete || object.error {
+                    continue;
+                }
+                if let Some(end_time) = object.end_time {
+                    if let Ok(duration) = end_time.duration_since(object.start_time) {
+                        reception_time.observe(duration.as_micros() as f64);
+                    }
+                }
+                self.processing_pipeline.push_to_decoder(object.data.clone(), self.stream_manager.clone(), stream_id.clone());
+            }
+            // Objects that failed are dropped as well, the sender does not repeat them
+            objects.retain(|object| {
+                let object = object.borrow();
+                !object.complete && !object.error
+            });
+        }
+    }
+}
+
+/// Binds the socket on the port, joins the group if it is a multicast address,
+/// and lets the socket time out regularly so the receive thread can notice that the server shuts down.
+fn bind_socket(config: &FluteIngressConfig) -> XrResult<UdpSocket> {
+    let socket = Socket::new(Domain::IPV4, Type::DGRAM, Some(Protocol::UDP)).map_err(XrError::transport)?;
+    // Other receivers on this host may join the same group
+    socket.set_reuse_address(true).map_err(XrError::transport)?;
+    // FLUTE objects arrive as bursts of packets
+    let _ = socket.set_recv_buffer_size(8 * 1024 * 1024);
+    socket
+        .bind(&SocketAddrV4::new(Ipv4Addr::UNSPECIFIED, config.port).into())
+        .map_err(|e| XrError::Transport(format!("Failed to bind the FLUTE ingress on port {}: {e}", config.port)))?;
+    if config.address.is_multicast() {
+        socket
+            .join_multicast_v4(&config.address, &config.interface.unwrap_or(Ipv4Addr::UNSPECIFIED))
+            .map_err(|e| XrError::Transport(format!("Failed to join the multicast group {}: {e}", config.address)))?;
+    }
+    let socket: UdpSocket = socket.into();
+    socket.set_read_timeout(Some(Duration::from_millis(100))).map_err(XrError::transport)?;
+    Ok(socket)
+}
@@ -0,0 +1,184 @@
+// ingress/grpc.rs
+
+use std::collections::HashSet;
+use std::net::{Ipv6Addr, SocketAddr};
+use std::sync::{Arc, Mutex, OnceLock};
+
+use crate::egress::grpc::proto::frame_ingest_service_server::{FrameIngestService, FrameIngestServiceServer};
+use crate::egress::grpc::proto::{PublishFramesResponse, PublishedFrame};
+use crate::processing::ProcessingPipeline;
+use crate::services::auth::bearer_token;
+use crate::services::shutdown::is_shutting_down;
+use crate::services::stream_manager::StreamManager;
+use shared_utils::error::XrError;
+
+use metrics::get_metrics;
+use prometheus::IntCounterVec;
+use tokio::runtime::{self, Runtime};
+use tonic::{Request, Response, Status, Streaming};
+use tracing::{debug, error, info, instrument};
+
+static GRPC_INGRESS_RUNTIME: OnceLock<Arc<Runtime>> = OnceLock::new();
+
+/// gRPC Ingress module responsible for receiving the frames that external producers publish through the `FrameIngestService`.
+/// The stream metadata of the first frame of a stream is stored in its stream settings, like the other ingresses derive it from the stream id.
+#[derive(Debug)]
+pub struct GrpcIngress {
+    processing_pipeline: Arc<ProcessingPipeline>,
+    stream_manager: Arc<StreamManager>,
+    /// The streams of which a frame was received
+    registered_streams: Mutex<HashSet<String>>,
+    frames: IntCounterVec,
+}
+
+/// Implements the gRPC service on top of the ingress.
+struct FrameIngestServiceImpl {
+    ingress: Arc<GrpcIngress>,
+}
+
+impl GrpcIngress {
+    /// Initializes the gRPC Ingress module, and starts serving the `FrameIngestService` on `port`.
+    #[instrument(skip_all)]
+    pub fn initialize(
+        stream_manager: Arc<StreamManager>,
+        processing_pipeline: Arc<ProcessingPipeline>,
+        port: Option<u16>,
+    ) {
+        let Some(port) = port else {
+            info!("No gRPC ingress port configured, the gRPC ingress is disabled");
+            return;
+        };
+        let frames = match get_metrics().get_or_create_counter_vec("grpc_ingress_frames", "Number of frames published through the gRPC ingress, by outcome", &["outcome"]) {
+            Ok(frames) => frames,
+            Err(e) => {
+                error!("Failed to create the gRPC ingress metrics: {}", e);
+                return;
+            }
+        };
+
+        let instance = Arc::new(Self {
+            processing_pipeline,
+            stream_manager: stream_manager.clone(),
+            registered_streams: Mutex::new(HashSet::new()),
+            frames,
+        });
+
+        let service = FrameIngestServiceImpl { ingress: instance.clone() };
+        instance.get_runtime().spawn(async move {
+            let address = SocketAddr::from((Ipv6Addr::UNSPECIFIED, port));
+            let result = tonic::transport::Server::builder()
+                .add_service(FrameIngestServiceServer::new(service))
+                .serve(address)
+                .await
+                .map_err(|e| XrError::Transport(format!("Failed to serve gRPC: {e}")));
+            if let Err(e) = result {
+                error!("gRPC ingress stopped: {}", e);
+            }
+        });
+
+        info!("gRPC ingress listening on port {}", port);
+
+        // Store the instance in the StreamManager
+        stream_manager.set_grpc_ingress(instance.clone());
+    }
+
+    #[instrument(skip_all)]
+    pub fn get_runtime(&self) -> Arc<Runtime> {
+        GRPC_INGRESS_RUNTIME.get_or_init(|| {
+            let rt = runtime::Builder::new_multi_thread()
+                .worker_threads(2)
+                .thread_name_fn(|| {
+                    static ATOMIC_GRPC_I_ID: std::sync::atomic::AtomicUsize = std::sync::atomic::AtomicUsize::new(0);
+                    let id = ATOMIC_GRPC_I_ID.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+                    format!("GRPC_I w-{}", id)
+                })
+                .enable_all()
+                .build().unwrap();
+            Arc::new(rt)
+        }).clone()
+    }
+
+    /// Stores the metadata of the first frame of a stream in its settings.
+    fn register_stream(&self, frame: &PublishedFrame) {
+        if !self.registered_streams.lock().unwrap().insert(frame.stream_id.clone()) {
+            return;
+        }
+        let mut settings = self.stream_manager.get_stream_settings(&frame.stream_id);
+        if frame.client_id.is_some() {
+            settings.sfu_client_id = frame.client_id;
+        }
+        if frame.tile_index.is_some() {
+            settings.sfu_tile_index = frame.tile_index;
+        }
+        // The frames are timestamped again when they are decoded, so the producer's latency budget is kept as an offset
+        if frame.presentation_time > frame.send_time && settings.presentation_time_offset.is_none() {
+            settings.presentation_time_offset = Some(frame.presentation_time - frame.send_time);
+        }
+        info!("gRPC producer registered stream {}", frame.stream_id);
+        self.stream_manager.update_stream_settings(settings);
+    }
+}
+
+#[tonic::async_trait]
+impl FrameIngestService for FrameIngestServiceImpl {
+    async fn publish_frames(
+        &self,
+        request: Request<Streaming<PublishedFrame>>,
+    ) -> Result<Response<PublishFramesResponse>, Status> {
+        let peer = request.remote_addr();
+        // Producers authenticate with an `authorization: Bearer <token>` metadata entry
+        let token = request.metadata().get("authorization")
+            .and_then(|value| value.to_str().ok())
+            .and_then(bearer_token)
+            .map(str::to_string);
+        let mut frames = request.into_inner();
+        let mut response = PublishFramesResponse::default();
+        let mut stream_id = String::new();
+        let mut authorized_streams = AuthorizedStreams {
+            stream_manager: &self.ingress.stream_manager,
+            stream_ids: HashSet::new(),
+        };
+
+        while let Some(mut frame) = frames.message().await? {
+            if is_shutting_down() {
+                return Err(Status::unavailable("The server is shutting down"));
+            }
+            if frame.stream_id.is_empty() {
+                frame.stream_id = stream_id.clone();
+            }
+            if frame.stream_id.is_empty() || frame.data.is_empty() {
+                response.frames_rejected += 1;
+                self.ingress.frames.with_label_values(&["rejected"]).inc();
+                continue;
+            }
+            stream_id = frame.stream_id.clone();
+            if !authorized_streams.stream_ids.contains(&frame.stream_id) {
+                self.ingress.stream_manager.authorize_producer(&frame.stream_id, token.as_deref())
+                    .map_err(|e| Status::permission_denied(e.to_string()))?;
+                authorized_streams.stream_ids.insert(frame.stream_id.clone());
+            }
+
+            self.ingress.register_stream(&frame);
+            response.frames_received += 1;
+            self.ingress.frames.with_label_values(&["received"]).inc();
+            self.ingress.processing_pipeline.push_to_decoder(frame.data, self.ingress.stream_manager.clone(), frame.stream_id);
+        }
+
+        debug!("gRPC producer {:?} published {} frames", peer, response.frames_received);
+        Ok(Response::new(response))
+    }
+}
+
+/// The streams a call is authorized to publish, the authorization ends with the call.
+struct AuthorizedStreams<'a> {
+    stream_manager: &'a StreamManager,
+    stream_ids: HashSet<String>,
+}
+
+impl Drop for AuthorizedStreams<'_> {
+    fn drop(&mut self) {
+        for stream_id in &self.stream_ids {
+            self.stream_manager.revoke_producer(stream_id);
+        }
+    }
+}
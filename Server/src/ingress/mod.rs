@@ -1,17 +1,64 @@
+pub mod capture;
+pub mod dash;
+pub mod flute;
+pub mod grpc;
+pub mod mqtt;
+pub mod replay;
+pub mod ros2;
+pub mod rtsp;
+pub mod srt;
+pub mod tcp;
+pub mod udp;
 pub mod webrtc;
+pub mod webtransport;
 pub mod websocket;
+pub mod zenoh;
 // Add other ingress protocols as needed
 
+use std::net::SocketAddr;
 use std::sync::Arc;
 use tracing::instrument;
 
 use crate::services::stream_manager::StreamManager;
 use crate::processing::ProcessingPipeline;
+use shared_utils::error::XrResult;
+use shared_utils::wire::{decode_frame, decode_frame_header};
 
+/// Returns the encoded point cloud of a payload of the pub/sub ingresses.
+/// Frames in the wire format of the egresses carry their timestamps and SFU metadata around it, other payloads are the encoded point cloud itself.
+pub(crate) fn frame_data(payload: &[u8]) -> XrResult<Vec<u8>> {
+    if decode_frame_header(payload).is_some() {
+        Ok(decode_frame(payload)?.data)
+    } else {
+        Ok(payload.to_vec())
+    }
+}
+
+/// Stream ids that producers announce may only hold letters, digits, '_', '-' and '.', so they are safe in paths, topics and metric labels.
+pub(crate) fn is_valid_stream_id(stream_id: &str) -> bool {
+    !stream_id.is_empty()
+        && stream_id.len() <= 256
+        && stream_id.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '-' | '.'))
+}
+
+#[allow(clippy::too_many_arguments)]
 #[instrument(skip_all)]
 pub fn initialize_ingress_protocols(
     stream_manager: Arc<StreamManager>,
     processing_pipeline: Arc<ProcessingPipeline>,
+    udp_config: Option<udp::UdpIngressConfig>,
+    tcp_listen: Option<SocketAddr>,
+    rtsp_url: Option<String>,
+    srt_config: Option<srt::SrtIngressConfig>,
+    replay_config: Option<replay::ReplayConfig>,
+    dash_config: Option<dash::DashIngressConfig>,
+    flute_config: Option<flute::FluteIngressConfig>,
+    grpc_port: Option<u16>,
+    capture_config: Option<capture::CaptureConfig>,
+    ros2_config: Option<ros2::Ros2IngressConfig>,
+    zenoh_config: Option<zenoh::ZenohIngressConfig>,
+    mqtt_config: Option<mqtt::MqttIngressConfig>,
+    webtransport_port: Option<u16>,
 ) {
     webrtc::WebRTCIngress::initialize(
         stream_manager.clone(),
@@ -23,5 +70,83 @@ pub fn initialize_ingress_protocols(
         processing_pipeline.clone(),
     );
 
+    udp::UdpIngress::initialize(
+        stream_manager.clone(),
+        processing_pipeline.clone(),
+        udp_config,
+    );
+
+    tcp::TcpIngress::initialize(
+        stream_manager.clone(),
+        processing_pipeline.clone(),
+        tcp_listen,
+    );
+
+    rtsp::RtspIngress::initialize(
+        stream_manager.clone(),
+        processing_pipeline.clone(),
+        rtsp_url,
+    );
+
+    srt::SrtIngress::initialize(
+        stream_manager.clone(),
+        processing_pipeline.clone(),
+        srt_config,
+    );
+
+    replay::ReplayIngress::initialize(
+        stream_manager.clone(),
+        processing_pipeline.clone(),
+        replay_config,
+    );
+
+    dash::DashIngress::initialize(
+        stream_manager.clone(),
+        processing_pipeline.clone(),
+        dash_config,
+    );
+
+    flute::FluteIngress::initialize(
+        stream_manager.clone(),
+        processing_pipeline.clone(),
+        flute_config,
+    );
+
+    grpc::GrpcIngress::initialize(
+        stream_manager.clone(),
+        processing_pipeline.clone(),
+        grpc_port,
+    );
+
+    capture::CaptureIngress::initialize(
+        stream_manager.clone(),
+        processing_pipeline.clone(),
+        capture_config,
+    );
+
+    ros2::Ros2Ingress::initialize(
+        stream_manager.clone(),
+        processing_pipeline.clone(),
+        ros2_config,
+    );
+
+    zenoh::ZenohIngress::initialize(
+        stream_manager.clone(),
+        processing_pipeline.clone(),
+        zenoh_config,
+    );
+
+    mqtt::MqttIngress::initialize(
+        stream_manager.clone(),
+        processing_pipeline.clone(),
+        mqtt_config,
+    );
+
+    webtransport::WebTransportIngress::initialize(
+        stream_manager.clone(),
+        processing_pipeline.clone(),
+        webtransport_port,
+    );
+
     // Initialize other ingress protocols similarly
 }
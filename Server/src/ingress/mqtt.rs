@@ -0,0 +1,221 @@
+// ingress/mqtt.rs
+
+use std::sync::{Arc, OnceLock};
+use std::time::Duration;
+
+use crate::processing::ProcessingPipeline;
+use crate::services::shutdown::is_shutting_down;
+use crate::services::stream_manager::StreamManager;
+use shared_utils::error::{XrError, XrResult};
+
+use super::frame_data;
+
+use rumqttc::{AsyncClient, Event, EventLoop, MqttOptions, Packet, QoS};
+use tokio::runtime::{self, Runtime};
+use tracing::{debug, error, info, instrument, warn};
+
+static MQTT_INGRESS_RUNTIME: OnceLock<Arc<Runtime>> = OnceLock::new();
+
+/// Largest MQTT packet the client accepts, point cloud frames are far larger than the default of 10 kB
+const MQTT_MAX_PACKET_SIZE: usize = 64 * 1024 * 1024;
+/// Last topic level of the retained metadata of the MQTT egress, which is not a frame
+const INIT_TOPIC_LEVEL: &str = "init";
+
+/// A topic filter the MQTT ingress subscribes to, and the stream id of the topics that match it.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct MqttSubscription {
+    /// e.g. pointclouds/+/frames for every stream of the MQTT egress of another server
+    pub topic_filter: String,
+    /// Stream id of the matching topics, `{1}`, `{2}`, ... are replaced by the levels that match the `+` wildcards
+    /// and `{#}` by the levels that match the `#` wildcard. Every topic gets its own stream without it
+    pub stream_id: Option<String>,
+}
+
+impl MqttSubscription {
+    /// Parses `topic_filter` or `topic_filter=stream_id`.
+    pub fn parse(value: &str) -> XrResult<Self> {
+        let (topic_filter, stream_id) = match value.split_once('=') {
+            Some((topic_filter, stream_id)) => (topic_filter.trim(), Some(stream_id.trim())),
+            None => (value.trim(), None),
+        };
+        if topic_filter.is_empty() || stream_id.is_some_and(str::is_empty) {
+            return Err(XrError::Config(format!("Invalid MQTT subscription {value}, expected topic_filter or topic_filter=stream_id")));
+        }
+        // The multi-level wildcard is only valid as the last level
+        let levels: Vec<&str> = topic_filter.split('/').collect();
+        if levels.iter().enumerate().any(|(index, level)| (level.contains('#') && (*level != "#" || index != levels.len() - 1)) || (level.contains('+') && *level != "+")) {
+            return Err(XrError::Config(format!("Invalid MQTT topic filter {topic_filter}")));
+        }
+        Ok(Self {
+            topic_filter: topic_filter.to_string(),
+            stream_id: stream_id.map(str::to_string),
+        })
+    }
+
+    /// Returns the stream id of a topic, `None` when the topic does not match the filter.
+    fn stream_id(&self, topic: &str) -> Option<String> {
+        let mut wildcards = Vec::new();
+        let mut remainder = None;
+        let mut topic_levels = topic.split('/');
+        for filter_level in self.topic_filter.split('/') {
+            match filter_level {
+                "#" => {
+                    remainder = Some(topic_levels.by_ref().collect::<Vec<_>>().join("/"));
+                    break;
+                }
+                "+" => wildcards.push(topic_levels.next()?),
+                level => {
+                    if topic_levels.next()? != level {
+                        return None;
+                    }
+                }
+            }
+        }
+        if remainder.is_none() && topic_levels.next().is_some() {
+            return None;
+        }
+
+        let Some(template) = &self.stream_id else {
+            return Some(format!("mqtt_{}", topic.trim_matches('/').replace('/', "_")));
+        };
+        let mut stream_id = template.replace("{#}", remainder.as_deref().unwrap_or_default());
+        for (index, wildcard) in wildcards.iter().enumerate() {
+            stream_id = stream_id.replace(&format!("{{{}}}", index + 1), wildcard);
+        }
+        Some(stream_id)
+    }
+}
+
+/// Settings of the MQTT ingress.
+#[derive(Clone, Debug)]
+pub struct MqttIngressConfig {
+    /// Address of the broker, e.g. localhost:1883
+    pub broker: String,
+    /// Has to differ from the client id of the MQTT egress, the broker disconnects the older client of an id
+    pub client_id: String,
+    pub subscriptions: Vec<MqttSubscription>,
+    /// QoS level (0, 1 or 2) of the subscriptions
+    pub qos: u8,
+}
+
+/// MQTT Ingress module responsible for consuming frames that constrained producers publish to an MQTT broker.
+/// Frames in the wire format of the egresses and plain encoded frames (PLY, Draco) are both accepted.
+#[derive(Debug)]
+pub struct MqttIngress {
+    processing_pipeline: Arc<ProcessingPipeline>,
+    stream_manager: Arc<StreamManager>,
+    config: MqttIngressConfig,
+    qos: QoS,
+}
+
+impl MqttIngress {
+    /// Initializes the MQTT Ingress module, and connects to the broker of `config`.
+    #[instrument(skip_all)]
+    pub fn initialize(
+        stream_manager: Arc<StreamManager>,
+        processing_pipeline: Arc<ProcessingPipeline>,
+        config: Option<MqttIngressConfig>,
+    ) {
+        let Some(config) = config.filter(|config| !config.subscriptions.is_empty()) else {
+            info!("No MQTT subscriptions configured, the MQTT ingress is disabled");
+            return;
+        };
+
+        let (options, qos) = match parse_config(&config) {
+            Ok(parsed) => parsed,
+            Err(e) => {
+                error!("Failed to initialize the MQTT ingress: {}", e);
+                return;
+            }
+        };
+
+        let (client, event_loop) = AsyncClient::new(options, 10);
+
+        info!("MQTT ingress subscribing to {:?} at {}", config.subscriptions, config.broker);
+
+        let instance = Arc::new(Self {
+            processing_pipeline,
+            stream_manager: stream_manager.clone(),
+            config,
+            qos,
+        });
+
+        let instance_clone = instance.clone();
+        instance.get_runtime().spawn(async move {
+            instance_clone.drive_event_loop(client, event_loop).await;
+        });
+
+        // Store the instance in the StreamManager
+        stream_manager.set_mqtt_ingress(instance.clone());
+    }
+
+    #[instrument(skip_all)]
+    pub fn get_runtime(&self) -> Arc<Runtime> {
+        MQTT_INGRESS_RUNTIME.get_or_init(|| {
+            let rt = runtime::Builder::new_multi_thread()
+                .worker_threads(1)
+                .thread_name_fn(|| {
+                    static ATOMIC_MQTT_I_ID: std::sync::atomic::AtomicUsize = std::sync::atomic::AtomicUsize::new(0);
+                    let id = ATOMIC_MQTT_I_ID.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+                    format!("MQTT_I w-{}", id)
+                })
+                .enable_all()
+                .build().unwrap();
+            Arc::new(rt)
+        }).clone()
+    }
+
+    /// The event loop performs the actual network I/O of the client, and reconnects when polled after an error.
+    /// The session is clean, so the filters are subscribed to again after every connection.
+    async fn drive_event_loop(&self, client: AsyncClient, mut event_loop: EventLoop) {
+        while !is_shutting_down() {
+            match event_loop.poll().await {
+                Ok(Event::Incoming(Packet::ConnAck(_))) => {
+                    info!("MQTT ingress connected to {}", self.config.broker);
+                    for subscription in &self.config.subscriptions {
+                        // The client queues the request, so it cannot wait for the event loop that runs it
+                        if let Err(e) = client.try_subscribe(subscription.topic_filter.clone(), self.qos) {
+                            error!("Failed to subscribe to {}: {}", subscription.topic_filter, e);
+                        }
+                    }
+                }
+                Ok(Event::Incoming(Packet::Publish(publish))) => self.handle_publish(&publish.topic, &publish.payload),
+                Ok(_) => {}
+                Err(e) => {
+                    warn!("MQTT ingress connection error: {}", e);
+                    tokio::time::sleep(Duration::from_secs(1)).await;
+                }
+            }
+        }
+        debug!("Stopping the MQTT ingress");
+    }
+
+    fn handle_publish(&self, topic: &str, payload: &[u8]) {
+        if topic.rsplit('/').next() == Some(INIT_TOPIC_LEVEL) {
+            return;
+        }
+        // The first subscription that matches decides the stream, like the broker delivers a message once per client
+        let Some(stream_id) = self.config.subscriptions.iter().find_map(|subscription| subscription.stream_id(topic)) else {
+            debug!("Dropping a message on {}, which matches no subscription", topic);
+            return;
+        };
+        match frame_data(payload) {
+            Ok(data) => self.processing_pipeline.push_to_decoder(data, self.stream_manager.clone(), stream_id),
+            Err(e) => debug!("Dropping an invalid frame on {}: {}", topic, e),
+        }
+    }
+}
+
+fn parse_config(config: &MqttIngressConfig) -> XrResult<(MqttOptions, QoS)> {
+    let (host, port) = config.broker.rsplit_once(':')
+        .ok_or_else(|| XrError::Config(format!("Expected an MQTT broker like host:port, got {}", config.broker)))?;
+    let port = port.parse::<u16>()
+        .map_err(|e| XrError::Config(format!("Invalid MQTT broker port {port}: {e}")))?;
+
+    let mut options = MqttOptions::new(config.client_id.clone(), host, port);
+    options.set_keep_alive(Duration::from_secs(5));
+    options.set_max_packet_size(MQTT_MAX_PACKET_SIZE, MQTT_MAX_PACKET_SIZE);
+
+    let qos = rumqttc::qos(config.qos).map_err(|_| XrError::Config(format!("Invalid MQTT QoS {}, expected 0, 1 or 2", config.qos)))?;
+    Ok((options, qos))
+}
@@ -0,0 +1,246 @@
+// ingress/replay.rs
+
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::processing::ProcessingPipeline;
+use crate::services::shutdown::is_shutting_down;
+use crate::services::stream_manager::StreamManager;
+use shared_utils::error::{XrError, XrResult};
+
+use bytes::Bytes;
+use dash_player::segment::demuxer::{demux_segment, parse_init_segment, TrackInfo};
+use tracing::{debug, error, info, instrument, warn};
+
+/// Extensions of the files that hold a single encoded frame, the file egress names Draco frames `.dra`
+const FRAME_EXTENSIONS: [&str; 3] = ["ply", "drc", "dra"];
+/// Extensions of fragmented MP4 recordings, e.g. the segments of the file egress
+const MP4_EXTENSIONS: [&str; 3] = ["mp4", "m4s", "cmfv"];
+/// Name of the initialization segment of a segmented recording
+const INIT_SEGMENT_NAME: &str = "init.mp4";
+/// Timescale of the samples of tracks that the recording does not describe
+const FALLBACK_TIMESCALE: u32 = 1000;
+
+/// How often the recording is played.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ReplayLoop {
+    Once,
+    Times(u32),
+    Forever,
+}
+
+impl ReplayLoop {
+    pub fn parse(value: &str) -> XrResult<Self> {
+        match value.trim().to_lowercase().as_str() {
+            "once" => Ok(ReplayLoop::Once),
+            "loop" | "forever" => Ok(ReplayLoop::Forever),
+            other => match other.parse::<u32>() {
+                Ok(0) => Err(XrError::Config("The recording has to be played at least once".to_string())),
+                Ok(times) => Ok(ReplayLoop::Times(times)),
+                Err(_) => Err(XrError::Config(format!("Unsupported replay loop mode {other}, expected once, loop or a number of passes"))),
+            },
+        }
+    }
+
+    fn passes(&self) -> Option<u32> {
+        match self {
+            ReplayLoop::Once => Some(1),
+            ReplayLoop::Times(times) => Some(*times),
+            ReplayLoop::Forever => None,
+        }
+    }
+}
+
+/// Settings of the replay ingress.
+#[derive(Clone, Debug)]
+pub struct ReplayConfig {
+    /// A directory of PLY/Draco frames or fragmented MP4 segments, or a single fragmented MP4 file
+    pub path: PathBuf,
+    pub fps: u32,
+    pub loop_mode: ReplayLoop,
+    /// Stream id the frames are pushed on, the tracks of a multi-track recording get their track id appended
+    pub stream_id: String,
+}
+
+/// A file of the recording, in playback order.
+#[derive(Clone, Debug)]
+enum ReplayItem {
+    Frame(PathBuf),
+    Mp4(PathBuf),
+}
+
+/// Replay Ingress module responsible for playing back a recording at a fixed frame rate, as if it was captured live.
+/// The frames get their timestamps when they are decoded, so experiments can be repeated without a capture source.
+#[derive(Debug)]
+pub struct ReplayIngress {
+    processing_pipeline: Arc<ProcessingPipeline>,
+    stream_manager: Arc<StreamManager>,
+    config: ReplayConfig,
+    items: Vec<ReplayItem>,
+    /// The tracks of the initialization segment of a segmented recording
+    init_tracks: HashMap<u32, TrackInfo>,
+}
+
+impl ReplayIngress {
+    /// Initializes the Replay Ingress module, and starts playing back the recording.
+    #[instrument(skip_all)]
+    pub fn initialize(
+        stream_manager: Arc<StreamManager>,
+        processing_pipeline: Arc<ProcessingPipeline>,
+        config: Option<ReplayConfig>,
+    ) {
+        let Some(config) = config else {
+            info!("No recording to replay configured, the replay ingress is disabled");
+            return;
+        };
+
+        let (items, init_tracks) = match load_recording(&config.path) {
+            Ok(recording) => recording,
+            Err(e) => {
+                error!("Failed to start the replay ingress: {}", e);
+                return;
+            }
+        };
+        info!("Replaying {} file(s) of {:?} at {} fps ({:?})", items.len(), config.path, config.fps, config.loop_mode);
+
+        let instance = Arc::new(Self {
+            processing_pipeline,
+            stream_manager: stream_manager.clone(),
+            config,
+            items,
+            init_tracks,
+        });
+
+        let instance_clone = instance.clone();
+        let _ = thread::Builder::new().name("REPLAY_I Thread".to_string()).spawn(move || {
+            instance_clone.replay();
+        });
+
+        // Store the instance in the StreamManager
+        stream_manager.set_replay_ingress(instance.clone());
+    }
+
+    /// Plays the recording the configured number of times, or until the server shuts down.
+    fn replay(&self) {
+        let frame_duration = Duration::from_micros(1_000_000 / self.config.fps.max(1) as u64);
+        let mut next_frame_time = Instant::now();
+        let mut pass = 0;
+
+        while self.config.loop_mode.passes().is_none_or(|passes| pass < passes) {
+            for item in &self.items {
+                let frames = match self.read_item(item) {
+                    Ok(frames) => frames,
+                    Err(e) => {
+                        warn!("Skipping {:?} during replay: {}", item, e);
+                        continue;
+                    }
+                };
+                for frame in frames {
+                    if is_shutting_down() {
+                        debug!("Stopping the replay");
+                        return;
+                    }
+                    // Frames are scheduled on a fixed clock, so slow reads do not make the replay drift
+                    if let Some(sleep_duration) = next_frame_time.checked_duration_since(Instant::now()) {
+                        thread::sleep(sleep_duration);
+                    }
+                    // After a stall the clock restarts, rather than pushing the missed frames in a burst
+                    next_frame_time = (next_frame_time + frame_duration).max(Instant::now());
+
+                    for (stream_id, data) in frame {
+                        self.processing_pipeline.push_to_decoder(data, self.stream_manager.clone(), stream_id);
+                    }
+                }
+            }
+            pass += 1;
+        }
+        info!("Replay of {:?} finished after {} pass(es)", self.config.path, pass);
+    }
+
+    /// Reads the frames of a file, every frame holds the data of each track that is presented at the same time.
+    fn read_item(&self, item: &ReplayItem) -> XrResult<Vec<Vec<(String, Vec<u8>)>>> {
+        match item {
+            ReplayItem::Frame(path) => {
+                let data = fs::read(path).map_err(|e| XrError::Config(format!("Failed to read {path:?}: {e}")))?;
+                Ok(vec![vec![(self.config.stream_id.clone(), data)]])
+            }
+            ReplayItem::Mp4(path) => {
+                let data = Bytes::from(fs::read(path).map_err(|e| XrError::Config(format!("Failed to read {path:?}: {e}")))?);
+                // A single file recording carries its own moov box
+                let mut tracks = parse_init_segment(&data).map_err(XrError::Codec)?;
+                if tracks.is_empty() {
+                    tracks = self.init_tracks.clone();
+                }
+                let samples = demux_segment(&data, &tracks, FALLBACK_TIMESCALE, 0).map_err(XrError::Codec)?;
+                let multi_track = tracks.len() > 1 || samples.iter().any(|sample| sample.track_id != samples[0].track_id);
+
+                // Samples of the tracks that are presented at the same time form one frame
+                let mut frames: Vec<(u64, Vec<(String, Vec<u8>)>)> = Vec::new();
+                for sample in samples {
+                    let time_us = sample.presentation_time * 1_000_000 / sample.timescale.max(1) as u64;
+                    let stream_id = if multi_track {
+                        format!("{}_{}", self.config.stream_id, sample.track_id)
+                    } else {
+                        self.config.stream_id.clone()
+                    };
+                    match frames.iter_mut().find(|(frame_time_us, _)| *frame_time_us == time_us) {
+                        Some((_, frame)) => frame.push((stream_id, sample.data.to_vec())),
+                        None => frames.push((time_us, vec![(stream_id, sample.data.to_vec())])),
+                    }
+                }
+                frames.sort_by_key(|(time_us, _)| *time_us);
+                Ok(frames.into_iter().map(|(_, frame)| frame).collect())
+            }
+        }
+    }
+}
+
+/// Lists the files of the recording in playback order, and reads the initialization segment of a segmented recording.
+fn load_recording(path: &Path) -> XrResult<(Vec<ReplayItem>, HashMap<u32, TrackInfo>)> {
+    if path.is_file() {
+        return match replay_item(path) {
+            Some(item) => Ok((vec![item], HashMap::new())),
+            None => Err(XrError::Config(format!("{path:?} is not a PLY, Draco or fragmented MP4 file"))),
+        };
+    }
+
+    let entries = fs::read_dir(path).map_err(|e| XrError::Config(format!("Failed to read the recording {path:?}: {e}")))?;
+    let mut paths: Vec<PathBuf> = entries
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| path.is_file())
+        .collect();
+    // The file egress names frames after their send time and numbers its segments, so the names sort in playback order
+    paths.sort();
+
+    let init_tracks = match paths.iter().find(|path| path.file_name().is_some_and(|name| name == INIT_SEGMENT_NAME)) {
+        Some(init_path) => {
+            let data = fs::read(init_path).map_err(|e| XrError::Config(format!("Failed to read {init_path:?}: {e}")))?;
+            parse_init_segment(&data).map_err(XrError::Codec)?
+        }
+        None => HashMap::new(),
+    };
+    let items: Vec<ReplayItem> = paths
+        .iter()
+        .filter(|path| path.file_name().is_none_or(|name| name != INIT_SEGMENT_NAME))
+        .filter_map(|path| replay_item(path))
+        .collect();
+    if items.is_empty() {
+        return Err(XrError::Config(format!("{path:?} holds no PLY, Draco or fragmented MP4 files")));
+    }
+    Ok((items, init_tracks))
+}
+
+fn replay_item(path: &Path) -> Option<ReplayItem> {
+    let extension = path.extension()?.to_str()?.to_lowercase();
+    if FRAME_EXTENSIONS.contains(&extension.as_str()) {
+        Some(ReplayItem::Frame(path.to_path_buf()))
+    } else if MP4_EXTENSIONS.contains(&extension.as_str()) {
+        Some(ReplayItem::Mp4(path.to_path_buf()))
+    } else {
+        None
+    }
+}
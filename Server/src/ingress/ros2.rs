@@ -0,0 +1,360 @@
+// ingress/ros2.rs
+
+use std::sync::{Arc, OnceLock};
+use std::time::Duration;
+
+use crate::egress::zenoh::open_session;
+use crate::processing::ProcessingPipeline;
+use crate::services::shutdown::is_shutting_down;
+use crate::services::stream_manager::StreamManager;
+use shared_utils::error::{XrError, XrResult};
+use shared_utils::types::{Point3D, PointAttributes, PointCloudData};
+
+use tokio::runtime::{self, Runtime};
+use tracing::{debug, error, info, instrument, warn};
+
+static ROS2_INGRESS_RUNTIME: OnceLock<Arc<Runtime>> = OnceLock::new();
+
+/// How long to wait before opening the Zenoh session again when the bridge cannot be reached
+const RETRY_DELAY: Duration = Duration::from_secs(2);
+
+/// `sensor_msgs/PointField` datatypes
+const INT8: u8 = 1;
+const UINT8: u8 = 2;
+const INT16: u8 = 3;
+const UINT16: u8 = 4;
+const INT32: u8 = 5;
+const UINT32: u8 = 6;
+const FLOAT32: u8 = 7;
+const FLOAT64: u8 = 8;
+
+/// Settings of the ROS 2 ingress.
+#[derive(Clone, Debug)]
+pub struct Ros2IngressConfig {
+    /// ROS 2 topics of `sensor_msgs/PointCloud2` messages, e.g. /camera/depth/color/points
+    pub topics: Vec<String>,
+    /// Namespace the bridge publishes the ROS 2 topics under, set with its `--namespace` option
+    pub namespace: Option<String>,
+    /// Zenoh endpoints of the bridge, e.g. tcp/192.168.1.30:7447, scouting is used when empty
+    pub connect: Vec<String>,
+}
+
+/// ROS 2 Ingress module responsible for subscribing to `sensor_msgs/PointCloud2` topics, so robotics stacks and recorded bags can feed the pipeline.
+/// The topics are reached through zenoh-bridge-ros2dds, which routes the DDS traffic of a ROS 2 domain over Zenoh,
+/// so the server does not need a ROS 2 installation. Every topic becomes the stream `ros2_{topic}`.
+#[derive(Debug)]
+pub struct Ros2Ingress {
+    processing_pipeline: Arc<ProcessingPipeline>,
+    stream_manager: Arc<StreamManager>,
+    config: Ros2IngressConfig,
+}
+
+impl Ros2Ingress {
+    /// Initializes the ROS 2 Ingress module, and subscribes to the configured topics.
+    #[instrument(skip_all)]
+    pub fn initialize(
+        stream_manager: Arc<StreamManager>,
+        processing_pipeline: Arc<ProcessingPipeline>,
+        config: Option<Ros2IngressConfig>,
+    ) {
+        let Some(config) = config.filter(|config| !config.topics.is_empty()) else {
+            info!("No ROS 2 topics configured, the ROS 2 ingress is disabled");
+            return;
+        };
+
+        let instance = Arc::new(Self {
+            processing_pipeline,
+            stream_manager: stream_manager.clone(),
+            config,
+        });
+
+        let instance_clone = instance.clone();
+        instance.get_runtime().spawn(async move {
+            instance_clone.subscribe().await;
+        });
+
+        info!("ROS 2 ingress subscribing to {:?}", instance.config.topics);
+
+        // Store the instance in the StreamManager
+        stream_manager.set_ros2_ingress(instance.clone());
+    }
+
+    #[instrument(skip_all)]
+    pub fn get_runtime(&self) -> Arc<Runtime> {
+        ROS2_INGRESS_RUNTIME.get_or_init(|| {
+            let rt = runtime::Builder::new_multi_thread()
+                .worker_threads(2)
+                .thread_name_fn(|| {
+                    static ATOMIC_ROS2_I_ID: std::sync::atomic::AtomicUsize = std::sync::atomic::AtomicUsize::new(0);
+                    let id = ATOMIC_ROS2_I_ID.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+                    format!("ROS2_I w-{}", id)
+                })
+                .enable_all()
+                .build().unwrap();
+            Arc::new(rt)
+        }).clone()
+    }
+
+    /// Opens the Zenoh session once the bridge can be reached, and receives the messages of every topic on its own task.
+    async fn subscribe(self: Arc<Self>) {
+        let session = loop {
+            if is_shutting_down() {
+                return;
+            }
+            match open_session("peer", &self.config.connect, &[]).await {
+                Ok(session) => break session,
+                Err(e) => {
+                    warn!("Failed to reach the ROS 2 bridge: {}", e);
+                    tokio::time::sleep(RETRY_DELAY).await;
+                }
+            }
+        };
+
+        for topic in &self.config.topics {
+            let key_expr = self.key_expr(topic);
+            let subscriber = match session.declare_subscriber(key_expr.clone()).await {
+                Ok(subscriber) => subscriber,
+                Err(e) => {
+                    error!("Failed to subscribe to the ROS 2 topic {} ({}): {}", topic, key_expr, e);
+                    continue;
+                }
+            };
+            let stream_id = stream_id(topic);
+            let self_clone = self.clone();
+            // The subscribers only receive while the session is open
+            let session = session.clone();
+            tokio::spawn(async move {
+                let _session = session;
+                while let Ok(sample) = subscriber.recv_async().await {
+                    if is_shutting_down() {
+                        break;
+                    }
+                    match parse_point_cloud2(&sample.payload().to_bytes()) {
+                        Ok(point_cloud) => self_clone.push_point_cloud(point_cloud, &stream_id),
+                        Err(e) => debug!("Dropping an invalid PointCloud2 message on {}: {}", sample.key_expr(), e),
+                    }
+                }
+                debug!("Stopped receiving {}", stream_id);
+            });
+        }
+    }
+
+    /// The bridge publishes a topic on its name without the leading slash, under its namespace.
+    fn key_expr(&self, topic: &str) -> String {
+        let topic = topic.trim_matches('/');
+        match self.config.namespace.as_deref().map(|namespace| namespace.trim_matches('/')) {
+            Some(namespace) if !namespace.is_empty() => format!("{namespace}/{topic}"),
+            _ => topic.to_string(),
+        }
+    }
+
+    /// The messages are not encoded, so they skip the decoder and are processed right away.
+    fn push_point_cloud(&self, mut point_cloud: PointCloudData, stream_id: &str) {
+        let settings = self.stream_manager.get_stream_settings(stream_id);
+        if !settings.process_incoming_frames {
+            return;
+        }
+        if let Some(offset) = settings.presentation_time_offset {
+            point_cloud.presentation_time = point_cloud.creation_time.saturating_add(offset);
+        }
+        self.processing_pipeline.process_frame(point_cloud, self.stream_manager.clone(), stream_id.to_string());
+    }
+}
+
+/// /camera/depth/points becomes ros2_camera_depth_points.
+fn stream_id(topic: &str) -> String {
+    format!("ros2_{}", topic.trim_matches('/').replace('/', "_"))
+}
+
+/// A `sensor_msgs/PointField`.
+#[derive(Debug)]
+struct PointField {
+    name: String,
+    offset: usize,
+    datatype: u8,
+}
+
+/// Reads the CDR serialization of a ROS 2 message, the alignment is relative to the end of the encapsulation header.
+struct CdrReader<'a> {
+    data: &'a [u8],
+    position: usize,
+    little_endian: bool,
+}
+
+impl<'a> CdrReader<'a> {
+    fn new(payload: &'a [u8]) -> XrResult<Self> {
+        if payload.len() < 4 {
+            return Err(XrError::Codec("The message has no CDR encapsulation header".to_string()));
+        }
+        // 0x0000 is plain CDR in big endian, 0x0001 in little endian
+        let little_endian = match payload[1] {
+            0 => false,
+            1 => true,
+            other => return Err(XrError::Codec(format!("Unsupported CDR encapsulation {other}"))),
+        };
+        Ok(Self { data: &payload[4..], position: 0, little_endian })
+    }
+
+    fn read_bytes(&mut self, len: usize) -> XrResult<&'a [u8]> {
+        let end = self.position.checked_add(len).filter(|end| *end <= self.data.len())
+            .ok_or_else(|| XrError::Codec("The CDR message is truncated".to_string()))?;
+        let bytes = &self.data[self.position..end];
+        self.position = end;
+        Ok(bytes)
+    }
+
+    fn align(&mut self, alignment: usize) {
+        self.position = self.position.div_ceil(alignment) * alignment;
+    }
+
+    fn read_u8(&mut self) -> XrResult<u8> {
+        Ok(self.read_bytes(1)?[0])
+    }
+
+    fn read_u32(&mut self) -> XrResult<u32> {
+        self.align(4);
+        let bytes: [u8; 4] = self.read_bytes(4)?.try_into().unwrap();
+        Ok(if self.little_endian { u32::from_le_bytes(bytes) } else { u32::from_be_bytes(bytes) })
+    }
+
+    fn read_string(&mut self) -> XrResult<String> {
+        let len = self.read_u32()? as usize;
+        let bytes = self.read_bytes(len)?;
+        // The length includes the terminating null character
+        let bytes = bytes.strip_suffix(&[0]).unwrap_or(bytes);
+        Ok(String::from_utf8_lossy(bytes).into_owned())
+    }
+}
+
+/// Converts a CDR serialized `sensor_msgs/PointCloud2` into a point cloud.
+/// ROS uses x forward, y left and z up, so the points are rotated to x right, y up and z backward.
+fn parse_point_cloud2(payload: &[u8]) -> XrResult<PointCloudData> {
+    let mut reader = CdrReader::new(payload)?;
+    // std_msgs/Header: the stamp (sec, nanosec) and the frame id
+    reader.read_u32()?;
+    reader.read_u32()?;
+    reader.read_string()?;
+    let height = reader.read_u32()? as usize;
+    let width = reader.read_u32()? as usize;
+    let field_count = reader.read_u32()? as usize;
+    let mut fields = Vec::with_capacity(field_count.min(64));
+    for _ in 0..field_count {
+        let name = reader.read_string()?;
+        let offset = reader.read_u32()? as usize;
+        let datatype = reader.read_u8()?;
+        // The number of elements, the point cloud fields of interest are scalars
+        reader.read_u32()?;
+        fields.push(PointField { name, offset, datatype });
+    }
+    let big_endian = reader.read_u8()? != 0;
+    let point_step = reader.read_u32()? as usize;
+    let row_step = reader.read_u32()? as usize;
+    let data_len = reader.read_u32()? as usize;
+    let data = reader.read_bytes(data_len)?;
+    if width == 0 || height == 0 {
+        return Ok(PointCloudData::default());
+    }
+    if point_step == 0 {
+        return Err(XrError::Codec("The PointCloud2 has a point step of 0".to_string()));
+    }
+    // The dimensions come from the message, so they are checked against the data before anything is allocated
+    let rows_len = height.checked_mul(row_step).filter(|len| *len <= data_len);
+    let row_len = width.checked_mul(point_step).filter(|len| *len <= row_step);
+    if rows_len.is_none() || row_len.is_none() {
+        return Err(XrError::Codec(format!(
+            "The PointCloud2 of {width}x{height} points with a point step of {point_step} and a row step of {row_step} does not fit in its {data_len} bytes"
+        )));
+    }
+
+    let field = |name: &str| fields.iter().find(|field| field.name == name);
+    let (Some(x_field), Some(y_field), Some(z_field)) = (field("x"), field("y"), field("z")) else {
+        return Err(XrError::Codec("The PointCloud2 has no x, y and z fields".to_string()));
+    };
+    // Colors are either packed in a single rgb(a) field, or stored as separate channels
+    let packed_color = field("rgb").or(field("rgba"));
+    let channels = match (field("r"), field("g"), field("b")) {
+        (Some(r), Some(g), Some(b)) => Some([r, g, b]),
+        _ => None,
+    };
+    let intensity_field = field("intensity");
+
+    let mut points = Vec::with_capacity((width * height).min(data_len / point_step));
+    let mut intensities = Vec::new();
+    for row in 0..height {
+        for column in 0..width {
+            // Within the data, as the dimensions were checked
+            let start = row * row_step + column * point_step;
+            let point = &data[start..start + point_step];
+            let x = read_field(point, x_field, big_endian)? as f32;
+            let y = read_field(point, y_field, big_endian)? as f32;
+            let z = read_field(point, z_field, big_endian)? as f32;
+            // Invalid points of organized clouds are NaN
+            if !(x.is_finite() && y.is_finite() && z.is_finite()) {
+                continue;
+            }
+            let [r, g, b] = if let Some(packed_color) = packed_color {
+                let bytes = point.get(packed_color.offset..packed_color.offset + 4)
+                    .ok_or_else(|| XrError::Codec(format!("The {} field is out of bounds", packed_color.name)))?;
+                // The color is packed as 0x00RRGGBB, also when it is declared as a float
+                let packed = if big_endian { u32::from_be_bytes(bytes.try_into().unwrap()) } else { u32::from_le_bytes(bytes.try_into().unwrap()) };
+                [(packed >> 16) as u8, (packed >> 8) as u8, packed as u8]
+            } else if let Some(channels) = channels {
+                let mut color = [0u8; 3];
+                for (value, channel) in color.iter_mut().zip(channels) {
+                    let channel_value = read_field(point, channel, big_endian)?;
+                    // Float channels are normalized
+                    *value = if channel.datatype == FLOAT32 || channel.datatype == FLOAT64 {
+                        (channel_value * 255.0).clamp(0.0, 255.0) as u8
+                    } else {
+                        channel_value.clamp(0.0, 255.0) as u8
+                    };
+                }
+                color
+            } else {
+                [255, 255, 255]
+            };
+            points.push(Point3D { x: -y, y: z, z: -x, r, g, b });
+            if let Some(intensity_field) = intensity_field {
+                intensities.push(read_field(point, intensity_field, big_endian)? as f32);
+            }
+        }
+    }
+
+    Ok(PointCloudData {
+        points,
+        attributes: PointAttributes {
+            intensities: intensity_field.map(|_| intensities),
+            ..Default::default()
+        },
+        ..Default::default()
+    })
+}
+
+/// Reads a scalar field of a point as an f64.
+fn read_field(point: &[u8], field: &PointField, big_endian: bool) -> XrResult<f64> {
+    let size = match field.datatype {
+        INT8 | UINT8 => 1,
+        INT16 | UINT16 => 2,
+        INT32 | UINT32 | FLOAT32 => 4,
+        FLOAT64 => 8,
+        other => return Err(XrError::Codec(format!("Unsupported datatype {other} of the {} field", field.name))),
+    };
+    let bytes = point.get(field.offset..field.offset + size)
+        .ok_or_else(|| XrError::Codec(format!("The {} field is out of bounds", field.name)))?;
+    macro_rules! read {
+        ($type:ty) => {{
+            let bytes = bytes.try_into().unwrap();
+            (if big_endian { <$type>::from_be_bytes(bytes) } else { <$type>::from_le_bytes(bytes) }) as f64
+        }};
+    }
+    Ok(match field.datatype {
+        INT8 => bytes[0] as i8 as f64,
+        UINT8 => bytes[0] as f64,
+        INT16 => read!(i16),
+        UINT16 => read!(u16),
+        INT32 => read!(i32),
+        UINT32 => read!(u32),
+        FLOAT32 => read!(f32),
+        _ => read!(f64),
+    })
+}
@@ -0,0 +1,364 @@
+// ingress/rtsp.rs
+
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::{Arc, OnceLock};
+use std::time::{Duration, Instant};
+
+use crate::processing::ProcessingPipeline;
+use crate::services::shutdown::is_shutting_down;
+use crate::services::stream_manager::StreamManager;
+use shared_utils::error::{XrError, XrResult};
+use shared_utils::track_remote_pointcloud_rtp::{FrameReassembler, STALE_FRAME_TIMEOUT};
+
+use tokio::io::{AsyncBufRead, AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+use tokio::net::tcp::{OwnedReadHalf, OwnedWriteHalf};
+use tokio::net::TcpStream;
+use tokio::runtime::{self, Runtime};
+use tokio::sync::Mutex;
+use tracing::{debug, error, info, instrument, warn};
+use url::Url;
+use webrtc::rtp::packet::Packet;
+use webrtc::util::Unmarshal;
+
+static RTSP_INGRESS_RUNTIME: OnceLock<Arc<Runtime>> = OnceLock::new();
+
+const DEFAULT_RTSP_PORT: u16 = 554;
+/// The interleaved channel the RTP packets are requested on, RTCP uses the next one
+const RTP_CHANNEL: u8 = 0;
+/// Used when the source does not announce a session timeout
+const DEFAULT_SESSION_TIMEOUT: Duration = Duration::from_secs(60);
+/// How long to wait before connecting again after the source was lost
+const RECONNECT_DELAY: Duration = Duration::from_secs(1);
+/// How often incomplete frames are cleaned up
+const STALE_FRAME_CHECK_INTERVAL: Duration = Duration::from_secs(5);
+
+#[derive(Debug)]
+struct RtspResponse {
+    status: u16,
+    headers: HashMap<String, String>,
+    body: String,
+}
+
+impl RtspResponse {
+    fn header(&self, name: &str) -> Option<&str> {
+        self.headers.get(&name.to_lowercase()).map(String::as_str)
+    }
+}
+
+/// The control connection of one RTSP session, requests are sent with increasing sequence numbers.
+struct RtspConnection {
+    reader: BufReader<OwnedReadHalf>,
+    writer: Arc<Mutex<OwnedWriteHalf>>,
+    cseq: Arc<AtomicU32>,
+    session: Option<String>,
+}
+
+impl RtspConnection {
+    async fn request(&mut self, method: &str, url: &str, headers: &[(&str, String)]) -> XrResult<RtspResponse> {
+        send_request(&self.writer, &self.cseq, method, url, self.session.as_deref(), headers).await?;
+        loop {
+            match read_message(&mut self.reader).await? {
+                None => return Err(XrError::Transport(format!("The RTSP source closed the connection during {method}"))),
+                Some(RtspMessage::Interleaved(..)) => continue,
+                Some(RtspMessage::Response(response)) if (200..300).contains(&response.status) => return Ok(response),
+                Some(RtspMessage::Response(response)) => {
+                    return Err(XrError::Transport(format!("RTSP {method} of {url} failed with status {}", response.status)));
+                }
+            }
+        }
+    }
+}
+
+enum RtspMessage {
+    /// An interleaved packet and its channel
+    Interleaved(u8, Vec<u8>),
+    Response(RtspResponse),
+}
+
+/// RTSP Ingress module responsible for pulling the point cloud stream of an RTSP source, e.g. a camera server or the RTSP egress
+/// of another server. The RTP packets are requested interleaved on the RTSP connection and carry the payload of the point cloud payloader,
+/// so frames are reassembled the same way as on the WebRTC tracks. The source is connected to again whenever it is lost.
+#[derive(Debug)]
+pub struct RtspIngress {
+    processing_pipeline: Arc<ProcessingPipeline>,
+    stream_manager: Arc<StreamManager>,
+    url: Url,
+}
+
+impl RtspIngress {
+    /// Initializes the RTSP Ingress module, and starts pulling the source.
+    #[instrument(skip_all)]
+    pub fn initialize(
+        stream_manager: Arc<StreamManager>,
+        processing_pipeline: Arc<ProcessingPipeline>,
+        url: Option<String>,
+    ) {
+        let Some(url) = url else {
+            info!("No RTSP source configured, the RTSP ingress is disabled");
+            return;
+        };
+        let url = match Url::parse(&url) {
+            Ok(url) if url.scheme() == "rtsp" && url.host_str().is_some() => url,
+            Ok(_) => {
+                error!("Failed to start the RTSP ingress: {} is not an rtsp:// URL", url);
+                return;
+            }
+            Err(e) => {
+                error!("Failed to start the RTSP ingress: invalid URL {}: {}", url, e);
+                return;
+            }
+        };
+
+        let instance = Arc::new(Self {
+            processing_pipeline,
+            stream_manager: stream_manager.clone(),
+            url,
+        });
+
+        let instance_clone = instance.clone();
+        instance.get_runtime().spawn(async move {
+            instance_clone.pull_source().await;
+        });
+
+        // Store the instance in the StreamManager
+        stream_manager.set_rtsp_ingress(instance.clone());
+    }
+
+    #[instrument(skip_all)]
+    pub fn get_runtime(&self) -> Arc<Runtime> {
+        RTSP_INGRESS_RUNTIME.get_or_init(|| {
+            let rt = runtime::Builder::new_multi_thread()
+                .worker_threads(1)
+                .thread_name_fn(|| {
+                    static ATOMIC_RTSP_I_ID: std::sync::atomic::AtomicUsize = std::sync::atomic::AtomicUsize::new(0);
+                    let id = ATOMIC_RTSP_I_ID.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+                    format!("RTSP_I w-{}", id)
+                })
+                .enable_all()
+                .build().unwrap();
+            Arc::new(rt)
+        }).clone()
+    }
+
+    /// Pulls the source, and connects to it again once it is lost, until the server shuts down.
+    async fn pull_source(&self) {
+        while !is_shutting_down() {
+            match self.play().await {
+                Ok(()) => info!("RTSP source {} ended the stream", self.url),
+                Err(e) => warn!("RTSP source {} lost: {}", self.url, e),
+            }
+            tokio::time::sleep(RECONNECT_DELAY).await;
+        }
+    }
+
+    /// Sets up a session with the source, and receives its frames until the connection closes.
+    async fn play(&self) -> XrResult<()> {
+        let host = self.url.host_str().unwrap_or_default();
+        let port = self.url.port().unwrap_or(DEFAULT_RTSP_PORT);
+        let stream = TcpStream::connect((host, port)).await
+            .map_err(|e| XrError::Transport(format!("Failed to connect to {host}:{port}: {e}")))?;
+        stream.set_nodelay(true).map_err(XrError::transport)?;
+        let (reader, writer) = stream.into_split();
+        let mut connection = RtspConnection {
+            reader: BufReader::new(reader),
+            writer: Arc::new(Mutex::new(writer)),
+            cseq: Arc::new(AtomicU32::new(1)),
+            session: None,
+        };
+
+        let url = self.url.to_string();
+        let description = connection.request("DESCRIBE", &url, &[("Accept", "application/sdp".to_string())]).await?;
+        let base = description
+            .header("content-base")
+            .or(description.header("content-location"))
+            .and_then(|base| Url::parse(base).ok())
+            .unwrap_or_else(|| self.url.clone());
+        let control = point_cloud_control(&description.body)
+            .ok_or_else(|| XrError::Transport(format!("{url} does not describe a point cloud stream")))?;
+        let track_url = resolve_control(&base, &control);
+
+        let transport = format!("RTP/AVP/TCP;unicast;interleaved={}-{}", RTP_CHANNEL, RTP_CHANNEL + 1);
+        let setup = connection.request("SETUP", &track_url, &[("Transport", transport)]).await?;
+        let session = setup.header("session").ok_or_else(|| XrError::Transport("SETUP response without a session".to_string()))?;
+        // The session may carry its timeout, e.g. `12345678;timeout=60`
+        let (session_id, timeout) = match session.split_once(';') {
+            Some((session_id, parameters)) => {
+                let timeout = parameters
+                    .trim()
+                    .strip_prefix("timeout=")
+                    .and_then(|timeout| timeout.trim().parse::<u64>().ok())
+                    .map(Duration::from_secs)
+                    .unwrap_or(DEFAULT_SESSION_TIMEOUT);
+                (session_id.trim().to_string(), timeout)
+            }
+            None => (session.trim().to_string(), DEFAULT_SESSION_TIMEOUT),
+        };
+        connection.session = Some(session_id.clone());
+
+        connection.request("PLAY", &url, &[("Range", "npt=0.000-".to_string())]).await?;
+        info!("Playing RTSP source {}", url);
+
+        // Keeps the session alive, the responses are skipped by the receive loop
+        let writer = connection.writer.clone();
+        let cseq = connection.cseq.clone();
+        let keep_alive_url = url.clone();
+        let keep_alive = tokio::spawn(async move {
+            loop {
+                tokio::time::sleep(timeout / 2).await;
+                if let Err(e) = send_request(&writer, &cseq, "GET_PARAMETER", &keep_alive_url, Some(&session_id), &[]).await {
+                    debug!("Failed to keep the RTSP session alive: {}", e);
+                    break;
+                }
+            }
+        });
+
+        let result = self.receive_frames(&mut connection.reader).await;
+        keep_alive.abort();
+
+        // The source may already be gone, so the teardown is best effort
+        let _ = send_request(&connection.writer, &connection.cseq, "TEARDOWN", &url, connection.session.as_deref(), &[]).await;
+        result
+    }
+
+    /// Reassembles the frames of the interleaved RTP packets, and pushes them to the decoder.
+    async fn receive_frames(&self, reader: &mut BufReader<OwnedReadHalf>) -> XrResult<()> {
+        let reassembler = FrameReassembler::default();
+        let mut last_stale_check = Instant::now();
+
+        loop {
+            if is_shutting_down() {
+                debug!("Stopping the RTSP ingress");
+                return Ok(());
+            }
+
+            let packet = match read_message(reader).await? {
+                Some(RtspMessage::Interleaved(RTP_CHANNEL, packet)) => packet,
+                // RTCP and the responses to the keep alive requests
+                Some(_) => continue,
+                None => return Ok(()),
+            };
+            let packet = match Packet::unmarshal(&mut packet.as_slice()) {
+                Ok(packet) => packet,
+                Err(e) => {
+                    debug!("Dropping an invalid RTP packet: {}", e);
+                    continue;
+                }
+            };
+            if let Some(frame) = reassembler.insert_packet(&packet.payload) {
+                let stream_id = match frame.sfu_tile_index {
+                    Some(tile_index) if tile_index > 0 => format!("rtsp_{}_{}", frame.sfu_client_id.unwrap_or(0), tile_index),
+                    _ => format!("rtsp_{}", frame.sfu_client_id.unwrap_or(0)),
+                };
+                self.processing_pipeline.push_to_decoder(frame.data, self.stream_manager.clone(), stream_id);
+            }
+            if last_stale_check.elapsed() > STALE_FRAME_CHECK_INTERVAL {
+                reassembler.remove_stale_frames(STALE_FRAME_TIMEOUT);
+                last_stale_check = Instant::now();
+            }
+        }
+    }
+}
+
+async fn send_request(
+    writer: &Mutex<OwnedWriteHalf>,
+    cseq: &AtomicU32,
+    method: &str,
+    url: &str,
+    session: Option<&str>,
+    headers: &[(&str, String)],
+) -> XrResult<()> {
+    let mut request = format!("{method} {url} RTSP/1.0\r\nCSeq: {}\r\nUser-Agent: pc-server\r\n", cseq.fetch_add(1, Ordering::Relaxed));
+    if let Some(session) = session {
+        request.push_str(&format!("Session: {session}\r\n"));
+    }
+    for (name, value) in headers {
+        request.push_str(&format!("{name}: {value}\r\n"));
+    }
+    request.push_str("\r\n");
+    writer.lock().await.write_all(request.as_bytes()).await
+        .map_err(|e| XrError::Transport(format!("Failed to write RTSP request: {e}")))
+}
+
+/// Reads the next interleaved packet or response from the source.
+/// Returns None once the source closed the connection.
+async fn read_message<R: AsyncBufRead + Unpin>(reader: &mut R) -> XrResult<Option<RtspMessage>> {
+    let transport_error = |e: std::io::Error| XrError::Transport(format!("Failed to read from the RTSP source: {e}"));
+    let buffer = reader.fill_buf().await.map_err(transport_error)?;
+    if buffer.is_empty() {
+        return Ok(None);
+    }
+    if buffer[0] == b'$' {
+        let mut header = [0u8; 4];
+        reader.read_exact(&mut header).await.map_err(transport_error)?;
+        let mut packet = vec![0u8; u16::from_be_bytes([header[2], header[3]]) as usize];
+        reader.read_exact(&mut packet).await.map_err(transport_error)?;
+        return Ok(Some(RtspMessage::Interleaved(header[1], packet)));
+    }
+
+    let mut status_line = String::new();
+    reader.read_line(&mut status_line).await.map_err(transport_error)?;
+    let status = status_line
+        .split_whitespace()
+        .nth(1)
+        .and_then(|status| status.parse::<u16>().ok())
+        .ok_or_else(|| XrError::Transport(format!("Invalid RTSP status line: {}", status_line.trim())))?;
+
+    let mut headers = HashMap::new();
+    loop {
+        let mut line = String::new();
+        if reader.read_line(&mut line).await.map_err(transport_error)? == 0 {
+            break;
+        }
+        let line = line.trim_end();
+        if line.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = line.split_once(':') {
+            headers.insert(name.trim().to_lowercase(), value.trim().to_string());
+        }
+    }
+
+    let content_length = headers.get("content-length").and_then(|value| value.parse::<usize>().ok()).unwrap_or(0);
+    let mut body = vec![0u8; content_length];
+    reader.read_exact(&mut body).await.map_err(transport_error)?;
+
+    Ok(Some(RtspMessage::Response(RtspResponse { status, headers, body: String::from_utf8_lossy(&body).into_owned() })))
+}
+
+/// Returns the control attribute of the first media section with the `pointcloud` encoding.
+fn point_cloud_control(session_description: &str) -> Option<String> {
+    let mut in_point_cloud_media = false;
+    let mut control = None;
+    for line in session_description.lines().map(str::trim) {
+        if line.starts_with("m=") {
+            if in_point_cloud_media {
+                break;
+            }
+            control = None;
+        } else if let Some(rtpmap) = line.strip_prefix("a=rtpmap:") {
+            if rtpmap.to_lowercase().contains(" pointcloud/") {
+                in_point_cloud_media = true;
+            }
+        } else if let Some(value) = line.strip_prefix("a=control:") {
+            control = Some(value.to_string());
+        }
+    }
+    in_point_cloud_media.then(|| control.unwrap_or_else(|| "*".to_string()))
+}
+
+/// Resolves the control attribute of a media section against the base URL of the session.
+fn resolve_control(base: &Url, control: &str) -> String {
+    if control == "*" {
+        return base.to_string();
+    }
+    if control.starts_with("rtsp://") {
+        return control.to_string();
+    }
+    // A relative control is appended to the base, which is treated as a directory
+    let mut base = base.to_string();
+    if !base.ends_with('/') {
+        base.push('/');
+    }
+    base + control
+}
@@ -0,0 +1,178 @@
+// ingress/srt.rs
+
+use std::fmt;
+use std::sync::{Arc, OnceLock};
+use std::time::Duration;
+
+use crate::egress::egress_common::frame_stream_id;
+use crate::processing::ProcessingPipeline;
+use crate::services::shutdown::is_shutting_down;
+use crate::services::stream_manager::StreamManager;
+use shared_utils::error::{XrError, XrResult};
+use shared_utils::wire::decode_frame;
+
+use futures::TryStreamExt;
+use srt_tokio::SrtSocket;
+use tokio::runtime::{self, Runtime};
+use tracing::{debug, error, info, instrument, warn};
+
+static SRT_INGRESS_RUNTIME: OnceLock<Arc<Runtime>> = OnceLock::new();
+
+/// How long to wait before listening or calling again after the sender was lost
+const RECONNECT_DELAY: Duration = Duration::from_secs(1);
+
+/// How the SRT ingress reaches its sender.
+#[derive(Clone, Debug)]
+pub enum SrtIngressMode {
+    /// Wait for a sender to call this UDP port
+    Listen(u16),
+    /// Call the listener of a sender on this address, e.g. the SRT egress of another server
+    Call(String),
+}
+
+/// Settings of the SRT ingress, they are applied during the handshake with the sender.
+#[derive(Clone)]
+pub struct SrtIngressConfig {
+    pub mode: SrtIngressMode,
+    /// The receive buffer, packets that cannot be recovered by retransmission within this time are dropped
+    pub latency: Duration,
+    /// Decrypt the stream with AES, the sender needs the same passphrase (10 to 79 characters)
+    pub passphrase: Option<String>,
+}
+
+impl fmt::Debug for SrtIngressConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SrtIngressConfig")
+            .field("mode", &self.mode)
+            .field("latency", &self.latency)
+            .field("passphrase", &self.passphrase.as_ref().map(|_| "<hidden>"))
+            .finish()
+    }
+}
+
+impl SrtIngressConfig {
+    fn validate(&self) -> XrResult<()> {
+        if let Some(passphrase) = &self.passphrase {
+            if !(10..=79).contains(&passphrase.len()) {
+                return Err(XrError::Config(format!("The SRT passphrase has {} characters, expected 10 to 79", passphrase.len())));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// SRT Ingress module responsible for receiving frames from a single SRT sender, e.g. the SRT egress of another server.
+/// Every SRT message holds one frame encoded with `encode_frame`, which makes the SRT egress and ingress a loop for end-to-end tests.
+#[derive(Debug)]
+pub struct SrtIngress {
+    processing_pipeline: Arc<ProcessingPipeline>,
+    stream_manager: Arc<StreamManager>,
+    config: SrtIngressConfig,
+}
+
+impl SrtIngress {
+    /// Initializes the SRT Ingress module, and starts listening for or calling the sender.
+    #[instrument(skip_all)]
+    pub fn initialize(
+        stream_manager: Arc<StreamManager>,
+        processing_pipeline: Arc<ProcessingPipeline>,
+        config: Option<SrtIngressConfig>,
+    ) {
+        let Some(config) = config else {
+            info!("No SRT ingress port or sender configured, the SRT ingress is disabled");
+            return;
+        };
+        if let Err(e) = config.validate() {
+            error!("Failed to initialize the SRT ingress: {}", e);
+            return;
+        }
+
+        let instance = Arc::new(Self {
+            processing_pipeline,
+            stream_manager: stream_manager.clone(),
+            config,
+        });
+
+        let instance_clone = instance.clone();
+        instance.get_runtime().spawn(async move {
+            instance_clone.receive_from_senders().await;
+        });
+
+        match &instance.config.mode {
+            SrtIngressMode::Listen(port) => info!("SRT ingress listening on port {} with a latency of {:?}", port, instance.config.latency),
+            SrtIngressMode::Call(address) => info!("SRT ingress calling {} with a latency of {:?}", address, instance.config.latency),
+        }
+
+        // Store the instance in the StreamManager
+        stream_manager.set_srt_ingress(instance.clone());
+    }
+
+    #[instrument(skip_all)]
+    pub fn get_runtime(&self) -> Arc<Runtime> {
+        SRT_INGRESS_RUNTIME.get_or_init(|| {
+            let rt = runtime::Builder::new_multi_thread()
+                .worker_threads(1)
+                .thread_name_fn(|| {
+                    static ATOMIC_SRT_I_ID: std::sync::atomic::AtomicUsize = std::sync::atomic::AtomicUsize::new(0);
+                    let id = ATOMIC_SRT_I_ID.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+                    format!("SRT_I w-{}", id)
+                })
+                .enable_all()
+                .build().unwrap();
+            Arc::new(rt)
+        }).clone()
+    }
+
+    /// Receives from a sender, and waits for or calls the next one once it is gone, until the server shuts down.
+    async fn receive_from_senders(&self) {
+        while !is_shutting_down() {
+            match self.connect().await {
+                Ok(socket) => {
+                    info!("SRT sender connected");
+                    match self.receive_frames(socket).await {
+                        Ok(()) => info!("SRT sender disconnected"),
+                        Err(e) => warn!("SRT sender lost: {}", e),
+                    }
+                }
+                Err(e) => {
+                    error!("Failed to connect to an SRT sender: {}", e);
+                }
+            }
+            tokio::time::sleep(RECONNECT_DELAY).await;
+        }
+    }
+
+    async fn connect(&self) -> XrResult<SrtSocket> {
+        let mut builder = SrtSocket::builder().latency(self.config.latency);
+        if let Some(passphrase) = &self.config.passphrase {
+            // A key size of 0 lets the sender pick the AES key length
+            builder = builder.encryption(0, passphrase.clone());
+        }
+        match &self.config.mode {
+            SrtIngressMode::Listen(port) => builder.listen_on(format!(":{port}").as_str()).await
+                .map_err(|e| XrError::Transport(format!("SRT listener failed: {e}"))),
+            SrtIngressMode::Call(address) => builder.call(address.as_str(), None).await
+                .map_err(|e| XrError::Transport(format!("Failed to call SRT sender {address}: {e}"))),
+        }
+    }
+
+    /// Pushes every received frame to the decoder, until the sender closes the connection.
+    async fn receive_frames(&self, mut socket: SrtSocket) -> XrResult<()> {
+        while let Some((_, message)) = socket.try_next().await.map_err(XrError::transport)? {
+            if is_shutting_down() {
+                debug!("Stopping the SRT ingress");
+                break;
+            }
+            let frame = match decode_frame(&message) {
+                Ok(frame) => frame,
+                Err(e) => {
+                    debug!("Dropping an invalid SRT message: {}", e);
+                    continue;
+                }
+            };
+            let stream_id = format!("srt_{}", frame_stream_id(&frame));
+            self.processing_pipeline.push_to_decoder(frame.data, self.stream_manager.clone(), stream_id);
+        }
+        Ok(())
+    }
+}
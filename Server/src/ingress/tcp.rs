@@ -0,0 +1,244 @@
+// ingress/tcp.rs
+
+use std::collections::HashSet;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::Duration;
+
+use crate::processing::ProcessingPipeline;
+use crate::services::shutdown::is_shutting_down;
+use crate::services::stream_manager::StreamManager;
+use shared_utils::error::{XrError, XrResult};
+
+use super::is_valid_stream_id;
+
+use metrics::get_metrics;
+use prometheus::{IntCounterVec, IntGauge};
+use tokio::io::AsyncReadExt;
+use tokio::net::{TcpListener, TcpStream};
+use tokio::runtime::{self, Runtime};
+use tracing::{debug, error, info, instrument, warn};
+
+static TCP_INGRESS_RUNTIME: OnceLock<Arc<Runtime>> = OnceLock::new();
+
+/// Every connection starts with these 4 bytes, followed by a 2 byte big endian length and the UTF-8 stream id
+pub const TCP_INGRESS_MAGIC: &[u8; 4] = b"XRPC";
+/// Like `TCP_INGRESS_MAGIC`, but the stream id is followed by a 2 byte big endian length and the access token of the producer
+pub const TCP_INGRESS_TOKEN_MAGIC: &[u8; 4] = b"XRPT";
+/// Longest stream id a producer may announce
+const MAX_STREAM_ID_LEN: usize = 256;
+/// Longest access token a producer may present
+const MAX_TOKEN_LEN: usize = 1024;
+/// Largest frame a length prefix may announce, anything larger means the producer lost the framing
+const MAX_FRAME_SIZE: usize = 64 * 1024 * 1024;
+/// A producer that does not send its handshake within this time is disconnected
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(5);
+
+#[derive(Debug)]
+struct TcpIngressMetrics {
+    connections: IntGauge,
+    frames: IntCounterVec,
+    bytes: IntCounterVec,
+}
+
+impl TcpIngressMetrics {
+    fn new() -> Self {
+        let metrics = get_metrics();
+        Self {
+            connections: metrics
+                .get_or_create_gauge("tcp_ingress_connections", "Number of producers that are connected to the TCP ingress")
+                .unwrap(),
+            frames: metrics
+                .get_or_create_counter_vec("tcp_ingress_frames", "Number of frames received by the TCP ingress, by stream", &["stream"])
+                .unwrap(),
+            bytes: metrics
+                .get_or_create_counter_vec("tcp_ingress_bytes", "Number of frame bytes received by the TCP ingress, by stream", &["stream"])
+                .unwrap(),
+        }
+    }
+}
+
+/// TCP Ingress module responsible for receiving encoded frames from producers on plain TCP connections, e.g. capture rigs on the LAN.
+/// A producer announces its stream id in a handshake (`XRPC`, a 2 byte big endian length and the stream id),
+/// or `XRPT` with the same fields followed by its access token when the server requires one, after which every frame is a 4 byte big endian length followed by the encoded frame, as written by the TCP egress.
+#[derive(Debug)]
+pub struct TcpIngress {
+    processing_pipeline: Arc<ProcessingPipeline>,
+    stream_manager: Arc<StreamManager>,
+    /// The stream ids of the connected producers, a stream id can only be published by one producer at a time
+    active_streams: Mutex<HashSet<String>>,
+    metrics: TcpIngressMetrics,
+}
+
+impl TcpIngress {
+    /// Initializes the TCP Ingress module, and starts accepting producers on the listen address.
+    #[instrument(skip_all)]
+    pub fn initialize(
+        stream_manager: Arc<StreamManager>,
+        processing_pipeline: Arc<ProcessingPipeline>,
+        address: Option<SocketAddr>,
+    ) {
+        let Some(address) = address else {
+            info!("No TCP ingress listen address configured, the TCP ingress is disabled");
+            return;
+        };
+
+        let instance = Arc::new(Self {
+            processing_pipeline,
+            stream_manager: stream_manager.clone(),
+            active_streams: Mutex::new(HashSet::new()),
+            metrics: TcpIngressMetrics::new(),
+        });
+
+        let instance_clone = instance.clone();
+        instance.get_runtime().spawn(async move {
+            if let Err(e) = instance_clone.accept_producers(address).await {
+                error!("TCP ingress stopped: {}", e);
+            }
+        });
+
+        // Store the instance in the StreamManager
+        stream_manager.set_tcp_ingress(instance.clone());
+    }
+
+    #[instrument(skip_all)]
+    pub fn get_runtime(&self) -> Arc<Runtime> {
+        TCP_INGRESS_RUNTIME.get_or_init(|| {
+            let rt = runtime::Builder::new_multi_thread()
+                .worker_threads(2)
+                .thread_name_fn(|| {
+                    static ATOMIC_TCP_I_ID: std::sync::atomic::AtomicUsize = std::sync::atomic::AtomicUsize::new(0);
+                    let id = ATOMIC_TCP_I_ID.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+                    format!("TCP_I w-{}", id)
+                })
+                .enable_all()
+                .build().unwrap();
+            Arc::new(rt)
+        }).clone()
+    }
+
+    /// Accepts producers on the listen address, every producer is read on its own task.
+    async fn accept_producers(self: Arc<Self>, address: SocketAddr) -> XrResult<()> {
+        let listener = TcpListener::bind(address).await
+            .map_err(|e| XrError::Transport(format!("Failed to listen on {address}: {e}")))?;
+        info!("TCP ingress listening on {}", address);
+
+        loop {
+            match listener.accept().await {
+                Ok((stream, peer)) => {
+                    let self_clone = self.clone();
+                    tokio::spawn(async move {
+                        self_clone.handle_producer(peer, stream).await;
+                    });
+                }
+                Err(e) => {
+                    error!("Failed to accept a TCP producer: {}", e);
+                    tokio::time::sleep(Duration::from_secs(1)).await;
+                }
+            }
+        }
+    }
+
+    async fn handle_producer(&self, peer: SocketAddr, mut stream: TcpStream) {
+        let (stream_id, token) = match tokio::time::timeout(HANDSHAKE_TIMEOUT, read_handshake(&mut stream)).await {
+            Ok(Ok(handshake)) => handshake,
+            Ok(Err(e)) => {
+                warn!("Rejecting TCP producer {}: {}", peer, e);
+                return;
+            }
+            Err(_) => {
+                warn!("Rejecting TCP producer {}: no handshake within {:?}", peer, HANDSHAKE_TIMEOUT);
+                return;
+            }
+        };
+        if let Err(e) = self.stream_manager.authorize_producer(&stream_id, token.as_deref()) {
+            warn!("Rejecting TCP producer {}: {}", peer, e);
+            return;
+        }
+        if !self.active_streams.lock().unwrap().insert(stream_id.clone()) {
+            warn!("Rejecting TCP producer {}: stream {} is already published by another producer", peer, stream_id);
+            return;
+        }
+        info!("TCP producer {} connected for stream {}", peer, stream_id);
+        self.metrics.connections.inc();
+
+        if let Err(e) = self.receive_frames(&mut stream, &stream_id).await {
+            warn!("TCP producer {} of stream {} disconnected: {}", peer, stream_id, e);
+        } else {
+            info!("TCP producer {} of stream {} disconnected", peer, stream_id);
+        }
+
+        self.metrics.connections.dec();
+        self.active_streams.lock().unwrap().remove(&stream_id);
+        self.stream_manager.revoke_producer(&stream_id);
+    }
+
+    /// Reads frames until the producer closes the connection or the server shuts down.
+    async fn receive_frames(&self, stream: &mut TcpStream, stream_id: &str) -> XrResult<()> {
+        let metrics = get_metrics();
+        let frames_rate = metrics
+            .get_or_create_rate_gauge("tcp_ingress_frames_rate", "Frames per second received by the TCP ingress, over the last second.", Duration::from_secs(1), &[("stream", stream_id)])
+            .unwrap();
+        let bytes_rate = metrics
+            .get_or_create_rate_gauge("tcp_ingress_bytes_rate", "Bytes per second received by the TCP ingress, over the last second.", Duration::from_secs(1), &[("stream", stream_id)])
+            .unwrap();
+        let frames = self.metrics.frames.with_label_values(&[stream_id]);
+        let bytes = self.metrics.bytes.with_label_values(&[stream_id]);
+
+        loop {
+            if is_shutting_down() {
+                debug!("Closing the TCP producer of stream {}", stream_id);
+                return Ok(());
+            }
+
+            let frame_len = match stream.read_u32().await {
+                Ok(frame_len) => frame_len as usize,
+                // The producer closed the connection between two frames
+                Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => return Ok(()),
+                Err(e) => return Err(XrError::transport(e)),
+            };
+            if frame_len == 0 || frame_len > MAX_FRAME_SIZE {
+                return Err(XrError::Transport(format!("Invalid frame length of {frame_len} bytes")));
+            }
+            let mut frame = vec![0u8; frame_len];
+            stream.read_exact(&mut frame).await.map_err(XrError::transport)?;
+
+            frames.inc();
+            bytes.inc_by(frame_len as u64);
+            frames_rate.add(1);
+            bytes_rate.add(frame_len as u64);
+            self.processing_pipeline.push_to_decoder(frame, self.stream_manager.clone(), stream_id.to_string());
+        }
+    }
+}
+
+/// Reads the handshake of a producer, and returns the stream id it publishes and the token it presented.
+async fn read_handshake(stream: &mut TcpStream) -> XrResult<(String, Option<String>)> {
+    let mut magic = [0u8; 4];
+    stream.read_exact(&mut magic).await.map_err(XrError::transport)?;
+    if &magic != TCP_INGRESS_MAGIC && &magic != TCP_INGRESS_TOKEN_MAGIC {
+        return Err(XrError::Transport("The connection does not start with the XRPC or XRPT handshake".to_string()));
+    }
+    let stream_id_len = stream.read_u16().await.map_err(XrError::transport)? as usize;
+    if stream_id_len == 0 || stream_id_len > MAX_STREAM_ID_LEN {
+        return Err(XrError::Transport(format!("Invalid stream id length of {stream_id_len} bytes")));
+    }
+    let mut stream_id = vec![0u8; stream_id_len];
+    stream.read_exact(&mut stream_id).await.map_err(XrError::transport)?;
+    let stream_id = String::from_utf8(stream_id).map_err(|_| XrError::Transport("The stream id is not valid UTF-8".to_string()))?;
+    if !is_valid_stream_id(&stream_id) {
+        return Err(XrError::Transport(format!("Invalid stream id {stream_id}, only letters, digits, '_', '-' and '.' are allowed")));
+    }
+    if &magic != TCP_INGRESS_TOKEN_MAGIC {
+        return Ok((stream_id, None));
+    }
+
+    let token_len = stream.read_u16().await.map_err(XrError::transport)? as usize;
+    if token_len == 0 || token_len > MAX_TOKEN_LEN {
+        return Err(XrError::Transport(format!("Invalid token length of {token_len} bytes")));
+    }
+    let mut token = vec![0u8; token_len];
+    stream.read_exact(&mut token).await.map_err(XrError::transport)?;
+    let token = String::from_utf8(token).map_err(|_| XrError::Transport("The token is not valid UTF-8".to_string()))?;
+    Ok((stream_id, Some(token)))
+}
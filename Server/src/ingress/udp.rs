@@ -0,0 +1,307 @@
+// ingress/udp.rs
+
+use std::collections::HashMap;
+use std::net::{Ipv4Addr, SocketAddr, SocketAddrV4, UdpSocket};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::processing::ProcessingPipeline;
+use crate::services::shutdown::is_shutting_down;
+use crate::services::stream_manager::StreamManager;
+use shared_utils::error::{XrError, XrResult};
+use shared_utils::track_remote_pointcloud_rtp::{FrameReassembler, STALE_FRAME_TIMEOUT};
+
+use socket2::{Domain, Protocol, Socket, Type};
+use tracing::{debug, error, info, instrument};
+use webrtc::rtp::packet::Packet;
+use webrtc::util::Unmarshal;
+
+/// Largest datagram that is accepted
+const MAX_DATAGRAM_SIZE: usize = 65_535;
+/// Largest frame a chunk header may announce, this also caps the data that is buffered for a sender
+const MAX_FRAME_SIZE: usize = 64 * 1024 * 1024;
+/// Every datagram of the chunked framing starts with the length of its frame and the offset of its chunk in the frame, both 4 byte big endian
+const CHUNK_HEADER_SIZE: usize = 8;
+/// How long an incomplete chunked frame is kept when no more of its chunks arrive
+const PENDING_FRAME_TIMEOUT: Duration = Duration::from_secs(2);
+/// How often incomplete frames are cleaned up
+const STALE_FRAME_CHECK_INTERVAL: Duration = Duration::from_secs(1);
+/// When the server requires access tokens, every datagram starts with these 4 bytes, a 2 byte big endian length and the token of the sender,
+/// followed by the datagram as it would be sent without authentication
+pub const UDP_INGRESS_TOKEN_MAGIC: &[u8; 4] = b"XRPT";
+
+/// How the point cloud frames are framed in the datagrams.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum UdpFraming {
+    /// Every datagram holds one or more whole frames, each after its 4 byte big endian length
+    LengthPrefixed,
+    /// Every datagram is a chunk of a frame, after the 4 byte big endian length of the frame and offset of the chunk.
+    /// A frame may span several datagrams, which have to arrive in order
+    Chunked,
+    /// Every datagram is an RTP packet with the point cloud payload of the RTP egress
+    Rtp,
+}
+
+impl UdpFraming {
+    pub fn parse(value: &str) -> XrResult<Self> {
+        match value.trim().to_lowercase().as_str() {
+            "length" | "length-prefixed" => Ok(UdpFraming::LengthPrefixed),
+            "chunked" => Ok(UdpFraming::Chunked),
+            "rtp" => Ok(UdpFraming::Rtp),
+            other => Err(XrError::Config(format!("Unsupported UDP framing {other}, expected length, chunked or rtp"))),
+        }
+    }
+}
+
+/// Settings of the UDP ingress.
+#[derive(Clone, Debug)]
+pub struct UdpIngressConfig {
+    pub port: u16,
+    /// Multicast group that is joined on all interfaces, the socket only receives unicast without it
+    pub multicast_group: Option<Ipv4Addr>,
+    pub framing: UdpFraming,
+}
+
+/// UDP Ingress module responsible for receiving encoded frames as plain UDP datagrams,
+/// so simple capture tools can inject frames without setting up WebRTC or a websocket.
+#[derive(Debug)]
+pub struct UdpIngress {
+    processing_pipeline: Arc<ProcessingPipeline>,
+    stream_manager: Arc<StreamManager>,
+    config: UdpIngressConfig,
+}
+
+impl UdpIngress {
+    /// Initializes the UDP Ingress module, and starts receiving on the configured port.
+    #[instrument(skip_all)]
+    pub fn initialize(
+        stream_manager: Arc<StreamManager>,
+        processing_pipeline: Arc<ProcessingPipeline>,
+        config: Option<UdpIngressConfig>,
+    ) {
+        let Some(config) = config else {
+            info!("No UDP ingress port configured, the UDP ingress is disabled");
+            return;
+        };
+
+        let socket = match bind_socket(&config) {
+            Ok(socket) => socket,
+            Err(e) => {
+                error!("Failed to start the UDP ingress: {}", e);
+                return;
+            }
+        };
+        match config.multicast_group {
+            Some(group) => info!("UDP ingress receives {:?} frames on {}:{}", config.framing, group, config.port),
+            None => info!("UDP ingress receives {:?} frames on port {}", config.framing, config.port),
+        }
+
+        let instance = Arc::new(Self {
+            processing_pipeline,
+            stream_manager: stream_manager.clone(),
+            config,
+        });
+
+        let instance_clone = instance.clone();
+        let _ = thread::Builder::new().name("UDP_I Receive Thread".to_string()).spawn(move || {
+            instance_clone.receive(socket);
+        });
+
+        // Store the instance in the StreamManager
+        stream_manager.set_udp_ingress(instance.clone());
+    }
+
+    /// Receives datagrams until the server shuts down.
+    fn receive(&self, socket: UdpSocket) {
+        let mut datagram = vec![0u8; MAX_DATAGRAM_SIZE];
+        // The partially received chunked frames, by sender
+        let mut pending: HashMap<SocketAddr, PendingFrame> = HashMap::new();
+        let reassembler = FrameReassembler::default();
+        let mut last_stale_check = Instant::now();
+
+        loop {
+            if is_shutting_down() {
+                debug!("Stopping the UDP ingress");
+                break;
+            }
+
+            // Senders that stopped, or were never more than a spoofed address, do not keep their data
+            if last_stale_check.elapsed() > STALE_FRAME_CHECK_INTERVAL {
+                reassembler.remove_stale_frames(STALE_FRAME_TIMEOUT);
+                pending.retain(|_, frame| frame.last_chunk.elapsed() < PENDING_FRAME_TIMEOUT);
+                last_stale_check = Instant::now();
+            }
+
+            let (len, source) = match socket.recv_from(&mut datagram) {
+                Ok(received) => received,
+                Err(e) if matches!(e.kind(), std::io::ErrorKind::WouldBlock | std::io::ErrorKind::TimedOut) => continue,
+                Err(e) => {
+                    error!("Failed to receive on the UDP ingress: {}", e);
+                    break;
+                }
+            };
+
+            let (token, payload) = if self.stream_manager.authentication_required() {
+                match split_token(&datagram[..len]) {
+                    Some((token, payload)) => (Some(token), payload),
+                    None => {
+                        debug!("Dropping a datagram from {} without an access token", source);
+                        continue;
+                    }
+                }
+            } else {
+                (None, &datagram[..len])
+            };
+
+            match self.config.framing {
+                UdpFraming::LengthPrefixed => {
+                    let stream_id = format!("udp_{source}");
+                    if let Err(e) = self.stream_manager.authorize_producer(&stream_id, token) {
+                        debug!("Dropping a datagram from {}: {}", source, e);
+                        continue;
+                    }
+                    for frame in split_length_prefixed_frames(payload, source) {
+                        self.push_frame(frame.to_vec(), stream_id.clone());
+                    }
+                }
+                UdpFraming::Chunked => {
+                    let stream_id = format!("udp_{source}");
+                    if let Err(e) = self.stream_manager.authorize_producer(&stream_id, token) {
+                        debug!("Dropping a datagram from {}: {}", source, e);
+                        continue;
+                    }
+                    if let Some(frame) = insert_chunk(&mut pending, source, payload) {
+                        self.push_frame(frame, stream_id);
+                    }
+                }
+                UdpFraming::Rtp => {
+                    let packet = match Packet::unmarshal(&mut &payload[..]) {
+                        Ok(packet) => packet,
+                        Err(e) => {
+                            debug!("Dropping a datagram from {} that is not an RTP packet: {}", source, e);
+                            continue;
+                        }
+                    };
+                    if let Some(frame) = reassembler.insert_packet(&packet.payload) {
+                        // The payload identifies the producer, so frames of one producer keep their stream id across senders
+                        let stream_id = match frame.sfu_tile_index {
+                            Some(tile_index) if tile_index > 0 => format!("udp_{}_{}", frame.sfu_client_id.unwrap_or(0), tile_index),
+                            _ => format!("udp_{}", frame.sfu_client_id.unwrap_or(0)),
+                        };
+                        // The token of the datagram that completes the frame decides, the stream id is only known from the payload
+                        match self.stream_manager.authorize_producer(&stream_id, token) {
+                            Ok(()) => self.push_frame(frame.data, stream_id),
+                            Err(e) => debug!("Dropping a frame from {}: {}", source, e),
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    fn push_frame(&self, raw_data: Vec<u8>, stream_id: String) {
+        self.processing_pipeline.push_to_decoder(raw_data, self.stream_manager.clone(), stream_id);
+    }
+}
+
+/// Splits a datagram into the access token of the sender and the actual datagram, `None` when it does not start with a token.
+fn split_token(datagram: &[u8]) -> Option<(&str, &[u8])> {
+    let rest = datagram.strip_prefix(UDP_INGRESS_TOKEN_MAGIC)?;
+    let token_len = u16::from_be_bytes(rest.get(..2)?.try_into().unwrap()) as usize;
+    let token = std::str::from_utf8(rest.get(2..2 + token_len)?).ok()?;
+    Some((token, &rest[2 + token_len..]))
+}
+
+/// Splits a datagram into the frames that follow their length prefix, a frame can not span datagrams.
+/// A length that runs past the end of the datagram drops the rest of the datagram.
+fn split_length_prefixed_frames(datagram: &[u8], source: SocketAddr) -> Vec<&[u8]> {
+    let mut frames = Vec::new();
+    let mut rest = datagram;
+    while !rest.is_empty() {
+        let frame_len = match rest.get(..4) {
+            Some(prefix) => u32::from_be_bytes(prefix.try_into().unwrap()) as usize,
+            None => 0,
+        };
+        if frame_len == 0 || rest.len() - 4 < frame_len {
+            debug!("Dropping the last {} bytes of a datagram from {}, they are not a length prefixed frame", rest.len(), source);
+            break;
+        }
+        frames.push(&rest[4..4 + frame_len]);
+        rest = &rest[4 + frame_len..];
+    }
+    frames
+}
+
+/// A chunked frame of which not all chunks were received yet.
+struct PendingFrame {
+    data: Vec<u8>,
+    frame_len: usize,
+    last_chunk: Instant,
+}
+
+/// Adds a chunk of a chunked frame, and returns the frame once all its chunks were received.
+/// Every datagram carries the length of its frame and the offset of its chunk, so a lost or reordered datagram
+/// only drops the frame it belongs to, the sender's next frame starts again at offset 0.
+fn insert_chunk(pending: &mut HashMap<SocketAddr, PendingFrame>, source: SocketAddr, datagram: &[u8]) -> Option<Vec<u8>> {
+    if datagram.len() < CHUNK_HEADER_SIZE {
+        debug!("Dropping a datagram from {} without a chunk header", source);
+        return None;
+    }
+    let frame_len = u32::from_be_bytes(datagram[..4].try_into().unwrap()) as usize;
+    let offset = u32::from_be_bytes(datagram[4..8].try_into().unwrap()) as usize;
+    let chunk = &datagram[CHUNK_HEADER_SIZE..];
+    if frame_len == 0 || frame_len > MAX_FRAME_SIZE || offset + chunk.len() > frame_len {
+        debug!("Dropping a chunk of {} bytes at offset {} of a frame of {} bytes from {}", chunk.len(), offset, frame_len, source);
+        return None;
+    }
+
+    if offset == 0 {
+        if chunk.len() == frame_len {
+            pending.remove(&source);
+            return Some(chunk.to_vec());
+        }
+        // A new frame replaces the incomplete frame of the sender
+        pending.insert(source, PendingFrame { data: chunk.to_vec(), frame_len, last_chunk: Instant::now() });
+        return None;
+    }
+
+    let frame = pending.get_mut(&source)?;
+    if frame.frame_len != frame_len || frame.data.len() != offset {
+        debug!("Dropping the incomplete frame of {}, one of its chunks was lost or reordered", source);
+        pending.remove(&source);
+        return None;
+    }
+    frame.data.extend_from_slice(chunk);
+    frame.last_chunk = Instant::now();
+    if frame.data.len() < frame_len {
+        return None;
+    }
+    pending.remove(&source).map(|frame| frame.data)
+}
+
+/// Binds the socket, joins the multicast group if there is one, and lets it time out regularly
+/// so the receive thread can notice that the server shuts down.
+fn bind_socket(config: &UdpIngressConfig) -> XrResult<UdpSocket> {
+    let socket = Socket::new(Domain::IPV4, Type::DGRAM, Some(Protocol::UDP)).map_err(XrError::transport)?;
+    // Other receivers on this host may join the same multicast group
+    if config.multicast_group.is_some() {
+        socket.set_reuse_address(true).map_err(XrError::transport)?;
+    }
+    // Fits a few large frames that arrive in a burst
+    let _ = socket.set_recv_buffer_size(8 * 1024 * 1024);
+    socket
+        .bind(&SocketAddrV4::new(Ipv4Addr::UNSPECIFIED, config.port).into())
+        .map_err(|e| XrError::Transport(format!("Failed to bind the UDP ingress on port {}: {e}", config.port)))?;
+    if let Some(group) = config.multicast_group {
+        if !group.is_multicast() {
+            return Err(XrError::Config(format!("{group} is not a multicast address")));
+        }
+        socket
+            .join_multicast_v4(&group, &Ipv4Addr::UNSPECIFIED)
+            .map_err(|e| XrError::Transport(format!("Failed to join the multicast group {group}: {e}")))?;
+    }
+    let socket: UdpSocket = socket.into();
+    socket.set_read_timeout(Some(Duration::from_millis(500))).map_err(XrError::transport)?;
+    Ok(socket)
+}
@@ -53,6 +53,10 @@ impl WebRTCIngress {
             let stream_manager_clone = stream_manager.clone();
             let processing_pipeline_clone = processing_pipeline.clone();
             Box::pin(async move {
+                // The websocket handler authorizes the stream when the client connects for its signaling
+                if !stream_manager_clone.is_producer_authorized(&stream_id_clone) {
+                    return;
+                }
                 processing_pipeline_clone.push_to_decoder(msg.data.to_vec(), stream_manager_clone, stream_id_clone);
             })
         }));
@@ -39,6 +39,10 @@ impl WebSocketIngress {
             let stream_manager_clone = stream_manager.clone();
             let processing_pipeline_clone = processing_pipeline.clone();
 
+            // The authorization is revoked when the socket disconnects or the tokens change
+            if !stream_manager_clone.is_producer_authorized(&stream_id_clone) {
+                return;
+            }
             processing_pipeline_clone.push_to_decoder(data.clone(), stream_manager_clone, stream_id_clone);
         });
 
@@ -0,0 +1,228 @@
+// ingress/webtransport.rs
+
+use std::fmt;
+use std::sync::{Arc, OnceLock};
+use std::time::Duration;
+
+use crate::processing::ProcessingPipeline;
+use crate::services::auth::bearer_token;
+use crate::services::shutdown::is_shutting_down;
+use crate::services::stream_manager::StreamManager;
+use shared_utils::error::{XrError, XrResult};
+
+use super::is_valid_stream_id;
+
+use metrics::get_metrics;
+use prometheus::IntCounterVec;
+use tokio::io::AsyncReadExt;
+use tokio::runtime::{self, Runtime};
+use tracing::{debug, error, info, instrument, warn};
+use wtransport::endpoint::IncomingSession;
+use wtransport::{Connection, Endpoint, Identity, ServerConfig};
+
+static WEBTRANSPORT_INGRESS_RUNTIME: OnceLock<Arc<Runtime>> = OnceLock::new();
+
+/// Largest frame a browser may push on a stream, frames are held in memory until the stream is finished
+const MAX_FRAME_SIZE: u64 = 64 * 1024 * 1024;
+
+/// WebTransport Ingress module responsible for accepting sessions of browsers that push captured frames upstream,
+/// as an alternative to the WebSocket upload path without head-of-line blocking between frames.
+/// The session path is the stream id, e.g. `https://server:4435/client_0`, and every unidirectional stream or datagram holds one encoded frame.
+/// When the server requires access tokens, the browser passes its token in the query string, e.g. `https://server:4435/client_0?token=s3cret`.
+pub struct WebTransportIngress {
+    processing_pipeline: Arc<ProcessingPipeline>,
+    stream_manager: Arc<StreamManager>,
+    /// Base64 encoded SHA-256 hash of the self-signed certificate, browsers need it in `serverCertificateHashes`
+    certificate_hash: String,
+    frames: IntCounterVec,
+}
+
+impl fmt::Debug for WebTransportIngress {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("WebTransportIngress")
+            .field("processing_pipeline", &self.processing_pipeline)
+            .field("certificate_hash", &self.certificate_hash)
+            .finish()
+    }
+}
+
+impl WebTransportIngress {
+    /// Initializes the WebTransport Ingress module, and starts accepting sessions on `port`.
+    #[instrument(skip_all)]
+    pub fn initialize(
+        stream_manager: Arc<StreamManager>,
+        processing_pipeline: Arc<ProcessingPipeline>,
+        port: Option<u16>,
+    ) {
+        let Some(port) = port else {
+            info!("No WebTransport ingress port configured, the WebTransport ingress is disabled");
+            return;
+        };
+        // Browsers only accept a self-signed certificate that is valid for at most 14 days, which is what wtransport generates
+        let identity = match Identity::self_signed(["localhost", "127.0.0.1", "::1"]) {
+            Ok(identity) => identity,
+            Err(e) => {
+                error!("Failed to generate the WebTransport ingress certificate: {}", e);
+                return;
+            }
+        };
+        let certificate_digest = identity.certificate_chain().as_slice()[0].hash();
+        let certificate_bytes: &[u8; 32] = certificate_digest.as_ref();
+        let certificate_hash = rbase64::encode(certificate_bytes);
+        let frames = match get_metrics().get_or_create_counter_vec("webtransport_ingress_frames", "Number of frames received by the WebTransport ingress, by transport", &["transport"]) {
+            Ok(frames) => frames,
+            Err(e) => {
+                error!("Failed to create the WebTransport ingress metrics: {}", e);
+                return;
+            }
+        };
+
+        let instance = Arc::new(Self {
+            processing_pipeline,
+            stream_manager: stream_manager.clone(),
+            certificate_hash,
+            frames,
+        });
+
+        let config = ServerConfig::builder()
+            .with_bind_default(port)
+            .with_identity(identity)
+            .keep_alive_interval(Some(Duration::from_secs(3)))
+            .build();
+
+        // The endpoint has to be created inside the runtime
+        let instance_clone = instance.clone();
+        instance.get_runtime().spawn(async move {
+            if let Err(e) = instance_clone.accept_sessions(config).await {
+                error!("WebTransport ingress stopped: {}", e);
+            }
+        });
+
+        info!("WebTransport ingress listening on port {} with certificate hash {}", port, instance.certificate_hash);
+
+        // Store the instance in the StreamManager
+        stream_manager.set_webtransport_ingress(instance.clone());
+    }
+
+    #[instrument(skip_all)]
+    pub fn get_runtime(&self) -> Arc<Runtime> {
+        WEBTRANSPORT_INGRESS_RUNTIME.get_or_init(|| {
+            let rt = runtime::Builder::new_multi_thread()
+                .worker_threads(2)
+                .thread_name_fn(|| {
+                    static ATOMIC_WT_I_ID: std::sync::atomic::AtomicUsize = std::sync::atomic::AtomicUsize::new(0);
+                    let id = ATOMIC_WT_I_ID.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+                    format!("WT_I w-{}", id)
+                })
+                .enable_all()
+                .build().unwrap();
+            Arc::new(rt)
+        }).clone()
+    }
+
+    /// Base64 encoded SHA-256 hash of the certificate of the WebTransport ingress endpoint.
+    pub fn certificate_hash(&self) -> &str {
+        &self.certificate_hash
+    }
+
+    /// Accepts the incoming sessions, until the endpoint fails or the server shuts down.
+    async fn accept_sessions(self: &Arc<Self>, config: ServerConfig) -> XrResult<()> {
+        let endpoint = Endpoint::server(config)
+            .map_err(|e| XrError::Transport(format!("Failed to create the WebTransport ingress endpoint: {e}")))?;
+
+        while !is_shutting_down() {
+            let incoming_session = endpoint.accept().await;
+            let self_clone = self.clone();
+            tokio::spawn(async move {
+                if let Err(e) = self_clone.handle_session(incoming_session).await {
+                    warn!("WebTransport producer session failed: {}", e);
+                }
+            });
+        }
+        Ok(())
+    }
+
+    /// Accepts a session on a valid stream id, and receives its frames until the browser closes it.
+    async fn handle_session(self: Arc<Self>, incoming_session: IncomingSession) -> XrResult<()> {
+        let session_request = incoming_session.await
+            .map_err(|e| XrError::Transport(format!("Failed to receive the session request: {e}")))?;
+        // The stream id is the path, without the query string
+        let path = session_request.path().split(['?', '#']).next().unwrap_or_default();
+        let stream_id = path.trim_matches('/').to_string();
+        if !is_valid_stream_id(&stream_id) {
+            session_request.not_found().await;
+            return Err(XrError::Transport(format!("Invalid stream id in the session path {path}")));
+        }
+        // Browsers cannot set headers on a WebTransport session, so the token may be in the query string as well
+        let token = session_request.headers().get("authorization")
+            .and_then(|header| bearer_token(header))
+            .or_else(|| query_token(session_request.path()))
+            .map(str::to_string);
+        if let Err(e) = self.stream_manager.authorize_producer(&stream_id, token.as_deref()) {
+            session_request.forbidden().await;
+            return Err(e);
+        }
+
+        let connection = match session_request.accept().await {
+            Ok(connection) => Arc::new(connection),
+            Err(e) => {
+                self.stream_manager.revoke_producer(&stream_id);
+                return Err(XrError::Transport(format!("Failed to accept the session: {e}")));
+            }
+        };
+        info!("WebTransport producer {} connected from {} on stream {}", connection.stable_id(), connection.remote_address(), stream_id);
+
+        let datagrams = tokio::spawn(self.clone().receive_datagrams(connection.clone(), stream_id.clone()));
+        loop {
+            let stream = tokio::select! {
+                stream = connection.accept_uni() => stream,
+                reason = connection.closed() => {
+                    info!("WebTransport producer {} closed: {}", connection.stable_id(), reason);
+                    break;
+                }
+            };
+            let Ok(stream) = stream else {
+                break;
+            };
+            if is_shutting_down() {
+                break;
+            }
+            // Frames are read concurrently, a lost packet only delays the frame it belongs to
+            let self_clone = self.clone();
+            let stream_id = stream_id.clone();
+            tokio::spawn(async move {
+                let mut data = Vec::new();
+                match stream.take(MAX_FRAME_SIZE + 1).read_to_end(&mut data).await {
+                    Ok(_) if data.len() as u64 > MAX_FRAME_SIZE => debug!("Dropping a frame of more than {} bytes on {}", MAX_FRAME_SIZE, stream_id),
+                    Ok(_) if data.is_empty() => {}
+                    Ok(_) => self_clone.push_frame(data, stream_id, "stream"),
+                    Err(e) => debug!("Failed to read a frame on {}: {}", stream_id, e),
+                }
+            });
+        }
+        datagrams.abort();
+        self.stream_manager.revoke_producer(&stream_id);
+        Ok(())
+    }
+
+    /// Small frames may be sent as a single datagram instead of on a stream.
+    async fn receive_datagrams(self: Arc<Self>, connection: Arc<Connection>, stream_id: String) {
+        while let Ok(datagram) = connection.receive_datagram().await {
+            if is_shutting_down() {
+                break;
+            }
+            self.push_frame(datagram.payload().to_vec(), stream_id.clone(), "datagram");
+        }
+    }
+
+    fn push_frame(&self, data: Vec<u8>, stream_id: String, transport: &str) {
+        self.frames.with_label_values(&[transport]).inc();
+        self.processing_pipeline.push_to_decoder(data, self.stream_manager.clone(), stream_id);
+    }
+}
+
+/// Returns the `token` parameter of the query string of a session path, e.g. `/client_0?token=s3cret`.
+fn query_token(path: &str) -> Option<&str> {
+    let query = path.split_once('?')?.1.split('#').next()?;
+    query.split('&').find_map(|parameter| parameter.strip_prefix("token=")).filter(|token| !token.is_empty())
+}
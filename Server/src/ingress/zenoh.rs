@@ -0,0 +1,165 @@
+// ingress/zenoh.rs
+
+use std::sync::{Arc, OnceLock};
+use std::time::Duration;
+
+use crate::egress::zenoh::open_session;
+use crate::processing::ProcessingPipeline;
+use crate::services::shutdown::is_shutting_down;
+use crate::services::stream_manager::StreamManager;
+use shared_utils::error::{XrError, XrResult};
+
+use super::frame_data;
+
+use tokio::runtime::{self, Runtime};
+use tracing::{debug, error, info, instrument, warn};
+
+static ZENOH_INGRESS_RUNTIME: OnceLock<Arc<Runtime>> = OnceLock::new();
+
+/// How long to wait before opening the Zenoh session again when no router or peer can be reached
+const RETRY_DELAY: Duration = Duration::from_secs(2);
+
+/// A key expression the Zenoh ingress subscribes to.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ZenohSubscription {
+    /// e.g. pointclouds/** for every stream of the Zenoh egress of another server
+    pub key_expr: String,
+    /// Stream id of every publisher on the key expression, each publisher gets its own stream without it
+    pub stream_id: Option<String>,
+}
+
+impl ZenohSubscription {
+    /// Parses `key_expr` or `key_expr=stream_id`.
+    pub fn parse(value: &str) -> XrResult<Self> {
+        let (key_expr, stream_id) = match value.split_once('=') {
+            Some((key_expr, stream_id)) => (key_expr.trim(), Some(stream_id.trim())),
+            None => (value.trim(), None),
+        };
+        if key_expr.is_empty() || stream_id.is_some_and(str::is_empty) {
+            return Err(XrError::Config(format!("Invalid Zenoh subscription {value}, expected key_expr or key_expr=stream_id")));
+        }
+        Ok(Self {
+            key_expr: key_expr.to_string(),
+            stream_id: stream_id.map(str::to_string),
+        })
+    }
+}
+
+/// Settings of the Zenoh ingress.
+#[derive(Clone, Debug)]
+pub struct ZenohIngressConfig {
+    pub subscriptions: Vec<ZenohSubscription>,
+    /// "peer" or "client"
+    pub mode: String,
+    /// Endpoints to connect to, e.g. tcp/192.168.1.10:7447, scouting is used when empty
+    pub connect: Vec<String>,
+}
+
+/// Zenoh Ingress module responsible for receiving frames that are published on Zenoh key expressions,
+/// e.g. by the Zenoh egress of another server, for pub/sub based topologies.
+/// Frames in the wire format of the egresses and plain encoded frames (PLY, Draco) are both accepted.
+#[derive(Debug)]
+pub struct ZenohIngress {
+    processing_pipeline: Arc<ProcessingPipeline>,
+    stream_manager: Arc<StreamManager>,
+    config: ZenohIngressConfig,
+}
+
+impl ZenohIngress {
+    /// Initializes the Zenoh Ingress module, and subscribes to the configured key expressions.
+    #[instrument(skip_all)]
+    pub fn initialize(
+        stream_manager: Arc<StreamManager>,
+        processing_pipeline: Arc<ProcessingPipeline>,
+        config: Option<ZenohIngressConfig>,
+    ) {
+        let Some(config) = config.filter(|config| !config.subscriptions.is_empty()) else {
+            info!("No Zenoh key expressions configured, the Zenoh ingress is disabled");
+            return;
+        };
+
+        let instance = Arc::new(Self {
+            processing_pipeline,
+            stream_manager: stream_manager.clone(),
+            config,
+        });
+
+        let instance_clone = instance.clone();
+        instance.get_runtime().spawn(async move {
+            instance_clone.subscribe().await;
+        });
+
+        info!("Zenoh ingress subscribing to {:?}", instance.config.subscriptions);
+
+        // Store the instance in the StreamManager
+        stream_manager.set_zenoh_ingress(instance.clone());
+    }
+
+    #[instrument(skip_all)]
+    pub fn get_runtime(&self) -> Arc<Runtime> {
+        ZENOH_INGRESS_RUNTIME.get_or_init(|| {
+            let rt = runtime::Builder::new_multi_thread()
+                .worker_threads(2)
+                .thread_name_fn(|| {
+                    static ATOMIC_ZNH_I_ID: std::sync::atomic::AtomicUsize = std::sync::atomic::AtomicUsize::new(0);
+                    let id = ATOMIC_ZNH_I_ID.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+                    format!("ZNH_I w-{}", id)
+                })
+                .enable_all()
+                .build().unwrap();
+            Arc::new(rt)
+        }).clone()
+    }
+
+    /// Opens the Zenoh session, and receives the samples of every subscription on its own task.
+    async fn subscribe(self: Arc<Self>) {
+        let session = loop {
+            if is_shutting_down() {
+                return;
+            }
+            match open_session(&self.config.mode, &self.config.connect, &[]).await {
+                Ok(session) => break session,
+                Err(e) => {
+                    warn!("Failed to open the Zenoh ingress session: {}", e);
+                    tokio::time::sleep(RETRY_DELAY).await;
+                }
+            }
+        };
+
+        for subscription in self.config.subscriptions.clone() {
+            let subscriber = match session.declare_subscriber(subscription.key_expr.clone()).await {
+                Ok(subscriber) => subscriber,
+                Err(e) => {
+                    error!("Failed to subscribe to {}: {}", subscription.key_expr, e);
+                    continue;
+                }
+            };
+            let self_clone = self.clone();
+            // The subscribers only receive while the session is open
+            let session = session.clone();
+            tokio::spawn(async move {
+                let _session = session;
+                while let Ok(sample) = subscriber.recv_async().await {
+                    if is_shutting_down() {
+                        break;
+                    }
+                    let stream_id = subscription.stream_id.clone().unwrap_or_else(|| stream_id(sample.key_expr().as_str()));
+                    self_clone.push_payload(&sample.payload().to_bytes(), stream_id);
+                }
+                debug!("Stopped receiving {}", subscription.key_expr);
+            });
+        }
+    }
+
+    fn push_payload(&self, payload: &[u8], stream_id: String) {
+        match frame_data(payload) {
+            Ok(data) => self.processing_pipeline.push_to_decoder(data, self.stream_manager.clone(), stream_id),
+            Err(e) => debug!("Dropping an invalid frame on {}: {}", stream_id, e),
+        }
+    }
+}
+
+/// Every publisher gets its own stream, pointclouds/client_0/tile_1 becomes zenoh_pointclouds_client_0_tile_1.
+fn stream_id(key_expr: &str) -> String {
+    format!("zenoh_{}", key_expr.trim_matches('/').replace('/', "_"))
+}
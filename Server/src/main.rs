@@ -1,10 +1,41 @@
 // main.rs
 
-use std::{collections::HashMap, sync::Arc, time};
+use std::{collections::HashMap, path::PathBuf, sync::Arc, time};
 use clap::{Parser, ValueEnum};
-use metrics::{get_all_interfaces, Metrics, MetricsBuilder};
-use tokio::{runtime, sync::oneshot, time as tokioTime};
-use tracing::{debug, error, info, instrument, level_filters::LevelFilter};
+use metrics::{get_all_interfaces, MetricsBuilder};
+use egress::EgressConfig;
+use egress::backpressure::BackpressureWatermarks;
+use egress::mqtt::MqttConfig;
+use egress::ndi::NdiConfig;
+#[cfg(unix)]
+use egress::local::{LocalConfig, LocalTransport};
+use egress::preview::PreviewCamera;
+use egress::projection::ProjectionConfig;
+use egress::rtp::RtpMulticastConfig;
+use egress::rtsp::RtspConfig;
+use egress::srt::SrtConfig;
+use egress::tcp::TcpMode;
+use egress::video_encoder::{HardwareVideoEncoder, VideoCodec};
+use egress::zenoh::ZenohConfig;
+use dash_player::mpd::builder::ServiceDescriptionDef;
+use encoders::gpcc::GpccParams;
+use encoders::vpcc::VpccConfig;
+use ingress::capture::{CaptureConfig, CaptureDeviceKind};
+use ingress::dash::DashIngressConfig;
+use ingress::flute::FluteIngressConfig;
+use ingress::mqtt::{MqttIngressConfig, MqttSubscription};
+use ingress::replay::{ReplayConfig, ReplayLoop};
+use ingress::ros2::Ros2IngressConfig;
+use ingress::srt::{SrtIngressConfig, SrtIngressMode};
+use ingress::udp::{UdpFraming, UdpIngressConfig};
+use ingress::zenoh::{ZenohIngressConfig, ZenohSubscription};
+use processing::admission::DropPolicy;
+use services::auth::AccessToken;
+use services::mpd_manager::DvrConfig;
+use shared_utils::delta::DeltaConfig;
+use shared_utils::peer_connection::PeerConnectionConfig;
+use tokio::{runtime, sync::oneshot};
+use tracing::{error, info, instrument, level_filters::LevelFilter};
 use tracing_subscriber::{layer::SubscriberExt, Layer};
 use rayon::ThreadPoolBuilder;
 
@@ -46,6 +77,358 @@ struct Args {
     /// FLUTE port
     #[arg(long, default_value_t = 40085)]
     flute_port: u16,
+    /// UDP port on which the FLUTE receivers report the objects they received, the reports can also be sent over the websocket
+    #[arg(long)]
+    flute_feedback_port: Option<u16>,
+    /// STUN/TURN server URLs used by the WebRTC peer connections
+    #[arg(long, value_delimiter = ',', default_value = "stun:stun.l.google.com:19302")]
+    ice_servers: Vec<String>,
+    /// Username of the TURN servers
+    #[arg(long)]
+    turn_username: Option<String>,
+    /// Credential of the TURN servers
+    #[arg(long)]
+    turn_credential: Option<String>,
+    /// Only connect WebRTC peers through the TURN servers
+    #[arg(long, action = clap::ArgAction::SetTrue)]
+    ice_relay_only: bool,
+    /// Range of local UDP ports used by WebRTC, e.g. 50000-50100
+    #[arg(long, value_parser = parse_port_range)]
+    webrtc_port_range: Option<(u16, u16)>,
+    /// Do not resolve the mDNS candidates of WebRTC peers
+    #[arg(long, action = clap::ArgAction::SetTrue)]
+    disable_mdns: bool,
+    /// UDP port of the WebTransport (HTTP/3) egress
+    #[arg(long, default_value_t = 4433)]
+    webtransport_port: u16,
+    /// UDP port of the raw QUIC egress
+    #[arg(long, default_value_t = 4434)]
+    quic_port: u16,
+    /// TCP port of the gRPC egress, which streams the frames to the subscribers of its FrameService
+    #[arg(long, default_value_t = 4437)]
+    grpc_port: u16,
+    /// Name of the NDI source that shows a 2D preview of the combined point cloud, the NDI egress is disabled without it
+    #[arg(long)]
+    ndi_source_name: Option<String>,
+    /// Camera of the NDI preview: top-down, front or perspective:x,y,z:target_x,target_y,target_z:fov_degrees
+    #[arg(long, default_value = "top-down")]
+    ndi_camera: String,
+    /// Resolution of the NDI preview, e.g. 1280x720
+    #[arg(long, default_value = "1280x720", value_parser = parse_resolution)]
+    ndi_resolution: (u32, u32),
+    /// Size of a point in the NDI preview, in pixels
+    #[arg(long, default_value_t = 2)]
+    ndi_point_size: u32,
+    /// Codec of the projection egress, which streams the combined point cloud as video from the camera pose of every client: h264 or av1.
+    /// The projection egress is disabled without it
+    #[arg(long)]
+    projection_codec: Option<String>,
+    /// Resolution of the projected video, e.g. 1280x720, the width and height have to be even
+    #[arg(long, default_value = "1280x720", value_parser = parse_resolution)]
+    projection_resolution: (u32, u32),
+    /// Target bitrate of the projected video of every client, in bits per second
+    #[arg(long, default_value_t = 2_000_000)]
+    projection_bitrate: u32,
+    /// Size of a point in the projected video, in pixels
+    #[arg(long, default_value_t = 2)]
+    projection_point_size: u32,
+    /// Hardware encoder of the H.264 video of the projection egress and the V-PCC atlases: nvenc, qsv or none.
+    /// OpenH264 encodes the video when the hardware encoder can not be opened, requires the hw-video feature
+    #[arg(long, default_value = "none")]
+    video_hardware_encoder: String,
+    /// Path of the Unix domain socket on which co-located consumers receive the frames, the local egress is disabled without it
+    #[arg(long)]
+    local_socket_path: Option<PathBuf>,
+    /// How the local egress delivers the payloads: memfd (Linux only) or inline
+    #[arg(long, default_value = "memfd")]
+    local_transport: String,
+    /// Deliver the decoded points to the local consumers instead of the encoded frames
+    #[arg(long, action = clap::ArgAction::SetTrue)]
+    local_decoded: bool,
+    /// UDP port of the SRT listener
+    #[arg(long, default_value_t = 4435)]
+    srt_port: u16,
+    /// SRT latency budget in milliseconds, lost packets that cannot be recovered within it are dropped
+    #[arg(long, default_value_t = 120)]
+    srt_latency_ms: u64,
+    /// Passphrase to encrypt the SRT stream with (10 to 79 characters)
+    #[arg(long)]
+    srt_passphrase: Option<String>,
+    /// Number of frames (LL-HLS partial segments) in one full LL-HLS segment
+    #[arg(long, default_value_t = 15)]
+    hls_parts_per_segment: u64,
+    /// Number of frames (CMAF chunks) in one DASH segment, 1 disables chunked transfer
+    #[arg(long, default_value_t = 1)]
+    dash_chunks_per_segment: u64,
+    /// Publish the tiles of a client as tracks of one DASH presentation, with an adaptation set per tile and a shared init segment
+    #[arg(long)]
+    dash_tile_tracks: bool,
+    /// Seconds of past DASH segments that are kept on disk, so receivers can rewind within them (0 disables the DVR window)
+    #[arg(long, default_value_t = 0.0)]
+    dash_dvr_window: f64,
+    /// Directory of the DVR window, with a subdirectory per stream
+    #[arg(long, default_value = "dist/dvr")]
+    dash_dvr_directory: PathBuf,
+    /// Live latency that DASH players should aim for, advertised in a ServiceDescription of the MPDs
+    #[arg(long)]
+    dash_target_latency_ms: Option<u64>,
+    /// Lowest and highest live latency that DASH players should accept, e.g. 50-500
+    #[arg(long, value_parser = parse_latency_range)]
+    dash_latency_range_ms: Option<(u64, u64)>,
+    /// Lowest playback rate DASH players may use to reach the target latency
+    #[arg(long)]
+    dash_min_playback_rate: Option<f64>,
+    /// Highest playback rate DASH players may use to reach the target latency
+    #[arg(long)]
+    dash_max_playback_rate: Option<f64>,
+    /// Multicast groups of the plain RTP egress, e.g. 239.0.3.1:40086, the egress is disabled when empty
+    #[arg(long, value_delimiter = ',')]
+    rtp_multicast_groups: Vec<std::net::SocketAddr>,
+    /// Multicast TTL of the plain RTP egress
+    #[arg(long, default_value_t = 2)]
+    rtp_multicast_ttl: u32,
+    /// Maximum size of the packets of the plain RTP egress, also used by the RTSP egress
+    #[arg(long, default_value_t = 1200)]
+    rtp_mtu: usize,
+    /// TCP port of the RTSP server that serves the stream with the RTP point cloud payload format, the RTSP egress is disabled without it
+    #[arg(long)]
+    rtsp_port: Option<u16>,
+    /// Address the TCP egress accepts consumers on, e.g. 0.0.0.0:4436
+    #[arg(long, conflicts_with = "tcp_connect")]
+    tcp_listen: Option<std::net::SocketAddr>,
+    /// Address of a consumer the TCP egress connects to
+    #[arg(long)]
+    tcp_connect: Option<std::net::SocketAddr>,
+    /// Address of the MQTT broker, e.g. localhost:1883, the MQTT egress is disabled when not set
+    #[arg(long)]
+    mqtt_broker: Option<String>,
+    /// Client identifier of the server at the MQTT broker
+    #[arg(long, default_value = "pc-server")]
+    mqtt_client_id: String,
+    /// Prefix of the MQTT topics the streams are published to
+    #[arg(long, default_value = "pointclouds")]
+    mqtt_topic_prefix: String,
+    /// QoS level of the published frames (0, 1 or 2)
+    #[arg(long, default_value_t = 0)]
+    mqtt_qos: u8,
+    /// Publish the streams over Zenoh
+    #[arg(long, action = clap::ArgAction::SetTrue)]
+    zenoh: bool,
+    /// Prefix of the Zenoh key expressions the streams are published on
+    #[arg(long, default_value = "pointclouds")]
+    zenoh_key_prefix: String,
+    /// Zenoh session mode, peer or client
+    #[arg(long, default_value = "peer")]
+    zenoh_mode: String,
+    /// Zenoh endpoints to connect to, e.g. tcp/192.168.1.10:7447
+    #[arg(long, value_delimiter = ',')]
+    zenoh_connect: Vec<String>,
+    /// Zenoh endpoints to listen on, e.g. tcp/0.0.0.0:7447
+    #[arg(long, value_delimiter = ',')]
+    zenoh_listen: Vec<String>,
+    /// Number of frames every egress buffers before transmission, can be changed per egress at runtime
+    #[arg(long, default_value = "10")]
+    frame_buffer_capacity: usize,
+    /// Fraction of the egress buffer capacity at which the generation of combined frames pauses
+    #[arg(long, default_value_t = 0.8)]
+    backpressure_high_watermark: f64,
+    /// Fraction of the egress buffer capacity at which the generation of combined frames resumes
+    #[arg(long, default_value_t = 0.5)]
+    backpressure_low_watermark: f64,
+    /// How long every egress may take to emit its buffered frames when the server shuts down, in milliseconds
+    #[arg(long, default_value_t = 5000)]
+    shutdown_flush_timeout_ms: u64,
+    /// UDP port on which capture tools can send encoded frames, the UDP ingress is disabled without it
+    #[arg(long)]
+    udp_ingress_port: Option<u16>,
+    /// Multicast group the UDP ingress joins, e.g. 239.0.0.1
+    #[arg(long)]
+    udp_ingress_multicast_group: Option<std::net::Ipv4Addr>,
+    /// How the frames are framed in the UDP datagrams, length (whole frames, each after its 4 byte big endian length),
+    /// chunked (frames split over datagrams, which start with the 4 byte big endian length of their frame and offset of their chunk) or rtp
+    #[arg(long, default_value = "length")]
+    udp_ingress_framing: String,
+    /// Address on which capture rigs connect to publish frames over TCP, e.g. 0.0.0.0:9100, the TCP ingress is disabled without it
+    #[arg(long)]
+    tcp_ingress_listen: Option<std::net::SocketAddr>,
+    /// RTSP source to pull point cloud frames from, e.g. rtsp://192.168.1.20:8554/pointcloud, the RTSP ingress is disabled without it
+    #[arg(long)]
+    rtsp_ingress_url: Option<String>,
+    /// UDP port on which the SRT ingress waits for a sender
+    #[arg(long, conflicts_with = "srt_ingress_call")]
+    srt_ingress_port: Option<u16>,
+    /// Address of an SRT sender the SRT ingress calls, e.g. 192.168.1.20:4435 for the SRT egress of another server
+    #[arg(long)]
+    srt_ingress_call: Option<String>,
+    /// SRT ingress latency budget in milliseconds, has to match the latency of the sender
+    #[arg(long, default_value_t = 120)]
+    srt_ingress_latency_ms: u64,
+    /// Passphrase to decrypt the SRT ingress stream with (10 to 79 characters)
+    #[arg(long)]
+    srt_ingress_passphrase: Option<String>,
+    /// Recording to replay as a live stream, a directory of PLY/Draco frames or fragmented MP4 segments, or a fragmented MP4 file
+    #[arg(long)]
+    replay_path: Option<PathBuf>,
+    /// Frame rate at which the recording is replayed
+    #[arg(long, default_value_t = 30)]
+    replay_fps: u32,
+    /// How often the recording is replayed: once, loop, or a number of passes
+    #[arg(long, default_value = "loop")]
+    replay_loop: String,
+    /// Stream id of the replayed frames
+    #[arg(long, default_value = "replay")]
+    replay_stream_id: String,
+    /// MPD of another server to relay, e.g. http://192.168.1.20:3001/dash/client_0_0.mpd
+    #[arg(long)]
+    dash_ingress_mpd: Option<String>,
+    /// Latency the relay aims for behind the upstream server, in milliseconds, the MPD's service description is used without it
+    #[arg(long)]
+    dash_ingress_target_latency_ms: Option<u64>,
+    /// Prefix of the stream ids of the relayed adaptation sets
+    #[arg(long, default_value = "relay")]
+    dash_ingress_stream_prefix: String,
+    /// Multicast group (or unicast address) on which the FLUTE ingress receives objects, e.g. 239.0.0.1
+    #[arg(long)]
+    flute_ingress_group: Option<std::net::Ipv4Addr>,
+    /// UDP port of the FLUTE ingress
+    #[arg(long, default_value_t = 40086)]
+    flute_ingress_port: u16,
+    /// Interface on which the FLUTE ingress joins the multicast group
+    #[arg(long)]
+    flute_ingress_interface: Option<std::net::Ipv4Addr>,
+    /// Port of the gRPC FrameIngestService on which producers publish frames, the gRPC ingress is disabled without it
+    #[arg(long)]
+    grpc_ingress_port: Option<u16>,
+    /// Depth camera to capture point clouds from: realsense or kinect, requires a server that is built with the capture feature.
+    /// The capture ingress is disabled without it
+    #[arg(long)]
+    capture_device: Option<String>,
+    /// Frame rate of the depth camera, the Azure Kinect supports 5, 15 and 30
+    #[arg(long, default_value_t = 30)]
+    capture_fps: u32,
+    /// Captured points further away from the camera are dropped, in meters
+    #[arg(long, default_value_t = 3.0)]
+    capture_max_depth: f32,
+    /// Only every n-th depth pixel of every n-th row becomes a point
+    #[arg(long, default_value_t = 2)]
+    capture_decimation: u32,
+    /// Stream id of the captured point clouds
+    #[arg(long, default_value = "capture")]
+    capture_stream_id: String,
+    /// ROS 2 topics of sensor_msgs/PointCloud2 messages to ingest through zenoh-bridge-ros2dds, e.g. /camera/depth/color/points.
+    /// The ROS 2 ingress is disabled without them
+    #[arg(long, value_delimiter = ',')]
+    ros2_topics: Vec<String>,
+    /// Namespace of zenoh-bridge-ros2dds, when it was started with one
+    #[arg(long)]
+    ros2_namespace: Option<String>,
+    /// Zenoh endpoints of zenoh-bridge-ros2dds, e.g. tcp/192.168.1.30:7447, scouting is used without them
+    #[arg(long, value_delimiter = ',')]
+    ros2_bridge_connect: Vec<String>,
+    /// Zenoh key expressions the Zenoh ingress subscribes to, as key_expr or key_expr=stream_id, e.g. pointclouds/**.
+    /// Every publisher gets its own stream without a stream id, the session uses --zenoh-mode and --zenoh-connect.
+    /// The Zenoh ingress is disabled without them
+    #[arg(long, value_delimiter = ',')]
+    zenoh_ingress_subscribe: Vec<String>,
+    /// Address of the MQTT broker the MQTT ingress consumes frames from, e.g. localhost:1883, defaults to --mqtt-broker
+    #[arg(long)]
+    mqtt_ingress_broker: Option<String>,
+    /// Client identifier of the MQTT ingress at the broker, has to differ from --mqtt-client-id
+    #[arg(long, default_value = "pc-server-ingress")]
+    mqtt_ingress_client_id: String,
+    /// Topic filters the MQTT ingress subscribes to, as filter or filter=stream_id, e.g. pointclouds/+/frames=relay_{1}.
+    /// {1}, {2}, ... are the levels that match the + wildcards and {#} the levels that match #, every topic gets its own stream without a stream id.
+    /// The MQTT ingress is disabled without them
+    #[arg(long, value_delimiter = ',')]
+    mqtt_ingress_subscribe: Vec<String>,
+    /// QoS level of the MQTT ingress subscriptions (0, 1 or 2)
+    #[arg(long, default_value_t = 0)]
+    mqtt_ingress_qos: u8,
+    /// UDP port on which browsers push frames over WebTransport, the session path is the stream id.
+    /// The WebTransport ingress is disabled without it
+    #[arg(long)]
+    webtransport_ingress_port: Option<u16>,
+    /// Access tokens producers have to present to the WebSocket, WebRTC, UDP, TCP, gRPC and WebTransport ingresses and the frames endpoint,
+    /// as token or token=stream_id, where a trailing * in the stream id matches every stream id with that prefix, e.g. s3cret=client_*.
+    /// Every producer may publish every stream without them
+    #[arg(long, value_delimiter = ',')]
+    ingress_token: Vec<String>,
+    /// Default limit of the frames per second every stream may push to the ingresses, the stream settings can override it
+    #[arg(long)]
+    ingress_max_fps: Option<f64>,
+    /// Default limit of the bytes per second every stream may push to the ingresses, the stream settings can override it
+    #[arg(long)]
+    ingress_max_bytes_per_second: Option<u64>,
+    /// What happens with the frames over the ingress limits: drop-newest, drop-oldest or downsample
+    #[arg(long, default_value = "drop-newest")]
+    ingress_drop_policy: String,
+    /// Path of the tmc3 executable of the MPEG G-PCC reference software, which encodes and decodes the G-PCC frames.
+    /// tmc3 is looked up on the PATH without it
+    #[arg(long)]
+    gpcc_tmc3_path: Option<PathBuf>,
+    /// Default size (m) of the grid G-PCC quantizes the positions to, /streams/gpcc overrides it per stream
+    #[arg(long, default_value_t = 0.001)]
+    gpcc_position_step: f32,
+    /// Default quantization parameter of the G-PCC colors (4 to 51), /streams/gpcc overrides it per stream
+    #[arg(long, default_value_t = 22)]
+    gpcc_attribute_qp: u8,
+    /// Size (m) of the voxels the experimental V-PCC encoder projects to patches
+    #[arg(long, default_value_t = 0.002)]
+    vpcc_voxel_size: f32,
+    /// Target bitrate of the H.264 atlas of the experimental V-PCC encoder, in bits per second at 30 frames per second
+    #[arg(long, default_value_t = 20_000_000)]
+    vpcc_bitrate: u32,
+    /// Depth of the octrees of the octree encoder (1 to 21), the leaves are 2^depth times smaller than the bounding cube of the point cloud
+    #[arg(long, default_value_t = 10)]
+    octree_depth: u8,
+    /// Size (m) of the voxels in which the delta encoder compares the points of a frame with the keyframe
+    #[arg(long, default_value_t = 0.001)]
+    delta_position_step: f32,
+    /// The delta encoder sends a keyframe at least every this many frames, so receivers can join the stream
+    #[arg(long, default_value_t = 30)]
+    delta_keyframe_interval: u32,
+    /// Color changes (per channel) up to which the delta encoder considers a point unchanged
+    #[arg(long, default_value_t = 4)]
+    delta_color_tolerance: u8,
+}
+
+fn parse_resolution(value: &str) -> Result<(u32, u32), String> {
+    let (width, height) = value.split_once('x').ok_or("Expected a resolution like 1280x720")?;
+    let width = width.trim().parse::<u32>().map_err(|e| format!("Invalid width {width}: {e}"))?;
+    let height = height.trim().parse::<u32>().map_err(|e| format!("Invalid height {height}: {e}"))?;
+    Ok((width, height))
+}
+
+fn parse_port_range(value: &str) -> Result<(u16, u16), String> {
+    let (port_min, port_max) = value.split_once('-').ok_or("Expected a port range like 50000-50100")?;
+    let port_min = port_min.trim().parse::<u16>().map_err(|e| format!("Invalid port {port_min}: {e}"))?;
+    let port_max = port_max.trim().parse::<u16>().map_err(|e| format!("Invalid port {port_max}: {e}"))?;
+    Ok((port_min, port_max))
+}
+
+fn parse_latency_range(value: &str) -> Result<(u64, u64), String> {
+    let (min, max) = value.split_once('-').ok_or("Expected a latency range like 50-500")?;
+    let min = min.trim().parse::<u64>().map_err(|e| format!("Invalid latency {min}: {e}"))?;
+    let max = max.trim().parse::<u64>().map_err(|e| format!("Invalid latency {max}: {e}"))?;
+    if min > max {
+        return Err(format!("The minimum latency {min} is higher than the maximum latency {max}"));
+    }
+    Ok((min, max))
+}
+
+/// Completes on Ctrl+C, or on SIGTERM on Unix.
+async fn shutdown_signal() {
+    #[cfg(unix)]
+    {
+        let mut terminate = tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate())
+            .expect("Failed to install the SIGTERM handler");
+        tokio::select! {
+            _ = tokio::signal::ctrl_c() => {},
+            _ = terminate.recv() => {},
+        }
+    }
+    #[cfg(not(unix))]
+    let _ = tokio::signal::ctrl_c().await;
 }
 
 #[instrument(skip_all)]
@@ -127,7 +510,7 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
     info!("Tracking the following interfaces: {:?}", interfaces);
 
     // Build the metrics instance, tracking all interfaces
-    let mut builder = MetricsBuilder::new().add_label("mode", "server");
+    let mut builder = MetricsBuilder::new().add_label("mode", "server").track_process();
 
     for interface in interfaces {
         builder = builder.track_interface(&interface);
@@ -138,11 +521,44 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
     // Start the metrics update loop
     // These are for some default system metrics
     // We are responsible for updating your custom metrics
-    let metrics_clone = Arc::new(metrics);
-    runtime.spawn(update_metrics_loop(metrics_clone));
+    metrics.start_updater(time::Duration::from_secs(1));
 
     // Initialize services
     let stream_manager = Arc::new(services::stream_manager::StreamManager::new());
+    stream_manager.set_default_frame_buffer_capacity(args.frame_buffer_capacity);
+    stream_manager.set_backpressure_watermarks(BackpressureWatermarks::new(args.backpressure_high_watermark, args.backpressure_low_watermark)?);
+    stream_manager.set_access_tokens(args.ingress_token.iter().map(|value| AccessToken::parse(value)).collect::<Result<_, _>>()?);
+    let gpcc_params = GpccParams {
+        position_step: args.gpcc_position_step,
+        attribute_qp: args.gpcc_attribute_qp,
+    };
+    gpcc_params.validate()?;
+    stream_manager.set_default_gpcc_params(gpcc_params);
+    if let Some(tmc3_path) = args.gpcc_tmc3_path.clone() {
+        encoders::gpcc::set_tmc3_path(tmc3_path);
+    }
+    if let Some(hardware_encoder) = HardwareVideoEncoder::parse(&args.video_hardware_encoder)? {
+        egress::video_encoder::set_hardware_encoder(hardware_encoder);
+    }
+    encoders::vpcc::set_vpcc_config(VpccConfig {
+        voxel_size: args.vpcc_voxel_size,
+        bitrate: args.vpcc_bitrate,
+    })?;
+    encoders::octree::set_octree_depth(args.octree_depth)?;
+    encoders::delta::set_delta_config(DeltaConfig {
+        position_step: args.delta_position_step,
+        keyframe_interval: args.delta_keyframe_interval,
+        color_tolerance: args.delta_color_tolerance,
+    })?;
+    let ingress_drop_policy = DropPolicy::parse(&args.ingress_drop_policy)?;
+    if args.ingress_max_fps.is_some() || args.ingress_max_bytes_per_second.is_some() {
+        // New streams copy the settings of the default stream
+        let mut default_settings = stream_manager.get_stream_settings("__default__");
+        default_settings.max_frames_per_second = args.ingress_max_fps.filter(|max| *max > 0.0);
+        default_settings.max_bytes_per_second = args.ingress_max_bytes_per_second.filter(|max| *max > 0);
+        default_settings.drop_policy = ingress_drop_policy;
+        stream_manager.update_stream_settings(default_settings);
+    }
     let mut mpd_manager = services::mpd_manager::MpdManager::new();
     let processing_pipeline = Arc::new(processing::ProcessingPipeline::new(thread_pool.clone()));
 
@@ -166,31 +582,227 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
     };
 
     mpd_manager.set_notify_callback(callback);
+    if let Some(latency_target_ms) = args.dash_target_latency_ms {
+        mpd_manager.set_service_description(ServiceDescriptionDef {
+            latency_target_ms,
+            latency_min_ms: args.dash_latency_range_ms.map(|(min, _)| min),
+            latency_max_ms: args.dash_latency_range_ms.map(|(_, max)| max),
+            playback_rate_min: args.dash_min_playback_rate,
+            playback_rate_max: args.dash_max_playback_rate,
+        });
+    }
+    if args.dash_dvr_window > 0.0 {
+        mpd_manager.set_dvr(DvrConfig {
+            depth: args.dash_dvr_window,
+            directory: args.dash_dvr_directory.clone(),
+        });
+    }
     // Wrap the MPD manager in an Arc
     let mpd_manager = Arc::new(mpd_manager);
 
 
 
+    let mut peer_connection_config = PeerConnectionConfig::default()
+        .with_ice_servers(&args.ice_servers, args.turn_username.as_deref(), args.turn_credential.as_deref())
+        .with_relay_only(args.ice_relay_only)
+        .with_mdns(!args.disable_mdns);
+    if let Some((port_min, port_max)) = args.webrtc_port_range {
+        peer_connection_config = peer_connection_config.with_port_range(port_min, port_max);
+    }
+
+    let ndi_config = match args.ndi_source_name.clone() {
+        Some(source_name) => Some(NdiConfig {
+            source_name,
+            camera: PreviewCamera::parse(&args.ndi_camera)?,
+            width: args.ndi_resolution.0,
+            height: args.ndi_resolution.1,
+            point_size: args.ndi_point_size,
+        }),
+        None => None,
+    };
+
+    let projection_config = match args.projection_codec.as_deref() {
+        Some(codec) => Some(ProjectionConfig {
+            codec: VideoCodec::parse(codec)?,
+            width: args.projection_resolution.0,
+            height: args.projection_resolution.1,
+            bitrate: args.projection_bitrate,
+            point_size: args.projection_point_size,
+        }),
+        None => None,
+    };
+
+    let udp_ingress_config = match args.udp_ingress_port {
+        Some(port) => Some(UdpIngressConfig {
+            port,
+            multicast_group: args.udp_ingress_multicast_group,
+            framing: UdpFraming::parse(&args.udp_ingress_framing)?,
+        }),
+        None => None,
+    };
+
+    let srt_ingress_mode = args.srt_ingress_port.map(SrtIngressMode::Listen).or(args.srt_ingress_call.clone().map(SrtIngressMode::Call));
+    let srt_ingress_config = srt_ingress_mode.map(|mode| SrtIngressConfig {
+        mode,
+        latency: time::Duration::from_millis(args.srt_ingress_latency_ms),
+        passphrase: args.srt_ingress_passphrase.clone(),
+    });
+
+    let replay_config = match args.replay_path.clone() {
+        Some(path) => Some(ReplayConfig {
+            path,
+            fps: args.replay_fps,
+            loop_mode: ReplayLoop::parse(&args.replay_loop)?,
+            stream_id: args.replay_stream_id.clone(),
+        }),
+        None => None,
+    };
+
+    let dash_ingress_config = args.dash_ingress_mpd.clone().map(|mpd_url| DashIngressConfig {
+        mpd_url,
+        target_latency: args.dash_ingress_target_latency_ms.map(time::Duration::from_millis),
+        stream_id_prefix: args.dash_ingress_stream_prefix.clone(),
+    });
+
+    let flute_ingress_config = args.flute_ingress_group.map(|address| FluteIngressConfig {
+        address,
+        port: args.flute_ingress_port,
+        interface: args.flute_ingress_interface,
+    });
+
+    let capture_config = match args.capture_device.as_deref() {
+        Some(device) => Some(CaptureConfig {
+            device: CaptureDeviceKind::parse(device)?,
+            fps: args.capture_fps,
+            max_depth: args.capture_max_depth,
+            decimation: args.capture_decimation,
+            stream_id: args.capture_stream_id.clone(),
+        }),
+        None => None,
+    };
+
+    let ros2_config = (!args.ros2_topics.is_empty()).then(|| Ros2IngressConfig {
+        topics: args.ros2_topics.clone(),
+        namespace: args.ros2_namespace.clone(),
+        connect: args.ros2_bridge_connect.clone(),
+    });
+
+    let zenoh_ingress_config = if args.zenoh_ingress_subscribe.is_empty() {
+        None
+    } else {
+        Some(ZenohIngressConfig {
+            subscriptions: args.zenoh_ingress_subscribe.iter().map(|value| ZenohSubscription::parse(value)).collect::<Result<_, _>>()?,
+            mode: args.zenoh_mode.clone(),
+            connect: args.zenoh_connect.clone(),
+        })
+    };
+
+    let mqtt_ingress_config = match args.mqtt_ingress_broker.clone().or(args.mqtt_broker.clone()) {
+        Some(broker) if !args.mqtt_ingress_subscribe.is_empty() => Some(MqttIngressConfig {
+            broker,
+            client_id: args.mqtt_ingress_client_id.clone(),
+            subscriptions: args.mqtt_ingress_subscribe.iter().map(|value| MqttSubscription::parse(value)).collect::<Result<_, _>>()?,
+            qos: args.mqtt_ingress_qos,
+        }),
+        Some(_) => None,
+        None if !args.mqtt_ingress_subscribe.is_empty() => return Err("The MQTT ingress requires --mqtt-ingress-broker or --mqtt-broker".into()),
+        None => None,
+    };
+
+    #[cfg(unix)]
+    let local_config = match args.local_socket_path.clone() {
+        Some(socket_path) => Some(LocalConfig {
+            socket_path,
+            transport: LocalTransport::parse(&args.local_transport)?,
+            decoded: args.local_decoded,
+        }),
+        None => None,
+    };
+    #[cfg(not(unix))]
+    if args.local_socket_path.is_some() {
+        tracing::warn!("The local egress is only available on Unix");
+    }
+
     // Initialize singleton egress protocols
+    // Additional egress protocols are registered with `egress::registry::get_egress_registry().register` before this call
     egress::initialize_egress_protocols(
         stream_manager.clone(),
         mpd_manager.clone(),
         processing_pipeline.clone(),
-        args.flute_endpoint_url.clone(),
-        args.flute_port,
+        EgressConfig {
+            flute_endpoint_url: args.flute_endpoint_url.clone(),
+            flute_port: args.flute_port,
+            flute_feedback_port: args.flute_feedback_port,
+            peer_connection_config,
+            webtransport_port: args.webtransport_port,
+            quic_port: args.quic_port,
+            srt_config: SrtConfig {
+                port: args.srt_port,
+                latency: time::Duration::from_millis(args.srt_latency_ms),
+                passphrase: args.srt_passphrase.clone(),
+            },
+            hls_parts_per_segment: args.hls_parts_per_segment,
+            dash_chunks_per_segment: args.dash_chunks_per_segment,
+            dash_tile_tracks: args.dash_tile_tracks,
+            rtp_config: RtpMulticastConfig {
+                groups: args.rtp_multicast_groups.clone(),
+                ttl: args.rtp_multicast_ttl,
+                mtu: args.rtp_mtu,
+            },
+            rtsp_config: args.rtsp_port.map(|port| RtspConfig { port, mtu: args.rtp_mtu }),
+            tcp_mode: args.tcp_listen.map(TcpMode::Listen).or(args.tcp_connect.map(TcpMode::Connect)),
+            mqtt_config: MqttConfig {
+                broker: args.mqtt_broker.clone(),
+                client_id: args.mqtt_client_id.clone(),
+                topic_prefix: args.mqtt_topic_prefix.clone(),
+                qos: args.mqtt_qos,
+            },
+            zenoh_config: ZenohConfig {
+                enabled: args.zenoh,
+                key_prefix: args.zenoh_key_prefix.clone(),
+                mode: args.zenoh_mode.clone(),
+                connect: args.zenoh_connect.clone(),
+                listen: args.zenoh_listen.clone(),
+            },
+            grpc_port: args.grpc_port,
+            ndi_config,
+            projection_config,
+            #[cfg(unix)]
+            local_config,
+        },
     );
 
     // Initialize singleton ingress protocols
     ingress::initialize_ingress_protocols(
         stream_manager.clone(),
         processing_pipeline.clone(),
+        udp_ingress_config,
+        args.tcp_ingress_listen,
+        args.rtsp_ingress_url.clone(),
+        srt_ingress_config,
+        replay_config,
+        dash_ingress_config,
+        flute_ingress_config,
+        args.grpc_ingress_port,
+        capture_config,
+        ros2_config,
+        zenoh_ingress_config,
+        mqtt_ingress_config,
+        args.webtransport_ingress_port,
     );
 
+    let shutdown_coordinator = Arc::new(services::shutdown::ShutdownCoordinator::new(
+        stream_manager.clone(),
+        active_jobs.clone(),
+        time::Duration::from_millis(args.shutdown_flush_timeout_ms),
+    ));
+
     // Create router
     let app = router::create_router(
         stream_manager.clone(),
         processing_pipeline.clone(),
         active_jobs.clone().into(),
+        shutdown_coordinator.clone(),
     );
 
     runtime.block_on(async move {
@@ -213,27 +825,23 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
 
         let listener = tokio::net::TcpListener::from_std(sock.into()).unwrap();
 
-        // let listener = tokio::net::TcpListener::bind(format!("0.0.0.0:{}", args.port)).await.unwrap();
-        axum::serve(listener, app).await.unwrap();
-    });
+        let signal_coordinator = shutdown_coordinator.clone();
+        tokio::spawn(async move {
+            shutdown_signal().await;
+            signal_coordinator.trigger("signal");
+        });
 
-    info!("Server started");
+        info!("Server started");
 
+        // let listener = tokio::net::TcpListener::bind(format!("0.0.0.0:{}", args.port)).await.unwrap();
+        // The HTTP server stops once the egresses are flushed, after either a signal or the shutdown endpoint
+        axum::serve(listener, app)
+            .with_graceful_shutdown(async move { shutdown_coordinator.wait().await })
+            .await
+            .unwrap();
+    });
 
-    loop {
-        std::thread::sleep(time::Duration::from_secs(1));
-    }
+    info!("Server stopped");
 
-    #[allow(unreachable_code)]
     Ok(())
 }
-
-#[instrument(skip_all)]
-async fn update_metrics_loop(metrics: Arc<Metrics>) {
-    let mut interval = tokioTime::interval(tokioTime::Duration::from_secs(1));
-    loop {
-        metrics.update();
-        debug!("Metrics updated");
-        interval.tick().await;
-    }
-}
\ No newline at end of file
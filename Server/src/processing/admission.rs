@@ -0,0 +1,250 @@
+use std::collections::{HashMap, VecDeque};
+use std::fmt;
+use std::sync::atomic::{AtomicU8, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use metrics::get_metrics;
+use prometheus::IntCounterVec;
+use serde::{Deserialize, Serialize};
+use shared_utils::error::{XrError, XrResult};
+use tracing::{debug, instrument};
+
+use crate::types::StreamSettings;
+
+/// Window over which the incoming rate of a stream is measured for the downsample policy
+const RATE_WINDOW: Duration = Duration::from_secs(1);
+
+const TICKET_QUEUED: u8 = 0;
+const TICKET_STARTED: u8 = 1;
+const TICKET_CANCELLED: u8 = 2;
+
+/// What happens with the frames of a stream that exceed its rate limits.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum DropPolicy {
+    /// The frames that arrive while the budget is exhausted are dropped, the producer gets bursts of frames through
+    #[default]
+    DropNewest,
+    /// The oldest frame of the stream that is still waiting for the thread pool is dropped in favor of the new one, so the freshest frames are decoded
+    DropOldest,
+    /// An evenly spaced subset of the frames is kept, e.g. every second frame when the stream arrives at twice the allowed rate
+    Downsample,
+}
+
+impl DropPolicy {
+    pub fn parse(value: &str) -> XrResult<Self> {
+        match value.trim().to_lowercase().as_str() {
+            "drop-newest" | "newest" => Ok(DropPolicy::DropNewest),
+            "drop-oldest" | "oldest" => Ok(DropPolicy::DropOldest),
+            "downsample" => Ok(DropPolicy::Downsample),
+            other => Err(XrError::Config(format!("Unsupported drop policy {other}, expected drop-newest, drop-oldest or downsample"))),
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            DropPolicy::DropNewest => "drop-newest",
+            DropPolicy::DropOldest => "drop-oldest",
+            DropPolicy::Downsample => "downsample",
+        }
+    }
+}
+
+/// Handed out for every admitted frame, the frame is only decoded if the ticket was not cancelled in the meantime.
+#[derive(Debug)]
+pub struct AdmissionTicket {
+    state: Option<Arc<AtomicU8>>,
+}
+
+impl AdmissionTicket {
+    /// Marks the frame as being decoded, returns false when a newer frame of the stream took its place.
+    pub fn start(&self) -> bool {
+        match &self.state {
+            Some(state) => state.compare_exchange(TICKET_QUEUED, TICKET_STARTED, Ordering::AcqRel, Ordering::Acquire).is_ok(),
+            None => true,
+        }
+    }
+}
+
+/// Token buckets and the frames waiting for the thread pool of one stream.
+#[derive(Debug)]
+struct StreamAdmission {
+    frame_tokens: f64,
+    byte_tokens: f64,
+    last_refill: Instant,
+    /// The frames that arrived in the current measurement window of the downsample policy
+    window_frames: u64,
+    window_bytes: u64,
+    window_start: Instant,
+    /// Share of the frames the downsample policy keeps, measured over the previous window
+    keep_ratio: f64,
+    keep_credit: f64,
+    /// Tickets of the admitted frames, in the order they arrived
+    queued: VecDeque<Arc<AtomicU8>>,
+}
+
+impl StreamAdmission {
+    /// A new stream starts with full buckets.
+    fn new(now: Instant, max_frames_per_second: Option<f64>, max_bytes_per_second: Option<u64>) -> Self {
+        Self {
+            frame_tokens: max_frames_per_second.map_or(0.0, |max| max.max(1.0)),
+            byte_tokens: max_bytes_per_second.map_or(0.0, |max| max as f64),
+            last_refill: now,
+            window_frames: 0,
+            window_bytes: 0,
+            window_start: now,
+            keep_ratio: 1.0,
+            keep_credit: 0.0,
+            queued: VecDeque::new(),
+        }
+    }
+
+    /// Refills the buckets, each holds at most one second of budget.
+    /// A single frame may take more bytes than are left, the debt is paid back before the next frame is admitted.
+    fn refill(&mut self, now: Instant, max_frames_per_second: Option<f64>, max_bytes_per_second: Option<u64>) {
+        let elapsed = now.duration_since(self.last_refill).as_secs_f64();
+        self.last_refill = now;
+        if let Some(max_frames_per_second) = max_frames_per_second {
+            self.frame_tokens = (self.frame_tokens + elapsed * max_frames_per_second).min(max_frames_per_second.max(1.0));
+        }
+        if let Some(max_bytes_per_second) = max_bytes_per_second {
+            self.byte_tokens = (self.byte_tokens + elapsed * max_bytes_per_second as f64).min(max_bytes_per_second as f64);
+        }
+    }
+
+    fn has_budget(&self, max_frames_per_second: Option<f64>, max_bytes_per_second: Option<u64>) -> bool {
+        (max_frames_per_second.is_none() || self.frame_tokens >= 1.0) && (max_bytes_per_second.is_none() || self.byte_tokens > 0.0)
+    }
+
+    fn spend(&mut self, size: usize) {
+        self.frame_tokens -= 1.0;
+        self.byte_tokens -= size as f64;
+    }
+
+    /// Measures the incoming rates, and decides whether the frame is part of the evenly spaced subset that is kept.
+    fn downsample(&mut self, now: Instant, size: usize, max_frames_per_second: Option<f64>, max_bytes_per_second: Option<u64>) -> bool {
+        self.window_frames += 1;
+        self.window_bytes += size as u64;
+        let elapsed = now.duration_since(self.window_start);
+        if elapsed >= RATE_WINDOW {
+            let elapsed = elapsed.as_secs_f64();
+            let frame_ratio = max_frames_per_second.map_or(1.0, |max| max / (self.window_frames as f64 / elapsed));
+            let byte_ratio = max_bytes_per_second.map_or(1.0, |max| max as f64 / (self.window_bytes as f64 / elapsed));
+            self.keep_ratio = frame_ratio.min(byte_ratio).clamp(0.0, 1.0);
+            self.window_frames = 0;
+            self.window_bytes = 0;
+            self.window_start = now;
+        }
+        self.keep_credit += self.keep_ratio;
+        if self.keep_credit >= 1.0 {
+            self.keep_credit -= 1.0;
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Cancels the oldest frame that is still waiting for the thread pool, returns false when every frame already started.
+    fn cancel_oldest(&mut self) -> bool {
+        while let Some(state) = self.queued.pop_front() {
+            if state.compare_exchange(TICKET_QUEUED, TICKET_CANCELLED, Ordering::AcqRel, Ordering::Acquire).is_ok() {
+                return true;
+            }
+        }
+        false
+    }
+
+    fn enqueue(&mut self) -> Arc<AtomicU8> {
+        // The frames that started no longer need to be tracked
+        while self.queued.front().is_some_and(|state| state.load(Ordering::Acquire) != TICKET_QUEUED) {
+            self.queued.pop_front();
+        }
+        let state = Arc::new(AtomicU8::new(TICKET_QUEUED));
+        self.queued.push_back(state.clone());
+        state
+    }
+}
+
+/// Enforces the frame and byte rate limits of the streams before their frames are handed to the thread pool,
+/// so a single misbehaving producer cannot starve the decoding of the other streams.
+pub struct AdmissionController {
+    streams: Mutex<HashMap<String, StreamAdmission>>,
+    frames: IntCounterVec,
+}
+
+impl fmt::Debug for AdmissionController {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("AdmissionController").finish_non_exhaustive()
+    }
+}
+
+impl Default for AdmissionController {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl AdmissionController {
+    pub fn new() -> Self {
+        Self {
+            streams: Mutex::new(HashMap::new()),
+            frames: get_metrics()
+                .get_or_create_counter_vec("ingress_admission_frames", "Number of incoming frames by stream and admission outcome (admitted, dropped or replaced)", &["stream", "outcome"])
+                .unwrap(),
+        }
+    }
+
+    /// Decides whether an incoming frame of `size` bytes is handed to the thread pool, `None` when it is dropped.
+    #[instrument(skip_all)]
+    pub fn admit(&self, stream_id: &str, size: usize, settings: &StreamSettings) -> Option<AdmissionTicket> {
+        let max_frames_per_second = settings.max_frames_per_second.filter(|max| *max > 0.0);
+        let max_bytes_per_second = settings.max_bytes_per_second.filter(|max| *max > 0);
+        if max_frames_per_second.is_none() && max_bytes_per_second.is_none() {
+            return Some(AdmissionTicket { state: None });
+        }
+
+        let now = Instant::now();
+        let mut streams = self.streams.lock().unwrap();
+        let stream = streams.entry(stream_id.to_string()).or_insert_with(|| StreamAdmission::new(now, max_frames_per_second, max_bytes_per_second));
+        stream.refill(now, max_frames_per_second, max_bytes_per_second);
+
+        let admitted = match settings.drop_policy {
+            DropPolicy::DropNewest => stream.has_budget(max_frames_per_second, max_bytes_per_second),
+            DropPolicy::DropOldest if stream.has_budget(max_frames_per_second, max_bytes_per_second) => true,
+            DropPolicy::DropOldest => {
+                // The new frame takes the place, and the budget, of the frame it replaces
+                if stream.cancel_oldest() {
+                    self.frames.with_label_values(&[stream_id, "replaced"]).inc();
+                    return Some(AdmissionTicket { state: Some(stream.enqueue()) });
+                }
+                false
+            }
+            DropPolicy::Downsample => stream.downsample(now, size, max_frames_per_second, max_bytes_per_second),
+        };
+        if !admitted {
+            debug!("Dropping a frame of stream {} that exceeds its rate limits ({})", stream_id, settings.drop_policy.name());
+            self.frames.with_label_values(&[stream_id, "dropped"]).inc();
+            return None;
+        }
+
+        self.frames.with_label_values(&[stream_id, "admitted"]).inc();
+        match settings.drop_policy {
+            DropPolicy::Downsample => Some(AdmissionTicket { state: None }),
+            DropPolicy::DropNewest => {
+                stream.spend(size);
+                Some(AdmissionTicket { state: None })
+            }
+            DropPolicy::DropOldest => {
+                stream.spend(size);
+                Some(AdmissionTicket { state: Some(stream.enqueue()) })
+            }
+        }
+    }
+
+    /// Forgets the budget of a stream, e.g. when its limits change.
+    #[instrument(skip_all)]
+    pub fn reset(&self, stream_id: &str) {
+        self.streams.lock().unwrap().remove(stream_id);
+    }
+}
@@ -1,12 +1,13 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, VecDeque};
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::{Arc, Mutex};
 use std::time::{SystemTime, UNIX_EPOCH};
-use shared_utils::types::{Point3D, PointCloudData};
+use shared_utils::types::{Point3D, PointAttributes, PointCloudData};
 use crate::services::stream_manager::StreamManager;
 use super::sampling::exact_random_sampling;
 use metrics::get_metrics;
 use nalgebra::{Vector3, Rotation3};
-use prometheus::IntGauge;
+use prometheus::IntCounter;
 use tracing::{instrument, debug};
 use circular_buffer::CircularBuffer;
 
@@ -16,11 +17,17 @@ pub struct PointCloudAggregator {
     stream_manager: Arc<StreamManager>,
     has_update: Mutex<bool>,
     max_age: Mutex<u64>,
-    dropped_after_insertion: IntGauge,
-    dropped_because_late_insertion: IntGauge,
-    dropped_old_age: IntGauge,
+    dropped_after_insertion: IntCounter,
+    dropped_because_late_insertion: IntCounter,
+    dropped_old_age: IntCounter,
+    /// Keep the latest combined point clouds, so they can be encoded again at other point budgets
+    keep_combined: AtomicBool,
+    recent_combined: Mutex<VecDeque<PointCloudData>>,
 }
 
+/// Number of combined point clouds that are kept, enough to cover the frames waiting in the frame buffer
+const RECENT_COMBINED_CAPACITY: usize = 8;
+
 impl PointCloudAggregator {
     
     #[instrument(skip_all)]
@@ -33,12 +40,40 @@ impl PointCloudAggregator {
             has_update: Mutex::new(false),
             // The maximum age of a point cloud in microseconds
             max_age: Mutex::new(5_000_000), // Currently 5 seconds
-            dropped_after_insertion: metrics.get_or_create_gauge("dropped_after_insertion", "The number of point clouds that were dropped before a newer point cloud was inserted").unwrap(),
-            dropped_because_late_insertion: metrics.get_or_create_gauge("dropped_because_late_insertion", "The number of point clouds that were dropped because they were older than the latest transmitted point cloud").unwrap(),
-            dropped_old_age: metrics.get_or_create_gauge("dropped_old_age", "The number of point clouds that were dropped because they were too old").unwrap(),
+            dropped_after_insertion: metrics.get_or_create_counter("dropped_after_insertion", "The number of point clouds that were dropped before a newer point cloud was inserted").unwrap(),
+            dropped_because_late_insertion: metrics.get_or_create_counter("dropped_because_late_insertion", "The number of point clouds that were dropped because they were older than the latest transmitted point cloud").unwrap(),
+            dropped_old_age: metrics.get_or_create_counter("dropped_old_age", "The number of point clouds that were dropped because they were too old").unwrap(),
+            keep_combined: AtomicBool::new(false),
+            recent_combined: Mutex::new(VecDeque::new()),
+        }
+    }
+
+    #[instrument(skip_all)]
+    pub fn stream_manager(&self) -> &Arc<StreamManager> {
+        &self.stream_manager
+    }
+
+    /// Enables keeping the latest combined point clouds, see `combined_point_cloud`.
+    #[instrument(skip_all)]
+    pub fn set_keep_combined(&self, keep_combined: bool) {
+        self.keep_combined.store(keep_combined, Ordering::Relaxed);
+        if !keep_combined {
+            self.recent_combined.lock().unwrap().clear();
         }
     }
 
+    /// Returns the combined point cloud with the given presentation time, if it is still kept.
+    #[instrument(skip_all)]
+    pub fn combined_point_cloud(&self, presentation_time: u64) -> Option<PointCloudData> {
+        self.recent_combined
+            .lock()
+            .unwrap()
+            .iter()
+            .rev()
+            .find(|point_cloud| point_cloud.presentation_time == presentation_time)
+            .cloned()
+    }
+
     #[instrument(skip_all, fields(stream_id = %stream_id))]
     pub fn update_point_cloud(&self, stream_id: String, point_cloud: PointCloudData) {
         let mut guard = self.latest_point_clouds.lock().unwrap();
@@ -151,6 +186,7 @@ impl PointCloudAggregator {
             debug!("No point clouds to aggregate");
             return PointCloudData {
                 points: Vec::new(),
+                attributes: PointAttributes::default(),
                 creation_time: current_time,
                 presentation_time: current_time,
                 error_count: 1,
@@ -164,6 +200,7 @@ impl PointCloudAggregator {
                 // debug!("No point cloud updates to aggregate");
                 return PointCloudData {
                     points: Vec::new(),
+                    attributes: PointAttributes::default(),
                     creation_time: current_time,
                     presentation_time: current_time,
                     error_count: 1,
@@ -305,11 +342,23 @@ impl PointCloudAggregator {
             combined_points = exact_random_sampling(&combined_points, max_number_of_points as usize);
         }
 
-        PointCloudData {
+        // The attributes of the aggregated point clouds are not combined, only xyz + rgb
+        let combined_point_cloud = PointCloudData {
             points: combined_points,
+            attributes: PointAttributes::default(),
             creation_time: if latest_creation_time > 0 { latest_creation_time } else { current_time },
             presentation_time: max_presentation_time,
             error_count,
+        };
+
+        if self.keep_combined.load(Ordering::Relaxed) {
+            let mut recent_combined = self.recent_combined.lock().unwrap();
+            if recent_combined.len() >= RECENT_COMBINED_CAPACITY {
+                recent_combined.pop_front();
+            }
+            recent_combined.push_back(combined_point_cloud.clone());
         }
+
+        combined_point_cloud
     }
 }
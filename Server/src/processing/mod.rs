@@ -1,16 +1,24 @@
 use std::sync::Arc;
 use std::time::{Instant, SystemTime, UNIX_EPOCH};
-use metrics::get_metrics;
+use metrics::{duration_buckets_us, get_metrics};
+use admission::{AdmissionController, AdmissionTicket};
 use pre_encode::prep_for_encoding;
-use prometheus::IntGauge;
+use prometheus::{Histogram, IntCounter};
 use rayon::ThreadPool;
-use sampling::partition_by_percentages;
+use sampling::partition_indices_by_percentages;
 use crate::decoders;
+use crate::decoders::vpcc::VpccDecoders;
 use crate::encoders::{self, EncodingFormat};
+use crate::encoders::delta::DeltaEncoders;
+use crate::encoders::gpcc::GpccParams;
+use crate::encoders::vpcc::{VpccEncoders, VPCC_MAGIC};
+use crate::services::shutdown::is_shutting_down;
 use crate::services::stream_manager::StreamManager;
 use tracing::{error, instrument};
+use shared_utils::error::XrResult;
 use shared_utils::types::{FrameTaskData, PointCloudData};
 
+pub mod admission;
 pub mod aggregator;
 pub mod filtering;
 pub mod pre_encode;
@@ -19,10 +27,13 @@ pub mod sampling;
 #[derive(Clone, Debug)]
 pub struct ProcessingPipeline {
     pub thread_pool: Arc<ThreadPool>,
-    pub decoding_time: IntGauge,
-    pub process_to_buffer_time: IntGauge,
-    pub frames_to_decode: IntGauge,
-    
+    pub decoding_time: Histogram,
+    pub process_to_buffer_time: Histogram,
+    pub frames_to_decode: IntCounter,
+    pub admission: Arc<AdmissionController>,
+    pub delta_encoders: Arc<DeltaEncoders>,
+    pub vpcc_encoders: Arc<VpccEncoders>,
+    pub vpcc_decoders: Arc<VpccDecoders>,
 }
 
 impl ProcessingPipeline {
@@ -31,20 +42,30 @@ impl ProcessingPipeline {
         let metrics = get_metrics();
         Self { 
             thread_pool,
-            decoding_time: metrics.get_or_create_gauge(
+            decoding_time: metrics.get_or_create_histogram(
                 "decoding_time", 
-                "Time taken to decode a frame").unwrap(),
-            process_to_buffer_time: metrics.get_or_create_gauge(
+                "Time (us) taken to decode a frame",
+                duration_buckets_us()).unwrap(),
+            process_to_buffer_time: metrics.get_or_create_histogram(
                 "process_to_buffer_time", 
-                "Time taken to process a frame and push it to the egress buffer where it will be combined with the other streams.").unwrap(),
-            frames_to_decode: metrics.get_or_create_gauge(
+                "Time (us) taken to process a frame and push it to the egress buffer where it will be combined with the other streams.",
+                duration_buckets_us()).unwrap(),
+            frames_to_decode: metrics.get_or_create_counter(
                 "frames_to_decode", 
                 "Number of frames to be decoded").unwrap(),
+            admission: Arc::new(AdmissionController::new()),
+            delta_encoders: Arc::new(DeltaEncoders::default()),
+            vpcc_encoders: Arc::new(VpccEncoders::default()),
+            vpcc_decoders: Arc::new(VpccDecoders::default()),
          }
     }
 
+    /// Decodes a frame of the stream, the V-PCC frames with the decoders of the stream.
     #[instrument(skip_all)]
-    pub fn decode(&self, raw_data: Vec<u8>) -> Result<PointCloudData, Box<dyn std::error::Error>> {
+    pub fn decode(&self, raw_data: Vec<u8>, stream_id: &str) -> XrResult<PointCloudData> {
+        if raw_data.starts_with(VPCC_MAGIC) {
+            return self.vpcc_decoders.decode(stream_id, raw_data);
+        }
         decoders::decode_data(raw_data)
     }
 
@@ -53,10 +74,39 @@ impl ProcessingPipeline {
         &self,
         point_cloud: PointCloudData,
         encoding: EncodingFormat,
-    ) -> Result<FrameTaskData, Box<dyn std::error::Error>> {
+    ) -> XrResult<FrameTaskData> {
+        self.encode_with_params(point_cloud, encoding, &GpccParams::default(), None)
+    }
+
+    /// Encodes a frame of the stream with the codec settings of that stream, see COMBINED_STREAM_ID for the aggregated frames.
+    /// Delta coded frames refer to the last keyframe that was encoded with the same key, and V-PCC frames reuse the encoders of the key, see `encoder_key`.
+    #[instrument(skip_all)]
+    pub fn encode_for_stream(
+        &self,
+        point_cloud: PointCloudData,
+        encoding: EncodingFormat,
+        stream_manager: &StreamManager,
+        stream_id: &str,
+        encoder_key: &str,
+    ) -> XrResult<FrameTaskData> {
+        self.encode_with_params(point_cloud, encoding, &stream_manager.gpcc_params(stream_id), Some(encoder_key))
+    }
+
+    fn encode_with_params(
+        &self,
+        point_cloud: PointCloudData,
+        encoding: EncodingFormat,
+        gpcc_params: &GpccParams,
+        encoder_key: Option<&str>,
+    ) -> XrResult<FrameTaskData> {
         let creation_time = point_cloud.creation_time;
         let presentation_time = point_cloud.presentation_time;
-        let data = encoders::encode_data(point_cloud, encoding);
+        let data = match (encoding, encoder_key) {
+            (EncodingFormat::Delta, Some(encoder_key)) => self.delta_encoders.encode(encoder_key, &point_cloud),
+            // Frames without a stream, e.g. the quality layers, share the encoders of the empty key
+            (EncodingFormat::Vpcc, encoder_key) => self.vpcc_encoders.encode(encoder_key.unwrap_or_default(), &point_cloud),
+            _ => encoders::encode_data_with_params(point_cloud, encoding, gpcc_params),
+        };
 
         match data {
             Ok(data) => Ok(FrameTaskData {
@@ -87,19 +137,25 @@ impl ProcessingPipeline {
         let stream_id_clone = stream_id.clone();
 
         let settings = stream_manager_clone.get_stream_settings(&stream_id);
-        // Check if we should process this frame
-        if !settings.process_incoming_frames {
+        // Check if we should process this frame, no new frames are accepted during the shutdown
+        if !settings.process_incoming_frames || is_shutting_down() {
             // Drop the frame
             return;
         }
 
-
+        // Frames over the rate limits of the stream are dropped before they reach the thread pool
+        let Some(ticket) = self.admission.admit(&stream_id, raw_data.len(), &settings) else {
+            return;
+        };
 
         let thread_pool = Arc::clone(&self.thread_pool);
         let presentation_time_offset = settings.presentation_time_offset;
 
         if settings.decode_bypass {
             thread_pool.spawn(move || {
+                if !ticket.start() {
+                    return;
+                }
                 // Instead of decoding, treat `raw_data` as “already decoded” or “raw frame”.
                 // We can call a new function that directly handles raw frames:
                 processing_pipeline.process_frame_raw(
@@ -122,6 +178,7 @@ impl ProcessingPipeline {
                     decoding_time,
                     process_to_buffer_time,
                     frames_to_decode,
+                    ticket,
                 );
             });
         }
@@ -136,14 +193,19 @@ impl ProcessingPipeline {
         stream_manager: Arc<StreamManager>,
         stream_id: String,
         presentation_time_offset: Option<u64>,
-        decoding_time: IntGauge,
-        process_to_buffer_time: IntGauge,
-        frames_to_decode: IntGauge,
+        decoding_time: Histogram,
+        process_to_buffer_time: Histogram,
+        frames_to_decode: IntCounter,
+        ticket: AdmissionTicket,
     ) {
+        // A newer frame of the stream may have replaced this one while it was waiting for the thread pool
+        if !ticket.start() {
+            return;
+        }
 
         let start_time = Instant::now();
         // Decode the raw data
-        let mut point_cloud = match processing_pipeline.decode(raw_data) {
+        let mut point_cloud = match processing_pipeline.decode(raw_data, &stream_id) {
             Ok(pc) => pc,
             Err(e) => {
                 error!("Decoding failed: {:?}", e);
@@ -157,7 +219,7 @@ impl ProcessingPipeline {
         }
 
         // Capture how long it took to decode the frame
-        decoding_time.set(start_time.elapsed().as_micros() as i64);
+        decoding_time.observe(start_time.elapsed().as_micros() as f64);
 
         let start_time = Instant::now();
 
@@ -167,7 +229,7 @@ impl ProcessingPipeline {
         processing_pipeline.process_frame(point_cloud, stream_manager, stream_id);
 
         // Capture how long it took to process the frame
-        process_to_buffer_time.set(start_time.elapsed().as_micros() as i64);
+        process_to_buffer_time.observe(start_time.elapsed().as_micros() as f64);
     }
 
     #[instrument(skip_all, fields(stream_id = %stream_id))]
@@ -177,27 +239,30 @@ impl ProcessingPipeline {
         stream_manager: Arc<StreamManager>,
         stream_id: String,
     ) {
+        if is_shutting_down() {
+            return;
+        }
         // Get stream settings
         let settings = stream_manager.get_stream_settings(&stream_id);
         let thread_pool = Arc::clone(&self.thread_pool);
 
         // Dispatch the point cloud to the egress protocols specified in the settings
-        for egress in stream_manager.get_egresses(&settings.egress_protocols) {
+        for kind in &settings.egress_protocols {
+            let Some(egress) = stream_manager.get_egress(kind) else {
+                continue;
+            };
             if settings.aggregator_bypass {
                 let point_cloud_prepped = prep_for_encoding(point_cloud.clone(), &settings, Some(egress.max_number_of_points()));
                 if let Some(ref percentages) = settings.max_point_percentages {
                     // Split the point cloud into disjoint sub-clouds
-                    let sub_clouds = partition_by_percentages(&point_cloud_prepped.points, percentages).unwrap();
+                    let sub_clouds = partition_indices_by_percentages(point_cloud_prepped.points.len(), percentages).unwrap();
                     for (index, sub_cloud) in sub_clouds.into_iter().enumerate() {
-                        let pc = PointCloudData {
-                            points: sub_cloud,
-                            creation_time: point_cloud.creation_time,
-                            presentation_time: point_cloud.presentation_time,
-                            error_count: point_cloud.error_count,
-                        };
+                        let pc = point_cloud_prepped.select(&sub_cloud);
                         let tile_index = settings.sfu_tile_index.map(|index_value| index_value + index as u32);
                         let ring_buffer_bypass = settings.ring_buffer_bypass;
                         let client_id = settings.sfu_client_id;
+                        // The tiles are encrypted with the key of the stream they are cut from
+                        let payload_stream_id = stream_id.clone();
                         // If both tile index and client id are None, we clone the stream id, otherwise we create a new stream id
                         let stream_id = if client_id.is_some() && tile_index.is_some() {
                             format!("client_{}_{}", client_id.unwrap(), tile_index.unwrap())
@@ -207,12 +272,23 @@ impl ProcessingPipeline {
                         
 
                         let egress_clone = egress.clone();
+                        let tile_encoder_key = encoder_key(&format!("{:?}", kind), &stream_id);
                         let thread_pool = thread_pool.clone();
                         let processing_pipeline_clone = self.clone();
+                        let stream_manager = stream_manager.clone();
 
 
                         thread_pool.spawn(move || {
-                            let bytes = processing_pipeline_clone.encode(pc.clone(), egress_clone.encoding_format()).unwrap().data;
+                            let bytes = match processing_pipeline_clone.encode_for_stream(pc.clone(), egress_clone.encoding_format(), &stream_manager, &payload_stream_id, &tile_encoder_key) {
+                                Ok(frame) => frame.data,
+                                Err(e) => {
+                                    error!("Failed to encode a tile of stream {}: {:?}", payload_stream_id, e);
+                                    return;
+                                }
+                            };
+                            let Some(bytes) = stream_manager.encrypt_payload(&payload_stream_id, bytes) else {
+                                return;
+                            };
                             egress_clone.push_encoded_frame(
                                 bytes,
                                 stream_id,
@@ -225,7 +301,16 @@ impl ProcessingPipeline {
                         });
                     }
                 } else {
-                    let bytes = self.encode(point_cloud_prepped.clone(), egress.encoding_format()).unwrap().data;
+                    let bytes = match self.encode_for_stream(point_cloud_prepped.clone(), egress.encoding_format(), &stream_manager, &stream_id, &encoder_key(&format!("{:?}", kind), &stream_id)) {
+                        Ok(frame) => frame.data,
+                        Err(e) => {
+                            error!("Failed to encode a frame of stream {}: {:?}", stream_id, e);
+                            continue;
+                        }
+                    };
+                    let Some(bytes) = stream_manager.encrypt_payload(&stream_id, bytes) else {
+                        continue;
+                    };
                     egress.push_encoded_frame(
                         bytes,
                         stream_id.clone(),
@@ -251,6 +336,9 @@ impl ProcessingPipeline {
         stream_manager: Arc<StreamManager>,
         stream_id: String,
     ) {
+        if is_shutting_down() {
+            return;
+        }
         // We skip decoding entirely.
         // Now proceed as if we had a "point cloud" or "raw chunk" to egress.
 
@@ -273,9 +361,18 @@ impl ProcessingPipeline {
         let client_id = settings.sfu_client_id;
         let tile_index = settings.sfu_tile_index;
 
+        let Some(raw_data) = stream_manager.encrypt_payload(&stream_id, raw_data) else {
+            return;
+        };
+
         // Push the encoded frame to all the requested egress protocols
         for egress in stream_manager.get_egresses(&settings.egress_protocols) {
             egress.push_encoded_frame(raw_data.clone(), stream_id.clone(), creation_time, presentation_time, ring_buffer_bypass, client_id, tile_index);
         }
     }
 }
+
+/// The key of the stateful encoders of a stream of an egress, as the receivers of every egress hold their own keyframes.
+pub fn encoder_key(egress_id: &str, stream_id: &str) -> String {
+    format!("{egress_id}/{stream_id}")
+}
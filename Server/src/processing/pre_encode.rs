@@ -1,7 +1,7 @@
 use nalgebra::{Vector3, Rotation3};
 use shared_utils::types::PointCloudData;
 
-use crate::{processing::sampling::exact_random_sampling, types::StreamSettings};
+use crate::{processing::sampling::exact_random_sampling_indices, types::StreamSettings};
 
 /// Apply the same steps the aggregator would have done – but on a single cloud.
 pub fn prep_for_encoding(
@@ -40,12 +40,21 @@ pub fn prep_for_encoding(
             point.y = transformed_point.y;
             point.z = transformed_point.z;
         }
+
+        // Normals are not translated, and scaled by the inverse of the scale to stay perpendicular to the surface
+        if let Some(normals) = pc.attributes.normals.as_mut() {
+            for normal in normals {
+                let scaled_normal = Vector3::new(normal[0] / scale[0], normal[1] / scale[1], normal[2] / scale[2]);
+                let transformed_normal = (rotation_matrix * scaled_normal).normalize();
+                *normal = [transformed_normal.x, transformed_normal.y, transformed_normal.z];
+            }
+        }
     }
 
     // 2) optional down‑sampling  -------------------------------------------
     if let Some(limit) = max_points {
         if pc.points.len() as u64 > limit {
-            pc.points = exact_random_sampling(&pc.points, limit as usize);
+            pc = pc.select(&exact_random_sampling_indices(pc.points.len(), limit as usize));
         }
     }
     pc
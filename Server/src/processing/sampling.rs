@@ -17,16 +17,27 @@ pub fn random_sampling<T: Clone>(data: &[T], sample_rate: f64) -> Vec<T> {
 /// using a reservoir sampling-inspired algorithm.
 #[instrument(skip_all)]
 pub fn exact_random_sampling<T: Clone>(data: &[T], target_count: usize) -> Vec<T> {
-    assert!(target_count <= data.len(), "Target count cannot exceed the number of input points");
+    // Collect sampled elements using the selected indices
+    exact_random_sampling_indices(data.len(), target_count)
+        .into_iter()
+        .map(|i| data[i].clone())
+        .collect()
+}
+
+/// Same as `exact_random_sampling`, but returns the sorted indices of the selected elements,
+/// so other data that belongs to the elements (e.g. point attributes) can be sampled as well.
+#[instrument(skip_all)]
+pub fn exact_random_sampling_indices(len: usize, target_count: usize) -> Vec<usize> {
+    assert!(target_count <= len, "Target count cannot exceed the number of input points");
     debug!("Performing exact random sampling with target count: {}", target_count);
 
     let mut rng = rand::thread_rng();
     let mut indices: Vec<usize> = Vec::with_capacity(target_count);
 
     let mut n = target_count; // Remaining slots to fill
-    let mut data_len = data.len(); // Remaining elements in the input data
+    let mut data_len = len; // Remaining elements in the input data
 
-    for (index, _) in data.iter().enumerate() {
+    for index in 0..len {
         let p: f64 = rng.gen(); // Generate a random number in the range [0, 1)
         if (data_len as f64 * p) <= n as f64 {
             indices.push(index);
@@ -38,8 +49,7 @@ pub fn exact_random_sampling<T: Clone>(data: &[T], target_count: usize) -> Vec<T
         data_len -= 1;
     }
 
-    // Collect sampled elements using the selected indices
-    indices.into_iter().map(|i| data[i].clone()).collect()
+    indices
 }
 
 /// Biased random sampling to select `target_count` elements from `data`
@@ -110,10 +120,23 @@ pub fn biased_exact_random_sampling(
 ///
 /// Example: with 100 k points and `[50, 30, 10]` you get three clouds
 /// containing 50 k, 30 k and 10 k points; 10 k points remain unused.
+#[allow(dead_code)]
 pub fn partition_by_percentages<T: Clone>(
     data: &[T],
     percentages: &[u8],
 ) -> Result<Vec<Vec<T>>, &'static str> {
+    let buckets = partition_indices_by_percentages(data.len(), percentages)?;
+    Ok(buckets
+        .into_iter()
+        .map(|bucket| bucket.into_iter().map(|idx| data[idx].clone()).collect())
+        .collect())
+}
+
+/// Same as `partition_by_percentages`, but returns the indices of the elements in every bucket.
+pub fn partition_indices_by_percentages(
+    n_items: usize,
+    percentages: &[u8],
+) -> Result<Vec<Vec<usize>>, &'static str> {
     if percentages.iter().any(|&p| p > 100) {
         return Err("Each percentage must be in 0‑100");
     }
@@ -122,7 +145,6 @@ pub fn partition_by_percentages<T: Clone>(
         return Err("Sum of percentages must not exceed 100");
     }
 
-    let n_items = data.len();
     if n_items == 0 {
         return Ok(Vec::new());
     }
@@ -139,13 +161,7 @@ pub fn partition_by_percentages<T: Clone>(
         let take = (pct as usize * n_items) / 100;
         let slice_end = offset + take;
 
-        let mut bucket = Vec::with_capacity(take);
-
-        for &idx in &indices[offset..slice_end] {
-            bucket.push(data[idx].clone());
-        }
-
-        buckets.push(bucket);
+        buckets.push(indices[offset..slice_end].to_vec());
         offset = slice_end;
     }
 
@@ -7,8 +7,9 @@ use std::sync::Arc;
 use tower_http::{cors::CorsLayer, trace::{DefaultMakeSpan, TraceLayer}};
 use metrics::metrics_handler;
 use crate::{handlers::egress, services};
-use crate::handlers::{dash, datasets, scheduler, frames, websocket, streams};
+use crate::handlers::{dash, datasets, hls, ingress, scheduler, frames, websocket, streams, shutdown, tiles};
 use crate::processing::ProcessingPipeline;
+use crate::services::shutdown::ShutdownCoordinator;
 use crate::types::ActiveJobs;
 use crate::types::AppState;
 
@@ -17,6 +18,7 @@ pub fn create_router(
     stream_manager: Arc<services::stream_manager::StreamManager>,
     processing_pipeline: Arc<ProcessingPipeline>,
     active_jobs: Arc<ActiveJobs>,
+    shutdown_coordinator: Arc<ShutdownCoordinator>,
 ) -> Router {
 
     // Initialize SocketIo
@@ -27,6 +29,7 @@ pub fn create_router(
         processing_pipeline,
         active_jobs,
         socket_io: Arc::new(socket_io),
+        shutdown: shutdown_coordinator,
     };
 
     stream_manager.set_socket_io(app_state.clone().socket_io.clone());
@@ -35,6 +38,9 @@ pub fn create_router(
         // Dash endpoints
         .route("/dash/:stream_id/:segment_name", get(dash::fetch_dash_segment))
         .route("/dash/:group_id.mpd", get(dash::fetch_dash_mpd))
+        // LL-HLS endpoints
+        .route("/hls/:stream_id/:file_name", get(hls::fetch_hls_file))
+        .route("/hls/:group_id.m3u8", get(hls::fetch_hls_multivariant_playlist))
         // Datasets endpoints
         .route("/datasets", get(datasets::list_datasets))
         .route("/datasets/list", get(datasets::list_datasets))
@@ -42,15 +48,30 @@ pub fn create_router(
         .route("/datasets/dra_files", get(datasets::list_dra_files))
         // Egress endpoints
         .route("/egress/update_settings", get(egress::update_egress_settings))
+        .route("/egress/settings", get(egress::list_egress_settings))
+        .route("/egress/:egress_protocol/settings", get(egress::get_egress_settings).post(egress::set_egress_settings))
+        .route("/egress/webtransport/certificate_hash", get(egress::get_webtransport_certificate_hash))
+        .route("/egress/flute/stsid", get(egress::get_route_stsid))
+        .route("/egress/flute/sessions", get(egress::get_flute_sessions))
+        .route("/egress/flute/loss_report", post(egress::report_flute_loss))
+        .route("/egress/flute/feedback", get(egress::get_flute_feedback))
+        // Ingress endpoints
+        .route("/ingress/webtransport/certificate_hash", get(ingress::get_webtransport_ingress_certificate_hash))
         // Scheduler endpoints
         .route("/start_job", get(scheduler::start_transmission_job))
         .route("/stop_job", get(scheduler::stop_transmission_job))
         .route("/stop_all_jobs", get(scheduler::stop_all_jobs))
+        // Stops the ingest, flushes the egresses and exits
+        .route("/shutdown", post(shutdown::shutdown_server))
         // Frame endpoints
         .route("/frames/receive", post(frames::receive_frame)) // Manually insert a frame for transmission
         // Stream settings endpoint
         .route("/streams/update_settings", get(streams::update_stream_settings))
         .route("/streams/list", get(streams::list_streams)) 
+        .route("/streams/payload_key", get(streams::update_payload_key))
+        .route("/streams/gpcc", get(streams::update_gpcc_params))
+        // Tile priorities reported by the viewers
+        .route("/tiles/priority", get(tiles::list_tile_priorities).post(tiles::report_tile_priorities))
         // Socket management
         .route("/sockets", get(websocket::list_sockets))
         .route("/sockets/list", get(websocket::list_sockets))
@@ -0,0 +1,64 @@
+// services/auth.rs
+
+use std::fmt;
+
+use shared_utils::error::{XrError, XrResult};
+
+/// A token producers authenticate with, and the streams it may publish.
+#[derive(Clone, PartialEq, Eq)]
+pub struct AccessToken {
+    token: String,
+    /// Stream id the token may publish, a trailing `*` matches every stream id that starts with the prefix before it.
+    /// The token may publish every stream without it
+    streams: Option<String>,
+}
+
+impl fmt::Debug for AccessToken {
+    // Never print the token itself
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("AccessToken").field("streams", &self.streams).finish_non_exhaustive()
+    }
+}
+
+impl AccessToken {
+    /// Parses `token` or `token=streams`, e.g. `s3cret=client_*`.
+    pub fn parse(value: &str) -> XrResult<Self> {
+        let (token, streams) = match value.split_once('=') {
+            Some((token, streams)) => (token.trim(), Some(streams.trim())),
+            None => (value.trim(), None),
+        };
+        if token.is_empty() || streams.is_some_and(str::is_empty) {
+            return Err(XrError::Config("Invalid ingress token, expected token or token=stream_id".to_string()));
+        }
+        if streams.is_some_and(|streams| streams.find('*').is_some_and(|index| index != streams.len() - 1)) {
+            return Err(XrError::Config(format!("Invalid stream pattern {}, only a trailing '*' is supported", streams.unwrap_or_default())));
+        }
+        Ok(Self {
+            token: token.to_string(),
+            streams: streams.map(str::to_string),
+        })
+    }
+
+    /// Whether the token is the given token, compared in constant time so the comparison does not leak how much of it matched.
+    pub fn is(&self, token: &str) -> bool {
+        self.token.len() == token.len()
+            && self.token.bytes().zip(token.bytes()).fold(0u8, |difference, (a, b)| difference | (a ^ b)) == 0
+    }
+
+    /// Whether the token may publish the stream.
+    pub fn allows(&self, stream_id: &str) -> bool {
+        match self.streams.as_deref() {
+            None => true,
+            Some(pattern) => match pattern.strip_suffix('*') {
+                Some(prefix) => stream_id.starts_with(prefix),
+                None => stream_id == pattern,
+            },
+        }
+    }
+}
+
+/// Returns the token of an `Authorization: Bearer <token>` header value.
+pub fn bearer_token(header: &str) -> Option<&str> {
+    let (scheme, token) = header.trim().split_once(' ')?;
+    scheme.eq_ignore_ascii_case("bearer").then(|| token.trim()).filter(|token| !token.is_empty())
+}
@@ -0,0 +1,106 @@
+// Server/src/services/hls_playlist.rs
+
+use std::fmt::Write;
+
+use dash_player::mpd::builder::RepresentationDef;
+
+/// State of a single stream in the buffer egress, as needed to describe it in a media playlist.
+/// Every buffered frame is one CMAF chunk and is advertised as one LL-HLS partial segment.
+/// A full segment groups `parts_per_segment` consecutive frames.
+#[derive(Clone, Debug)]
+pub struct HlsMediaWindow {
+    /// Index of the oldest frame still present in the buffer
+    pub first_index: u64,
+    /// Index of the newest frame present in the buffer
+    pub last_index: u64,
+    /// Duration of one frame (and thus of one partial segment) in seconds
+    pub part_duration: f64,
+    pub parts_per_segment: u64,
+}
+
+impl HlsMediaWindow {
+    pub fn segment_duration(&self) -> f64 {
+        self.part_duration * self.parts_per_segment as f64
+    }
+
+    /// Media sequence number of the segment that contains the given frame index
+    pub fn segment_of(&self, index: u64) -> u64 {
+        index / self.parts_per_segment
+    }
+
+    /// Frame index of the given partial segment within the given media sequence number
+    pub fn index_of(&self, msn: u64, part: u64) -> u64 {
+        msn * self.parts_per_segment + part
+    }
+}
+
+/// Build the multivariant playlist of a group, with one variant per stream.
+/// The variants are derived from the representations the MPD of that group advertises.
+pub fn build_multivariant_playlist(representations: &[RepresentationDef]) -> String {
+    let mut playlist = String::new();
+    let _ = writeln!(playlist, "#EXTM3U");
+    let _ = writeln!(playlist, "#EXT-X-VERSION:6");
+    let _ = writeln!(playlist, "#EXT-X-INDEPENDENT-SEGMENTS");
+    for representation in representations {
+        let _ = writeln!(
+            playlist,
+            "#EXT-X-STREAM-INF:BANDWIDTH={},CODECS=\"{}\"",
+            representation.bandwidth, representation.codecs
+        );
+        let _ = writeln!(playlist, "{}/playlist.m3u8", representation.id);
+    }
+    playlist
+}
+
+/// Build the LL-HLS media playlist of a stream.
+/// Only segments of which all frames are still buffered are listed as full segments,
+/// the frames after the last complete segment are listed as partial segments only.
+pub fn build_media_playlist(window: &HlsMediaWindow) -> String {
+    let parts_per_segment = window.parts_per_segment;
+    // The first segment of which all parts are still in the buffer
+    let first_msn = window.first_index.div_ceil(parts_per_segment);
+    // The segment that is currently being filled
+    let current_msn = window.segment_of(window.last_index + 1);
+    // Before the first segment is complete, only the parts of the current segment are listed
+    let first_msn = first_msn.min(current_msn);
+    let target_duration = window.segment_duration().ceil().max(1.0) as u64;
+
+    let mut playlist = String::new();
+    let _ = writeln!(playlist, "#EXTM3U");
+    let _ = writeln!(playlist, "#EXT-X-VERSION:6");
+    let _ = writeln!(playlist, "#EXT-X-TARGETDURATION:{}", target_duration);
+    let _ = writeln!(
+        playlist,
+        "#EXT-X-SERVER-CONTROL:CAN-BLOCK-RELOAD=YES,PART-HOLD-BACK={:.3}",
+        window.part_duration * 3.0
+    );
+    let _ = writeln!(playlist, "#EXT-X-PART-INF:PART-TARGET={:.5}", window.part_duration);
+    let _ = writeln!(playlist, "#EXT-X-MEDIA-SEQUENCE:{}", first_msn);
+    let _ = writeln!(playlist, "#EXT-X-MAP:URI=\"init.mp4\"");
+
+    for msn in first_msn..=current_msn {
+        let first_part = window.index_of(msn, 0).max(window.first_index);
+        let last_part = window.index_of(msn + 1, 0).min(window.last_index + 1);
+        for index in first_part..last_part {
+            let _ = writeln!(
+                playlist,
+                "#EXT-X-PART:DURATION={:.5},URI=\"{:09}.m4s\",INDEPENDENT=YES",
+                window.part_duration, index
+            );
+        }
+        if msn < current_msn {
+            let _ = writeln!(playlist, "#EXTINF:{:.5},", window.segment_duration());
+            let _ = writeln!(playlist, "segment_{}.m4s", msn);
+        }
+    }
+
+    // Let the player open the request for the next frame before it exists,
+    // the segment handler holds it until the frame has been buffered.
+    let _ = writeln!(
+        playlist,
+        "#EXT-X-PRELOAD-HINT:TYPE=PART,URI=\"{:09}.m4s\"",
+        window.last_index + 1
+    );
+
+    playlist
+}
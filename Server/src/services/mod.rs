@@ -1,2 +1,5 @@
+pub mod auth;
+pub mod hls_playlist;
 pub mod mpd_manager;
+pub mod shutdown;
 pub mod stream_manager;
@@ -1,14 +1,43 @@
 // Server/src/egress/mpd_manager.rs
 
 use std::collections::HashMap;
+use std::fs;
+use std::path::PathBuf;
 use std::sync::{Arc, Mutex};
 use chrono::Utc;
-use dash_player::mpd::builder::{MpdBuilder, RepresentationDef};
+use dash_player::mpd::builder::{MpdBuilder, RepresentationDef, ServiceDescriptionDef};
+use tracing::{error, info, warn};
+
+/// Time shift buffer depth of live groups without a DVR window, in seconds
+const LIVE_TIME_SHIFT_BUFFER_DEPTH: f64 = 0.2;
+
+/// Settings of the DVR time-shift window.
+#[derive(Clone, Debug)]
+pub struct DvrConfig {
+    /// How far receivers can rewind, in seconds
+    pub depth: f64,
+    /// The chunks of the window are stored in a subdirectory per stream
+    pub directory: PathBuf,
+}
+
+/// The chunks of a stream that are stored on disk.
+#[derive(Clone, Debug)]
+struct DvrWindow {
+    group_id: String,
+    first_index: u64,
+    next_index: u64,
+    chunk_duration: f64,
+}
 
 #[derive(Clone)]
 pub struct MpdManager {
     pub builders: Arc<Mutex<HashMap<String, MpdBuilder>>>,
     notify_new_group: Option<Arc<dyn Fn(String) + Send + Sync>>,
+    dvr: Option<DvrConfig>,
+    /// Latency targets that are advertised in the MPD of every group
+    service_description: Option<ServiceDescriptionDef>,
+    /// The DVR window of every stream, by stream id
+    dvr_windows: Arc<Mutex<HashMap<String, DvrWindow>>>,
 }
 
 impl std::fmt::Debug for MpdManager {
@@ -16,6 +45,7 @@ impl std::fmt::Debug for MpdManager {
         f.debug_struct("MpdManager")
             .field("notify_new_group", &"<callback>")
             .field("builders", &self.builders.lock().unwrap().len())
+            .field("dvr", &self.dvr)
             .finish()
     }
 }
@@ -25,6 +55,9 @@ impl MpdManager {
         Self {
             builders: Arc::new(Mutex::new(HashMap::new())),
             notify_new_group: None,
+            dvr: None,
+            service_description: None,
+            dvr_windows: Arc::new(Mutex::new(HashMap::new())),
         }
     }
 
@@ -32,6 +65,20 @@ impl MpdManager {
         self.notify_new_group = Some(callback);
     }
 
+    /// Keeps the chunks of the last `depth` seconds on disk, so receivers can rewind within that window.
+    pub fn set_dvr(&mut self, config: DvrConfig) {
+        self.dvr = Some(config);
+    }
+
+    /// Advertises the latency targets in the MPD of every group, for low latency players.
+    pub fn set_service_description(&mut self, service_description: ServiceDescriptionDef) {
+        self.service_description = Some(service_description);
+    }
+
+    /// Adds the stream as a representation to the current period of its group.
+    /// A stream with an adaptation set id is published as a track of the group, in its own adaptation set,
+    /// and shares the init segment of the group with the other tracks.
+    #[allow(clippy::too_many_arguments)]
     pub fn add_stream_to_mpd(
         &self,
         group_id: &str,
@@ -39,42 +86,162 @@ impl MpdManager {
         mime_type: &str,
         codecs: &str,
         bandwidth: u64,
-        fps: u64
+        fps: u64,
+        chunks_per_segment: u64,
+        adaptation_set_id: Option<&str>,
     ) {
         let mut builders = self.builders.lock().unwrap();
         let builder = builders.entry(group_id.to_string()).or_insert_with(|| {
-            MpdBuilder::live()
+            let builder = MpdBuilder::live()
                 .availability_start(Utc::now() - chrono::Duration::milliseconds(124))
-                .time_shift_buffer(0.2)
-                .segment_duration(1_000, fps * 1_000)
+                .time_shift_buffer(LIVE_TIME_SHIFT_BUFFER_DEPTH)
+                .segment_duration(chunks_per_segment * 1_000, fps * 1_000)
                 .minimum_update_period(60.0)
-                .suggested_presentation_delay(0.030)
+                .suggested_presentation_delay(0.030);
+            match &self.service_description {
+                Some(service_description) => builder.service_description(service_description.clone()),
+                None => builder,
+            }
         });
 
         let representation_exists = builder.representations.iter().any(|r| r.id == stream_id);
-        if !representation_exists {
-            builder.representations.push(RepresentationDef {
-                id: stream_id.to_string(),
-                mime_type: mime_type.to_string(),
-                codecs: codecs.to_string(),
-                bandwidth,
-                initialization: format!("{}/init.mp4", stream_id),
-                media: format!("{}/$Number%09d$.m4s", stream_id),
-                availability_time_offset: Some(-0.030),
-                availability_time_complete: Some(false)
-            });
+        if representation_exists {
+            return;
         }
 
+        // With chunked CMAF, a segment can be requested as soon as its first chunk is complete.
+        // The remaining chunks are streamed to the player while they are being produced.
+        // Segments of a single chunk are only available once they are complete.
+        let (availability_time_offset, availability_time_complete) = if chunks_per_segment > 1 {
+            (Some(chunks_per_segment.saturating_sub(1) as f64 / fps as f64 - 0.030), Some(false))
+        } else {
+            (None, None)
+        };
+        builder.representations.push(RepresentationDef {
+            id: stream_id.to_string(),
+            mime_type: mime_type.to_string(),
+            codecs: codecs.to_string(),
+            bandwidth,
+            initialization: match adaptation_set_id {
+                Some(_) => format!("{}/init.mp4", group_id),
+                None => format!("{}/init.mp4", stream_id),
+            },
+            media: format!("{}/$Number%09d$.m4s", stream_id),
+            availability_time_offset,
+            availability_time_complete,
+            adaptation_set_id: adaptation_set_id.map(str::to_string),
+        });
+
         if let Some(callback) = &self.notify_new_group {
             (callback)(group_id.to_string());
         }
     }
 
+    /// Whether the current period of the group has a representation for the stream.
+    pub fn has_representation(&self, group_id: &str, stream_id: &str) -> bool {
+        let builders = self.builders.lock().unwrap();
+        builders.get(group_id).is_some_and(|builder| builder.representations.iter().any(|r| r.id == stream_id))
+    }
+
+    /// Ends the current period of the group, e.g. because its representations are reconfigured.
+    /// The new period starts with the segment `next_segment_number`, and has no representations until the streams are added again.
+    pub fn start_period(&self, group_id: &str, next_segment_number: u64, fps: u64, chunks_per_segment: u64) {
+        let mut builders = self.builders.lock().unwrap();
+        let Some(builder) = builders.get_mut(group_id) else {
+            return;
+        };
+        if builder.representations.is_empty() {
+            // Nothing was published in the current period yet, so it can be reconfigured as is
+            builder.segment_duration = chunks_per_segment * 1_000;
+            builder.timescale = fps * 1_000;
+            return;
+        }
+
+        let period_id = (builder.previous_periods.len() + 1).to_string();
+        let start = builder.segment_start(next_segment_number);
+        builder.start_period(&period_id, start, next_segment_number);
+        builder.segment_duration = chunks_per_segment * 1_000;
+        builder.timescale = fps * 1_000;
+        info!("Started period {} of group {} at {:.3}s", period_id, group_id, start);
+    }
+
+    pub fn dvr_enabled(&self) -> bool {
+        self.dvr.is_some()
+    }
+
+    /// Stores a chunk of a stream in its DVR window, and evicts the segments that fell out of the window.
+    /// The time shift buffer depth of the group grows with the window, until it covers the configured depth.
+    pub fn store_dvr_chunk(&self, group_id: &str, stream_id: &str, index: u64, data: &[u8], fps: u64, chunks_per_segment: u64) {
+        let Some(dvr) = &self.dvr else {
+            return;
+        };
+        let directory = dvr.directory.join(stream_id);
+
+        let mut windows = self.dvr_windows.lock().unwrap();
+        let window = windows.entry(stream_id.to_string()).or_insert_with(|| {
+            // Chunks of a previous run would be served as if they belonged to this one
+            if directory.exists() {
+                if let Err(e) = fs::remove_dir_all(&directory) {
+                    warn!("Failed to clear the DVR directory {:?}: {}", directory, e);
+                }
+            }
+            DvrWindow {
+                group_id: group_id.to_string(),
+                first_index: index,
+                next_index: index,
+                chunk_duration: 1.0 / fps.max(1) as f64,
+            }
+        });
+
+        if let Err(e) = fs::create_dir_all(&directory).and_then(|_| fs::write(directory.join(format!("{:09}.m4s", index)), data)) {
+            error!("Failed to store DVR chunk {} of stream {}: {}", index, stream_id, e);
+            return;
+        }
+        window.next_index = window.next_index.max(index + 1);
+        window.chunk_duration = 1.0 / fps.max(1) as f64;
+
+        // Only whole segments are evicted, so a segment in the window is always complete
+        let window_chunks = (dvr.depth / window.chunk_duration).ceil() as u64;
+        let chunks_per_segment = chunks_per_segment.max(1);
+        let first_kept = window.next_index.saturating_sub(window_chunks).div_ceil(chunks_per_segment) * chunks_per_segment;
+        for evicted in window.first_index..first_kept {
+            let path = directory.join(format!("{:09}.m4s", evicted));
+            if let Err(e) = fs::remove_file(&path) {
+                warn!("Failed to evict DVR chunk {:?}: {}", path, e);
+            }
+        }
+        window.first_index = window.first_index.max(first_kept);
+
+        // The MPD only advertises the part of the window that is stored
+        let stored_depth = (window.next_index - window.first_index) as f64 * window.chunk_duration;
+        if let Some(builder) = self.builders.lock().unwrap().get_mut(&window.group_id) {
+            builder.time_shift_buffer_depth = stored_depth.clamp(LIVE_TIME_SHIFT_BUFFER_DEPTH, dvr.depth.max(LIVE_TIME_SHIFT_BUFFER_DEPTH));
+        }
+    }
+
+    /// Returns a chunk of a stream from its DVR window, None if it is not (or no longer) in the window.
+    pub fn get_dvr_chunk(&self, stream_id: &str, index: u64) -> Option<Vec<u8>> {
+        let dvr = self.dvr.as_ref()?;
+        {
+            let windows = self.dvr_windows.lock().unwrap();
+            let window = windows.get(stream_id)?;
+            if !(window.first_index..window.next_index).contains(&index) {
+                return None;
+            }
+        }
+        fs::read(dvr.directory.join(stream_id).join(format!("{:09}.m4s", index))).ok()
+    }
+
     pub fn get_mpd(&self, group_id: &str) -> Option<String> {
         let builders = self.builders.lock().unwrap();
         builders.get(group_id).and_then(|b| b.build_xml_string().ok())
     }
 
+    pub fn get_representations(&self, group_id: &str) -> Option<Vec<RepresentationDef>> {
+        let builders = self.builders.lock().unwrap();
+        builders.get(group_id).map(|b| b.representations.clone())
+    }
+
     pub fn get_groups(&self) -> Vec<String> {
         let builders = self.builders.lock().unwrap();
         builders.keys().cloned().collect()
@@ -0,0 +1,89 @@
+// Server/src/services/shutdown.rs
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+use tokio::sync::Notify;
+use tracing::{error, info, instrument};
+
+use crate::services::stream_manager::StreamManager;
+use crate::types::ActiveJobs;
+
+/// Set once the shutdown started, from then on no new frames are ingested or generated
+static SHUTTING_DOWN: AtomicBool = AtomicBool::new(false);
+
+/// Whether the server is shutting down.
+pub fn is_shutting_down() -> bool {
+    SHUTTING_DOWN.load(Ordering::Relaxed)
+}
+
+/// Stops the server in order: first the ingest and the transmission jobs,
+/// then every egress flushes the frames it buffered and closes its files and connections.
+#[derive(Debug)]
+pub struct ShutdownCoordinator {
+    stream_manager: Arc<StreamManager>,
+    active_jobs: ActiveJobs,
+    /// How long every egress may take to flush its buffers
+    flush_timeout: Duration,
+    finished: Notify,
+}
+
+impl ShutdownCoordinator {
+    pub fn new(stream_manager: Arc<StreamManager>, active_jobs: ActiveJobs, flush_timeout: Duration) -> Self {
+        Self {
+            stream_manager,
+            active_jobs,
+            flush_timeout,
+            finished: Notify::new(),
+        }
+    }
+
+    /// Starts the shutdown, returns false if it was already started.
+    /// The egresses are flushed on their own thread, as they block on their own runtimes.
+    #[instrument(skip_all)]
+    pub fn trigger(self: &Arc<Self>, reason: &str) -> bool {
+        if SHUTTING_DOWN.swap(true, Ordering::SeqCst) {
+            return false;
+        }
+        info!("Shutting down ({})", reason);
+
+        let coordinator = self.clone();
+        let spawned = thread::Builder::new().name("Shutdown".to_string()).spawn(move || {
+            coordinator.run();
+        });
+        if let Err(e) = spawned {
+            error!("Failed to start the shutdown thread: {}", e);
+            self.finished.notify_one();
+        }
+        true
+    }
+
+    /// Completes once the egresses are flushed and closed.
+    pub async fn wait(&self) {
+        self.finished.notified().await;
+    }
+
+    fn run(&self) {
+        // Stop the jobs that generate frames, the ingress drops frames from now on
+        for (job_id, tx) in self.active_jobs.blocking_write().drain() {
+            let _ = tx.send(());
+            info!("Stopped job {}", job_id);
+        }
+        // The relayed upstream server would otherwise keep being polled while the egresses flush
+        if let Some(dash_ingress) = self.stream_manager.get_dash_ingress() {
+            dash_ingress.stop();
+        }
+
+        for kind in self.stream_manager.egress_protocol_types() {
+            if let Some(egress) = self.stream_manager.get_egress(&kind) {
+                info!("Flushing the {:?} egress", kind);
+                egress.flush_and_close(self.flush_timeout);
+            }
+        }
+
+        info!("All egresses flushed and closed");
+        self.finished.notify_one();
+    }
+}
@@ -1,15 +1,48 @@
+use shared_utils::payload_crypto::PayloadKey;
+use shared_utils::error::{XrError, XrResult};
 use socketioxide::SocketIo;
-use tracing::instrument;
-use std::collections::HashMap;
+use tracing::{error, instrument};
+use std::collections::{HashMap, HashSet};
 use std::sync::{Arc, RwLock};
+use crate::egress::backpressure::BackpressureWatermarks;
 use crate::egress::buffer::BufferEgress;
 use crate::egress::egress_common::EgressProtocol;
 use crate::egress::file::FileEgress;
 use crate::egress::flute::FluteEgress;
+use crate::egress::grpc::GrpcEgress;
+use crate::egress::mqtt::MqttEgress;
+use crate::egress::ndi::NdiEgress;
+use crate::egress::projection::ProjectionEgress;
+use crate::egress::rtsp::RtspEgress;
+#[cfg(unix)]
+use crate::egress::local::LocalEgress;
+use crate::egress::ring_buffer::DEFAULT_FRAME_BUFFER_CAPACITY;
+use crate::egress::quic::QuicEgress;
+use crate::egress::rtp::RtpMulticastEgress;
+use crate::egress::srt::SrtEgress;
+use crate::egress::tcp::TcpEgress;
 use crate::egress::webrtc::WebRTCEgress;
 use crate::egress::websocket::WebSocketEgress;
+use crate::egress::webtransport::WebTransportEgress;
+use crate::egress::zenoh::ZenohEgress;
+use crate::encoders::gpcc::GpccParams;
+use crate::ingress::capture::CaptureIngress;
+use crate::ingress::dash::DashIngress;
+use crate::ingress::flute::FluteIngress;
+use crate::ingress::grpc::GrpcIngress;
+use crate::ingress::mqtt::MqttIngress;
+use crate::ingress::replay::ReplayIngress;
+use crate::ingress::ros2::Ros2Ingress;
+use crate::ingress::rtsp::RtspIngress;
+use crate::ingress::srt::SrtIngress;
+use crate::ingress::tcp::TcpIngress;
+use crate::ingress::udp::UdpIngress;
 use crate::ingress::webrtc::WebRTCIngress;
 use crate::ingress::websocket::WebSocketIngress;
+use crate::ingress::webtransport::WebTransportIngress;
+use crate::ingress::zenoh::ZenohIngress;
+use crate::processing::admission::DropPolicy;
+use crate::services::auth::AccessToken;
 use crate::types::{StreamSettings, EgressProtocolType};
 
 #[derive(Debug)]
@@ -18,30 +51,110 @@ pub struct StreamManager {
     pub stream_settings: RwLock<HashMap<String, StreamSettings>>,
     // Reference to the socket.io instance
     pub socket_io: RwLock<Option<Arc<SocketIo>>>,
+    // Capacity of the frame buffer of every egress protocol, egresses that are not listed use the default
+    pub frame_buffer_capacities: RwLock<HashMap<EgressProtocolType, usize>>,
+    pub default_frame_buffer_capacity: RwLock<usize>,
+    // Buffer occupancies at which the generators of the egresses pause and resume
+    pub backpressure_watermarks: RwLock<BackpressureWatermarks>,
+    // Keys the encoded frames of a stream are encrypted with, see COMBINED_STREAM_ID for the aggregated frames
+    pub payload_keys: RwLock<HashMap<String, PayloadKey>>,
+    // G-PCC rate and quantization settings of a stream, streams that are not listed use the default
+    pub gpcc_params: RwLock<HashMap<String, GpccParams>>,
+    pub default_gpcc_params: RwLock<GpccParams>,
+    // Tokens the producers of the push ingresses authenticate with, every producer is accepted when there are none
+    pub access_tokens: RwLock<Vec<AccessToken>>,
+    // Streams whose producer is authenticated and may publish frames
+    pub authorized_producers: RwLock<HashSet<String>>,
     // References to singleton egress protocols
     pub webrtc_egress: RwLock<Option<Arc<WebRTCEgress>>>,
     pub websocket_egress: RwLock<Option<Arc<WebSocketEgress>>>,
     pub flute_egress: RwLock<Option<Arc<FluteEgress>>>,
     pub file_egress: RwLock<Option<Arc<FileEgress>>>,
     pub buffer_egress: RwLock<Option<Arc<BufferEgress>>>,
+    pub webtransport_egress: RwLock<Option<Arc<WebTransportEgress>>>,
+    pub quic_egress: RwLock<Option<Arc<QuicEgress>>>,
+    pub srt_egress: RwLock<Option<Arc<SrtEgress>>>,
+    pub rtp_egress: RwLock<Option<Arc<RtpMulticastEgress>>>,
+    pub tcp_egress: RwLock<Option<Arc<TcpEgress>>>,
+    pub mqtt_egress: RwLock<Option<Arc<MqttEgress>>>,
+    pub zenoh_egress: RwLock<Option<Arc<ZenohEgress>>>,
+    pub grpc_egress: RwLock<Option<Arc<GrpcEgress>>>,
+    pub ndi_egress: RwLock<Option<Arc<NdiEgress>>>,
+    #[cfg(unix)]
+    pub local_egress: RwLock<Option<Arc<LocalEgress>>>,
+    pub rtsp_egress: RwLock<Option<Arc<RtspEgress>>>,
+    pub projection_egress: RwLock<Option<Arc<ProjectionEgress>>>,
+    // Egress protocols that were registered in the EgressRegistry, by name
+    pub plugin_egresses: RwLock<HashMap<String, Arc<dyn EgressProtocol>>>,
     // Ingress protocol singletons
     pub webrtc_ingress: RwLock<Option<Arc<WebRTCIngress>>>,
     pub websocket_ingress: RwLock<Option<Arc<WebSocketIngress>>>,
+    pub udp_ingress: RwLock<Option<Arc<UdpIngress>>>,
+    pub tcp_ingress: RwLock<Option<Arc<TcpIngress>>>,
+    pub rtsp_ingress: RwLock<Option<Arc<RtspIngress>>>,
+    pub srt_ingress: RwLock<Option<Arc<SrtIngress>>>,
+    pub replay_ingress: RwLock<Option<Arc<ReplayIngress>>>,
+    pub dash_ingress: RwLock<Option<Arc<DashIngress>>>,
+    pub flute_ingress: RwLock<Option<Arc<FluteIngress>>>,
+    pub grpc_ingress: RwLock<Option<Arc<GrpcIngress>>>,
+    pub capture_ingress: RwLock<Option<Arc<CaptureIngress>>>,
+    pub ros2_ingress: RwLock<Option<Arc<Ros2Ingress>>>,
+    pub zenoh_ingress: RwLock<Option<Arc<ZenohIngress>>>,
+    pub mqtt_ingress: RwLock<Option<Arc<MqttIngress>>>,
+    pub webtransport_ingress: RwLock<Option<Arc<WebTransportIngress>>>,
 }
 
+/// Stream id of the key that the aggregated frames, which combine all streams, are encrypted with
+pub const COMBINED_STREAM_ID: &str = "combined";
+
 impl StreamManager {
     #[instrument(skip_all)]
     pub fn new() -> Self {
         Self {
             socket_io: RwLock::new(None),
+            frame_buffer_capacities: RwLock::new(HashMap::new()),
+            default_frame_buffer_capacity: RwLock::new(DEFAULT_FRAME_BUFFER_CAPACITY),
+            backpressure_watermarks: RwLock::new(BackpressureWatermarks::default()),
+            payload_keys: RwLock::new(HashMap::new()),
+            gpcc_params: RwLock::new(HashMap::new()),
+            default_gpcc_params: RwLock::new(GpccParams::default()),
+            access_tokens: RwLock::new(Vec::new()),
+            authorized_producers: RwLock::new(HashSet::new()),
             webrtc_egress: RwLock::new(None),
             websocket_egress: RwLock::new(None),
             flute_egress: RwLock::new(None),
             file_egress: RwLock::new(None),
             buffer_egress: RwLock::new(None),
+            webtransport_egress: RwLock::new(None),
+            quic_egress: RwLock::new(None),
+            srt_egress: RwLock::new(None),
+            rtp_egress: RwLock::new(None),
+            tcp_egress: RwLock::new(None),
+            mqtt_egress: RwLock::new(None),
+            zenoh_egress: RwLock::new(None),
+            grpc_egress: RwLock::new(None),
+            ndi_egress: RwLock::new(None),
+            #[cfg(unix)]
+            local_egress: RwLock::new(None),
+            rtsp_egress: RwLock::new(None),
+            projection_egress: RwLock::new(None),
+            plugin_egresses: RwLock::new(HashMap::new()),
             stream_settings: RwLock::new(HashMap::new()),
             webrtc_ingress: RwLock::new(None),
             websocket_ingress: RwLock::new(None),
+            udp_ingress: RwLock::new(None),
+            tcp_ingress: RwLock::new(None),
+            rtsp_ingress: RwLock::new(None),
+            srt_ingress: RwLock::new(None),
+            replay_ingress: RwLock::new(None),
+            dash_ingress: RwLock::new(None),
+            flute_ingress: RwLock::new(None),
+            grpc_ingress: RwLock::new(None),
+            capture_ingress: RwLock::new(None),
+            ros2_ingress: RwLock::new(None),
+            zenoh_ingress: RwLock::new(None),
+            mqtt_ingress: RwLock::new(None),
+            webtransport_ingress: RwLock::new(None),
         }
     }
 
@@ -74,6 +187,9 @@ impl StreamManager {
                 sfu_client_id: None,
                 sfu_tile_index: None,
                 max_point_percentages: None,
+                max_frames_per_second: None,
+                max_bytes_per_second: None,
+                drop_policy: DropPolicy::default(),
             }
         };
 
@@ -107,6 +223,140 @@ impl StreamManager {
         self.socket_io.read().unwrap().clone()
     }
 
+    /// Returns the capacity of the frame buffer of the given egress protocol.
+    #[instrument(skip_all)]
+    pub fn frame_buffer_capacity(&self, kind: &EgressProtocolType) -> usize {
+        self.frame_buffer_capacities
+            .read()
+            .unwrap()
+            .get(kind)
+            .copied()
+            .unwrap_or_else(|| *self.default_frame_buffer_capacity.read().unwrap())
+    }
+
+    /// Sets the capacity of the frame buffer used by egresses that have no capacity of their own.
+    /// This only affects egresses that are initialized afterwards.
+    #[instrument(skip_all)]
+    pub fn set_default_frame_buffer_capacity(&self, capacity: usize) {
+        *self.default_frame_buffer_capacity.write().unwrap() = capacity;
+    }
+
+    /// Sets the capacity of the frame buffer of the given egress protocol, and applies it if the egress is running.
+    #[instrument(skip_all)]
+    pub fn set_frame_buffer_capacity(&self, kind: EgressProtocolType, capacity: usize) {
+        if let Some(egress) = self.get_egress(&kind) {
+            egress.set_frame_buffer_capacity(capacity);
+        }
+        self.frame_buffer_capacities.write().unwrap().insert(kind, capacity);
+    }
+
+    /// Returns the buffer occupancies at which the generators of the egresses pause and resume.
+    #[instrument(skip_all)]
+    pub fn backpressure_watermarks(&self) -> BackpressureWatermarks {
+        *self.backpressure_watermarks.read().unwrap()
+    }
+
+    #[instrument(skip_all)]
+    pub fn set_backpressure_watermarks(&self, watermarks: BackpressureWatermarks) {
+        *self.backpressure_watermarks.write().unwrap() = watermarks;
+    }
+
+    /// Encrypts the encoded frames of the stream with the given key from now on.
+    /// The key id is sent along with every frame, so receivers can hold the keys of several streams or rotations.
+    #[instrument(skip_all)]
+    pub fn set_payload_key(&self, stream_id: &str, key: PayloadKey) {
+        self.payload_keys.write().unwrap().insert(stream_id.to_string(), key);
+    }
+
+    #[instrument(skip_all)]
+    pub fn remove_payload_key(&self, stream_id: &str) {
+        self.payload_keys.write().unwrap().remove(stream_id);
+    }
+
+    /// Encrypts an encoded frame of the stream if it has a key, otherwise the frame is returned as is.
+    /// Returns `None` when the encryption failed, the frame must then not be sent in the clear.
+    #[instrument(skip_all)]
+    pub fn encrypt_payload(&self, stream_id: &str, payload: Vec<u8>) -> Option<Vec<u8>> {
+        let payload_keys = self.payload_keys.read().unwrap();
+        let Some(key) = payload_keys.get(stream_id) else {
+            return Some(payload);
+        };
+        match key.encrypt(&payload) {
+            Ok(encrypted) => Some(encrypted),
+            Err(e) => {
+                error!("Failed to encrypt a frame of stream {}: {}", stream_id, e);
+                None
+            }
+        }
+    }
+
+    /// Sets the G-PCC settings the frames of the stream are encoded with from now on.
+    #[instrument(skip_all)]
+    pub fn set_gpcc_params(&self, stream_id: &str, params: GpccParams) {
+        self.gpcc_params.write().unwrap().insert(stream_id.to_string(), params);
+    }
+
+    #[instrument(skip_all)]
+    pub fn remove_gpcc_params(&self, stream_id: &str) {
+        self.gpcc_params.write().unwrap().remove(stream_id);
+    }
+
+    #[instrument(skip_all)]
+    pub fn set_default_gpcc_params(&self, params: GpccParams) {
+        *self.default_gpcc_params.write().unwrap() = params;
+    }
+
+    #[instrument(skip_all)]
+    pub fn gpcc_params(&self, stream_id: &str) -> GpccParams {
+        self.gpcc_params.read().unwrap().get(stream_id).copied()
+            .unwrap_or_else(|| *self.default_gpcc_params.read().unwrap())
+    }
+
+    /// Sets the tokens the producers of the push ingresses (WebSocket, WebRTC, UDP, TCP, gRPC, WebTransport and the frames endpoint) have to present.
+    /// Producers are no longer authenticated when the list is empty.
+    #[instrument(skip_all)]
+    pub fn set_access_tokens(&self, tokens: Vec<AccessToken>) {
+        *self.access_tokens.write().unwrap() = tokens;
+        self.authorized_producers.write().unwrap().clear();
+    }
+
+    #[instrument(skip_all)]
+    pub fn authentication_required(&self) -> bool {
+        !self.access_tokens.read().unwrap().is_empty()
+    }
+
+    /// Checks whether a producer with the given token may publish the stream, and remembers the stream as authorized if so.
+    #[instrument(skip_all)]
+    pub fn authorize_producer(&self, stream_id: &str, token: Option<&str>) -> XrResult<()> {
+        let access_tokens = self.access_tokens.read().unwrap();
+        if access_tokens.is_empty() {
+            return Ok(());
+        }
+        let Some(token) = token else {
+            return Err(XrError::Transport(format!("The producer of stream {stream_id} did not present a token")));
+        };
+        let Some(access_token) = access_tokens.iter().find(|access_token| access_token.is(token)) else {
+            return Err(XrError::Transport(format!("The producer of stream {stream_id} presented an unknown token")));
+        };
+        if !access_token.allows(stream_id) {
+            return Err(XrError::Transport(format!("The token of the producer is not allowed to publish stream {stream_id}")));
+        }
+        self.authorized_producers.write().unwrap().insert(stream_id.to_string());
+        Ok(())
+    }
+
+    /// Forgets the authorization of a stream, e.g. when its producer disconnects.
+    #[instrument(skip_all)]
+    pub fn revoke_producer(&self, stream_id: &str) {
+        self.authorized_producers.write().unwrap().remove(stream_id);
+    }
+
+    /// Whether frames of the stream are accepted from a push ingress.
+    #[instrument(skip_all)]
+    pub fn is_producer_authorized(&self, stream_id: &str) -> bool {
+        !self.authentication_required() || self.authorized_producers.read().unwrap().contains(stream_id)
+    }
+
     // Methods to set and get egress protocol singletons
     pub fn get_egress(
         &self,
@@ -119,9 +369,35 @@ impl StreamManager {
             Flute     => self.get_flute_egress    ().map(|e| e as _),
             File      => self.get_file_egress     ().map(|e| e as _),
             Buffer    => self.get_buffer_egress   ().map(|e| e as _),
+            WebTransport => self.get_webtransport_egress().map(|e| e as _),
+            Quic      => self.get_quic_egress     ().map(|e| e as _),
+            Srt       => self.get_srt_egress      ().map(|e| e as _),
+            Rtp       => self.get_rtp_egress      ().map(|e| e as _),
+            Tcp       => self.get_tcp_egress      ().map(|e| e as _),
+            Mqtt      => self.get_mqtt_egress     ().map(|e| e as _),
+            Zenoh     => self.get_zenoh_egress    ().map(|e| e as _),
+            Grpc      => self.get_grpc_egress     ().map(|e| e as _),
+            Ndi       => self.get_ndi_egress      ().map(|e| e as _),
+            #[cfg(unix)]
+            Local     => self.get_local_egress    ().map(|e| e as _),
+            #[cfg(not(unix))]
+            Local     => None,
+            Rtsp      => self.get_rtsp_egress     ().map(|e| e as _),
+            Projection => self.get_projection_egress().map(|e| e as _),
+            Plugin(name) => self.get_plugin_egress(name),
         }
     }
 
+    /// Returns the types of all egress protocols that are running, the built-in ones first.
+    pub fn egress_protocol_types(&self) -> Vec<EgressProtocolType> {
+        use EgressProtocolType::*;
+        let mut kinds = vec![WebSocket, WebRTC, Flute, File, Buffer, WebTransport, Quic, Srt, Rtp, Tcp, Mqtt, Zenoh, Grpc, Ndi, Local, Rtsp, Projection];
+        let mut names: Vec<String> = self.plugin_egresses.read().unwrap().keys().cloned().collect();
+        names.sort();
+        kinds.extend(names.into_iter().map(Plugin));
+        kinds
+    }
+
     pub fn get_egresses(
         &self,
         kinds: &[EgressProtocolType],
@@ -182,6 +458,138 @@ impl StreamManager {
         self.buffer_egress.read().unwrap().clone()
     }
 
+    #[instrument(skip_all)]
+    pub fn set_webtransport_egress(&self, egress: Arc<WebTransportEgress>) {
+        *self.webtransport_egress.write().unwrap() = Some(egress);
+    }
+
+    #[instrument(skip_all)]
+    pub fn get_webtransport_egress(&self) -> Option<Arc<WebTransportEgress>> {
+        self.webtransport_egress.read().unwrap().clone()
+    }
+
+    #[instrument(skip_all)]
+    pub fn set_quic_egress(&self, egress: Arc<QuicEgress>) {
+        *self.quic_egress.write().unwrap() = Some(egress);
+    }
+
+    #[instrument(skip_all)]
+    pub fn get_quic_egress(&self) -> Option<Arc<QuicEgress>> {
+        self.quic_egress.read().unwrap().clone()
+    }
+
+    #[instrument(skip_all)]
+    pub fn set_srt_egress(&self, egress: Arc<SrtEgress>) {
+        *self.srt_egress.write().unwrap() = Some(egress);
+    }
+
+    #[instrument(skip_all)]
+    pub fn get_srt_egress(&self) -> Option<Arc<SrtEgress>> {
+        self.srt_egress.read().unwrap().clone()
+    }
+
+    #[instrument(skip_all)]
+    pub fn set_rtp_egress(&self, egress: Arc<RtpMulticastEgress>) {
+        *self.rtp_egress.write().unwrap() = Some(egress);
+    }
+
+    #[instrument(skip_all)]
+    pub fn get_rtp_egress(&self) -> Option<Arc<RtpMulticastEgress>> {
+        self.rtp_egress.read().unwrap().clone()
+    }
+
+    #[instrument(skip_all)]
+    pub fn set_tcp_egress(&self, egress: Arc<TcpEgress>) {
+        *self.tcp_egress.write().unwrap() = Some(egress);
+    }
+
+    #[instrument(skip_all)]
+    pub fn get_tcp_egress(&self) -> Option<Arc<TcpEgress>> {
+        self.tcp_egress.read().unwrap().clone()
+    }
+
+    #[instrument(skip_all)]
+    pub fn set_mqtt_egress(&self, egress: Arc<MqttEgress>) {
+        *self.mqtt_egress.write().unwrap() = Some(egress);
+    }
+
+    #[instrument(skip_all)]
+    pub fn get_mqtt_egress(&self) -> Option<Arc<MqttEgress>> {
+        self.mqtt_egress.read().unwrap().clone()
+    }
+
+    #[instrument(skip_all)]
+    pub fn set_zenoh_egress(&self, egress: Arc<ZenohEgress>) {
+        *self.zenoh_egress.write().unwrap() = Some(egress);
+    }
+
+    #[instrument(skip_all)]
+    pub fn get_zenoh_egress(&self) -> Option<Arc<ZenohEgress>> {
+        self.zenoh_egress.read().unwrap().clone()
+    }
+
+    #[instrument(skip_all)]
+    pub fn set_grpc_egress(&self, egress: Arc<GrpcEgress>) {
+        *self.grpc_egress.write().unwrap() = Some(egress);
+    }
+
+    #[instrument(skip_all)]
+    pub fn get_grpc_egress(&self) -> Option<Arc<GrpcEgress>> {
+        self.grpc_egress.read().unwrap().clone()
+    }
+
+    #[instrument(skip_all)]
+    pub fn set_ndi_egress(&self, egress: Arc<NdiEgress>) {
+        *self.ndi_egress.write().unwrap() = Some(egress);
+    }
+
+    #[instrument(skip_all)]
+    pub fn get_ndi_egress(&self) -> Option<Arc<NdiEgress>> {
+        self.ndi_egress.read().unwrap().clone()
+    }
+
+    #[cfg(unix)]
+    #[instrument(skip_all)]
+    pub fn set_local_egress(&self, egress: Arc<LocalEgress>) {
+        *self.local_egress.write().unwrap() = Some(egress);
+    }
+
+    #[cfg(unix)]
+    #[instrument(skip_all)]
+    pub fn get_local_egress(&self) -> Option<Arc<LocalEgress>> {
+        self.local_egress.read().unwrap().clone()
+    }
+
+    #[instrument(skip_all)]
+    pub fn set_rtsp_egress(&self, egress: Arc<RtspEgress>) {
+        *self.rtsp_egress.write().unwrap() = Some(egress);
+    }
+
+    #[instrument(skip_all)]
+    pub fn get_rtsp_egress(&self) -> Option<Arc<RtspEgress>> {
+        self.rtsp_egress.read().unwrap().clone()
+    }
+
+    #[instrument(skip_all)]
+    pub fn set_projection_egress(&self, egress: Arc<ProjectionEgress>) {
+        *self.projection_egress.write().unwrap() = Some(egress);
+    }
+
+    #[instrument(skip_all)]
+    pub fn get_projection_egress(&self) -> Option<Arc<ProjectionEgress>> {
+        self.projection_egress.read().unwrap().clone()
+    }
+
+    #[instrument(skip_all)]
+    pub fn set_plugin_egress(&self, name: &str, egress: Arc<dyn EgressProtocol>) {
+        self.plugin_egresses.write().unwrap().insert(name.to_lowercase(), egress);
+    }
+
+    #[instrument(skip_all)]
+    pub fn get_plugin_egress(&self, name: &str) -> Option<Arc<dyn EgressProtocol>> {
+        self.plugin_egresses.read().unwrap().get(&name.to_lowercase()).cloned()
+    }
+
     // Methods to set and get ingress protocol singletons
     #[instrument(skip_all)]
     pub fn set_webrtc_ingress(&self, ingress: Arc<crate::ingress::webrtc::WebRTCIngress>) {
@@ -202,5 +610,135 @@ impl StreamManager {
         self.websocket_ingress.read().unwrap().clone()
     }
 
+    #[instrument(skip_all)]
+    pub fn set_udp_ingress(&self, ingress: Arc<UdpIngress>) {
+        *self.udp_ingress.write().unwrap() = Some(ingress);
+    }
+
+    #[instrument(skip_all)]
+    pub fn get_udp_ingress(&self) -> Option<Arc<UdpIngress>> {
+        self.udp_ingress.read().unwrap().clone()
+    }
+
+    #[instrument(skip_all)]
+    pub fn set_tcp_ingress(&self, ingress: Arc<TcpIngress>) {
+        *self.tcp_ingress.write().unwrap() = Some(ingress);
+    }
+
+    #[instrument(skip_all)]
+    pub fn get_tcp_ingress(&self) -> Option<Arc<TcpIngress>> {
+        self.tcp_ingress.read().unwrap().clone()
+    }
+
+    #[instrument(skip_all)]
+    pub fn set_rtsp_ingress(&self, ingress: Arc<RtspIngress>) {
+        *self.rtsp_ingress.write().unwrap() = Some(ingress);
+    }
+
+    #[instrument(skip_all)]
+    pub fn get_rtsp_ingress(&self) -> Option<Arc<RtspIngress>> {
+        self.rtsp_ingress.read().unwrap().clone()
+    }
+
+    #[instrument(skip_all)]
+    pub fn set_srt_ingress(&self, ingress: Arc<SrtIngress>) {
+        *self.srt_ingress.write().unwrap() = Some(ingress);
+    }
+
+    #[instrument(skip_all)]
+    pub fn get_srt_ingress(&self) -> Option<Arc<SrtIngress>> {
+        self.srt_ingress.read().unwrap().clone()
+    }
+
+    #[instrument(skip_all)]
+    pub fn set_replay_ingress(&self, ingress: Arc<ReplayIngress>) {
+        *self.replay_ingress.write().unwrap() = Some(ingress);
+    }
+
+    #[instrument(skip_all)]
+    pub fn get_replay_ingress(&self) -> Option<Arc<ReplayIngress>> {
+        self.replay_ingress.read().unwrap().clone()
+    }
+
+    #[instrument(skip_all)]
+    pub fn set_dash_ingress(&self, ingress: Arc<DashIngress>) {
+        *self.dash_ingress.write().unwrap() = Some(ingress);
+    }
+
+    #[instrument(skip_all)]
+    pub fn get_dash_ingress(&self) -> Option<Arc<DashIngress>> {
+        self.dash_ingress.read().unwrap().clone()
+    }
+
+    #[instrument(skip_all)]
+    pub fn set_flute_ingress(&self, ingress: Arc<FluteIngress>) {
+        *self.flute_ingress.write().unwrap() = Some(ingress);
+    }
+
+    #[instrument(skip_all)]
+    pub fn get_flute_ingress(&self) -> Option<Arc<FluteIngress>> {
+        self.flute_ingress.read().unwrap().clone()
+    }
+
+    #[instrument(skip_all)]
+    pub fn set_grpc_ingress(&self, ingress: Arc<GrpcIngress>) {
+        *self.grpc_ingress.write().unwrap() = Some(ingress);
+    }
+
+    #[instrument(skip_all)]
+    pub fn get_grpc_ingress(&self) -> Option<Arc<GrpcIngress>> {
+        self.grpc_ingress.read().unwrap().clone()
+    }
+
+    #[instrument(skip_all)]
+    pub fn set_capture_ingress(&self, ingress: Arc<CaptureIngress>) {
+        *self.capture_ingress.write().unwrap() = Some(ingress);
+    }
+
+    #[instrument(skip_all)]
+    pub fn get_capture_ingress(&self) -> Option<Arc<CaptureIngress>> {
+        self.capture_ingress.read().unwrap().clone()
+    }
+
+    #[instrument(skip_all)]
+    pub fn set_ros2_ingress(&self, ingress: Arc<Ros2Ingress>) {
+        *self.ros2_ingress.write().unwrap() = Some(ingress);
+    }
+
+    #[instrument(skip_all)]
+    pub fn get_ros2_ingress(&self) -> Option<Arc<Ros2Ingress>> {
+        self.ros2_ingress.read().unwrap().clone()
+    }
+
+    #[instrument(skip_all)]
+    pub fn set_zenoh_ingress(&self, ingress: Arc<ZenohIngress>) {
+        *self.zenoh_ingress.write().unwrap() = Some(ingress);
+    }
+
+    #[instrument(skip_all)]
+    pub fn get_zenoh_ingress(&self) -> Option<Arc<ZenohIngress>> {
+        self.zenoh_ingress.read().unwrap().clone()
+    }
+
+    #[instrument(skip_all)]
+    pub fn set_mqtt_ingress(&self, ingress: Arc<MqttIngress>) {
+        *self.mqtt_ingress.write().unwrap() = Some(ingress);
+    }
+
+    #[instrument(skip_all)]
+    pub fn get_mqtt_ingress(&self) -> Option<Arc<MqttIngress>> {
+        self.mqtt_ingress.read().unwrap().clone()
+    }
+
+    #[instrument(skip_all)]
+    pub fn set_webtransport_ingress(&self, ingress: Arc<WebTransportIngress>) {
+        *self.webtransport_ingress.write().unwrap() = Some(ingress);
+    }
+
+    #[instrument(skip_all)]
+    pub fn get_webtransport_ingress(&self) -> Option<Arc<WebTransportIngress>> {
+        self.webtransport_ingress.read().unwrap().clone()
+    }
+
     // Existing methods for managing sockets...
 }
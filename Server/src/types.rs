@@ -3,6 +3,9 @@ use serde::{Deserialize, Serialize};
 use serde_json::Value;
 use tokio::sync::oneshot;
 
+use crate::egress::registry::get_egress_registry;
+use crate::processing::admission::DropPolicy;
+
 pub type ActiveJobs = Arc<tokio::sync::RwLock<HashMap<String, oneshot::Sender<()>>>>;
 
 
@@ -19,7 +22,59 @@ pub enum EgressProtocolType {
     Flute,
     File,
     Buffer,
+    WebTransport,
+    Quic,
+    Srt,
+    Rtp,
+    Tcp,
+    Mqtt,
+    Zenoh,
+    Grpc,
+    Ndi,
+    /// Only available on Unix, as it relies on Unix domain sockets
+    Local,
+    Rtsp,
+    Projection,
     // Add other egress protocols as needed
+    /// An egress protocol that was registered in the `EgressRegistry`, by its lowercase name
+    #[serde(untagged)]
+    Plugin(String),
+}
+
+impl EgressProtocolType {
+    /// Parses the (case insensitive) name that is used by the HTTP API, e.g. "websocket" or "flute",
+    /// or the name of a registered egress protocol.
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::from_builtin_name(name).or_else(|| {
+            let name = name.to_lowercase();
+            get_egress_registry().contains(&name).then_some(EgressProtocolType::Plugin(name))
+        })
+    }
+
+    /// Parses the (case insensitive) name of a built-in egress protocol.
+    pub fn from_builtin_name(name: &str) -> Option<Self> {
+        use EgressProtocolType::*;
+        match name.to_lowercase().as_str() {
+            "websocket" => Some(WebSocket),
+            "webrtc" => Some(WebRTC),
+            "flute" => Some(Flute),
+            "file" => Some(File),
+            "buffer" => Some(Buffer),
+            "webtransport" => Some(WebTransport),
+            "quic" => Some(Quic),
+            "srt" => Some(Srt),
+            "rtp" => Some(Rtp),
+            "tcp" => Some(Tcp),
+            "mqtt" => Some(Mqtt),
+            "zenoh" => Some(Zenoh),
+            "grpc" => Some(Grpc),
+            "ndi" => Some(Ndi),
+            "local" => Some(Local),
+            "rtsp" => Some(Rtsp),
+            "projection" => Some(Projection),
+            _ => None,
+        }
+    }
 }
 
 #[derive(Clone, Debug)]
@@ -46,6 +101,11 @@ pub struct StreamSettings {
     // This is useful for Multiple Description Coding (MDC)
     // We could also give priority to certain partial frames such that at least some of them are being received.
     pub max_point_percentages: Option<Vec<u8>>,   // e.g. [15, 25, 60]
+
+    // Rate limits enforced before the frames of the stream are handed to the thread pool, see `processing::admission`
+    pub max_frames_per_second: Option<f64>,
+    pub max_bytes_per_second: Option<u64>,
+    pub drop_policy: DropPolicy, // What happens with the frames that exceed the limits
 }
 
 #[derive(Clone, Debug)]
@@ -54,6 +114,7 @@ pub struct AppState {
     pub processing_pipeline: Arc<crate::processing::ProcessingPipeline>,
     pub active_jobs: Arc<ActiveJobs>,
     pub socket_io: Arc<socketioxide::SocketIo>,
+    pub shutdown: Arc<crate::services::shutdown::ShutdownCoordinator>,
 }
 
 /// Event used for containing SDP data and the room ID.
@@ -76,6 +137,13 @@ pub struct WebRtcIceCandidate {
     pub sdp_mline_index: Option<u16>,
 }
 
+/// Event used by a client to switch to another quality layer of the WebRTC track.
+#[derive(Deserialize, Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct WebRtcQualitySelection {
+    pub rid: String,
+}
+
 #[derive(Debug, Serialize)]
 pub struct WebRtcIceCandidateResponse {
     pub candidate: Value,